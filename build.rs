@@ -1,8 +1,11 @@
+#[cfg(feature = "server")]
 extern crate protoc_rust;
 
-use protoc_rust::Customize;
-
+// protobuf API messages are used only by the server and its clients
+#[cfg(feature = "server")]
 fn main() {
+    use protoc_rust::Customize;
+
     protoc_rust::run(protoc_rust::Args {
         out_dir: "src/protos",
         input: &["src/protos/pbapi.proto"],
@@ -13,3 +16,6 @@ fn main() {
     })
     .expect("protoc");
 }
+
+#[cfg(not(feature = "server"))]
+fn main() {}
@@ -0,0 +1,173 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Local};
+use log::warn;
+use serde_json::json;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Common, // Apache Common Log Format, plus trailing kvdbd fields
+    Json,   // one JSON object per line
+}
+
+impl LogFormat {
+    pub fn from_name(name: &str) -> Option<LogFormat> {
+        match name {
+            "" | "common" => Some(LogFormat::Common),
+            "json" => Some(LogFormat::Json),
+            _ => None,
+        }
+    }
+}
+
+// one logged request
+pub struct AccessLogEntry<'a> {
+    pub time: DateTime<Local>,    // request start
+    pub peer: Option<SocketAddr>, // remote address
+    pub method: &'a str,          // HTTP method
+    pub path: &'a str,            // request URI path
+    pub version: &'a str,         // HTTP protocol version
+    pub db: Option<&'a str>,      // db name, if request matched a db route
+    pub key_len: Option<usize>,   // key length, if key is in URI path
+    pub status: u16,              // response status code
+    pub bytes: u64,               // response body size
+    pub duration: Duration,       // time spent handling request
+}
+
+/// Append-only request audit log, written to its own file.
+///
+/// The file is opened in append mode, and re-opened after SIGHUP, so that
+/// logrotate may move the old file aside.
+pub struct AccessLog {
+    path: String,
+    format: LogFormat,
+    file: Mutex<File>,
+    reopen: Arc<AtomicBool>,
+}
+
+fn open_append(path: &str) -> io::Result<File> {
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl AccessLog {
+    pub fn open(path: &str, format: LogFormat) -> io::Result<AccessLog> {
+        let file = open_append(path)?;
+
+        let reopen = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::SIGHUP, Arc::clone(&reopen))?;
+
+        Ok(AccessLog {
+            path: path.to_string(),
+            format,
+            file: Mutex::new(file),
+            reopen,
+        })
+    }
+
+    fn format_common(entry: &AccessLogEntry) -> String {
+        let peer = match entry.peer {
+            None => String::from("-"),
+            Some(addr) => addr.ip().to_string(),
+        };
+        let key_len = match entry.key_len {
+            None => String::from("-"),
+            Some(n) => n.to_string(),
+        };
+
+        format!(
+            "{} - - [{}] \"{} {} {}\" {} {} \"{}\" {} {}",
+            peer,
+            entry.time.format("%d/%b/%Y:%H:%M:%S %z"),
+            entry.method,
+            entry.path,
+            entry.version,
+            entry.status,
+            entry.bytes,
+            entry.db.unwrap_or("-"),
+            key_len,
+            entry.duration.as_micros()
+        )
+    }
+
+    fn format_json(entry: &AccessLogEntry) -> String {
+        json!({
+            "time": entry.time.to_rfc3339(),
+            "peer": entry.peer.map(|addr| addr.ip().to_string()),
+            "method": entry.method,
+            "path": entry.path,
+            "version": entry.version,
+            "db": entry.db,
+            "key_len": entry.key_len,
+            "status": entry.status,
+            "bytes": entry.bytes,
+            "duration_us": entry.duration.as_micros() as u64,
+        })
+        .to_string()
+    }
+
+    pub fn write(&self, entry: &AccessLogEntry) {
+        let line = match self.format {
+            LogFormat::Common => AccessLog::format_common(entry),
+            LogFormat::Json => AccessLog::format_json(entry),
+        };
+
+        let mut file = self.file.lock().unwrap();
+
+        // logrotate moved the file aside; continue in a new file
+        if self.reopen.swap(false, Ordering::SeqCst) {
+            match open_append(&self.path) {
+                Ok(new_file) => *file = new_file,
+                Err(e) => warn!("access log: reopen {} failed: {}", self.path, e),
+            }
+        }
+
+        if let Err(e) = writeln!(file, "{line}") {
+            warn!("access log: write {} failed: {}", self.path, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn test_entry() -> AccessLogEntry<'static> {
+        AccessLogEntry {
+            time: Local.timestamp(0, 0),
+            peer: Some("127.0.0.1:9999".parse().unwrap()),
+            method: "GET",
+            path: "/api/db1/obj/foo",
+            version: "HTTP/1.1",
+            db: Some("db1"),
+            key_len: Some(3),
+            status: 200,
+            bytes: 42,
+            duration: Duration::from_micros(1500),
+        }
+    }
+
+    #[test]
+    fn test_format_common() {
+        let line = AccessLog::format_common(&test_entry());
+        assert!(line.starts_with("127.0.0.1 - - ["));
+        assert!(line.ends_with("] \"GET /api/db1/obj/foo HTTP/1.1\" 200 42 \"db1\" 3 1500"));
+    }
+
+    #[test]
+    fn test_format_json() {
+        let line = AccessLog::format_json(&test_entry());
+        let v: serde_json::Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(v["method"], "GET");
+        assert_eq!(v["db"], "db1");
+        assert_eq!(v["key_len"], 3);
+        assert_eq!(v["status"], 200);
+        assert_eq!(v["bytes"], 42);
+        assert_eq!(v["duration_us"], 1500);
+    }
+}
@@ -0,0 +1,264 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, Local};
+use log::warn;
+use serde_json::json;
+
+use kvdbd::db;
+use kvdbd::db::audit::{AuditOp, AuditSink};
+use kvdbd::db::sha256::sha256;
+
+/// Append-only log of every write to the server's dbs, one JSON object
+/// per line, for debugging and compliance.
+///
+/// Lines are buffered, and written out when the buffer fills, by flush()
+/// (called periodically, and at shutdown), or when the file is re-opened
+/// after SIGHUP, so that logrotate may move the old file aside.  Values
+/// are logged only if log_values; otherwise, only their length and
+/// SHA-256 digest.
+pub struct AuditLog {
+    path: String,
+    log_values: bool,
+    out: Mutex<BufWriter<File>>,
+    reopen: Arc<AtomicBool>,
+    peer: Mutex<Option<IpAddr>>, // client whose writes are being made
+}
+
+fn open_append(path: &str) -> io::Result<BufWriter<File>> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    Ok(BufWriter::new(file))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+impl AuditLog {
+    pub fn open(path: &str, log_values: bool) -> io::Result<AuditLog> {
+        let out = open_append(path)?;
+
+        let reopen = Arc::new(AtomicBool::new(false));
+        signal_hook::flag::register(signal_hook::SIGHUP, Arc::clone(&reopen))?;
+
+        Ok(AuditLog {
+            path: path.to_string(),
+            log_values,
+            out: Mutex::new(out),
+            reopen,
+            peer: Mutex::new(None),
+        })
+    }
+
+    /// Attribute the writes logged from now on to peer, the client making
+    /// them, if any.  Writes are made under the server's state lock, so
+    /// each request sets its peer once holding the lock.
+    pub fn set_peer(&self, peer: Option<IpAddr>) {
+        if let Ok(mut cur_peer) = self.peer.lock() {
+            *cur_peer = peer;
+        }
+    }
+
+    /// Sink logging the writes to db db_name.
+    pub fn sink(self: &Arc<Self>, db_name: &str) -> Arc<dyn AuditSink> {
+        Arc::new(DbAudit {
+            log: Arc::clone(self),
+            db_name: db_name.to_string(),
+        })
+    }
+
+    // a write's key and value, as logged
+    fn format_record(&self, op: &str, key: &[u8], value: Option<&[u8]>) -> serde_json::Value {
+        let mut record = json!({"op": op, "key": base64::encode(key)});
+        match value {
+            None => {}
+            Some(value) if self.log_values => {
+                record["value"] = json!(base64::encode(value));
+            }
+            Some(value) => {
+                record["value_len"] = json!(value.len());
+                record["value_sha256"] = json!(to_hex(&sha256(value)));
+            }
+        }
+        record
+    }
+
+    fn format_entry(
+        &self,
+        time: DateTime<Local>,
+        peer: Option<IpAddr>,
+        db_name: &str,
+        op: &AuditOp,
+        result: &Result<bool, db::Error>,
+    ) -> String {
+        let mut entry = match op {
+            AuditOp::Put { key, value } => self.format_record("put", key, Some(value)),
+            AuditOp::Del { key } => self.format_record("del", key, None),
+            AuditOp::Batch(ops) => {
+                let records: Vec<serde_json::Value> = ops
+                    .iter()
+                    .map(|dbm| match dbm.op {
+                        db::api::MutationOp::Insert => {
+                            self.format_record("put", &dbm.key, dbm.value.as_deref())
+                        }
+                        db::api::MutationOp::Remove => self.format_record("del", &dbm.key, None),
+                    })
+                    .collect();
+                json!({"op": "batch", "ops": records})
+            }
+            AuditOp::Clear => json!({"op": "clear"}),
+        };
+        entry["time"] = json!(time.to_rfc3339());
+        entry["peer"] = json!(peer.map(|ip| ip.to_string()));
+        entry["db"] = json!(db_name);
+        match result {
+            Ok(res) => entry["result"] = json!(res),
+            Err(e) => entry["error"] = json!(e.to_string()),
+        }
+        entry.to_string()
+    }
+
+    fn write(&self, db_name: &str, op: &AuditOp, result: &Result<bool, db::Error>) {
+        let peer = self.peer.lock().ok().and_then(|peer| *peer);
+        let line = self.format_entry(Local::now(), peer, db_name, op, result);
+
+        let mut out = match self.out.lock() {
+            Ok(out) => out,
+            Err(_e) => return, // a write panicked; its line is lost
+        };
+
+        // logrotate moved the file aside; finish it, and continue in a
+        // new file
+        if self.reopen.swap(false, Ordering::SeqCst) {
+            if let Err(e) = out.flush() {
+                warn!("audit log: write {} failed: {}", self.path, e);
+            }
+            match open_append(&self.path) {
+                Ok(new_out) => *out = new_out,
+                Err(e) => warn!("audit log: reopen {} failed: {}", self.path, e),
+            }
+        }
+
+        if let Err(e) = writeln!(out, "{line}") {
+            warn!("audit log: write {} failed: {}", self.path, e);
+        }
+    }
+
+    /// Write out buffered lines.
+    pub fn flush(&self) {
+        if let Ok(mut out) = self.out.lock() {
+            if let Err(e) = out.flush() {
+                warn!("audit log: write {} failed: {}", self.path, e);
+            }
+        }
+    }
+}
+
+// sink of one db's writes, logging each to the shared log
+struct DbAudit {
+    log: Arc<AuditLog>,
+    db_name: String,
+}
+
+impl AuditSink for DbAudit {
+    fn record(&self, op: &AuditOp, result: &Result<bool, db::Error>) {
+        self.log.write(&self.db_name, op, result);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempdir::TempDir;
+
+    fn test_log(log_values: bool) -> (TempDir, AuditLog) {
+        let dir = TempDir::new("auditlog").unwrap();
+        let path = dir.path().join("audit.log");
+        let log = AuditLog::open(path.to_str().unwrap(), log_values).unwrap();
+        (dir, log)
+    }
+
+    fn entry(log: &AuditLog, op: &AuditOp, result: &Result<bool, db::Error>) -> serde_json::Value {
+        let peer = Some(IpAddr::from([127, 0, 0, 1]));
+        let line = log.format_entry(Local.timestamp(0, 0), peer, "db1", op, result);
+        serde_json::from_str(&line).unwrap()
+    }
+
+    #[test]
+    fn test_format_entry() {
+        let (_dir, log) = test_log(false);
+        let put = AuditOp::Put {
+            key: b"k\xff",
+            value: b"secret",
+        };
+        let v = entry(&log, &put, &Ok(true));
+        assert_eq!(v["peer"], "127.0.0.1");
+        assert_eq!(v["db"], "db1");
+        assert_eq!(v["op"], "put");
+        assert_eq!(v["key"], "a/8=");
+        assert_eq!(v["result"], true);
+
+        // values are digested, unless logged
+        assert!(v.get("value").is_none());
+        assert_eq!(v["value_len"], 6);
+        assert_eq!(
+            v["value_sha256"],
+            "2bb80d537b1da3e38bd30361aa855686bde0eacd7162fef6a25fe97bf527a25b"
+        );
+        let (_dir, log_values) = test_log(true);
+        let v = entry(&log_values, &put, &Ok(true));
+        assert_eq!(v["value"], "c2VjcmV0");
+        assert!(v.get("value_sha256").is_none());
+
+        // a batch lists its mutations; a failed write gives its error
+        let mut batch = db::api::Batch::default();
+        batch.insert(b"a", b"1");
+        batch.remove(b"b");
+        let v = entry(
+            &log,
+            &AuditOp::Batch(&batch.ops),
+            &Err(db::Error::QuotaExceeded),
+        );
+        assert_eq!(v["op"], "batch");
+        assert_eq!(v["ops"][0]["op"], "put");
+        assert_eq!(v["ops"][0]["value_len"], 1);
+        assert_eq!(v["ops"][1]["op"], "del");
+        assert_eq!(v["ops"][1]["key"], "Yg==");
+        assert!(v.get("result").is_none());
+        assert!(v["error"].is_string());
+
+        let v = entry(&log, &AuditOp::Del { key: b"a" }, &Ok(false));
+        assert_eq!(
+            (v["op"].as_str(), v["result"].as_bool()),
+            (Some("del"), Some(false))
+        );
+        let v = entry(&log, &AuditOp::Clear, &Ok(true));
+        assert_eq!(v["op"], "clear");
+    }
+
+    #[test]
+    fn test_write_flush() {
+        let (dir, log) = test_log(false);
+        log.set_peer(Some(IpAddr::from([10, 0, 0, 1])));
+        log.write("db1", &AuditOp::Del { key: b"a" }, &Ok(true));
+        log.set_peer(None);
+        log.write("db1", &AuditOp::Clear, &Ok(true));
+
+        // buffered, until flushed
+        let path = dir.path().join("audit.log");
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "");
+        log.flush();
+        let text = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<serde_json::Value> = text
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["peer"], "10.0.0.1");
+        assert!(lines[1]["peer"].is_null());
+    }
+}
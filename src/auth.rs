@@ -0,0 +1,152 @@
+// shared-secret request authentication:  Authorization: Bearer <api_key>,
+// or, on the binary protocol, an AUTH request presenting api_key
+
+use std::fmt;
+
+use actix_web::http::header;
+use actix_web::HttpRequest;
+
+use crate::binproto;
+
+const BEARER_PREFIX: &[u8] = b"Bearer ";
+
+// compare in time independent of where a and b first differ, so response
+// timing does not reveal how much of a guessed key was right
+fn ct_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// True if auth_hdr, a request's Authorization header value, if any,
+/// presents api_key as a bearer token.
+pub fn bearer_admits(auth_hdr: Option<&[u8]>, api_key: &str) -> bool {
+    match auth_hdr.and_then(|hdr| hdr.strip_prefix(BEARER_PREFIX)) {
+        None => false,
+        Some(token) => ct_eq(token, api_key.as_bytes()),
+    }
+}
+
+/// True if body, a binary protocol request, is an AUTH request
+/// presenting api_key.
+pub fn auth_frame_admits(body: &[u8], api_key: &str) -> bool {
+    let mut rd = binproto::Reader::new(body);
+    if rd.byte() != Ok(binproto::OP_AUTH) || rd.bytes().is_err() {
+        return false;
+    }
+    match rd.bytes() {
+        Ok(token) if rd.is_empty() => ct_eq(token, api_key.as_bytes()),
+        _ => false,
+    }
+}
+
+// headers whose values are withheld from debug output
+const REDACTED_HEADERS: [header::HeaderName; 2] =
+    [header::AUTHORIZATION, header::PROXY_AUTHORIZATION];
+
+/// A request, as printed by the debug setting:  as HttpRequest's Debug,
+/// but with credential headers' values withheld, so that a bearer token
+/// does not reach the log.
+pub struct DebugRequest<'a>(pub &'a HttpRequest);
+
+impl fmt::Debug for DebugRequest<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let req = self.0;
+        writeln!(
+            f,
+            "\nHttpRequest {:?} {}:{}",
+            req.version(),
+            req.method(),
+            req.path()
+        )?;
+        if !req.query_string().is_empty() {
+            writeln!(f, "  query: ?{:?}", req.query_string())?;
+        }
+        if !req.match_info().is_empty() {
+            writeln!(f, "  params: {:?}", req.match_info())?;
+        }
+        writeln!(f, "  headers:")?;
+        for (key, val) in req.headers().iter() {
+            if REDACTED_HEADERS.contains(key) {
+                writeln!(f, "    {key:?}: (redacted)")?;
+            } else {
+                writeln!(f, "    {key:?}: {val:?}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    #[test]
+    fn test_bearer_admits() {
+        let key = "s3cret-key";
+        assert!(bearer_admits(Some(b"Bearer s3cret-key"), key));
+
+        // missing header, or another scheme
+        assert!(!bearer_admits(None, key));
+        assert!(!bearer_admits(Some(b""), key));
+        assert!(!bearer_admits(Some(b"Basic s3cret-key"), key));
+        assert!(!bearer_admits(Some(b"s3cret-key"), key));
+
+        // wrong key, including a prefix or extension of the right one
+        assert!(!bearer_admits(Some(b"Bearer wrong"), key));
+        assert!(!bearer_admits(Some(b"Bearer s3cret"), key));
+        assert!(!bearer_admits(Some(b"Bearer s3cret-key2"), key));
+        assert!(!bearer_admits(Some(b"Bearer "), key));
+    }
+
+    #[test]
+    fn test_auth_frame_admits() {
+        let key = "s3cret-key";
+        let auth =
+            |db: &str, fields: &[&[u8]]| binproto::enc_request(binproto::OP_AUTH, db, fields);
+        assert!(auth_frame_admits(&auth("", &[b"s3cret-key"]), key));
+        assert!(auth_frame_admits(&auth("db1", &[b"s3cret-key"]), key));
+
+        // wrong key, or none
+        assert!(!auth_frame_admits(&auth("", &[b"s3cret"]), key));
+        assert!(!auth_frame_admits(&auth("", &[b"s3cret-key2"]), key));
+        assert!(!auth_frame_admits(&auth("", &[b""]), key));
+        assert!(!auth_frame_admits(&auth("", &[]), key));
+
+        // another opcode carrying the key, extra fields, or a bad frame
+        let get = binproto::enc_request(binproto::OP_GET, "", &[b"s3cret-key"]);
+        assert!(!auth_frame_admits(&get, key));
+        assert!(!auth_frame_admits(&auth("", &[b"s3cret-key", b""]), key));
+        assert!(!auth_frame_admits(&[], key));
+        assert!(!auth_frame_admits(&[binproto::OP_AUTH, 0x05], key));
+    }
+
+    #[test]
+    fn test_debug_request() {
+        let req = TestRequest::with_uri("/api/db1/obj/key?n=1")
+            .header(header::AUTHORIZATION, "Bearer s3cret-key")
+            .header(header::PROXY_AUTHORIZATION, "Basic czNjcmV0")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .to_http_request();
+        let logged = format!("{:?}", DebugRequest(&req));
+        assert!(!logged.contains("s3cret-key"), "{}", logged);
+        assert!(!logged.contains("czNjcmV0"), "{}", logged);
+        assert!(
+            logged.contains("\"authorization\": (redacted)"),
+            "{}",
+            logged
+        );
+
+        // otherwise as HttpRequest's own
+        assert!(format!("{req:?}").contains("s3cret-key"));
+        assert!(logged.contains("GET:/api/db1/obj/key"), "{}", logged);
+        assert!(logged.contains("query: ?\"n=1\""), "{}", logged);
+        assert!(
+            logged.contains("\"content-type\": \"text/plain\""),
+            "{}",
+            logged
+        );
+    }
+}
@@ -0,0 +1,257 @@
+/*
+ * binproto: Compact binary protocol for kvdbd
+ *
+ * Wire format shared by the server's binary listener and BinClient.  See
+ * doc/api.md, "Binary protocol", for the full specification.
+ */
+
+use std::io::{self, Read, Write};
+
+/// Largest frame body accepted, in either direction.
+pub const MAX_FRAME_LEN: u64 = 64 * 1024 * 1024;
+
+/// Largest frame body accepted from a client not yet authenticated.
+pub const MAX_AUTH_FRAME_LEN: u64 = 4096;
+
+// longest valid varint encoding of a u64
+const MAX_VARINT_LEN: usize = 10;
+
+// request opcodes
+pub const OP_GET: u8 = 0x01; // db, key
+pub const OP_PUT: u8 = 0x02; // db, key, value
+pub const OP_DEL: u8 = 0x03; // db, key
+pub const OP_BATCH: u8 = 0x04; // db, protobuf BatchRequest
+pub const OP_KEYS: u8 = 0x05; // db, start key, prefix
+pub const OP_STAT: u8 = 0x06; // db
+pub const OP_AUTH: u8 = 0x07; // db (ignored), api key
+
+// response status codes
+pub const ST_OK: u8 = 0x00;
+pub const ST_NOT_FOUND: u8 = 0x01; // key not found
+pub const ST_NO_DB: u8 = 0x02; // db not found
+pub const ST_BAD_REQUEST: u8 = 0x03; // malformed request body
+pub const ST_DB_ERROR: u8 = 0x04; // db operation failed
+pub const ST_BAD_FRAME: u8 = 0x05; // malformed or oversized frame; closes
+pub const ST_QUOTA_EXCEEDED: u8 = 0x06; // write refused by db quota
+pub const ST_FORBIDDEN: u8 = 0x07; // operation disabled for db
+pub const ST_UNAVAILABLE: u8 = 0x08; // write refused while server drains
+pub const ST_UNAUTHORIZED: u8 = 0x09; // missing or wrong api key; closes
+pub const ST_TOO_MANY_REQUESTS: u8 = 0x0a; // over the client's request rate
+
+/// Append n to buf, as an unsigned LEB128 varint.
+pub fn put_varint(buf: &mut Vec<u8>, mut n: u64) {
+    while n >= 0x80 {
+        buf.push((n as u8) | 0x80);
+        n >>= 7;
+    }
+    buf.push(n as u8);
+}
+
+/// Append b to buf, prefixed by its varint length.
+pub fn put_bytes(buf: &mut Vec<u8>, b: &[u8]) {
+    put_varint(buf, b.len() as u64);
+    buf.extend_from_slice(b);
+}
+
+/// Encode request body: opcode, db name, then each field as bytes.
+pub fn enc_request(op: u8, db: &str, fields: &[&[u8]]) -> Vec<u8> {
+    let mut buf = vec![op];
+    put_bytes(&mut buf, db.as_bytes());
+    for field in fields {
+        put_bytes(&mut buf, field);
+    }
+    buf
+}
+
+/// Encode error response body: status, then message as bytes.
+pub fn enc_error(status: u8, msg: &str) -> Vec<u8> {
+    let mut buf = vec![status];
+    put_bytes(&mut buf, msg.as_bytes());
+    buf
+}
+
+/// Cursor over one frame body, decoding the protocol's field types.
+pub struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(buf: &'a [u8]) -> Reader<'a> {
+        Reader { buf, pos: 0 }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pos == self.buf.len()
+    }
+
+    pub fn byte(&mut self) -> Result<u8, &'static str> {
+        match self.buf.get(self.pos) {
+            None => Err("truncated frame"),
+            Some(b) => {
+                self.pos += 1;
+                Ok(*b)
+            }
+        }
+    }
+
+    pub fn varint(&mut self) -> Result<u64, &'static str> {
+        let mut n: u64 = 0;
+        for i in 0..MAX_VARINT_LEN {
+            let b = self.byte()?;
+            if i == MAX_VARINT_LEN - 1 && b > 0x01 {
+                return Err("varint overflow");
+            }
+            n |= u64::from(b & 0x7f) << (7 * i);
+            if b & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err("varint overflow")
+    }
+
+    pub fn bytes(&mut self) -> Result<&'a [u8], &'static str> {
+        let len = self.varint()?;
+        if len > (self.buf.len() - self.pos) as u64 {
+            return Err("truncated frame");
+        }
+        let start = self.pos;
+        self.pos += len as usize;
+        Ok(&self.buf[start..self.pos])
+    }
+}
+
+// read one varint from a stream.  None at end of stream, before any byte
+fn read_varint<R: Read>(r: &mut R) -> io::Result<Option<u64>> {
+    let mut n: u64 = 0;
+    for i in 0..MAX_VARINT_LEN {
+        let mut b = [0u8; 1];
+        if r.read(&mut b)? == 0 {
+            if i == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        if i == MAX_VARINT_LEN - 1 && b[0] > 0x01 {
+            break;
+        }
+        n |= u64::from(b[0] & 0x7f) << (7 * i);
+        if b[0] & 0x80 == 0 {
+            return Ok(Some(n));
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::InvalidData,
+        "varint overflow",
+    ))
+}
+
+/// Read one frame, returning its body.  Returns None if the stream ends
+/// cleanly, between frames.  Malformed or oversized frames are
+/// io::ErrorKind::InvalidData errors.
+pub fn read_frame<R: Read>(r: &mut R) -> io::Result<Option<Vec<u8>>> {
+    read_frame_max(r, MAX_FRAME_LEN)
+}
+
+/// Read one frame, as read_frame(), refusing bodies longer than max_len.
+pub fn read_frame_max<R: Read>(r: &mut R, max_len: u64) -> io::Result<Option<Vec<u8>>> {
+    let len = match read_varint(r)? {
+        None => return Ok(None),
+        Some(len) => len,
+    };
+    if len > max_len {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "frame too large",
+        ));
+    }
+
+    let mut body = vec![0u8; len as usize];
+    r.read_exact(&mut body)?;
+    Ok(Some(body))
+}
+
+/// Write one frame, with the given body.
+pub fn write_frame<W: Write>(w: &mut W, body: &[u8]) -> io::Result<()> {
+    let mut frame = Vec::with_capacity(body.len() + MAX_VARINT_LEN);
+    put_bytes(&mut frame, body);
+    w.write_all(&frame)?;
+    w.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        for n in &[
+            0,
+            1,
+            127,
+            128,
+            300,
+            16383,
+            16384,
+            u64::from(u32::MAX),
+            u64::MAX,
+        ] {
+            let mut buf = Vec::new();
+            put_varint(&mut buf, *n);
+            let mut rd = Reader::new(&buf);
+            assert_eq!(rd.varint(), Ok(*n));
+            assert!(rd.is_empty());
+        }
+
+        let mut buf = Vec::new();
+        put_varint(&mut buf, 300);
+        assert_eq!(buf, vec![0xac, 0x02]);
+
+        // longer than any u64, or truncated
+        assert!(Reader::new(&[0xff; 10]).varint().is_err());
+        assert!(Reader::new(&[0x80]).varint().is_err());
+    }
+
+    #[test]
+    fn test_request() {
+        let buf = enc_request(OP_PUT, "db1", &[b"key", b""]);
+        let mut rd = Reader::new(&buf);
+        assert_eq!(rd.byte(), Ok(OP_PUT));
+        assert_eq!(rd.bytes(), Ok(&b"db1"[..]));
+        assert_eq!(rd.bytes(), Ok(&b"key"[..]));
+        assert_eq!(rd.bytes(), Ok(&b""[..]));
+        assert!(rd.is_empty());
+        assert!(rd.byte().is_err());
+
+        // bytes field longer than remaining frame
+        assert!(Reader::new(&[0x05, b'a']).bytes().is_err());
+    }
+
+    #[test]
+    fn test_frame() {
+        let mut wire = Vec::new();
+        write_frame(&mut wire, b"first").unwrap();
+        write_frame(&mut wire, b"").unwrap();
+        write_frame(&mut wire, &[7u8; 200]).unwrap();
+
+        let mut r = &wire[..];
+        assert_eq!(read_frame(&mut r).unwrap(), Some(b"first".to_vec()));
+        assert_eq!(read_frame(&mut r).unwrap(), Some(Vec::new()));
+        assert_eq!(read_frame(&mut r).unwrap(), Some(vec![7u8; 200]));
+        assert_eq!(read_frame(&mut r).unwrap(), None);
+
+        // truncated body
+        let mut r = &wire[..3];
+        assert!(read_frame(&mut r).is_err());
+
+        // oversized
+        let mut buf = Vec::new();
+        put_varint(&mut buf, MAX_FRAME_LEN + 1);
+        let err = read_frame(&mut &buf[..]).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        let mut r = &wire[..];
+        assert_eq!(read_frame_max(&mut r, 5).unwrap(), Some(b"first".to_vec()));
+        let err = read_frame_max(&mut &wire[..], 4).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+}
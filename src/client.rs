@@ -0,0 +1,784 @@
+/*
+ * client: Typed HTTP client for kvdbd
+ *
+ * Wraps the REST/Protobufs API.  Keys and values travel inside protobuf
+ * messages, so binary keys need no escaping or base64 encoding.  Batch
+ * and key list models are shared with the server's db::api.
+ *
+ * BinClient speaks the compact binary protocol instead, over one TCP
+ * connection.
+ *
+ * KvdbClient retries transient failures per its RetryPolicy.
+ */
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use protobuf::{parse_from_bytes, Message, ProtobufError};
+use reqwest::{Client, StatusCode};
+
+use crate::binproto;
+use crate::db::api::{Batch, DuplicatePolicy, EntryList, IterOptions, KeyList, MutationOp};
+use crate::protos::pbapi::{
+    BatchRequest, BatchRequest_MagicNum, EntryResponse, EntryResponse_MagicNum, IterRequest,
+    IterRequest_MagicNum, KeyRequest, KeyRequest_MagicNum, KeyResponse, UpdateRequest,
+    UpdateRequest_MagicNum,
+};
+
+const BASEURI: &str = "/api";
+const PROTOBUF_MEDIA: &str = "application/x-protobuf";
+pub const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[derive(Debug)]
+pub enum ClientError {
+    Http(reqwest::Error),   // connection or transfer failed
+    Status(StatusCode),     // server returned unexpected status
+    Decode(ProtobufError),  // malformed protobuf response
+    Io(io::Error),          // binary protocol connection failed
+    Protocol(&'static str), // malformed binary protocol response
+    BinStatus(u8, String),  // binary protocol error status, message
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "http: {e}"),
+            ClientError::Status(s) => write!(f, "server status: {s}"),
+            ClientError::Decode(e) => write!(f, "decode: {e}"),
+            ClientError::Io(e) => write!(f, "io: {e}"),
+            ClientError::Protocol(e) => write!(f, "protocol: {e}"),
+            ClientError::BinStatus(st, msg) => write!(f, "server status: {st} ({msg})"),
+        }
+    }
+}
+
+impl error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> ClientError {
+        ClientError::Http(e)
+    }
+}
+
+impl From<io::Error> for ClientError {
+    fn from(e: io::Error) -> ClientError {
+        ClientError::Io(e)
+    }
+}
+
+impl From<&'static str> for ClientError {
+    fn from(e: &'static str) -> ClientError {
+        ClientError::Protocol(e)
+    }
+}
+
+impl From<ProtobufError> for ClientError {
+    fn from(e: ProtobufError) -> ClientError {
+        ClientError::Decode(e)
+    }
+}
+
+pub fn pbenc_iter_req(start_key: Option<Vec<u8>>, prefix: Option<Vec<u8>>) -> Vec<u8> {
+    let mut out_msg = IterRequest::new();
+    out_msg.magic = IterRequest_MagicNum::MAGIC;
+    match start_key {
+        None => out_msg.set_start_key(Vec::new()),
+        Some(s) => out_msg.set_start_key(s),
+    }
+    match prefix {
+        None => out_msg.set_prefix(Vec::new()),
+        Some(s) => out_msg.set_prefix(s),
+    }
+    out_msg.write_to_bytes().unwrap()
+}
+
+pub fn pbenc_key_req(key: &[u8]) -> Vec<u8> {
+    let mut out_msg = KeyRequest::new();
+    out_msg.magic = KeyRequest_MagicNum::MAGIC;
+    out_msg.set_key(key.to_vec());
+    out_msg.write_to_bytes().unwrap()
+}
+
+pub fn pbenc_update_ins(key: &[u8], val: &[u8]) -> UpdateRequest {
+    let mut out_msg = UpdateRequest::new();
+    out_msg.magic = UpdateRequest_MagicNum::MAGIC;
+    out_msg.set_key(key.to_vec());
+    out_msg.set_value(val.to_vec());
+    out_msg.set_is_insert(true);
+
+    out_msg
+}
+
+pub fn pbenc_update_del(key: &[u8]) -> UpdateRequest {
+    let mut out_msg = UpdateRequest::new();
+    out_msg.magic = UpdateRequest_MagicNum::MAGIC;
+    out_msg.set_key(key.to_vec());
+    out_msg.set_is_insert(false);
+
+    out_msg
+}
+
+pub fn pbenc_update_req(key: &[u8], val: &[u8]) -> Vec<u8> {
+    let out_msg = pbenc_update_ins(key, val);
+    out_msg.write_to_bytes().unwrap()
+}
+
+// base64 of bytes, escaped for a query string
+fn query_b64(bytes: &[u8]) -> String {
+    base64::encode(bytes)
+        .replace('+', "%2B")
+        .replace('/', "%2F")
+        .replace('=', "%3D")
+}
+
+// batch request path, passing the batch's duplicate policy and guard
+fn batch_op(batch: &Batch) -> String {
+    let mut params = Vec::new();
+    if batch.duplicate_policy == DuplicatePolicy::Reject {
+        params.push("duplicates=reject".to_string());
+    }
+    if let Some((key, expected)) = &batch.guard {
+        params.push(format!("guard_key={}", query_b64(key)));
+        if let Some(val) = expected {
+            params.push(format!("guard_value={}", query_b64(val)));
+        }
+    }
+    if params.is_empty() {
+        "batch".to_string()
+    } else {
+        format!("batch?{}", params.join("&"))
+    }
+}
+
+pub fn pbenc_batch_req(batch: &Batch) -> Vec<u8> {
+    let mut out_msg = BatchRequest::new();
+    out_msg.magic = BatchRequest_MagicNum::MAGIC;
+    for mutation in &batch.ops {
+        let req = match mutation.op {
+            MutationOp::Insert => pbenc_update_ins(&mutation.key, mutation.value.as_ref().unwrap()),
+            MutationOp::Remove => pbenc_update_del(&mutation.key),
+        };
+        out_msg.reqs.push(req);
+    }
+    out_msg.write_to_bytes().unwrap()
+}
+
+pub fn pbdec_entries_resp(wiredata: &[u8]) -> Result<EntryList, ProtobufError> {
+    let in_msg = parse_from_bytes::<EntryResponse>(wiredata)?;
+    if in_msg.magic != EntryResponse_MagicNum::MAGIC {
+        return Err(ProtobufError::WireError(protobuf::error::WireError::Other));
+    }
+    Ok(EntryList {
+        entries: in_msg
+            .get_entries()
+            .iter()
+            .map(|entry| (entry.get_key().to_vec(), entry.get_value().to_vec()))
+            .collect(),
+        list_end: in_msg.list_end,
+    })
+}
+
+/// When to retry requests failing transiently:  on connection errors,
+/// timeouts, and 502, 503 or 504 responses, as seen during a server
+/// restart.  Retry n (from 1) waits base_backoff * 2^(n-1), less a random
+/// fraction, up to jitter, of that wait, so clients failing together do
+/// not retry together.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RetryPolicy {
+    pub max_attempts: u32, // including the first; 1 = never retry
+    pub base_backoff: Duration,
+    pub jitter: f64, // 0.0 - 1.0
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    // wait before retry n
+    fn backoff(&self, retry: u32) -> Duration {
+        let wait = self.base_backoff * 2u32.saturating_pow(retry - 1);
+        let nanos = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.subsec_nanos(),
+            Err(_e) => 0,
+        };
+        let rand = f64::from(nanos % 1000) / 1000.0;
+        wait.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * rand)
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> RetryPolicy {
+        RetryPolicy {
+            max_attempts: 3,
+            base_backoff: Duration::from_millis(100),
+            jitter: 0.5,
+        }
+    }
+}
+
+pub struct RetryPolicyBuilder {
+    pub max_attempts: Option<u32>,
+    pub base_backoff: Option<Duration>,
+    pub jitter: Option<f64>,
+}
+
+impl RetryPolicyBuilder {
+    pub fn new() -> RetryPolicyBuilder {
+        RetryPolicyBuilder {
+            max_attempts: None,
+            base_backoff: None,
+            jitter: None,
+        }
+    }
+
+    pub fn max_attempts(&mut self, val_in: u32) -> &mut RetryPolicyBuilder {
+        self.max_attempts = Some(val_in);
+        self
+    }
+
+    pub fn base_backoff(&mut self, val_in: Duration) -> &mut RetryPolicyBuilder {
+        self.base_backoff = Some(val_in);
+        self
+    }
+
+    pub fn jitter(&mut self, val_in: f64) -> &mut RetryPolicyBuilder {
+        self.jitter = Some(val_in);
+        self
+    }
+
+    pub fn build(&self) -> RetryPolicy {
+        let def = RetryPolicy::default();
+        RetryPolicy {
+            max_attempts: self.max_attempts.unwrap_or(def.max_attempts).max(1),
+            base_backoff: self.base_backoff.unwrap_or(def.base_backoff),
+            jitter: self.jitter.unwrap_or(def.jitter),
+        }
+    }
+}
+
+// worth retrying:  the server may be restarting, or briefly overloaded
+fn is_transient(res: &Result<reqwest::Response, reqwest::Error>) -> bool {
+    match res {
+        Ok(resp) => matches!(
+            resp.status(),
+            StatusCode::BAD_GATEWAY | StatusCode::SERVICE_UNAVAILABLE | StatusCode::GATEWAY_TIMEOUT
+        ),
+        Err(e) => e.is_http() || e.is_timeout(),
+    }
+}
+
+/// Client for a single database, served by a kvdbd instance.
+///
+/// Reads and deletes are retried per the client's RetryPolicy.  Writes
+/// which replayed could apply twice, interleaved with other clients'
+/// writes, are sent once, unless given an idempotency key:  the server
+/// applies at most one write per key, per db, within ten minutes.
+pub struct KvdbClient {
+    client: Client,
+    basepath: String, // e.g. "http://127.0.0.1:8080/api/db1/"
+    retry: RetryPolicy,
+}
+
+impl KvdbClient {
+    /// Connect to database `db` at `endpoint`, e.g. "http://127.0.0.1:8080".
+    pub fn new(endpoint: &str, db: &str) -> KvdbClient {
+        KvdbClient::with_client(Client::new(), endpoint, db)
+    }
+
+    /// As new(), re-using an existing HTTP client and its connection pool.
+    pub fn with_client(client: Client, endpoint: &str, db: &str) -> KvdbClient {
+        KvdbClient::with_api_base(client, endpoint, BASEURI, db)
+    }
+
+    /// As with_client(), for a server whose api_base is not the default,
+    /// e.g. "/kv", or "" if API routes are mounted at root.
+    pub fn with_api_base(client: Client, endpoint: &str, api_base: &str, db: &str) -> KvdbClient {
+        KvdbClient {
+            client,
+            basepath: format!("{endpoint}{api_base}/{db}/"),
+            retry: RetryPolicy::default(),
+        }
+    }
+
+    /// Replace the default RetryPolicy.
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.retry = policy;
+    }
+
+    // send request once, or, if retryable, up to policy max_attempts times
+    fn post_opt(
+        &self,
+        op: &str,
+        body: Vec<u8>,
+        retryable: bool,
+        idem_key: Option<&str>,
+    ) -> Result<reqwest::Response, ClientError> {
+        let url = format!("{}{}", self.basepath, op);
+        let max_attempts = if retryable {
+            self.retry.max_attempts
+        } else {
+            1
+        };
+        let mut attempt = 1;
+        loop {
+            let mut req = self.client.post(&url).body(body.clone());
+            if let Some(key) = idem_key {
+                req = req.header(IDEMPOTENCY_KEY_HEADER, key);
+            }
+            let res = req.send();
+            if attempt >= max_attempts || !is_transient(&res) {
+                return Ok(res?);
+            }
+            thread::sleep(self.retry.backoff(attempt));
+            attempt += 1;
+        }
+    }
+
+    // GET op, as a read:  retried per policy.  Asks for a protobuf response
+    fn get_pb(&self, op: &str) -> Result<reqwest::Response, ClientError> {
+        let url = format!("{}{}", self.basepath, op);
+        let mut attempt = 1;
+        loop {
+            let res = self
+                .client
+                .get(&url)
+                .header(reqwest::header::ACCEPT, PROTOBUF_MEDIA)
+                .send();
+            if attempt >= self.retry.max_attempts || !is_transient(&res) {
+                return Ok(res?);
+            }
+            thread::sleep(self.retry.backoff(attempt));
+            attempt += 1;
+        }
+    }
+
+    fn post(&self, op: &str, body: Vec<u8>) -> Result<reqwest::Response, ClientError> {
+        self.post_opt(op, body, true, None)
+    }
+
+    /// Lookup value by key.  Returns None, if key not found.
+    pub fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, ClientError> {
+        let mut resp = self.post("get", pbenc_key_req(key))?;
+        match resp.status() {
+            StatusCode::OK => {
+                let mut body: Vec<u8> = vec![];
+                resp.copy_to(&mut body)?;
+                Ok(Some(body))
+            }
+            StatusCode::NOT_FOUND => Ok(None),
+            status => Err(ClientError::Status(status)),
+        }
+    }
+
+    /// Store key and value.  Not retried.
+    pub fn put(&self, key: &[u8], val: &[u8]) -> Result<bool, ClientError> {
+        let resp = self.post_opt("put", pbenc_update_req(key, val), false, None)?;
+        match resp.status() {
+            StatusCode::OK => Ok(true),
+            status => Err(ClientError::Status(status)),
+        }
+    }
+
+    /// As put(), retried, applied at most once per idempotency key.
+    pub fn put_idempotent(
+        &self,
+        key: &[u8],
+        val: &[u8],
+        idem_key: &str,
+    ) -> Result<bool, ClientError> {
+        let resp = self.post_opt("put", pbenc_update_req(key, val), true, Some(idem_key))?;
+        match resp.status() {
+            StatusCode::OK => Ok(true),
+            status => Err(ClientError::Status(status)),
+        }
+    }
+
+    /// Remove record by key.  Returns false, if key not found.
+    pub fn del(&self, key: &[u8]) -> Result<bool, ClientError> {
+        let resp = self.post("del", pbenc_key_req(key))?;
+        match resp.status() {
+            StatusCode::OK => Ok(true),
+            StatusCode::NOT_FOUND => Ok(false),
+            status => Err(ClientError::Status(status)),
+        }
+    }
+
+    /// Atomically apply all mutations in batch, per its duplicate_policy.
+    /// A batch refused by its guard fails with Status(CONFLICT).  Not
+    /// retried.
+    pub fn batch(&self, batch: &Batch) -> Result<bool, ClientError> {
+        let resp = self.post_opt(&batch_op(batch), pbenc_batch_req(batch), false, None)?;
+        match resp.status() {
+            StatusCode::OK => Ok(true),
+            status => Err(ClientError::Status(status)),
+        }
+    }
+
+    /// As batch(), retried, applied at most once per idempotency key.
+    pub fn batch_idempotent(&self, batch: &Batch, idem_key: &str) -> Result<bool, ClientError> {
+        let resp = self.post_opt(
+            &batch_op(batch),
+            pbenc_batch_req(batch),
+            true,
+            Some(idem_key),
+        )?;
+        match resp.status() {
+            StatusCode::OK => Ok(true),
+            status => Err(ClientError::Status(status)),
+        }
+    }
+
+    /// List keys, in db order.  If KeyList.list_end is false, continue
+    /// with start key set to the last key returned.
+    pub fn iter(&self, opts: IterOptions) -> Result<KeyList, ClientError> {
+        let mut resp = self.post("keys", pbenc_iter_req(opts.start_key, opts.prefix))?;
+        if resp.status() != StatusCode::OK {
+            return Err(ClientError::Status(resp.status()));
+        }
+
+        // decode protobuf list-of-keys response
+        let mut body: Vec<u8> = vec![];
+        resp.copy_to(&mut body)?;
+        let in_msg = parse_from_bytes::<KeyResponse>(&body)?;
+
+        Ok(KeyList {
+            keys: in_msg.get_keys().to_vec(),
+            list_end: in_msg.list_end,
+        })
+    }
+
+    /// List records, in db order, resuming after key after, if any.  If
+    /// EntryList.list_end is false, continue after the last key returned.
+    pub fn entries(&self, after: Option<&[u8]>) -> Result<EntryList, ClientError> {
+        let op = match after {
+            None => String::from("_entries"),
+            Some(key) => format!("_entries?after={}", query_b64(key)),
+        };
+        let mut resp = self.get_pb(&op)?;
+        if resp.status() != StatusCode::OK {
+            return Err(ClientError::Status(resp.status()));
+        }
+
+        // decode protobuf list-of-records response
+        let mut body: Vec<u8> = vec![];
+        resp.copy_to(&mut body)?;
+        Ok(pbdec_entries_resp(&body)?)
+    }
+}
+
+/// Client for a single database, via a kvdbd binary protocol listener.
+/// Requests are sent one at a time, on one connection.
+pub struct BinClient {
+    stream: TcpStream,
+    db: String,
+}
+
+impl BinClient {
+    /// Connect to database `db` at `addr`, e.g. "127.0.0.1:8081".
+    pub fn connect<A: ToSocketAddrs>(addr: A, db: &str) -> Result<BinClient, ClientError> {
+        Ok(BinClient {
+            stream: TcpStream::connect(addr)?,
+            db: db.to_string(),
+        })
+    }
+
+    /// Present api_key, as the server requires before any other request
+    /// if its api_key is set.  A wrong key closes the connection.
+    pub fn auth(&mut self, api_key: &str) -> Result<(), ClientError> {
+        self.call_ok(binproto::OP_AUTH, &[api_key.as_bytes()])?;
+        Ok(())
+    }
+
+    // send one request, returning response status and remaining body
+    fn call(&mut self, op: u8, fields: &[&[u8]]) -> Result<(u8, Vec<u8>), ClientError> {
+        let req = binproto::enc_request(op, &self.db, fields);
+        binproto::write_frame(&mut self.stream, &req)?;
+
+        let mut resp = match binproto::read_frame(&mut self.stream)? {
+            None => return Err(ClientError::Protocol("connection closed")),
+            Some(resp) => resp,
+        };
+        if resp.is_empty() {
+            return Err(ClientError::Protocol("empty response"));
+        }
+        let status = resp.remove(0);
+        Ok((status, resp))
+    }
+
+    // check for ok status, returning body; any other status is an error
+    fn call_ok(&mut self, op: u8, fields: &[&[u8]]) -> Result<Vec<u8>, ClientError> {
+        match self.call(op, fields)? {
+            (binproto::ST_OK, body) => Ok(body),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    /// Lookup value by key.  Returns None, if key not found.
+    pub fn get(&mut self, key: &[u8]) -> Result<Option<Vec<u8>>, ClientError> {
+        match self.call(binproto::OP_GET, &[key])? {
+            (binproto::ST_OK, body) => Ok(Some(binproto::Reader::new(&body).bytes()?.to_vec())),
+            (binproto::ST_NOT_FOUND, _body) => Ok(None),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    /// Store key and value.
+    pub fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, ClientError> {
+        self.call_ok(binproto::OP_PUT, &[key, val])?;
+        Ok(true)
+    }
+
+    /// Remove record by key.  Returns false, if key not found.
+    pub fn del(&mut self, key: &[u8]) -> Result<bool, ClientError> {
+        match self.call(binproto::OP_DEL, &[key])? {
+            (binproto::ST_OK, _body) => Ok(true),
+            (binproto::ST_NOT_FOUND, _body) => Ok(false),
+            (status, body) => Err(status_error(status, &body)),
+        }
+    }
+
+    /// Atomically apply all mutations in batch.
+    pub fn batch(&mut self, batch: &Batch) -> Result<bool, ClientError> {
+        self.call_ok(binproto::OP_BATCH, &[&pbenc_batch_req(batch)])?;
+        Ok(true)
+    }
+
+    /// List keys, in db order, as KvdbClient::iter().
+    pub fn iter(&mut self, opts: IterOptions) -> Result<KeyList, ClientError> {
+        let start_key = opts.start_key.unwrap_or_default();
+        let prefix = opts.prefix.unwrap_or_default();
+        let body = self.call_ok(binproto::OP_KEYS, &[&start_key, &prefix])?;
+
+        let mut rd = binproto::Reader::new(&body);
+        let n_keys = rd.varint()?;
+        let mut keys = Vec::new();
+        for _i in 0..n_keys {
+            keys.push(rd.bytes()?.to_vec());
+        }
+        let list_end = rd.byte()? != 0;
+
+        Ok(KeyList { keys, list_end })
+    }
+
+    /// Count records in db.
+    pub fn stat(&mut self) -> Result<u64, ClientError> {
+        let body = self.call_ok(binproto::OP_STAT, &[])?;
+        Ok(binproto::Reader::new(&body).varint()?)
+    }
+}
+
+// error status response carries a message
+fn status_error(status: u8, body: &[u8]) -> ClientError {
+    let msg = match binproto::Reader::new(body).bytes() {
+        Ok(msg) => String::from_utf8_lossy(msg).into_owned(),
+        Err(_e) => String::new(),
+    };
+    ClientError::BinStatus(status, msg)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protos::pbapi::EntryResponse_Entry;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+
+    // HTTP server answering its first n_fail requests 503, and the rest
+    // 200, one request per connection.  Returns endpoint, and the
+    // requests seen, as text
+    fn flaky_server(n_fail: usize) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let seen_srv = Arc::clone(&seen);
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let req = read_request(&mut stream);
+                let n_seen = {
+                    let mut seen = seen_srv.lock().unwrap();
+                    seen.push(req);
+                    seen.len()
+                };
+                let resp: &[u8] = if n_seen <= n_fail {
+                    b"HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                } else {
+                    b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nConnection: close\r\n\r\nvalue"
+                };
+                stream.write_all(resp).unwrap();
+            }
+        });
+        (endpoint, seen)
+    }
+
+    // read request header, and body of content-length
+    fn read_request(stream: &mut TcpStream) -> String {
+        let mut buf = Vec::new();
+        let mut byte = [0u8; 1];
+        while !buf.ends_with(b"\r\n\r\n") {
+            stream.read_exact(&mut byte).unwrap();
+            buf.push(byte[0]);
+        }
+        let head = String::from_utf8_lossy(&buf).to_lowercase();
+        let body_len: usize = head
+            .lines()
+            .find_map(|line| line.strip_prefix("content-length:"))
+            .map_or(0, |len| len.trim().parse().unwrap());
+        let mut body = vec![0u8; body_len];
+        stream.read_exact(&mut body).unwrap();
+        head
+    }
+
+    fn test_client(endpoint: &str, max_attempts: u32) -> KvdbClient {
+        let mut kc = KvdbClient::new(endpoint, "db1");
+        let mut builder = RetryPolicyBuilder::new();
+        builder
+            .max_attempts(max_attempts)
+            .base_backoff(Duration::from_millis(1));
+        kc.set_retry_policy(builder.build());
+        kc
+    }
+
+    #[test]
+    fn test_pbenc_batch() {
+        let mut batch = Batch::default();
+        batch.insert(b"a", &[0xff, 0x00]);
+        batch.remove(b"b");
+        batch.insert(b"", b"");
+
+        let in_msg = parse_from_bytes::<BatchRequest>(&pbenc_batch_req(&batch)).unwrap();
+        assert_eq!(in_msg.magic, BatchRequest_MagicNum::MAGIC);
+        let ops: Vec<(&[u8], &[u8], bool)> = in_msg
+            .get_reqs()
+            .iter()
+            .map(|req| (req.get_key(), req.get_value(), req.is_insert))
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                (&b"a"[..], &[0xff, 0x00][..], true),
+                (b"b", b"", false),
+                (b"", b"", true)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pbdec_entries() {
+        let mut out_msg = EntryResponse::new();
+        out_msg.magic = EntryResponse_MagicNum::MAGIC;
+        for (key, val) in [(&b"a"[..], &[0xff, 0x00][..]), (b"b", b"")] {
+            let mut entry = EntryResponse_Entry::new();
+            entry.set_key(key.to_vec());
+            entry.set_value(val.to_vec());
+            out_msg.entries.push(entry);
+        }
+        let wiredata = out_msg.write_to_bytes().unwrap();
+
+        let entry_list = pbdec_entries_resp(&wiredata).unwrap();
+        assert_eq!(
+            entry_list.entries,
+            vec![
+                (b"a".to_vec(), vec![0xff, 0x00]),
+                (b"b".to_vec(), Vec::new())
+            ]
+        );
+        assert!(!entry_list.list_end);
+
+        // another message, or none, is refused
+        assert!(pbdec_entries_resp(&pbenc_key_req(b"a")).is_err());
+        assert!(pbdec_entries_resp(b"\xff").is_err());
+        assert!(pbdec_entries_resp(&EntryResponse::new().write_to_bytes().unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_retry_policy() {
+        let policy = RetryPolicyBuilder::new().build();
+        assert_eq!(policy, RetryPolicy::default());
+        assert_eq!(
+            RetryPolicyBuilder::new()
+                .max_attempts(0)
+                .build()
+                .max_attempts,
+            1
+        );
+
+        // exponential, less up to jitter
+        let mut builder = RetryPolicyBuilder::new();
+        builder.base_backoff(Duration::from_millis(100)).jitter(0.5);
+        let policy = builder.build();
+        for retry in 1..4 {
+            let full = Duration::from_millis(100) * 2u32.pow(retry - 1);
+            let wait = policy.backoff(retry);
+            assert!(wait <= full && wait >= full / 2);
+        }
+        builder.jitter(0.0);
+        assert_eq!(builder.build().backoff(3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_retry_flaky() {
+        // succeeds within the retry budget
+        let (endpoint, seen) = flaky_server(2);
+        let kc = test_client(&endpoint, 3);
+        assert_eq!(kc.get(b"key").unwrap(), Some(b"value".to_vec()));
+        assert_eq!(seen.lock().unwrap().len(), 3);
+
+        // budget exhausted:  the last failure is returned
+        let (endpoint, seen) = flaky_server(3);
+        let kc = test_client(&endpoint, 3);
+        match kc.get(b"key") {
+            Err(ClientError::Status(status)) => {
+                assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE)
+            }
+            _ => panic!("unexpected result"),
+        }
+        assert_eq!(seen.lock().unwrap().len(), 3);
+
+        // connection refused is retried too
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        drop(listener);
+        match test_client(&endpoint, 2).del(b"key") {
+            Err(ClientError::Http(_e)) => {}
+            _ => panic!("unexpected result"),
+        }
+    }
+
+    #[test]
+    fn test_retry_writes() {
+        // writes without idempotency key are sent once
+        let (endpoint, seen) = flaky_server(1);
+        let kc = test_client(&endpoint, 3);
+        match kc.put(b"key", b"value") {
+            Err(ClientError::Status(status)) => {
+                assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE)
+            }
+            _ => panic!("unexpected result"),
+        }
+        assert_eq!(seen.lock().unwrap().len(), 1);
+
+        // with one, retried, sending the key each attempt
+        let (endpoint, seen) = flaky_server(1);
+        let kc = test_client(&endpoint, 3);
+        assert!(kc.put_idempotent(b"key", b"value", "op-1").unwrap());
+        let mut batch = Batch::default();
+        batch.insert(b"key", b"value");
+        assert!(kc.batch_idempotent(&batch, "op-2").unwrap());
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 3);
+        assert!(seen[0].contains("idempotency-key: op-1"));
+        assert!(seen[1].contains("idempotency-key: op-1"));
+        assert!(seen[2].contains("idempotency-key: op-2"));
+    }
+}
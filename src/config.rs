@@ -0,0 +1,1300 @@
+/*
+ * config: Server configuration file schema, parsing and validation
+ *
+ * The configuration file is JSON, or TOML if its name ends in ".toml".
+ * Both formats share one schema; see doc/config.md.
+ */
+
+use std::collections::HashSet;
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::accesslog::LogFormat;
+use crate::db::api::{
+    merge_json_shallow, Eviction, KeyCase, MergeFn, DEF_MAX_KEY_LEN, DEF_MAX_VALUE_LEN,
+    MAX_ITER_KEYS,
+};
+use crate::db::encrypt::ENCRYPTION_KEY_LEN;
+
+pub const DEF_MAX_MGET_KEYS: usize = 1000;
+pub const DEF_API_BASE: &str = "/api";
+pub const DEF_TXN_TIMEOUT_SECS: u64 = 30;
+pub const DEF_SCAN_TIMEOUT_SECS: u64 = 60;
+pub const DEF_SHUTDOWN_GRACE_SECS: u64 = 30;
+pub const DEF_GROUP_COMMIT_MAX_OPS: usize = 64;
+pub const DEF_MAX_REQUEST_BYTES: usize = 262_144;
+
+/// Placeholder in a db path, replaced by the db's name.
+pub const DB_ID_PLACEHOLDER: &str = "{db_id}";
+
+// path of each db created at runtime, beneath runtime_dbs.dir
+const DEF_RUNTIME_PATH_TEMPLATE: &str = DB_ID_PLACEHOLDER;
+
+/// True if db_id may replace DB_ID_PLACEHOLDER in a path:  not empty, "."
+/// or "..", and without path separators or NUL, so that the path it makes
+/// stays where its template puts it.
+pub fn safe_db_id(db_id: &str) -> bool {
+    !db_id.is_empty() && db_id != "." && db_id != ".." && !db_id.contains(['/', '\\', '\0'])
+}
+
+/// Path template, with each DB_ID_PLACEHOLDER replaced by db_id.  A
+/// template without a placeholder is returned as is, whatever db_id.
+pub fn expand_path_template(template: &str, db_id: &str) -> Result<String, String> {
+    if !template.contains(DB_ID_PLACEHOLDER) {
+        return Ok(template.to_string());
+    }
+    if !safe_db_id(db_id) {
+        return Err(format!("db name \"{db_id}\" unsafe in path"));
+    }
+    Ok(template.replace(DB_ID_PLACEHOLDER, db_id))
+}
+
+// struct used for both input (server config file) and output (server info)
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DbConfig {
+    pub name: String,
+    pub path: String, // may contain DB_ID_PLACEHOLDER, expanded when parsed
+    pub driver: String,
+    pub read_only: bool,
+    #[serde(default)]
+    pub replica: bool, // open a snapshot copy, read-only and without locking
+    #[serde(default)]
+    pub missing_as_empty: bool, // GET of absent key returns 200 + empty body
+    #[serde(default)]
+    pub key_case: String, // "case_sensitive" (default) or "lowercase_fold"
+    #[serde(default)]
+    pub ttl_sweep_secs: u64, // interval between expired-key sweeps; 0 = never
+    #[serde(default)]
+    pub default_ttl_secs: Option<u64>, // TTL of writes giving none; none = never
+    #[serde(default)]
+    pub auto_compact_interval_secs: u64, // interval between compactions; 0 = never
+    #[serde(default)]
+    pub default_cache_control: Option<String>, // Cache-Control sent with GET values
+    #[serde(default = "def_max_mget_keys")]
+    pub max_mget_keys: usize, // max keys per _mget request
+    #[serde(default = "def_max_iter_keys")]
+    pub max_iter_keys: usize, // max keys per listing page
+    #[serde(default)]
+    pub max_page_bytes: Option<usize>, // max serialized records per list page
+    #[serde(default)]
+    pub count_cache_secs: u64, // lifetime of cached _count results; 0 = no cache
+    #[serde(default)]
+    pub treat_empty_put_as_delete: bool, // PUT of empty value removes key
+    #[serde(default)]
+    pub slow_op_threshold_ms: Option<u64>, // warn on db ops slower than this
+    #[serde(default)]
+    pub op_timeout_ms: Option<u64>, // fail db ops slower than this, with 503
+    #[serde(default)]
+    pub change_log_len: Option<u64>, // changes retained for _changes; none = disabled
+    #[serde(default)]
+    pub max_keys: Option<u64>, // key count quota
+    #[serde(default)]
+    pub max_total_bytes: Option<u64>, // key plus value bytes quota
+    #[serde(default)]
+    pub eviction: String, // on reaching the quota: "none" (default), "lru" or "fifo"
+    #[serde(default)]
+    pub write_failure_threshold: Option<u64>, // failed writes before read-only
+    #[serde(default)]
+    pub value_metadata: bool, // store metadata headers with PUT values
+    #[serde(default)]
+    pub compress_values: bool, // gzip values at rest
+    #[serde(default)]
+    pub encryption_key_file: Option<String>, // key to encrypt values at rest, in hex
+    #[serde(default)]
+    pub verify_checksums: bool, // checksum values at rest, verified on read
+    #[serde(default)]
+    pub track_modified: bool, // Last-Modified of values, on GET
+    #[serde(default)]
+    pub track_versions: bool, // version of each key, on GET, checked by PUT
+    #[serde(default)]
+    pub track_hot_keys: bool, // approximate GET counts of most read keys
+    #[serde(default)]
+    pub sync_writes: bool, // fsync every write before responding
+    #[serde(default = "def_max_key_len")]
+    pub max_key_len: usize, // longest key written, in bytes; 0 = unlimited
+    #[serde(default = "def_max_value_len")]
+    pub max_value_len: usize, // longest value written, in bytes; 0 = unlimited
+    #[serde(default)]
+    pub group_commit_window_us: u64, // PUT group collection window; 0 = disabled
+    #[serde(default = "def_group_commit_max_ops")]
+    pub group_commit_max_ops: usize, // max PUTs per group
+    #[serde(default)]
+    pub disabled_ops: Vec<OpKind>, // operations refused with 403
+    #[serde(default)]
+    pub merge: String, // merge operator: "replace" (default) or "json_shallow"
+}
+
+// merge operators of POST obj/{key}/merge, for merge
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MergeOp {
+    Replace,     // last write wins
+    JsonShallow, // fields of JSON objects, per api::merge_json_shallow
+}
+
+impl MergeOp {
+    /// Backend merge operator, or None for the default, last-write-wins.
+    pub fn merge_fn(self) -> Option<MergeFn> {
+        match self {
+            MergeOp::Replace => None,
+            MergeOp::JsonShallow => Some(merge_json_shallow),
+        }
+    }
+}
+
+// classes of db operation, for disabled_ops
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum OpKind {
+    Get,    // value reads:  GET obj, get, _mget, _exists
+    Put,    // value writes:  PUT and POST obj, alloc, put, _swap, flush
+    Delete, // DELETE obj, del, DELETE keys
+    Batch,  // batch, _import, restore, _multibatch
+    Clear,  // clear, and _import?clear=1 or restore?clear=1
+    Iter,   // keys, _keys, _entries, _scan, _export, dump, _changes, watch
+    Stat,   // stat, stats, _size, _histogram, _count
+    Txn,    // _txn
+    Sweep,  // _sweep, compact
+}
+
+impl OpKind {
+    /// Name of this class, as in disabled_ops, e.g. "get".
+    pub fn name(self) -> &'static str {
+        match self {
+            OpKind::Get => "get",
+            OpKind::Put => "put",
+            OpKind::Delete => "delete",
+            OpKind::Batch => "batch",
+            OpKind::Clear => "clear",
+            OpKind::Iter => "iter",
+            OpKind::Stat => "stat",
+            OpKind::Txn => "txn",
+            OpKind::Sweep => "sweep",
+        }
+    }
+
+    /// True if operations of this class may modify a db.
+    pub fn is_write(self) -> bool {
+        !matches!(self, OpKind::Get | OpKind::Iter | OpKind::Stat)
+    }
+}
+
+fn def_max_mget_keys() -> usize {
+    DEF_MAX_MGET_KEYS
+}
+
+fn def_max_iter_keys() -> usize {
+    MAX_ITER_KEYS
+}
+
+fn def_max_key_len() -> usize {
+    DEF_MAX_KEY_LEN
+}
+
+fn def_max_value_len() -> usize {
+    DEF_MAX_VALUE_LEN
+}
+
+fn def_group_commit_max_ops() -> usize {
+    DEF_GROUP_COMMIT_MAX_OPS
+}
+
+impl DbConfig {
+    /// Key case mode named by key_case, or None if unsupported.
+    pub fn key_case_mode(&self) -> Option<KeyCase> {
+        match self.key_case.as_str() {
+            "" | "case_sensitive" => Some(KeyCase::CaseSensitive),
+            "lowercase_fold" => Some(KeyCase::LowercaseFold),
+            _ => None,
+        }
+    }
+
+    /// Merge operator named by merge, or None if unsupported.
+    pub fn merge_op(&self) -> Option<MergeOp> {
+        match self.merge.as_str() {
+            "" | "replace" => Some(MergeOp::Replace),
+            "json_shallow" => Some(MergeOp::JsonShallow),
+            _ => None,
+        }
+    }
+
+    /// Key to encrypt values with, read from encryption_key_file, if set:
+    /// ENCRYPTION_KEY_LEN bytes, as hex digits, surrounding whitespace
+    /// ignored.
+    pub fn encryption_key(&self) -> Result<Option<Vec<u8>>, String> {
+        let path = match &self.encryption_key_file {
+            None => return Ok(None),
+            Some(path) => path,
+        };
+        let text = fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let hex = text.trim();
+        if hex.len() != ENCRYPTION_KEY_LEN * 2 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+            return Err(format!(
+                "{}: not {} hex digits",
+                path,
+                ENCRYPTION_KEY_LEN * 2
+            ));
+        }
+        let key = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect();
+        Ok(Some(key))
+    }
+
+    /// Eviction policy named by eviction, or None if unsupported.
+    pub fn eviction_mode(&self) -> Option<Eviction> {
+        match self.eviction.as_str() {
+            "" | "none" => Some(Eviction::None),
+            "lru" => Some(Eviction::Lru),
+            "fifo" => Some(Eviction::Fifo),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct SslConfig {
+    pub private_key_path: String, // empty, if no SSL
+    pub cert_chain_path: String,  // empty, if no SSL
+}
+
+impl SslConfig {
+    pub fn new() -> SslConfig {
+        SslConfig {
+            private_key_path: String::new(),
+            cert_chain_path: String::new(),
+        }
+    }
+
+    pub fn enabled(&self) -> bool {
+        !self.private_key_path.is_empty() && !self.cert_chain_path.is_empty()
+    }
+}
+
+// HTTP listen address; command line options take precedence
+#[derive(Serialize, Deserialize, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct ListenConfig {
+    #[serde(default)]
+    pub addr: Option<String>, // bind address, e.g. "0.0.0.0"
+    #[serde(default)]
+    pub port: Option<u16>,
+}
+
+// databases created at runtime, by PUT of a db name, rather than listed
+// under databases
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct RuntimeDbsConfig {
+    pub dir: String,    // base directory; each db is stored at dir/name
+    pub driver: String, // driver of every created db, e.g. "sled"
+    #[serde(default)]
+    pub create_on_use: bool, // also create a db when a request first names it
+    #[serde(default)]
+    pub path_template: Option<String>, // path beneath dir; default "{db_id}"
+    #[serde(default)]
+    pub key_case: String, // key_case of every created db, as DbConfig's
+}
+
+// longest name of a db created at runtime
+const MAX_RUNTIME_DB_NAME: usize = 64;
+
+impl RuntimeDbsConfig {
+    /// True if name may be given to a db created at runtime:  ASCII
+    /// letters, digits, '-', '_' and '.', not beginning with '_' (as
+    /// admin paths do) or '.', so that dir/name stays beneath dir.
+    pub fn valid_name(name: &str) -> bool {
+        !name.is_empty()
+            && name.len() <= MAX_RUNTIME_DB_NAME
+            && !name.starts_with('_')
+            && !name.starts_with('.')
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'.')
+    }
+
+    /// Path of db name, created at runtime, beneath dir:  path_template,
+    /// expanded with name.  Valid names are all safe to expand.
+    pub fn db_path(&self, name: &str) -> String {
+        let template = self
+            .path_template
+            .as_deref()
+            .unwrap_or(DEF_RUNTIME_PATH_TEMPLATE);
+        Path::new(&self.dir)
+            .join(template.replace(DB_ID_PLACEHOLDER, name))
+            .to_string_lossy()
+            .into_owned()
+    }
+
+    /// Configuration of db name, created at runtime, with default settings.
+    pub fn db_config(&self, name: &str) -> DbConfig {
+        DbConfig {
+            name: name.to_string(),
+            path: self.db_path(name),
+            driver: self.driver.clone(),
+            read_only: false,
+            replica: false,
+            missing_as_empty: false,
+            key_case: self.key_case.clone(),
+            ttl_sweep_secs: 0,
+            default_ttl_secs: None,
+            auto_compact_interval_secs: 0,
+            default_cache_control: None,
+            max_mget_keys: DEF_MAX_MGET_KEYS,
+            max_iter_keys: MAX_ITER_KEYS,
+            max_page_bytes: None,
+            count_cache_secs: 0,
+            treat_empty_put_as_delete: false,
+            slow_op_threshold_ms: None,
+            op_timeout_ms: None,
+            change_log_len: None,
+            max_keys: None,
+            max_total_bytes: None,
+            eviction: String::new(),
+            write_failure_threshold: None,
+            value_metadata: false,
+            compress_values: false,
+            encryption_key_file: None,
+            verify_checksums: false,
+            track_modified: false,
+            track_versions: false,
+            track_hot_keys: false,
+            sync_writes: false,
+            max_key_len: DEF_MAX_KEY_LEN,
+            max_value_len: DEF_MAX_VALUE_LEN,
+            group_commit_window_us: 0,
+            group_commit_max_ops: DEF_GROUP_COMMIT_MAX_OPS,
+            disabled_ops: Vec::new(),
+            merge: String::new(),
+        }
+    }
+}
+
+// top-level schema for server configuration file
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct ServerConfig {
+    #[serde(default)]
+    pub debug: bool,
+    #[serde(default)]
+    pub listen: ListenConfig,
+    #[serde(default = "SslConfig::new")]
+    pub ssl: SslConfig,
+    pub databases: Vec<DbConfig>,
+    #[serde(default)]
+    pub access_log_path: Option<String>, // request audit log file, if any
+    #[serde(default)]
+    pub access_log_format: String, // "common" (default) or "json"
+    #[serde(default)]
+    pub audit_log_path: Option<String>, // log of every write, if any
+    #[serde(default)]
+    pub audit_log_values: bool, // log values written, not only their digests
+    #[serde(default = "def_api_base")]
+    pub api_base: String, // path prefix of API routes; empty = mounted at root
+    #[serde(default)]
+    pub http2_cleartext: bool, // accept h2c (prior knowledge) on non-TLS listener
+    #[serde(default)]
+    pub max_connections: Option<usize>, // concurrent client connections served
+    #[serde(default = "def_max_request_bytes")]
+    pub max_request_bytes: usize, // longest request body read, in bytes
+    #[serde(default)]
+    pub binary_port: Option<u16>, // binary protocol listener port, if any
+    #[serde(default = "def_txn_timeout_secs")]
+    pub txn_timeout_secs: u64, // idle time before an open transaction is rolled back
+    #[serde(default = "def_scan_timeout_secs")]
+    pub scan_timeout_secs: u64, // idle time before an open snapshot scan is closed
+    #[serde(default = "def_shutdown_grace_secs")]
+    pub shutdown_grace_secs: u64, // time given requests in progress at shutdown
+    #[serde(default)]
+    pub recover_poisoned_lock: bool, // keep serving after a panic under the state lock
+    #[serde(default)]
+    pub api_key: Option<String>, // bearer token required of API requests, if set
+    #[serde(default)]
+    pub rate_limit_rps: u32, // API requests per second per client IP; 0 = unlimited
+    #[serde(default)]
+    pub metrics_enabled: bool, // serve GET /metrics, in Prometheus text format
+    #[serde(default)]
+    pub runtime_dbs: Option<RuntimeDbsConfig>, // dbs created via the API, if allowed
+}
+
+fn def_api_base() -> String {
+    String::from(DEF_API_BASE)
+}
+
+fn def_txn_timeout_secs() -> u64 {
+    DEF_TXN_TIMEOUT_SECS
+}
+
+fn def_scan_timeout_secs() -> u64 {
+    DEF_SCAN_TIMEOUT_SECS
+}
+
+fn def_shutdown_grace_secs() -> u64 {
+    DEF_SHUTDOWN_GRACE_SECS
+}
+
+fn def_max_request_bytes() -> usize {
+    DEF_MAX_REQUEST_BYTES
+}
+
+impl Default for ServerConfig {
+    /// Configuration with no databases, and every setting at the default
+    /// it takes when omitted from a configuration file.
+    fn default() -> ServerConfig {
+        ServerConfig {
+            debug: false,
+            listen: ListenConfig::default(),
+            ssl: SslConfig::new(),
+            databases: Vec::new(),
+            access_log_path: None,
+            access_log_format: String::new(),
+            audit_log_path: None,
+            audit_log_values: false,
+            api_base: def_api_base(),
+            http2_cleartext: false,
+            max_connections: None,
+            max_request_bytes: def_max_request_bytes(),
+            binary_port: None,
+            txn_timeout_secs: def_txn_timeout_secs(),
+            scan_timeout_secs: def_scan_timeout_secs(),
+            shutdown_grace_secs: def_shutdown_grace_secs(),
+            recover_poisoned_lock: false,
+            api_key: None,
+            rate_limit_rps: 0,
+            metrics_enabled: false,
+            runtime_dbs: None,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigFormat {
+    Json,
+    Toml,
+}
+
+impl ConfigFormat {
+    /// Format of the named file:  TOML if it ends in ".toml", else JSON.
+    pub fn from_path(path: &str) -> ConfigFormat {
+        match Path::new(path).extension() {
+            Some(ext) if ext == "toml" => ConfigFormat::Toml,
+            _ => ConfigFormat::Json,
+        }
+    }
+}
+
+/// Settings changed by a reloaded configuration file, by name, e.g.
+/// "txn_timeout_secs" or "databases.db1.max_mget_keys".
+#[derive(Debug, Default, PartialEq)]
+pub struct ConfigChanges {
+    pub live: Vec<String>,    // may be applied to the running server
+    pub restart: Vec<String>, // take effect only at restart
+}
+
+impl ConfigChanges {
+    // record the name of each changed field, beneath prefix
+    fn note(names: &mut Vec<String>, prefix: &str, fields: &[(&str, bool)]) {
+        for (field, changed) in fields {
+            if *changed {
+                names.push(format!("{prefix}{field}"));
+            }
+        }
+    }
+
+    fn note_db(&mut self, old: &DbConfig, new: &DbConfig) {
+        let prefix = format!("databases.{}.", old.name);
+        ConfigChanges::note(
+            &mut self.live,
+            &prefix,
+            &[
+                (
+                    "missing_as_empty",
+                    old.missing_as_empty != new.missing_as_empty,
+                ),
+                (
+                    "default_cache_control",
+                    old.default_cache_control != new.default_cache_control,
+                ),
+                ("max_mget_keys", old.max_mget_keys != new.max_mget_keys),
+                ("max_page_bytes", old.max_page_bytes != new.max_page_bytes),
+                (
+                    "count_cache_secs",
+                    old.count_cache_secs != new.count_cache_secs,
+                ),
+                (
+                    "treat_empty_put_as_delete",
+                    old.treat_empty_put_as_delete != new.treat_empty_put_as_delete,
+                ),
+            ],
+        );
+
+        // fixed when the db is opened, or its sweeper or compactor started
+        ConfigChanges::note(
+            &mut self.restart,
+            &prefix,
+            &[
+                ("path", old.path != new.path),
+                ("driver", old.driver != new.driver),
+                ("read_only", old.read_only != new.read_only),
+                ("replica", old.replica != new.replica),
+                ("key_case", old.key_case_mode() != new.key_case_mode()),
+                ("ttl_sweep_secs", old.ttl_sweep_secs != new.ttl_sweep_secs),
+                (
+                    "default_ttl_secs",
+                    old.default_ttl_secs != new.default_ttl_secs,
+                ),
+                (
+                    "auto_compact_interval_secs",
+                    old.auto_compact_interval_secs != new.auto_compact_interval_secs,
+                ),
+                (
+                    "slow_op_threshold_ms",
+                    old.slow_op_threshold_ms != new.slow_op_threshold_ms,
+                ),
+                ("op_timeout_ms", old.op_timeout_ms != new.op_timeout_ms),
+                ("max_iter_keys", old.max_iter_keys != new.max_iter_keys),
+                ("change_log_len", old.change_log_len != new.change_log_len),
+                ("max_keys", old.max_keys != new.max_keys),
+                (
+                    "max_total_bytes",
+                    old.max_total_bytes != new.max_total_bytes,
+                ),
+                ("eviction", old.eviction_mode() != new.eviction_mode()),
+                (
+                    "write_failure_threshold",
+                    old.write_failure_threshold != new.write_failure_threshold,
+                ),
+                ("value_metadata", old.value_metadata != new.value_metadata),
+                (
+                    "compress_values",
+                    old.compress_values != new.compress_values,
+                ),
+                (
+                    "encryption_key_file",
+                    old.encryption_key_file != new.encryption_key_file,
+                ),
+                (
+                    "verify_checksums",
+                    old.verify_checksums != new.verify_checksums,
+                ),
+                ("track_modified", old.track_modified != new.track_modified),
+                ("track_versions", old.track_versions != new.track_versions),
+                ("track_hot_keys", old.track_hot_keys != new.track_hot_keys),
+                ("sync_writes", old.sync_writes != new.sync_writes),
+                ("max_key_len", old.max_key_len != new.max_key_len),
+                ("max_value_len", old.max_value_len != new.max_value_len),
+                ("merge", old.merge_op() != new.merge_op()),
+                (
+                    "group_commit_window_us",
+                    old.group_commit_window_us != new.group_commit_window_us,
+                ),
+                (
+                    "group_commit_max_ops",
+                    old.group_commit_max_ops != new.group_commit_max_ops,
+                ),
+                ("disabled_ops", old.disabled_ops != new.disabled_ops),
+            ],
+        );
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),   // file could not be read
+    Parse(String),   // malformed JSON or TOML, or schema mismatch
+    Invalid(String), // well-formed, but a setting is invalid
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "read failed: {e}"),
+            ConfigError::Parse(e) => write!(f, "parse failed: {e}"),
+            ConfigError::Invalid(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl error::Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+    fn from(e: io::Error) -> ConfigError {
+        ConfigError::Io(e)
+    }
+}
+
+fn invalid(msg: String) -> Result<(), ConfigError> {
+    Err(ConfigError::Invalid(msg))
+}
+
+impl ServerConfig {
+    /// Read, parse and validate configuration file.
+    pub fn from_file(path: &str) -> Result<ServerConfig, ConfigError> {
+        let text = fs::read_to_string(path)?;
+        ServerConfig::parse(&text, ConfigFormat::from_path(path))
+    }
+
+    /// Parse and validate configuration text.
+    pub fn parse(text: &str, format: ConfigFormat) -> Result<ServerConfig, ConfigError> {
+        let mut cfg: ServerConfig = match format {
+            ConfigFormat::Json => {
+                serde_json::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            ConfigFormat::Toml => {
+                toml::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+        };
+        for db_cfg in &mut cfg.databases {
+            db_cfg.path = expand_path_template(&db_cfg.path, &db_cfg.name)
+                .map_err(|e| ConfigError::Invalid(format!("Database path: {e}.")))?;
+        }
+        cfg.validate()?;
+
+        Ok(cfg)
+    }
+
+    /// API base path, without trailing '/'; "/" is the same as "".
+    pub fn api_base_path(&self) -> String {
+        self.api_base.trim_end_matches('/').to_string()
+    }
+
+    /// Settings changed between this configuration and new, grouped by
+    /// whether they may be applied live.  Databases are matched by name;
+    /// one added or removed is a restart change.
+    pub fn changes(&self, new: &ServerConfig) -> ConfigChanges {
+        let mut changes = ConfigChanges::default();
+        ConfigChanges::note(
+            &mut changes.live,
+            "",
+            &[
+                ("debug", self.debug != new.debug),
+                (
+                    "txn_timeout_secs",
+                    self.txn_timeout_secs != new.txn_timeout_secs,
+                ),
+                (
+                    "scan_timeout_secs",
+                    self.scan_timeout_secs != new.scan_timeout_secs,
+                ),
+                (
+                    "recover_poisoned_lock",
+                    self.recover_poisoned_lock != new.recover_poisoned_lock,
+                ),
+            ],
+        );
+
+        // fixed when listeners and the access log are started
+        ConfigChanges::note(
+            &mut changes.restart,
+            "",
+            &[
+                ("listen", self.listen != new.listen),
+                ("ssl", self.ssl != new.ssl),
+                (
+                    "access_log_path",
+                    self.access_log_path != new.access_log_path,
+                ),
+                (
+                    "access_log_format",
+                    self.access_log_format != new.access_log_format,
+                ),
+                ("audit_log_path", self.audit_log_path != new.audit_log_path),
+                (
+                    "audit_log_values",
+                    self.audit_log_values != new.audit_log_values,
+                ),
+                ("api_base", self.api_base_path() != new.api_base_path()),
+                (
+                    "http2_cleartext",
+                    self.http2_cleartext != new.http2_cleartext,
+                ),
+                (
+                    "max_connections",
+                    self.max_connections != new.max_connections,
+                ),
+                (
+                    "max_request_bytes",
+                    self.max_request_bytes != new.max_request_bytes,
+                ),
+                ("binary_port", self.binary_port != new.binary_port),
+                ("api_key", self.api_key != new.api_key),
+                ("rate_limit_rps", self.rate_limit_rps != new.rate_limit_rps),
+                (
+                    "metrics_enabled",
+                    self.metrics_enabled != new.metrics_enabled,
+                ),
+                ("runtime_dbs", self.runtime_dbs != new.runtime_dbs),
+                (
+                    "shutdown_grace_secs",
+                    self.shutdown_grace_secs != new.shutdown_grace_secs,
+                ),
+            ],
+        );
+
+        for old_db in &self.databases {
+            match new.databases.iter().find(|db| db.name == old_db.name) {
+                None => changes.restart.push(format!("databases.{}", old_db.name)),
+                Some(new_db) => changes.note_db(old_db, new_db),
+            }
+        }
+        for new_db in &new.databases {
+            if !self.databases.iter().any(|db| db.name == new_db.name) {
+                changes.restart.push(format!("databases.{}", new_db.name));
+            }
+        }
+
+        changes
+    }
+
+    /// Check settings which parsing alone does not.  Driver names are
+    /// checked when databases are opened.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let mut names = HashSet::new();
+        for db_cfg in &self.databases {
+            if db_cfg.name.is_empty() || db_cfg.name.contains('/') {
+                return invalid(format!("Invalid db name \"{}\" specified.", db_cfg.name));
+            }
+            if !names.insert(db_cfg.name.as_str()) {
+                return invalid(format!("Duplicate db name {} specified.", db_cfg.name));
+            }
+            if db_cfg.path.is_empty() {
+                return invalid(format!("Database {} has no path.", db_cfg.name));
+            }
+            if db_cfg.replica && !db_cfg.read_only {
+                return invalid(format!(
+                    "Database {} replica requires read_only.",
+                    db_cfg.name
+                ));
+            }
+            if let Err(e) = db_cfg.encryption_key() {
+                return invalid(format!(
+                    "Database {} encryption_key_file {}",
+                    db_cfg.name, e
+                ));
+            }
+            if db_cfg.merge_op().is_none() {
+                return invalid(format!("Unsupported merge {} specified.", db_cfg.merge));
+            }
+            if db_cfg.key_case_mode().is_none() {
+                return invalid(format!(
+                    "Unsupported key_case {} specified.",
+                    db_cfg.key_case
+                ));
+            }
+            match db_cfg.eviction_mode() {
+                None => {
+                    return invalid(format!(
+                        "Unsupported eviction {} specified.",
+                        db_cfg.eviction
+                    ));
+                }
+                Some(Eviction::None) => {}
+                Some(_) if db_cfg.max_keys.is_none() && db_cfg.max_total_bytes.is_none() => {
+                    return invalid(format!(
+                        "Database {} eviction requires max_keys or max_total_bytes.",
+                        db_cfg.name
+                    ));
+                }
+                Some(_) => {}
+            }
+            if db_cfg.max_iter_keys == 0 {
+                return invalid(format!(
+                    "Database {} max_iter_keys must be at least 1.",
+                    db_cfg.name
+                ));
+            }
+            if db_cfg.op_timeout_ms == Some(0) {
+                return invalid(format!(
+                    "Database {} op_timeout_ms must be at least 1.",
+                    db_cfg.name
+                ));
+            }
+            if db_cfg.default_ttl_secs == Some(0) {
+                return invalid(format!(
+                    "Database {} default_ttl_secs must be at least 1.",
+                    db_cfg.name
+                ));
+            }
+            if db_cfg.group_commit_max_ops == 0 {
+                return invalid(format!(
+                    "Database {} group_commit_max_ops must be at least 1.",
+                    db_cfg.name
+                ));
+            }
+        }
+
+        if let Some(runtime_dbs) = &self.runtime_dbs {
+            if runtime_dbs.dir.is_empty() {
+                return invalid(String::from("runtime_dbs has no dir."));
+            }
+            match &runtime_dbs.path_template {
+                Some(template) if !template.contains(DB_ID_PLACEHOLDER) => {
+                    return invalid(format!(
+                        "runtime_dbs path_template {template} lacks {DB_ID_PLACEHOLDER}."
+                    ));
+                }
+                _ => {}
+            }
+            if runtime_dbs.db_config("").key_case_mode().is_none() {
+                return invalid(format!(
+                    "Unsupported runtime_dbs key_case {} specified.",
+                    runtime_dbs.key_case
+                ));
+            }
+        }
+
+        let api_base = self.api_base_path();
+        if !api_base.is_empty() && !api_base.starts_with('/') {
+            return invalid(format!("api_base {} must begin with '/'.", self.api_base));
+        }
+
+        if LogFormat::from_name(&self.access_log_format).is_none() {
+            return invalid(format!(
+                "Unsupported access_log_format {} specified.",
+                self.access_log_format
+            ));
+        }
+
+        if self.binary_port.is_some() && self.binary_port == self.listen.port {
+            return invalid(String::from(
+                "binary_port must differ from the listen port.",
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempdir::TempDir;
+
+    const VALID_TOML: &str = r#"
+api_base = "/kv"
+binary_port = 8081
+api_key = "s3cret"
+rate_limit_rps = 200
+audit_log_path = "audit.log"
+
+[listen]
+addr = "0.0.0.0"
+port = 8080
+
+[ssl]
+private_key_path = "tls-key.pem"
+cert_chain_path = "tls-cert.pem"
+
+[runtime_dbs]
+dir = "dbs"
+driver = "sled"
+key_case = "lowercase_fold"
+
+[[databases]]
+name = "db1"
+path = "db1.kv"
+driver = "sled"
+read_only = false
+max_keys = 1000
+max_total_bytes = 1048576
+eviction = "lru"
+write_failure_threshold = 5
+value_metadata = true
+compress_values = true
+verify_checksums = true
+track_modified = true
+track_versions = true
+track_hot_keys = true
+merge = "json_shallow"
+
+[[databases]]
+name = "db2"
+path = "db2.kv"
+driver = "lmdb"
+read_only = true
+replica = true
+key_case = "lowercase_fold"
+disabled_ops = ["clear", "delete"]
+"#;
+
+    fn parse_err(text: &str, format: ConfigFormat) -> String {
+        match ServerConfig::parse(text, format) {
+            Ok(_cfg) => panic!("config unexpectedly valid"),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_parse_toml() {
+        let cfg = ServerConfig::parse(VALID_TOML, ConfigFormat::Toml).unwrap();
+        assert_eq!(cfg.api_base_path(), "/kv");
+        assert_eq!(cfg.binary_port, Some(8081));
+        assert_eq!(cfg.api_key.as_deref(), Some("s3cret"));
+        assert_eq!(cfg.rate_limit_rps, 200);
+        assert_eq!(cfg.audit_log_path.as_deref(), Some("audit.log"));
+        assert!(!cfg.audit_log_values);
+        assert_eq!(cfg.listen.addr, Some(String::from("0.0.0.0")));
+        assert_eq!(cfg.listen.port, Some(8080));
+        assert!(cfg.ssl.enabled());
+
+        assert_eq!(cfg.databases.len(), 2);
+        let db1 = &cfg.databases[0];
+        assert_eq!((db1.name.as_str(), db1.driver.as_str()), ("db1", "sled"));
+        assert_eq!(db1.max_keys, Some(1000));
+        assert_eq!(db1.max_total_bytes, Some(1048576));
+        assert_eq!(db1.eviction_mode(), Some(Eviction::Lru));
+        assert_eq!(db1.max_mget_keys, DEF_MAX_MGET_KEYS);
+        assert_eq!(db1.max_iter_keys, MAX_ITER_KEYS);
+        assert_eq!(db1.write_failure_threshold, Some(5));
+        assert!(db1.value_metadata);
+        assert!(db1.compress_values);
+        assert!(db1.verify_checksums);
+        assert!(db1.track_modified);
+        assert!(db1.track_versions);
+        assert!(db1.track_hot_keys);
+        assert_eq!(db1.merge_op(), Some(MergeOp::JsonShallow));
+        assert_eq!(db1.group_commit_window_us, 0);
+        assert_eq!(db1.group_commit_max_ops, DEF_GROUP_COMMIT_MAX_OPS);
+        assert!(db1.disabled_ops.is_empty());
+        assert!(!db1.replica);
+        assert!(!db1.sync_writes);
+        assert_eq!(db1.max_key_len, DEF_MAX_KEY_LEN);
+        assert_eq!(db1.max_value_len, DEF_MAX_VALUE_LEN);
+        let db2 = &cfg.databases[1];
+        assert!(db2.read_only);
+        assert!(db2.replica);
+        assert_eq!(db2.max_keys, None);
+        assert_eq!(db2.eviction_mode(), Some(Eviction::None));
+        assert_eq!(db2.write_failure_threshold, None);
+        assert!(!db2.value_metadata);
+        assert!(!db2.compress_values);
+        assert!(!db2.verify_checksums);
+        assert!(!db2.track_modified);
+        assert!(!db2.track_versions);
+        assert_eq!(db2.merge_op(), Some(MergeOp::Replace));
+        assert!(db2.key_case_mode() == Some(KeyCase::LowercaseFold));
+        assert_eq!(db2.disabled_ops, vec![OpKind::Clear, OpKind::Delete]);
+
+        let runtime_dbs = cfg.runtime_dbs.as_ref().unwrap();
+        assert_eq!(runtime_dbs.driver, "sled");
+        assert!(!runtime_dbs.create_on_use);
+        assert_eq!(
+            runtime_dbs.db_config("db3").key_case_mode(),
+            Some(KeyCase::LowercaseFold)
+        );
+
+        // omitted settings take their defaults
+        assert!(!cfg.debug);
+        assert_eq!(cfg.txn_timeout_secs, DEF_TXN_TIMEOUT_SECS);
+        assert_eq!(cfg.scan_timeout_secs, DEF_SCAN_TIMEOUT_SECS);
+        assert_eq!(cfg.shutdown_grace_secs, DEF_SHUTDOWN_GRACE_SECS);
+        assert_eq!(cfg.max_connections, None);
+        assert_eq!(cfg.max_request_bytes, DEF_MAX_REQUEST_BYTES);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let text = r#"{
+            "debug": true,
+            "ssl": {"private_key_path": "", "cert_chain_path": ""},
+            "databases": [
+                {"name": "db1", "path": "db1.kv", "driver": "sled", "read_only": false}
+            ]
+        }"#;
+        let cfg = ServerConfig::parse(text, ConfigFormat::Json).unwrap();
+        assert!(cfg.debug);
+        assert!(!cfg.ssl.enabled());
+        assert_eq!(cfg.api_base_path(), DEF_API_BASE);
+        assert_eq!(cfg.listen.port, None);
+        assert_eq!(cfg.rate_limit_rps, 0);
+        assert_eq!(cfg.databases[0].name, "db1");
+    }
+
+    #[test]
+    fn test_default() {
+        // as a configuration file omitting every setting
+        let cfg = ServerConfig::parse(r#"{"databases": []}"#, ConfigFormat::Json).unwrap();
+        assert_eq!(
+            serde_json::to_value(ServerConfig::default()).unwrap(),
+            serde_json::to_value(cfg).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_parse_examples() {
+        // the example files stay valid, as unknown settings are refused
+        let cfg = ServerConfig::parse(
+            include_str!("../example-cfg-kvdbd.json"),
+            ConfigFormat::Json,
+        );
+        assert!(cfg.is_ok());
+        let cfg = ServerConfig::parse(
+            include_str!("../example-cfg-kvdbd.toml"),
+            ConfigFormat::Toml,
+        );
+        assert!(cfg.is_ok());
+    }
+
+    #[test]
+    fn test_parse_malformed() {
+        // syntax errors
+        let err = parse_err("[[databases]\nname = \"db1\"", ConfigFormat::Toml);
+        assert!(err.starts_with("parse failed"));
+        let err = parse_err("{\"databases\": [", ConfigFormat::Json);
+        assert!(err.starts_with("parse failed"));
+
+        // schema mismatch: missing field, wrong type
+        let err = parse_err(
+            "[[databases]]\nname = \"db1\"\ndriver = \"sled\"\nread_only = false\n",
+            ConfigFormat::Toml,
+        );
+        assert!(err.contains("path"));
+        let text = VALID_TOML.replace("max_keys = 1000", "max_keys = \"many\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).starts_with("parse failed"));
+        let text = VALID_TOML.replace("port = 8080", "port = 80800");
+        assert!(parse_err(&text, ConfigFormat::Toml).starts_with("parse failed"));
+
+        // unknown settings, e.g. misspelt, at any level
+        let text = VALID_TOML.replace("binary_port", "binary_prot");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("unknown field `binary_prot`"));
+        let text = VALID_TOML.replace("max_keys", "max_key");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("unknown field `max_key`"));
+        let text = VALID_TOML.replace("addr =", "address =");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("unknown field `address`"));
+        let err = parse_err("{\"databases\": [], \"debgu\": true}", ConfigFormat::Json);
+        assert!(err.contains("unknown field `debgu`"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        let text = VALID_TOML.replace("name = \"db2\"", "name = \"db1\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("Duplicate db name db1"));
+
+        let text = VALID_TOML.replace("name = \"db2\"", "name = \"db/2\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("Invalid db name"));
+
+        let text = VALID_TOML.replace("path = \"db2.kv\"", "path = \"\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("has no path"));
+
+        let text = VALID_TOML.replace("lowercase_fold", "uppercase");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("key_case uppercase"));
+
+        let text = VALID_TOML.replace("read_only = true", "read_only = false");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("db2 replica requires read_only"));
+
+        let text = VALID_TOML.replace("\"lru\"", "\"random\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("eviction random"));
+
+        let text = VALID_TOML.replace("\"json_shallow\"", "\"json_deep\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("merge json_deep"));
+
+        let text = VALID_TOML.replace("read_only = true", "read_only = true\neviction = \"fifo\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("db2 eviction requires"));
+
+        let text = VALID_TOML.replace("max_keys = 1000", "max_iter_keys = 0");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("db1 max_iter_keys"));
+
+        let text = VALID_TOML.replace("max_keys = 1000", "group_commit_max_ops = 0");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("group_commit_max_ops"));
+        let text = VALID_TOML.replace("max_keys = 1000", "op_timeout_ms = 0");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("op_timeout_ms"));
+        let text = VALID_TOML.replace("max_keys = 1000", "default_ttl_secs = 0");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("default_ttl_secs"));
+
+        let text = VALID_TOML.replace("dir = \"dbs\"", "dir = \"\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("runtime_dbs has no dir"));
+        let text = VALID_TOML.replace("dir = \"dbs\"", "dir = \"dbs\"\npath_template = \"db.kv\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("path_template db.kv lacks"));
+        let text = VALID_TOML.replace(
+            "driver = \"sled\"\nkey_case = \"lowercase_fold\"",
+            "driver = \"sled\"\nkey_case = \"upper\"",
+        );
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("runtime_dbs key_case upper"));
+
+        let text = VALID_TOML
+            .replace("name = \"db2\"", "name = \"..\"")
+            .replace("path = \"db2.kv\"", "path = \"data/{db_id}\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("db name \"..\" unsafe in path"));
+
+        let text = VALID_TOML.replace("\"/kv\"", "\"kv\"");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("api_base kv"));
+
+        let text = format!("access_log_format = \"xml\"\n{VALID_TOML}");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("access_log_format xml"));
+
+        let text = VALID_TOML.replace("cert_chain_path = \"tls-cert.pem\"", "");
+        assert!(parse_err(&text, ConfigFormat::Toml).starts_with("parse failed"));
+
+        let text = VALID_TOML.replace("binary_port = 8081", "binary_port = 8080");
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("binary_port"));
+
+        let text = VALID_TOML.replace("\"delete\"]", "\"drop\"]");
+        assert!(parse_err(&text, ConfigFormat::Toml).starts_with("parse failed"));
+    }
+
+    #[test]
+    fn test_changes() {
+        let cfg = ServerConfig::parse(VALID_TOML, ConfigFormat::Toml).unwrap();
+        assert_eq!(cfg.changes(&cfg), ConfigChanges::default());
+
+        // live settings only
+        let text = format!("txn_timeout_secs = 5\n{VALID_TOML}").replace(
+            "max_keys = 1000",
+            "max_keys = 1000\nmax_mget_keys = 10\nmax_page_bytes = 65536\ncount_cache_secs = 5",
+        );
+        let new_cfg = ServerConfig::parse(&text, ConfigFormat::Toml).unwrap();
+        let changes = cfg.changes(&new_cfg);
+        assert_eq!(
+            changes.live,
+            vec![
+                "txn_timeout_secs",
+                "databases.db1.max_mget_keys",
+                "databases.db1.max_page_bytes",
+                "databases.db1.count_cache_secs"
+            ]
+        );
+        assert!(changes.restart.is_empty());
+
+        // restart settings, including an added db
+        let text = VALID_TOML
+            .replace("port = 8080", "port = 8090")
+            .replace("max_keys = 1000", "max_keys = 2000")
+            .replace("name = \"db2\"", "name = \"db3\"");
+        let new_cfg = ServerConfig::parse(&text, ConfigFormat::Toml).unwrap();
+        let changes = cfg.changes(&new_cfg);
+        assert!(changes.live.is_empty());
+        assert_eq!(
+            changes.restart,
+            vec![
+                "listen",
+                "databases.db1.max_keys",
+                "databases.db2",
+                "databases.db3"
+            ]
+        );
+
+        // equivalent spellings are not changes
+        let text = VALID_TOML
+            .replace("\"/kv\"", "\"/kv/\"")
+            .replace("read_only = true", "read_only = true\nmax_mget_keys = 1000");
+        let new_cfg = ServerConfig::parse(&text, ConfigFormat::Toml).unwrap();
+        assert_eq!(cfg.changes(&new_cfg), ConfigChanges::default());
+    }
+
+    #[test]
+    fn test_runtime_db_config() {
+        for name in ["db3", "Logs-2024.06", "a_b"] {
+            assert!(RuntimeDbsConfig::valid_name(name), "{}", name);
+        }
+        let too_long = "x".repeat(MAX_RUNTIME_DB_NAME + 1);
+        for name in [
+            "",
+            "_reload",
+            ".",
+            "..",
+            "a/b",
+            "a b",
+            "caf\u{e9}",
+            &too_long,
+        ] {
+            assert!(!RuntimeDbsConfig::valid_name(name), "{}", name);
+        }
+
+        let runtime_dbs = RuntimeDbsConfig {
+            dir: String::from("/var/lib/kvdbd"),
+            driver: String::from("lmdb"),
+            create_on_use: true,
+            path_template: None,
+            key_case: String::new(),
+        };
+        let db_cfg = runtime_dbs.db_config("db3");
+        assert_eq!(db_cfg.name, "db3");
+        assert_eq!(db_cfg.path, "/var/lib/kvdbd/db3");
+        assert_eq!(db_cfg.driver, "lmdb");
+        assert!(!db_cfg.read_only);
+        assert_eq!(db_cfg.key_case_mode(), Some(KeyCase::CaseSensitive));
+        assert_eq!(db_cfg.max_mget_keys, DEF_MAX_MGET_KEYS);
+
+        let runtime_dbs = RuntimeDbsConfig {
+            path_template: Some(String::from("{db_id}/data.kv")),
+            ..runtime_dbs
+        };
+        assert_eq!(runtime_dbs.db_path("db3"), "/var/lib/kvdbd/db3/data.kv");
+    }
+
+    #[test]
+    fn test_expand_path_template() {
+        assert_eq!(
+            expand_path_template("./data/{db_id}", "db1").unwrap(),
+            "./data/db1"
+        );
+        assert_eq!(
+            expand_path_template("{db_id}/{db_id}.kv", "a..b").unwrap(),
+            "a..b/a..b.kv"
+        );
+
+        // without a placeholder, the path is unchanged, whatever the name
+        assert_eq!(expand_path_template("db1.kv", "db1").unwrap(), "db1.kv");
+        assert_eq!(expand_path_template("db1.kv", "../etc").unwrap(), "db1.kv");
+
+        // names which could leave the template's directory
+        for db_id in ["../etc", "..", ".", "", "a/b", "/etc", "a\\b", "a\0b"] {
+            assert!(!safe_db_id(db_id), "{:?}", db_id);
+            assert!(expand_path_template("./data/{db_id}", db_id).is_err());
+        }
+
+        // each configured db is given its own path
+        let text = VALID_TOML
+            .replace("path = \"db1.kv\"", "path = \"data/{db_id}\"")
+            .replace("path = \"db2.kv\"", "path = \"data/{db_id}\"");
+        let cfg = ServerConfig::parse(&text, ConfigFormat::Toml).unwrap();
+        assert_eq!(cfg.databases[0].path, "data/db1");
+        assert_eq!(cfg.databases[1].path, "data/db2");
+    }
+
+    #[test]
+    fn test_from_file() {
+        let tmp_dir = TempDir::new("tff").unwrap();
+        let toml_path = tmp_dir.path().join("cfg-kvdbd.toml");
+        let mut file = fs::File::create(&toml_path).unwrap();
+        file.write_all(VALID_TOML.as_bytes()).unwrap();
+
+        // format follows file name
+        assert_eq!(ConfigFormat::from_path("cfg.toml"), ConfigFormat::Toml);
+        assert_eq!(ConfigFormat::from_path("cfg.json"), ConfigFormat::Json);
+        assert_eq!(ConfigFormat::from_path("cfg"), ConfigFormat::Json);
+        let cfg = ServerConfig::from_file(toml_path.to_str().unwrap()).unwrap();
+        assert_eq!(cfg.databases.len(), 2);
+
+        // encryption keys are read, and checked, with the file
+        let key_path = tmp_dir.path().join("db1.key");
+        let key_hex = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+        fs::write(&key_path, format!("{key_hex}\n")).unwrap();
+        let text = VALID_TOML.replace(
+            "compress_values = true",
+            &format!("encryption_key_file = {:?}", key_path.to_str().unwrap()),
+        );
+        let cfg = ServerConfig::parse(&text, ConfigFormat::Toml).unwrap();
+        let key: Vec<u8> = (0..32).collect();
+        assert_eq!(cfg.databases[0].encryption_key(), Ok(Some(key)));
+        assert_eq!(cfg.databases[1].encryption_key(), Ok(None));
+        fs::write(&key_path, &key_hex[2..]).unwrap();
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("not 64 hex digits"));
+        fs::remove_file(&key_path).unwrap();
+        assert!(parse_err(&text, ConfigFormat::Toml).contains("db1 encryption_key_file"));
+
+        let missing_path = tmp_dir.path().join("missing.toml");
+        match ServerConfig::from_file(missing_path.to_str().unwrap()) {
+            Err(ConfigError::Io(e)) => assert_eq!(e.kind(), io::ErrorKind::NotFound),
+            _ => panic!("missing file not reported"),
+        }
+    }
+}
@@ -1,21 +1,283 @@
+use super::Error;
+use serde::{de, Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::Read;
+use std::ops::{ControlFlow, Deref};
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use super::audit::{AuditDb, AuditSink};
+use super::changelog::ChangeLogDb;
+use super::checksum::ChecksumDb;
+use super::compress::GzipTransform;
+use super::degrade::DegradeDb;
+use super::encrypt::EncryptedDb;
+use super::evict::EvictDb;
+use super::expiry::ExpiryDb;
+use super::index::IndexDb;
+use super::keycase::FoldCaseDb;
+use super::limit::LimitDb;
+use super::merge::MergeDb;
+use super::meta::MetaDb;
+use super::modtime::ModTimeDb;
+use super::quota::QuotaDb;
+use super::sha256::sha256;
+use super::slowlog::SlowLogDb;
+use super::snapshot::{CopySnapshot, SnapshotDb};
+use super::sync::SyncDb;
+use super::timeout::TimeoutDb;
+use super::transform::{TransformDb, ValueTransform};
+use super::version::VersionDb;
+use super::watch::{WatchDb, WatchHub};
+
+#[derive(Clone, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum MutationOp {
     Insert,
     Remove,
 }
 
+/// One mutation of a batch.  Serialized as {"key": ..., "value": ...},
+/// an insert if value is given, else a remove; key and value are byte
+/// strings, or base64 in JSON (see bytesenc).  An "op" of "insert" or
+/// "remove" may also be given, and is checked against the value:  an
+/// insert without one, or a remove with one, is refused, as is any other
+/// field, rather than mistaken for a remove.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(try_from = "MutationFields", into = "MutationFields")]
 pub struct Mutation {
     pub op: MutationOp,
     pub key: Vec<u8>,
     pub value: Option<Vec<u8>>,
 }
 
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+struct MutationFields {
+    #[serde(with = "super::bytesenc")]
+    key: Vec<u8>,
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        with = "super::bytesenc::option"
+    )]
+    value: Option<Vec<u8>>,
+    #[serde(default, skip_serializing)]
+    op: Option<MutationOp>,
+}
+
+impl TryFrom<MutationFields> for Mutation {
+    type Error = &'static str;
+
+    fn try_from(fields: MutationFields) -> Result<Mutation, &'static str> {
+        let op = match (fields.op, &fields.value) {
+            (None, None) | (Some(MutationOp::Remove), None) => MutationOp::Remove,
+            (None, Some(_)) | (Some(MutationOp::Insert), Some(_)) => MutationOp::Insert,
+            (Some(MutationOp::Insert), None) => return Err("insert missing value"),
+            (Some(MutationOp::Remove), Some(_)) => return Err("remove with value"),
+        };
+        Ok(Mutation {
+            op,
+            key: fields.key,
+            value: fields.value,
+        })
+    }
+}
+
+// a remove's value, which Batch::check() refuses, is dropped
+impl From<Mutation> for MutationFields {
+    fn from(dbm: Mutation) -> MutationFields {
+        let value = match dbm.op {
+            MutationOp::Insert => dbm.value,
+            MutationOp::Remove => None,
+        };
+        MutationFields {
+            key: dbm.key,
+            value,
+            op: None,
+        }
+    }
+}
+
+/// One put of a bulk conditional set (Db::put_if_not_newer()):  value
+/// is stored under key only if the key's version is at most version.
+/// Serialized as {"key": ..., "value": ..., "version": ...}, key and
+/// value as in Mutation.
+#[derive(Clone, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VersionedPut {
+    #[serde(with = "super::bytesenc")]
+    pub key: Vec<u8>,
+    #[serde(with = "super::bytesenc")]
+    pub value: Vec<u8>,
+    pub version: u64,
+}
+
+/// A batch's mutations, as decoded from JSON or MessagePack, refused
+/// whole if any mutation is malformed, with an error naming the first
+/// malformed one by its index, e.g. "mutation 3: insert missing value".
+pub struct MutationList(pub Vec<Mutation>);
+
+struct MutationListVisitor;
+
+impl<'de> de::Visitor<'de> for MutationListVisitor {
+    type Value = MutationList;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a list of mutations")
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, mut seq: A) -> Result<MutationList, A::Error> {
+        let mut ops = Vec::with_capacity(seq.size_hint().unwrap_or(0).min(4096));
+        loop {
+            match seq.next_element::<Mutation>() {
+                Ok(Some(dbm)) => ops.push(dbm),
+                Ok(None) => return Ok(MutationList(ops)),
+                Err(e) => {
+                    let msg = format!("mutation {}: {}", ops.len(), e);
+                    return Err(de::Error::custom(msg));
+                }
+            }
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for MutationList {
+    fn deserialize<D: de::Deserializer<'de>>(deserializer: D) -> Result<MutationList, D::Error> {
+        deserializer.deserialize_seq(MutationListVisitor)
+    }
+}
+
+/// How a batch treats mutations of one key repeated within it.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum DuplicatePolicy {
+    /// Mutations apply in order, so the last mutation of a key decides
+    /// its outcome:  of two inserts, the second value is stored; an
+    /// insert then a remove leaves the key absent.
+    #[default]
+    LastWins,
+    /// A batch mutating any key more than once is refused as a whole,
+    /// with ERR_DUPLICATE_KEY, before anything is applied.
+    Reject,
+}
+
+/// Freshness a read requires.  A single node serves every read from its
+/// one copy, so both levels read alike, and every read is Strong; a
+/// replicated or cached read path may serve Eventual reads from a copy
+/// which lags recent writes.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Consistency {
+    /// Reflects every write completed before the read.
+    #[default]
+    Strong,
+    /// May miss recent writes.
+    Eventual,
+}
+
+impl Consistency {
+    /// Consistency named "strong" or "eventual", ignoring case.
+    pub fn parse(name: &str) -> Option<Consistency> {
+        if name.eq_ignore_ascii_case("strong") {
+            Some(Consistency::Strong)
+        } else if name.eq_ignore_ascii_case("eventual") {
+            Some(Consistency::Eventual)
+        } else {
+            None
+        }
+    }
+}
+
+/// Mutations applied atomically, in order, by Db::apply_batch().
+#[derive(Clone, Default)]
 pub struct Batch {
     pub ops: Vec<Mutation>,
+    pub duplicate_policy: DuplicatePolicy,
+    /// Apply only if this key's value, before the batch, is the expected
+    /// value (None:  key absent); else refuse the whole batch, with
+    /// ERR_GUARD_MISMATCH.  A compare-and-swap over the whole batch.
+    pub guard: Option<(Vec<u8>, Option<Vec<u8>>)>,
 }
 
 impl Batch {
-    pub fn default() -> Batch {
-        Batch { ops: Vec::new() }
+    /// Empty batch, with room for n mutations before reallocating.
+    pub fn with_capacity(n: usize) -> Batch {
+        Batch {
+            ops: Vec::with_capacity(n),
+            ..Batch::default()
+        }
+    }
+
+    /// Mutations in the batch.
+    pub fn len(&self) -> usize {
+        self.ops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ops.is_empty()
+    }
+
+    /// Key and value bytes of all mutations, e.g. to split a large batch
+    /// into requests within max_request_bytes.  A request encodes them
+    /// at some cost (base64, in JSON; framing, in protobuf), so is
+    /// larger, and a guard's key and value are not counted.
+    pub fn byte_size(&self) -> usize {
+        self.ops
+            .iter()
+            .map(|dbm| dbm.key.len() + dbm.value.as_ref().map_or(0, Vec::len))
+            .sum()
+    }
+
+    /// Ok, unless the batch is malformed (an insert without a value, or
+    /// a remove with one), or check_duplicates() refuses it.  Backends,
+    /// and layers acting on a batch before passing it on, call this
+    /// before applying anything, so that a refused batch changes nothing.
+    pub fn check(&self) -> Result<(), Error> {
+        for dbm in &self.ops {
+            match (&dbm.op, &dbm.value) {
+                (MutationOp::Insert, None) => {
+                    return Err(Error::InvalidInput(String::from("insert without value")));
+                }
+                (MutationOp::Remove, Some(_val)) => {
+                    return Err(Error::InvalidInput(String::from("remove with value")));
+                }
+                _ => {}
+            }
+        }
+        self.check_duplicates()
+    }
+
+    /// Ok, unless duplicate_policy is Reject and some key is mutated more
+    /// than once.
+    pub fn check_duplicates(&self) -> Result<(), Error> {
+        if self.duplicate_policy == DuplicatePolicy::Reject {
+            let mut keys = HashSet::with_capacity(self.ops.len());
+            for mutation in &self.ops {
+                if !keys.insert(mutation.key.as_slice()) {
+                    return Err(ERR_DUPLICATE_KEY);
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Ok, unless the batch has a guard, and current, the value of its
+    /// key as read by the backend, is not the expected value.  Backends
+    /// call this before applying a batch, atomically with applying it.
+    pub fn check_guard(&self, current: Option<&[u8]>) -> Result<(), Error> {
+        match &self.guard {
+            Some((_key, expected)) if expected.as_deref() != current => Err(ERR_GUARD_MISMATCH),
+            _ => Ok(()),
+        }
+    }
+
+    /// Key read by check_guard(), if the batch has a guard.
+    pub fn guard_key(&self) -> Option<&[u8]> {
+        self.guard.as_ref().map(|(key, _expected)| key.as_slice())
     }
 
     pub fn insert(&mut self, key_in: &[u8], value_in: &[u8]) {
@@ -33,21 +295,164 @@ impl Batch {
             value: None,
         });
     }
+
+    /// Effect of the batch, as built so far, on key:  None if no mutation
+    /// names it, else Some of the value its last mutation leaves (None:
+    /// removed), as apply_batch() applies mutations in order.  Matches
+    /// key as given, before any folding by the db (Config.key_case).
+    pub fn get_pending(&self, key: &[u8]) -> Option<Option<&[u8]>> {
+        self.ops
+            .iter()
+            .rev()
+            .find(|dbm| dbm.key == key)
+            .map(|dbm| match dbm.op {
+                MutationOp::Insert => dbm.value.as_deref(),
+                MutationOp::Remove => None,
+            })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyCase {
+    CaseSensitive, // keys stored and matched as-is
+    LowercaseFold, // ASCII A-Z folded to a-z, on write, read and iteration
+}
+
+/// Keys evicted to make room for a write which would exceed the db's
+/// quota; see evict::EvictDb.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Eviction {
+    None, // writes past the quota fail with ERR_QUOTA_EXCEEDED
+    Lru,  // least recently read or written first
+    Fifo, // least recently inserted first
 }
 
 pub struct Config {
     pub path: String,
     pub read_only: bool,
+    pub replica: bool, // read-only copy, opened without locking or recovery
+    pub key_case: KeyCase,
+    pub slow_op_threshold_ms: Option<u64>, // warn on db ops slower than this
+    pub op_timeout_ms: Option<u64>,        // fail db ops slower than this
+    pub change_log_len: Option<u64>,       // changes retained, if change log enabled
+    pub default_ttl_secs: Option<u64>,     // TTL of writes giving no expiry, if set
+    pub max_keys: Option<u64>,             // key count quota
+    pub max_total_bytes: Option<u64>,      // key plus value bytes quota
+    pub eviction: Eviction,                // on reaching the quota
+    pub write_failure_threshold: Option<u64>, // failed writes before read-only
+    pub value_metadata: bool,              // store metadata with values
+    pub value_transforms: Vec<Arc<dyn ValueTransform>>, // applied in order on write
+    pub compress_values: bool,             // gzip values at rest, before transforms
+    pub encryption_key: Option<Vec<u8>>,   // encrypt values at rest, if set
+    pub verify_checksums: bool,            // checksum values at rest, verified on read
+    pub track_modified: bool,              // record each value's last write time
+    pub track_versions: bool,              // count writes of each key
+    pub max_iter_keys: usize,              // keys per iter_keys() page, at most
+    pub sync_writes: bool,                 // flush after every write
+    pub max_key_len: usize,                // key bytes written, at most; 0 = unlimited
+    pub max_value_len: usize,              // value bytes written, at most; 0 = unlimited
+    pub merge_fn: Option<MergeFn>,         // Db::merge() operator; None = last-write-wins
+    pub watch: Option<Arc<WatchHub>>,      // publish changes to, if set
+    pub audit: Option<Arc<dyn AuditSink>>, // record writes to, if set
+}
+
+/// Merge operator, per Db::merge():  the value to store, given key's
+/// existing value, if any, and the operand merged into it.
+pub type MergeFn = fn(existing: Option<&[u8]>, operand: &[u8]) -> Vec<u8>;
+
+/// Last-write-wins merge operator:  the operand replaces the value.
+pub fn merge_replace(_existing: Option<&[u8]>, operand: &[u8]) -> Vec<u8> {
+    operand.to_vec()
+}
+
+/// Shallow merge of JSON objects:  each field of the operand replaces
+/// the field of that name in the existing object, or, if null, removes
+/// it (as RFC 7386 merge patch does, but only at the top level).  An
+/// existing value which is absent, or not a JSON object, is merged as an
+/// empty object.  An operand which is not a JSON object replaces the
+/// value.
+pub fn merge_json_shallow(existing: Option<&[u8]>, operand: &[u8]) -> Vec<u8> {
+    let patch = match serde_json::from_slice(operand) {
+        Ok(serde_json::Value::Object(patch)) => patch,
+        _ => return operand.to_vec(),
+    };
+    let mut doc = match existing.map(serde_json::from_slice) {
+        Some(Ok(serde_json::Value::Object(doc))) => doc,
+        _ => serde_json::Map::new(),
+    };
+    for (name, val) in patch {
+        if val.is_null() {
+            doc.remove(&name);
+        } else {
+            doc.insert(name, val);
+        }
+    }
+    serde_json::Value::Object(doc).to_string().into_bytes()
+}
+
+/// Merge operand into key's value by merge_fn, as Db::merge() does, for
+/// implementations of it:  read the value, and store merge_fn's result,
+/// keeping any metadata stored with the value.
+pub fn merge_value<D: Db + ?Sized>(
+    db: &mut D,
+    key: &[u8],
+    operand: &[u8],
+    merge_fn: MergeFn,
+) -> Result<(), Error> {
+    let (val, meta) = match db.get_meta(key)? {
+        None => (merge_fn(None, operand), Vec::new()),
+        Some((val, meta)) => (merge_fn(Some(&val), operand), meta),
+    };
+    if meta.is_empty() {
+        db.put(key, &val)?;
+    } else {
+        db.put_meta(key, &val, &meta)?;
+    }
+    Ok(())
+}
+
+/// Read-only view of a db as of a point in time, per Db::snapshot().
+pub trait Snapshot {
+    /// Lookup value by key, as of the snapshot.
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+
+    /// List keys as of the snapshot, as Db::iter_keys() lists them.
+    fn iter_keys(&self, opts: IterOptions) -> Result<KeyList, Error>;
 }
 
+#[derive(Deserialize, Serialize)]
 pub struct KeyList {
+    #[serde(with = "super::bytesenc::list")]
     pub keys: Vec<Vec<u8>>,
     pub list_end: bool,
 }
 
+impl KeyList {
+    /// Cursor continuing the listing after this page, if more keys
+    /// follow:  its last key, as the next page's start_key.  Listing
+    /// resumes at the first key past it, so the cursor stays valid if
+    /// that key is since removed.
+    pub fn next_cursor(&self) -> Option<Vec<u8>> {
+        match self.keys.last() {
+            Some(key) if !self.list_end => Some(key.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// A page of records, key and value, in db order.
+#[derive(Deserialize, Serialize)]
+pub struct EntryList {
+    #[serde(with = "super::bytesenc::pairs")]
+    pub entries: Vec<(Vec<u8>, Vec<u8>)>,
+    pub list_end: bool,
+}
+
+#[derive(Clone, Default)]
 pub struct IterOptions {
     pub start_key: Option<Vec<u8>>,
     pub prefix: Option<Vec<u8>>,
+    pub reverse: bool, // descending key order, continuing before start_key
 }
 
 impl IterOptions {
@@ -55,6 +460,7 @@ impl IterOptions {
         IterOptions {
             start_key: None,
             prefix: None,
+            reverse: false,
         }
     }
 
@@ -69,372 +475,6276 @@ impl IterOptions {
 
         self
     }
-}
 
-pub struct DbStat {
-    pub n_records: u64,
-}
+    /// List keys in descending order, from the last key (of the prefix,
+    /// if any), or from the key preceding start_key.
+    pub fn reverse(&mut self) -> &mut IterOptions {
+        self.reverse = true;
 
-pub const MAX_ITER_KEYS: usize = 1000;
+        self
+    }
 
-pub trait Db {
-    fn apply_batch(&mut self, batch: &Batch) -> Result<bool, &'static str>;
-    fn clear(&mut self) -> Result<bool, &'static str>;
-    fn del(&mut self, key: &[u8]) -> Result<bool, &'static str>;
-    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, &'static str>;
-    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, &'static str>;
-    fn iter_keys(&self, opts: IterOptions) -> Result<KeyList, &'static str>;
-    fn stat(&self) -> Result<DbStat, &'static str>;
+    /// Options listing the page following one ending at last_key, in the
+    /// same direction and prefix.
+    pub fn continue_after(&self, last_key: &[u8]) -> IterOptions {
+        IterOptions {
+            start_key: Some(last_key.to_vec()),
+            prefix: self.prefix.clone(),
+            reverse: self.reverse,
+        }
+    }
 }
 
-pub trait Driver {
-    fn start_db(&self, cfg: Config) -> Result<Box<dyn Db + Send>, &'static str>;
+/// Exclusive upper bound of a reverse iteration with opts:  the lesser
+/// of its start key and the upper bound of its prefix, or None if
+/// unbounded.
+pub fn reverse_upper_bound(opts: &IterOptions) -> Option<Vec<u8>> {
+    let prefix_bound = opts.prefix.as_deref().and_then(prefix_upper_bound);
+    match (&opts.start_key, prefix_bound) {
+        (None, bound) => bound,
+        (Some(start_key), None) => Some(start_key.clone()),
+        (Some(start_key), Some(bound)) => Some(start_key.clone().min(bound)),
+    }
 }
 
-pub struct ConfigBuilder {
-    pub path: Option<String>,
-    pub read_only: Option<bool>,
+/// Read-only view of a stored value.  Depending on backend, this may
+/// share the backend's buffer (e.g. ref-counted), rather than copying it.
+pub struct ValueRef {
+    buf: Box<dyn AsRef<[u8]> + Send + Sync>,
 }
 
-impl ConfigBuilder {
-    pub fn new() -> ConfigBuilder {
-        ConfigBuilder {
-            path: None,
-            read_only: None,
-        }
+impl ValueRef {
+    pub fn new<T: AsRef<[u8]> + Send + Sync + 'static>(buf: T) -> ValueRef {
+        ValueRef { buf: Box::new(buf) }
     }
+}
 
-    pub fn path(&mut self, path_in: String) -> &mut ConfigBuilder {
-        self.path = Some(path_in);
-        self
-    }
+impl Deref for ValueRef {
+    type Target = [u8];
 
-    pub fn read_only(&mut self, val_in: bool) -> &mut ConfigBuilder {
-        self.read_only = Some(val_in);
-        self
+    fn deref(&self) -> &[u8] {
+        (*self.buf).as_ref()
     }
+}
 
-    pub fn build(&self) -> Config {
-        Config {
-            path: match &self.path {
-                None => String::from("./db"),
-                Some(p) => String::from(p),
-            },
-            read_only: match &self.read_only {
-                None => false,
-                Some(v) => *v,
-            },
+/// Returns the smallest key greater than every key beginning with
+/// prefix, i.e. the exclusive upper bound of a prefix scan.  Returns
+/// None if there is no such bound: the prefix is empty, or all 0xff.
+pub fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+    let mut bound = prefix.to_vec();
+    while let Some(last) = bound.pop() {
+        if last < 0xff {
+            bound.push(last + 1);
+            return Some(bound);
         }
     }
-}
-
-#[cfg(test)]
-use std::collections::HashMap;
 
-#[cfg(test)]
-mod tests {
-    // Note this useful idiom: importing names from outer (for mod tests) scope.
-    use super::*;
+    None
+}
 
-    pub struct MemDb {
-        db: HashMap<Vec<u8>, Vec<u8>>,
+/// Returns the literal prefix of a glob pattern:  the bytes before its
+/// first wildcard.  Every key matching the pattern begins with it.
+pub fn glob_prefix(pattern: &[u8]) -> &[u8] {
+    match pattern.iter().position(|c| *c == b'*' || *c == b'?') {
+        Some(pos) => &pattern[..pos],
+        None => pattern,
     }
+}
 
-    impl Db for MemDb {
-        fn clear(&mut self) -> Result<bool, &'static str> {
-            self.db.clear();
-            Ok(true)
-        }
+/// Match key against a glob pattern, byte-wise.  `*` matches any run of
+/// bytes, including none; `?` matches exactly one byte; every other byte
+/// matches itself.  There is no escape syntax.
+pub fn glob_match(pattern: &[u8], key: &[u8]) -> bool {
+    let mut p = 0;
+    let mut k = 0;
 
-        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
-            match self.db.get(key) {
-                None => Ok(None),
-                Some(val) => Ok(Some(val.to_vec())),
+    // position after the most recent `*`, and the key position it was
+    // last tried against; on mismatch, let that `*` absorb one more byte
+    let mut star: Option<(usize, usize)> = None;
+    while k < key.len() {
+        match pattern.get(p) {
+            Some(b'*') => {
+                p += 1;
+                star = Some((p, k));
+                continue;
+            }
+            Some(b'?') => {
+                p += 1;
+                k += 1;
+                continue;
             }
+            Some(c) if *c == key[k] => {
+                p += 1;
+                k += 1;
+                continue;
+            }
+            _ => {}
         }
 
-        fn stat(&self) -> Result<DbStat, &'static str> {
-            Ok(DbStat {
-                n_records: self.db.len() as u64,
-            })
+        match star {
+            None => return false,
+            Some((star_p, star_k)) => {
+                p = star_p;
+                k = star_k + 1;
+                star = Some((star_p, k));
+            }
         }
+    }
 
-        fn iter_keys(&self, opts: IterOptions) -> Result<KeyList, &'static str> {
-            let mut key_list = KeyList {
-                keys: Vec::new(),
-                list_end: true,
-            };
-
-            let prefix: Vec<u8> = match opts.prefix {
-                None => Vec::new(),
-                Some(value) => value,
-            };
-            let pfx_len = prefix.len();
-
-            let start_key: Vec<u8> = match opts.start_key {
-                None => Vec::new(),
-                Some(value) => value,
-            };
-            let have_start_key: bool = start_key.len() > 0;
-
-            let mut capture = !have_start_key;
-            for key in self.db.keys() {
-                // handle prefix-only iteration; skip if no match
-                if pfx_len > 0 {
-                    if key.len() < pfx_len || prefix != &key[0..pfx_len] {
-                        continue;
-                    }
-                }
-
-                // initialize iteration
-                if !capture {
-                    if start_key == &key[0..] {
-                        capture = true;
-                        // don't push this key; caller is passing
-                        // last key seen in their previous iter()
-                    }
+    pattern[p..].iter().all(|c| *c == b'*')
+}
 
-                // continue iteration
-                } else {
-                    key_list.keys.push(key.clone());
-                }
+/// List up to db.max_iter_keys() keys matching a glob pattern, in db order,
+/// via db.iter_keys().  Only keys beginning with the pattern's literal
+/// prefix are read.  Resumes after start, if given.  The default
+/// implementation of Db::scan_glob().
+pub fn scan_glob_keys<D: Db + ?Sized>(
+    db: &D,
+    pattern: &[u8],
+    start: Option<&[u8]>,
+) -> Result<KeyList, Error> {
+    let prefix = glob_prefix(pattern);
+    let mut key_list = KeyList {
+        keys: Vec::new(),
+        list_end: false,
+    };
+    let mut last_key: Option<Vec<u8>> = start.map(|k| k.to_vec());
+    loop {
+        let mut opts = IterOptions::new();
+        if !prefix.is_empty() {
+            opts.prefix(prefix);
+        }
+        if let Some(key) = &last_key {
+            opts.start(key);
+        }
+        let page = db.iter_keys(opts)?;
 
-                if key_list.keys.len() >= MAX_ITER_KEYS {
-                    key_list.list_end = false;
-                    break;
+        for key in &page.keys {
+            if glob_match(pattern, key) {
+                key_list.keys.push(key.clone());
+                if key_list.keys.len() >= db.max_iter_keys() {
+                    return Ok(key_list);
                 }
             }
-
-            Ok(key_list)
         }
 
-        fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, &'static str> {
-            self.db.insert(key.to_vec(), val.to_vec());
-            Ok(true)
+        if page.list_end || page.keys.is_empty() {
+            key_list.list_end = true;
+            return Ok(key_list);
         }
+        last_key = page.keys.last().cloned();
+    }
+}
 
-        fn del(&mut self, key: &[u8]) -> Result<bool, &'static str> {
-            match self.db.remove(key) {
-                None => Ok(false),
-                Some(_v) => Ok(true),
-            }
-        }
+/// List up to db.max_iter_keys() records, via db.iter_keys() and db.get(),
+/// ending the page early rather than let its value bytes exceed
+/// max_bytes, if given.  The default implementation of
+/// Db::iter_entries().
+pub fn iter_entries_page<D: Db + ?Sized>(
+    db: &D,
+    opts: IterOptions,
+    max_bytes: Option<usize>,
+) -> Result<EntryList, Error> {
+    let mut entry_list = EntryList {
+        entries: Vec::new(),
+        list_end: false,
+    };
+    let mut n_bytes = 0;
+    let mut opts = opts;
+    loop {
+        let page = db.iter_keys(opts.clone())?;
 
-        fn apply_batch(&mut self, batch: &Batch) -> Result<bool, &'static str> {
-            for dbm in &batch.ops {
-                match dbm.op {
-                    MutationOp::Insert => {
-                        let val: Vec<u8> = dbm.value.clone().unwrap();
-                        self.db.insert(dbm.key.to_vec(), val);
-                    }
-                    MutationOp::Remove => {
-                        self.db.remove(&dbm.key);
-                    }
+        for key in &page.keys {
+            // a key deleted since listed is skipped
+            let val = match db.get(key)? {
+                None => continue,
+                Some(val) => val,
+            };
+            if let Some(max_bytes) = max_bytes {
+                if !entry_list.entries.is_empty() && n_bytes + val.len() > max_bytes {
+                    return Ok(entry_list);
                 }
             }
+            n_bytes += val.len();
+            entry_list.entries.push((key.clone(), val));
+        }
 
-            Ok(true)
+        // a page of keys all since deleted does not end the list
+        if page.list_end || page.keys.is_empty() {
+            entry_list.list_end = true;
+            return Ok(entry_list);
+        }
+        if !entry_list.entries.is_empty() {
+            return Ok(entry_list);
         }
+        opts = opts.continue_after(page.keys.last().unwrap());
     }
+}
 
-    pub struct MemDriver {}
+/// One write recorded in a db's change log.
+pub struct Change {
+    pub seq: u64,       // sequence number; increases by one with each change
+    pub op: MutationOp, // Insert for put, Remove for delete
+    pub key: Vec<u8>,
+}
 
-    impl Driver for MemDriver {
-        fn start_db(&self, _cfg: Config) -> Result<Box<dyn Db + Send>, &'static str> {
-            Ok(Box::new(MemDb { db: HashMap::new() }) as Box<dyn Db + Send>)
-        }
-    }
+pub struct ChangeList {
+    pub changes: Vec<Change>,
+    pub last_seq: u64,      // newest change in db; 0 if none
+    pub list_end: bool,     // false: continue from last change returned
+    pub needs_resync: bool, // requested history not retained; full resync
+}
 
-    pub fn new_driver() -> Box<dyn Driver> {
-        Box::new(MemDriver {})
-    }
+pub struct DbStat {
+    pub n_records: u64,
+}
 
-    #[test]
-    fn test_get_put() {
-        let db_config = ConfigBuilder::new()
-            .path("/dev/null".to_string())
-            .read_only(false)
-            .build();
+/// Current usage, and limits, of a db with a quota.
+pub struct QuotaUsage {
+    pub n_keys: u64,
+    pub total_bytes: u64, // key plus value bytes, of all records
+    pub max_keys: Option<u64>,
+    pub max_total_bytes: Option<u64>,
+}
 
-        let driver = new_driver();
+/// Error returned by writes refused for exceeding the db's quota.
+pub const ERR_QUOTA_EXCEEDED: Error = Error::QuotaExceeded;
 
-        let mut db = driver.start_db(db_config).unwrap();
+/// Error returned by writes to a db opened with Config.read_only.
+pub const ERR_READ_ONLY: Error = Error::ReadOnly;
 
-        assert_eq!(db.get(b"name"), Ok(None));
-        assert_eq!(db.put(b"name", b"alan"), Ok(true));
-        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
-        assert_eq!(db.del(b"name"), Ok(true));
-        assert_eq!(db.get(b"name"), Ok(None));
-        assert_eq!(db.get(b"never_existed"), Ok(None));
-    }
+/// Error returned by apply_batch() of a batch with duplicate_policy
+/// Reject which mutates some key more than once.
+pub const ERR_DUPLICATE_KEY: Error = Error::DuplicateKey;
 
-    #[test]
-    fn test_del() {
-        let db_config = ConfigBuilder::new()
-            .path("/dev/null".to_string())
-            .read_only(false)
-            .build();
+/// Error returned by apply_batch() of a batch whose guard key does not
+/// hold the expected value.
+pub const ERR_GUARD_MISMATCH: Error = Error::GuardMismatch;
 
-        let driver = new_driver();
+/// Error returned by put_hashed(), for a value whose hash key already
+/// holds other content.
+pub const ERR_HASH_CONFLICT: Error = Error::HashConflict;
 
-        let mut db = driver.start_db(db_config).unwrap();
+/// Error returned by put_expire_at(), for an expiry in the future, by a
+/// db without the expiry layer (expiry::ExpiryDb).
+pub const ERR_EXPIRY_UNSUPPORTED: Error = Error::ExpiryUnsupported;
 
-        assert_eq!(db.put(b"name", b"alan"), Ok(true));
-        assert_eq!(db.del(b"name"), Ok(true));
-        assert_eq!(db.del(b"name"), Ok(false));
-    }
+/// Error returned by Driver::start_db(), for a db which another process
+/// holds open for writing.
+pub const ERR_DB_LOCKED: Error = Error::DbLocked;
 
-    #[test]
-    fn test_batch() {
-        let db_config = ConfigBuilder::new()
-            .path("/dev/null".to_string())
-            .read_only(false)
-            .build();
+/// Error returned by create_index() and index_lookup(), for a db
+/// without the index layer.
+pub const ERR_NO_INDEXES: Error = Error::NoIndexes;
 
-        let driver = new_driver();
+/// Error returned by snapshot reads, for an id not open (or closed).
+pub const ERR_NO_SNAPSHOT: Error = Error::NotFound;
 
-        let mut db = driver.start_db(db_config).unwrap();
+/// Error returned by writes to a db degraded to read-only, after
+/// repeated write failures.
+pub const ERR_DEGRADED: Error = Error::Degraded;
 
-        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+/// Error returned by lock_shared(), for a lock poisoned by a panic.
+pub const ERR_LOCK_POISONED: Error = Error::LockPoisoned;
 
-        let mut batch = Batch::default();
-        batch.insert(b"age", b"25");
-        batch.insert(b"city", b"anytown");
-        batch.remove(b"name");
-        assert_eq!(db.apply_batch(&batch), Ok(true));
+/// Error returned by put_meta(), with metadata, by a db without the
+/// metadata layer (see Config.value_metadata).
+pub const ERR_META_UNSUPPORTED: Error = Error::MetaUnsupported;
 
-        assert_eq!(db.get(b"name"), Ok(None));
-        assert_eq!(db.get(b"age"), Ok(Some(Vec::from("25"))));
-        assert_eq!(db.get(b"city"), Ok(Some(Vec::from("anytown"))));
-    }
+/// Error returned by put_meta(), for metadata past MAX_META_ENTRIES or
+/// MAX_META_BYTES.
+pub const ERR_META_TOO_LARGE: Error = Error::MetaTooLarge;
 
-    #[test]
-    fn test_clear() {
-        let db_config = ConfigBuilder::new()
-            .path("/dev/null".to_string())
-            .read_only(false)
-            .build();
+/// Error returned by writes of a key or value longer than
+/// Config.max_key_len or max_value_len.
+pub const ERR_TOO_LARGE: Error = Error::TooLarge;
 
-        let driver = new_driver();
+/// Error returned by get_modified(), by a db without the modification
+/// time layer (see Config.track_modified).
+pub const ERR_MODTIME_UNSUPPORTED: Error = Error::ModTimeUnsupported;
 
-        let mut db = driver.start_db(db_config).unwrap();
+/// Error returned by get_versioned() and put_if_version(), by a db
+/// without the version layer (see Config.track_versions).
+pub const ERR_VERSION_UNSUPPORTED: Error = Error::VersionUnsupported;
 
-        assert_eq!(db.put(b"name", b"alan"), Ok(true));
-        assert_eq!(db.put(b"age", b"25"), Ok(true));
-        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
-        assert_eq!(db.clear(), Ok(true));
-        assert_eq!(db.get(b"name"), Ok(None));
-        assert_eq!(db.get(b"age"), Ok(None));
-    }
+/// Metadata stored with a value by put_meta():  (name, value) pairs.
+pub type Metadata = Vec<(String, String)>;
 
-    #[test]
-    fn test_stat() {
-        let db_config = ConfigBuilder::new()
-            .path("/dev/null".to_string())
-            .read_only(false)
-            .build();
+// limits on the metadata stored with one value, by put_meta():  entries,
+// and name plus value bytes of all entries
+pub const MAX_META_ENTRIES: usize = 8;
+pub const MAX_META_BYTES: usize = 1024;
 
-        let driver = new_driver();
+/// Default of Config.max_iter_keys, the most keys listed per page.
+pub const MAX_ITER_KEYS: usize = 1000;
 
-        let mut db = driver.start_db(db_config).unwrap();
+// defaults of Config.max_key_len and max_value_len, in bytes
+pub const DEF_MAX_KEY_LEN: usize = 4096;
+pub const DEF_MAX_VALUE_LEN: usize = 16 * 1024 * 1024;
 
-        assert_eq!(db.put(b"name1", b"alan"), Ok(true));
-        assert_eq!(db.put(b"age1", b"25"), Ok(true));
-        assert_eq!(db.put(b"name", b"alan"), Ok(true));
-        assert_eq!(db.del(b"name"), Ok(true));
-        assert_eq!(db.del(b"name"), Ok(false));
+// number of mutations committed per chunk, by apply_batch_until()
+pub const BATCH_CHUNK_OPS: usize = 1000;
 
-        let st = db.stat().unwrap();
-        assert_eq!(st.n_records, 2);
-    }
+// reserved key, storing the next id to be allocated by put_next()
+pub const NEXT_ID_KEY: &[u8] = b"\x00kvdbd:next_id";
 
-    #[test]
-    fn test_iter() {
+// width of allocated keys; zero-padded so that keys sort in id order
+const ID_KEY_WIDTH: usize = 20;
+
+// separates a sharded counter's key from its shard number, in sub-keys
+pub const COUNTER_SHARD_SEP: &[u8] = b"\x00kvdbd:shard:";
+
+// next shard chosen by add_sharded(); spreads increments round-robin
+static NEXT_COUNTER_SHARD: AtomicUsize = AtomicUsize::new(0);
+
+fn counter_shard_key(key: &[u8], shard: u32) -> Vec<u8> {
+    let mut shard_key = key.to_vec();
+    shard_key.extend_from_slice(COUNTER_SHARD_SEP);
+    shard_key.extend_from_slice(format!("{shard:010}").as_bytes());
+    shard_key
+}
+
+fn decode_counter(val: &[u8]) -> Result<i64, Error> {
+    if val.len() != 8 {
+        return Err(Error::Corruption(String::from("invalid counter")));
+    }
+    let mut n_bytes = [0u8; 8];
+    n_bytes.copy_from_slice(val);
+    Ok(i64::from_be_bytes(n_bytes))
+}
+
+/// Current time, in milliseconds since the Unix epoch, by the system
+/// clock; 0 if the clock is set before it.
+pub fn unix_now_ms() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_millis() as u64,
+        Err(_e) => 0,
+    }
+}
+
+/// Absolute expiry, in milliseconds since the Unix epoch, of a record
+/// expiring ttl_secs from now.
+pub fn ttl_expire_at(ttl_secs: u64) -> u64 {
+    unix_now_ms().saturating_add(ttl_secs.saturating_mul(1000))
+}
+
+/// Expiry, for put_expire_at(), of a record which never expires:  the
+/// key's expiry, and the db's default TTL (Config.default_ttl_secs), if
+/// any, are cleared rather than applied.
+pub const NO_EXPIRY: u64 = u64::MAX;
+
+// max number of expired records removed per sweep_expired() call
+pub const SWEEP_BATCH_KEYS: usize = 1000;
+
+/// A key/value database.  Reads take &self, and writes &mut self, so a
+/// db behind a RwLock serves any number of readers at once, and each
+/// writer alone; Sync, so that readers may share it across threads.
+/// State a read updates (e.g. an eviction layer's recency) is kept
+/// behind the layer's own lock or atomics.
+pub trait Db: Send + Sync {
+    /// Apply all of batch's mutations atomically, in order:  on success
+    /// every mutation is applied, and on error none is.  A malformed
+    /// batch, e.g. an insert without a value, fails with InvalidInput.
+    /// Repeated mutations of one key are handled per
+    /// batch.duplicate_policy.  Both are checked (via Batch::check())
+    /// before anything is applied.
+    ///
+    /// Order is the batch's, however a backend executes it:  each mutation
+    /// sees the effects of every earlier mutation in the batch, e.g. a
+    /// layer deriving writes from a key's old value (an index, a quota)
+    /// takes the value written earlier in the batch, not the stored one;
+    /// and anything reported per mutation, e.g. change log records, is
+    /// listed in op order, ops[i] before ops[i + 1].  So a key inserted
+    /// then removed is left absent, and one removed then inserted holds
+    /// the insert's value, as Batch::get_pending() reports before the
+    /// batch is applied.  The guard alone is checked against the db as it
+    /// was before the batch.
+    fn apply_batch(&mut self, batch: &Batch) -> Result<bool, Error>;
+    fn clear(&mut self) -> Result<bool, Error>;
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error>;
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error>;
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error>;
+
+    /// List up to max_iter_keys() keys per opts, in lexicographic byte
+    /// order (descending, if opts.reverse), starting strictly after
+    /// opts.start_key, whether or not that key is present.  Callers page
+    /// by passing each page's last key as the next start_key; every
+    /// implementation must keep this order, or paging repeats and skips
+    /// keys.
+    fn iter_keys(&self, opts: IterOptions) -> Result<KeyList, Error>;
+    fn stat(&self) -> Result<DbStat, Error>;
+
+    /// Number of records stored, as stat().n_records.  Counts what the
+    /// db holds now:  writes not yet applied, e.g. queued for a group
+    /// commit or in an open transaction, are not included.  Backends
+    /// with a cheaper native count override this; layers leave the
+    /// default, so their stat() adjustments apply.
+    fn len(&self) -> Result<u64, Error> {
+        Ok(self.stat()?.n_records)
+    }
+
+    /// True if the db holds no records, per len().
+    fn is_empty(&self) -> Result<bool, Error> {
+        Ok(self.len()? == 0)
+    }
+
+    /// Most keys listed per page by iter_keys(), and by the other
+    /// paged listings, per Config.max_iter_keys.  Backends return their
+    /// configured value, and layers forward it.
+    fn max_iter_keys(&self) -> usize {
+        MAX_ITER_KEYS
+    }
+
+    /// List keys beginning with prefix (all keys, if empty), resuming
+    /// after start_key, if any.  Iteration stops at the first key past
+    /// the prefix, so list_end is true once the prefix's keys run out
+    /// within max_iter_keys().  Default implementation calls iter_keys().
+    fn iter_keys_prefix(&self, prefix: &[u8], start_key: Option<&[u8]>) -> Result<KeyList, Error> {
+        let mut opts = IterOptions::new();
+        opts.prefix(prefix);
+        // a start key sorting before the prefix would only skip keys
+        // outside it
+        if let Some(key) = start_key.filter(|key| *key >= prefix) {
+            opts.start(key);
+        }
+        self.iter_keys(opts)
+    }
+
+    /// True if any key begins with prefix (any key at all, if empty).
+    /// Default implementation lists the prefix's first page of keys, via
+    /// iter_keys_prefix(); ordered backends override it with a seek to
+    /// the prefix, reading one key.
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        Ok(!self.iter_keys_prefix(prefix, None)?.keys.is_empty())
+    }
+
+    /// List keys from start (inclusive) to end (exclusive; empty for no
+    /// upper bound), resuming after start_key, if any.  As iter_keys(),
+    /// lists at most max_iter_keys() keys, with list_end true once the
+    /// range's keys run out.  Default implementation calls iter_keys(),
+    /// reading at most one page past the range.
+    fn iter_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        start_key: Option<&[u8]>,
+    ) -> Result<KeyList, Error> {
+        let mut key_list = KeyList {
+            keys: Vec::new(),
+            list_end: true,
+        };
+        if !end.is_empty() && start >= end {
+            return Ok(key_list);
+        }
+
+        // a scan resumes after its start key, so list start itself first
+        let mut opts = IterOptions::new();
+        match start_key.filter(|key| *key >= start) {
+            Some(key) => {
+                opts.start(key);
+            }
+            None if start.is_empty() => {}
+            None => {
+                if self.get_ref(start)?.is_some() {
+                    key_list.keys.push(start.to_vec());
+                }
+                opts.start(start);
+            }
+        }
+
+        let page = self.iter_keys(opts)?;
+        key_list.list_end = page.list_end;
+        for key in page.keys {
+            if !end.is_empty() && key.as_slice() >= end {
+                key_list.list_end = true;
+                break;
+            }
+            key_list.keys.push(key);
+        }
+        if key_list.keys.len() > self.max_iter_keys() {
+            key_list.keys.truncate(self.max_iter_keys());
+            key_list.list_end = false;
+        }
+        Ok(key_list)
+    }
+
+    /// List records, key and value, with keys from start (inclusive) to
+    /// end (exclusive; empty for no upper bound), in key order, e.g. for
+    /// a report.  Returns at most limit records, limit clamped to 1 to
+    /// max_iter_keys(); list_end is false if more of the range follows.
+    /// Default implementation calls iter_range(), then get_multi().
+    fn get_range(&self, start: &[u8], end: &[u8], limit: usize) -> Result<EntryList, Error> {
+        let limit = limit.clamp(1, self.max_iter_keys());
+        let mut key_list = self.iter_range(start, end, None)?;
+        if key_list.keys.len() > limit {
+            key_list.keys.truncate(limit);
+            key_list.list_end = false;
+        }
+
+        // a key deleted since listed is skipped
+        let values = self.get_multi(&key_list.keys)?;
+        let entries = key_list
+            .keys
+            .into_iter()
+            .zip(values)
+            .filter_map(|(key, val)| val.map(|val| (key, val)))
+            .collect();
+        Ok(EntryList {
+            entries,
+            list_end: key_list.list_end,
+        })
+    }
+
+    /// Lookup value by key, avoiding a copy of the value where the
+    /// backend supports it.  Default implementation copies, via get().
+    fn get_ref(&self, key: &[u8]) -> Result<Option<ValueRef>, Error> {
+        match self.get(key)? {
+            None => Ok(None),
+            Some(val) => Ok(Some(ValueRef::new(val))),
+        }
+    }
+
+    /// Lookup the values of many keys, returned in the same order as keys.
+    /// Missing keys yield None.  Default implementation calls get() for
+    /// each key; backends may override it with a native multi-key read.
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            values.push(self.get(key)?);
+        }
+
+        Ok(values)
+    }
+
+    /// Test whether each of many keys is present, returned in the same
+    /// order as keys, without reading values where the backend supports
+    /// it.  Default implementation calls get_ref() for each key.
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            found.push(self.get_ref(key)?.is_some());
+        }
+
+        Ok(found)
+    }
+
+    /// Test whether key is present, without reading its value where the
+    /// backend supports it.  Default implementation calls exists_multi().
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        Ok(self.exists_multi(&[key])?[0])
+    }
+
+    /// Apply batch mutations in order, in chunks of BATCH_CHUNK_OPS,
+    /// stopping once deadline has passed.  Each chunk is committed
+    /// atomically; the batch as a whole is not.  Returns the number of
+    /// mutations applied, which is always a multiple of BATCH_CHUNK_OPS,
+    /// or the length of the batch.  Resume an incomplete batch by applying
+    /// the remaining mutations, starting at the returned index.  A guard
+    /// is checked with the first chunk only.
+    fn apply_batch_until(&mut self, batch: &Batch, deadline: Instant) -> Result<usize, Error> {
+        // refuse before applying any chunk; a chunk can hold no
+        // duplicates which the whole batch does not
+        batch.check()?;
+
+        let mut n_applied = 0;
+        for ops in batch.ops.chunks(BATCH_CHUNK_OPS) {
+            if Instant::now() >= deadline {
+                break;
+            }
+
+            let chunk = Batch {
+                ops: ops.to_vec(),
+                duplicate_policy: batch.duplicate_policy,
+                guard: match n_applied {
+                    0 => batch.guard.clone(),
+                    _ => None,
+                },
+            };
+            self.apply_batch(&chunk)?;
+            n_applied += ops.len();
+        }
+
+        Ok(n_applied)
+    }
+
+    /// Store value under a newly allocated, never-before-used key, and
+    /// return that key.  Keys are the decimal id, zero-padded to a fixed
+    /// width, so they sort in allocation order.  The id counter is stored
+    /// at NEXT_ID_KEY, and is updated in the same atomic batch as the
+    /// value insert.  Callers must serialize access to the db.
+    fn put_next(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        let next_id = match self.get(NEXT_ID_KEY)? {
+            None => 1,
+            Some(v) => {
+                if v.len() != 8 {
+                    return Err(Error::Corruption(String::from("invalid id counter")));
+                }
+                let mut id_bytes = [0u8; 8];
+                id_bytes.copy_from_slice(&v);
+                u64::from_be_bytes(id_bytes)
+            }
+        };
+
+        let key = format!("{next_id:0ID_KEY_WIDTH$}").into_bytes();
+
+        let mut batch = Batch::default();
+        batch.insert(NEXT_ID_KEY, &(next_id + 1).to_be_bytes());
+        batch.insert(&key, val);
+        self.apply_batch(&batch)?;
+
+        Ok(key)
+    }
+
+    /// Atomically move the value stored at key `from` to key `to`.  If `to`
+    /// already exists, it is replaced only if `overwrite` is true.  Returns
+    /// whether the value was moved: false if `from` is absent, or if `to`
+    /// exists and overwrite was not requested.
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> Result<bool, Error> {
+        let val = match self.get(from)? {
+            None => return Ok(false),
+            Some(v) => v,
+        };
+        if from == to {
+            return Ok(true);
+        }
+        if !overwrite && self.get(to)?.is_some() {
+            return Ok(false);
+        }
+
+        let mut batch = Batch::default();
+        batch.insert(to, &val);
+        batch.remove(from);
+        self.apply_batch(&batch)
+    }
+
+    /// Atomically exchange the values stored at keys a and b, in one
+    /// batch.  An absent key swaps as an absent value:  if only one key
+    /// is present, its value moves to the other key, and it is removed;
+    /// if neither is present, nothing changes.
+    fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<(), Error> {
+        if key_a == key_b {
+            return Ok(());
+        }
+        let val_a = self.get(key_a)?;
+        let val_b = self.get(key_b)?;
+        if val_a.is_none() && val_b.is_none() {
+            return Ok(());
+        }
+
+        let mut batch = Batch::default();
+        for (key, val) in &[(key_a, val_b), (key_b, val_a)] {
+            match val {
+                Some(v) => batch.insert(key, v),
+                None => batch.remove(key),
+            }
+        }
+        self.apply_batch(&batch)?;
+
+        Ok(())
+    }
+
+    /// Remove key only if its stored value is empty (zero-length), e.g. a
+    /// container key once its last member is gone.  Returns true if
+    /// removed; false if key is absent, or its value is not empty.  The
+    /// check and removal are atomic, as &mut self excludes other writers.
+    fn del_if_empty(&mut self, key: &[u8]) -> Result<bool, Error> {
+        match self.get_ref(key)? {
+            Some(val) if val.is_empty() => self.del(key),
+            _ => Ok(false),
+        }
+    }
+
+    /// Remove every key beginning with prefix, returning the number
+    /// removed.  An empty prefix fails with InvalidInput, rather than
+    /// removing every key; use clear() for that.  Default implementation
+    /// lists the keys via iter_keys_prefix(), then removes them in one
+    /// batch:  atomic, as &mut self excludes other writers, and passing
+    /// each removal through every layer, at the cost of holding every
+    /// matching key in memory.  Backends override it, removing the range
+    /// in one native batch or transaction, as reached by a PrefixedDb
+    /// clearing its namespace of a shared backend.
+    fn delete_prefix(&mut self, prefix: &[u8]) -> Result<u64, Error> {
+        if prefix.is_empty() {
+            return Err(Error::InvalidInput(String::from("empty prefix")));
+        }
+
+        let mut batch = Batch::default();
+        let mut last_key: Option<Vec<u8>> = None;
+        loop {
+            let key_list = self.iter_keys_prefix(prefix, last_key.as_deref())?;
+            for key in &key_list.keys {
+                batch.remove(key);
+            }
+            if key_list.list_end || key_list.keys.is_empty() {
+                break;
+            }
+            last_key = key_list.keys.last().cloned();
+        }
+
+        if !batch.ops.is_empty() {
+            self.apply_batch(&batch)?;
+        }
+        Ok(batch.ops.len() as u64)
+    }
+
+    /// Remove each listed key which is present, returning the number
+    /// removed.  An absent key counts for nothing, as del() returns false
+    /// for it, and a key listed twice is removed once.  Default
+    /// implementation tests the keys with exists_multi(), then removes
+    /// those present in one batch:  atomic, as &mut self excludes other
+    /// writers.
+    fn del_many(&mut self, keys: &[Vec<u8>]) -> Result<u64, Error> {
+        let mut keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+        keys.sort();
+        keys.dedup();
+
+        let mut batch = Batch::default();
+        for (key, found) in keys.iter().zip(self.exists_multi(&keys)?) {
+            if found {
+                batch.remove(key);
+            }
+        }
+        if !batch.ops.is_empty() {
+            self.apply_batch(&batch)?;
+        }
+        Ok(batch.ops.len() as u64)
+    }
+
+    /// Atomically replace key's value with new (None:  remove key), only
+    /// if its current value is expected (None:  key absent).  Returns
+    /// true if swapped; false, changing nothing, if the value was not
+    /// expected.  Default implementation applies a guarded batch.
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let mut batch = Batch {
+            guard: Some((key.to_vec(), expected.map(|val| val.to_vec()))),
+            ..Batch::default()
+        };
+        match new {
+            Some(val) => batch.insert(key, val),
+            None => batch.remove(key),
+        }
+        match self.apply_batch(&batch) {
+            Err(ERR_GUARD_MISMATCH) => Ok(false),
+            Err(e) => Err(e),
+            Ok(_res) => Ok(true),
+        }
+    }
+
+    /// Store val under key only if key is absent, e.g. to take a lock, or
+    /// make an insert idempotent.  Returns true if stored; false, leaving
+    /// the value unchanged, if key was present.  The check and insert are
+    /// atomic, so of concurrent callers, exactly one stores.
+    fn put_if_absent(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.compare_and_swap(key, None, Some(val))
+    }
+
+    /// Store val under its content hash, and return that key:  the
+    /// SHA-256 digest of val, as 64 lowercase hex digits.  Storing the
+    /// same content again stores nothing, and returns the same key.  A
+    /// key already holding other content, written by other means, is not
+    /// overwritten:  fails with ERR_HASH_CONFLICT.
+    fn put_hashed(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        let key: String = sha256(val).iter().map(|b| format!("{b:02x}")).collect();
+        let key = key.into_bytes();
+        if !self.put_if_absent(&key, val)? && self.get(&key)?.as_deref() != Some(val) {
+            return Err(ERR_HASH_CONFLICT);
+        }
+        Ok(key)
+    }
+
+    /// Store init under key only if key is absent, e.g. a counter's
+    /// starting value; then return the value now present, whether init
+    /// or the value already stored.  The check and insert are atomic, as
+    /// &mut self excludes other writers, so concurrent initializers all
+    /// return the same value.
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        if let Some(val) = self.get(key)? {
+            return Ok(val);
+        }
+        self.put(key, init)?;
+        Ok(init.to_vec())
+    }
+
+    /// Append suffix to key's value, storing it as key's value if key is
+    /// absent, and return the new length of the value.  The read and
+    /// write are atomic, as &mut self excludes other writers, so
+    /// concurrent appends are each applied whole, in some order.  Any
+    /// metadata stored with the value is kept.
+    ///
+    /// No backend appends in place:  sled and lmdb each store a value as
+    /// one record, so every append rewrites the whole value, at a cost
+    /// proportional to its length, as a read-modify-write by the caller
+    /// does.  What append() saves is the round trip, and the race.
+    /// Limits on the value length (Config.max_value_len) apply to the
+    /// whole value, not the suffix.
+    fn append(&mut self, key: &[u8], suffix: &[u8]) -> Result<usize, Error> {
+        let (mut val, meta) = match self.get_meta(key)? {
+            None => (Vec::new(), Vec::new()),
+            Some((val, meta)) => (val.to_vec(), meta),
+        };
+        val.extend_from_slice(suffix);
+        if meta.is_empty() {
+            self.put(key, &val)?;
+        } else {
+            self.put_meta(key, &val, &meta)?;
+        }
+        Ok(val.len())
+    }
+
+    /// Merge operand into key's value, by the db's merge operator
+    /// (Config.merge_fn), e.g. to patch a few fields of a JSON document
+    /// without sending it whole.  By default, and unless configured,
+    /// last-write-wins:  the operand replaces the value.  The read and
+    /// write are atomic, as for append(), and any metadata stored with the
+    /// value is kept.  Limits on the value length apply to the merged
+    /// value.
+    fn merge(&mut self, key: &[u8], operand: &[u8]) -> Result<(), Error> {
+        merge_value(self, key, operand, merge_replace)
+    }
+
+    /// Add delta to a sharded counter.  The counter is stored as up to
+    /// `shards` sub-keys (key, COUNTER_SHARD_SEP, shard number), each an
+    /// 8-byte big-endian i64, and each call updates a single sub-key,
+    /// chosen round-robin.  Spreading writes across sub-keys reduces
+    /// backend contention on hot counters; the cost is paid by
+    /// read_counter(), which must read and sum every sub-key.
+    fn add_sharded(&mut self, key: &[u8], delta: i64, shards: u32) -> Result<bool, Error> {
+        if shards == 0 {
+            return Err(Error::InvalidInput(String::from("invalid shard count")));
+        }
+        let shard = (NEXT_COUNTER_SHARD.fetch_add(1, Ordering::Relaxed) % shards as usize) as u32;
+        let shard_key = counter_shard_key(key, shard);
+
+        let old_val = match self.get(&shard_key)? {
+            None => 0,
+            Some(v) => decode_counter(&v)?,
+        };
+        let new_val = match old_val.checked_add(delta) {
+            None => return Err(Error::InvalidInput(String::from("counter overflow"))),
+            Some(n) => n,
+        };
+        self.put(&shard_key, &new_val.to_be_bytes())
+    }
+
+    /// Read a sharded counter, summing all of its sub-keys.  Reads one
+    /// value per shard written.  A counter never written reads as zero.
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        let mut prefix = key.to_vec();
+        prefix.extend_from_slice(COUNTER_SHARD_SEP);
+
+        let mut total: i64 = 0;
+        let mut last_key: Option<Vec<u8>> = None;
+        loop {
+            let mut opts = IterOptions::new();
+            opts.prefix(&prefix);
+            if let Some(k) = &last_key {
+                opts.start(k);
+            }
+            let key_list = self.iter_keys(opts)?;
+
+            for shard_key in &key_list.keys {
+                if let Some(v) = self.get(shard_key)? {
+                    total = match total.checked_add(decode_counter(&v)?) {
+                        None => return Err(Error::InvalidInput(String::from("counter overflow"))),
+                        Some(n) => n,
+                    };
+                }
+            }
+
+            if key_list.list_end || key_list.keys.is_empty() {
+                return Ok(total);
+            }
+            last_key = key_list.keys.last().cloned();
+        }
+    }
+
+    /// Store value, expiring at unix_ms, an absolute time in milliseconds
+    /// since the Unix epoch:  once it has passed, key is absent to every
+    /// read, as if deleted.  An expiry at or before now is not rejected:
+    /// the key is absent at once, as if stored and expired, so any
+    /// existing value is removed, and nothing is stored.  Any later write
+    /// of key without an expiry (put(), a batch) clears its expiry, or,
+    /// if the db has a default TTL, resets it; an expiry of NO_EXPIRY
+    /// stores value as put() would without one.  Requires the expiry
+    /// layer (expiry::ExpiryDb), which wrap_db() adds to every db; the
+    /// default implementation, for a db without it, refuses a later
+    /// expiry with ERR_EXPIRY_UNSUPPORTED, leaving key unchanged.
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        if unix_ms == NO_EXPIRY {
+            return self.put(key, val);
+        }
+        if unix_ms > unix_now_ms() {
+            return Err(ERR_EXPIRY_UNSUPPORTED);
+        }
+        self.del(key)?;
+        Ok(true)
+    }
+
+    /// Store value, expiring ttl_secs from now, as put_expire_at().  A
+    /// ttl of 0 expires the key at once.
+    fn put_ttl(&mut self, key: &[u8], val: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.put_expire_at(key, val, ttl_expire_at(ttl_secs))
+    }
+
+    /// Reset key's expiry to ttl_secs from now, without rewriting its
+    /// value, e.g. for sliding expiry of sessions.  Returns false if key
+    /// is absent.  A ttl of 0 expires the key at once, as an expiry at
+    /// or before now does for put_expire_at().  The expiry layer
+    /// (expiry::ExpiryDb) updates only the expiry; the default
+    /// implementation, for a db without it, refuses a later expiry with
+    /// ERR_EXPIRY_UNSUPPORTED, leaving key unchanged.
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        if self.get_ref(key)?.is_none() {
+            return Ok(false);
+        }
+        if ttl_secs > 0 {
+            return Err(ERR_EXPIRY_UNSUPPORTED);
+        }
+        self.del(key)
+    }
+
+    /// Remove up to max_keys expired records, returning the number
+    /// removed.  Callers sweep in a loop, releasing the db between calls,
+    /// until fewer than max_keys are returned.  A db without the expiry
+    /// layer (expiry::ExpiryDb) has nothing to remove.
+    fn sweep_expired(&mut self, _max_keys: usize) -> Result<usize, Error> {
+        Ok(0)
+    }
+
+    /// Returns the soonest expiry time of any record, in milliseconds
+    /// since the Unix epoch, or None if no record expires.  The expiry
+    /// layer (expiry::ExpiryDb) keeps a secondary index ordered by
+    /// (expiry, key), so that this, and sweep_expired(), read only the
+    /// due head of the index rather than scanning every record.  The cost is one extra
+    /// index write per expiring put or delete, and two (remove old, add
+    /// new) when a record's expiry changes.
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        Ok(None)
+    }
+
+    /// List changes (puts and deletes) with sequence numbers after seq, in
+    /// order, for incremental sync.  Pass 0 to list all retained changes,
+    /// then the seq of the last change applied.  If changes after seq are
+    /// no longer retained, returns needs_resync, with last_seq set: copy
+    /// the db in full, then continue from last_seq.  Requires the change
+    /// log (Config.change_log_len).
+    fn changes_since(&self, _seq: u64) -> Result<ChangeList, Error> {
+        Err(Error::NotEnabled(String::from("change log")))
+    }
+
+    /// Returns usage against quota, or None if no quota is configured
+    /// (Config.max_keys, Config.max_total_bytes).
+    fn quota_usage(&self) -> Option<QuotaUsage> {
+        None
+    }
+
+    /// List keys matching a glob pattern (see glob_match()), resuming
+    /// after start, as iter_keys() does.  Seeks to the pattern's literal
+    /// prefix, rather than scanning the whole db; a pattern beginning
+    /// with a wildcard scans every key.  Returns at most max_iter_keys()
+    /// keys; list_end is false if more may follow the last key returned.
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<KeyList, Error> {
+        scan_glob_keys(self, pattern, start)
+    }
+
+    /// List records, key and value, as iter_keys() lists keys:  at most
+    /// max_iter_keys(), resuming after opts.start_key.  If max_bytes is
+    /// given, the page ends before the record whose value would take
+    /// the page's total value bytes past max_bytes, so that a page of a
+    /// few huge values stays bounded.  The first record is returned
+    /// however large, so that resuming after the last key returned
+    /// always progresses.  list_end is false if more may follow.
+    fn iter_entries(
+        &self,
+        opts: IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<EntryList, Error> {
+        iter_entries_page(self, opts, max_bytes)
+    }
+
+    /// Approximate bytes stored in records with keys from start
+    /// (inclusive) to end (exclusive; empty for no upper bound), for
+    /// splitting a keyspace into shares of similar size.  Counts each
+    /// record's key and value bytes, not the engine's own overhead (tree
+    /// or page structure, free space), so is approximate on disk
+    /// backends.  Default implementation reads every record, checking
+    /// each against the range, as backends need not iterate in key
+    /// order; ordered backends override it with a scan of the range.
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        let mut n_bytes = 0;
+        self.for_each_entry(None, |key, val| {
+            if key >= start && (end.is_empty() || key < end) {
+                n_bytes += (key.len() + val.len()) as u64;
+            }
+            ControlFlow::Continue(())
+        })?;
+        Ok(n_bytes)
+    }
+
+    /// Open a read snapshot of the db, returning its id.  Reads via
+    /// snapshot_get() and snapshot_iter_keys() see the db as it was when
+    /// opened, however it is written since, until snapshot_close().  An
+    /// open snapshot holds a copy of each record written since, so close
+    /// snapshots promptly.  Requires the snapshot layer
+    /// (snapshot::SnapshotDb), which wrap_db() adds to every db.
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        Err(Error::NotEnabled(String::from("snapshots")))
+    }
+
+    /// Close snapshot id, releasing its copies.  Returns false if it was
+    /// not open.
+    fn snapshot_close(&mut self, _id: u64) -> bool {
+        false
+    }
+
+    /// Lookup value by key, as of snapshot id.
+    fn snapshot_get(&self, _id: u64, _key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Err(ERR_NO_SNAPSHOT)
+    }
+
+    /// List keys as of snapshot id, in db order, resuming after start, as
+    /// iter_keys() does.  Returns at most max_iter_keys() keys; list_end is
+    /// false if more may follow the last key returned.
+    fn snapshot_iter_keys(&self, _id: u64, _start: Option<&[u8]>) -> Result<KeyList, Error> {
+        Err(ERR_NO_SNAPSHOT)
+    }
+
+    /// Point-in-time, read-only copy of the db, which later writes to the
+    /// db do not change, for reads consistent across many keys and pages
+    /// without holding the db.  Unlike snapshot_open(), it needs no
+    /// closing, and is released when dropped.  This default copies every
+    /// record, key and value, so holds memory proportional to the whole
+    /// db, for as long as the snapshot is held, and reads the whole db to
+    /// take it.  Backends with native snapshots may share storage instead.
+    fn snapshot(&self) -> Result<Box<dyn Snapshot + Send>, Error> {
+        let mut records = BTreeMap::new();
+        self.for_each_entry(None, |key, val| {
+            records.insert(key.to_vec(), Arc::from(val));
+            ControlFlow::Continue(())
+        })?;
+        Ok(Box::new(CopySnapshot::new(records, self.max_iter_keys())))
+    }
+
+    /// Register secondary index name, over the scalar found at json_path
+    /// (e.g. "$.user.email") in each record's value, parsed as JSON, and
+    /// index every record already stored.  Thereafter each write
+    /// maintains the index, via entries stored within the db.  Returns
+    /// false if name is already registered with the same json_path.
+    /// Requires the index layer (index::IndexDb), which wrap_db() adds to
+    /// every db.
+    fn create_index(&mut self, _name: &str, _json_path: &str) -> Result<bool, Error> {
+        Err(ERR_NO_INDEXES)
+    }
+
+    /// Keys of the records whose indexed field equals value, in key
+    /// order, or None if index name is not registered.
+    fn index_lookup(&self, _name: &str, _value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        Err(ERR_NO_INDEXES)
+    }
+
+    /// True while writes are refused with ERR_DEGRADED, after repeated
+    /// write failures (see Config.write_failure_threshold).
+    fn degraded(&self) -> bool {
+        false
+    }
+
+    /// Leave degraded mode, accepting writes again.
+    fn reset_degraded(&mut self) {}
+
+    /// As put(), storing metadata with the value:  (name, value) pairs,
+    /// e.g. a content type, returned with it by get_meta().  Any other
+    /// write of the key but append() replaces its metadata with none.
+    /// Metadata is limited to MAX_META_ENTRIES entries and MAX_META_BYTES,
+    /// else ERR_META_TOO_LARGE.  Requires the metadata layer (meta::MetaDb),
+    /// which wrap_db() adds if Config.value_metadata is set; otherwise
+    /// non-empty metadata fails with ERR_META_UNSUPPORTED.
+    fn put_meta(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        meta: &[(String, String)],
+    ) -> Result<bool, Error> {
+        if !meta.is_empty() {
+            return Err(ERR_META_UNSUPPORTED);
+        }
+        self.put(key, val)
+    }
+
+    /// As get_ref(), also returning the metadata stored with the value by
+    /// put_meta(); empty, if none.
+    fn get_meta(&self, key: &[u8]) -> Result<Option<(ValueRef, Metadata)>, Error> {
+        Ok(self.get_ref(key)?.map(|val| (val, Vec::new())))
+    }
+
+    /// As get(), also returning when the value was last written, in
+    /// milliseconds since the Unix epoch; 0 for a value written before
+    /// times were tracked.  Requires the modification time layer
+    /// (modtime::ModTimeDb), which wrap_db() adds if Config.track_modified
+    /// is set; otherwise fails with ERR_MODTIME_UNSUPPORTED.
+    fn get_modified(&self, _key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        Err(ERR_MODTIME_UNSUPPORTED)
+    }
+
+    /// As get(), also returning the key's version:  the count of writes
+    /// of the key since it was last absent, bumped by every put, batch
+    /// insert and merge; 0 for a value written before versions were
+    /// tracked.  Requires the version layer (version::VersionDb), which
+    /// wrap_db() adds if Config.track_versions is set; otherwise fails
+    /// with ERR_VERSION_UNSUPPORTED.
+    fn get_versioned(&self, _key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        Err(ERR_VERSION_UNSUPPORTED)
+    }
+
+    /// Put val under key only if the key's version, per get_versioned(),
+    /// is expected (0:  key absent), returning false, storing nothing,
+    /// if not.  The check and write are atomic, as &mut self excludes
+    /// other writers, so of two writers expecting one version, only the
+    /// first succeeds.
+    fn put_if_version(&mut self, key: &[u8], val: &[u8], expected: u64) -> Result<bool, Error> {
+        let version = self
+            .get_versioned(key)?
+            .map_or(0, |(_val, version)| version);
+        if version != expected {
+            return Ok(false);
+        }
+        self.put(key, val)?;
+        Ok(true)
+    }
+
+    /// Bulk conditional set, e.g. to refresh cache entries only where
+    /// stale:  store each put's value only if its key's version, per
+    /// get_versioned(), is at most the put's version (0:  key absent),
+    /// skipping the rest, and return whether each put was applied, in
+    /// order.  The versions are read, and the applied puts written as one
+    /// batch, atomically, as &mut self excludes other writers; a key put
+    /// twice is checked the second time against the version its first
+    /// put left.  Requires the version layer (version::VersionDb), as
+    /// get_versioned() does.
+    fn put_if_not_newer(&mut self, puts: &[VersionedPut]) -> Result<Vec<bool>, Error> {
+        let mut versions: HashMap<&[u8], u64> = HashMap::new();
+        let mut applied = Vec::with_capacity(puts.len());
+        let mut batch = Batch::with_capacity(puts.len());
+        for put in puts {
+            let version = match versions.get(put.key.as_slice()) {
+                Some(version) => *version,
+                None => self
+                    .get_versioned(&put.key)?
+                    .map_or(0, |(_val, version)| version),
+            };
+            let apply = version <= put.version;
+            if apply {
+                batch.insert(&put.key, &put.value);
+            }
+            versions.insert(&put.key, if apply { version + 1 } else { version });
+            applied.push(apply);
+        }
+        if !batch.is_empty() {
+            self.apply_batch(&batch)?;
+        }
+        Ok(applied)
+    }
+
+    /// Put the value read from reader, to its end, under key, as put().
+    /// For values too large to buffer comfortably:  a backend able to
+    /// write a value incrementally (e.g. to a file or object store) may
+    /// do so, chunk by chunk.  By default, and on every backend here, the
+    /// value is read whole into memory, then put, through each layer's
+    /// put() as usual; the memory backend keeps it so.  An error reading
+    /// fails with Backend, storing nothing.  A caller bounding the value
+    /// should bound the reader (e.g. by Read::take()).
+    fn put_stream(&mut self, key: &[u8], reader: &mut dyn Read) -> Result<bool, Error> {
+        let mut val = Vec::new();
+        if let Err(e) = reader.read_to_end(&mut val) {
+            return Err(Error::Backend(format!("read failed: {e}")));
+        }
+        self.put(key, &val)
+    }
+
+    /// Reclaim the space of deleted and overwritten records, e.g. by
+    /// compacting a log-structured backend's storage, so that reads no
+    /// longer pass over them.  Runs to completion before returning;
+    /// stored records are unchanged.  Backends which reclaim space as
+    /// they go (e.g. memory, or LMDB, reusing freed pages) have nothing
+    /// to compact.
+    fn compact(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Write all completed writes durably to storage (fsync), before
+    /// returning, whatever the backend's own sync policy.  Backends
+    /// without durable storage (e.g. memory) have nothing to flush.
+    fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// As put(), then flush() if sync is true, for individual writes
+    /// which must be durable once acknowledged.
+    fn put_sync(&mut self, key: &[u8], val: &[u8], sync: bool) -> Result<bool, Error> {
+        let res = self.put(key, val)?;
+        if sync {
+            self.flush()?;
+        }
+        Ok(res)
+    }
+
+    /// As del(), then flush() if sync is true.  Flushes even if key was
+    /// not found, as earlier writes may still be pending.
+    fn del_sync(&mut self, key: &[u8], sync: bool) -> Result<bool, Error> {
+        let res = self.del(key)?;
+        if sync {
+            self.flush()?;
+        }
+        Ok(res)
+    }
+}
+
+/// Convenience methods, layered on the Db trait.  Generic methods live
+/// here rather than in Db, so that Db remains object-safe.
+pub trait DbExt: Db {
+    /// Visit each key in db order, fetching pages of keys via iter_keys()
+    /// as needed.  Iteration resumes after `start`, if given, as with
+    /// IterOptions::start().  Stop early by returning ControlFlow::Break
+    /// from the visitor.
+    fn for_each_key<F: FnMut(&[u8]) -> ControlFlow<()>>(
+        &self,
+        start: Option<&[u8]>,
+        mut f: F,
+    ) -> Result<(), Error> {
+        let mut last_key: Option<Vec<u8>> = start.map(|k| k.to_vec());
+        loop {
+            let mut opts = IterOptions::new();
+            if let Some(key) = &last_key {
+                opts.start(key);
+            }
+            let key_list = self.iter_keys(opts)?;
+
+            for key in &key_list.keys {
+                if let ControlFlow::Break(()) = f(key) {
+                    return Ok(());
+                }
+            }
+
+            if key_list.list_end || key_list.keys.is_empty() {
+                return Ok(());
+            }
+            last_key = key_list.keys.last().cloned();
+        }
+    }
+
+    /// As for_each_key(), visiting each key and its value.  Keys removed
+    /// between fetching a page of keys and reading the value are skipped.
+    fn for_each_entry<F: FnMut(&[u8], &[u8]) -> ControlFlow<()>>(
+        &self,
+        start: Option<&[u8]>,
+        mut f: F,
+    ) -> Result<(), Error> {
+        let mut res = Ok(());
+        self.for_each_key(start, |key| match self.get_ref(key) {
+            Ok(Some(val)) => f(key, &val),
+            Ok(None) => ControlFlow::Continue(()),
+            Err(e) => {
+                res = Err(e);
+                ControlFlow::Break(())
+            }
+        })?;
+
+        res
+    }
+
+    /// Lookup value by key; on a miss, call loader to fetch the value from
+    /// its origin, store it, and return it.  Nothing is stored if loader
+    /// returns None or an error.
+    ///
+    /// Single flight:  the db is borrowed mutably for the whole lookup,
+    /// including the load.  Callers sharing a db (e.g. the server, via
+    /// its Mutex) therefore wait for an in-progress load, then find the
+    /// stored value, so an origin is asked for a key at most once per
+    /// miss.  The cost is that loads, even of unrelated keys, are
+    /// serialized with all other db access; keep loaders fast, or use
+    /// singleflight::SingleFlight, which locks per key.
+    fn get_or_load<F: FnOnce(&[u8]) -> Result<Option<Vec<u8>>, Error>>(
+        &mut self,
+        key: &[u8],
+        loader: F,
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(val) = self.get(key)? {
+            return Ok(Some(val));
+        }
+
+        let loaded = loader(key)?;
+        if let Some(val) = &loaded {
+            self.put(key, val)?;
+        }
+
+        Ok(loaded)
+    }
+
+    /// Count keys beginning with prefix (all keys, if empty), fetching
+    /// pages of keys via iter_keys_prefix().  Reads every matching key,
+    /// so costs time in proportion to the count.
+    fn count_prefix(&self, prefix: &[u8]) -> Result<u64, Error> {
+        let mut n_keys = 0;
+        let mut last_key: Option<Vec<u8>> = None;
+        loop {
+            let key_list = self.iter_keys_prefix(prefix, last_key.as_deref())?;
+            n_keys += key_list.keys.len() as u64;
+
+            if key_list.list_end || key_list.keys.is_empty() {
+                return Ok(n_keys);
+            }
+            last_key = key_list.keys.last().cloned();
+        }
+    }
+}
+
+impl<T: Db + ?Sized> DbExt for T {}
+
+pub trait Driver {
+    fn start_db(&self, cfg: Config) -> Result<Box<dyn Db + Send>, Error>;
+}
+
+#[derive(Default)]
+pub struct ConfigBuilder {
+    pub path: Option<String>,
+    pub read_only: Option<bool>,
+    pub replica: Option<bool>,
+    pub key_case: Option<KeyCase>,
+    pub slow_op_threshold_ms: Option<u64>,
+    pub op_timeout_ms: Option<u64>,
+    pub change_log_len: Option<u64>,
+    pub default_ttl_secs: Option<u64>,
+    pub max_keys: Option<u64>,
+    pub max_total_bytes: Option<u64>,
+    pub eviction: Option<Eviction>,
+    pub write_failure_threshold: Option<u64>,
+    pub value_metadata: Option<bool>,
+    pub value_transforms: Vec<Arc<dyn ValueTransform>>,
+    pub compress_values: Option<bool>,
+    pub encryption_key: Option<Vec<u8>>,
+    pub verify_checksums: Option<bool>,
+    pub track_modified: Option<bool>,
+    pub track_versions: Option<bool>,
+    pub max_iter_keys: Option<usize>,
+    pub sync_writes: Option<bool>,
+    pub max_key_len: Option<usize>,
+    pub max_value_len: Option<usize>,
+    pub merge_fn: Option<MergeFn>,
+    pub watch: Option<Arc<WatchHub>>,
+    pub audit: Option<Arc<dyn AuditSink>>,
+}
+
+impl ConfigBuilder {
+    pub fn new() -> ConfigBuilder {
+        ConfigBuilder {
+            path: None,
+            read_only: None,
+            replica: None,
+            key_case: None,
+            slow_op_threshold_ms: None,
+            op_timeout_ms: None,
+            change_log_len: None,
+            default_ttl_secs: None,
+            max_keys: None,
+            max_total_bytes: None,
+            eviction: None,
+            write_failure_threshold: None,
+            value_metadata: None,
+            value_transforms: Vec::new(),
+            compress_values: None,
+            encryption_key: None,
+            verify_checksums: None,
+            track_modified: None,
+            track_versions: None,
+            max_iter_keys: None,
+            sync_writes: None,
+            max_key_len: None,
+            max_value_len: None,
+            merge_fn: None,
+            watch: None,
+            audit: None,
+        }
+    }
+
+    pub fn path(&mut self, path_in: String) -> &mut ConfigBuilder {
+        self.path = Some(path_in);
+        self
+    }
+
+    pub fn read_only(&mut self, val_in: bool) -> &mut ConfigBuilder {
+        self.read_only = Some(val_in);
+        self
+    }
+
+    /// Open the db as a replica:  a copy of another db's directory, e.g.
+    /// a filesystem snapshot, which may be held open by its primary.  A
+    /// replica is read-only, and never locks or modifies its files.
+    pub fn replica(&mut self, val_in: bool) -> &mut ConfigBuilder {
+        self.replica = Some(val_in);
+        self
+    }
+
+    pub fn key_case(&mut self, val_in: KeyCase) -> &mut ConfigBuilder {
+        self.key_case = Some(val_in);
+        self
+    }
+
+    pub fn slow_op_threshold_ms(&mut self, val_in: u64) -> &mut ConfigBuilder {
+        self.slow_op_threshold_ms = Some(val_in);
+        self
+    }
+
+    /// Fail db ops not complete within val_in ms; see timeout::TimeoutDb.
+    pub fn op_timeout_ms(&mut self, val_in: u64) -> &mut ConfigBuilder {
+        self.op_timeout_ms = Some(val_in);
+        self
+    }
+
+    pub fn change_log_len(&mut self, val_in: u64) -> &mut ConfigBuilder {
+        self.change_log_len = Some(val_in);
+        self
+    }
+
+    /// Expire every write giving no expiry of its own val_in secs after
+    /// it; see expiry::ExpiryDb.
+    pub fn default_ttl_secs(&mut self, val_in: u64) -> &mut ConfigBuilder {
+        self.default_ttl_secs = Some(val_in);
+        self
+    }
+
+    pub fn max_keys(&mut self, val_in: u64) -> &mut ConfigBuilder {
+        self.max_keys = Some(val_in);
+        self
+    }
+
+    pub fn max_total_bytes(&mut self, val_in: u64) -> &mut ConfigBuilder {
+        self.max_total_bytes = Some(val_in);
+        self
+    }
+
+    pub fn eviction(&mut self, val_in: Eviction) -> &mut ConfigBuilder {
+        self.eviction = Some(val_in);
+        self
+    }
+
+    pub fn write_failure_threshold(&mut self, val_in: u64) -> &mut ConfigBuilder {
+        self.write_failure_threshold = Some(val_in);
+        self
+    }
+
+    pub fn value_metadata(&mut self, val_in: bool) -> &mut ConfigBuilder {
+        self.value_metadata = Some(val_in);
+        self
+    }
+
+    /// Add a transform to the db's chain; see transform::TransformDb for
+    /// the order in which chained transforms apply.
+    pub fn value_transform(&mut self, val_in: Arc<dyn ValueTransform>) -> &mut ConfigBuilder {
+        self.value_transforms.push(val_in);
+        self
+    }
+
+    /// Compress values at rest; see compress::CompressedDb.
+    pub fn compress_values(&mut self, val_in: bool) -> &mut ConfigBuilder {
+        self.compress_values = Some(val_in);
+        self
+    }
+
+    /// Encrypt values at rest under key, of encrypt::ENCRYPTION_KEY_LEN
+    /// bytes; see encrypt::EncryptedDb.
+    pub fn encryption_key(&mut self, val_in: Vec<u8>) -> &mut ConfigBuilder {
+        self.encryption_key = Some(val_in);
+        self
+    }
+
+    /// Store a checksum with each value, verified on read; see
+    /// checksum::ChecksumDb.
+    pub fn verify_checksums(&mut self, val_in: bool) -> &mut ConfigBuilder {
+        self.verify_checksums = Some(val_in);
+        self
+    }
+
+    /// Record when each value was last written, for get_modified(); see
+    /// modtime::ModTimeDb.
+    pub fn track_modified(&mut self, val_in: bool) -> &mut ConfigBuilder {
+        self.track_modified = Some(val_in);
+        self
+    }
+
+    /// Count writes of each key, for get_versioned() and put_if_version();
+    /// see version::VersionDb.
+    pub fn track_versions(&mut self, val_in: bool) -> &mut ConfigBuilder {
+        self.track_versions = Some(val_in);
+        self
+    }
+
+    /// Most keys listed per iter_keys() page; must be at least 1.
+    pub fn max_iter_keys(&mut self, val_in: usize) -> &mut ConfigBuilder {
+        self.max_iter_keys = Some(val_in);
+        self
+    }
+
+    /// Make every write durable before it returns; see sync::SyncDb.
+    pub fn sync_writes(&mut self, val_in: bool) -> &mut ConfigBuilder {
+        self.sync_writes = Some(val_in);
+        self
+    }
+
+    /// Longest key written, in bytes; 0 for no limit.  See limit::LimitDb.
+    pub fn max_key_len(&mut self, val_in: usize) -> &mut ConfigBuilder {
+        self.max_key_len = Some(val_in);
+        self
+    }
+
+    /// Longest value written, in bytes; 0 for no limit.
+    pub fn max_value_len(&mut self, val_in: usize) -> &mut ConfigBuilder {
+        self.max_value_len = Some(val_in);
+        self
+    }
+
+    /// Merge operator of Db::merge(); see merge::MergeDb.
+    pub fn merge_fn(&mut self, val_in: MergeFn) -> &mut ConfigBuilder {
+        self.merge_fn = Some(val_in);
+        self
+    }
+
+    /// Publish the db's changes to hub; see watch::WatchDb.
+    pub fn watch(&mut self, val_in: Arc<WatchHub>) -> &mut ConfigBuilder {
+        self.watch = Some(val_in);
+        self
+    }
+
+    /// Record the db's writes to sink; see audit::AuditDb.
+    pub fn audit(&mut self, val_in: Arc<dyn AuditSink>) -> &mut ConfigBuilder {
+        self.audit = Some(val_in);
+        self
+    }
+
+    /// Configuration as set; a path not set defaults to "./db".  See
+    /// try_build() to refuse an unset path instead.
+    pub fn build(&self) -> Config {
+        Config {
+            path: match &self.path {
+                None => String::from("./db"),
+                Some(p) => String::from(p),
+            },
+            // a replica is always read-only
+            read_only: self.read_only.unwrap_or(false) || self.replica.unwrap_or(false),
+            replica: self.replica.unwrap_or(false),
+            key_case: match &self.key_case {
+                None => KeyCase::CaseSensitive,
+                Some(v) => *v,
+            },
+            slow_op_threshold_ms: self.slow_op_threshold_ms,
+            op_timeout_ms: self.op_timeout_ms,
+            change_log_len: self.change_log_len,
+            default_ttl_secs: self.default_ttl_secs,
+            max_keys: self.max_keys,
+            max_total_bytes: self.max_total_bytes,
+            eviction: self.eviction.unwrap_or(Eviction::None),
+            write_failure_threshold: self.write_failure_threshold,
+            value_metadata: self.value_metadata.unwrap_or(false),
+            value_transforms: self.value_transforms.clone(),
+            compress_values: self.compress_values.unwrap_or(false),
+            encryption_key: self.encryption_key.clone(),
+            verify_checksums: self.verify_checksums.unwrap_or(false),
+            track_modified: self.track_modified.unwrap_or(false),
+            track_versions: self.track_versions.unwrap_or(false),
+            max_iter_keys: self.max_iter_keys.unwrap_or(MAX_ITER_KEYS),
+            sync_writes: self.sync_writes.unwrap_or(false),
+            max_key_len: self.max_key_len.unwrap_or(DEF_MAX_KEY_LEN),
+            max_value_len: self.max_value_len.unwrap_or(DEF_MAX_VALUE_LEN),
+            merge_fn: self.merge_fn,
+            watch: self.watch.clone(),
+            audit: self.audit.clone(),
+        }
+    }
+
+    /// As build(), but failing with Error::InvalidInput, rather than
+    /// defaulting, if path was never set, and if it names somewhere a db
+    /// could not be stored:  an existing file, not a directory, or,
+    /// unless the db is read-only, within a directory whose permissions
+    /// forbid writing.  The path need not exist yet; its nearest existing
+    /// ancestor is checked instead.  Permissions are judged by their
+    /// bits alone, not the user's rights (see fs::Permissions::readonly),
+    /// so the driver may still fail to open the db.  Drivers storing
+    /// nothing (mem) ignore path:  use build() for them.
+    pub fn try_build(&self) -> Result<Config, Error> {
+        let cfg = match &self.path {
+            None => return Err(Error::InvalidInput(String::from("db path not set"))),
+            Some(_path) => self.build(),
+        };
+
+        // the path itself, or the directory it would be created within
+        let path = Path::new(&cfg.path);
+        let existing = path
+            .ancestors()
+            .map(|dir| {
+                if dir.as_os_str().is_empty() {
+                    Path::new(".")
+                } else {
+                    dir
+                }
+            })
+            .find_map(|dir| dir.metadata().ok().map(|meta| (dir, meta)));
+        let (dir, meta) = match existing {
+            None => {
+                return Err(Error::InvalidInput(format!(
+                    "db path {} not found",
+                    cfg.path
+                )))
+            }
+            Some(existing) => existing,
+        };
+        if !meta.is_dir() {
+            return Err(Error::InvalidInput(format!(
+                "db path {} is not a directory",
+                dir.display()
+            )));
+        }
+        if !cfg.read_only && meta.permissions().readonly() {
+            return Err(Error::InvalidInput(format!(
+                "db path {} is not writable",
+                dir.display()
+            )));
+        }
+        Ok(cfg)
+    }
+}
+
+/// Wrap a newly opened backend db in the layers requested by its
+/// configuration.  Called by each Driver's start_db().
+pub fn wrap_db(db: Box<dyn Db + Send>, cfg: &Config) -> Result<Box<dyn Db + Send>, Error> {
+    if cfg.max_iter_keys == 0 {
+        return Err(Error::InvalidInput(String::from(
+            "max_iter_keys must be at least 1",
+        )));
+    }
+
+    // innermost, so that every stored value, as stored, is checksummed
+    let db: Box<dyn Db + Send> = if cfg.verify_checksums {
+        Box::new(ChecksumDb::new(db))
+    } else {
+        db
+    };
+
+    // next, so that every stored value is encrypted
+    let db: Box<dyn Db + Send> = match &cfg.encryption_key {
+        None => db,
+        Some(key) => Box::new(EncryptedDb::new(db, key)?),
+    };
+
+    // next, so that every other layer sees values untransformed, and
+    // values are transformed before they are encrypted.  compression
+    // first, as other transforms may leave values incompressible
+    let mut transforms = cfg.value_transforms.clone();
+    if cfg.compress_values {
+        transforms.insert(0, Arc::new(GzipTransform::new()));
+    }
+    let db: Box<dyn Db + Send> = if transforms.is_empty() {
+        db
+    } else {
+        Box::new(TransformDb::new(db, transforms))
+    };
+
+    // next, so that times are stored, transformed, with values, and
+    // every other layer sees values without them
+    let db: Box<dyn Db + Send> = if cfg.track_modified {
+        Box::new(ModTimeDb::new(db))
+    } else {
+        db
+    };
+
+    // next, as times are, so that versions are stored with values, and
+    // every other layer sees values without them
+    let db: Box<dyn Db + Send> = if cfg.track_versions {
+        Box::new(VersionDb::new(db))
+    } else {
+        db
+    };
+
+    // next, so that logged keys are case-folded
+    let db: Box<dyn Db + Send> = match cfg.change_log_len {
+        None => db,
+        Some(n) => Box::new(ChangeLogDb::new(db, n)?),
+    };
+
+    // above the change log, whose records are not counted
+    let db: Box<dyn Db + Send> = match (cfg.max_keys, cfg.max_total_bytes) {
+        (None, None) => db,
+        (max_keys, max_total_bytes) => Box::new(QuotaDb::new(db, max_keys, max_total_bytes)?),
+    };
+
+    // above the change log and quota, so that snapshots see user writes
+    // only, and below key folding, so that they hold folded keys
+    let db: Box<dyn Db + Send> = Box::new(SnapshotDb::new(db));
+
+    // above snapshots, so that they hold index entries as of their
+    // records, and below key folding, so that entries hold folded keys
+    let db: Box<dyn Db + Send> = Box::new(IndexDb::new(db, &cfg.path, cfg.read_only)?);
+
+    // above indexes, the change log and quota, so that removing an
+    // expired record goes through them, and below auditing, so that its
+    // index keys are not recorded
+    let db: Box<dyn Db + Send> = Box::new(ExpiryDb::new(db, cfg.default_ttl_secs)?);
+
+    // above indexes, so that their entries are not recorded, and below
+    // eviction and key folding, so that evictions are, with folded keys
+    let db: Box<dyn Db + Send> = match &cfg.audit {
+        None => db,
+        Some(sink) => Box::new(AuditDb::new(db, Arc::clone(sink))),
+    };
+
+    // as auditing, so that watchers see the writes recorded
+    let db: Box<dyn Db + Send> = match &cfg.watch {
+        None => db,
+        Some(hub) => Box::new(WatchDb::new(db, Arc::clone(hub))),
+    };
+
+    // above indexes, so that evicting a key removes its index entries,
+    // and below key folding, so that it tracks folded keys
+    let quota = cfg.max_keys.is_some() || cfg.max_total_bytes.is_some();
+    let db: Box<dyn Db + Send> = match cfg.eviction {
+        Eviction::None => db,
+        _ if !quota => db,
+        policy => Box::new(EvictDb::new(db, policy)?),
+    };
+
+    let db: Box<dyn Db + Send> = match cfg.key_case {
+        KeyCase::CaseSensitive => db,
+        KeyCase::LowercaseFold => Box::new(FoldCaseDb::new(db)),
+    };
+
+    // above every layer which writes, so that a write is flushed once
+    // complete, and below degrading, so that a failed flush counts
+    let db: Box<dyn Db + Send> = if cfg.sync_writes {
+        Box::new(SyncDb::new(db))
+    } else {
+        db
+    };
+
+    // above every layer which writes, so that any failed write counts
+    let db: Box<dyn Db + Send> = match cfg.write_failure_threshold {
+        None => db,
+        Some(n) => Box::new(DegradeDb::new(db, n)),
+    };
+
+    // above every other layer, so that all see values as stored, with
+    // any metadata header:  quotas count it, and rename and swap move it
+    let db: Box<dyn Db + Send> = if cfg.value_metadata {
+        Box::new(MetaDb::new(db))
+    } else {
+        db
+    };
+
+    // above metadata, so that values are measured as written, without
+    // its header
+    let db: Box<dyn Db + Send> = match (cfg.max_key_len, cfg.max_value_len) {
+        (0, 0) => db,
+        (max_key_len, max_value_len) => Box::new(LimitDb::new(db, max_key_len, max_value_len)),
+    };
+
+    // above limits, so that merged values are measured, and metadata, so
+    // that the operator sees values without its header
+    let db: Box<dyn Db + Send> = match cfg.merge_fn {
+        None => db,
+        Some(merge_fn) => Box::new(MergeDb::new(db, merge_fn)),
+    };
+
+    // next, so that timing covers all other layers
+    let db: Box<dyn Db + Send> = match cfg.slow_op_threshold_ms {
+        None => db,
+        Some(ms) => Box::new(SlowLogDb::new(db, Duration::from_millis(ms))),
+    };
+
+    // outermost, so that the limit covers all other layers, and a slow op
+    // is logged once complete, even if timed out
+    Ok(match cfg.op_timeout_ms {
+        None => db,
+        Some(ms) => Box::new(TimeoutDb::new(db, Duration::from_millis(ms))),
+    })
+}
+
+/// Lock a db (or other state) shared between threads.  If a thread
+/// panicked while holding the lock, the lock is poisoned, and this
+/// returns ERR_LOCK_POISONED, rather than panicking in turn, unless
+/// recover(value) allows the lock to be recovered.  A recovered lock is
+/// cleared of poison, so later callers lock it normally.
+///
+/// Integrity after a panic is best-effort:  each backend write is atomic,
+/// but a sequence of writes interrupted part-way is not rolled back, and
+/// in-memory state of db layers (e.g. quota tallies) may be stale.
+pub fn lock_shared<T: ?Sized, F: FnOnce(&T) -> bool>(
+    m: &Mutex<T>,
+    recover: F,
+) -> Result<MutexGuard<'_, T>, Error> {
+    match m.lock() {
+        Ok(guard) => Ok(guard),
+        Err(poisoned) => {
+            let guard = poisoned.into_inner();
+            if !recover(&*guard) {
+                return Err(ERR_LOCK_POISONED);
+            }
+            m.clear_poison();
+            Ok(guard)
+        }
+    }
+}
+
+/// As lock_shared(), for state behind a RwLock:  a shared lock, held
+/// alongside other readers, e.g. to read a db.  Only a writer panicking
+/// poisons the lock.
+pub fn read_shared<T: ?Sized, F: FnOnce(&T) -> bool>(
+    m: &RwLock<T>,
+    recover: F,
+) -> Result<RwLockReadGuard<'_, T>, Error> {
+    match m.read() {
+        Ok(guard) => Ok(guard),
+        Err(poisoned) => {
+            let guard = poisoned.into_inner();
+            if !recover(&*guard) {
+                return Err(ERR_LOCK_POISONED);
+            }
+            m.clear_poison();
+            Ok(guard)
+        }
+    }
+}
+
+/// As lock_shared(), for state behind a RwLock:  an exclusive lock, e.g.
+/// to write to a db.
+pub fn write_shared<T: ?Sized, F: FnOnce(&T) -> bool>(
+    m: &RwLock<T>,
+    recover: F,
+) -> Result<RwLockWriteGuard<'_, T>, Error> {
+    match m.write() {
+        Ok(guard) => Ok(guard),
+        Err(poisoned) => {
+            let guard = poisoned.into_inner();
+            if !recover(&*guard) {
+                return Err(ERR_LOCK_POISONED);
+            }
+            m.clear_poison();
+            Ok(guard)
+        }
+    }
+}
+
+// forward to boxed db, so that db layers may wrap any backend
+impl<T: Db + ?Sized> Db for Box<T> {
+    fn apply_batch(&mut self, batch: &Batch) -> Result<bool, Error> {
+        (**self).apply_batch(batch)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        (**self).clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        (**self).del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        (**self).get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        (**self).put(key, val)
+    }
+
+    fn iter_keys(&self, opts: IterOptions) -> Result<KeyList, Error> {
+        (**self).iter_keys(opts)
+    }
+
+    fn stat(&self) -> Result<DbStat, Error> {
+        (**self).stat()
+    }
+
+    fn len(&self) -> Result<u64, Error> {
+        (**self).len()
+    }
+
+    fn is_empty(&self) -> Result<bool, Error> {
+        (**self).is_empty()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        (**self).max_iter_keys()
+    }
+
+    fn iter_keys_prefix(&self, prefix: &[u8], start_key: Option<&[u8]>) -> Result<KeyList, Error> {
+        (**self).iter_keys_prefix(prefix, start_key)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        (**self).has_prefix(prefix)
+    }
+
+    fn iter_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        start_key: Option<&[u8]>,
+    ) -> Result<KeyList, Error> {
+        (**self).iter_range(start, end, start_key)
+    }
+
+    fn get_range(&self, start: &[u8], end: &[u8], limit: usize) -> Result<EntryList, Error> {
+        (**self).get_range(start, end, limit)
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<ValueRef>, Error> {
+        (**self).get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        (**self).get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        (**self).exists_multi(keys)
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        (**self).exists(key)
+    }
+
+    fn apply_batch_until(&mut self, batch: &Batch, deadline: Instant) -> Result<usize, Error> {
+        (**self).apply_batch_until(batch, deadline)
+    }
+
+    fn put_next(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).put_next(val)
+    }
+
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> Result<bool, Error> {
+        (**self).rename(from, to, overwrite)
+    }
+
+    fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<(), Error> {
+        (**self).swap(key_a, key_b)
+    }
+
+    fn del_if_empty(&mut self, key: &[u8]) -> Result<bool, Error> {
+        (**self).del_if_empty(key)
+    }
+
+    fn delete_prefix(&mut self, prefix: &[u8]) -> Result<u64, Error> {
+        (**self).delete_prefix(prefix)
+    }
+
+    fn del_many(&mut self, keys: &[Vec<u8>]) -> Result<u64, Error> {
+        (**self).del_many(keys)
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        (**self).compare_and_swap(key, expected, new)
+    }
+
+    fn put_if_absent(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        (**self).put_if_absent(key, val)
+    }
+
+    fn put_hashed(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).put_hashed(val)
+    }
+
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        (**self).get_or_init(key, init)
+    }
+
+    fn append(&mut self, key: &[u8], suffix: &[u8]) -> Result<usize, Error> {
+        (**self).append(key, suffix)
+    }
+
+    fn merge(&mut self, key: &[u8], operand: &[u8]) -> Result<(), Error> {
+        (**self).merge(key, operand)
+    }
+
+    fn add_sharded(&mut self, key: &[u8], delta: i64, shards: u32) -> Result<bool, Error> {
+        (**self).add_sharded(key, delta, shards)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        (**self).read_counter(key)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        (**self).put_expire_at(key, val, unix_ms)
+    }
+
+    fn put_ttl(&mut self, key: &[u8], val: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        (**self).put_ttl(key, val, ttl_secs)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        (**self).touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        (**self).sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        (**self).next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<ChangeList, Error> {
+        (**self).changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<QuotaUsage> {
+        (**self).quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<KeyList, Error> {
+        (**self).scan_glob(pattern, start)
+    }
+
+    fn iter_entries(
+        &self,
+        opts: IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<EntryList, Error> {
+        (**self).iter_entries(opts, max_bytes)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        (**self).size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        (**self).snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        (**self).snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        (**self).snapshot_get(id, key)
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<KeyList, Error> {
+        (**self).snapshot_iter_keys(id, start)
+    }
+
+    fn snapshot(&self) -> Result<Box<dyn Snapshot + Send>, Error> {
+        (**self).snapshot()
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        (**self).create_index(name, json_path)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        (**self).index_lookup(name, value)
+    }
+
+    fn degraded(&self) -> bool {
+        (**self).degraded()
+    }
+
+    fn reset_degraded(&mut self) {
+        (**self).reset_degraded()
+    }
+
+    fn put_meta(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        meta: &[(String, String)],
+    ) -> Result<bool, Error> {
+        (**self).put_meta(key, val, meta)
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<(ValueRef, Metadata)>, Error> {
+        (**self).get_meta(key)
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        (**self).get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        (**self).get_versioned(key)
+    }
+
+    fn put_if_version(&mut self, key: &[u8], val: &[u8], expected: u64) -> Result<bool, Error> {
+        (**self).put_if_version(key, val, expected)
+    }
+
+    fn put_if_not_newer(&mut self, puts: &[VersionedPut]) -> Result<Vec<bool>, Error> {
+        (**self).put_if_not_newer(puts)
+    }
+
+    fn put_stream(&mut self, key: &[u8], reader: &mut dyn Read) -> Result<bool, Error> {
+        (**self).put_stream(key, reader)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        (**self).compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        (**self).flush()
+    }
+
+    fn put_sync(&mut self, key: &[u8], val: &[u8], sync: bool) -> Result<bool, Error> {
+        (**self).put_sync(key, val, sync)
+    }
+
+    fn del_sync(&mut self, key: &[u8], sync: bool) -> Result<bool, Error> {
+        (**self).del_sync(key, sync)
+    }
+}
+
+#[cfg(test)]
+use super::asyncdb::{AsyncDb, BlockingAdapter};
+#[cfg(test)]
+use super::audit::AuditOp;
+#[cfg(test)]
+use super::compress::compressed_db;
+#[cfg(test)]
+use super::groupcommit::{apply_group, GroupCommit};
+#[cfg(test)]
+use super::histogram::{sample_page, Percentiles, SizeSampler};
+#[cfg(test)]
+use super::mem::{new_driver, MemDb};
+#[cfg(test)]
+use super::prefix::{namespace_prefix, PrefixedDb};
+#[cfg(test)]
+use super::singleflight::SingleFlight;
+#[cfg(test)]
+use super::txn::Txn;
+#[cfg(test)]
+use super::watch::{WatchEvent, WatchItem, WatchOp};
+#[cfg(test)]
+use std::fs;
+#[cfg(test)]
+use std::sync::Barrier;
+#[cfg(test)]
+use std::thread;
+#[cfg(test)]
+use tempdir::TempDir;
+
+#[cfg(test)]
+mod tests {
+    // Note this useful idiom: importing names from outer (for mod tests) scope.
+    use super::*;
+
+    #[test]
+    fn test_prefix_upper_bound() {
+        assert_eq!(prefix_upper_bound(b"abc"), Some(b"abd".to_vec()));
+        assert_eq!(prefix_upper_bound(b"2019/"), Some(b"20190".to_vec()));
+        assert_eq!(prefix_upper_bound(b"a\xff"), Some(b"b".to_vec()));
+        assert_eq!(
+            prefix_upper_bound(b"a\xfe\xff\xff"),
+            Some(b"a\xff".to_vec())
+        );
+        assert_eq!(prefix_upper_bound(b"\xff"), None);
+        assert_eq!(prefix_upper_bound(b"\xff\xff\xff"), None);
+        assert_eq!(prefix_upper_bound(b""), None);
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert_eq!(glob_prefix(b"user:*:active"), b"user:");
+        assert_eq!(glob_prefix(b"user:?"), b"user:");
+        assert_eq!(glob_prefix(b"*user"), b"");
+        assert_eq!(glob_prefix(b"user"), b"user");
+
+        assert!(glob_match(b"user:*:active", b"user:1:active"));
+        assert!(glob_match(b"user:*:active", b"user::active"));
+        assert!(glob_match(b"user:*:active", b"user:1:2:active"));
+        assert!(!glob_match(b"user:*:active", b"user:1:inactive:x"));
+        assert!(!glob_match(b"user:*:active", b"user:1:activex"));
+        assert!(glob_match(b"user:?", b"user:1"));
+        assert!(!glob_match(b"user:?", b"user:"));
+        assert!(!glob_match(b"user:?", b"user:12"));
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"**a**", b"a"));
+        assert!(glob_match(b"*a*b", b"xaxxab"));
+        assert!(!glob_match(b"*a*b", b"xaxxa"));
+        assert!(glob_match(b"a*b*c", b"abbbcbc"));
+        assert!(!glob_match(b"", b"a"));
+        assert!(glob_match(b"", b""));
+
+        // byte-wise, not UTF-8 character-wise
+        assert!(glob_match(b"?", b"\xff"));
+        assert!(glob_match(b"??", "\u{e9}".as_bytes()));
+        assert!(!glob_match(b"?", "\u{e9}".as_bytes()));
+    }
+
+    #[test]
+    fn test_get_put() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.get(b"name"), Ok(None));
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
+        assert_eq!(db.del(b"name"), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(None));
+        assert_eq!(db.get(b"never_existed"), Ok(None));
+    }
+
+    #[test]
+    fn test_get_ref() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert!(db.get_ref(b"name").unwrap().is_none());
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+
+        let val1 = db.get_ref(b"name").unwrap().unwrap();
+        let val2 = db.get_ref(b"name").unwrap().unwrap();
+        assert_eq!(&val1[..], b"alan");
+
+        // both views share the stored buffer; no copy
+        assert_eq!(val1.as_ptr(), val2.as_ptr());
+    }
+
+    #[test]
+    fn test_for_each() {
+        const DATA_COUNT: usize = MAX_ITER_KEYS * 2 + 500;
+
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        for i in 0..DATA_COUNT {
+            let key = format!("key {i}");
+            assert_eq!(db.put(key.as_bytes(), &(i as u64).to_be_bytes()), Ok(true));
+        }
+
+        // full traversal, across several pages
+        let mut seen = HashSet::new();
+        let res = db.for_each_key(None, |key| {
+            assert!(seen.insert(key.to_vec()));
+            ControlFlow::Continue(())
+        });
+        assert_eq!(res, Ok(()));
+        assert_eq!(seen.len(), DATA_COUNT);
+
+        // early termination, past the first page
+        let mut n_seen = 0;
+        let res = db.for_each_key(None, |_key| {
+            n_seen += 1;
+            if n_seen == MAX_ITER_KEYS + 10 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        });
+        assert_eq!(res, Ok(()));
+        assert_eq!(n_seen, MAX_ITER_KEYS + 10);
+
+        // entries
+        let mut sum = 0;
+        let res = db.for_each_entry(None, |_key, val| {
+            let mut id_bytes = [0u8; 8];
+            id_bytes.copy_from_slice(val);
+            sum += u64::from_be_bytes(id_bytes);
+            ControlFlow::Continue(())
+        });
+        assert_eq!(res, Ok(()));
+        assert_eq!(sum, (DATA_COUNT * (DATA_COUNT - 1) / 2) as u64);
+    }
+
+    #[test]
+    fn test_iter_entries() {
+        const MAX_BYTES: usize = 1000;
+
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let driver = new_driver();
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // tiny values, amid a few huge ones, one alone over budget
+        let mut expected = HashMap::new();
+        for i in 0..50 {
+            let val = match i {
+                10 | 11 => vec![b'h'; 600],
+                30 => vec![b'H'; 5000],
+                _ => vec![b't'; 3],
+            };
+            let key = format!("entry {i}").into_bytes();
+            assert_eq!(db.put(&key, &val), Ok(true));
+            expected.insert(key, val);
+        }
+
+        // without a budget, one page holds every record
+        let entry_list = db.iter_entries(IterOptions::new(), None).unwrap();
+        assert_eq!(entry_list.entries.len(), expected.len());
+        assert!(entry_list.list_end);
+
+        // with one, pages stay within it, unless of a single record
+        let mut seen = HashMap::new();
+        let mut n_pages = 0;
+        let mut opts = IterOptions::new();
+        loop {
+            let entry_list = db.iter_entries(opts, Some(MAX_BYTES)).unwrap();
+            n_pages += 1;
+            assert!(!entry_list.entries.is_empty());
+            let n_bytes: usize = entry_list.entries.iter().map(|(_k, v)| v.len()).sum();
+            assert!(n_bytes <= MAX_BYTES || entry_list.entries.len() == 1);
+            for (key, val) in &entry_list.entries {
+                assert!(seen.insert(key.clone(), val.clone()).is_none());
+            }
+            if entry_list.list_end {
+                break;
+            }
+            opts = IterOptions::new();
+            opts.start(&entry_list.entries.last().unwrap().0);
+        }
+        assert_eq!(seen, expected);
+        assert!(n_pages >= 3);
+
+        // a budget smaller than any value still progresses, by one a page
+        let entry_list = db.iter_entries(IterOptions::new(), Some(0)).unwrap();
+        assert_eq!(entry_list.entries.len(), 1);
+        assert!(!entry_list.list_end);
+    }
+
+    #[test]
+    fn test_get_or_load() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // miss: loaded from origin, and stored
+        let mut n_loads = 0;
+        let res = db.get_or_load(b"name", |key| {
+            n_loads += 1;
+            assert_eq!(key, b"name");
+            Ok(Some(Vec::from("alan")))
+        });
+        assert_eq!(res, Ok(Some(Vec::from("alan"))));
+        assert_eq!(n_loads, 1);
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
+
+        // hit: origin not consulted
+        let res = db.get_or_load(b"name", |_key| {
+            n_loads += 1;
+            Ok(Some(Vec::from("bob")))
+        });
+        assert_eq!(res, Ok(Some(Vec::from("alan"))));
+        assert_eq!(n_loads, 1);
+
+        // absent at origin, or origin failure: nothing stored
+        assert_eq!(db.get_or_load(b"absent", |_key| Ok(None)), Ok(None));
+        assert_eq!(
+            db.get_or_load(b"absent", |_key| Err(Error::Backend(String::from(
+                "origin down"
+            )))),
+            Err(Error::Backend(String::from("origin down")))
+        );
+        assert_eq!(db.get(b"absent"), Ok(None));
+        assert_eq!(db.stat().unwrap().n_records, 1);
+    }
+
+    #[test]
+    fn test_single_flight() {
+        const N_THREADS: usize = 8;
+
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let db = Arc::new(Mutex::new(new_driver().start_db(db_config).unwrap()));
+        let flights = Arc::new(SingleFlight::new(Duration::from_secs(10)));
+        let n_loads = Arc::new(AtomicUsize::new(0));
+        let barrier = Arc::new(Barrier::new(N_THREADS));
+
+        // concurrent misses of one key run one, slow, load
+        let mut threads = Vec::new();
+        for _ in 0..N_THREADS {
+            let db = Arc::clone(&db);
+            let flights = Arc::clone(&flights);
+            let n_loads = Arc::clone(&n_loads);
+            let barrier = Arc::clone(&barrier);
+            threads.push(thread::spawn(move || {
+                barrier.wait();
+                flights.get_or_load(&*db, b"name", |_key| {
+                    n_loads.fetch_add(1, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(100));
+                    Ok(Some(Vec::from("alan")))
+                })
+            }));
+        }
+        for th in threads {
+            assert_eq!(th.join().unwrap(), Ok(Some(Vec::from("alan"))));
+        }
+        assert_eq!(n_loads.load(Ordering::SeqCst), 1);
+        assert_eq!(db.lock().unwrap().get(b"name"), Ok(Some(Vec::from("alan"))));
+
+        // waiter gives up after timeout; the load still completes
+        let flights = Arc::new(SingleFlight::new(Duration::from_millis(20)));
+        let leader = {
+            let db = Arc::clone(&db);
+            let flights = Arc::clone(&flights);
+            thread::spawn(move || {
+                flights.get_or_load(&*db, b"slow", |_key| {
+                    thread::sleep(Duration::from_millis(200));
+                    Ok(Some(Vec::from("bob")))
+                })
+            })
+        };
+        thread::sleep(Duration::from_millis(50));
+        let res = flights.get_or_load(&*db, b"slow", |_key| panic!("second load"));
+        assert!(res.is_err());
+        assert_eq!(leader.join().unwrap(), Ok(Some(Vec::from("bob"))));
+        assert_eq!(db.lock().unwrap().get(b"slow"), Ok(Some(Vec::from("bob"))));
+    }
+
+    #[test]
+    fn test_group_commit() {
+        const N_THREADS: usize = 8;
+
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_total_bytes(100)
+            .build();
+        let db = Arc::new(Mutex::new(new_driver().start_db(db_config).unwrap()));
+        let n_applies = Arc::new(AtomicUsize::new(0));
+
+        // concurrent puts are applied as one batch, flushed if any asked
+        let group = Arc::new(GroupCommit::new(Duration::from_secs(10), N_THREADS));
+        let barrier = Arc::new(Barrier::new(N_THREADS));
+        let mut threads = Vec::new();
+        for n in 0..N_THREADS {
+            let db = Arc::clone(&db);
+            let group = Arc::clone(&group);
+            let n_applies = Arc::clone(&n_applies);
+            let barrier = Arc::clone(&barrier);
+            threads.push(thread::spawn(move || {
+                barrier.wait();
+                let key = format!("k{n}");
+                group.put(key.as_bytes(), b"v", n == 0, |batch, sync| {
+                    n_applies.fetch_add(1, Ordering::SeqCst);
+                    assert!(sync);
+                    apply_group(&mut **db.lock().unwrap(), batch, sync)
+                })
+            }));
+        }
+        for th in threads {
+            assert_eq!(th.join().unwrap(), Ok(true));
+        }
+        assert_eq!(n_applies.load(Ordering::SeqCst), 1);
+        assert_eq!(
+            db.lock().unwrap().stat().unwrap().n_records,
+            N_THREADS as u64
+        );
+
+        // a failed batch is retried put by put; only the bad put fails
+        let group = Arc::new(GroupCommit::new(Duration::from_secs(10), 2));
+        let big = {
+            let db = Arc::clone(&db);
+            let group = Arc::clone(&group);
+            thread::spawn(move || {
+                group.put(b"big", &[0u8; 100], false, |batch, sync| {
+                    apply_group(&mut **db.lock().unwrap(), batch, sync)
+                })
+            })
+        };
+        let res = group.put(b"small", b"v", false, |batch, sync| {
+            apply_group(&mut **db.lock().unwrap(), batch, sync)
+        });
+        let big_res = big.join().unwrap();
+        assert_eq!(res, Ok(true));
+        assert_eq!(big_res, Err(ERR_QUOTA_EXCEEDED));
+        assert_eq!(db.lock().unwrap().get(b"small"), Ok(Some(Vec::from("v"))));
+        assert_eq!(db.lock().unwrap().get(b"big"), Ok(None));
+
+        // a lone put waits out the window
+        let group = GroupCommit::new(Duration::from_millis(20), N_THREADS);
+        let start = Instant::now();
+        let res = group.put(b"lone", b"v", false, |batch, sync| {
+            apply_group(&mut **db.lock().unwrap(), batch, sync)
+        });
+        assert_eq!(res, Ok(true));
+        assert!(start.elapsed() >= Duration::from_millis(20));
+    }
+
+    fn change_summary(change_list: &ChangeList) -> Vec<(u64, bool, Vec<u8>)> {
+        change_list
+            .changes
+            .iter()
+            .map(|c| match c.op {
+                MutationOp::Insert => (c.seq, true, c.key.clone()),
+                MutationOp::Remove => (c.seq, false, c.key.clone()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_changes_since() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(4)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.changes_since(0).unwrap().last_seq, 0);
+
+        // puts and deletes are listed in order; absent deletes are not
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put(b"b", b"2"), Ok(true));
+        assert_eq!(db.del(b"a"), Ok(true));
+        assert_eq!(db.del(b"never_existed"), Ok(false));
+
+        let change_list = db.changes_since(0).unwrap();
+        assert_eq!(change_list.last_seq, 3);
+        assert!(change_list.list_end);
+        assert!(!change_list.needs_resync);
+        assert_eq!(
+            change_summary(&change_list),
+            vec![
+                (1, true, b"a".to_vec()),
+                (2, true, b"b".to_vec()),
+                (3, false, b"a".to_vec())
+            ]
+        );
+        assert_eq!(db.changes_since(3).unwrap().changes.len(), 0);
+
+        // change log keys are hidden
+        assert_eq!(db.stat().unwrap().n_records, 1);
+        assert_eq!(
+            db.iter_keys(IterOptions::new()).unwrap().keys,
+            vec![b"b".to_vec()]
+        );
+
+        // batch mutations are logged individually; oldest changes trimmed
+        let mut batch = Batch::default();
+        batch.insert(b"c", b"3");
+        batch.remove(b"b");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        let change_list = db.changes_since(1).unwrap();
+        assert_eq!(
+            change_summary(&change_list),
+            vec![
+                (2, true, b"b".to_vec()),
+                (3, false, b"a".to_vec()),
+                (4, true, b"c".to_vec()),
+                (5, false, b"b".to_vec())
+            ]
+        );
+        let change_list = db.changes_since(0).unwrap();
+        assert!(change_list.needs_resync);
+        assert_eq!(change_list.last_seq, 5);
+
+        // seq from the future
+        assert!(db.changes_since(6).unwrap().needs_resync);
+
+        // history does not survive clear
+        assert_eq!(db.clear(), Ok(true));
+        let change_list = db.changes_since(5).unwrap();
+        assert!(change_list.needs_resync);
+        assert_eq!(change_list.last_seq, 6);
+        assert_eq!(db.put(b"d", b"4"), Ok(true));
+        assert_eq!(
+            change_summary(&db.changes_since(6).unwrap()),
+            vec![(7, true, b"d".to_vec())]
+        );
+
+        // change log must be enabled
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let db = driver.start_db(db_config).unwrap();
+        assert!(db.changes_since(0).is_err());
+    }
+
+    #[test]
+    fn test_add_sharded() {
+        const N_THREADS: usize = 8;
+        const N_ADDS: i64 = 100;
+
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let db = Arc::new(Mutex::new(driver.start_db(db_config).unwrap()));
+
+        assert_eq!(db.lock().unwrap().read_counter(b"hits"), Ok(0));
+
+        // concurrent increments, spread across shards
+        let mut threads = Vec::new();
+        for _t in 0..N_THREADS {
+            let db = Arc::clone(&db);
+            threads.push(thread::spawn(move || {
+                for _i in 0..N_ADDS {
+                    let mut db = db.lock().unwrap();
+                    assert_eq!(db.add_sharded(b"hits", 3, 4), Ok(true));
+                }
+            }));
+        }
+        for th in threads {
+            th.join().unwrap();
+        }
+
+        let mut db = db.lock().unwrap();
+        assert_eq!(db.read_counter(b"hits"), Ok(N_THREADS as i64 * N_ADDS * 3));
+        assert!(db.stat().unwrap().n_records <= 4);
+
+        // negative deltas; unrelated keys sharing a prefix are not summed
+        assert_eq!(db.add_sharded(b"hits", -2400, 4), Ok(true));
+        assert_eq!(db.add_sharded(b"hitsx", 5, 4), Ok(true));
+        assert_eq!(db.read_counter(b"hits"), Ok(0));
+        assert_eq!(db.read_counter(b"hitsx"), Ok(5));
+
+        assert_eq!(
+            db.add_sharded(b"hits", 1, 0),
+            Err(Error::InvalidInput(String::from("invalid shard count")))
+        );
+    }
+
+    #[test]
+    fn test_get_multi() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"flag", b""), Ok(true));
+
+        let keys = vec![b"flag".to_vec(), b"missing".to_vec(), b"name".to_vec()];
+        let values = db.get_multi(&keys).unwrap();
+        assert_eq!(
+            values,
+            vec![Some(Vec::new()), None, Some(Vec::from("alan"))]
+        );
+
+        assert_eq!(db.get_multi(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_exists_multi() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"flag", b""), Ok(true));
+
+        // order preserved, repeats included; keys are folded
+        let keys: Vec<&[u8]> = vec![b"Flag", b"missing", b"name", b"missing", b"NAME"];
+        assert_eq!(
+            db.exists_multi(&keys),
+            Ok(vec![true, false, true, false, true])
+        );
+
+        assert_eq!(db.exists_multi(&[]), Ok(Vec::new()));
+
+        // one key at a time; an empty value is still present
+        assert_eq!(db.exists(b"NAME"), Ok(true));
+        assert_eq!(db.exists(b"flag"), Ok(true));
+        assert_eq!(db.exists(b"missing"), Ok(false));
+    }
+
+    #[test]
+    fn test_rename() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"tmp:1", b"alan"), Ok(true));
+        assert_eq!(db.put(b"taken", b"bob"), Ok(true));
+
+        // absent source is not moved
+        assert_eq!(db.rename(b"tmp:none", b"final:1", false), Ok(false));
+        assert_eq!(db.get(b"final:1"), Ok(None));
+
+        // existing target is not replaced, without overwrite
+        assert_eq!(db.rename(b"tmp:1", b"taken", false), Ok(false));
+        assert_eq!(db.get(b"tmp:1"), Ok(Some(Vec::from("alan"))));
+        assert_eq!(db.get(b"taken"), Ok(Some(Vec::from("bob"))));
+
+        // existing target is replaced, with overwrite
+        assert_eq!(db.rename(b"tmp:1", b"taken", true), Ok(true));
+        assert_eq!(db.get(b"tmp:1"), Ok(None));
+        assert_eq!(db.get(b"taken"), Ok(Some(Vec::from("alan"))));
+
+        // rename to absent target
+        assert_eq!(db.rename(b"taken", b"final:1", false), Ok(true));
+        assert_eq!(db.get(b"taken"), Ok(None));
+        assert_eq!(db.get(b"final:1"), Ok(Some(Vec::from("alan"))));
+        assert_eq!(db.stat().unwrap().n_records, 1);
+    }
+
+    // backend wrapper counting flush() calls
+    struct FlushCountDb {
+        inner: Box<dyn Db + Send>,
+        n_flushes: Arc<AtomicUsize>,
+    }
+
+    impl Db for FlushCountDb {
+        fn apply_batch(&mut self, batch: &Batch) -> Result<bool, Error> {
+            self.inner.apply_batch(batch)
+        }
+
+        fn clear(&mut self) -> Result<bool, Error> {
+            self.inner.clear()
+        }
+
+        fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+            self.inner.del(key)
+        }
+
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+            self.inner.get(key)
+        }
+
+        fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+            self.inner.put(key, val)
+        }
+
+        fn iter_keys(&self, opts: IterOptions) -> Result<KeyList, Error> {
+            self.inner.iter_keys(opts)
+        }
+
+        fn stat(&self) -> Result<DbStat, Error> {
+            self.inner.stat()
+        }
+
+        fn flush(&self) -> Result<(), Error> {
+            self.n_flushes.fetch_add(1, Ordering::SeqCst);
+            self.inner.flush()
+        }
+    }
+
+    #[test]
+    fn test_put_sync() {
+        let n_flushes = Arc::new(AtomicUsize::new(0));
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let flush_db = FlushCountDb {
+            inner: new_driver().start_db(db_config).unwrap(),
+            n_flushes: Arc::clone(&n_flushes),
+        };
+
+        // every layer passes flush() through, to the backend
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .slow_op_threshold_ms(1000)
+            .change_log_len(10)
+            .max_keys(10)
+            .build();
+        let mut db = wrap_db(Box::new(flush_db), &db_config).unwrap();
+
+        // default writes do not flush
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put_sync(b"b", b"2", false), Ok(true));
+        assert_eq!(db.del_sync(b"b", false), Ok(true));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 0);
+
+        // sync writes flush, once each, having written
+        assert_eq!(db.put_sync(b"C", b"3", true), Ok(true));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 1);
+        assert_eq!(db.get(b"c"), Ok(Some(b"3".to_vec())));
+        assert_eq!(db.del_sync(b"a", true), Ok(true));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 2);
+        assert_eq!(db.get(b"a"), Ok(None));
+
+        // absent key: still flushed
+        assert_eq!(db.del_sync(b"none", true), Ok(false));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 3);
+
+        // memory backend itself has nothing to flush
+        assert_eq!(db.flush(), Ok(()));
+    }
+
+    #[test]
+    fn test_sync_writes() {
+        let n_flushes = Arc::new(AtomicUsize::new(0));
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let flush_db = FlushCountDb {
+            inner: new_driver().start_db(db_config).unwrap(),
+            n_flushes: Arc::clone(&n_flushes),
+        };
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_keys(2)
+            .sync_writes(true)
+            .build();
+        let mut db = wrap_db(Box::new(flush_db), &db_config).unwrap();
+
+        // each write flushes once, even if also requested durable
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 1);
+        assert_eq!(db.put_sync(b"b", b"2", true), Ok(true));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 2);
+        assert_eq!(db.del(b"b"), Ok(true));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 3);
+        let mut batch = Batch::default();
+        batch.insert(b"c", b"3");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 4);
+
+        // reads do not flush, nor do failed writes
+        assert_eq!(db.get(b"a"), Ok(Some(b"1".to_vec())));
+        assert_eq!(db.put(b"d", b"4"), Err(Error::QuotaExceeded));
+        assert_eq!(n_flushes.load(Ordering::SeqCst), 4);
+    }
+
+    #[test]
+    fn test_size_between() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // memory backend sums actual sizes, in any iteration order
+        for (key, val) in &[("a", "1"), ("b", "22"), ("c", "333")] {
+            assert_eq!(db.put(key.as_bytes(), val.as_bytes()), Ok(true));
+        }
+        assert_eq!(db.size_between(b"", b""), Ok(9));
+        assert_eq!(db.size_between(b"b", b""), Ok(7));
+        assert_eq!(db.size_between(b"a", b"c"), Ok(5));
+        assert_eq!(db.size_between(b"c", b"a"), Ok(0));
+
+        // change log keys are not counted; bounds are case-folded
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(10)
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put(b"B", b"22"), Ok(true));
+        assert_eq!(db.size_between(b"", b""), Ok(5));
+        assert_eq!(db.size_between(b"", b"b"), Ok(2));
+        assert_eq!(db.size_between(b"B", b"C"), Ok(3));
+    }
+
+    #[test]
+    fn test_count_prefix() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(10)
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.count_prefix(b""), Ok(0));
+
+        // more keys than one page; change log keys are not counted, and
+        // the prefix is case-folded
+        for i in 0..MAX_ITER_KEYS + 5 {
+            let key = format!("Tenant1:{i}");
+            assert_eq!(db.put(key.as_bytes(), b"x"), Ok(true));
+        }
+        assert_eq!(db.put(b"tenant2:a", b"x"), Ok(true));
+        assert_eq!(db.put(b"tenant", b"x"), Ok(true));
+        let n_tenant1 = (MAX_ITER_KEYS + 5) as u64;
+        assert_eq!(db.count_prefix(b"TENANT1:"), Ok(n_tenant1));
+        assert_eq!(db.count_prefix(b"tenant2:"), Ok(1));
+        assert_eq!(db.count_prefix(b"tenant"), Ok(n_tenant1 + 2));
+        assert_eq!(db.count_prefix(b""), Ok(n_tenant1 + 2));
+        assert_eq!(db.count_prefix(b"tenant3:"), Ok(0));
+    }
+
+    #[test]
+    fn test_del_many() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .max_keys(10)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        for key in [&b"order:1"[..], b"order:2", b"order:3"] {
+            assert_eq!(db.put(key, b"x"), Ok(true));
+        }
+
+        // absent keys count for nothing; repeats, once folded, count once
+        let keys = vec![
+            b"order:1".to_vec(),
+            b"ORDER:2".to_vec(),
+            b"order:2".to_vec(),
+            b"order:9".to_vec(),
+        ];
+        assert_eq!(db.del_many(&keys), Ok(2));
+        assert_eq!(db.get(b"order:1"), Ok(None));
+        assert_eq!(db.get(b"order:3"), Ok(Some(b"x".to_vec())));
+        assert_eq!(db.quota_usage().unwrap().n_keys, 1);
+
+        assert_eq!(db.del_many(&keys), Ok(0));
+        assert_eq!(db.del_many(&[]), Ok(0));
+    }
+
+    #[test]
+    fn test_delete_prefix() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(10_000)
+            .max_keys(10_000)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // more keys than one page, removed through the quota layer
+        for i in 0..MAX_ITER_KEYS + 5 {
+            let key = format!("session:1:{i}");
+            assert_eq!(db.put(key.as_bytes(), b"x"), Ok(true));
+        }
+        assert_eq!(db.put(b"session:2:a", b"x"), Ok(true));
+        assert_eq!(db.put(b"session:1", b"x"), Ok(true));
+        let n_session1 = (MAX_ITER_KEYS + 5) as u64;
+        assert_eq!(db.delete_prefix(b"session:1:"), Ok(n_session1));
+        assert_eq!(db.count_prefix(b"session:"), Ok(2));
+        assert_eq!(db.quota_usage().unwrap().n_keys, 2);
+        assert_eq!(db.delete_prefix(b"session:1:"), Ok(0));
+
+        // an empty prefix is no shorthand for clear()
+        assert!(matches!(db.delete_prefix(b""), Err(Error::InvalidInput(_))));
+        assert_eq!(db.len(), Ok(2));
+
+        let mut mem_db = MemDb::new();
+        for key in [&b"a:1"[..], b"a:2", b"b:1"] {
+            assert_eq!(mem_db.put(key, b"x"), Ok(true));
+        }
+        assert_eq!(mem_db.delete_prefix(b"a:"), Ok(2));
+        assert_eq!(mem_db.get(b"b:1"), Ok(Some(b"x".to_vec())));
+        assert!(matches!(
+            mem_db.delete_prefix(b""),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_iter_removed_start() {
+        let mut db = MemDb::new();
+        for key in [&b"a"[..], b"b", b"c", b"d"] {
+            assert_eq!(db.put(key, b"x"), Ok(true));
+        }
+
+        // continuing after a key removed since listed, or never present,
+        // resumes at the next key in order
+        assert_eq!(db.del(b"b"), Ok(true));
+        let mut opts = IterOptions::new();
+        opts.start(b"b");
+        let key_list = db.iter_keys(opts).unwrap();
+        assert_eq!(key_list.keys, vec![b"c".to_vec(), b"d".to_vec()]);
+        assert!(key_list.list_end);
+        let mut opts = IterOptions::new();
+        opts.start(b"bb");
+        assert_eq!(db.iter_keys(opts).unwrap().keys[0], b"c".to_vec());
+
+        // ... and back, in reverse
+        let mut opts = IterOptions::new();
+        opts.start(b"b").reverse();
+        assert_eq!(db.iter_keys(opts).unwrap().keys, vec![b"a".to_vec()]);
+
+        // ... nor fails, past the last key
+        let mut opts = IterOptions::new();
+        opts.start(b"e");
+        let key_list = db.iter_keys(opts).unwrap();
+        assert!(key_list.keys.is_empty());
+        assert!(key_list.list_end);
+        assert_eq!(key_list.next_cursor(), None);
+
+        // a page's cursor resumes past its last key, once removed
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_iter_keys(2)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        for key in [&b"a"[..], b"b", b"c", b"d"] {
+            assert_eq!(db.put(key, b"x"), Ok(true));
+        }
+        let key_list = db.iter_keys(IterOptions::new()).unwrap();
+        let cursor = key_list.next_cursor().unwrap();
+        assert_eq!(cursor, b"b".to_vec());
+        assert_eq!(db.del(&cursor), Ok(true));
+        let mut opts = IterOptions::new();
+        opts.start(&cursor);
+        let key_list = db.iter_keys(opts).unwrap();
+        assert_eq!(key_list.keys, vec![b"c".to_vec(), b"d".to_vec()]);
+    }
+
+    #[test]
+    fn test_iter_pages() {
+        let mut db = MemDb::new();
+        let n_keys = 2500;
+        for i in 0..n_keys {
+            assert_eq!(
+                db.put(format!("key{}", i * 7919 % n_keys).as_bytes(), b"x"),
+                Ok(true)
+            );
+        }
+
+        // pages resume in order, seeing every key once
+        let mut seen: Vec<Vec<u8>> = Vec::new();
+        let mut opts = IterOptions::new();
+        loop {
+            let key_list = db.iter_keys(opts.clone()).unwrap();
+            assert!(key_list.keys.len() <= MAX_ITER_KEYS);
+            seen.extend(key_list.keys);
+            if key_list.list_end {
+                break;
+            }
+            opts.start(seen.last().unwrap());
+        }
+        assert_eq!(seen.len(), n_keys);
+        assert!(seen.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_max_iter_keys() {
+        // forwarded through layers, to their paged listings
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(100)
+            .max_keys(1000)
+            .max_iter_keys(10)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.max_iter_keys(), 10);
+        for i in 0..25 {
+            assert_eq!(db.put(format!("key{i:02}").as_bytes(), b"x"), Ok(true));
+        }
+
+        // pages may run short, where the change log hides its own keys
+        let mut seen: Vec<Vec<u8>> = Vec::new();
+        let mut opts = IterOptions::new();
+        loop {
+            let key_list = db.iter_keys(opts.clone()).unwrap();
+            assert!(key_list.keys.len() <= 10);
+            seen.extend(key_list.keys);
+            if key_list.list_end {
+                break;
+            }
+            opts.start(seen.last().unwrap());
+        }
+        assert_eq!(seen.len(), 25);
+
+        let key_list = db.iter_range(b"key00", b"key20", None).unwrap();
+        assert_eq!(key_list.keys.len(), 10);
+        assert!(!key_list.list_end);
+        let key_list = db.scan_glob(b"key*", None).unwrap();
+        assert_eq!(key_list.keys.len(), 10);
+        assert!(!key_list.list_end);
+
+        // a page must hold at least one key
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_iter_keys(0)
+            .build();
+        assert!(matches!(
+            new_driver().start_db(db_config),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_len() {
+        // via stat(), so the change log's own keys are not counted
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(100)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.len(), Ok(0));
+        assert_eq!(db.is_empty(), Ok(true));
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put(b"b", b"2"), Ok(true));
+        assert_eq!(db.put(b"a", b"3"), Ok(true));
+        assert_eq!(db.len(), Ok(2));
+        assert_eq!(db.del(b"b"), Ok(true));
+        assert_eq!(db.len(), Ok(1));
+        assert_eq!(db.is_empty(), Ok(false));
+    }
+
+    #[test]
+    fn test_iter_keys_prefix() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        for key in [
+            &b"user:1001:email"[..],
+            b"user:1001:name",
+            b"user:1002:name",
+            b"users",
+        ] {
+            assert_eq!(db.put(key, b"x"), Ok(true));
+        }
+
+        // stops at the end of the prefix, resuming after start_key
+        let key_list = db.iter_keys_prefix(b"user:1001:", None).unwrap();
+        assert!(key_list.list_end);
+        assert_eq!(
+            key_list.keys,
+            vec![b"user:1001:email".to_vec(), b"user:1001:name".to_vec()]
+        );
+        let key_list = db
+            .iter_keys_prefix(b"user:1001:", Some(b"user:1001:email"))
+            .unwrap();
+        assert_eq!(key_list.keys, vec![b"user:1001:name".to_vec()]);
+        let key_list = db
+            .iter_keys_prefix(b"user:1001:", Some(b"user:1001:name"))
+            .unwrap();
+        assert!(key_list.list_end);
+        assert!(key_list.keys.is_empty());
+        let key_list = db.iter_keys_prefix(b"user:1002:", Some(b"a")).unwrap();
+        assert_eq!(key_list.keys, vec![b"user:1002:name".to_vec()]);
+        assert!(db
+            .iter_keys_prefix(b"user:9", None)
+            .unwrap()
+            .keys
+            .is_empty());
+
+        // empty prefix:  every key
+        let key_list = db.iter_keys_prefix(b"", None).unwrap();
+        assert!(key_list.list_end);
+        assert_eq!(key_list.keys.len(), 4);
+        let key_list = db.iter_keys_prefix(b"", Some(b"user:1002:name")).unwrap();
+        assert_eq!(key_list.keys, vec![b"users".to_vec()]);
+    }
+
+    #[test]
+    fn test_iter_range() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        for i in 0..MAX_ITER_KEYS + 10 {
+            let key = format!("t:{i:05}");
+            assert_eq!(db.put(key.as_bytes(), b"x"), Ok(true));
+        }
+        assert_eq!(db.put(b"u", b"x"), Ok(true));
+
+        // start is inclusive, end exclusive, and start need not exist
+        let key_list = db.iter_range(b"t:00003", b"t:00006", None).unwrap();
+        assert!(key_list.list_end);
+        let want: Vec<Vec<u8>> = vec![
+            b"t:00003".to_vec(),
+            b"t:00004".to_vec(),
+            b"t:00005".to_vec(),
+        ];
+        assert_eq!(key_list.keys, want);
+        let key_list = db.iter_range(b"t:00002x", b"t:00006", None).unwrap();
+        assert_eq!(key_list.keys, want);
+
+        // the cursor resumes within the range; one before it is ignored
+        let key_list = db
+            .iter_range(b"t:00003", b"t:00006", Some(b"t:00003"))
+            .unwrap();
+        assert_eq!(key_list.keys, &want[1..]);
+        let key_list = db.iter_range(b"t:00003", b"t:00006", Some(b"a")).unwrap();
+        assert_eq!(key_list.keys, want);
+        let key_list = db
+            .iter_range(b"t:00003", b"t:00006", Some(b"t:00005"))
+            .unwrap();
+        assert!(key_list.list_end);
+        assert!(key_list.keys.is_empty());
+
+        // empty or inverted ranges
+        assert!(db
+            .iter_range(b"t:00006", b"t:00003", None)
+            .unwrap()
+            .keys
+            .is_empty());
+        assert!(db
+            .iter_range(b"t:00003", b"t:00003", None)
+            .unwrap()
+            .keys
+            .is_empty());
+        assert!(db
+            .iter_range(b"t:00003x", b"t:00004", None)
+            .unwrap()
+            .keys
+            .is_empty());
+
+        // pages of at most MAX_ITER_KEYS, to the last key if end is empty
+        let key_list = db.iter_range(b"", b"", None).unwrap();
+        assert!(!key_list.list_end);
+        assert_eq!(key_list.keys.len(), MAX_ITER_KEYS);
+        let key_list = db.iter_range(b"t:00000", b"u", None).unwrap();
+        assert!(!key_list.list_end);
+        assert_eq!(key_list.keys.len(), MAX_ITER_KEYS);
+        let last_key = key_list.keys.last().unwrap().clone();
+        let key_list = db.iter_range(b"t:00000", b"u", Some(&last_key)).unwrap();
+        assert!(key_list.list_end);
+        assert_eq!(key_list.keys.len(), 10);
+        let key_list = db.iter_range(b"t:01005", b"", None).unwrap();
+        assert!(key_list.list_end);
+        assert_eq!(key_list.keys.len(), 6);
+    }
+
+    #[test]
+    fn test_get_range() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_iter_keys(4)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        for i in 0..8 {
+            let key = format!("r:{i}");
+            assert_eq!(db.put(key.as_bytes(), format!("v{i}").as_bytes()), Ok(true));
+        }
+        let entry = |i: usize| (format!("r:{i}").into_bytes(), format!("v{i}").into_bytes());
+
+        // half-open range, in key order, with values
+        let entry_list = db.get_range(b"r:2", b"r:5", 100).unwrap();
+        assert_eq!(entry_list.entries, vec![entry(2), entry(3), entry(4)]);
+        assert!(entry_list.list_end);
+
+        // cut short by limit, and by max_iter_keys; limit 0 lists one
+        let entry_list = db.get_range(b"r:2", b"r:5", 2).unwrap();
+        assert_eq!(entry_list.entries, vec![entry(2), entry(3)]);
+        assert!(!entry_list.list_end);
+        let entry_list = db.get_range(b"r:", b"", 100).unwrap();
+        assert_eq!(entry_list.entries.len(), 4);
+        assert!(!entry_list.list_end);
+        let entry_list = db.get_range(b"r:7", b"", 0).unwrap();
+        assert_eq!(entry_list.entries, vec![entry(7)]);
+        assert!(entry_list.list_end);
+
+        // empty and inverted ranges
+        assert!(db.get_range(b"r:5", b"r:5", 10).unwrap().entries.is_empty());
+        assert!(db.get_range(b"r:5", b"r:2", 10).unwrap().list_end);
+    }
+
+    #[test]
+    fn test_has_prefix() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .change_log_len(10)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // change log records are not keys of the db
+        assert_eq!(db.has_prefix(b""), Ok(false));
+        assert_eq!(db.has_prefix(b"\x00"), Ok(false));
+        assert_eq!(db.put(b"user:1", b"a"), Ok(true));
+        assert_eq!(db.has_prefix(b""), Ok(true));
+        assert_eq!(db.has_prefix(b"\x00"), Ok(false));
+
+        // prefixes are folded, as keys are
+        assert_eq!(db.has_prefix(b"USER:"), Ok(true));
+        assert_eq!(db.has_prefix(b"user:2"), Ok(false));
+        assert_eq!(db.del(b"user:1"), Ok(true));
+        assert_eq!(db.has_prefix(b"user:"), Ok(false));
+    }
+    #[test]
+    fn test_put_expire_at() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let now_ms = unix_now_ms();
+
+        // past expiry:  key is absent at once, replacing any value
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put_expire_at(b"A", b"2", now_ms - 1000), Ok(true));
+        assert_eq!(db.get(b"a"), Ok(None));
+        assert_eq!(db.put_expire_at(b"b", b"2", 0), Ok(true));
+        assert_eq!(db.get(b"b"), Ok(None));
+
+        // future expiry:  present until then, then absent to every read
+        assert_eq!(db.put(b"c", b"3"), Ok(true));
+        assert_eq!(db.put_expire_at(b"C", b"4", now_ms + 100), Ok(true));
+        assert_eq!(db.put_ttl(b"d", b"5", 60), Ok(true));
+        assert_eq!(db.put_ttl(b"e", b"6", 60), Ok(true));
+        assert_eq!(db.get(b"c"), Ok(Some(b"4".to_vec())));
+        assert_eq!(db.stat().unwrap().n_records, 3);
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(db.get(b"c"), Ok(None));
+        assert!(db.get_ref(b"c").unwrap().is_none());
+        assert_eq!(db.exists(b"c"), Ok(false));
+        assert_eq!(db.get_multi(&[b"c".to_vec()]), Ok(vec![None]));
+        assert_eq!(
+            db.iter_keys(IterOptions::new()).unwrap().keys,
+            vec![b"d".to_vec(), b"e".to_vec()]
+        );
+        assert_eq!(db.stat().unwrap().n_records, 2);
+        assert_eq!(db.has_prefix(b"c"), Ok(false));
+        assert_eq!(db.del(b"c"), Ok(false));
+
+        // a plain put clears the expiry, as does any other write
+        assert_eq!(db.put(b"d", b"7"), Ok(true));
+        assert_eq!(db.del(b"e"), Ok(true));
+        assert_eq!(db.put(b"e", b"8"), Ok(true));
+        assert_eq!(db.put_expire_at(b"d", b"9", now_ms + 100), Ok(true));
+        let mut batch = Batch::default();
+        batch.insert(b"d", b"10");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(db.get(b"d"), Ok(Some(b"10".to_vec())));
+        assert_eq!(db.get(b"e"), Ok(Some(b"8".to_vec())));
+        assert_eq!(db.stat().unwrap().n_records, 2);
+
+        // ttl:  0 expires at once
+        assert_eq!(db.put_ttl(b"E", b"11", 0), Ok(true));
+        assert_eq!(db.get(b"e"), Ok(None));
+    }
+
+    #[test]
+    fn test_next_expiry() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let now_ms = unix_now_ms();
+        assert_eq!(db.next_expiry(), Ok(None));
+
+        // the soonest expiry, as puts, touches and deletes change it
+        assert_eq!(db.put_expire_at(b"a", b"1", now_ms + 60_000), Ok(true));
+        assert_eq!(db.next_expiry(), Ok(Some(now_ms + 60_000)));
+        assert_eq!(db.put_expire_at(b"b", b"2", now_ms + 30_000), Ok(true));
+        assert_eq!(db.next_expiry(), Ok(Some(now_ms + 30_000)));
+        assert_eq!(db.touch(b"b", 3600), Ok(true));
+        assert_eq!(db.next_expiry(), Ok(Some(now_ms + 60_000)));
+        assert_eq!(db.del(b"a"), Ok(true));
+        let next_ms = db.next_expiry().unwrap().unwrap();
+        assert!(next_ms >= now_ms + 3_600_000);
+        assert_eq!(db.put(b"b", b"3"), Ok(true));
+        assert_eq!(db.next_expiry(), Ok(None));
+    }
+
+    #[test]
+    fn test_default_ttl() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .default_ttl_secs(1)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let in_ttl = |unix_ms: Option<u64>, now_ms: u64| {
+            let unix_ms = unix_ms.unwrap();
+            assert!(unix_ms >= now_ms + 1000 && unix_ms <= unix_now_ms() + 1000);
+        };
+
+        // plain puts and batch inserts take the default; removes clear it
+        let now_ms = unix_now_ms();
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        in_ttl(db.next_expiry().unwrap(), now_ms);
+        let mut batch = Batch::default();
+        batch.insert(b"b", b"2");
+        batch.insert(b"c", b"3");
+        batch.remove(b"c");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.stat().unwrap().n_records, 2);
+
+        // an explicit expiry overrides it, and NO_EXPIRY disables it
+        assert_eq!(db.put_ttl(b"d", b"4", 60), Ok(true));
+        assert_eq!(db.put_expire_at(b"e", b"5", NO_EXPIRY), Ok(true));
+        assert_eq!(db.put_expire_at(b"b", b"6", NO_EXPIRY), Ok(true));
+        thread::sleep(Duration::from_millis(1100));
+        assert_eq!(db.get(b"a"), Ok(None));
+        assert_eq!(db.get(b"b"), Ok(Some(b"6".to_vec())));
+        assert_eq!(db.get(b"d"), Ok(Some(b"4".to_vec())));
+        assert_eq!(db.get(b"e"), Ok(Some(b"5".to_vec())));
+        assert_eq!(db.sweep_expired(SWEEP_BATCH_KEYS), Ok(1));
+        assert!(db.next_expiry().unwrap().unwrap() >= now_ms + 60_000);
+
+        // a rewrite restarts the TTL
+        let now_ms = unix_now_ms();
+        assert_eq!(db.put(b"d", b"7"), Ok(true));
+        in_ttl(db.next_expiry().unwrap(), now_ms);
+    }
+
+    #[test]
+    fn test_touch() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // missing key
+        assert_eq!(db.touch(b"a", 60), Ok(false));
+        assert_eq!(db.touch(b"a", 0), Ok(false));
+
+        // later expiry:  set, or extended, leaving the value
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.touch(b"A", 60), Ok(true));
+        assert_eq!(db.get(b"a"), Ok(Some(b"1".to_vec())));
+        assert_eq!(db.put_expire_at(b"b", b"2", unix_now_ms() + 100), Ok(true));
+        assert_eq!(db.touch(b"b", 60), Ok(true));
+        thread::sleep(Duration::from_millis(150));
+        assert_eq!(db.get(b"b"), Ok(Some(b"2".to_vec())));
+        assert_eq!(db.stat().unwrap().n_records, 2);
+
+        // an expired key is absent
+        assert_eq!(db.put_expire_at(b"c", b"3", unix_now_ms() + 50), Ok(true));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(db.touch(b"c", 60), Ok(false));
+        assert_eq!(db.get(b"c"), Ok(None));
+
+        // zero ttl:  expired at once
+        assert_eq!(db.touch(b"A", 0), Ok(true));
+        assert_eq!(db.get(b"a"), Ok(None));
+    }
+
+    #[test]
+    fn test_snapshot() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .change_log_len(10)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put(b"b", b"2"), Ok(true));
+        assert_eq!(db.put(b"c", b"3"), Ok(true));
+
+        // writes after open are not seen by the snapshot
+        let id = db.snapshot_open().unwrap();
+        assert_eq!(db.put(b"A", b"9"), Ok(true));
+        assert_eq!(db.del(b"b"), Ok(true));
+        assert_eq!(db.put(b"d", b"4"), Ok(true));
+        let mut batch = Batch::default();
+        batch.remove(b"c");
+        batch.insert(b"c", b"8");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        let key_list = db.snapshot_iter_keys(id, None).unwrap();
+        assert_eq!(
+            key_list.keys,
+            vec![b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]
+        );
+        assert!(key_list.list_end);
+        let key_list = db.snapshot_iter_keys(id, Some(b"A")).unwrap();
+        assert_eq!(key_list.keys, vec![b"b".to_vec(), b"c".to_vec()]);
+        assert_eq!(db.snapshot_get(id, b"A"), Ok(Some(b"1".to_vec())));
+        assert_eq!(db.snapshot_get(id, b"b"), Ok(Some(b"2".to_vec())));
+        assert_eq!(db.snapshot_get(id, b"c"), Ok(Some(b"3".to_vec())));
+        assert_eq!(db.snapshot_get(id, b"d"), Ok(None));
+
+        // the db itself sees every write
+        assert_eq!(db.get(b"a"), Ok(Some(b"9".to_vec())));
+        assert_eq!(db.get(b"b"), Ok(None));
+
+        // a later snapshot sees the db as of its own open, through clear
+        let id2 = db.snapshot_open().unwrap();
+        assert_eq!(db.clear(), Ok(true));
+        assert_eq!(db.snapshot_get(id2, b"a"), Ok(Some(b"9".to_vec())));
+        assert_eq!(db.snapshot_get(id2, b"b"), Ok(None));
+        assert_eq!(db.snapshot_iter_keys(id2, None).unwrap().keys.len(), 3);
+        assert_eq!(db.snapshot_get(id, b"a"), Ok(Some(b"1".to_vec())));
+        assert_eq!(db.snapshot_iter_keys(id, None).unwrap().keys.len(), 3);
+
+        // closed snapshots are gone
+        assert!(db.snapshot_close(id));
+        assert!(!db.snapshot_close(id));
+        assert_eq!(db.snapshot_get(id, b"a"), Err(ERR_NO_SNAPSHOT));
+        assert!(db.snapshot_iter_keys(id, None).is_err());
+        assert!(db.snapshot_close(id2));
+    }
+
+    #[test]
+    fn test_snapshot_copy() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .value_metadata(true)
+            .max_iter_keys(2)
+            .build();
+        let mut layered = new_driver().start_db(db_config).unwrap();
+        let mut mem_db = MemDb::new();
+        for db in [&mut layered as &mut dyn Db, &mut mem_db] {
+            for key in ["a", "b1", "b2", "b3", "c"] {
+                assert_eq!(db.put(key.as_bytes(), b"1"), Ok(true));
+            }
+
+            // writes after the snapshot is taken are not seen by it
+            let snap = db.snapshot().unwrap();
+            assert_eq!(db.put(b"a", b"2"), Ok(true));
+            assert_eq!(db.del(b"b2"), Ok(true));
+            assert_eq!(db.put(b"b4", b"2"), Ok(true));
+            assert_eq!(snap.get(b"a"), Ok(Some(b"1".to_vec())));
+            assert_eq!(snap.get(b"b2"), Ok(Some(b"1".to_vec())));
+            assert_eq!(snap.get(b"b4"), Ok(None));
+            assert_eq!(db.get(b"a"), Ok(Some(b"2".to_vec())));
+
+            // paged (by 2, when layered), in either direction, within a
+            // prefix
+            let mut opts = IterOptions::new();
+            opts.prefix(b"b");
+            let mut keys: Vec<Vec<u8>> = Vec::new();
+            let mut page_opts = opts.clone();
+            loop {
+                let key_list = snap.iter_keys(page_opts).unwrap();
+                keys.extend(key_list.keys);
+                if key_list.list_end {
+                    break;
+                }
+                page_opts = opts.continue_after(keys.last().unwrap());
+            }
+            assert_eq!(keys, vec![b"b1".to_vec(), b"b2".to_vec(), b"b3".to_vec()]);
+            let key_list = snap.iter_keys(opts.continue_after(b"b3")).unwrap();
+            assert_eq!(key_list.keys.len(), 0);
+            assert!(key_list.list_end);
+            opts.reverse = true;
+            let key_list = snap.iter_keys(opts.continue_after(b"b3")).unwrap();
+            assert_eq!(key_list.keys, vec![b"b2".to_vec(), b"b1".to_vec()]);
+            let key_list = snap.iter_keys(IterOptions::new()).unwrap();
+            assert_eq!(key_list.keys.len(), db.max_iter_keys().min(5));
+            assert_eq!(key_list.list_end, db.max_iter_keys() >= 5);
+        }
+    }
+
+    #[test]
+    fn test_swap() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // both present
+        assert_eq!(db.put(b"active", b"cfg:1"), Ok(true));
+        assert_eq!(db.put(b"standby", b"cfg:2"), Ok(true));
+        assert_eq!(db.swap(b"active", b"standby"), Ok(()));
+        assert_eq!(db.get(b"active"), Ok(Some(Vec::from("cfg:2"))));
+        assert_eq!(db.get(b"standby"), Ok(Some(Vec::from("cfg:1"))));
+
+        // same key
+        assert_eq!(db.swap(b"active", b"active"), Ok(()));
+        assert_eq!(db.get(b"active"), Ok(Some(Vec::from("cfg:2"))));
+
+        // one absent:  value moves, source is removed; and back again
+        assert_eq!(db.swap(b"active", b"spare"), Ok(()));
+        assert_eq!(db.get(b"active"), Ok(None));
+        assert_eq!(db.get(b"spare"), Ok(Some(Vec::from("cfg:2"))));
+        assert_eq!(db.swap(b"active", b"spare"), Ok(()));
+        assert_eq!(db.get(b"active"), Ok(Some(Vec::from("cfg:2"))));
+        assert_eq!(db.get(b"spare"), Ok(None));
+
+        // both absent
+        assert_eq!(db.swap(b"none:1", b"none:2"), Ok(()));
+        assert_eq!(db.get(b"none:1"), Ok(None));
+        assert_eq!(db.get(b"none:2"), Ok(None));
+        assert_eq!(db.stat().unwrap().n_records, 2);
+    }
+
+    #[test]
+    fn test_del_if_empty() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"empty", b""), Ok(true));
+        assert_eq!(db.put(b"full", b"member:1"), Ok(true));
+
+        assert_eq!(db.del_if_empty(b"empty"), Ok(true));
+        assert_eq!(db.get(b"empty"), Ok(None));
+        assert_eq!(db.del_if_empty(b"full"), Ok(false));
+        assert_eq!(db.get(b"full"), Ok(Some(Vec::from("member:1"))));
+        assert_eq!(db.del_if_empty(b"absent"), Ok(false));
+        assert_eq!(db.stat().unwrap().n_records, 1);
+    }
+
+    #[test]
+    fn test_compare_and_swap() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // absent expected:  create only if absent
+        assert_eq!(db.compare_and_swap(b"Key", None, Some(b"1")), Ok(true));
+        assert_eq!(db.get(b"key"), Ok(Some(Vec::from("1"))));
+        assert_eq!(db.compare_and_swap(b"key", None, Some(b"2")), Ok(false));
+        assert_eq!(db.compare_and_swap(b"key", None, None), Ok(false));
+        assert_eq!(db.get(b"key"), Ok(Some(Vec::from("1"))));
+
+        // value expected:  replace, or remove, only if it matches
+        assert_eq!(
+            db.compare_and_swap(b"key", Some(b"0"), Some(b"2")),
+            Ok(false)
+        );
+        assert_eq!(db.compare_and_swap(b"key", Some(b""), None), Ok(false));
+        assert_eq!(
+            db.compare_and_swap(b"KEY", Some(b"1"), Some(b"2")),
+            Ok(true)
+        );
+        assert_eq!(db.get(b"key"), Ok(Some(Vec::from("2"))));
+        assert_eq!(db.compare_and_swap(b"key", Some(b"1"), None), Ok(false));
+        assert_eq!(db.compare_and_swap(b"key", Some(b"2"), None), Ok(true));
+        assert_eq!(db.get(b"key"), Ok(None));
+
+        // an absent key matches no value; removing it stays absent
+        assert_eq!(
+            db.compare_and_swap(b"key", Some(b"2"), Some(b"3")),
+            Ok(false)
+        );
+        assert_eq!(db.compare_and_swap(b"key", None, None), Ok(true));
+        assert_eq!(db.get(b"key"), Ok(None));
+        assert_eq!(db.stat().unwrap().n_records, 0);
+
+        // with the metadata layer, values compare without their metadata
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .value_metadata(true)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let meta = vec![(String::from("content-type"), String::from("text/plain"))];
+        assert_eq!(db.put_meta(b"doc", b"hello", &meta), Ok(true));
+        assert_eq!(
+            db.compare_and_swap(b"doc", Some(b"bye"), Some(b"x")),
+            Ok(false)
+        );
+        assert_eq!(
+            db.compare_and_swap(b"doc", Some(b"hello"), Some(b"bye")),
+            Ok(true)
+        );
+        let (val, got_meta) = db.get_meta(b"doc").unwrap().unwrap();
+        assert_eq!((&val[..], got_meta), (&b"bye"[..], Vec::new()));
+    }
+
+    #[test]
+    fn test_put_hashed() {
+        // FIPS 180-4 examples, and a length padded to a second block
+        let hex =
+            |digest: [u8; 32]| -> String { digest.iter().map(|b| format!("{b:02x}")).collect() };
+        assert_eq!(
+            hex(sha256(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            hex(sha256(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            hex(sha256(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+        assert_eq!(
+            hex(sha256(&[b'a'; 1000])),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+
+        let mut db = MemDb::new();
+        let key = db.put_hashed(b"abc").unwrap();
+        assert_eq!(
+            key,
+            b"ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_vec()
+        );
+        assert_eq!(db.get(&key), Ok(Some(Vec::from("abc"))));
+
+        // same content, same key, one record
+        assert_eq!(db.put_hashed(b"abc"), Ok(key.clone()));
+        assert_eq!(db.stat().unwrap().n_records, 1);
+        let key2 = db.put_hashed(b"abd").unwrap();
+        assert_ne!(key2, key);
+        assert_eq!(db.stat().unwrap().n_records, 2);
+
+        // a hash key pre-seeded with other content is left as is
+        let key3 = db.put_hashed(b"abe").unwrap();
+        assert_eq!(db.put(&key3, b"not abe"), Ok(true));
+        assert_eq!(db.put_hashed(b"abe"), Err(ERR_HASH_CONFLICT));
+        assert_eq!(db.get(&key3), Ok(Some(Vec::from("not abe"))));
+
+        // through the layers, as a server db
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_value_len(4)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put_hashed(b"abc"), Ok(key.clone()));
+        assert_eq!(db.get(&key), Ok(Some(Vec::from("abc"))));
+        assert_eq!(db.put_hashed(b"abcde"), Err(ERR_TOO_LARGE));
+    }
+
+    #[test]
+    fn test_prefixed_db() {
+        let shared = Arc::new(Mutex::new(MemDb::new()));
+        let mut db1 = PrefixedDb::new(Arc::clone(&shared), "db1").unwrap();
+        let mut db10 = PrefixedDb::new(Arc::clone(&shared), "db10").unwrap();
+        assert_ne!(namespace_prefix("db1"), namespace_prefix("db10"));
+
+        // one key, stored apart in each namespace
+        assert_eq!(db1.put(b"k", b"1"), Ok(true));
+        assert_eq!(db10.put(b"k", b"10"), Ok(true));
+        assert_eq!(db1.get(b"k"), Ok(Some(Vec::from("1"))));
+        assert_eq!(db10.get(b"k"), Ok(Some(Vec::from("10"))));
+        let mut raw_key = namespace_prefix("db1");
+        raw_key.extend_from_slice(b"k");
+        assert_eq!(
+            shared.lock().unwrap().get(&raw_key),
+            Ok(Some(Vec::from("1")))
+        );
+
+        // listings, in either order, hold only the namespace's own keys
+        let mut batch = Batch::default();
+        batch.insert(b"a", b"x");
+        batch.insert(b"z", b"x");
+        assert_eq!(db1.apply_batch(&batch), Ok(true));
+        assert_eq!(db10.put(b"m", b"x"), Ok(true));
+        let keys = db1.iter_keys(IterOptions::new()).unwrap();
+        assert_eq!(keys.keys, vec![b"a".to_vec(), b"k".to_vec(), b"z".to_vec()]);
+        assert!(keys.list_end);
+        let keys = db1.iter_keys(IterOptions::new().reverse().clone()).unwrap();
+        assert_eq!(keys.keys, vec![b"z".to_vec(), b"k".to_vec(), b"a".to_vec()]);
+        let keys = db1
+            .iter_keys(IterOptions::new().start(b"k").clone())
+            .unwrap();
+        assert_eq!(keys.keys, vec![b"z".to_vec()]);
+        let keys = db10
+            .iter_keys(IterOptions::new().prefix(b"k").clone())
+            .unwrap();
+        assert_eq!(keys.keys, vec![b"k".to_vec()]);
+        assert_eq!(db1.stat().unwrap().n_records, 3);
+        assert_eq!(db10.stat().unwrap().n_records, 2);
+
+        // a guarded batch checks the guard key within the namespace
+        assert_eq!(
+            db10.compare_and_swap(b"k", Some(b"1"), Some(b"2")),
+            Ok(false)
+        );
+        assert_eq!(
+            db10.compare_and_swap(b"k", Some(b"10"), Some(b"2")),
+            Ok(true)
+        );
+
+        // clearing one namespace leaves the others
+        assert_eq!(db1.clear(), Ok(true));
+        assert_eq!(db1.stat().unwrap().n_records, 0);
+        assert_eq!(db10.get(b"k"), Ok(Some(Vec::from("2"))));
+        assert_eq!(shared.lock().unwrap().stat().unwrap().n_records, 2);
+
+        // per-db layers wrap a namespace as any backend
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_keys(1)
+            .build();
+        let db2 = PrefixedDb::new(Arc::clone(&shared), "db2").unwrap();
+        let mut db2 = wrap_db(Box::new(db2), &db_config).unwrap();
+        assert_eq!(db2.put(b"a", b"1"), Ok(true));
+        assert_eq!(db2.put(b"b", b"2"), Err(Error::QuotaExceeded));
+        assert_eq!(db2.get(b"a"), Ok(Some(Vec::from("1"))));
+        assert_eq!(db1.get(b"a"), Ok(None));
+    }
+
+    #[test]
+    fn test_put_if_absent() {
+        const N_THREADS: usize = 8;
+
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let layered: Box<dyn Db + Send> = new_driver().start_db(db_config).unwrap();
+        let mem: Box<dyn Db + Send> = Box::new(MemDb::new());
+        for mut db in [layered, mem] {
+            // absent: stored; present: value unchanged
+            assert_eq!(db.put_if_absent(b"lock", b"owner1"), Ok(true));
+            assert_eq!(db.put_if_absent(b"lock", b"owner2"), Ok(false));
+            assert_eq!(db.get(b"lock"), Ok(Some(Vec::from("owner1"))));
+            assert_eq!(db.del(b"lock"), Ok(true));
+            assert_eq!(db.put_if_absent(b"lock", b""), Ok(true));
+            assert_eq!(db.put_if_absent(b"lock", b"owner2"), Ok(false));
+            assert_eq!(db.get(b"lock"), Ok(Some(Vec::new())));
+
+            // of concurrent callers, exactly one stores its value
+            let db = Arc::new(Mutex::new(db));
+            let barrier = Arc::new(Barrier::new(N_THREADS));
+            let mut threads = Vec::new();
+            for n in 0..N_THREADS {
+                let db = Arc::clone(&db);
+                let barrier = Arc::clone(&barrier);
+                threads.push(thread::spawn(move || {
+                    let owner = format!("owner{n}");
+                    barrier.wait();
+                    let created = db.lock().unwrap().put_if_absent(b"race", owner.as_bytes());
+                    (owner, created.unwrap())
+                }));
+            }
+            let winners: Vec<String> = threads
+                .into_iter()
+                .map(|th| th.join().unwrap())
+                .filter(|(_owner, created)| *created)
+                .map(|(owner, _created)| owner)
+                .collect();
+            assert_eq!(winners.len(), 1);
+            let stored = db.lock().unwrap().get(b"race").unwrap();
+            assert_eq!(stored, Some(winners[0].as_bytes().to_vec()));
+        }
+    }
+
+    #[test]
+    fn test_get_or_init() {
+        const N_THREADS: usize = 8;
+
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // absent: init stored; present: stored value kept
+        assert_eq!(db.get_or_init(b"Hits", b"0"), Ok(Vec::from("0")));
+        assert_eq!(db.get(b"hits"), Ok(Some(Vec::from("0"))));
+        assert_eq!(db.put(b"hits", b"7"), Ok(true));
+        assert_eq!(db.get_or_init(b"hits", b"0"), Ok(Vec::from("7")));
+        assert_eq!(db.get_or_init(b"empty", b""), Ok(Vec::new()));
+        assert_eq!(db.get_or_init(b"empty", b"x"), Ok(Vec::new()));
+
+        // concurrent initializers, each with its own init, all observe
+        // the one value stored
+        let db = Arc::new(Mutex::new(db));
+        let barrier = Arc::new(Barrier::new(N_THREADS));
+        let mut threads = Vec::new();
+        for n in 0..N_THREADS {
+            let db = Arc::clone(&db);
+            let barrier = Arc::clone(&barrier);
+            threads.push(thread::spawn(move || {
+                barrier.wait();
+                let init = format!("init{n}");
+                db.lock().unwrap().get_or_init(b"counter", init.as_bytes())
+            }));
+        }
+        let vals: Vec<Vec<u8>> = threads
+            .into_iter()
+            .map(|th| th.join().unwrap().unwrap())
+            .collect();
+        let stored = db.lock().unwrap().get(b"counter").unwrap().unwrap();
+        assert!(stored.starts_with(b"init"));
+        assert!(vals.iter().all(|val| *val == stored));
+    }
+
+    #[test]
+    fn test_merge() {
+        // unconfigured:  last write wins
+        let mut db = MemDb::new();
+        assert_eq!(db.merge(b"k", b"1"), Ok(()));
+        assert_eq!(db.merge(b"k", b"2"), Ok(()));
+        assert_eq!(db.get(b"k"), Ok(Some(b"2".to_vec())));
+
+        // JSON shallow merge:  fields replaced, added, or removed by null
+        assert_eq!(
+            merge_json_shallow(None, br#"{"a":1}"#),
+            br#"{"a":1}"#.to_vec()
+        );
+        assert_eq!(
+            merge_json_shallow(Some(br#"{"a":1,"b":2}"#), br#"{"b":{"c":3},"d":4}"#),
+            br#"{"a":1,"b":{"c":3},"d":4}"#.to_vec()
+        );
+        assert_eq!(
+            merge_json_shallow(Some(br#"{"a":1,"b":2}"#), br#"{"a":null}"#),
+            br#"{"b":2}"#.to_vec()
+        );
+        assert_eq!(
+            merge_json_shallow(Some(b"[1]"), br#"{"a":1}"#),
+            br#"{"a":1}"#.to_vec()
+        );
+        assert_eq!(
+            merge_json_shallow(Some(br#"{"a":1}"#), b"[2]"),
+            b"[2]".to_vec()
+        );
+
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .value_metadata(true)
+            .max_value_len(24)
+            .merge_fn(merge_json_shallow)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.merge(b"doc", br#"{"a":1}"#), Ok(()));
+        assert_eq!(db.merge(b"doc", br#"{"b":2}"#), Ok(()));
+        assert_eq!(db.get(b"doc"), Ok(Some(br#"{"a":1,"b":2}"#.to_vec())));
+
+        // metadata is kept
+        let meta = vec![(
+            String::from("content-type"),
+            String::from("application/json"),
+        )];
+        assert_eq!(db.put_meta(b"m", b"{}", &meta), Ok(true));
+        assert_eq!(db.merge(b"m", br#"{"a":1}"#), Ok(()));
+        let (val, got_meta) = db.get_meta(b"m").unwrap().unwrap();
+        assert_eq!(val.to_vec(), br#"{"a":1}"#.to_vec());
+        assert_eq!(got_meta, meta);
+
+        // the value limit applies to the merged value
+        assert_eq!(
+            db.merge(b"doc", br#"{"c":"0123456789"}"#),
+            Err(ERR_TOO_LARGE)
+        );
+        assert_eq!(db.get(b"doc"), Ok(Some(br#"{"a":1,"b":2}"#.to_vec())));
+    }
+
+    #[test]
+    fn test_put_stream() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .track_versions(true)
+            .max_value_len(8)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // read to its end, and put through every layer, as put()
+        let mut reader: &[u8] = b"abcdefgh";
+        assert_eq!(db.put_stream(b"k", &mut reader), Ok(true));
+        assert_eq!(db.get_versioned(b"k"), Ok(Some((b"abcdefgh".to_vec(), 1))));
+
+        // the value limit applies, storing nothing
+        let mut reader: &[u8] = b"abcdefghi";
+        assert_eq!(db.put_stream(b"k", &mut reader), Err(Error::TooLarge));
+        assert_eq!(db.get(b"k"), Ok(Some(b"abcdefgh".to_vec())));
+
+        // a read error fails the put, storing nothing
+        struct FailingReader;
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::other("connection reset"))
+            }
+        }
+        assert!(matches!(
+            db.put_stream(b"k2", &mut FailingReader),
+            Err(Error::Backend(_))
+        ));
+        assert_eq!(db.get(b"k2"), Ok(None));
+    }
+
+    #[test]
+    fn test_append() {
+        const N_THREADS: usize = 8;
+
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .value_metadata(true)
+            .max_value_len(8)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // absent: suffix stored; present: suffix appended
+        assert_eq!(db.append(b"log", b"ab"), Ok(2));
+        assert_eq!(db.append(b"log", b"cd"), Ok(4));
+        assert_eq!(db.append(b"log", b""), Ok(4));
+        assert_eq!(db.get(b"log"), Ok(Some(b"abcd".to_vec())));
+
+        // metadata is kept
+        let meta = vec![(String::from("content-type"), String::from("text/plain"))];
+        assert_eq!(db.put_meta(b"m", b"1", &meta), Ok(true));
+        assert_eq!(db.append(b"m", b"2"), Ok(2));
+        let (val, got_meta) = db.get_meta(b"m").unwrap().unwrap();
+        assert_eq!(val.to_vec(), b"12".to_vec());
+        assert_eq!(got_meta, meta);
+
+        // the value limit applies to the whole value
+        assert_eq!(db.append(b"log", b"efgh"), Ok(8));
+        assert_eq!(db.append(b"log", b"i"), Err(ERR_TOO_LARGE));
+        assert_eq!(db.get(b"log"), Ok(Some(b"abcdefgh".to_vec())));
+
+        // concurrent appends are each applied whole
+        let db = Arc::new(Mutex::new(MemDb::new()));
+        let mut threads = Vec::new();
+        for n in 0..N_THREADS {
+            let db = Arc::clone(&db);
+            threads.push(thread::spawn(move || {
+                let rec = format!("{n}.");
+                db.lock().unwrap().append(b"log", rec.as_bytes())
+            }));
+        }
+        for th in threads {
+            assert!(th.join().unwrap().is_ok());
+        }
+        let stored = db.lock().unwrap().get(b"log").unwrap().unwrap();
+        let mut recs: Vec<&[u8]> = stored.split(|b| *b == b'.').collect();
+        assert_eq!(recs.pop(), Some(&b""[..]));
+        recs.sort();
+        let want: Vec<String> = (0..N_THREADS).map(|n| n.to_string()).collect();
+        assert_eq!(recs, want.iter().map(|n| n.as_bytes()).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_sweep_expired() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // records without expiry are never swept
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.sweep_expired(SWEEP_BATCH_KEYS), Ok(0));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
+        assert_eq!(db.stat().unwrap().n_records, 1);
+        assert_eq!(db.next_expiry(), Ok(None));
+
+        // expired records are removed, soonest first, up to max_keys
+        let now_ms = unix_now_ms();
+        for (n, key) in [&b"a"[..], b"b", b"c"].iter().enumerate() {
+            assert_eq!(
+                db.put_expire_at(key, b"x", now_ms + 50 + n as u64),
+                Ok(true)
+            );
+        }
+        assert_eq!(db.put_ttl(b"later", b"y", 60), Ok(true));
+        assert_eq!(db.sweep_expired(SWEEP_BATCH_KEYS), Ok(0));
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(db.stat().unwrap().n_records, 2);
+        assert_eq!(db.sweep_expired(2), Ok(2));
+        assert_eq!(db.next_expiry(), Ok(Some(now_ms + 52)));
+        assert_eq!(db.sweep_expired(SWEEP_BATCH_KEYS), Ok(1));
+        assert_eq!(db.sweep_expired(SWEEP_BATCH_KEYS), Ok(0));
+        assert_eq!(db.stat().unwrap().n_records, 2);
+        assert_eq!(db.get(b"later"), Ok(Some(b"y".to_vec())));
+    }
+
+    #[test]
+    fn test_sweep_expired_quota() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_keys(10)
+            .change_log_len(100)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // a swept record, and its index key, leave the quota's count, and
+        // its removal is logged
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put_expire_at(b"b", b"2", unix_now_ms() + 50), Ok(true));
+        assert_eq!(db.quota_usage().unwrap().n_keys, 3);
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(db.sweep_expired(SWEEP_BATCH_KEYS), Ok(1));
+        assert_eq!(db.quota_usage().unwrap().n_keys, 1);
+        let changes = db.changes_since(0).unwrap().changes;
+        let last = changes.last().unwrap();
+        assert!(matches!(last.op, MutationOp::Remove));
+        assert_eq!(last.key, b"b".to_vec());
+    }
+
+    #[test]
+    fn test_key_case_fold() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // writes and reads are folded; mixed-case keys do not diverge
+        assert_eq!(db.put(b"User:1", b"alan"), Ok(true));
+        assert_eq!(db.get(b"user:1"), Ok(Some(Vec::from("alan"))));
+        assert_eq!(db.get(b"USER:1"), Ok(Some(Vec::from("alan"))));
+        assert_eq!(db.put(b"user:1", b"bob"), Ok(true));
+        assert_eq!(db.get(b"User:1"), Ok(Some(Vec::from("bob"))));
+        assert_eq!(db.stat().unwrap().n_records, 1);
+
+        let mut batch = Batch::default();
+        batch.insert(b"User:2", b"carol");
+        batch.remove(b"USER:1");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"user:1"), Ok(None));
+        assert_eq!(db.get(b"user:2"), Ok(Some(Vec::from("carol"))));
+
+        // iteration bounds are folded too
+        let mut opts = IterOptions::new();
+        opts.prefix(b"USER:");
+        let key_list = db.iter_keys(opts).unwrap();
+        assert_eq!(key_list.keys, vec![b"user:2".to_vec()]);
+        let key_list = db.scan_glob(b"USER:?", None).unwrap();
+        assert_eq!(key_list.keys, vec![b"user:2".to_vec()]);
+
+        // only ASCII is folded
+        assert_eq!(db.put("\u{c9}T\u{c9}".as_bytes(), b"summer"), Ok(true));
+        assert_eq!(
+            db.get("\u{c9}t\u{c9}".as_bytes()),
+            Ok(Some(Vec::from("summer")))
+        );
+        assert_eq!(db.get("\u{e9}t\u{e9}".as_bytes()), Ok(None));
+
+        assert_eq!(db.del(b"USER:2"), Ok(true));
+    }
+
+    #[test]
+    fn test_key_case_sensitive() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"User:1", b"alan"), Ok(true));
+        assert_eq!(db.get(b"user:1"), Ok(None));
+        assert_eq!(db.get(b"User:1"), Ok(Some(Vec::from("alan"))));
+    }
+
+    #[test]
+    fn test_del() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.del(b"name"), Ok(true));
+        assert_eq!(db.del(b"name"), Ok(false));
+    }
+
+    #[test]
+    fn test_batch() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+
+        let mut batch = Batch::default();
+        batch.insert(b"age", b"25");
+        batch.insert(b"city", b"anytown");
+        batch.remove(b"name");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        assert_eq!(db.get(b"name"), Ok(None));
+        assert_eq!(db.get(b"age"), Ok(Some(Vec::from("25"))));
+        assert_eq!(db.get(b"city"), Ok(Some(Vec::from("anytown"))));
+    }
+
+    #[test]
+    fn test_mutation_serde() {
+        let mut batch = Batch::default();
+        batch.insert(b"k\xff", b"\x00v");
+        batch.remove(b"gone");
+
+        // JSON:  base64 keys and values, no value for a remove
+        let jv = serde_json::to_value(&batch.ops).unwrap();
+        assert_eq!(
+            jv,
+            serde_json::json!([{"key": "a/8=", "value": "AHY="}, {"key": "Z29uZQ=="}])
+        );
+        let ops: Vec<Mutation> = serde_json::from_value(jv).unwrap();
+        assert!(matches!(ops[0].op, MutationOp::Insert));
+        assert_eq!(ops[0].key, b"k\xff");
+        assert_eq!(ops[0].value.as_deref(), Some(&b"\x00v"[..]));
+        assert!(matches!(ops[1].op, MutationOp::Remove));
+        assert_eq!(ops[1].value, None);
+        let bad = serde_json::json!([{"key": "not base64!"}]);
+        assert!(serde_json::from_value::<Vec<Mutation>>(bad).is_err());
+
+        // MessagePack:  native byte strings, fields by name or position
+        for body in &[
+            rmp_serde::to_vec_named(&batch.ops).unwrap(),
+            rmp_serde::to_vec(&batch.ops).unwrap(),
+        ] {
+            let ops: Vec<Mutation> = rmp_serde::from_slice(body).unwrap();
+            assert_eq!(ops.len(), 2);
+            assert_eq!(ops[0].key, b"k\xff");
+            assert_eq!(ops[0].value.as_deref(), Some(&b"\x00v"[..]));
+            assert!(matches!(ops[1].op, MutationOp::Remove));
+        }
+        let body = rmp_serde::to_vec_named(&batch.ops[0]).unwrap();
+        assert!(body.windows(4).any(|w| w == b"\xc4\x02k\xff")); // bin 8
+
+        let key_list = KeyList {
+            keys: vec![b"a".to_vec(), b"\xff".to_vec()],
+            list_end: true,
+        };
+        let body = rmp_serde::to_vec_named(&key_list).unwrap();
+        let decoded: KeyList = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!((decoded.keys, decoded.list_end), (key_list.keys, true));
+        let entry_list = EntryList {
+            entries: vec![(b"a".to_vec(), b"1".to_vec())],
+            list_end: false,
+        };
+        let jv = serde_json::to_value(&entry_list).unwrap();
+        assert_eq!(jv["entries"], serde_json::json!([["YQ==", "MQ=="]]));
+        let body = rmp_serde::to_vec_named(&entry_list).unwrap();
+        let decoded: EntryList = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!(decoded.entries, entry_list.entries);
+    }
+
+    #[test]
+    fn test_mutation_list_errors() {
+        // an explicit op, checked against the value
+        let jv = serde_json::json!([
+            {"key": "YQ==", "value": "MQ==", "op": "insert"},
+            {"key": "Yg==", "op": "remove"},
+        ]);
+        let ops = serde_json::from_value::<MutationList>(jv).unwrap().0;
+        assert!(matches!(ops[0].op, MutationOp::Insert));
+        assert!(matches!(ops[1].op, MutationOp::Remove));
+
+        // the first malformed mutation is named by its index
+        let decode_err = |jv: serde_json::Value| match serde_json::from_value::<MutationList>(jv) {
+            Ok(_ops) => String::from("decoded"),
+            Err(e) => e.to_string(),
+        };
+        let ok = serde_json::json!({"key": "YQ=="});
+        let cases = [
+            (
+                serde_json::json!({"key": "Yg==", "op": "insert"}),
+                "insert missing value",
+            ),
+            (
+                serde_json::json!({"key": "Yg==", "value": "", "op": "remove"}),
+                "remove with value",
+            ),
+            (
+                serde_json::json!({"key": "Yg==", "op": "upsert"}),
+                "unknown variant",
+            ),
+            (
+                serde_json::json!({"key": "Yg==", "vaule": "MQ=="}),
+                "unknown field",
+            ),
+            (serde_json::json!({"key": "Yg==", "value": "%%"}), "base64"),
+            (serde_json::json!({"value": "MQ=="}), "missing field"),
+            (serde_json::json!("Yg=="), "invalid type"),
+        ];
+        for (bad, reason) in cases.iter() {
+            let msg = decode_err(serde_json::json!([ok, ok, ok, bad, bad]));
+            assert!(msg.starts_with("mutation 3: "), "{}", msg);
+            assert!(msg.contains(reason), "{}", msg);
+        }
+        assert!(decode_err(serde_json::json!(ok)).contains("a list of mutations"));
+    }
+
+    #[test]
+    fn test_consistency_parse() {
+        assert_eq!(Consistency::parse("strong"), Some(Consistency::Strong));
+        assert_eq!(Consistency::parse("Eventual"), Some(Consistency::Eventual));
+        assert_eq!(Consistency::parse("weak"), None);
+        assert_eq!(Consistency::parse(""), None);
+        assert_eq!(Consistency::default(), Consistency::Strong);
+    }
+
+    #[test]
+    fn test_batch_order() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(16)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"a", b"old"), Ok(true));
+
+        // each mutation sees the ones before it:  the remove of b removes
+        // what the batch inserted, and the insert of a follows its remove
+        let mut batch = Batch::default();
+        batch.insert(b"b", b"1");
+        batch.remove(b"b");
+        batch.remove(b"a");
+        batch.insert(b"c", b"1");
+        batch.insert(b"a", b"new");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        assert_eq!(db.get(b"a"), Ok(Some(Vec::from("new"))));
+        assert_eq!(db.get(b"b"), Ok(None));
+        assert_eq!(db.get(b"c"), Ok(Some(Vec::from("1"))));
+
+        // per-mutation records are in op order
+        let change_list = db.changes_since(1).unwrap();
+        assert_eq!(
+            change_summary(&change_list),
+            vec![
+                (2, true, b"b".to_vec()),
+                (3, false, b"b".to_vec()),
+                (4, false, b"a".to_vec()),
+                (5, true, b"c".to_vec()),
+                (6, true, b"a".to_vec())
+            ]
+        );
+
+        // the same mutations, reordered, leave a different result
+        let mut batch = Batch::default();
+        batch.insert(b"c", b"2");
+        batch.remove(b"c");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"c"), Ok(None));
+        let mut batch = Batch::default();
+        batch.remove(b"c");
+        batch.insert(b"c", b"2");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"c"), Ok(Some(Vec::from("2"))));
+    }
+
+    #[test]
+    fn test_batch_duplicates() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .change_log_len(4)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"gone", b"x"), Ok(true));
+        let dup_batch = |policy| {
+            let mut batch = Batch::default();
+            batch.insert(b"name", b"alan");
+            batch.insert(b"Name", b"bob"); // the same key, once folded
+            batch.insert(b"gone", b"y");
+            batch.remove(b"gone");
+            batch.duplicate_policy = policy;
+            batch
+        };
+
+        // reject: nothing applied, even by apply_batch_until()
+        let batch = dup_batch(DuplicatePolicy::Reject);
+        assert_eq!(db.apply_batch(&batch), Err(ERR_DUPLICATE_KEY));
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        assert_eq!(
+            db.apply_batch_until(&batch, deadline),
+            Err(ERR_DUPLICATE_KEY)
+        );
+        assert_eq!(db.get(b"name"), Ok(None));
+        assert_eq!(db.get(b"gone"), Ok(Some(Vec::from("x"))));
+        assert_eq!(db.changes_since(0).unwrap().changes.len(), 1);
+
+        // distinct keys are accepted under reject
+        let mut batch = Batch {
+            duplicate_policy: DuplicatePolicy::Reject,
+            ..Batch::default()
+        };
+        batch.insert(b"a", b"1");
+        batch.remove(b"b");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        // last wins (the default):  the later insert, and the remove
+        let batch = dup_batch(DuplicatePolicy::default());
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("bob"))));
+        assert_eq!(db.get(b"gone"), Ok(None));
+
+        // a remove then an insert stores the insert, as applied in order
+        let mut batch = Batch::default();
+        batch.remove(b"name");
+        batch.insert(b"name", b"carol");
+        batch.insert(b"gone", b"z");
+        batch.remove(b"gone");
+        batch.insert(b"gone", b"back");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("carol"))));
+        assert_eq!(db.get(b"gone"), Ok(Some(Vec::from("back"))));
+    }
+
+    #[test]
+    fn test_batch_size() {
+        let mut batch = Batch::with_capacity(4);
+        assert!(batch.ops.capacity() >= 4);
+        assert!(batch.is_empty());
+        assert_eq!((batch.len(), batch.byte_size()), (0, 0));
+
+        // inserts count key and value, removes the key alone
+        batch.insert(b"name", b"alan");
+        batch.remove(b"age");
+        batch.insert(b"empty", b"");
+        batch.remove(b"name");
+        assert!(!batch.is_empty());
+        assert_eq!(batch.len(), 4);
+        assert_eq!(batch.byte_size(), 8 + 3 + 5 + 4);
+
+        // a guard is not counted
+        batch.guard = Some((b"lock".to_vec(), Some(b"owner1".to_vec())));
+        assert_eq!(batch.byte_size(), 20);
+    }
+
+    #[test]
+    fn test_batch_get_pending() {
+        let mut batch = Batch::default();
+        assert_eq!(batch.get_pending(b"a"), None);
+
+        // the value the key's last mutation leaves, as built
+        batch.insert(b"a", b"1");
+        assert_eq!(batch.get_pending(b"a"), Some(Some(&b"1"[..])));
+        batch.insert(b"a", b"2");
+        assert_eq!(batch.get_pending(b"a"), Some(Some(&b"2"[..])));
+        batch.remove(b"a");
+        assert_eq!(batch.get_pending(b"a"), Some(None));
+        batch.remove(b"b");
+        batch.insert(b"b", b"3");
+        assert_eq!(batch.get_pending(b"a"), Some(None));
+        assert_eq!(batch.get_pending(b"b"), Some(Some(&b"3"[..])));
+        assert_eq!(batch.get_pending(b"c"), None);
+
+        // and the value apply_batch() leaves
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"a", b"0"), Ok(true));
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        for key in [&b"a"[..], b"b"] {
+            assert_eq!(
+                batch.get_pending(key).unwrap(),
+                db.get(key).unwrap().as_deref()
+            );
+        }
+    }
+
+    #[test]
+    fn test_length_limits() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .value_metadata(true)
+            .max_key_len(4)
+            .max_value_len(8)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // just under, at, and just over each limit
+        assert_eq!(db.put(b"abc", b"v"), Ok(true));
+        assert_eq!(db.put(b"abcd", b"v"), Ok(true));
+        assert_eq!(db.put(b"abcde", b"v"), Err(ERR_TOO_LARGE));
+        assert_eq!(db.put(b"k", b"1234567"), Ok(true));
+        assert_eq!(db.put(b"k", b"12345678"), Ok(true));
+        assert_eq!(db.put(b"k", b"123456789"), Err(ERR_TOO_LARGE));
+        assert_eq!(db.get(b"k"), Ok(Some(b"12345678".to_vec())));
+        assert_eq!(db.get(b"abcde"), Ok(None));
+
+        // values are measured without their metadata header
+        let meta = vec![(String::from("content-type"), String::from("text/plain"))];
+        assert_eq!(db.put_meta(b"m", b"12345678", &meta), Ok(true));
+        assert_eq!(db.put_meta(b"m", b"123456789", &meta), Err(ERR_TOO_LARGE));
+
+        // one oversized mutation refuses the whole batch
+        for (key, val) in [(&b"abcde"[..], &b"v"[..]), (b"b", b"123456789")] {
+            let mut batch = Batch::default();
+            batch.insert(b"a", b"1");
+            batch.insert(key, val);
+            assert_eq!(db.apply_batch(&batch), Err(ERR_TOO_LARGE));
+            assert_eq!(db.get(b"a"), Ok(None));
+        }
+        let mut batch = Batch::default();
+        batch.insert(b"abcd", b"12345678");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        // other writes of keys and values are limited alike
+        assert_eq!(db.put_ttl(b"abcde", b"v", 60), Err(ERR_TOO_LARGE));
+        assert_eq!(db.get_or_init(b"i", b"123456789"), Err(ERR_TOO_LARGE));
+        assert_eq!(db.rename(b"k", b"abcde", false), Err(ERR_TOO_LARGE));
+        assert_eq!(db.get(b"k"), Ok(Some(b"12345678".to_vec())));
+
+        // 0 is no limit; by default, limits are generous
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_key_len(0)
+            .max_value_len(0)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let long = vec![b'x'; DEF_MAX_KEY_LEN + 1];
+        assert_eq!(db.put(&long, &long), Ok(true));
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        assert_eq!(db_config.max_key_len, DEF_MAX_KEY_LEN);
+        assert_eq!(db_config.max_value_len, DEF_MAX_VALUE_LEN);
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(&long, b"v"), Err(ERR_TOO_LARGE));
+    }
+
+    #[test]
+    fn test_batch_invalid() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(4)
+            .max_keys(10)
+            .eviction(Eviction::Lru)
+            .build();
+        let mut layered = new_driver().start_db(db_config).unwrap();
+        let mut mem_db = MemDb::new();
+
+        // an insert without a value, after valid mutations:  refused,
+        // changing nothing, through every layer or none
+        let mut batch = Batch::default();
+        batch.insert(b"b", b"2");
+        batch.remove(b"a");
+        batch.ops.push(Mutation {
+            op: MutationOp::Insert,
+            key: b"c".to_vec(),
+            value: None,
+        });
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        for db in [&mut layered as &mut dyn Db, &mut mem_db] {
+            assert_eq!(db.put(b"a", b"1"), Ok(true));
+            let n_changes = db.changes_since(0).map(|list| list.changes.len());
+            assert!(matches!(
+                db.apply_batch(&batch),
+                Err(Error::InvalidInput(_))
+            ));
+            assert!(matches!(
+                db.apply_batch_until(&batch, deadline),
+                Err(Error::InvalidInput(_))
+            ));
+            assert_eq!(db.get(b"a"), Ok(Some(Vec::from("1"))));
+            assert_eq!(db.get(b"b"), Ok(None));
+            assert_eq!(db.get(b"c"), Ok(None));
+            assert_eq!(db.len(), Ok(1));
+            assert_eq!(
+                db.changes_since(0).map(|list| list.changes.len()),
+                n_changes
+            );
+        }
+
+        // a remove with a value is as malformed, wherever it falls
+        let mut batch = Batch::default();
+        batch.ops.push(Mutation {
+            op: MutationOp::Remove,
+            key: b"a".to_vec(),
+            value: Some(b"1".to_vec()),
+        });
+        batch.insert(b"b", b"2");
+        for db in [&mut layered as &mut dyn Db, &mut mem_db] {
+            assert!(matches!(
+                db.apply_batch(&batch),
+                Err(Error::InvalidInput(_))
+            ));
+            assert_eq!(db.get(b"a"), Ok(Some(Vec::from("1"))));
+            assert_eq!(db.get(b"b"), Ok(None));
+        }
+
+        // a remove needs no value
+        let mut batch = Batch::default();
+        batch.ops.push(Mutation {
+            op: MutationOp::Remove,
+            key: b"a".to_vec(),
+            value: None,
+        });
+        assert_eq!(mem_db.apply_batch(&batch), Ok(true));
+        assert_eq!(mem_db.len(), Ok(0));
+    }
+
+    #[test]
+    fn test_batch_guard() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .change_log_len(4)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"version", b"1"), Ok(true));
+        let guarded_batch = |expected: Option<&[u8]>| {
+            let mut batch = Batch {
+                guard: Some((b"Version".to_vec(), expected.map(Vec::from))),
+                ..Batch::default()
+            };
+            batch.insert(b"version", b"2");
+            batch.insert(b"name", b"alan");
+            batch
+        };
+
+        // stale guard:  nothing applied, even by apply_batch_until()
+        let batch = guarded_batch(Some(b"0"));
+        assert_eq!(db.apply_batch(&batch), Err(ERR_GUARD_MISMATCH));
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        assert_eq!(
+            db.apply_batch_until(&batch, deadline),
+            Err(ERR_GUARD_MISMATCH)
+        );
+        assert_eq!(
+            db.apply_batch(&guarded_batch(None)),
+            Err(ERR_GUARD_MISMATCH)
+        );
+        assert_eq!(db.get(b"version"), Ok(Some(Vec::from("1"))));
+        assert_eq!(db.get(b"name"), Ok(None));
+        assert_eq!(db.changes_since(0).unwrap().changes.len(), 1);
+
+        // matching guard, on the folded key:  applied, once
+        let batch = guarded_batch(Some(b"1"));
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"version"), Ok(Some(Vec::from("2"))));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
+        assert_eq!(db.apply_batch(&batch), Err(ERR_GUARD_MISMATCH));
+
+        // guard expecting the key absent
+        let mut batch = Batch {
+            guard: Some((b"lock".to_vec(), None)),
+            ..Batch::default()
+        };
+        batch.insert(b"lock", b"held");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.apply_batch(&batch), Err(ERR_GUARD_MISMATCH));
+    }
+
+    #[test]
+    fn test_batch_until() {
+        use std::time::Duration;
+
+        const N_OPS: usize = 200000;
+
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        let mut batch = Batch::default();
+        for i in 0..N_OPS {
+            let key = format!("key {i}");
+            batch.insert(key.as_bytes(), b"value");
+        }
+
+        // deadline already passed: nothing applied
+        assert_eq!(db.apply_batch_until(&batch, Instant::now()), Ok(0));
+        assert_eq!(db.stat().unwrap().n_records, 0);
+
+        // short deadline: partially applied, at a chunk boundary
+        let deadline = Instant::now() + Duration::from_millis(20);
+        let n_applied = db.apply_batch_until(&batch, deadline).unwrap();
+        assert!(n_applied > 0);
+        assert!(n_applied < N_OPS);
+        assert_eq!(n_applied % BATCH_CHUNK_OPS, 0);
+        assert_eq!(db.stat().unwrap().n_records, n_applied as u64);
+
+        // resume from returned index, to complete the rest
+        let rest = Batch {
+            ops: batch.ops[n_applied..].to_vec(),
+            ..Batch::default()
+        };
+        let deadline = Instant::now() + Duration::from_secs(3600);
+        assert_eq!(db.apply_batch_until(&rest, deadline), Ok(N_OPS - n_applied));
+        assert_eq!(db.stat().unwrap().n_records, N_OPS as u64);
+    }
+
+    #[test]
+    fn test_clear() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"age", b"25"), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
+        assert_eq!(db.clear(), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(None));
+        assert_eq!(db.get(b"age"), Ok(None));
+    }
+
+    #[test]
+    fn test_put_next() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        let key1 = db.put_next(b"first").unwrap();
+        let key2 = db.put_next(b"second").unwrap();
+        assert_eq!(key1, b"00000000000000000001");
+        assert_eq!(key2, b"00000000000000000002");
+        assert_eq!(db.get(&key1), Ok(Some(Vec::from("first"))));
+        assert_eq!(db.get(&key2), Ok(Some(Vec::from("second"))));
+
+        // deleted keys are not re-used
+        assert_eq!(db.del(&key2), Ok(true));
+        let key3 = db.put_next(b"third").unwrap();
+        assert_eq!(key3, b"00000000000000000003");
+    }
+
+    #[test]
+    fn test_stat() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name1", b"alan"), Ok(true));
+        assert_eq!(db.put(b"age1", b"25"), Ok(true));
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.del(b"name"), Ok(true));
+        assert_eq!(db.del(b"name"), Ok(false));
+
+        let st = db.stat().unwrap();
+        assert_eq!(st.n_records, 2);
+    }
+
+    #[test]
+    fn test_iter() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // iterate empty list
+        let key_list_res = db.iter_keys(IterOptions::new());
+        assert_eq!(key_list_res.is_err(), false);
+
+        let mut key_list = key_list_res.unwrap();
+        assert_eq!(key_list.list_end, true);
+
+        key_list.keys.sort();
+        assert_eq!(key_list.keys.len(), 0);
+
+        // iterate small list
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"age", b"25"), Ok(true));
+
+        let key_list_res = db.iter_keys(IterOptions::new());
+        assert_eq!(key_list_res.is_err(), false);
+
+        let mut key_list = key_list_res.unwrap();
+        assert_eq!(key_list.list_end, true);
+
+        key_list.keys.sort();
+        assert_eq!(key_list.keys.len(), 2);
+        assert_eq!(key_list.keys[0], b"age");
+        assert_eq!(key_list.keys[1], b"name");
+    }
+
+    #[test]
+    fn test_iter_prefix() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .read_only(false)
+            .build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // iterate small list
+        assert_eq!(db.put(b"2018/name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2018/bame", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2019/fame", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2019/lame", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2019/game", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2020/tame", b"alan"), Ok(true));
+        assert_eq!(db.put(b"age", b"25"), Ok(true));
+
+        let key_list_res = db.iter_keys(IterOptions::new());
+        assert_eq!(key_list_res.is_err(), false);
+
+        let key_list = key_list_res.unwrap();
+        assert_eq!(key_list.list_end, true);
+        assert_eq!(key_list.keys.len(), 7);
+
+        // iterate with prefix matching
+        let mut opts = IterOptions::new();
+        opts.prefix(b"2019/");
+
+        let key_list_res = db.iter_keys(opts);
+        assert_eq!(key_list_res.is_err(), false);
+
+        let mut key_list = key_list_res.unwrap();
+        assert_eq!(key_list.list_end, true);
+        assert_eq!(key_list.keys.len(), 3);
+
+        key_list.keys.sort();
+        assert_eq!(
+            String::from_utf8_lossy(&key_list.keys[0]),
+            String::from("2019/fame")
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&key_list.keys[1]),
+            String::from("2019/game")
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&key_list.keys[2]),
+            String::from("2019/lame")
+        );
+    }
+
+    // backend wrapper whose get() of key "slow" takes SLOW_GET_MS
+    const SLOW_GET_MS: u64 = 50;
+
+    struct SlowDb {
+        inner: Box<dyn Db + Send>,
+    }
+
+    impl Db for SlowDb {
+        fn apply_batch(&mut self, batch: &Batch) -> Result<bool, Error> {
+            self.inner.apply_batch(batch)
+        }
+
+        fn clear(&mut self) -> Result<bool, Error> {
+            self.inner.clear()
+        }
+
+        fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+            self.inner.del(key)
+        }
+
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+            if key == b"slow" {
+                thread::sleep(Duration::from_millis(SLOW_GET_MS));
+            }
+            self.inner.get(key)
+        }
+
+        fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+            self.inner.put(key, val)
+        }
+
+        fn iter_keys(&self, opts: IterOptions) -> Result<KeyList, Error> {
+            self.inner.iter_keys(opts)
+        }
+
+        fn stat(&self) -> Result<DbStat, Error> {
+            self.inner.stat()
+        }
+    }
+
+    #[test]
+    fn test_concurrent_readers() {
+        const N_READERS: usize = 4;
+        let db_config = || ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let slow_db = SlowDb {
+            inner: new_driver().start_db(db_config()).unwrap(),
+        };
+        let db = wrap_db(Box::new(slow_db), &db_config()).unwrap();
+        let shared = Arc::new(RwLock::new(db));
+        let mut writer = write_shared(&shared, |_db| false).unwrap();
+        assert_eq!(writer.put(b"slow", b"v"), Ok(true));
+        drop(writer);
+
+        // every reader holds the lock at once, so their reads overlap
+        let barrier = Arc::new(Barrier::new(N_READERS));
+        let start = Instant::now();
+        let readers: Vec<_> = (0..N_READERS)
+            .map(|_i| {
+                let shared = Arc::clone(&shared);
+                let barrier = Arc::clone(&barrier);
+                thread::spawn(move || {
+                    let db = read_shared(&shared, |_db| false).unwrap();
+                    barrier.wait();
+                    assert_eq!(db.get(b"slow"), Ok(Some(b"v".to_vec())));
+                })
+            })
+            .collect();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+        let serial = Duration::from_millis(SLOW_GET_MS * N_READERS as u64);
+        assert!(start.elapsed() < serial);
+    }
+
+    #[test]
+    fn test_slow_op_log() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let slow_db = SlowDb {
+            inner: new_driver().start_db(db_config).unwrap(),
+        };
+
+        let reports = Arc::new(Mutex::new(Vec::new()));
+        let reports_cb = Arc::clone(&reports);
+        let mut db = SlowLogDb::with_reporter(
+            slow_db,
+            Duration::from_millis(SLOW_GET_MS / 2),
+            Box::new(move |slow_op| {
+                reports_cb
+                    .lock()
+                    .unwrap()
+                    .push((slow_op.op, slow_op.key_len, slow_op.elapsed));
+            }),
+        );
+
+        // fast ops are not reported
+        assert_eq!(db.put(b"fast", b"1"), Ok(true));
+        assert_eq!(db.put(b"slow", b"2"), Ok(true));
+        assert_eq!(db.get(b"fast"), Ok(Some(b"1".to_vec())));
+        assert!(reports.lock().unwrap().is_empty());
+
+        // slow op is reported, with key length but not key
+        assert_eq!(db.get(b"slow"), Ok(Some(b"2".to_vec())));
+        {
+            let reports = reports.lock().unwrap();
+            assert_eq!(reports.len(), 1);
+            assert_eq!(reports[0].0, "get");
+            assert_eq!(reports[0].1, Some(4));
+            assert!(reports[0].2 >= Duration::from_millis(SLOW_GET_MS));
+        }
+
+        // threshold above op duration: nothing reported
+        let mut db = SlowLogDb::with_reporter(
+            db,
+            Duration::from_millis(SLOW_GET_MS * 20),
+            Box::new(|_| panic!("unexpected slow op report")),
+        );
+        assert_eq!(db.get(b"slow"), Ok(Some(b"2".to_vec())));
+        assert_eq!(db.del(b"slow"), Ok(true));
+    }
+
+    #[test]
+    fn test_op_timeout() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let slow_db = SlowDb {
+            inner: new_driver().start_db(db_config).unwrap(),
+        };
+        let mut db = TimeoutDb::new(slow_db, Duration::from_millis(SLOW_GET_MS / 5));
+        assert_eq!(db.put(b"fast", b"1"), Ok(true));
+        assert_eq!(db.put(b"slow", b"2"), Ok(true));
+        assert_eq!(db.get(b"fast"), Ok(Some(b"1".to_vec())));
+
+        // an op past the limit fails, rather than waiting it out; the
+        // db serves again once it completes
+        let start = Instant::now();
+        assert_eq!(db.get(b"slow"), Err(Error::Timeout));
+        assert!(start.elapsed() < Duration::from_millis(SLOW_GET_MS));
+        thread::sleep(Duration::from_millis(SLOW_GET_MS));
+        assert_eq!(db.get(b"fast"), Ok(Some(b"1".to_vec())));
+        assert_eq!(db.del(b"slow"), Ok(true));
+        assert_eq!(db.stat().unwrap().n_records, 1);
+
+        // a limit above op duration is transparent
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .op_timeout_ms(SLOW_GET_MS * 20)
+            .build();
+        assert_eq!(db_config.op_timeout_ms, Some(SLOW_GET_MS * 20));
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert_eq!(db.max_iter_keys(), MAX_ITER_KEYS);
+    }
+
+    #[test]
+    fn test_slow_op_config() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .slow_op_threshold_ms(1000)
+            .build();
+        assert_eq!(db_config.slow_op_threshold_ms, Some(1000));
+
+        // logging layer is transparent
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert_eq!(db.stat().unwrap().n_records, 1);
+    }
+
+    // backend wrapper whose writes fail while fail is set, as if its
+    // disk were failing
+    struct FailingDb {
+        inner: Box<dyn Db + Send>,
+        fail: Arc<std::sync::atomic::AtomicBool>,
+    }
+
+    impl FailingDb {
+        fn check(&self) -> Result<(), Error> {
+            match self.fail.load(Ordering::SeqCst) {
+                true => Err(Error::Backend(String::from("put failed"))),
+                false => Ok(()),
+            }
+        }
+    }
+
+    impl Db for FailingDb {
+        fn apply_batch(&mut self, batch: &Batch) -> Result<bool, Error> {
+            self.check()?;
+            self.inner.apply_batch(batch)
+        }
+
+        fn clear(&mut self) -> Result<bool, Error> {
+            self.check()?;
+            self.inner.clear()
+        }
+
+        fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+            self.check()?;
+            self.inner.del(key)
+        }
+
+        fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+            self.inner.get(key)
+        }
+
+        fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+            self.check()?;
+            self.inner.put(key, val)
+        }
+
+        fn iter_keys(&self, opts: IterOptions) -> Result<KeyList, Error> {
+            self.inner.iter_keys(opts)
+        }
+
+        fn stat(&self) -> Result<DbStat, Error> {
+            self.inner.stat()
+        }
+    }
+
+    #[test]
+    fn test_degrade() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let fail = Arc::new(std::sync::atomic::AtomicBool::new(false));
+        let failing_db = FailingDb {
+            inner: new_driver().start_db(db_config).unwrap(),
+            fail: Arc::clone(&fail),
+        };
+        let mut db = DegradeDb::new(failing_db, 3);
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+
+        // failures short of the threshold, then a success, reset the count
+        fail.store(true, Ordering::SeqCst);
+        assert_eq!(
+            db.put(b"name", b"bob"),
+            Err(Error::Backend(String::from("put failed")))
+        );
+        assert_eq!(
+            db.del(b"name"),
+            Err(Error::Backend(String::from("put failed")))
+        );
+        fail.store(false, Ordering::SeqCst);
+        assert_eq!(db.put(b"age", b"41"), Ok(true));
+        assert!(!db.degraded());
+
+        // refused writes are not failures
+        let mut batch = Batch::default();
+        batch.insert(b"k", b"1");
+        batch.insert(b"k", b"2");
+        batch.duplicate_policy = DuplicatePolicy::Reject;
+        for _ in 0..3 {
+            assert_eq!(db.apply_batch(&batch), Err(ERR_DUPLICATE_KEY));
+        }
+        assert!(!db.degraded());
+
+        // threshold failures in a row degrade to read-only
+        fail.store(true, Ordering::SeqCst);
+        for _ in 0..3 {
+            assert_eq!(
+                db.put(b"name", b"bob"),
+                Err(Error::Backend(String::from("put failed")))
+            );
+        }
+        assert!(db.degraded());
+
+        // writes are refused even once the backend recovers; reads continue
+        fail.store(false, Ordering::SeqCst);
+        assert_eq!(db.put(b"name", b"bob"), Err(ERR_DEGRADED));
+        assert_eq!(db.del(b"name"), Err(ERR_DEGRADED));
+        assert_eq!(db.clear(), Err(ERR_DEGRADED));
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert_eq!(db.stat().unwrap().n_records, 2);
+
+        db.reset_degraded();
+        assert!(!db.degraded());
+        assert_eq!(db.put(b"name", b"bob"), Ok(true));
+
+        // configured layer is transparent until writes fail
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .write_failure_threshold(3)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert!(!db.degraded());
+    }
+
+    #[test]
+    fn test_value_metadata() {
+        let meta = |pairs: &[(&str, &str)]| -> Vec<(String, String)> {
+            pairs
+                .iter()
+                .map(|(name, val)| (name.to_string(), val.to_string()))
+                .collect()
+        };
+        let text_meta = meta(&[("content-type", "text/plain")]);
+
+        // without the metadata layer, only empty metadata is accepted
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(
+            db.put_meta(b"a", b"1", &text_meta),
+            Err(ERR_META_UNSUPPORTED)
+        );
+        assert_eq!(db.put_meta(b"a", b"1", &[]), Ok(true));
+        let (val, got_meta) = db.get_meta(b"a").unwrap().unwrap();
+        assert_eq!((&val[..], got_meta), (&b"1"[..], Vec::new()));
+
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .value_metadata(true)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // metadata is returned with the value, and stripped from reads
+        assert_eq!(db.put_meta(b"Doc", b"hello", &text_meta), Ok(true));
+        let (val, got_meta) = db.get_meta(b"doc").unwrap().unwrap();
+        assert_eq!((&val[..], got_meta), (&b"hello"[..], text_meta.clone()));
+        assert_eq!(db.get(b"doc"), Ok(Some(Vec::from("hello"))));
+        assert_eq!(&db.get_ref(b"doc").unwrap().unwrap()[..], b"hello");
+        assert_eq!(
+            db.get_multi(&[b"doc".to_vec()]),
+            Ok(vec![Some(Vec::from("hello"))])
+        );
+        let entries = db.iter_entries(IterOptions::new(), None).unwrap().entries;
+        assert_eq!(entries, vec![(b"doc".to_vec(), b"hello".to_vec())]);
+        assert!(db.get_meta(b"absent").unwrap().is_none());
+
+        // metadata moves with its value, and any other write clears it
+        assert_eq!(db.rename(b"doc", b"doc2", false), Ok(true));
+        assert_eq!(db.get_meta(b"doc2").unwrap().unwrap().1, text_meta);
+        assert_eq!(db.put(b"doc2", b"bye"), Ok(true));
+        let (val, got_meta) = db.get_meta(b"doc2").unwrap().unwrap();
+        assert_eq!((&val[..], got_meta), (&b"bye"[..], Vec::new()));
+
+        // values resembling a metadata header read back unchanged
+        let tricky = [&b"\xffkvdbd:meta\x00"[..], b"\x00\x00\x00\x02[]x"].concat();
+        assert_eq!(db.put(b"tricky", &tricky), Ok(true));
+        assert_eq!(db.get(b"tricky"), Ok(Some(tricky.clone())));
+        let mut batch = Batch::default();
+        batch.insert(b"tricky2", &tricky);
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        let (val, got_meta) = db.get_meta(b"tricky2").unwrap().unwrap();
+        assert_eq!((val.to_vec(), got_meta), (tricky, Vec::new()));
+
+        // metadata is capped in entries and bytes; nothing is stored
+        let many: Vec<(String, String)> = (0..MAX_META_ENTRIES + 1)
+            .map(|n| (format!("name{n}"), String::from("v")))
+            .collect();
+        assert_eq!(db.put_meta(b"big", b"x", &many), Err(ERR_META_TOO_LARGE));
+        let long = meta(&[("content-disposition", &"x".repeat(MAX_META_BYTES))]);
+        assert_eq!(db.put_meta(b"big", b"x", &long), Err(ERR_META_TOO_LARGE));
+        assert_eq!(db.get(b"big"), Ok(None));
+    }
+
+    // reverses each value's bytes
+    struct ReverseTransform;
+
+    impl ValueTransform for ReverseTransform {
+        fn on_write(&self, val: &[u8]) -> Vec<u8> {
+            val.iter().rev().cloned().collect()
+        }
+
+        fn on_read(&self, stored: &[u8]) -> Vec<u8> {
+            stored.iter().rev().cloned().collect()
+        }
+    }
+
+    // appends a tag byte, which must be last when read back
+    struct TagTransform(u8);
+
+    impl ValueTransform for TagTransform {
+        fn on_write(&self, val: &[u8]) -> Vec<u8> {
+            let mut stored = val.to_vec();
+            stored.push(self.0);
+            stored
+        }
+
+        fn on_read(&self, stored: &[u8]) -> Vec<u8> {
+            assert_eq!(stored.last(), Some(&self.0));
+            stored[..stored.len() - 1].to_vec()
+        }
+    }
+
+    #[test]
+    fn test_value_transform() {
+        // reversed, then tagged, on write; untagged, then reversed, on read
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .change_log_len(4)
+            .value_metadata(true)
+            .value_transform(Arc::new(ReverseTransform))
+            .value_transform(Arc::new(TagTransform(b'!')))
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"a", b"abc"), Ok(true));
+        assert_eq!(db.put(b"empty", b""), Ok(true));
+        assert_eq!(db.get(b"a"), Ok(Some(Vec::from("abc"))));
+        assert_eq!(db.get(b"empty"), Ok(Some(Vec::new())));
+        assert_eq!(&db.get_ref(b"a").unwrap().unwrap()[..], b"abc");
+
+        // stored transformed:  one tag byte more per value
+        assert_eq!(db.size_between(b"a", b"b"), Ok(1 + 3 + 1));
+
+        // batches, guards, and every other layer see values as written
+        let mut batch = Batch {
+            guard: Some((b"a".to_vec(), Some(b"abc".to_vec()))),
+            ..Batch::default()
+        };
+        batch.insert(b"b", b"xyz");
+        batch.remove(b"empty");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        batch.guard = Some((b"a".to_vec(), Some(b"cba".to_vec())));
+        assert_eq!(db.apply_batch(&batch), Err(ERR_GUARD_MISMATCH));
+        assert_eq!(
+            db.get_multi(&[b"b".to_vec(), b"empty".to_vec()]),
+            Ok(vec![Some(Vec::from("xyz")), None])
+        );
+        let mut entries = db.iter_entries(IterOptions::new(), None).unwrap().entries;
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                (b"a".to_vec(), b"abc".to_vec()),
+                (b"b".to_vec(), b"xyz".to_vec())
+            ]
+        );
+        let meta = vec![(String::from("content-type"), String::from("text/plain"))];
+        assert_eq!(db.put_meta(b"c", b"hi", &meta), Ok(true));
+        let (val, got_meta) = db.get_meta(b"c").unwrap().unwrap();
+        assert_eq!((&val[..], got_meta), (&b"hi"[..], meta));
+        assert_eq!(db.changes_since(0).unwrap().last_seq, 7);
+        assert_eq!(db.put_next(b"next"), Ok(b"00000000000000000001".to_vec()));
+    }
+
+    // n_records JSON records, as an application might store them, each
+    // listing n_items line items
+    fn json_records(n_records: usize, n_items: usize) -> Vec<(Vec<u8>, Vec<u8>)> {
+        (0..n_records)
+            .map(|i| {
+                let items: Vec<serde_json::Value> = (0..n_items)
+                    .map(|j| {
+                        serde_json::json!({
+                            "sku": format!("SKU-{:05}", (i * 7 + j * 13) % 10_000),
+                            "qty": 1 + j % 4,
+                            "price": format!("{}.99", 5 + (i + j) % 50),
+                            "tags": ["stock", "ships-free"],
+                        })
+                    })
+                    .collect();
+                let val = serde_json::json!({
+                    "id": i,
+                    "name": format!("user {}", i),
+                    "email": format!("user{}@example.com", i),
+                    "roles": ["reader", "writer"],
+                    "active": i % 3 != 0,
+                    "created": "2026-10-14T12:00:00Z",
+                    "prefs": {"theme": "dark", "lang": "en", "page_size": 50},
+                    "items": items,
+                });
+                (
+                    format!("user:{i:06}").into_bytes(),
+                    val.to_string().into_bytes(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_compress_values() {
         let db_config = ConfigBuilder::new()
             .path("/dev/null".to_string())
-            .read_only(false)
+            .compress_values(true)
+            .value_metadata(true)
             .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
 
-        let driver = new_driver();
+        // stored compressed, read back as written
+        let records = json_records(16, 4);
+        let (key, val) = &records[0];
+        assert_eq!(db.put(key, val), Ok(true));
+        assert_eq!(db.get(key), Ok(Some(val.clone())));
+        let stored_len = db.size_between(key, b"user:999999").unwrap();
+        assert!(stored_len < (key.len() + val.len()) as u64);
 
-        let mut db = driver.start_db(db_config).unwrap();
+        // short values, and those compression would not shrink, as-is
+        let noise: Vec<u8> = (0..200u32)
+            .map(|i| (i.wrapping_mul(2654435761) >> 24) as u8)
+            .collect();
+        assert_eq!(db.put(b"short", b"abc"), Ok(true));
+        assert_eq!(db.put(b"noise", &noise), Ok(true));
+        assert_eq!(db.put(b"empty", b""), Ok(true));
+        assert_eq!(db.size_between(b"short", b"shortz"), Ok(5 + 3));
+        assert_eq!(db.size_between(b"noise", b"noisez"), Ok(5 + 200));
+        assert_eq!(db.get(b"short"), Ok(Some(Vec::from("abc"))));
+        assert_eq!(db.get(b"noise"), Ok(Some(noise.clone())));
+        assert_eq!(db.get(b"empty"), Ok(Some(Vec::new())));
 
-        // iterate empty list
-        let key_list_res = db.iter_keys(IterOptions::new());
-        assert_eq!(key_list_res.is_err(), false);
+        // batches and entry listings, decompressed; metadata kept
+        let mut batch = Batch::default();
+        for (key, val) in &records[1..] {
+            batch.insert(key, val);
+        }
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        let mut opts = IterOptions::new();
+        opts.prefix(b"user:");
+        assert_eq!(db.iter_entries(opts, None).unwrap().entries, records);
+        let meta = vec![(
+            String::from("content-type"),
+            String::from("application/json"),
+        )];
+        assert_eq!(db.put_meta(b"m", val, &meta), Ok(true));
+        let (got, got_meta) = db.get_meta(b"m").unwrap().unwrap();
+        assert_eq!((&got[..], got_meta), (&val[..], meta));
 
-        let mut key_list = key_list_res.unwrap();
-        assert_eq!(key_list.list_end, true);
+        // values stored before compression was enabled read as written,
+        // even one beginning with the header
+        let shared = Arc::new(Mutex::new(MemDb::new()));
+        let legacy_hdr = b"\xffkvdbd:gzip\x00 not gzip".to_vec();
+        shared.lock().unwrap().put(b"1:c:old", val).unwrap();
+        shared.lock().unwrap().put(b"1:c:hdr", &legacy_hdr).unwrap();
+        let mut cdb = compressed_db(PrefixedDb::new(Arc::clone(&shared), "c").unwrap());
+        assert_eq!(cdb.get(b"old"), Ok(Some(val.clone())));
+        assert_eq!(cdb.get(b"hdr"), Ok(Some(legacy_hdr.clone())));
 
-        key_list.keys.sort();
-        assert_eq!(key_list.keys.len(), 0);
+        // a new value beginning with the header is compressed, so that
+        // it reads back unambiguously
+        assert_eq!(cdb.put(b"hdr", &legacy_hdr), Ok(true));
+        assert_ne!(
+            shared.lock().unwrap().get(b"1:c:hdr"),
+            Ok(Some(legacy_hdr.clone()))
+        );
+        assert_eq!(cdb.get(b"hdr"), Ok(Some(legacy_hdr)));
+        assert_eq!(cdb.put(b"new", val), Ok(true));
+        assert_ne!(
+            shared.lock().unwrap().get(b"1:c:new"),
+            Ok(Some(val.clone()))
+        );
+        assert_eq!(cdb.get(b"new"), Ok(Some(val.clone())));
+    }
 
-        // iterate small list
-        assert_eq!(db.put(b"name", b"alan"), Ok(true));
-        assert_eq!(db.put(b"age", b"25"), Ok(true));
+    #[test]
+    fn test_encrypted_db() {
+        let key: Vec<u8> = (0..32).collect();
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .encryption_key(key.clone())
+            .compress_values(true)
+            .change_log_len(4)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
 
-        let key_list_res = db.iter_keys(IterOptions::new());
-        assert_eq!(key_list_res.is_err(), false);
+        // every read path sees plaintext, whatever the write path
+        let records = json_records(4, 4);
+        let (key0, val0) = &records[0];
+        assert_eq!(db.put(key0, val0), Ok(true));
+        assert_eq!(db.put(b"empty", b""), Ok(true));
+        let mut batch = Batch::default();
+        for (key, val) in &records[1..] {
+            batch.insert(key, val);
+        }
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(key0), Ok(Some(val0.clone())));
+        assert_eq!(db.get(b"empty"), Ok(Some(Vec::new())));
+        let mut opts = IterOptions::new();
+        opts.prefix(b"user:");
+        assert_eq!(db.iter_entries(opts, None).unwrap().entries, records);
+        assert_eq!(db.compare_and_swap(key0, Some(val0), Some(b"v2")), Ok(true));
+        assert_eq!(db.get(key0), Ok(Some(Vec::from("v2"))));
+        assert_eq!(db.changes_since(0).unwrap().last_seq, 6);
 
-        let mut key_list = key_list_res.unwrap();
-        assert_eq!(key_list.list_end, true);
+        // keys are plaintext, so listed in order; values are not
+        let shared = Arc::new(Mutex::new(MemDb::new()));
+        let mut edb =
+            EncryptedDb::new(PrefixedDb::new(Arc::clone(&shared), "e").unwrap(), &key).unwrap();
+        assert_eq!(edb.put(b"b", b"secret value"), Ok(true));
+        assert_eq!(edb.put(b"a", b"secret value"), Ok(true));
+        assert_eq!(
+            edb.iter_keys(IterOptions::new()).unwrap().keys,
+            vec![b"a".to_vec(), b"b".to_vec()]
+        );
+        let stored_a = shared.lock().unwrap().get(b"1:e:a").unwrap().unwrap();
+        let stored_b = shared.lock().unwrap().get(b"1:e:b").unwrap().unwrap();
+        assert!(!stored_a.windows(6).any(|w| w == b"secret"));
+        assert_ne!(stored_a, stored_b); // nonce per value
+        assert_eq!(edb.get(b"a"), Ok(Some(Vec::from("secret value"))));
 
-        key_list.keys.sort();
-        assert_eq!(key_list.keys.len(), 2);
-        assert_eq!(key_list.keys[0], b"age");
-        assert_eq!(key_list.keys[1], b"name");
+        // another key fails to decrypt, rather than returning garbage;
+        // as do values stored unencrypted
+        let mut other_key = key.clone();
+        other_key[0] ^= 1;
+        let odb = EncryptedDb::new(
+            PrefixedDb::new(Arc::clone(&shared), "e").unwrap(),
+            &other_key,
+        )
+        .unwrap();
+        assert!(matches!(odb.get(b"a"), Err(Error::Corruption(_))));
+        shared
+            .lock()
+            .unwrap()
+            .put(b"1:e:plain", b"plaintext")
+            .unwrap();
+        assert!(matches!(edb.get(b"plain"), Err(Error::Corruption(_))));
+
+        assert!(matches!(
+            EncryptedDb::new(MemDb::new(), &key[..16]),
+            Err(Error::InvalidInput(_))
+        ));
     }
 
     #[test]
-    fn test_iter_prefix() {
+    fn test_encrypted_db_tamper() {
+        let key = [7u8; 32];
+        let shared = Arc::new(Mutex::new(MemDb::new()));
+        let mut edb =
+            EncryptedDb::new(PrefixedDb::new(Arc::clone(&shared), "e").unwrap(), &key).unwrap();
+        assert_eq!(edb.put(b"a", b"secret value"), Ok(true));
+        assert_eq!(edb.put(b"b", b"other value"), Ok(true));
+        let stored = shared.lock().unwrap().get(b"1:e:a").unwrap().unwrap();
+
+        // a flip of any byte - version, nonce, ciphertext or tag - fails
+        // authentication
+        for i in 0..stored.len() {
+            let mut tampered = stored.clone();
+            tampered[i] ^= 0x80;
+            shared.lock().unwrap().put(b"1:e:a", &tampered).unwrap();
+            assert_eq!(
+                edb.get(b"a"),
+                Err(Error::Corruption(String::from("value decryption failed")))
+            );
+        }
+
+        // as does a value truncated, or moved to another key
+        shared
+            .lock()
+            .unwrap()
+            .put(b"1:e:a", &stored[..stored.len() - 1])
+            .unwrap();
+        assert!(matches!(edb.get(b"a"), Err(Error::Corruption(_))));
+        shared.lock().unwrap().put(b"1:e:b", &stored).unwrap();
+        assert!(matches!(edb.get(b"b"), Err(Error::Corruption(_))));
+
+        // the untampered value still reads back
+        shared.lock().unwrap().put(b"1:e:a", &stored).unwrap();
+        assert_eq!(edb.get(b"a"), Ok(Some(Vec::from("secret value"))));
+    }
+
+    #[test]
+    fn test_verify_checksums() {
         let db_config = ConfigBuilder::new()
             .path("/dev/null".to_string())
-            .read_only(false)
+            .verify_checksums(true)
+            .compress_values(true)
+            .change_log_len(4)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // every read path sees values as written
+        let records = json_records(4, 4);
+        let (key0, val0) = &records[0];
+        assert_eq!(db.put(key0, val0), Ok(true));
+        assert_eq!(db.put(b"empty", b""), Ok(true));
+        let mut batch = Batch::default();
+        for (key, val) in &records[1..] {
+            batch.insert(key, val);
+        }
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"empty"), Ok(Some(Vec::new())));
+        let mut opts = IterOptions::new();
+        opts.prefix(b"user:");
+        assert_eq!(db.iter_entries(opts, None).unwrap().entries, records);
+        assert_eq!(db.compare_and_swap(key0, Some(val0), Some(b"v2")), Ok(true));
+        assert_eq!(db.get(key0), Ok(Some(Vec::from("v2"))));
+        assert_eq!(db.changes_since(0).unwrap().last_seq, 6);
+    }
+
+    #[test]
+    fn test_verify_checksums_corrupt() {
+        let shared = Arc::new(Mutex::new(MemDb::new()));
+        let mut cdb = ChecksumDb::new(PrefixedDb::new(Arc::clone(&shared), "c").unwrap());
+        assert_eq!(cdb.put(b"a", b"some value"), Ok(true));
+        assert_eq!(cdb.put(b"b", b"other value"), Ok(true));
+        let stored = shared.lock().unwrap().get(b"1:c:a").unwrap().unwrap();
+        assert_eq!(cdb.get(b"a"), Ok(Some(Vec::from("some value"))));
+
+        // a flip of any byte - version, checksum or value - fails the
+        // read, rather than returning bad bytes
+        for i in 0..stored.len() {
+            let mut corrupt = stored.clone();
+            corrupt[i] ^= 0x04;
+            shared.lock().unwrap().put(b"1:c:a", &corrupt).unwrap();
+            assert!(matches!(cdb.get(b"a"), Err(Error::Corruption(_))));
+        }
+        let mut corrupt = stored.clone();
+        corrupt[stored.len() - 1] ^= 0x04;
+        shared.lock().unwrap().put(b"1:c:a", &corrupt).unwrap();
+        assert_eq!(
+            cdb.get(b"a"),
+            Err(Error::Corruption(String::from("value checksum mismatch")))
+        );
+        assert!(matches!(
+            cdb.get_multi(&[b"a".to_vec(), b"b".to_vec()]),
+            Err(Error::Corruption(_))
+        ));
+
+        // as does a value truncated, or stored without a checksum
+        shared
+            .lock()
+            .unwrap()
+            .put(b"1:c:a", &stored[..stored.len() - 1])
+            .unwrap();
+        assert!(matches!(cdb.get(b"a"), Err(Error::Corruption(_))));
+        shared.lock().unwrap().put(b"1:c:a", b"").unwrap();
+        assert_eq!(
+            cdb.get(b"a"),
+            Err(Error::Corruption(String::from("value checksum missing")))
+        );
+
+        // the intact value still reads back, as do others
+        shared.lock().unwrap().put(b"1:c:a", &stored).unwrap();
+        assert_eq!(cdb.get(b"a"), Ok(Some(Vec::from("some value"))));
+        assert_eq!(cdb.get(b"b"), Ok(Some(Vec::from("other value"))));
+    }
+
+    #[test]
+    fn test_track_modified() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .track_modified(true)
+            .value_metadata(true)
+            .key_case(KeyCase::LowercaseFold)
+            .change_log_len(4)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.get_modified(b"a"), Ok(None));
+
+        // stamped by each write, and read without the stamp
+        let before_ms = unix_now_ms();
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        let (val, unix_ms) = db.get_modified(b"A").unwrap().unwrap();
+        assert_eq!(val, b"1".to_vec());
+        assert!(unix_ms >= before_ms && unix_ms <= unix_now_ms());
+        assert_eq!(db.get(b"a"), Ok(Some(Vec::from("1"))));
+
+        thread::sleep(Duration::from_millis(5));
+        let mut batch = Batch::default();
+        batch.insert(b"a", b"2");
+        batch.insert(b"b", b"3");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        let (val, batch_ms) = db.get_modified(b"a").unwrap().unwrap();
+        assert_eq!(val, b"2".to_vec());
+        assert!(batch_ms > unix_ms);
+        assert_eq!(db.get_modified(b"b").unwrap().unwrap().1, batch_ms);
+
+        // metadata is stripped, as by get()
+        let meta = vec![(String::from("content-type"), String::from("text/plain"))];
+        assert_eq!(db.put_meta(b"m", b"4", &meta), Ok(true));
+        assert_eq!(db.get_modified(b"m").unwrap().unwrap().0, b"4".to_vec());
+        assert_eq!(db.get_meta(b"m").unwrap().unwrap().1, meta);
+        assert_eq!(db.changes_since(0).unwrap().last_seq, 4);
+
+        // a value stored before times were tracked has time 0
+        let shared = Arc::new(Mutex::new(MemDb::new()));
+        shared.lock().unwrap().put(b"1:t:old", b"5").unwrap();
+        let tdb = ModTimeDb::new(PrefixedDb::new(Arc::clone(&shared), "t").unwrap());
+        assert_eq!(tdb.get_modified(b"old"), Ok(Some((b"5".to_vec(), 0))));
+        assert_eq!(tdb.get(b"old"), Ok(Some(Vec::from("5"))));
+
+        // without the layer, times are not tracked
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.get_modified(b"a"), Err(ERR_MODTIME_UNSUPPORTED));
+    }
+
+    #[test]
+    fn test_config_try_build() {
+        let build_err = |builder: &ConfigBuilder| match builder.try_build() {
+            Err(Error::InvalidInput(msg)) => msg,
+            _ => String::new(),
+        };
+
+        // an unset path is refused, rather than defaulted
+        let mut builder = ConfigBuilder::new();
+        assert_eq!(builder.build().path, "./db");
+        assert!(build_err(&builder).contains("not set"));
+
+        // an existing directory, or one yet to be created within one
+        let tmp_dir = TempDir::new("ttb").unwrap();
+        let dir_path = tmp_dir.path().to_str().unwrap().to_string();
+        builder.path(dir_path.clone());
+        assert_eq!(builder.try_build().unwrap().path, dir_path);
+        let new_path = tmp_dir.path().join("a/b/db1.kv");
+        builder.path(new_path.to_str().unwrap().to_string());
+        assert!(builder.try_build().is_ok());
+
+        // a file is not a directory, whether the path or its ancestor
+        let file_path = tmp_dir.path().join("file");
+        fs::write(&file_path, b"x").unwrap();
+        builder.path(file_path.to_str().unwrap().to_string());
+        assert!(build_err(&builder).contains("not a directory"));
+        builder.path(file_path.join("db1.kv").to_str().unwrap().to_string());
+        assert!(builder.try_build().is_err());
+
+        // a directory without write permission, unless read-only
+        let ro_dir = tmp_dir.path().join("ro");
+        fs::create_dir(&ro_dir).unwrap();
+        let writable = fs::metadata(&ro_dir).unwrap().permissions();
+        let mut perms = writable.clone();
+        perms.set_readonly(true);
+        fs::set_permissions(&ro_dir, perms).unwrap();
+        builder.path(ro_dir.join("db1.kv").to_str().unwrap().to_string());
+        assert!(build_err(&builder).contains("not writable"));
+        builder.read_only(true);
+        assert!(builder.try_build().unwrap().read_only);
+        fs::set_permissions(&ro_dir, writable).unwrap();
+    }
+
+    #[test]
+    fn test_track_versions() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .track_versions(true)
+            .track_modified(true)
+            .value_metadata(true)
+            .key_case(KeyCase::LowercaseFold)
             .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.get_versioned(b"a"), Ok(None));
+
+        // bumped by each write, and read without the header
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.get_versioned(b"A"), Ok(Some((b"1".to_vec(), 1))));
+        assert_eq!(db.put(b"a", b"2"), Ok(true));
+        assert_eq!(db.get_versioned(b"a"), Ok(Some((b"2".to_vec(), 2))));
+        assert_eq!(db.get(b"a"), Ok(Some(Vec::from("2"))));
+        assert_eq!(db.get_modified(b"a").unwrap().unwrap().0, b"2".to_vec());
+
+        // once per mutation of a key in a batch; a remove resets it
+        let mut batch = Batch::default();
+        batch.insert(b"a", b"3");
+        batch.insert(b"a", b"4");
+        batch.insert(b"b", b"5");
+        batch.remove(b"b");
+        batch.insert(b"b", b"6");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get_versioned(b"a"), Ok(Some((b"4".to_vec(), 4))));
+        assert_eq!(db.get_versioned(b"b"), Ok(Some((b"6".to_vec(), 1))));
+        db.merge(b"b", b"7").unwrap();
+        assert_eq!(db.get_versioned(b"b"), Ok(Some((b"7".to_vec(), 2))));
+        assert_eq!(db.del(b"b"), Ok(true));
+        assert_eq!(db.get_versioned(b"b"), Ok(None));
+
+        // written only at the version expected; 0 expects absence
+        assert_eq!(db.put_if_version(b"a", b"8", 3), Ok(false));
+        assert_eq!(db.put_if_version(b"a", b"8", 4), Ok(true));
+        assert_eq!(db.put_if_version(b"a", b"9", 4), Ok(false));
+        assert_eq!(db.get_versioned(b"a"), Ok(Some((b"8".to_vec(), 5))));
+        assert_eq!(db.put_if_version(b"c", b"1", 1), Ok(false));
+        assert_eq!(db.put_if_version(b"c", b"1", 0), Ok(true));
+        assert_eq!(db.put_if_version(b"c", b"2", 0), Ok(false));
+
+        // bulk, each put applied only over a version no newer than its own
+        let put = |key: &[u8], value: &[u8], version| VersionedPut {
+            key: key.to_vec(),
+            value: value.to_vec(),
+            version,
+        };
+        let puts = [
+            put(b"a", b"x", 4),
+            put(b"a", b"y", 5),
+            put(b"c", b"y", 1),
+            put(b"c", b"z", 1),
+            put(b"d", b"y", 0),
+        ];
+        assert_eq!(
+            db.put_if_not_newer(&puts),
+            Ok(vec![false, true, true, false, true])
+        );
+        assert_eq!(db.get_versioned(b"a"), Ok(Some((b"y".to_vec(), 6))));
+        assert_eq!(db.get_versioned(b"c"), Ok(Some((b"y".to_vec(), 2))));
+        assert_eq!(db.get_versioned(b"d"), Ok(Some((b"y".to_vec(), 1))));
+        assert_eq!(db.put_if_not_newer(&[]), Ok(vec![]));
+        assert_eq!(db.put_if_not_newer(&puts[..1]), Ok(vec![false]));
+        assert_eq!(
+            MemDb::new().put_if_not_newer(&puts),
+            Err(ERR_VERSION_UNSUPPORTED)
+        );
+
+        // metadata is stripped, as by get()
+        let meta = vec![(String::from("content-type"), String::from("text/plain"))];
+        assert_eq!(db.put_meta(b"m", b"4", &meta), Ok(true));
+        assert_eq!(db.get_versioned(b"m"), Ok(Some((b"4".to_vec(), 1))));
+        assert_eq!(db.get_meta(b"m").unwrap().unwrap().1, meta);
+
+        // a value stored before versions were tracked is at version 0
+        let shared = Arc::new(Mutex::new(MemDb::new()));
+        shared.lock().unwrap().put(b"1:t:old", b"5").unwrap();
+        let mut tdb = VersionDb::new(PrefixedDb::new(Arc::clone(&shared), "t").unwrap());
+        assert_eq!(tdb.get_versioned(b"old"), Ok(Some((b"5".to_vec(), 0))));
+        assert_eq!(tdb.put_if_version(b"old", b"6", 0), Ok(true));
+        assert_eq!(tdb.get_versioned(b"old"), Ok(Some((b"6".to_vec(), 1))));
+
+        // without the layer, versions are not tracked
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.get_versioned(b"a"), Err(ERR_VERSION_UNSUPPORTED));
+        assert_eq!(
+            db.put_if_version(b"a", b"1", 0),
+            Err(ERR_VERSION_UNSUPPORTED)
+        );
+    }
+
+    // CPU cost of compression, over 4 MB of JSON, in 4 KB values:  run
+    // with cargo test --release -- --ignored --nocapture bench_compress
+    #[test]
+    #[ignore]
+    fn bench_compress_values() {
+        let records = json_records(1_000, 50);
+        let raw_len: usize = records.iter().map(|(key, val)| key.len() + val.len()).sum();
+        for compress in [false, true] {
+            let db_config = ConfigBuilder::new()
+                .path("/dev/null".to_string())
+                .compress_values(compress)
+                .build();
+            let mut db = new_driver().start_db(db_config).unwrap();
+
+            let start = Instant::now();
+            for (key, val) in &records {
+                db.put(key, val).unwrap();
+            }
+            let put_time = start.elapsed();
+            let start = Instant::now();
+            for (key, val) in &records {
+                assert_eq!(db.get(key).unwrap().as_ref(), Some(val));
+            }
+            let get_time = start.elapsed();
+            let stored_len = db.size_between(b"", b"").unwrap();
+            println!(
+                "compress_values={compress}: {stored_len} bytes stored of {raw_len}; put {put_time:?}, get {get_time:?}"
+            );
+        }
+    }
+
+    // copy avoided by get_ref(), reading a 1 MB value 1,000 times, from
+    // the memory backend, bare and behind every default layer:  run with
+    // cargo test --release -- --ignored --nocapture bench_get_ref
+    #[test]
+    #[ignore]
+    fn bench_get_ref() {
+        const N_READS: usize = 1_000;
+        let val: Vec<u8> = (0..1_000_000).map(|i| (i % 251) as u8).collect();
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let dbs: Vec<(&str, Box<dyn Db + Send>)> = vec![
+            ("MemDb", Box::new(MemDb::new())),
+            ("wrapped", new_driver().start_db(db_config).unwrap()),
+        ];
+        for (name, mut db) in dbs {
+            db.put(b"big", &val).unwrap();
+
+            let start = Instant::now();
+            let mut n_bytes = 0;
+            for _ in 0..N_READS {
+                n_bytes += db.get(b"big").unwrap().unwrap().len();
+            }
+            let get_time = start.elapsed();
+            let start = Instant::now();
+            for _ in 0..N_READS {
+                n_bytes -= db.get_ref(b"big").unwrap().unwrap().len();
+            }
+            let get_ref_time = start.elapsed();
+            assert_eq!(n_bytes, 0);
+            println!(
+                "{name}: {N_READS} reads of {} bytes:  get {get_time:?} ({} MB copied), get_ref {get_ref_time:?}",
+                val.len(),
+                N_READS * val.len() / 1_000_000
+            );
+        }
+    }
+
+    #[test]
+    fn test_txn() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
 
         let driver = new_driver();
 
         let mut db = driver.start_db(db_config).unwrap();
+        db.put(b"alice", b"10").unwrap();
+        db.put(b"bob", b"5").unwrap();
 
-        // iterate small list
-        assert_eq!(db.put(b"2018/name", b"alan"), Ok(true));
-        assert_eq!(db.put(b"2018/bame", b"alan"), Ok(true));
-        assert_eq!(db.put(b"2019/fame", b"alan"), Ok(true));
-        assert_eq!(db.put(b"2019/lame", b"alan"), Ok(true));
-        assert_eq!(db.put(b"2019/game", b"alan"), Ok(true));
-        assert_eq!(db.put(b"2020/tame", b"alan"), Ok(true));
-        assert_eq!(db.put(b"age", b"25"), Ok(true));
-
-        let key_list_res = db.iter_keys(IterOptions::new());
-        assert_eq!(key_list_res.is_err(), false);
+        // reads see the txn's own writes; the db sees none until commit
+        let mut txn = Txn::new();
+        assert_eq!(txn.get(&db, b"alice"), Ok(Some(Vec::from("10"))));
+        txn.put(b"alice", b"7");
+        txn.put(b"bob", b"8");
+        txn.del(b"carol");
+        assert_eq!(txn.get(&db, b"alice"), Ok(Some(Vec::from("7"))));
+        assert_eq!(txn.get(&db, b"carol"), Ok(None));
+        assert_eq!(db.get(b"alice"), Ok(Some(Vec::from("10"))));
+        assert_eq!(txn.commit(&mut db), Ok(true));
+        assert_eq!(db.get(b"alice"), Ok(Some(Vec::from("7"))));
+        assert_eq!(db.get(b"bob"), Ok(Some(Vec::from("8"))));
 
-        let key_list = key_list_res.unwrap();
-        assert_eq!(key_list.list_end, true);
-        assert_eq!(key_list.keys.len(), 7);
+        // a key read has changed since: nothing applied
+        let mut txn1 = Txn::new();
+        let mut txn2 = Txn::new();
+        assert_eq!(txn1.get(&db, b"alice"), Ok(Some(Vec::from("7"))));
+        assert_eq!(txn2.get(&db, b"alice"), Ok(Some(Vec::from("7"))));
+        txn1.put(b"alice", b"1");
+        txn2.put(b"alice", b"2");
+        txn2.put(b"bob", b"2");
+        assert_eq!(txn1.commit(&mut db), Ok(true));
+        assert_eq!(txn2.commit(&mut db), Ok(false));
+        assert_eq!(db.get(b"alice"), Ok(Some(Vec::from("1"))));
+        assert_eq!(db.get(b"bob"), Ok(Some(Vec::from("8"))));
 
-        // iterate with prefix matching
-        let mut opts = IterOptions::new();
-        opts.prefix(b"2019/");
+        // absent keys read are checked too; blind writes are not
+        let mut txn1 = Txn::new();
+        assert_eq!(txn1.get(&db, b"carol"), Ok(None));
+        txn1.put(b"carol", b"1");
+        let mut txn2 = Txn::new();
+        txn2.put(b"bob", b"9");
+        db.put(b"carol", b"3").unwrap();
+        db.put(b"bob", b"4").unwrap();
+        assert_eq!(txn1.commit(&mut db), Ok(false));
+        assert_eq!(txn2.commit(&mut db), Ok(true));
+        assert_eq!(db.get(b"carol"), Ok(Some(Vec::from("3"))));
+        assert_eq!(db.get(b"bob"), Ok(Some(Vec::from("9"))));
+    }
 
-        let key_list_res = db.iter_keys(opts);
-        assert_eq!(key_list_res.is_err(), false);
+    #[test]
+    fn test_size_histogram() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
 
-        let mut key_list = key_list_res.unwrap();
-        assert_eq!(key_list.list_end, true);
-        assert_eq!(key_list.keys.len(), 3);
+        // keys of 5 bytes; values of 1..=100 bytes
+        let mut batch = Batch::default();
+        for i in 1..=100 {
+            batch.insert(format!("k{i:04}").as_bytes(), &vec![b'v'; i]);
+        }
+        assert_eq!(db.apply_batch(&batch), Ok(true));
 
-        key_list.keys.sort();
+        // sample holds every record:  exact
+        let mut sampler = SizeSampler::new(1000);
+        let mut start = None;
+        while let Some(key) = sample_page(&db, &mut sampler, start.as_deref(), 30).unwrap() {
+            start = Some(key);
+        }
+        let hist = sampler.summary();
+        assert_eq!((hist.n_seen, hist.n_sampled), (100, 100));
         assert_eq!(
-            String::from_utf8_lossy(&key_list.keys[0]),
-            String::from("2019/fame")
+            hist.key_len,
+            Percentiles {
+                p50: 5,
+                p90: 5,
+                p99: 5,
+                max: 5
+            }
         );
         assert_eq!(
-            String::from_utf8_lossy(&key_list.keys[1]),
-            String::from("2019/game")
+            hist.value_len,
+            Percentiles {
+                p50: 50,
+                p90: 90,
+                p99: 99,
+                max: 100
+            }
         );
+
+        // bounded scan, smaller sample; max is still exact
+        let mut sampler = SizeSampler::new(10);
+        let next = sample_page(&db, &mut sampler, None, 40).unwrap();
+        assert!(next.is_some());
+        let hist = sampler.summary();
+        assert_eq!((hist.n_seen, hist.n_sampled), (40, 10));
+        assert!(hist.value_len.p50 <= hist.value_len.max);
+
+        // nothing seen
+        assert_eq!(db.clear(), Ok(true));
+        let mut sampler = SizeSampler::new(10);
+        assert_eq!(sample_page(&db, &mut sampler, None, 40), Ok(None));
+        let hist = sampler.summary();
+        assert_eq!((hist.n_seen, hist.n_sampled), (0, 0));
+        assert_eq!(hist.value_len, Percentiles::default());
+
+        // the reservoir is a uniform sample of everything seen
+        let mut sampler = SizeSampler::with_seed(1000, 42);
+        for len in 0..100_000 {
+            sampler.add(8, len);
+        }
+        let hist = sampler.summary();
+        assert_eq!((hist.n_seen, hist.n_sampled), (100_000, 1000));
+        assert!(hist.value_len.p50 > 45_000 && hist.value_len.p50 < 55_000);
+        assert!(hist.value_len.p90 > 87_000 && hist.value_len.p90 < 93_000);
+        assert_eq!(hist.value_len.max, 99_999);
+    }
+
+    #[test]
+    fn test_quota() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_keys(3)
+            .max_total_bytes(20)
+            .build();
+
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (0, 0));
+        assert_eq!((usage.max_keys, usage.max_total_bytes), (Some(3), Some(20)));
+
+        // writes succeed up to the key quota, and fail past it
+        assert_eq!(db.put(b"k1", b"v1"), Ok(true));
+        assert_eq!(db.put(b"k2", b"v2"), Ok(true));
+        assert_eq!(db.put(b"k3", b"v3"), Ok(true));
+        assert_eq!(db.put(b"k4", b"v4"), Err(ERR_QUOTA_EXCEEDED));
+        assert_eq!(db.get(b"k4"), Ok(None));
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (3, 12));
+
+        // overwrite is not a new key; growing it counts toward bytes
+        assert_eq!(db.put(b"k1", b"0123456789"), Ok(true));
+        assert_eq!(db.put(b"k2", b"0123456789"), Err(ERR_QUOTA_EXCEEDED));
+        assert_eq!(db.get(b"k2"), Ok(Some(b"v2".to_vec())));
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (3, 20));
+
+        // deletes free quota
+        assert_eq!(db.del(b"k1"), Ok(true));
+        assert_eq!(db.del(b"k1"), Ok(false));
+        assert_eq!(db.put(b"k4", b"v4"), Ok(true));
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (3, 12));
+
+        // batches are counted as a whole, including repeated keys
+        let mut batch = Batch::default();
+        batch.remove(b"k2");
+        batch.insert(b"k5", b"v5");
+        batch.insert(b"k5", b"v55");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (3, 13));
+
+        let mut batch = Batch::default();
+        batch.insert(b"k6", b"v6");
+        batch.remove(b"k6");
+        batch.insert(b"k7", b"v7");
+        assert_eq!(db.apply_batch(&batch), Err(ERR_QUOTA_EXCEEDED));
+        assert_eq!(db.get(b"k6"), Ok(None));
+        assert_eq!(db.stat().unwrap().n_records, 3);
+
+        assert_eq!(db.clear(), Ok(true));
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (0, 0));
+
+        // no quota configured
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let db = new_driver().start_db(db_config).unwrap();
+        assert!(db.quota_usage().is_none());
+    }
+
+    #[test]
+    fn test_eviction() {
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_keys(3)
+            .max_total_bytes(20)
+            .eviction(Eviction::Lru)
+            .build();
+
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"k1", b"v1"), Ok(true));
+        assert_eq!(db.put(b"k2", b"v2"), Ok(true));
+        assert_eq!(db.put(b"k3", b"v3"), Ok(true));
+
+        // writing past the quota evicts the least recently used key
+        assert_eq!(db.get(b"k1"), Ok(Some(b"v1".to_vec())));
+        assert_eq!(db.put(b"k4", b"v4"), Ok(true));
+        assert_eq!(db.get(b"k2"), Ok(None));
+        assert_eq!(db.get(b"k1"), Ok(Some(b"v1".to_vec())));
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (3, 12));
+
+        // as many keys as needed, for bytes; never those written
+        let mut batch = Batch::default();
+        batch.insert(b"k1", b"01234567890123");
+        batch.insert(b"k5", b"v5");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"k3"), Ok(None));
+        assert_eq!(db.get(b"k4"), Ok(None));
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (2, 20));
+
+        // a write too large for an empty db fails, evicting nothing
+        assert_eq!(db.put(b"k6", &[b'x'; 20]), Err(ERR_QUOTA_EXCEEDED));
+        assert_eq!(db.stat().unwrap().n_records, 2);
+
+        // fifo:  reads and overwrites keep insertion order
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .max_keys(2)
+            .eviction(Eviction::Fifo)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"k1", b"v1"), Ok(true));
+        assert_eq!(db.put(b"k2", b"v2"), Ok(true));
+        assert_eq!(db.get(b"k1"), Ok(Some(b"v1".to_vec())));
+        assert_eq!(db.put(b"k1", b"v11"), Ok(true));
+        assert_eq!(db.put(b"k3", b"v3"), Ok(true));
+        assert_eq!(db.get(b"k1"), Ok(None));
+        assert_eq!(db.get(b"k2"), Ok(Some(b"v2".to_vec())));
+    }
+
+    #[test]
+    fn test_lock_poisoned() {
+        let db_config = ConfigBuilder::new().path("/dev/null".to_string()).build();
+        let db = Arc::new(Mutex::new(new_driver().start_db(db_config).unwrap()));
+        db.lock().unwrap().put(b"name", b"alan").unwrap();
+
+        // panic while holding the lock
+        let db_panic = Arc::clone(&db);
+        let res = thread::spawn(move || {
+            let mut db = db_panic.lock().unwrap();
+            db.put(b"name", b"bob").unwrap();
+            panic!("induced panic under db lock");
+        })
+        .join();
+        assert!(res.is_err());
+
+        // later callers get a clean error, until recovery is allowed
+        assert_eq!(lock_shared(&db, |_db| false).err(), Some(ERR_LOCK_POISONED));
+        assert_eq!(lock_shared(&db, |_db| false).err(), Some(ERR_LOCK_POISONED));
+        {
+            let db = lock_shared(&db, |_db| true).unwrap();
+            assert_eq!(db.get(b"name"), Ok(Some(b"bob".to_vec())));
+        }
+        let db = lock_shared(&db, |_db| false).unwrap();
+        assert_eq!(db.get(b"name"), Ok(Some(b"bob".to_vec())));
+
+        // single flight reports the poisoned db as an error
+        let sf_db = Arc::new(Mutex::new(MemDb::new()));
+        let sf_db_panic = Arc::clone(&sf_db);
+        let res = thread::spawn(move || {
+            let _db = sf_db_panic.lock().unwrap();
+            panic!("induced panic under db lock");
+        })
+        .join();
+        assert!(res.is_err());
+        let flights = SingleFlight::new(Duration::from_secs(1));
+        let res = flights.get_or_load(&sf_db, b"name", |_key| Ok(Some(Vec::from("alan"))));
+        assert_eq!(res, Err(ERR_LOCK_POISONED));
+    }
+
+    // poll a future to completion on this thread, parking between polls
+    fn block_on<F: std::future::Future>(fut: F) -> F::Output {
+        struct Unpark(thread::Thread);
+        impl std::task::Wake for Unpark {
+            fn wake(self: Arc<Self>) {
+                self.0.unpark();
+            }
+        }
+        let waker = std::task::Waker::from(Arc::new(Unpark(thread::current())));
+        let mut cx = std::task::Context::from_waker(&waker);
+        let mut fut = Box::pin(fut);
+        loop {
+            match fut.as_mut().poll(&mut cx) {
+                std::task::Poll::Ready(res) => return res,
+                std::task::Poll::Pending => thread::park(),
+            }
+        }
+    }
+
+    #[test]
+    fn test_watch() {
+        let hub = Arc::new(WatchHub::new());
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .max_keys(4)
+            .eviction(Eviction::Fifo)
+            .watch(Arc::clone(&hub))
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // changes before subscribing are not seen
+        assert_eq!(db.put(b"early", b"1"), Ok(true));
+        let all = hub.subscribe(b"");
+        let a_keys = hub.subscribe(b"a");
+        assert_eq!(hub.watchers(), 2);
+        assert_eq!(all.try_next(), None);
+
+        let event = |seq, op, key: &[u8]| {
+            Some(WatchItem::Event(WatchEvent {
+                seq,
+                op,
+                key: key.to_vec(),
+            }))
+        };
+
+        // keys as stored, folded; filtered by prefix
+        assert_eq!(db.put(b"A1", b"1"), Ok(true));
+        assert_eq!(db.put(b"b", b"2"), Ok(true));
+        assert_eq!(all.try_next(), event(1, WatchOp::Put, b"a1"));
+        assert_eq!(all.try_next(), event(2, WatchOp::Put, b"b"));
+        assert_eq!(a_keys.try_next(), event(1, WatchOp::Put, b"a1"));
+        assert_eq!(a_keys.try_next(), None);
+
+        // writes which change nothing are not published
+        assert_eq!(db.del(b"absent"), Ok(false));
+        assert_eq!(db.compare_and_swap(b"b", Some(b"x"), Some(b"3")), Ok(false));
+        assert_eq!(db.put(b"a2", &[0u8; 0]), Ok(true));
+        assert_eq!(all.try_next(), event(3, WatchOp::Put, b"a2"));
+
+        // an eviction, made room for a put, precedes it
+        assert_eq!(db.put(b"c", b"3"), Ok(true));
+        assert_eq!(all.try_next(), event(4, WatchOp::Del, b"early"));
+        assert_eq!(all.try_next(), event(5, WatchOp::Put, b"c"));
+
+        // a batch, or compound write, publishes each mutation
+        assert_eq!(db.rename(b"b", b"a3", false), Ok(true));
+        assert_eq!(all.try_next(), event(6, WatchOp::Put, b"a3"));
+        assert_eq!(all.try_next(), event(7, WatchOp::Del, b"b"));
+        assert_eq!(a_keys.try_next(), event(3, WatchOp::Put, b"a2"));
+        assert_eq!(a_keys.try_next(), event(6, WatchOp::Put, b"a3"));
+
+        assert_eq!(db.delete_prefix(b"a"), Ok(3));
+        assert_eq!(db.clear(), Ok(true));
+        for watcher in &[&all, &a_keys] {
+            assert_eq!(watcher.try_next(), event(8, WatchOp::Del, b"a1"));
+            assert_eq!(watcher.try_next(), event(9, WatchOp::Del, b"a2"));
+            assert_eq!(watcher.try_next(), event(10, WatchOp::Del, b"a3"));
+            assert_eq!(watcher.try_next(), event(11, WatchOp::Clear, b""));
+            assert_eq!(watcher.try_next(), None);
+        }
+
+        // a waiting watcher is woken by a change
+        struct Woken(AtomicUsize);
+        impl std::task::Wake for Woken {
+            fn wake(self: Arc<Self>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        let woken = Arc::new(Woken(AtomicUsize::new(0)));
+        let waker = std::task::Waker::from(Arc::clone(&woken));
+        assert!(all.poll_next(&waker).is_pending());
+        assert_eq!(db.put(b"d", b"4"), Ok(true));
+        assert_eq!(woken.0.load(Ordering::SeqCst), 1);
+        assert!(all.poll_next(&waker).is_ready());
+
+        // a watcher falling behind is cut off; others are not
+        let hub = Arc::new(WatchHub::with_queue_len(2));
+        let slow = hub.subscribe(b"");
+        let fast = hub.subscribe(b"");
+        for key in [b"k1", b"k2", b"k3"] {
+            hub.publish([(WatchOp::Put, &key[..])]);
+            assert!(matches!(fast.try_next(), Some(WatchItem::Event(_))));
+        }
+        assert_eq!(slow.try_next(), Some(WatchItem::Lagged));
+        hub.publish([(WatchOp::Put, &b"k4"[..])]);
+        assert_eq!(slow.try_next(), Some(WatchItem::Lagged));
+
+        // closing ends each watcher, once its changes are read
+        hub.publish([(WatchOp::Put, &b"k5"[..])]);
+        hub.close();
+        assert_eq!(fast.try_next(), event(4, WatchOp::Put, b"k4"));
+        assert_eq!(fast.try_next(), event(5, WatchOp::Put, b"k5"));
+        assert_eq!(fast.try_next(), Some(WatchItem::Closed));
+        drop(slow);
+        assert_eq!(hub.watchers(), 1);
+    }
+
+    // records each write it is sent, as "op key=value result"
+    #[derive(Default)]
+    struct AuditRecorder(Mutex<Vec<String>>);
+
+    impl AuditSink for AuditRecorder {
+        fn record(&self, op: &AuditOp, result: &Result<bool, Error>) {
+            let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+            let op = match op {
+                AuditOp::Put { key, value } => format!("put {}={}", text(key), text(value)),
+                AuditOp::Del { key } => format!("del {}", text(key)),
+                AuditOp::Batch(ops) => format!("batch of {}", ops.len()),
+                AuditOp::Clear => String::from("clear"),
+            };
+            self.0.lock().unwrap().push(format!("{op} {result:?}"));
+        }
+    }
+
+    #[test]
+    fn test_audit() {
+        let audit = Arc::new(AuditRecorder::default());
+        let db_config = ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .key_case(KeyCase::LowercaseFold)
+            .max_keys(2)
+            .max_value_len(8)
+            .audit(Arc::clone(&audit) as Arc<dyn AuditSink>)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+
+        // writes are recorded with folded keys, and results, successful
+        // or not; reads are not recorded
+        assert_eq!(db.put(b"A", b"1"), Ok(true));
+        assert_eq!(db.get(b"a"), Ok(Some(b"1".to_vec())));
+        assert_eq!(db.del(b"nope"), Ok(false));
+        assert_eq!(db.rename(b"a", b"b", false), Ok(true));
+        assert_eq!(db.put(b"c", b"3"), Ok(true));
+        assert_eq!(db.put(b"d", b"4"), Err(Error::QuotaExceeded));
+
+        // a write refused by an outer layer never reaches the audit
+        assert_eq!(db.put(b"c", b"too long!"), Err(Error::TooLarge));
+        assert_eq!(db.clear(), Ok(true));
+
         assert_eq!(
-            String::from_utf8_lossy(&key_list.keys[2]),
-            String::from("2019/lame")
+            *audit.0.lock().unwrap(),
+            vec![
+                "put a=1 Ok(true)",
+                "del nope Ok(false)",
+                "batch of 2 Ok(true)",
+                "put c=3 Ok(true)",
+                "put d=4 Err(QuotaExceeded)",
+                "clear Ok(true)",
+            ]
         );
     }
+
+    #[test]
+    fn test_blocking_adapter() {
+        let db = BlockingAdapter::new(MemDb::new());
+
+        assert_eq!(block_on(db.put(b"a", b"1")), Ok(true));
+        assert_eq!(block_on(db.get(b"a")), Ok(Some(b"1".to_vec())));
+        let mut batch = Batch::default();
+        batch.insert(b"b", b"2");
+        batch.remove(b"a");
+        assert_eq!(block_on(db.apply_batch(&batch)), Ok(true));
+        let keys = block_on(db.iter_keys(IterOptions::default())).unwrap();
+        assert_eq!(keys.keys, vec![b"b".to_vec()]);
+        assert_eq!(block_on(db.stat()).unwrap().n_records, 1);
+        assert_eq!(block_on(db.flush()), Ok(()));
+
+        // operations run in the order called, not the order awaited
+        let put = db.put(b"c", b"3");
+        let get = db.get(b"c");
+        let del = db.del(b"c");
+        assert_eq!(block_on(del), Ok(true));
+        assert_eq!(block_on(get), Ok(Some(b"3".to_vec())));
+        assert_eq!(block_on(put), Ok(true));
+
+        // any other Db method, via run()
+        assert_eq!(block_on(db.run(|db| db.len())), Ok(Ok(1)));
+
+        // a panic stops the worker; later operations fail, not hang
+        let res = block_on(db.run(|_db| -> bool { panic!("job failed") }));
+        assert!(matches!(res, Err(Error::Backend(_))));
+        assert!(matches!(block_on(db.get(b"b")), Err(Error::Backend(_))));
+    }
 }
@@ -0,0 +1,198 @@
+use super::api;
+use super::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+/// Db operations returning futures, for embedders with an async
+/// runtime:  implemented by natively asynchronous (e.g. networked)
+/// backends, or by sync backends run off the caller's thread via
+/// BlockingAdapter.
+///
+/// Mirrors the core of api::Db:  get, put, del, batches, key listing,
+/// stat and flush; BlockingAdapter::run() reaches the rest.  Methods take
+/// &self, so that a caller may have several operations in flight at
+/// once; an implementation orders or runs them concurrently as its
+/// backend allows.  The futures are runtime-agnostic (std::future), and
+/// are Send, so that any executor may poll them.
+///
+/// A library interface only:  the kvdbd server neither opens nor serves
+/// an AsyncDb.  It opens every db as a sync api::Db, via api::Driver, and
+/// its handlers (futures 0.1, under actix-web 1.0) call it directly,
+/// under the server state lock.
+pub trait AsyncDb {
+    fn get(&self, key: &[u8]) -> impl Future<Output = Result<Option<Vec<u8>>, Error>> + Send;
+
+    fn put(&self, key: &[u8], val: &[u8]) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn del(&self, key: &[u8]) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn apply_batch(&self, batch: &api::Batch) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    fn iter_keys(
+        &self,
+        opts: api::IterOptions,
+    ) -> impl Future<Output = Result<api::KeyList, Error>> + Send;
+
+    fn stat(&self) -> impl Future<Output = Result<api::DbStat, Error>> + Send;
+
+    fn flush(&self) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+type Job<T> = Box<dyn FnOnce(&mut T) + Send>;
+
+// result of one job, set by the worker; closed if the job was dropped
+// without running, i.e. the worker has stopped
+struct Slot<R> {
+    result: Option<R>,
+    closed: bool,
+    waker: Option<Waker>,
+}
+
+/// Future of a BlockingAdapter job's result.  Resolves to an error if the
+/// worker stopped before running the job, e.g. after a panic in an
+/// earlier job.
+pub struct Pending<R> {
+    slot: Arc<Mutex<Slot<R>>>,
+}
+
+impl<R> Future for Pending<R> {
+    type Output = Result<R, Error>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let mut slot = match self.slot.lock() {
+            Ok(slot) => slot,
+            Err(_poisoned) => return Poll::Ready(Err(api::ERR_LOCK_POISONED)),
+        };
+        if let Some(res) = slot.result.take() {
+            return Poll::Ready(Ok(res));
+        }
+        if slot.closed {
+            return Poll::Ready(Err(Error::Backend(String::from("db worker stopped"))));
+        }
+        slot.waker = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+// worker's end of a Pending; closes the slot when dropped, run or not
+struct Completion<R> {
+    slot: Arc<Mutex<Slot<R>>>,
+}
+
+impl<R> Completion<R> {
+    fn complete(self, res: R) {
+        if let Ok(mut slot) = self.slot.lock() {
+            slot.result = Some(res);
+        }
+    }
+}
+
+impl<R> Drop for Completion<R> {
+    fn drop(&mut self) {
+        if let Ok(mut slot) = self.slot.lock() {
+            slot.closed = true;
+            if let Some(waker) = slot.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+}
+
+/// AsyncDb over a sync api::Db, so that existing backends and layers may
+/// be used where an AsyncDb is expected.
+///
+/// The db is moved to a worker thread of its own, which runs each
+/// operation in turn, in the order called; the caller's thread never
+/// blocks on the db.  Operations on one adapter are thus serialized, as
+/// they are for a db behind a Mutex; use one adapter per db.  The worker
+/// exits, dropping the db, once the adapter is dropped and its queued
+/// operations have run.  A panic in an operation stops the worker, and
+/// every later operation fails.
+pub struct BlockingAdapter<T: api::Db + Send + 'static> {
+    jobs: Sender<Job<T>>,
+}
+
+impl<T: api::Db + Send + 'static> BlockingAdapter<T> {
+    pub fn new(db: T) -> BlockingAdapter<T> {
+        let (jobs, queue) = channel::<Job<T>>();
+        thread::spawn(move || {
+            let mut db = db;
+            for job in queue {
+                job(&mut db);
+            }
+        });
+        BlockingAdapter { jobs }
+    }
+
+    /// Run f on the worker thread, with the db, e.g. for api::Db methods
+    /// not in AsyncDb.
+    pub fn run<R, F>(&self, f: F) -> Pending<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut T) -> R + Send + 'static,
+    {
+        let slot = Arc::new(Mutex::new(Slot {
+            result: None,
+            closed: false,
+            waker: None,
+        }));
+        let completion = Completion {
+            slot: Arc::clone(&slot),
+        };
+        // if the worker has stopped, the job (and completion) is dropped
+        let _res = self.jobs.send(Box::new(move |db: &mut T| {
+            completion.complete(f(db));
+        }));
+        Pending { slot }
+    }
+
+    fn call<R, F>(&self, f: F) -> impl Future<Output = Result<R, Error>> + Send
+    where
+        R: Send + 'static,
+        F: FnOnce(&mut T) -> Result<R, Error> + Send + 'static,
+    {
+        let pending = self.run(f);
+        async move { pending.await? }
+    }
+}
+
+impl<T: api::Db + Send + 'static> AsyncDb for BlockingAdapter<T> {
+    fn get(&self, key: &[u8]) -> impl Future<Output = Result<Option<Vec<u8>>, Error>> + Send {
+        let key = key.to_vec();
+        self.call(move |db| db.get(&key))
+    }
+
+    fn put(&self, key: &[u8], val: &[u8]) -> impl Future<Output = Result<bool, Error>> + Send {
+        let (key, val) = (key.to_vec(), val.to_vec());
+        self.call(move |db| db.put(&key, &val))
+    }
+
+    fn del(&self, key: &[u8]) -> impl Future<Output = Result<bool, Error>> + Send {
+        let key = key.to_vec();
+        self.call(move |db| db.del(&key))
+    }
+
+    fn apply_batch(&self, batch: &api::Batch) -> impl Future<Output = Result<bool, Error>> + Send {
+        let batch = batch.clone();
+        self.call(move |db| db.apply_batch(&batch))
+    }
+
+    fn iter_keys(
+        &self,
+        opts: api::IterOptions,
+    ) -> impl Future<Output = Result<api::KeyList, Error>> + Send {
+        self.call(move |db| db.iter_keys(opts))
+    }
+
+    fn stat(&self) -> impl Future<Output = Result<api::DbStat, Error>> + Send {
+        self.call(|db| db.stat())
+    }
+
+    fn flush(&self) -> impl Future<Output = Result<(), Error>> + Send {
+        self.call(|db| db.flush())
+    }
+}
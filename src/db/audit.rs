@@ -0,0 +1,190 @@
+use super::api;
+use super::Error;
+use std::sync::Arc;
+
+/// One write, as recorded by AuditDb.
+pub enum AuditOp<'a> {
+    Put { key: &'a [u8], value: &'a [u8] },
+    Del { key: &'a [u8] },
+    Batch(&'a [api::Mutation]),
+    Clear,
+}
+
+/// Receiver of the writes made to an AuditDb, e.g. an audit log file.
+pub trait AuditSink: Send + Sync {
+    /// Record op, once applied (or failed) with result.
+    fn record(&self, op: &AuditOp, result: &Result<bool, Error>);
+}
+
+/// Db layer which records each write to the db, once applied, with its
+/// result, to an AuditSink:  each put, del, apply_batch and clear, and
+/// each write made of them (renames, prefix deletes and so on record the
+/// puts, deletes and batches they make).
+///
+/// A write which fails is recorded with its error; one refused by an
+/// outer layer, before reaching this one (e.g. a value over
+/// max_value_len), is not.  Records removed by an expiry sweep are not
+/// recorded:  the backend removes them unseen.
+pub struct AuditDb<T: api::Db> {
+    inner: T,
+    sink: Arc<dyn AuditSink>,
+}
+
+impl<T: api::Db> AuditDb<T> {
+    pub fn new(inner: T, sink: Arc<dyn AuditSink>) -> AuditDb<T> {
+        AuditDb { inner, sink }
+    }
+}
+
+impl<T: api::Db> api::Db for AuditDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        let res = self.inner.apply_batch(batch);
+        self.sink.record(&AuditOp::Batch(&batch.ops), &res);
+        res
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        let res = self.inner.clear();
+        self.sink.record(&AuditOp::Clear, &res);
+        res
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let res = self.inner.del(key);
+        self.sink.record(&AuditOp::Del { key }, &res);
+        res
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let res = self.inner.put(key, val);
+        self.sink.record(&AuditOp::Put { key, value: val }, &res);
+        res
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.inner.get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    // recorded as a put; the expiry is not
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        let res = self.inner.put_expire_at(key, val, unix_ms);
+        self.sink.record(&AuditOp::Put { key, value: val }, &res);
+        res
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.inner.iter_entries(opts, max_bytes)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.snapshot_get(id, key)
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.snapshot_iter_keys(id, start)
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.inner.create_index(name, json_path)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        self.inner.index_lookup(name, value)
+    }
+
+    fn degraded(&self) -> bool {
+        self.inner.degraded()
+    }
+
+    fn reset_degraded(&mut self) {
+        self.inner.reset_degraded()
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,300 @@
+use super::api;
+use super::Error;
+
+// reserved key, storing the next sequence number and oldest retained one
+pub const CHANGE_SEQ_KEY: &[u8] = b"\x00kvdbd:change_seq";
+
+// prefix of reserved change log keys, followed by zero-padded sequence number
+pub const CHANGE_LOG_PREFIX: &[u8] = b"\x00kvdbd:change:";
+
+// width of change log sequence numbers; zero-padded so keys sort in order
+const SEQ_KEY_WIDTH: usize = 20;
+
+// common prefix of all change log keys, hidden from iteration and stat
+const RESERVED_PREFIX: &[u8] = b"\x00kvdbd:change";
+
+// op bytes, prefixing the changed key in each change log record
+const OP_PUT: u8 = b'p';
+const OP_DEL: u8 = b'd';
+
+fn seq_key(seq: u64) -> Vec<u8> {
+    let mut key = CHANGE_LOG_PREFIX.to_vec();
+    key.extend_from_slice(format!("{seq:0SEQ_KEY_WIDTH$}").as_bytes());
+    key
+}
+
+fn encode_seqs(next_seq: u64, min_seq: u64) -> Vec<u8> {
+    let mut val = next_seq.to_be_bytes().to_vec();
+    val.extend_from_slice(&min_seq.to_be_bytes());
+    val
+}
+
+fn decode_seqs(val: &[u8]) -> Result<(u64, u64), Error> {
+    if val.len() != 16 {
+        return Err(Error::Corruption(String::from(
+            "invalid change log sequence",
+        )));
+    }
+    let mut next_bytes = [0u8; 8];
+    let mut min_bytes = [0u8; 8];
+    next_bytes.copy_from_slice(&val[..8]);
+    min_bytes.copy_from_slice(&val[8..]);
+    Ok((
+        u64::from_be_bytes(next_bytes),
+        u64::from_be_bytes(min_bytes),
+    ))
+}
+
+/// Db layer which records each put and delete in a bounded change log,
+/// stored within the db itself, for incremental sync via changes_since().
+///
+/// Each write is committed in one batch with its change log record, so
+/// the log never misses, or invents, a change.  Records store the op and
+/// key, not the value; replaying a put reads the key's current value.
+/// Only the newest `retain` changes are kept; older records are removed
+/// as new ones are written.  Change log keys are hidden from iter_keys()
+/// and stat().
+pub struct ChangeLogDb<T: api::Db> {
+    inner: T,
+    retain: u64,
+    next_seq: u64,      // sequence number of the next change; first is 1
+    min_seq: u64,       // oldest retained sequence number
+    have_seq_key: bool, // CHANGE_SEQ_KEY is stored
+}
+
+impl<T: api::Db> ChangeLogDb<T> {
+    pub fn new(inner: T, retain: u64) -> Result<ChangeLogDb<T>, Error> {
+        let seq_val = inner.get(CHANGE_SEQ_KEY)?;
+        let (next_seq, min_seq) = match &seq_val {
+            None => (1, 1),
+            Some(v) => decode_seqs(v)?,
+        };
+
+        Ok(ChangeLogDb {
+            inner,
+            retain: retain.max(1),
+            next_seq,
+            min_seq,
+            have_seq_key: seq_val.is_some(),
+        })
+    }
+
+    /// Apply batch, appending a change log record for each of its
+    /// mutations, and trimming the log to its retention limit.
+    fn apply_logged(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        // trimming may remove a record this batch inserts, so the logged
+        // batch is always last-wins; check the caller's policy first
+        batch.check()?;
+        let mut logged = api::Batch {
+            ops: batch.ops.clone(),
+            duplicate_policy: api::DuplicatePolicy::LastWins,
+            guard: batch.guard.clone(),
+        };
+
+        let mut next_seq = self.next_seq;
+        for mutation in &batch.ops {
+            let mut record = vec![match mutation.op {
+                api::MutationOp::Insert => OP_PUT,
+                api::MutationOp::Remove => OP_DEL,
+            }];
+            record.extend_from_slice(&mutation.key);
+            logged.insert(&seq_key(next_seq), &record);
+            next_seq += 1;
+        }
+
+        let mut min_seq = self.min_seq;
+        while next_seq - min_seq > self.retain {
+            logged.remove(&seq_key(min_seq));
+            min_seq += 1;
+        }
+        logged.insert(CHANGE_SEQ_KEY, &encode_seqs(next_seq, min_seq));
+
+        let res = self.inner.apply_batch(&logged)?;
+        self.next_seq = next_seq;
+        self.min_seq = min_seq;
+        self.have_seq_key = true;
+
+        Ok(res)
+    }
+}
+
+impl<T: api::Db> api::Db for ChangeLogDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        self.apply_logged(batch)
+    }
+
+    // history does not survive clear.  clear consumes a sequence number,
+    // without a record, so that every syncing client sees its position as
+    // lost, and resyncs
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()?;
+        self.next_seq += 1;
+        self.min_seq = self.next_seq;
+        self.have_seq_key = true;
+        self.inner
+            .put(CHANGE_SEQ_KEY, &encode_seqs(self.next_seq, self.min_seq))
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        if self.inner.get(key)?.is_none() {
+            return Ok(false);
+        }
+
+        let mut batch = api::Batch::default();
+        batch.remove(key);
+        self.apply_logged(&batch)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let mut batch = api::Batch::default();
+        batch.insert(key, val);
+        self.apply_logged(&batch)
+    }
+
+    // skip change log keys; continue past any page holding only those
+    fn iter_keys(&self, mut opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        loop {
+            let mut key_list = self.inner.iter_keys(opts.clone())?;
+            let last_key = key_list.keys.last().cloned();
+            key_list
+                .keys
+                .retain(|key| !key.starts_with(RESERVED_PREFIX));
+
+            let last_key = match last_key {
+                Some(key) if key_list.keys.is_empty() && !key_list.list_end => key,
+                _ => return Ok(key_list),
+            };
+            opts = opts.continue_after(&last_key);
+        }
+    }
+
+    // a prefix which change log keys may match is listed, skipping them
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        if RESERVED_PREFIX.starts_with(prefix) || prefix.starts_with(RESERVED_PREFIX) {
+            return Ok(!self.iter_keys_prefix(prefix, None)?.keys.is_empty());
+        }
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        let mut st = self.inner.stat()?;
+        let n_reserved = (self.next_seq - self.min_seq) + (self.have_seq_key as u64);
+        st.n_records = st.n_records.saturating_sub(n_reserved);
+        Ok(st)
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.inner.get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        let mut change_list = api::ChangeList {
+            changes: Vec::new(),
+            last_seq: self.next_seq - 1,
+            list_end: true,
+            needs_resync: false,
+        };
+
+        // changes after seq were trimmed or lost to clear; or seq is from
+        // another db's history
+        if seq.saturating_add(1) < self.min_seq || seq > change_list.last_seq {
+            change_list.needs_resync = true;
+            return Ok(change_list);
+        }
+
+        // sequence numbers are dense, so read records directly
+        let end_seq = self
+            .next_seq
+            .min(seq + 1 + self.inner.max_iter_keys() as u64);
+        for change_seq in (seq + 1)..end_seq {
+            let record = match self.inner.get(&seq_key(change_seq))? {
+                None => return Err(Error::Corruption(String::from("missing change log record"))),
+                Some(v) => v,
+            };
+            let op = match record.first() {
+                Some(&OP_PUT) => api::MutationOp::Insert,
+                Some(&OP_DEL) => api::MutationOp::Remove,
+                _ => return Err(Error::Corruption(String::from("invalid change log record"))),
+            };
+            change_list.changes.push(api::Change {
+                seq: change_seq,
+                op,
+                key: record[1..].to_vec(),
+            });
+        }
+        change_list.list_end = end_seq == self.next_seq;
+
+        Ok(change_list)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    // scan via iter_keys() above, which skips change log keys
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        api::scan_glob_keys(self, pattern, start)
+    }
+
+    // less the size of any change log keys within the range
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        let n_bytes = self.inner.size_between(start, end)?;
+
+        let reserved_start = start.max(RESERVED_PREFIX);
+        let reserved_upper = api::prefix_upper_bound(RESERVED_PREFIX).unwrap();
+        let reserved_end = if end.is_empty() {
+            &reserved_upper[..]
+        } else {
+            end.min(&reserved_upper[..])
+        };
+        if reserved_start >= reserved_end {
+            return Ok(n_bytes);
+        }
+        let n_reserved = self.inner.size_between(reserved_start, reserved_end)?;
+        Ok(n_bytes.saturating_sub(n_reserved))
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
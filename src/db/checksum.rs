@@ -0,0 +1,154 @@
+use super::api;
+use super::Error;
+use flate2::Crc;
+
+// first byte of a checksummed value, naming its format:  the CRC32 of
+// the value, little-endian, then the value
+const CRC_VERSION: u8 = 1;
+
+const CRC_LEN: usize = 4;
+
+fn crc32(val: &[u8]) -> [u8; CRC_LEN] {
+    let mut crc = Crc::new();
+    crc.update(val);
+    crc.sum().to_le_bytes()
+}
+
+/// Db layer storing a CRC32 checksum with each value, and verifying it on
+/// every read, so that a value corrupted at rest, e.g. by a failing disk,
+/// fails with Error::Corruption, rather than returning bad bytes.
+/// Enabled per db by Config.verify_checksums.
+///
+/// A checksum detects accidental damage, not tampering:  anyone able to
+/// write the files can store a value with a matching checksum.  For
+/// that, see encrypt::EncryptedDb, whose authentication also detects
+/// corruption, making checksums redundant alongside it.
+///
+/// Innermost, directly above the backend, so that every stored value,
+/// change log and index records included, is checked, and the checksum
+/// covers values as stored, after any compression and encryption.  As
+/// in TransformDb, a batch guard is checked here, against the value
+/// without its checksum.  A value stored before checksums were enabled
+/// has none, and is reported as corrupt.
+pub struct ChecksumDb<T: api::Db> {
+    inner: T,
+}
+
+impl<T: api::Db> ChecksumDb<T> {
+    pub fn new(inner: T) -> ChecksumDb<T> {
+        ChecksumDb { inner }
+    }
+
+    fn seal(val: &[u8]) -> Vec<u8> {
+        let mut stored = Vec::with_capacity(1 + CRC_LEN + val.len());
+        stored.push(CRC_VERSION);
+        stored.extend_from_slice(&crc32(val));
+        stored.extend_from_slice(val);
+        stored
+    }
+
+    fn verify(mut stored: Vec<u8>) -> Result<Vec<u8>, Error> {
+        if stored.len() < 1 + CRC_LEN || stored[0] != CRC_VERSION {
+            return Err(Error::Corruption(String::from("value checksum missing")));
+        }
+        if stored[1..1 + CRC_LEN] != crc32(&stored[1 + CRC_LEN..]) {
+            return Err(Error::Corruption(String::from("value checksum mismatch")));
+        }
+        stored.drain(..1 + CRC_LEN);
+        Ok(stored)
+    }
+}
+
+impl<T: api::Db> api::Db for ChecksumDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        batch.check()?;
+
+        // &mut self:  no other write to this db between check and apply
+        if let Some(key) = batch.guard_key() {
+            batch.check_guard(self.get(key)?.as_deref())?;
+        }
+        let sealed = api::Batch {
+            ops: batch
+                .ops
+                .iter()
+                .map(|dbm| api::Mutation {
+                    op: dbm.op.clone(),
+                    key: dbm.key.clone(),
+                    value: dbm.value.as_deref().map(ChecksumDb::<T>::seal),
+                })
+                .collect(),
+            duplicate_policy: batch.duplicate_policy,
+            guard: None,
+        };
+        self.inner.apply_batch(&sealed)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.inner.get(key)? {
+            None => Ok(None),
+            Some(stored) => Ok(Some(ChecksumDb::<T>::verify(stored)?)),
+        }
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.inner.put(key, &ChecksumDb::<T>::seal(val))
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        self.inner
+            .put_expire_at(key, &ChecksumDb::<T>::seal(val), unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    // sizes as stored, with checksums
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,89 @@
+use super::api;
+use super::transform::{TransformDb, ValueTransform};
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+// prefix of a stored value which is compressed, followed by the value as
+// a gzip stream (RFC 1952)
+const GZIP_MAGIC: &[u8] = b"\xffkvdbd:gzip\x00";
+
+// values shorter than this are stored as-is:  the header and gzip framing
+// would outweigh any saving
+const MIN_COMPRESS_LEN: usize = 64;
+
+/// ValueTransform gzip-compressing values at rest.
+///
+/// A compressed value is stored with a small header, GZIP_MAGIC; a value
+/// which compression would not shrink is stored as-is.  Reads decompress
+/// values with the header, and return others unchanged, so that values
+/// stored before compression was enabled are read back as written.  A
+/// value stored beforehand which happens to begin with the header, and
+/// is not a valid gzip stream after it, is also returned unchanged.
+#[derive(Default)]
+pub struct GzipTransform {
+    level: Compression, // default:  flate2's, level 6
+}
+
+impl GzipTransform {
+    pub fn new() -> GzipTransform {
+        GzipTransform::default()
+    }
+
+    /// Transform compressing at level, from 0 (none) to 9 (best).
+    pub fn with_level(level: u32) -> GzipTransform {
+        GzipTransform {
+            level: Compression::new(level.min(9)),
+        }
+    }
+
+    fn compress(&self, val: &[u8]) -> Vec<u8> {
+        let mut enc = GzEncoder::new(GZIP_MAGIC.to_vec(), self.level);
+        enc.write_all(val).unwrap(); // writes to a Vec cannot fail
+        enc.finish().unwrap()
+    }
+
+    fn decompress(gz: &[u8]) -> Option<Vec<u8>> {
+        let mut val = Vec::with_capacity(gz.len() * 4);
+        GzDecoder::new(gz).read_to_end(&mut val).ok()?;
+        Some(val)
+    }
+}
+
+impl ValueTransform for GzipTransform {
+    fn on_write(&self, val: &[u8]) -> Vec<u8> {
+        if val.len() >= MIN_COMPRESS_LEN {
+            let stored = self.compress(val);
+            if stored.len() < val.len() {
+                return stored;
+            }
+        }
+
+        // stored as-is, unless it would be read as compressed
+        if val.starts_with(GZIP_MAGIC) {
+            return self.compress(val);
+        }
+        val.to_vec()
+    }
+
+    fn on_read(&self, stored: &[u8]) -> Vec<u8> {
+        if let Some(gz) = stored.strip_prefix(GZIP_MAGIC) {
+            if let Some(val) = GzipTransform::decompress(gz) {
+                return val;
+            }
+        }
+        stored.to_vec()
+    }
+}
+
+/// Db layer gzip-compressing values at rest, leaving keys untouched:  a
+/// TransformDb with a GzipTransform.  Enabled per db by
+/// Config.compress_values.
+pub type CompressedDb<T> = TransformDb<T>;
+
+/// inner, with values compressed at rest per GzipTransform.
+pub fn compressed_db<T: api::Db>(inner: T) -> CompressedDb<T> {
+    TransformDb::new(inner, vec![Arc::new(GzipTransform::new())])
+}
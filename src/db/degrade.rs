@@ -0,0 +1,261 @@
+use super::api;
+use super::Error;
+use log::warn;
+use std::time::Instant;
+
+// errors refusing a write for its content or the db's settings, rather
+// than reporting a failing backend
+fn is_write_failure(e: &Error) -> bool {
+    !matches!(
+        e,
+        Error::QuotaExceeded
+            | Error::DuplicateKey
+            | Error::GuardMismatch
+            | Error::HashConflict
+            | Error::ExpiryUnsupported
+            | Error::NoIndexes
+            | Error::NotFound
+            | Error::IndexExists
+            | Error::InvalidIndex
+            | Error::ReadOnly
+            | Error::InvalidKey
+            | Error::InvalidInput(_)
+            | Error::MetaUnsupported
+            | Error::MetaTooLarge
+            | Error::TooLarge
+    )
+}
+
+/// Db layer which degrades the db to read-only after repeated write
+/// failures, e.g. I/O errors from a failing disk.
+///
+/// Each write failing in a backend counts one failure; any successful
+/// write resets the count.  Once `threshold` writes in a row have
+/// failed, further writes fail with api::ERR_DEGRADED, without reaching
+/// the backend, while reads continue.  Writes refused for their content,
+/// such as by a quota, are not failures.  The db stays degraded until
+/// reset_degraded().
+pub struct DegradeDb<T: api::Db> {
+    inner: T,
+    threshold: u64,
+    n_failures: u64, // consecutive failed writes
+    degraded: bool,
+}
+
+impl<T: api::Db> DegradeDb<T> {
+    pub fn new(inner: T, threshold: u64) -> DegradeDb<T> {
+        DegradeDb {
+            inner,
+            threshold: threshold.max(1),
+            n_failures: 0,
+            degraded: false,
+        }
+    }
+
+    fn write<R>(&mut self, f: impl FnOnce(&mut T) -> Result<R, Error>) -> Result<R, Error> {
+        if self.degraded {
+            return Err(api::ERR_DEGRADED);
+        }
+
+        let res = f(&mut self.inner);
+        match &res {
+            Ok(_) => self.n_failures = 0,
+            Err(e) if is_write_failure(e) => {
+                self.n_failures += 1;
+                if self.n_failures >= self.threshold {
+                    warn!(
+                        "db degraded to read-only after {} failed writes: {}",
+                        self.n_failures, e
+                    );
+                    self.degraded = true;
+                }
+            }
+            Err(_e) => {}
+        }
+        res
+    }
+}
+
+impl<T: api::Db> api::Db for DegradeDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        self.write(|db| db.apply_batch(batch))
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.write(|db| db.clear())
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.write(|db| db.del(key))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.write(|db| db.put(key, val))
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.inner.get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn apply_batch_until(&mut self, batch: &api::Batch, deadline: Instant) -> Result<usize, Error> {
+        self.write(|db| db.apply_batch_until(batch, deadline))
+    }
+
+    fn put_next(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        self.write(|db| db.put_next(val))
+    }
+
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> Result<bool, Error> {
+        self.write(|db| db.rename(from, to, overwrite))
+    }
+
+    fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<(), Error> {
+        self.write(|db| db.swap(key_a, key_b))
+    }
+
+    fn del_if_empty(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.write(|db| db.del_if_empty(key))
+    }
+
+    fn del_many(&mut self, keys: &[Vec<u8>]) -> Result<u64, Error> {
+        self.write(|db| db.del_many(keys))
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        self.write(|db| db.compare_and_swap(key, expected, new))
+    }
+
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        self.write(|db| db.get_or_init(key, init))
+    }
+
+    fn add_sharded(&mut self, key: &[u8], delta: i64, shards: u32) -> Result<bool, Error> {
+        self.write(|db| db.add_sharded(key, delta, shards))
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        self.write(|db| db.put_expire_at(key, val, unix_ms))
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.write(|db| db.touch(key, ttl_secs))
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.write(|db| db.sweep_expired(max_keys))
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.inner.iter_entries(opts, max_bytes)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.snapshot_get(id, key)
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.snapshot_iter_keys(id, start)
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.write(|db| db.create_index(name, json_path))
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        self.inner.index_lookup(name, value)
+    }
+
+    fn degraded(&self) -> bool {
+        self.degraded
+    }
+
+    fn reset_degraded(&mut self) {
+        self.n_failures = 0;
+        self.degraded = false;
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,201 @@
+use super::api;
+use super::Error;
+use ring::aead::{self, Aad, Nonce, OpeningKey, SealingKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+/// Bytes of an encryption key:  AES-256.
+pub const ENCRYPTION_KEY_LEN: usize = 32;
+
+// first byte of an encrypted value, naming its format:  the nonce, then
+// the AES-256-GCM ciphertext and tag
+const ENC_VERSION: u8 = 1;
+
+const TAG_LEN: usize = 16;
+
+/// Db layer encrypting values at rest, with AES-256-GCM, under the key
+/// given by Config.encryption_key.
+///
+/// Each value is stored with a random nonce of its own, and a tag
+/// authenticating it, along with the key it is stored under:  a value
+/// which is altered, copied to another key, or read with another
+/// encryption key fails with Error::Corruption, rather than returning
+/// garbage.  Random nonces keep the key safe for some billions of
+/// writes; rotate it (copy the db to one with a new key) well before.
+///
+/// Keys are not encrypted, so that iter_keys(), prefix and range scans,
+/// and the key order they return, work as without encryption:  anyone
+/// with access to the files can read the keys, and each value's size and
+/// expiry.  Keep secrets out of keys.
+///
+/// Innermost, but for checksum::ChecksumDb, so that values are encrypted
+/// after any compression or other ValueTransform, and every stored
+/// value, change log and index records included, is encrypted.  As in
+/// TransformDb, a batch guard is checked here, against the plaintext.  A
+/// value stored before encryption was enabled does not decrypt, and is
+/// reported as corrupt.
+pub struct EncryptedDb<T: api::Db> {
+    inner: T,
+    sealing_key: SealingKey,
+    opening_key: OpeningKey,
+    rng: SystemRandom,
+}
+
+impl<T: api::Db> EncryptedDb<T> {
+    /// inner, encrypting with key, of ENCRYPTION_KEY_LEN bytes.
+    pub fn new(inner: T, key: &[u8]) -> Result<EncryptedDb<T>, Error> {
+        if key.len() != ENCRYPTION_KEY_LEN {
+            return Err(Error::InvalidInput(format!(
+                "encryption key must be {ENCRYPTION_KEY_LEN} bytes"
+            )));
+        }
+
+        // of the right length, so accepted
+        Ok(EncryptedDb {
+            inner,
+            sealing_key: SealingKey::new(&AES_256_GCM, key).unwrap(),
+            opening_key: OpeningKey::new(&AES_256_GCM, key).unwrap(),
+            rng: SystemRandom::new(),
+        })
+    }
+
+    fn encrypt(&self, key: &[u8], val: &[u8]) -> Result<Vec<u8>, Error> {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_e| Error::Backend(String::from("random nonce unavailable")))?;
+
+        let mut stored = Vec::with_capacity(1 + NONCE_LEN + val.len() + TAG_LEN);
+        stored.push(ENC_VERSION);
+        stored.extend_from_slice(&nonce);
+        stored.extend_from_slice(val);
+        stored.extend_from_slice(&[0u8; TAG_LEN]);
+        let nonce = Nonce::assume_unique_for_key(nonce);
+        aead::seal_in_place(
+            &self.sealing_key,
+            nonce,
+            Aad::from(key),
+            &mut stored[1 + NONCE_LEN..],
+            TAG_LEN,
+        )
+        .map_err(|_e| Error::InvalidInput(String::from("value too large to encrypt")))?;
+        Ok(stored)
+    }
+
+    fn decrypt(&self, key: &[u8], mut stored: Vec<u8>) -> Result<Vec<u8>, Error> {
+        let failed = || Error::Corruption(String::from("value decryption failed"));
+        if stored.len() < 1 + NONCE_LEN + TAG_LEN || stored[0] != ENC_VERSION {
+            return Err(failed());
+        }
+        let nonce =
+            Nonce::try_assume_unique_for_key(&stored[1..1 + NONCE_LEN]).map_err(|_e| failed())?;
+        let val_len = aead::open_in_place(
+            &self.opening_key,
+            nonce,
+            Aad::from(key),
+            1 + NONCE_LEN,
+            &mut stored,
+        )
+        .map_err(|_e| failed())?
+        .len();
+        stored.truncate(val_len);
+        Ok(stored)
+    }
+}
+
+impl<T: api::Db> api::Db for EncryptedDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        batch.check()?;
+
+        // &mut self:  no other write to this db between check and apply
+        if let Some(key) = batch.guard_key() {
+            batch.check_guard(self.get(key)?.as_deref())?;
+        }
+        let mut ops = Vec::with_capacity(batch.ops.len());
+        for dbm in &batch.ops {
+            ops.push(api::Mutation {
+                op: dbm.op.clone(),
+                key: dbm.key.clone(),
+                value: match &dbm.value {
+                    None => None,
+                    Some(val) => Some(self.encrypt(&dbm.key, val)?),
+                },
+            });
+        }
+        let encrypted = api::Batch {
+            ops,
+            duplicate_policy: batch.duplicate_policy,
+            guard: None,
+        };
+        self.inner.apply_batch(&encrypted)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.inner.get(key)? {
+            None => Ok(None),
+            Some(stored) => Ok(Some(self.decrypt(key, stored)?)),
+        }
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let stored = self.encrypt(key, val)?;
+        self.inner.put(key, &stored)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        let stored = self.encrypt(key, val)?;
+        self.inner.put_expire_at(key, &stored, unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    // sizes as stored, encrypted
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+
+/// Error returned by Db, Driver and Db layer operations.
+///
+/// Variants distinguish the failures callers act on (a refused write, a
+/// stale guard, an unsupported feature) from backend failures, which
+/// carry a description.  A missing key is not an error:  reads return
+/// Ok(None), or Ok(false).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    NotFound,             // named item absent, e.g. a snapshot id
+    ReadOnly,             // write to a db opened read-only
+    InvalidKey,           // key the backend cannot store, e.g. too long
+    Corruption(String),   // stored data which does not decode
+    Backend(String),      // storage engine or I/O failure
+    QuotaExceeded,        // write would exceed the db's quota
+    DuplicateKey,         // batch mutates a key twice, under Reject
+    GuardMismatch,        // batch guard key not the expected value
+    HashConflict,         // content-hash key holding other content
+    ExpiryUnsupported,    // later expiry, on a backend without expiry
+    DbLocked,             // db held open by another process
+    NoIndexes,            // index op, on a db without the index layer
+    IndexExists,          // index name registered with another json_path
+    InvalidIndex,         // invalid index name or json_path
+    Degraded,             // write to a db degraded to read-only
+    LockPoisoned,         // lock poisoned by a panic
+    MetaUnsupported,      // metadata, on a db without the metadata layer
+    MetaTooLarge,         // metadata past MAX_META_ENTRIES or MAX_META_BYTES
+    ModTimeUnsupported,   // modification time, on a db not tracking them
+    VersionUnsupported,   // key version, on a db not tracking them
+    TooLarge,             // key or value past Config.max_key_len or max_value_len
+    Timeout,              // op not complete within Config.op_timeout_ms
+    InvalidInput(String), // argument out of range, e.g. a shard count
+    NotEnabled(String),   // op of a feature the db was not configured with
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::NotFound => write!(f, "not found"),
+            Error::ReadOnly => write!(f, "db is read-only"),
+            Error::InvalidKey => write!(f, "invalid key"),
+            Error::Corruption(msg) => write!(f, "corrupt data: {msg}"),
+            Error::Backend(msg) => write!(f, "{msg}"),
+            Error::QuotaExceeded => write!(f, "quota exceeded"),
+            Error::DuplicateKey => write!(f, "duplicate key in batch"),
+            Error::GuardMismatch => write!(f, "batch guard mismatch"),
+            Error::HashConflict => write!(f, "hash key holds other content"),
+            Error::ExpiryUnsupported => write!(f, "key expiry not supported"),
+            Error::DbLocked => write!(f, "db locked by another process"),
+            Error::NoIndexes => write!(f, "indexes not enabled"),
+            Error::IndexExists => write!(f, "index exists with a different json_path"),
+            Error::InvalidIndex => write!(f, "invalid index name or json_path"),
+            Error::Degraded => write!(f, "db degraded to read-only"),
+            Error::LockPoisoned => write!(f, "lock poisoned"),
+            Error::MetaUnsupported => write!(f, "value metadata not enabled"),
+            Error::MetaTooLarge => write!(f, "value metadata too large"),
+            Error::ModTimeUnsupported => write!(f, "modification times not enabled"),
+            Error::VersionUnsupported => write!(f, "key versions not enabled"),
+            Error::TooLarge => write!(f, "key or value too large"),
+            Error::Timeout => write!(f, "db op timed out"),
+            Error::InvalidInput(msg) => write!(f, "invalid input: {msg}"),
+            Error::NotEnabled(feature) => write!(f, "{feature} not enabled"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Error::ReadOnly.to_string(), "db is read-only");
+        assert_eq!(
+            Error::Backend(String::from("put failed")).to_string(),
+            "put failed"
+        );
+        assert_eq!(
+            Error::NotEnabled(String::from("snapshots")).to_string(),
+            "snapshots not enabled"
+        );
+        assert_eq!(
+            Error::Corruption(String::from("invalid counter")).to_string(),
+            "corrupt data: invalid counter"
+        );
+    }
+}
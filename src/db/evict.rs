@@ -0,0 +1,381 @@
+use super::api;
+use super::api::DbExt;
+use super::index;
+use super::Error;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::ControlFlow;
+use std::sync::Mutex;
+
+// eviction order of tracked keys:  each key's tick, and the keys by
+// tick, lowest (next evicted) first
+#[derive(Default)]
+struct EvictOrder {
+    next_tick: u64,
+    ticks: HashMap<Vec<u8>, u64>,
+    queue: BTreeMap<u64, Vec<u8>>,
+}
+
+impl EvictOrder {
+    // move key to the back of the queue, adding it if untracked
+    fn bump(&mut self, key: &[u8]) {
+        let tick = self.next_tick;
+        self.next_tick += 1;
+        if let Some(old_tick) = self.ticks.insert(key.to_vec(), tick) {
+            self.queue.remove(&old_tick);
+        }
+        self.queue.insert(tick, key.to_vec());
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        if let Some(tick) = self.ticks.remove(key) {
+            self.queue.remove(&tick);
+        }
+    }
+
+    // oldest key not in exclude
+    fn oldest(&self, exclude: &HashSet<&[u8]>) -> Option<Vec<u8>> {
+        self.queue
+            .values()
+            .find(|key| !exclude.contains(key.as_slice()))
+            .cloned()
+    }
+}
+
+// keys never evicted:  index entries, removed with their primary key
+fn evictable(key: &[u8]) -> bool {
+    !key.starts_with(index::INDEX_KEY_PREFIX)
+}
+
+// stored size of one record, as counted by the quota
+fn record_size(key: &[u8], val_len: usize) -> u64 {
+    (key.len() + val_len) as u64
+}
+
+/// Db layer which, when a write would exceed the db's quota, evicts keys
+/// to make room, rather than failing the write.
+///
+/// Keys are evicted least recently used first (api::Eviction::Lru), or
+/// least recently inserted first (api::Eviction::Fifo), one at a time by
+/// del(), until the write fits.  Keys written by the write itself, and a
+/// batch's guard key, are never evicted for it.  A write which could not
+/// fit in an empty db fails with api::ERR_QUOTA_EXCEEDED, evicting
+/// nothing.
+///
+/// Order is approximate:  it is kept in memory only, one entry per key
+/// (the key, plus about 64 bytes), and is seeded in key order when the
+/// db is opened.  Reads by get(), get_ref() and get_multi() count as use;
+/// scans, counter reads and snapshot reads do not.
+pub struct EvictDb<T: api::Db> {
+    inner: T,
+    policy: api::Eviction,
+    order: Mutex<EvictOrder>,
+}
+
+impl<T: api::Db> EvictDb<T> {
+    pub fn new(inner: T, policy: api::Eviction) -> Result<EvictDb<T>, Error> {
+        let mut order = EvictOrder::default();
+        inner.for_each_key(None, |key| {
+            if evictable(key) {
+                order.bump(key);
+            }
+            ControlFlow::Continue(())
+        })?;
+
+        Ok(EvictDb {
+            inner,
+            policy,
+            order: Mutex::new(order),
+        })
+    }
+
+    fn order_mut(&mut self) -> &mut EvictOrder {
+        // order is advisory; after a panic, carry on with it as it is
+        self.order.get_mut().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // note a read of key, if it counts as use
+    fn note_read(&self, key: &[u8]) {
+        if self.policy != api::Eviction::Lru || !evictable(key) {
+            return;
+        }
+        if let Ok(mut order) = api::lock_shared(&self.order, |_order| true) {
+            if order.ticks.contains_key(key) {
+                order.bump(key);
+            }
+        }
+    }
+
+    fn note_insert(&mut self, key: &[u8]) {
+        if !evictable(key) {
+            return;
+        }
+        let fifo = self.policy == api::Eviction::Fifo;
+        let order = self.order_mut();
+        if !(fifo && order.ticks.contains_key(key)) {
+            order.bump(key);
+        }
+    }
+
+    fn note_batch(&mut self, batch: &api::Batch) {
+        for dbm in &batch.ops {
+            match dbm.op {
+                api::MutationOp::Insert => self.note_insert(&dbm.key),
+                api::MutationOp::Remove => self.order_mut().remove(&dbm.key),
+            }
+        }
+    }
+
+    // Ok, unless records, the records a write leaves in the db, could
+    // not fit within the quota even with every other key evicted
+    fn check_fits(&self, records: &HashMap<&[u8], usize>) -> Result<(), Error> {
+        let usage = match self.inner.quota_usage() {
+            None => return Ok(()),
+            Some(usage) => usage,
+        };
+        let n_keys = records.len() as u64;
+        let total_bytes: u64 = records
+            .iter()
+            .map(|(key, val_len)| record_size(key, *val_len))
+            .sum();
+        let over_keys = usage.max_keys.is_some_and(|max| n_keys > max);
+        let over_bytes = usage.max_total_bytes.is_some_and(|max| total_bytes > max);
+        if over_keys || over_bytes {
+            return Err(api::ERR_QUOTA_EXCEEDED);
+        }
+        Ok(())
+    }
+
+    /// Run write, evicting the oldest key not in exclude and retrying,
+    /// for as long as it fails for exceeding the quota.
+    fn evicting<R, F>(&mut self, exclude: &HashSet<&[u8]>, mut write: F) -> Result<R, Error>
+    where
+        F: FnMut(&mut T) -> Result<R, Error>,
+    {
+        loop {
+            match write(&mut self.inner) {
+                Err(api::ERR_QUOTA_EXCEEDED) => {}
+                res => return res,
+            }
+            let key = match self.order_mut().oldest(exclude) {
+                None => return Err(api::ERR_QUOTA_EXCEEDED),
+                Some(key) => key,
+            };
+            self.inner.del(&key)?;
+            self.order_mut().remove(&key);
+        }
+    }
+
+    fn put_evicting(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.check_fits(&HashMap::from([(key, val.len())]))?;
+        let exclude = HashSet::from([key]);
+        let res = self.evicting(&exclude, |inner| inner.put(key, val))?;
+        self.note_insert(key);
+        Ok(res)
+    }
+}
+
+impl<T: api::Db> api::Db for EvictDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        // refuse before evicting anything to make room
+        batch.check()?;
+
+        // records the batch leaves, by key, as of its last mutation of each
+        let mut records: HashMap<&[u8], Option<usize>> = HashMap::new();
+        for dbm in &batch.ops {
+            let val_len = dbm.value.as_ref().map(|val| val.len());
+            records.insert(dbm.key.as_slice(), val_len);
+        }
+        let mut exclude: HashSet<&[u8]> = records.keys().copied().collect();
+        if let Some(key) = batch.guard_key() {
+            exclude.insert(key);
+        }
+        let inserted = records
+            .into_iter()
+            .filter_map(|(key, val_len)| val_len.map(|len| (key, len)))
+            .collect();
+        self.check_fits(&inserted)?;
+
+        let res = self.evicting(&exclude, |inner| inner.apply_batch(batch))?;
+        self.note_batch(batch);
+        Ok(res)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        let res = self.inner.clear()?;
+        *self.order_mut() = EvictOrder::default();
+        Ok(res)
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let res = self.inner.del(key)?;
+        self.order_mut().remove(key);
+        Ok(res)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let val = self.inner.get(key)?;
+        if val.is_some() {
+            self.note_read(key);
+        }
+        Ok(val)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.put_evicting(key, val)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        let val = self.inner.get_ref(key)?;
+        if val.is_some() {
+            self.note_read(key);
+        }
+        Ok(val)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let vals = self.inner.get_multi(keys)?;
+        for (key, val) in keys.iter().zip(&vals) {
+            if val.is_some() {
+                self.note_read(key);
+            }
+        }
+        Ok(vals)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        self.check_fits(&HashMap::from([(key, val.len())]))?;
+        let exclude = HashSet::from([key]);
+        let res = self.evicting(&exclude, |inner| inner.put_expire_at(key, val, unix_ms))?;
+        self.note_insert(key);
+        Ok(res)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    // records removed by the backend are not seen here; forget any
+    // tracked key no longer present
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        let n_removed = self.inner.sweep_expired(max_keys)?;
+        if n_removed > 0 {
+            let mut present = HashSet::new();
+            self.inner.for_each_key(None, |key| {
+                present.insert(key.to_vec());
+                ControlFlow::Continue(())
+            })?;
+            let order = self.order_mut();
+            order.queue.retain(|_tick, key| present.contains(key));
+            order.ticks.retain(|key, _tick| present.contains(key));
+        }
+        Ok(n_removed)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.inner.iter_entries(opts, max_bytes)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.snapshot_get(id, key)
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.snapshot_iter_keys(id, start)
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.inner.create_index(name, json_path)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        self.inner.index_lookup(name, value)
+    }
+
+    fn degraded(&self) -> bool {
+        self.inner.degraded()
+    }
+
+    fn reset_degraded(&mut self) {
+        self.inner.reset_degraded()
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        let modified = self.inner.get_modified(key)?;
+        if modified.is_some() {
+            self.note_read(key);
+        }
+        Ok(modified)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        let versioned = self.inner.get_versioned(key)?;
+        if versioned.is_some() {
+            self.note_read(key);
+        }
+        Ok(versioned)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
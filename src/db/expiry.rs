@@ -0,0 +1,451 @@
+use super::api;
+use super::iter::DbIterator;
+use super::Error;
+use std::collections::{BTreeSet, HashMap};
+
+// prefix of reserved expiry index keys, followed by the expiry (u64,
+// big-endian, ms since the Unix epoch), then the expiring key
+pub const EXPIRY_PREFIX: &[u8] = b"\x00kvdbd:expiry:";
+
+const EXPIRY_LEN: usize = 8;
+
+fn expiry_key(unix_ms: u64, key: &[u8]) -> Vec<u8> {
+    let mut index_key = Vec::with_capacity(EXPIRY_PREFIX.len() + EXPIRY_LEN + key.len());
+    index_key.extend_from_slice(EXPIRY_PREFIX);
+    index_key.extend_from_slice(&unix_ms.to_be_bytes());
+    index_key.extend_from_slice(key);
+    index_key
+}
+
+// expiry and expiring key of an index key
+fn split_expiry_key(index_key: &[u8]) -> Result<(u64, Vec<u8>), Error> {
+    let header_len = EXPIRY_PREFIX.len() + EXPIRY_LEN;
+    if index_key.len() < header_len {
+        return Err(Error::Corruption(String::from("invalid expiry index key")));
+    }
+    let mut time_bytes = [0u8; EXPIRY_LEN];
+    time_bytes.copy_from_slice(&index_key[EXPIRY_PREFIX.len()..header_len]);
+    Ok((
+        u64::from_be_bytes(time_bytes),
+        index_key[header_len..].to_vec(),
+    ))
+}
+
+fn reserved(key: &[u8]) -> bool {
+    key.starts_with(EXPIRY_PREFIX)
+}
+
+/// Db layer recording key expiry, set by put_expire_at() and put_ttl(),
+/// and reset by touch():  a key is absent to every read once its expiry
+/// has passed.  wrap_db() adds it to every db.
+///
+/// Expiries are stored within the db itself, as an index of reserved
+/// keys ordered by (expiry, key), each committed in one batch with the
+/// write it belongs to:  one extra key written per expiring put, and
+/// one removed when the key's expiry is cleared or changed.  Any write
+/// of a key without an expiry (put(), a batch, or a write made of them,
+/// such as append() or rename()) clears it; or, given a default TTL
+/// (Config.default_ttl_secs), expires the key that long after the write,
+/// as put_ttl() would, so that a read-modify-write of a key restarts its
+/// TTL.  put_expire_at() with api::NO_EXPIRY stores a key without one,
+/// whatever the default.  touch() sets the TTL it is given, and removes
+/// are unaffected.  The index is also held in
+/// memory, read when the db is opened, at about 64 bytes plus the key
+/// per expiring key, so that reads check expiry without a lookup.
+///
+/// An expired record is hidden, but stays stored until removed by
+/// sweep_expired(), or a write of its key.  Index keys and expired records are
+/// hidden from iter_keys(), stat() and the change log as listed by
+/// changes_since(), though both count against a quota.
+///
+/// Above indexes, so that removing an expired record removes its index
+/// entries, and the change log and quota, so that they see its removal;
+/// below auditing and watching, so that they see writes as made, without
+/// index keys, and eviction and key folding, so that evicting a key
+/// clears its expiry, and expiries are kept by folded key.
+pub struct ExpiryDb<T: api::Db> {
+    inner: T,
+    expiries: HashMap<Vec<u8>, u64>, // expiring keys' expiry
+    queue: BTreeSet<(u64, Vec<u8>)>, // expiring keys, soonest first
+    default_ttl_secs: Option<u64>,   // TTL of writes giving none, if set
+}
+
+impl<T: api::Db> ExpiryDb<T> {
+    pub fn new(inner: T, default_ttl_secs: Option<u64>) -> Result<ExpiryDb<T>, Error> {
+        let mut expiries = HashMap::new();
+        let mut queue = BTreeSet::new();
+        let mut opts = api::IterOptions::new();
+        opts.prefix(EXPIRY_PREFIX);
+        for index_key in DbIterator::new(&inner, opts) {
+            let (unix_ms, key) = split_expiry_key(&index_key?)?;
+            expiries.insert(key.clone(), unix_ms);
+            queue.insert((unix_ms, key));
+        }
+
+        Ok(ExpiryDb {
+            inner,
+            expiries,
+            queue,
+            default_ttl_secs,
+        })
+    }
+
+    // key is absent to reads:  an index key, or expired by now_ms
+    fn hidden(&self, key: &[u8], now_ms: u64) -> bool {
+        if self.expiries.is_empty() {
+            return false;
+        }
+        reserved(key)
+            || self
+                .expiries
+                .get(key)
+                .is_some_and(|unix_ms| *unix_ms <= now_ms)
+    }
+
+    fn hidden_now(&self, key: &[u8]) -> bool {
+        !self.expiries.is_empty() && self.hidden(key, api::unix_now_ms())
+    }
+
+    // add the removal of key's expiry, if any, to batch
+    fn remove_expiry(&self, batch: &mut api::Batch, key: &[u8]) {
+        if let Some(unix_ms) = self.expiries.get(key) {
+            batch.remove(&expiry_key(*unix_ms, key));
+        }
+    }
+
+    // expiry of a write made now giving none
+    fn default_expiry(&self) -> Option<u64> {
+        self.default_ttl_secs.map(api::ttl_expire_at)
+    }
+
+    fn note_expiry(&mut self, key: &[u8], unix_ms: Option<u64>) {
+        if let Some(old_ms) = self.expiries.remove(key) {
+            self.queue.remove(&(old_ms, key.to_vec()));
+        }
+        if let Some(unix_ms) = unix_ms {
+            self.expiries.insert(key.to_vec(), unix_ms);
+            self.queue.insert((unix_ms, key.to_vec()));
+        }
+    }
+
+    // number of expiring keys expired by now_ms
+    fn n_expired(&self, now_ms: u64) -> u64 {
+        self.queue
+            .iter()
+            .take_while(|(unix_ms, _key)| *unix_ms <= now_ms)
+            .count() as u64
+    }
+}
+
+impl<T: api::Db> api::Db for ExpiryDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        batch.check()?;
+
+        // &mut self:  no other write to this db between check and apply
+        if let Some(key) = batch.guard_key() {
+            batch.check_guard(self.get(key)?.as_deref())?;
+        }
+        let mut expiry_batch = api::Batch {
+            ops: batch.ops.clone(),
+            duplicate_policy: batch.duplicate_policy,
+            guard: None,
+        };
+
+        // each key's expiry follows from its last mutation:  cleared, or,
+        // if inserted, set to the default
+        let default_ms = self.default_expiry();
+        let mut inserted: HashMap<&[u8], bool> = HashMap::new();
+        for dbm in &batch.ops {
+            inserted.insert(&dbm.key, matches!(dbm.op, api::MutationOp::Insert));
+        }
+        let mut changed = Vec::new();
+        for (key, is_insert) in inserted {
+            let unix_ms = if is_insert { default_ms } else { None };
+            if self.expiries.get(key).copied() == unix_ms {
+                continue;
+            }
+            self.remove_expiry(&mut expiry_batch, key);
+            if let Some(unix_ms) = unix_ms {
+                expiry_batch.insert(&expiry_key(unix_ms, key), b"");
+            }
+            changed.push((key, unix_ms));
+        }
+
+        let res = self.inner.apply_batch(&expiry_batch)?;
+        for (key, unix_ms) in changed {
+            self.note_expiry(key, unix_ms);
+        }
+        Ok(res)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        let res = self.inner.clear()?;
+        self.expiries.clear();
+        self.queue.clear();
+        Ok(res)
+    }
+
+    // an expired record is removed, but was absent
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        if !self.expiries.contains_key(key) {
+            return self.inner.del(key);
+        }
+
+        let present = !self.hidden_now(key) && self.inner.exists(key)?;
+        let mut batch = api::Batch::default();
+        batch.remove(key);
+        self.remove_expiry(&mut batch, key);
+        self.inner.apply_batch(&batch)?;
+        self.note_expiry(key, None);
+        Ok(present)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if self.hidden_now(key) {
+            return Ok(None);
+        }
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let unix_ms = self.default_expiry().unwrap_or(api::NO_EXPIRY);
+        self.put_expire_at(key, val, unix_ms)
+    }
+
+    // skip hidden keys; continue past any page holding only those
+    fn iter_keys(&self, mut opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        if self.expiries.is_empty() {
+            return self.inner.iter_keys(opts);
+        }
+
+        let now_ms = api::unix_now_ms();
+        loop {
+            let mut key_list = self.inner.iter_keys(opts.clone())?;
+            let last_key = key_list.keys.last().cloned();
+            key_list.keys.retain(|key| !self.hidden(key, now_ms));
+
+            let last_key = match last_key {
+                Some(key) if key_list.keys.is_empty() && !key_list.list_end => key,
+                _ => return Ok(key_list),
+            };
+            opts = opts.continue_after(&last_key);
+        }
+    }
+
+    // a prefix which only hidden keys match is absent
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        if self.expiries.is_empty() {
+            return self.inner.has_prefix(prefix);
+        }
+        Ok(!self.iter_keys_prefix(prefix, None)?.keys.is_empty())
+    }
+
+    // less index keys, and expired records not yet removed
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        let mut st = self.inner.stat()?;
+        let n_hidden = self.expiries.len() as u64 + self.n_expired(api::unix_now_ms());
+        st.n_records = st.n_records.saturating_sub(n_hidden);
+        Ok(st)
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        if self.hidden_now(key) {
+            return Ok(None);
+        }
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let mut vals = self.inner.get_multi(keys)?;
+        let now_ms = api::unix_now_ms();
+        for (key, val) in keys.iter().zip(vals.iter_mut()) {
+            if self.hidden(key, now_ms) {
+                *val = None;
+            }
+        }
+        Ok(vals)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        let mut found = self.inner.exists_multi(keys)?;
+        let now_ms = api::unix_now_ms();
+        for (key, present) in keys.iter().zip(found.iter_mut()) {
+            *present = *present && !self.hidden(key, now_ms);
+        }
+        Ok(found)
+    }
+
+    // an expiry at or before now deletes key, as in the default;
+    // NO_EXPIRY clears it, bypassing any default
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        if unix_ms == api::NO_EXPIRY {
+            if !self.expiries.contains_key(key) {
+                return self.inner.put(key, val);
+            }
+            let mut batch = api::Batch::default();
+            batch.insert(key, val);
+            self.remove_expiry(&mut batch, key);
+            let res = self.inner.apply_batch(&batch)?;
+            self.note_expiry(key, None);
+            return Ok(res);
+        }
+        if unix_ms <= api::unix_now_ms() {
+            self.del(key)?;
+            return Ok(true);
+        }
+
+        let mut batch = api::Batch::default();
+        batch.insert(key, val);
+        if self.expiries.get(key) != Some(&unix_ms) {
+            self.remove_expiry(&mut batch, key);
+            batch.insert(&expiry_key(unix_ms, key), b"");
+        }
+        let res = self.inner.apply_batch(&batch)?;
+        self.note_expiry(key, Some(unix_ms));
+        Ok(res)
+    }
+
+    // only the index key changes; the record is not rewritten
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        if !self.exists(key)? {
+            return Ok(false);
+        }
+        let unix_ms = api::ttl_expire_at(ttl_secs);
+        if unix_ms <= api::unix_now_ms() {
+            return self.del(key);
+        }
+        if self.expiries.get(key) == Some(&unix_ms) {
+            return Ok(true);
+        }
+
+        let mut batch = api::Batch::default();
+        self.remove_expiry(&mut batch, key);
+        batch.insert(&expiry_key(unix_ms, key), b"");
+        self.inner.apply_batch(&batch)?;
+        self.note_expiry(key, Some(unix_ms));
+        Ok(true)
+    }
+
+    // soonest first, each record removed with its index key
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        let now_ms = api::unix_now_ms();
+        let due: Vec<(u64, Vec<u8>)> = self
+            .queue
+            .iter()
+            .take_while(|(unix_ms, _key)| *unix_ms <= now_ms)
+            .take(max_keys)
+            .cloned()
+            .collect();
+        if due.is_empty() {
+            return Ok(0);
+        }
+
+        let mut batch = api::Batch::with_capacity(due.len() * 2);
+        for (unix_ms, key) in &due {
+            batch.remove(key);
+            batch.remove(&expiry_key(*unix_ms, key));
+        }
+        self.inner.apply_batch(&batch)?;
+        for (_unix_ms, key) in &due {
+            self.note_expiry(key, None);
+        }
+        Ok(due.len())
+    }
+
+    // includes expiries passed, of records not yet removed
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        Ok(self.queue.iter().next().map(|(unix_ms, _key)| *unix_ms))
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        let mut change_list = self.inner.changes_since(seq)?;
+        change_list.changes.retain(|change| !reserved(&change.key));
+        Ok(change_list)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    // sizes as stored, with index keys and expired records
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    // snapshots hide index keys, but not records expired since opened
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        if reserved(key) {
+            return Ok(None);
+        }
+        self.inner.snapshot_get(id, key)
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        let mut start = start.map(|key| key.to_vec());
+        loop {
+            let mut key_list = self.inner.snapshot_iter_keys(id, start.as_deref())?;
+            let last_key = key_list.keys.last().cloned();
+            key_list.keys.retain(|key| !reserved(key));
+
+            match last_key {
+                Some(key) if key_list.keys.is_empty() && !key_list.list_end => start = Some(key),
+                _ => return Ok(key_list),
+            }
+        }
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.inner.create_index(name, json_path)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        let mut keys = self.inner.index_lookup(name, value)?;
+        if let Some(keys) = &mut keys {
+            let now_ms = api::unix_now_ms();
+            keys.retain(|key| !self.hidden(key, now_ms));
+        }
+        Ok(keys)
+    }
+
+    fn degraded(&self) -> bool {
+        self.inner.degraded()
+    }
+
+    fn reset_degraded(&mut self) {
+        self.inner.reset_degraded()
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        if self.hidden_now(key) {
+            return Ok(None);
+        }
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        if self.hidden_now(key) {
+            return Ok(None);
+        }
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
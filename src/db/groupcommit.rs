@@ -0,0 +1,198 @@
+use super::api;
+use super::Error;
+use std::collections::HashMap;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+type PutResult = Result<bool, Error>;
+
+// results of an applied group, held until each waiting caller takes its own
+struct GroupResults {
+    results: Vec<Option<PutResult>>,
+    n_waiting: usize,
+}
+
+struct Queue {
+    gen: u64,                         // id of the group currently collecting puts
+    batch: api::Batch,                // its puts, in arrival order
+    sync: bool,                       // true if any of its puts requested a flush
+    done: HashMap<u64, GroupResults>, // applied groups, by id
+}
+
+/// Group commit:  puts to a db shared between threads, arriving together,
+/// are applied as one write batch, followed by at most one flush.
+///
+/// The first put to arrive while no group is collecting leads a new
+/// group.  It waits until the window has passed, or max_ops puts have
+/// joined, then applies the group, and wakes the other callers.  Each
+/// caller returns only once its own put is applied (and flushed, if it
+/// asked for sync), with its own result.  If the batch fails, e.g. one
+/// put would exceed a quota, each put is retried alone, so that one bad
+/// put does not fail the others.
+///
+/// The trade is latency for throughput:  a lone put waits out the whole
+/// window, while under concurrent load many fsyncs collapse into one.
+/// Puts arriving while a group is being applied start the next group.
+pub struct GroupCommit {
+    window: Duration,
+    max_ops: usize,
+    queue: Mutex<Queue>,
+    wake: Condvar,
+}
+
+impl GroupCommit {
+    pub fn new(window: Duration, max_ops: usize) -> GroupCommit {
+        GroupCommit {
+            window,
+            max_ops: max_ops.max(1),
+            queue: Mutex::new(Queue {
+                gen: 0,
+                batch: api::Batch::default(),
+                sync: false,
+                done: HashMap::new(),
+            }),
+            wake: Condvar::new(),
+        }
+    }
+
+    // queue state is only changed by short, non-panicking sections
+    fn lock(&self) -> MutexGuard<Queue> {
+        self.queue.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Store key and val as part of a group of puts, flushing before
+    /// returning if sync.  If this put leads its group, apply is called
+    /// with the group's batch, and whether any put requested sync; it
+    /// returns one result per put, typically via apply_group().
+    pub fn put<F>(&self, key: &[u8], val: &[u8], sync: bool, apply: F) -> PutResult
+    where
+        F: FnOnce(&api::Batch, bool) -> Vec<PutResult>,
+    {
+        let mut queue = self.lock();
+        let gen = queue.gen;
+        let pos = queue.batch.ops.len();
+        queue.batch.insert(key, val);
+        queue.sync |= sync;
+
+        if pos == 0 {
+            self.lead(queue, apply)
+        } else {
+            if queue.batch.ops.len() >= self.max_ops {
+                self.wake.notify_all();
+            }
+            self.wait(queue, gen, pos)
+        }
+    }
+
+    fn lead<F>(&self, mut queue: MutexGuard<Queue>, apply: F) -> PutResult
+    where
+        F: FnOnce(&api::Batch, bool) -> Vec<PutResult>,
+    {
+        // collect the group
+        let deadline = Instant::now() + self.window;
+        while queue.batch.ops.len() < self.max_ops {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            queue = self
+                .wake
+                .wait_timeout(queue, deadline - now)
+                .unwrap_or_else(PoisonError::into_inner)
+                .0;
+        }
+
+        // close it, so that later puts start the next group
+        let gen = queue.gen;
+        queue.gen += 1;
+        let batch = mem::take(&mut queue.batch);
+        let sync = mem::replace(&mut queue.sync, false);
+        drop(queue);
+
+        // apply, unlocked; waiters must be woken even if apply panics
+        let n_ops = batch.ops.len();
+        let mut panicked = None;
+        let mut results: Vec<Option<PutResult>> =
+            match panic::catch_unwind(AssertUnwindSafe(|| apply(&batch, sync))) {
+                Ok(results) if results.len() == n_ops => results.into_iter().map(Some).collect(),
+                Ok(_) => vec![
+                    Some(Err(Error::Backend(String::from(
+                        "group commit: result count mismatch"
+                    ))));
+                    n_ops
+                ],
+                Err(payload) => {
+                    panicked = Some(payload);
+                    vec![
+                        Some(Err(Error::Backend(String::from(
+                            "group commit: write failed by panic"
+                        ))));
+                        n_ops
+                    ]
+                }
+            };
+        let own = results[0].take().unwrap();
+
+        if n_ops > 1 {
+            let mut queue = self.lock();
+            queue.done.insert(
+                gen,
+                GroupResults {
+                    results,
+                    n_waiting: n_ops - 1,
+                },
+            );
+            self.wake.notify_all();
+        }
+
+        if let Some(payload) = panicked {
+            panic::resume_unwind(payload);
+        }
+        own
+    }
+
+    fn wait(&self, mut queue: MutexGuard<Queue>, gen: u64, pos: usize) -> PutResult {
+        loop {
+            if let Some(group) = queue.done.get_mut(&gen) {
+                let res = group.results[pos].take().unwrap();
+                group.n_waiting -= 1;
+                if group.n_waiting == 0 {
+                    queue.done.remove(&gen);
+                }
+                return res;
+            }
+            queue = self
+                .wake
+                .wait(queue)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+}
+
+/// Apply a group's batch of puts to db, then flush once if sync, returning
+/// one result per put.  If the batch fails, each put is applied alone.
+pub fn apply_group<D: api::Db + ?Sized>(
+    db: &mut D,
+    batch: &api::Batch,
+    sync: bool,
+) -> Vec<PutResult> {
+    let mut results = match db.apply_batch(batch) {
+        Ok(_) => vec![Ok(true); batch.ops.len()],
+        Err(_e) => batch
+            .ops
+            .iter()
+            .map(|op| db.put(&op.key, op.value.as_deref().unwrap_or_default()))
+            .collect(),
+    };
+
+    if sync {
+        if let Err(e) = db.flush() {
+            for res in results.iter_mut().filter(|res| res.is_ok()) {
+                *res = Err(e.clone());
+            }
+        }
+    }
+    results
+}
@@ -0,0 +1,147 @@
+use super::api;
+use super::Error;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Approximate distribution of one size measure, over a sample.
+#[derive(Debug, Default, PartialEq)]
+pub struct Percentiles {
+    pub p50: usize,
+    pub p90: usize,
+    pub p99: usize,
+    pub max: usize, // exact, over every record seen; not sampled
+}
+
+/// Key and value length distributions, summarized by SizeSampler.
+#[derive(Debug, PartialEq)]
+pub struct SizeHistogram {
+    pub n_seen: u64,      // records fed to the sampler
+    pub n_sampled: usize, // records retained in the sample
+    pub key_len: Percentiles,
+    pub value_len: Percentiles,
+}
+
+// xorshift64*:  fast, and plenty for choosing reservoir slots
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+}
+
+/// Uniform random sample of record sizes, of bounded size, by reservoir
+/// sampling (Algorithm R):  each record seen is equally likely to be
+/// retained, however many are seen, using memory proportional to the
+/// sample size only.
+pub struct SizeSampler {
+    sample_size: usize,
+    sample: Vec<(usize, usize)>, // (key length, value length)
+    n_seen: u64,
+    max_key_len: usize,
+    max_value_len: usize,
+    rng: Rng,
+}
+
+impl SizeSampler {
+    pub fn new(sample_size: usize) -> SizeSampler {
+        let seed = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(d) => d.as_nanos() as u64,
+            Err(_e) => 0,
+        };
+        SizeSampler::with_seed(sample_size, seed)
+    }
+
+    /// As new(), with a fixed seed, for repeatable samples.
+    pub fn with_seed(sample_size: usize, seed: u64) -> SizeSampler {
+        SizeSampler {
+            sample_size,
+            sample: Vec::with_capacity(sample_size),
+            n_seen: 0,
+            max_key_len: 0,
+            max_value_len: 0,
+            rng: Rng(seed | 1), // xorshift state must be non-zero
+        }
+    }
+
+    pub fn n_seen(&self) -> u64 {
+        self.n_seen
+    }
+
+    pub fn add(&mut self, key_len: usize, value_len: usize) {
+        self.max_key_len = self.max_key_len.max(key_len);
+        self.max_value_len = self.max_value_len.max(value_len);
+
+        // the n'th record replaces a random slot, with probability k/n
+        if self.sample.len() < self.sample_size {
+            self.sample.push((key_len, value_len));
+        } else {
+            let slot = self.rng.next() % (self.n_seen + 1);
+            if slot < self.sample_size as u64 {
+                self.sample[slot as usize] = (key_len, value_len);
+            }
+        }
+        self.n_seen += 1;
+    }
+
+    /// Summarize sample.  Percentiles are nearest-rank, over the sample;
+    /// all zero, if nothing was seen.
+    pub fn summary(&self) -> SizeHistogram {
+        let mut key_lens: Vec<usize> = self.sample.iter().map(|s| s.0).collect();
+        let mut value_lens: Vec<usize> = self.sample.iter().map(|s| s.1).collect();
+
+        SizeHistogram {
+            n_seen: self.n_seen,
+            n_sampled: self.sample.len(),
+            key_len: percentiles(&mut key_lens, self.max_key_len),
+            value_len: percentiles(&mut value_lens, self.max_value_len),
+        }
+    }
+}
+
+fn percentiles(lens: &mut [usize], max: usize) -> Percentiles {
+    lens.sort_unstable();
+    let rank = |p: usize| match lens.len() {
+        0 => 0,
+        n => lens[(p * n).div_ceil(100).max(1) - 1],
+    };
+
+    Percentiles {
+        p50: rank(50),
+        p90: rank(90),
+        p99: rank(99),
+        max,
+    }
+}
+
+/// Feed up to max_keys records following start (or from the first key,
+/// if none) to sampler, reading at most one page of keys.  Returns the
+/// key to continue after, or None once every key has been read.  Callers
+/// loop, releasing the db between calls, until they have seen enough.
+pub fn sample_page<D: api::Db + ?Sized>(
+    db: &D,
+    sampler: &mut SizeSampler,
+    start: Option<&[u8]>,
+    max_keys: usize,
+) -> Result<Option<Vec<u8>>, Error> {
+    let mut opts = api::IterOptions::new();
+    if let Some(key) = start {
+        opts.start(key);
+    }
+    let key_list = db.iter_keys(opts)?;
+
+    let n_keys = key_list.keys.len().min(max_keys);
+    for key in &key_list.keys[..n_keys] {
+        // skip keys removed since listed
+        if let Some(val) = db.get_ref(key)? {
+            sampler.add(key.len(), val.len());
+        }
+    }
+
+    if n_keys == 0 || (key_list.list_end && n_keys == key_list.keys.len()) {
+        return Ok(None);
+    }
+    Ok(Some(key_list.keys[n_keys - 1].clone()))
+}
@@ -0,0 +1,431 @@
+use super::api;
+use super::meta;
+use super::Error;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// file within db directory, recording the db's index definitions
+pub const INDEXES_FN: &str = "kvdbd-indexes.json";
+
+/// Prefix of every index entry key, `idx:{name}:{value}:{primary_key}`.
+pub const INDEX_KEY_PREFIX: &[u8] = b"idx:";
+
+// keys never indexed:  index entries, and other layers' reserved keys
+const RESERVED_PREFIX: &[u8] = b"\x00kvdbd:";
+
+/// Error returned by create_index(), for a name already registered with
+/// another json_path.
+pub const ERR_INDEX_EXISTS: Error = Error::IndexExists;
+
+/// Error returned by create_index(), for an invalid name or json_path.
+pub const ERR_INDEX_INVALID: Error = Error::InvalidIndex;
+
+// index entries written per batch, when indexing existing records
+const BACKFILL_BATCH_KEYS: usize = 1000;
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+struct IndexDef {
+    name: String,
+    json_path: String,
+}
+
+// JSON path, e.g. "$.user.email" or "user.email", as field names (or
+// array positions)
+fn parse_json_path(json_path: &str) -> Option<Vec<String>> {
+    let path = json_path.strip_prefix("$.").unwrap_or(json_path);
+    let fields: Vec<String> = path.split('.').map(String::from).collect();
+    if fields.iter().any(|f| f.is_empty()) {
+        return None;
+    }
+    Some(fields)
+}
+
+fn valid_index_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+/// Value indexed for a record:  the scalar at fields within val, parsed
+/// as JSON, as text.  Records which are not JSON, or lack the field, or
+/// hold an object, array or null there, are not indexed.  val is as
+/// stored, so may carry a metadata header, which is skipped.
+fn extract(val: &[u8], fields: &[String]) -> Option<String> {
+    let doc: serde_json::Value = serde_json::from_slice(meta::value_of(val)).ok()?;
+    let mut v = &doc;
+    for field in fields {
+        v = match v {
+            serde_json::Value::Object(map) => map.get(field)?,
+            serde_json::Value::Array(items) => items.get(field.parse::<usize>().ok()?)?,
+            _ => return None,
+        };
+    }
+
+    match v {
+        serde_json::Value::String(s) => Some(s.clone()),
+        serde_json::Value::Number(n) => Some(n.to_string()),
+        serde_json::Value::Bool(b) => Some(b.to_string()),
+        _ => None,
+    }
+}
+
+// "idx:{name}:{value}:", with '%' and ':' in value percent-encoded, so
+// that the prefix of one value never matches another's entries
+fn entry_prefix(name: &str, value: &str) -> Vec<u8> {
+    let mut key = INDEX_KEY_PREFIX.to_vec();
+    key.extend_from_slice(name.as_bytes());
+    key.push(b':');
+    for b in value.bytes() {
+        match b {
+            b'%' => key.extend_from_slice(b"%25"),
+            b':' => key.extend_from_slice(b"%3A"),
+            _ => key.push(b),
+        }
+    }
+    key.push(b':');
+    key
+}
+
+fn entry_key(name: &str, value: &str, primary_key: &[u8]) -> Vec<u8> {
+    let mut key = entry_prefix(name, value);
+    key.extend_from_slice(primary_key);
+    key
+}
+
+fn indexed_key(key: &[u8]) -> bool {
+    !key.starts_with(INDEX_KEY_PREFIX) && !key.starts_with(RESERVED_PREFIX)
+}
+
+/// Db layer maintaining secondary indexes over a field of JSON values.
+///
+/// Each index maps the value found at its json_path in a record to the
+/// record's key, stored as an empty-valued entry under key
+/// `idx:{name}:{value}:{primary_key}`, within the db itself.  Each write
+/// is committed in one batch with the index entries it adds and removes:
+/// a put reads the key's old value, removing the entries for its old
+/// field values, and a delete removes them.  Definitions are recorded in
+/// INDEXES_FN within the db directory.
+pub struct IndexDb<T: api::Db> {
+    inner: T,
+    defs_path: PathBuf,
+    read_only: bool,
+    indexes: Vec<(IndexDef, Vec<String>)>, // with parsed json_path
+}
+
+impl<T: api::Db> IndexDb<T> {
+    pub fn new(inner: T, db_path: &str, read_only: bool) -> Result<IndexDb<T>, Error> {
+        let defs_path = Path::new(db_path).join(INDEXES_FN);
+        let defs: Vec<IndexDef> = match fs::read_to_string(&defs_path) {
+            Err(_e) if !defs_path.exists() => Vec::new(),
+            Err(_e) => return Err(Error::Backend(String::from("db indexes: read failed"))),
+            Ok(text) => match serde_json::from_str(&text) {
+                Ok(defs) => defs,
+                Err(_e) => return Err(Error::Corruption(String::from("db indexes: invalid"))),
+            },
+        };
+
+        let mut indexes = Vec::with_capacity(defs.len());
+        for def in defs {
+            match parse_json_path(&def.json_path) {
+                None => return Err(Error::Corruption(String::from("db indexes: invalid"))),
+                Some(fields) => indexes.push((def, fields)),
+            }
+        }
+
+        Ok(IndexDb {
+            inner,
+            defs_path,
+            read_only,
+            indexes,
+        })
+    }
+
+    fn save_defs(&self) -> Result<(), Error> {
+        let defs: Vec<&IndexDef> = self.indexes.iter().map(|(def, _)| def).collect();
+        let text = serde_json::to_string_pretty(&defs).unwrap();
+
+        // replace whole, so a crash leaves the old or new definitions
+        let tmp_path = self.defs_path.with_extension("json.tmp");
+        match fs::write(&tmp_path, text).and_then(|_| fs::rename(&tmp_path, &self.defs_path)) {
+            Ok(_) => Ok(()),
+            Err(_e) => Err(Error::Backend(String::from("db indexes: write failed"))),
+        }
+    }
+
+    /// Batch, with the index entries its mutations add and remove.
+    fn index_batch(&self, batch: &api::Batch) -> Result<api::Batch, Error> {
+        // a batch may add and then remove one index entry, so the indexed
+        // batch is always last-wins; check the caller's policy first
+        batch.check()?;
+        let mut indexed = api::Batch {
+            ops: batch.ops.clone(),
+            duplicate_policy: api::DuplicatePolicy::LastWins,
+            guard: batch.guard.clone(),
+        };
+
+        // values as written by earlier mutations in this batch
+        let mut pending: HashMap<&[u8], Option<&[u8]>> = HashMap::new();
+        for mutation in &batch.ops {
+            let key = mutation.key.as_slice();
+            if !indexed_key(key) {
+                continue;
+            }
+
+            let old_val = match pending.get(key) {
+                Some(v) => v.map(|v| v.to_vec()),
+                None => self.inner.get(key)?,
+            };
+            let new_val = mutation.value.as_deref();
+            pending.insert(key, new_val);
+
+            for (def, fields) in &self.indexes {
+                let old_field = old_val.as_ref().and_then(|v| extract(v, fields));
+                let new_field = new_val.and_then(|v| extract(v, fields));
+                if old_field == new_field {
+                    continue;
+                }
+                if let Some(field) = old_field {
+                    indexed.remove(&entry_key(&def.name, &field, key));
+                }
+                if let Some(field) = new_field {
+                    indexed.insert(&entry_key(&def.name, &field, key), b"");
+                }
+            }
+        }
+
+        Ok(indexed)
+    }
+
+    // add name's entries for every record already stored, a page at a time
+    fn backfill(&mut self, name: &str, fields: &[String]) -> Result<(), Error> {
+        let mut opts = api::IterOptions::new();
+        loop {
+            let key_list = self.inner.iter_keys(opts)?;
+
+            let mut batch = api::Batch::default();
+            for key in key_list.keys.iter().filter(|key| indexed_key(key)) {
+                if let Some(val) = self.inner.get(key)? {
+                    if let Some(field) = extract(&val, fields) {
+                        batch.insert(&entry_key(name, &field, key), b"");
+                    }
+                }
+                if batch.ops.len() >= BACKFILL_BATCH_KEYS {
+                    self.inner.apply_batch(&batch)?;
+                    batch = api::Batch::default();
+                }
+            }
+            if !batch.ops.is_empty() {
+                self.inner.apply_batch(&batch)?;
+            }
+
+            match key_list.keys.last() {
+                Some(last_key) if !key_list.list_end => {
+                    opts = api::IterOptions::new();
+                    opts.start(last_key);
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+}
+
+impl<T: api::Db> api::Db for IndexDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        if self.indexes.is_empty() {
+            return self.inner.apply_batch(batch);
+        }
+        let indexed = self.index_batch(batch)?;
+        self.inner.apply_batch(&indexed)
+    }
+
+    // removes index entries with the records; definitions are kept
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        if self.indexes.is_empty() || !indexed_key(key) {
+            return self.inner.del(key);
+        }
+        if self.inner.get(key)?.is_none() {
+            return Ok(false);
+        }
+
+        let mut batch = api::Batch::default();
+        batch.remove(key);
+        self.apply_batch(&batch)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        if self.indexes.is_empty() || !indexed_key(key) {
+            return self.inner.put(key, val);
+        }
+
+        let mut batch = api::Batch::default();
+        batch.insert(key, val);
+        self.apply_batch(&batch)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.inner.get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.inner.iter_entries(opts, max_bytes)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.snapshot_get(id, key)
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.snapshot_iter_keys(id, start)
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        if !valid_index_name(name) {
+            return Err(ERR_INDEX_INVALID);
+        }
+        let fields = match parse_json_path(json_path) {
+            None => return Err(ERR_INDEX_INVALID),
+            Some(fields) => fields,
+        };
+        if let Some((def, _)) = self.indexes.iter().find(|(def, _)| def.name == name) {
+            return match def.json_path == json_path {
+                true => Ok(false),
+                false => Err(ERR_INDEX_EXISTS),
+            };
+        }
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+
+        self.backfill(name, &fields)?;
+        self.indexes.push((
+            IndexDef {
+                name: name.to_string(),
+                json_path: json_path.to_string(),
+            },
+            fields,
+        ));
+        if let Err(e) = self.save_defs() {
+            self.indexes.pop();
+            return Err(e);
+        }
+
+        Ok(true)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        if !self.indexes.iter().any(|(def, _)| def.name == name) {
+            return Ok(None);
+        }
+
+        let prefix = entry_prefix(name, value);
+        let mut primary_keys = Vec::new();
+        let mut opts = api::IterOptions::new();
+        opts.prefix(&prefix);
+        loop {
+            let key_list = self.inner.iter_keys(opts)?;
+            for key in &key_list.keys {
+                primary_keys.push(key[prefix.len()..].to_vec());
+            }
+
+            match key_list.keys.last() {
+                Some(last_key) if !key_list.list_end => {
+                    opts = api::IterOptions::new();
+                    opts.prefix(&prefix);
+                    opts.start(last_key);
+                }
+                _ => return Ok(Some(primary_keys)),
+            }
+        }
+    }
+}
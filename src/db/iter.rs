@@ -0,0 +1,182 @@
+use super::api;
+use super::Error;
+use std::vec;
+
+// where keys, and values, are read from
+enum Source<'a> {
+    Db(&'a dyn api::Db),
+    Snapshot(&'a dyn api::Snapshot),
+}
+
+impl Source<'_> {
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        match self {
+            Source::Db(db) => db.iter_keys(opts),
+            Source::Snapshot(snap) => snap.iter_keys(opts),
+        }
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self {
+            Source::Db(db) => db.get(key),
+            Source::Snapshot(snap) => snap.get(key),
+        }
+    }
+}
+
+/// Iterator over the keys of a db, or of a snapshot, in the order and
+/// range given by IterOptions, fetching pages of keys via iter_keys() as
+/// needed:  an embedder's alternative to paging by hand, as
+/// DbExt::for_each_key() is, for callers wanting an Iterator.
+///
+/// A page is fetched when the one before runs out, so, over a db, keys
+/// written or removed during iteration may or may not be seen; iterate
+/// a snapshot (Db::snapshot()) for a consistent view.  A failed fetch
+/// is yielded as an error, once, and ends the iteration.
+pub struct DbIterator<'a> {
+    source: Source<'a>,
+    page: vec::IntoIter<Vec<u8>>,
+    next_page: Option<api::IterOptions>, // None, once the last is fetched
+}
+
+impl<'a> DbIterator<'a> {
+    /// Keys of db, per opts.
+    pub fn new(db: &'a dyn api::Db, opts: api::IterOptions) -> DbIterator<'a> {
+        DbIterator::with_source(Source::Db(db), opts)
+    }
+
+    /// Keys of snap, per opts.
+    pub fn snapshot(snap: &'a dyn api::Snapshot, opts: api::IterOptions) -> DbIterator<'a> {
+        DbIterator::with_source(Source::Snapshot(snap), opts)
+    }
+
+    fn with_source(source: Source<'a>, opts: api::IterOptions) -> DbIterator<'a> {
+        DbIterator {
+            source,
+            page: Vec::new().into_iter(),
+            next_page: Some(opts),
+        }
+    }
+
+    /// The same keys, each with its value, read as it is reached.  Keys
+    /// removed between fetching a page and reading the value are
+    /// skipped, as by DbExt::for_each_entry().
+    pub fn entries(self) -> EntryIterator<'a> {
+        EntryIterator { keys: self }
+    }
+
+    // end the iteration, after an error
+    fn stop(&mut self) {
+        self.page = Vec::new().into_iter();
+        self.next_page = None;
+    }
+}
+
+impl Iterator for DbIterator<'_> {
+    type Item = Result<Vec<u8>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some(key) = self.page.next() {
+                return Some(Ok(key));
+            }
+            let opts = self.next_page.take()?;
+            let key_list = match self.source.iter_keys(opts.clone()) {
+                Err(e) => return Some(Err(e)),
+                Ok(key_list) => key_list,
+            };
+            if !key_list.list_end {
+                if let Some(last_key) = key_list.keys.last() {
+                    self.next_page = Some(opts.continue_after(last_key));
+                }
+            }
+            self.page = key_list.keys.into_iter();
+        }
+    }
+}
+
+/// Iterator over the keys and values of a db, or of a snapshot; see
+/// DbIterator::entries().
+pub struct EntryIterator<'a> {
+    keys: DbIterator<'a>,
+}
+
+impl Iterator for EntryIterator<'_> {
+    type Item = Result<(Vec<u8>, Vec<u8>), Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let key = match self.keys.next()? {
+                Err(e) => return Some(Err(e)),
+                Ok(key) => key,
+            };
+            match self.keys.source.get(&key) {
+                Ok(None) => continue,
+                Ok(Some(val)) => return Some(Ok((key, val))),
+                Err(e) => {
+                    self.keys.stop();
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::api::{Db, IterOptions, MAX_ITER_KEYS};
+    use super::super::mem::MemDb;
+    use super::*;
+
+    #[test]
+    fn test_db_iterator() {
+        // keys over several pages of MAX_ITER_KEYS
+        let n_keys = MAX_ITER_KEYS * 2 + 5;
+        let keys: Vec<Vec<u8>> = (0..n_keys)
+            .map(|i| format!("key{i:05}").into_bytes())
+            .collect();
+        let mut db = MemDb::new();
+        for key in &keys {
+            assert_eq!(db.put(key, key), Ok(true));
+        }
+
+        let listed: Result<Vec<Vec<u8>>, Error> =
+            DbIterator::new(&db, IterOptions::new()).collect();
+        assert_eq!(listed, Ok(keys.clone()));
+
+        // in reverse, and after a start key, across page boundaries too
+        let mut opts = IterOptions::new();
+        opts.reverse();
+        let listed: Vec<Vec<u8>> = DbIterator::new(&db, opts).map(Result::unwrap).collect();
+        assert!(listed.iter().eq(keys.iter().rev()));
+        let mut opts = IterOptions::new();
+        opts.start(&keys[MAX_ITER_KEYS - 1]);
+        assert_eq!(DbIterator::new(&db, opts).count(), n_keys - MAX_ITER_KEYS);
+
+        // within a prefix, an empty one included
+        let mut opts = IterOptions::new();
+        opts.prefix(b"key01");
+        assert_eq!(DbIterator::new(&db, opts).count(), 1000);
+        let mut opts = IterOptions::new();
+        opts.prefix(b"nokey");
+        assert_eq!(DbIterator::new(&db, opts).next(), None);
+
+        // entries, each with its value
+        let mut n_entries = 0;
+        for entry in DbIterator::new(&db, IterOptions::new()).entries() {
+            let (key, val) = entry.unwrap();
+            assert_eq!(key, val);
+            n_entries += 1;
+        }
+        assert_eq!(n_entries, n_keys);
+
+        // a snapshot's keys, unchanged by later writes
+        let snap = db.snapshot().unwrap();
+        assert_eq!(db.del(&keys[0]), Ok(true));
+        assert_eq!(db.put(b"later", b"x"), Ok(true));
+        let mut entries = DbIterator::snapshot(&*snap, IterOptions::new()).entries();
+        assert_eq!(entries.next(), Some(Ok((keys[0].clone(), keys[0].clone()))));
+        assert_eq!(entries.count(), n_keys - 1);
+        assert_eq!(DbIterator::new(&db, IterOptions::new()).count(), n_keys);
+    }
+}
@@ -0,0 +1,237 @@
+use super::api;
+use super::Error;
+use std::time::Instant;
+
+/// Db layer which folds keys to lowercase, before passing them to the
+/// inner db.  Only ASCII A-Z is folded; there is no Unicode case mapping,
+/// which keeps key ordering predictable.
+pub struct FoldCaseDb<T: api::Db> {
+    inner: T,
+}
+
+impl<T: api::Db> FoldCaseDb<T> {
+    pub fn new(inner: T) -> FoldCaseDb<T> {
+        FoldCaseDb { inner }
+    }
+}
+
+fn fold(key: &[u8]) -> Vec<u8> {
+    key.to_ascii_lowercase()
+}
+
+fn fold_opts(opts: &api::IterOptions) -> api::IterOptions {
+    let mut folded = api::IterOptions::new();
+    if let Some(start_key) = &opts.start_key {
+        folded.start(&fold(start_key));
+    }
+    if let Some(prefix) = &opts.prefix {
+        folded.prefix(&fold(prefix));
+    }
+    folded.reverse = opts.reverse;
+
+    folded
+}
+
+fn fold_batch(batch: &api::Batch) -> api::Batch {
+    let mut folded = api::Batch {
+        duplicate_policy: batch.duplicate_policy,
+        guard: batch
+            .guard
+            .as_ref()
+            .map(|(key, expected)| (fold(key), expected.clone())),
+        ..api::Batch::default()
+    };
+    for mutation in &batch.ops {
+        folded.ops.push(api::Mutation {
+            op: mutation.op.clone(),
+            key: fold(&mutation.key),
+            value: mutation.value.clone(),
+        });
+    }
+
+    folded
+}
+
+impl<T: api::Db> api::Db for FoldCaseDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        self.inner.apply_batch(&fold_batch(batch))
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del(&fold(key))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(&fold(key))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.inner.put(&fold(key), val)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(fold_opts(&opts))
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(&fold(prefix))
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(&fold(key))
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let folded: Vec<Vec<u8>> = keys.iter().map(|k| fold(k)).collect();
+        self.inner.get_multi(&folded)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        let folded: Vec<Vec<u8>> = keys.iter().map(|k| fold(k)).collect();
+        let folded: Vec<&[u8]> = folded.iter().map(|k| k.as_slice()).collect();
+        self.inner.exists_multi(&folded)
+    }
+
+    fn apply_batch_until(&mut self, batch: &api::Batch, deadline: Instant) -> Result<usize, Error> {
+        self.inner.apply_batch_until(&fold_batch(batch), deadline)
+    }
+
+    fn put_next(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        self.inner.put_next(val)
+    }
+
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> Result<bool, Error> {
+        self.inner.rename(&fold(from), &fold(to), overwrite)
+    }
+
+    fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<(), Error> {
+        self.inner.swap(&fold(key_a), &fold(key_b))
+    }
+
+    fn del_if_empty(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del_if_empty(&fold(key))
+    }
+
+    fn del_many(&mut self, keys: &[Vec<u8>]) -> Result<u64, Error> {
+        let folded: Vec<Vec<u8>> = keys.iter().map(|k| fold(k)).collect();
+        self.inner.del_many(&folded)
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        self.inner.compare_and_swap(&fold(key), expected, new)
+    }
+
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        self.inner.get_or_init(&fold(key), init)
+    }
+
+    fn add_sharded(&mut self, key: &[u8], delta: i64, shards: u32) -> Result<bool, Error> {
+        self.inner.add_sharded(&fold(key), delta, shards)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(&fold(key))
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        self.inner.put_expire_at(&fold(key), val, unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(&fold(key), ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    // logged keys are already folded, by the change log beneath this layer
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    // wildcards are not letters, so are unchanged by folding
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        let start = start.map(fold);
+        self.inner.scan_glob(&fold(pattern), start.as_deref())
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.inner.iter_entries(fold_opts(&opts), max_bytes)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(&fold(start), &fold(end))
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.snapshot_get(id, &fold(key))
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        let start = start.map(fold);
+        self.inner.snapshot_iter_keys(id, start.as_deref())
+    }
+
+    // indexed values come from record values, and are not folded
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.inner.create_index(name, json_path)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        self.inner.index_lookup(name, value)
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(&fold(key))
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(&fold(key))
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
@@ -1,31 +1,108 @@
 use super::api;
+use super::manifest;
+use super::Error;
 use lmdb::{Cursor, Transaction};
 use std::path::Path;
 
 pub struct LmdbWrapper {
     env: lmdb::Environment,
     db: lmdb::Database,
+    read_only: bool,
+    max_iter_keys: usize, // per Config.max_iter_keys
+}
+
+impl LmdbWrapper {
+    // read-only environments refuse write txns with a bare EACCES;
+    // refuse writes up front, as the sled driver does
+    fn check_writable(&self) -> Result<(), Error> {
+        match self.read_only {
+            true => Err(api::ERR_READ_ONLY),
+            false => Ok(()),
+        }
+    }
+
+    // iter_keys() in reverse, on a non-empty db.  lmdb-rs iterators only
+    // walk forward, so step the cursor back directly
+    fn iter_keys_rev(&self, opts: &api::IterOptions) -> Result<api::KeyList, Error> {
+        let mut key_list = api::KeyList {
+            keys: Vec::new(),
+            list_end: true,
+        };
+        let prefix = opts.prefix.clone().unwrap_or_default();
+        let bound = api::reverse_upper_bound(opts);
+        if bound.as_ref().is_some_and(|bound| *bound <= prefix) {
+            return Ok(key_list); // no key of the prefix precedes it
+        }
+
+        let txn = match self.env.begin_ro_txn() {
+            Err(_e) => return Err(Error::Backend(String::from("begin-ro-txn failed"))),
+            Ok(txn) => txn,
+        };
+        {
+            // extra scope, for cursor lifetime
+            let cursor = match txn.open_ro_cursor(self.db) {
+                Err(_e) => return Err(Error::Backend(String::from("open-ro-cursor failed"))),
+                Ok(cursor) => cursor,
+            };
+
+            // last key before the bound:  the one preceding the first key
+            // at or past it, or the db's last key, if none is
+            let mut res = match bound {
+                None => cursor.get(None, None, lmdb_sys::MDB_LAST),
+                Some(bound) => match cursor.get(Some(&bound), None, lmdb_sys::MDB_SET_RANGE) {
+                    Err(lmdb::Error::NotFound) => cursor.get(None, None, lmdb_sys::MDB_LAST),
+                    Err(e) => Err(e),
+                    Ok(_found) => cursor.get(None, None, lmdb_sys::MDB_PREV),
+                },
+            };
+
+            loop {
+                let key = match res {
+                    Err(lmdb::Error::NotFound) => break,
+                    Err(_e) => return Err(Error::Backend(String::from("cursor seek failed"))),
+                    Ok((key, _val)) => key.unwrap_or_default(),
+                };
+
+                // stop, once before all keys matching prefix
+                if !key.starts_with(&prefix) {
+                    break;
+                }
+                key_list.keys.push(key.to_vec());
+                if key_list.keys.len() >= self.max_iter_keys {
+                    key_list.list_end = false;
+                    break;
+                }
+
+                res = cursor.get(None, None, lmdb_sys::MDB_PREV);
+            }
+        } // end cursor scope, before we abort txn
+
+        txn.abort();
+
+        Ok(key_list)
+    }
 }
 
 impl api::Db for LmdbWrapper {
-    fn clear(&mut self) -> Result<bool, &'static str> {
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.check_writable()?;
         let res = self.env.begin_rw_txn();
         match res {
-            Err(_e) => Err("begin-rw-txn failed"),
+            Err(_e) => Err(Error::Backend(String::from("begin-rw-txn failed"))),
             Ok(mut txn) => match txn.clear_db(self.db) {
-                Err(_e) => Err("clear_db failed"),
+                Err(_e) => Err(Error::Backend(String::from("clear_db failed"))),
                 Ok(_) => match txn.commit() {
-                    Err(_e) => Err("commit failed"),
+                    Err(_e) => Err(Error::Backend(String::from("commit failed"))),
                     Ok(_) => Ok(true),
                 },
             },
         }
     }
 
-    fn stat(&self) -> Result<api::DbStat, &'static str> {
+    fn stat(&self) -> Result<api::DbStat, Error> {
         let res = self.env.stat();
         if res.is_err() {
-            return Err("db stat failed");
+            return Err(Error::Backend(String::from("db stat failed")));
         }
         let st = res.unwrap();
 
@@ -34,16 +111,20 @@ impl api::Db for LmdbWrapper {
         })
     }
 
-    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+    fn max_iter_keys(&self) -> usize {
+        self.max_iter_keys
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
         let res = self.env.begin_ro_txn();
         match res {
-            Err(_e) => Err("begin-ro-txn failed"),
+            Err(_e) => Err(Error::Backend(String::from("begin-ro-txn failed"))),
             Ok(txn) => match txn.get(self.db, &key.to_vec()) {
                 Err(e) => {
                     if e == lmdb::Error::NotFound {
                         Ok(None)
                     } else {
-                        Err("get failed")
+                        Err(Error::Backend(String::from("get failed")))
                     }
                 }
                 Ok(data) => {
@@ -55,77 +136,127 @@ impl api::Db for LmdbWrapper {
         }
     }
 
-    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, &'static str> {
+    // one read txn for all keys; values are not copied out
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        let txn = match self.env.begin_ro_txn() {
+            Err(_e) => return Err(Error::Backend(String::from("begin-ro-txn failed"))),
+            Ok(txn) => txn,
+        };
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            match txn.get(self.db, key) {
+                Ok(_data) => found.push(true),
+                Err(lmdb::Error::NotFound) => found.push(false),
+                Err(_e) => return Err(Error::Backend(String::from("get failed"))),
+            }
+        }
+        txn.abort();
+
+        Ok(found)
+    }
+
+    // one read txn:  values are as of a single moment
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let txn = match self.env.begin_ro_txn() {
+            Err(_e) => return Err(Error::Backend(String::from("begin-ro-txn failed"))),
+            Ok(txn) => txn,
+        };
+        let mut values = Vec::with_capacity(keys.len());
+        for key in keys {
+            match txn.get(self.db, key) {
+                Ok(data) => values.push(Some(data.to_vec())),
+                Err(lmdb::Error::NotFound) => values.push(None),
+                Err(_e) => return Err(Error::Backend(String::from("get failed"))),
+            }
+        }
+        txn.abort();
+
+        Ok(values)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.check_writable()?;
         let res = self.env.begin_rw_txn();
         match res {
-            Err(_e) => Err("begin-rw-txn failed"),
+            Err(_e) => Err(Error::Backend(String::from("begin-rw-txn failed"))),
             Ok(mut txn) => match txn.put(
                 self.db,
                 &key.to_vec(),
                 &val.to_vec(),
                 lmdb::WriteFlags::empty(),
             ) {
-                Err(_e) => Err("put failed"),
+                Err(_e) => Err(Error::Backend(String::from("put failed"))),
                 Ok(_) => match txn.commit() {
-                    Err(_e) => Err("commit failed"),
+                    Err(_e) => Err(Error::Backend(String::from("commit failed"))),
                     Ok(_) => Ok(true),
                 },
             },
         }
     }
 
-    fn del(&mut self, key: &[u8]) -> Result<bool, &'static str> {
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.check_writable()?;
         let res = self.env.begin_rw_txn();
         match res {
-            Err(_e) => Err("begin-rw-txn failed"),
+            Err(_e) => Err(Error::Backend(String::from("begin-rw-txn failed"))),
             Ok(mut txn) => match txn.del(self.db, &key.to_vec(), None) {
                 Err(e) => {
                     if e == lmdb::Error::NotFound {
                         Ok(false)
                     } else {
-                        Err("del failed")
+                        Err(Error::Backend(String::from("del failed")))
                     }
                 }
                 Ok(_) => match txn.commit() {
-                    Err(_e) => Err("commit failed"),
+                    Err(_e) => Err(Error::Backend(String::from("commit failed"))),
                     Ok(_) => Ok(true),
                 },
             },
         }
     }
 
-    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, &'static str> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        self.check_writable()?;
+        batch.check()?;
         let res = self.env.begin_rw_txn();
         match res {
-            Err(_e) => Err("begin-rw-txn failed"),
+            Err(_e) => Err(Error::Backend(String::from("begin-rw-txn failed"))),
             Ok(mut txn) => {
+                if let Some(key) = batch.guard_key() {
+                    let current = match txn.get(self.db, &key) {
+                        Ok(val) => Some(val),
+                        Err(lmdb::Error::NotFound) => None,
+                        Err(_e) => return Err(Error::Backend(String::from("get failed"))),
+                    };
+                    batch.check_guard(current)?;
+                }
                 for dbm in &batch.ops {
                     match dbm.op {
                         api::MutationOp::Insert => {
                             let value = dbm.value.clone().unwrap();
                             let res = txn.put(self.db, &dbm.key, &value, lmdb::WriteFlags::empty());
                             if res.is_err() {
-                                return Err("txn.put failed");
+                                return Err(Error::Backend(String::from("txn.put failed")));
                             }
                         }
                         api::MutationOp::Remove => {
                             let res = txn.del(self.db, &dbm.key, None);
                             if res.is_err() {
-                                return Err("txn.put failed");
+                                return Err(Error::Backend(String::from("txn.put failed")));
                             }
                         }
                     }
                 }
 
                 match txn.commit() {
-                    Err(_e) => Err("commit failed"),
+                    Err(_e) => Err(Error::Backend(String::from("commit failed"))),
                     Ok(_) => Ok(true),
                 }
             }
         }
     }
 
-    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, &'static str> {
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
         let mut key_list = api::KeyList {
             keys: Vec::new(),
             list_end: true,
@@ -139,10 +270,13 @@ impl api::Db for LmdbWrapper {
         if st.n_records == 0 {
             return Ok(key_list);
         }
+        if opts.reverse {
+            return self.iter_keys_rev(&opts);
+        }
 
         let res = self.env.begin_ro_txn();
         if res.is_err() {
-            return Err("begin-ro-txn failed");
+            return Err(Error::Backend(String::from("begin-ro-txn failed")));
         }
         let txn = res.unwrap();
 
@@ -150,23 +284,43 @@ impl api::Db for LmdbWrapper {
             // extra scope, for cursor lifetime
             let res = txn.open_ro_cursor(self.db);
             if res.is_err() {
-                return Err("open-ro-cursor failed");
+                return Err(Error::Backend(String::from("open-ro-cursor failed")));
             }
             let mut cursor = res.unwrap();
 
-            let mut it;
-            if opts.start_key.is_none() {
-                it = cursor.iter_start();
-            } else {
-                it = cursor.iter_from(opts.start_key.unwrap());
-                it.next(); // absorb queried-for prev-key
-            }
-
             let prefix: Vec<u8> = match opts.prefix {
                 None => Vec::new(),
                 Some(value) => value,
             };
             let pfx_len = prefix.len();
+            let upper_bound = api::prefix_upper_bound(&prefix);
+
+            // seek to start key, if continuing a previous iteration; or to
+            // prefix, if scanning a prefix; or to db start.
+            let mut it;
+            if let Some(start_key) = opts.start_key {
+                // iter_from() panics if no key follows start; seek first
+                let found_start = match cursor.get(Some(&start_key), None, lmdb_sys::MDB_SET_RANGE)
+                {
+                    Err(lmdb::Error::NotFound) => return Ok(key_list),
+                    Err(_e) => return Err(Error::Backend(String::from("cursor seek failed"))),
+                    Ok((key, _val)) => key == Some(&start_key[..]),
+                };
+                it = cursor.iter_from(&start_key);
+                if found_start {
+                    it.next(); // absorb queried-for prev-key, unless since removed
+                }
+            } else if pfx_len > 0 {
+                // as for start, iter_from() panics if no key follows prefix
+                match cursor.get(Some(&prefix), None, lmdb_sys::MDB_SET_RANGE) {
+                    Err(lmdb::Error::NotFound) => return Ok(key_list),
+                    Err(_e) => return Err(Error::Backend(String::from("cursor seek failed"))),
+                    Ok(_record) => {}
+                }
+                it = cursor.iter_from(&prefix);
+            } else {
+                it = cursor.iter_start();
+            }
 
             loop {
                 // get next record
@@ -177,6 +331,13 @@ impl api::Db for LmdbWrapper {
                 let record_tuple = opt_val.unwrap();
                 let key = record_tuple.0.to_vec();
 
+                // stop, once past all keys matching prefix
+                if let Some(ub) = &upper_bound {
+                    if key >= *ub {
+                        break;
+                    }
+                }
+
                 // filter by prefix
                 let mut want_push = true;
                 if pfx_len > 0 {
@@ -189,7 +350,7 @@ impl api::Db for LmdbWrapper {
                 if want_push {
                     key_list.keys.push(record_tuple.0.to_vec());
 
-                    if key_list.keys.len() >= api::MAX_ITER_KEYS {
+                    if key_list.keys.len() >= self.max_iter_keys {
                         key_list.list_end = false;
                         break;
                     }
@@ -201,24 +362,171 @@ impl api::Db for LmdbWrapper {
 
         Ok(key_list)
     }
+
+    // seek to the first key at or past prefix, and compare
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        // see iter_keys(): cursors panic on an empty database
+        let n_records = self.stat()?.n_records;
+        if n_records == 0 || prefix.is_empty() {
+            return Ok(n_records > 0);
+        }
+
+        let txn = match self.env.begin_ro_txn() {
+            Err(_e) => return Err(Error::Backend(String::from("begin-ro-txn failed"))),
+            Ok(txn) => txn,
+        };
+        let found = {
+            // extra scope, for cursor lifetime
+            let cursor = match txn.open_ro_cursor(self.db) {
+                Err(_e) => return Err(Error::Backend(String::from("open-ro-cursor failed"))),
+                Ok(cursor) => cursor,
+            };
+            match cursor.get(Some(prefix), None, lmdb_sys::MDB_SET_RANGE) {
+                Err(lmdb::Error::NotFound) => Ok(false),
+                Err(_e) => Err(Error::Backend(String::from("cursor seek failed"))),
+                Ok((key, _val)) => Ok(key.is_some_and(|key| key.starts_with(prefix))),
+            }
+        };
+        txn.abort();
+        found
+    }
+
+    // removes the range in one write transaction
+    fn delete_prefix(&mut self, prefix: &[u8]) -> Result<u64, Error> {
+        if prefix.is_empty() {
+            return Err(Error::InvalidInput(String::from("empty prefix")));
+        }
+        self.check_writable()?;
+        // see iter_keys(): cursors panic on an empty database
+        if self.stat()?.n_records == 0 {
+            return Ok(0);
+        }
+
+        let mut txn = match self.env.begin_rw_txn() {
+            Err(_e) => return Err(Error::Backend(String::from("begin-rw-txn failed"))),
+            Ok(txn) => txn,
+        };
+        let keys: Vec<Vec<u8>> = {
+            // extra scope, for cursor lifetime
+            let mut cursor = match txn.open_ro_cursor(self.db) {
+                Err(_e) => return Err(Error::Backend(String::from("open-ro-cursor failed"))),
+                Ok(cursor) => cursor,
+            };
+            // iter_from() panics if no key follows prefix; seek first
+            match cursor.get(Some(prefix), None, lmdb_sys::MDB_SET_RANGE) {
+                Err(lmdb::Error::NotFound) => Vec::new(),
+                Err(_e) => return Err(Error::Backend(String::from("cursor seek failed"))),
+                Ok(_record) => cursor
+                    .iter_from(prefix)
+                    .map(|(key, _val)| key)
+                    .take_while(|key| key.starts_with(prefix))
+                    .map(|key| key.to_vec())
+                    .collect(),
+            }
+        };
+        for key in &keys {
+            if txn.del(self.db, key, None).is_err() {
+                return Err(Error::Backend(String::from("del failed")));
+            }
+        }
+        match txn.commit() {
+            Err(_e) => Err(Error::Backend(String::from("commit failed"))),
+            Ok(_) => Ok(keys.len() as u64),
+        }
+    }
+
+    // lmdb has no size estimate; sum the range's records
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        // see iter_keys(): cursors panic on an empty database
+        if self.stat()?.n_records == 0 || (!end.is_empty() && start >= end) {
+            return Ok(0);
+        }
+
+        let txn = match self.env.begin_ro_txn() {
+            Err(_e) => return Err(Error::Backend(String::from("begin-ro-txn failed"))),
+            Ok(txn) => txn,
+        };
+
+        let mut n_bytes = 0;
+        {
+            // extra scope, for cursor lifetime
+            let mut cursor = match txn.open_ro_cursor(self.db) {
+                Err(_e) => return Err(Error::Backend(String::from("open-ro-cursor failed"))),
+                Ok(cursor) => cursor,
+            };
+            // iter_from() panics if no key follows start; seek first
+            if !start.is_empty() {
+                match cursor.get(Some(start), None, lmdb_sys::MDB_SET_RANGE) {
+                    Err(lmdb::Error::NotFound) => return Ok(0),
+                    Err(_e) => return Err(Error::Backend(String::from("cursor seek failed"))),
+                    Ok(_record) => {}
+                }
+            }
+            let it = if start.is_empty() {
+                cursor.iter_start()
+            } else {
+                cursor.iter_from(start)
+            };
+            for (key, val) in it {
+                if !end.is_empty() && key >= end {
+                    break;
+                }
+                n_bytes += (key.len() + val.len()) as u64;
+            }
+        } // end cursor scope, before we abort txn
+
+        txn.abort();
+
+        Ok(n_bytes)
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        match self.env.sync(true) {
+            Ok(()) => Ok(()),
+            Err(_e) => Err(Error::Backend(String::from("sync failed"))),
+        }
+    }
 }
 
 pub struct LmdbDriver {}
 
 impl api::Driver for LmdbDriver {
-    fn start_db(&self, cfg: api::Config) -> Result<Box<dyn api::Db + Send>, &'static str> {
+    fn start_db(&self, cfg: api::Config) -> Result<Box<dyn api::Db + Send>, Error> {
+        manifest::validate(&cfg, "lmdb")?;
+
         let mut cfg_builder = lmdb::Environment::new();
-        if cfg.read_only {
+        if cfg.replica {
+            // no lock file:  the primary's lock is neither taken nor
+            // written, and the files may be on a read-only filesystem
+            let flags = lmdb::EnvironmentFlags::READ_ONLY | lmdb::EnvironmentFlags::NO_LOCK;
+            cfg_builder = *cfg_builder.set_flags(flags);
+        } else if cfg.read_only {
             cfg_builder = *cfg_builder.set_flags(lmdb::EnvironmentFlags::READ_ONLY);
         }
         let path = Path::new(&cfg.path);
 
         let db_env_res = cfg_builder.open(path);
         match db_env_res {
-            Err(_e) => Err("env-open failed"),
+            Err(_e) => Err(Error::Backend(String::from("env-open failed"))),
             Ok(env) => {
-                let db = env.create_db(None, lmdb::DatabaseFlags::empty()).unwrap();
-                Ok(Box::new(LmdbWrapper { env, db }) as Box<dyn api::Db + Send>)
+                // creating the db needs a write txn, refused when read-only
+                let db = match cfg.read_only {
+                    true => env.open_db(None),
+                    false => env.create_db(None, lmdb::DatabaseFlags::empty()),
+                };
+                let db = match db {
+                    Ok(db) => db,
+                    Err(_e) => return Err(Error::Backend(String::from("db-open failed"))),
+                };
+                let db = Box::new(LmdbWrapper {
+                    env,
+                    db,
+                    read_only: cfg.read_only,
+                    max_iter_keys: cfg.max_iter_keys,
+                }) as Box<dyn api::Db + Send>;
+
+                manifest::create(&cfg, "lmdb")?;
+                api::wrap_db(db, &cfg)
             }
         }
     }
@@ -229,7 +537,18 @@ pub fn new_driver() -> Box<dyn api::Driver> {
 }
 
 #[cfg(test)]
-use super::api::{Batch, ConfigBuilder};
+use super::api::Db;
+#[cfg(test)]
+use super::api::{
+    Batch, ConfigBuilder, DuplicatePolicy, KeyCase, ERR_DUPLICATE_KEY, ERR_GUARD_MISMATCH,
+    ERR_QUOTA_EXCEEDED, ERR_READ_ONLY,
+};
+#[cfg(test)]
+use super::index::{ERR_INDEX_EXISTS, ERR_INDEX_INVALID};
+#[cfg(test)]
+use super::prefix::PrefixedDb;
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
 #[cfg(test)]
 use tempdir::TempDir;
 
@@ -254,6 +573,31 @@ mod tests {
         assert_eq!(db.del(b"name"), Ok(true));
         assert_eq!(db.get(b"name"), Ok(None));
         assert_eq!(db.get(b"never_existed"), Ok(None));
+
+        // durable writes
+        assert_eq!(db.put_sync(b"name", b"grace", true), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("grace"))));
+        assert_eq!(db.del_sync(b"name", true), Ok(true));
+        assert_eq!(db.flush(), Ok(()));
+    }
+
+    #[test]
+    fn test_get_ref() {
+        let tmp_dir = TempDir::new("tgr").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // default lmdb map size is 1MB; stay well under it
+        let big_val = vec![7u8; 64 * 1024];
+        assert!(db.get_ref(b"name").unwrap().is_none());
+        assert_eq!(db.put(b"name", &big_val), Ok(true));
+
+        let val = db.get_ref(b"name").unwrap().unwrap();
+        assert_eq!(&val[..], &big_val[..]);
     }
 
     #[test]
@@ -271,6 +615,55 @@ mod tests {
         assert_eq!(db.del(b"name"), Ok(false));
     }
 
+    #[test]
+    fn test_read_only() {
+        let tmp_dir = TempDir::new("tro").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(false)
+            .build();
+        let mut writer = new_driver().start_db(db_config).unwrap();
+        assert_eq!(writer.put(b"name", b"alan"), Ok(true));
+
+        // readable alongside an open writer
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(true).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert_eq!(db.put(b"name", b"bob"), Err(ERR_READ_ONLY));
+        assert_eq!(db.del(b"name"), Err(ERR_READ_ONLY));
+        assert_eq!(db.clear(), Err(ERR_READ_ONLY));
+        let mut batch = Batch::default();
+        batch.insert(b"age", b"40");
+        assert_eq!(db.apply_batch(&batch), Err(ERR_READ_ONLY));
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert_eq!(db.iter_keys(api::IterOptions::new()).unwrap().keys.len(), 1);
+
+        assert_eq!(writer.put(b"age", b"41"), Ok(true));
+        assert_eq!(db.get(b"age"), Ok(Some(b"41".to_vec())));
+    }
+
+    #[test]
+    fn test_replica() {
+        let tmp_dir = TempDir::new("trp").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(false)
+            .build();
+        let mut writer = new_driver().start_db(db_config).unwrap();
+        assert_eq!(writer.put(b"name", b"alan"), Ok(true));
+
+        // opens while the writer holds the db, without its lock file
+        let db_config = ConfigBuilder::new().path(tmp_path).replica(true).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert_eq!(db.put(b"name", b"bob"), Err(ERR_READ_ONLY));
+        assert_eq!(writer.put(b"age", b"41"), Ok(true));
+    }
+
     #[test]
     fn test_batch() {
         let tmp_dir = TempDir::new("tb").unwrap();
@@ -294,6 +687,102 @@ mod tests {
         assert_eq!(db.get(b"city"), Ok(Some(Vec::from("anytown"))));
     }
 
+    #[test]
+    fn test_batch_order() {
+        let tmp_dir = TempDir::new("tbo").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+
+        // each mutation sees the ones before it
+        let mut batch = Batch::default();
+        batch.insert(b"age", b"25");
+        batch.remove(b"age");
+        batch.remove(b"name");
+        batch.insert(b"name", b"bob");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        assert_eq!(db.get(b"age"), Ok(None));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("bob"))));
+    }
+
+    #[test]
+    fn test_batch_duplicates() {
+        let tmp_dir = TempDir::new("tc").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"gone", b"x"), Ok(true));
+        let mut batch = Batch::default();
+        batch.insert(b"name", b"alan");
+        batch.insert(b"name", b"bob");
+        batch.insert(b"gone", b"y");
+        batch.remove(b"gone");
+
+        batch.duplicate_policy = DuplicatePolicy::Reject;
+        assert_eq!(db.apply_batch(&batch), Err(ERR_DUPLICATE_KEY));
+        assert_eq!(db.get(b"name"), Ok(None));
+        assert_eq!(db.get(b"gone"), Ok(Some(Vec::from("x"))));
+
+        batch.duplicate_policy = DuplicatePolicy::LastWins;
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("bob"))));
+        assert_eq!(db.get(b"gone"), Ok(None));
+
+        // a remove then an insert stores the insert, as applied in order
+        let mut batch = Batch::default();
+        batch.remove(b"name");
+        batch.insert(b"name", b"carol");
+        batch.insert(b"gone", b"z");
+        batch.remove(b"gone");
+        batch.insert(b"gone", b"back");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("carol"))));
+        assert_eq!(db.get(b"gone"), Ok(Some(Vec::from("back"))));
+    }
+
+    #[test]
+    fn test_batch_guard() {
+        let tmp_dir = TempDir::new("tc").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"version", b"1"), Ok(true));
+        let mut batch = Batch {
+            guard: Some((b"version".to_vec(), Some(b"0".to_vec()))),
+            ..Batch::default()
+        };
+        batch.insert(b"version", b"2");
+        batch.insert(b"name", b"alan");
+
+        assert_eq!(db.apply_batch(&batch), Err(ERR_GUARD_MISMATCH));
+        assert_eq!(db.get(b"version"), Ok(Some(Vec::from("1"))));
+        assert_eq!(db.get(b"name"), Ok(None));
+
+        batch.guard = Some((b"version".to_vec(), Some(b"1".to_vec())));
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"version"), Ok(Some(Vec::from("2"))));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
+
+        batch.guard = Some((b"lock".to_vec(), None));
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        batch.guard = Some((b"name".to_vec(), None));
+        assert_eq!(db.apply_batch(&batch), Err(ERR_GUARD_MISMATCH));
+    }
+
     #[test]
     fn test_clear() {
         let tmp_dir = TempDir::new("tc").unwrap();
@@ -312,6 +801,259 @@ mod tests {
         assert_eq!(db.get(b"age"), Ok(None));
     }
 
+    #[test]
+    fn test_put_next() {
+        let tmp_dir = TempDir::new("tpn").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        let key1 = db.put_next(b"first").unwrap();
+        let key2 = db.put_next(b"second").unwrap();
+        assert_eq!(key1, b"00000000000000000001");
+        assert_eq!(key2, b"00000000000000000002");
+        assert_eq!(db.get(&key1), Ok(Some(Vec::from("first"))));
+        assert_eq!(db.get(&key2), Ok(Some(Vec::from("second"))));
+
+        // deleted keys are not re-used
+        assert_eq!(db.del(&key2), Ok(true));
+        let key3 = db.put_next(b"third").unwrap();
+        assert_eq!(key3, b"00000000000000000003");
+    }
+
+    #[test]
+    fn test_rename() {
+        let tmp_dir = TempDir::new("trn").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"tmp:1", b"alan"), Ok(true));
+        assert_eq!(db.put(b"taken", b"bob"), Ok(true));
+
+        assert_eq!(db.rename(b"tmp:none", b"final:1", false), Ok(false));
+        assert_eq!(db.rename(b"tmp:1", b"taken", false), Ok(false));
+        assert_eq!(db.get(b"taken"), Ok(Some(Vec::from("bob"))));
+
+        assert_eq!(db.rename(b"tmp:1", b"taken", true), Ok(true));
+        assert_eq!(db.get(b"tmp:1"), Ok(None));
+        assert_eq!(db.get(b"taken"), Ok(Some(Vec::from("alan"))));
+    }
+
+    #[test]
+    fn test_change_log() {
+        let tmp_dir = TempDir::new("tcl").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .change_log_len(100)
+            .build();
+
+        {
+            let mut db = new_driver().start_db(db_config).unwrap();
+            assert_eq!(db.put(b"name", b"alan"), Ok(true));
+            assert_eq!(db.del(b"name"), Ok(true));
+        }
+
+        // change log, and its sequence numbers, persist across re-open
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path)
+            .change_log_len(100)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.changes_since(0).unwrap().changes.len(), 2);
+        assert_eq!(db.put(b"name", b"bob"), Ok(true));
+
+        let change_list = db.changes_since(2).unwrap();
+        assert_eq!(change_list.last_seq, 3);
+        assert_eq!(change_list.changes.len(), 1);
+        assert_eq!(change_list.changes[0].key, b"name".to_vec());
+    }
+
+    #[test]
+    fn test_index() {
+        let tmp_dir = TempDir::new("tix").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(false)
+            .build();
+        let lookup =
+            |db: &dyn api::Db, value: &str| db.index_lookup("city", value).unwrap().unwrap();
+
+        {
+            let mut db = new_driver().start_db(db_config).unwrap();
+            assert_eq!(db.put(b"u1", br#"{"city":"oslo"}"#), Ok(true));
+            assert_eq!(db.index_lookup("city", "oslo"), Ok(None));
+
+            // existing records are indexed on creation
+            assert_eq!(db.create_index("city", "$.city"), Ok(true));
+            assert_eq!(db.create_index("city", "$.city"), Ok(false));
+            assert_eq!(db.create_index("city", "$.town"), Err(ERR_INDEX_EXISTS));
+            assert_eq!(
+                db.create_index("bad:name", "$.city"),
+                Err(ERR_INDEX_INVALID)
+            );
+            assert_eq!(db.create_index("town", "$.a..b"), Err(ERR_INDEX_INVALID));
+            assert_eq!(lookup(&*db, "oslo"), vec![b"u1".to_vec()]);
+
+            // insert
+            assert_eq!(db.put(b"u2", br#"{"city":"oslo"}"#), Ok(true));
+            assert_eq!(db.put(b"u3", br#"{"city":"rome"}"#), Ok(true));
+            assert_eq!(db.put(b"u4", b"not json"), Ok(true));
+            assert_eq!(lookup(&*db, "oslo"), vec![b"u1".to_vec(), b"u2".to_vec()]);
+            assert_eq!(db.get(b"idx:city:rome:u3"), Ok(Some(Vec::new())));
+
+            // overwrite removes the old entry
+            assert_eq!(db.put(b"u1", br#"{"city":"rome"}"#), Ok(true));
+            assert_eq!(lookup(&*db, "oslo"), vec![b"u2".to_vec()]);
+            assert_eq!(lookup(&*db, "rome"), vec![b"u1".to_vec(), b"u3".to_vec()]);
+            assert_eq!(db.put(b"u1", b"{}"), Ok(true));
+            assert_eq!(lookup(&*db, "rome"), vec![b"u3".to_vec()]);
+
+            // delete removes it too
+            assert_eq!(db.del(b"u3"), Ok(true));
+            assert_eq!(db.del(b"u3"), Ok(false));
+            assert!(lookup(&*db, "rome").is_empty());
+            assert_eq!(db.get(b"idx:city:rome:u3"), Ok(None));
+        }
+
+        // definitions persist across re-open
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.index_lookup("town", "oslo"), Ok(None));
+
+        // a batch sees its own earlier writes
+        let mut batch = Batch::default();
+        batch.insert(b"u5", br#"{"city":"oslo"}"#);
+        batch.insert(b"u5", br#"{"city":"kyiv"}"#);
+        batch.remove(b"u2");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert!(lookup(&*db, "oslo").is_empty());
+        assert_eq!(lookup(&*db, "kyiv"), vec![b"u5".to_vec()]);
+
+        // numbers are indexed as JSON text; ':' in a value matches only itself
+        assert_eq!(db.put(b"u6", br#"{"city":"a:b"}"#), Ok(true));
+        assert_eq!(db.put(b"b:u7", br#"{"city":"a"}"#), Ok(true));
+        assert_eq!(db.put(b"u8", br#"{"city":42}"#), Ok(true));
+        assert_eq!(lookup(&*db, "a:b"), vec![b"u6".to_vec()]);
+        assert_eq!(lookup(&*db, "a"), vec![b"b:u7".to_vec()]);
+        assert_eq!(lookup(&*db, "42"), vec![b"u8".to_vec()]);
+    }
+
+    #[test]
+    fn test_value_metadata() {
+        let tmp_dir = TempDir::new("tvm").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = || {
+            ConfigBuilder::new()
+                .path(tmp_path.clone())
+                .value_metadata(true)
+                .build()
+        };
+        let meta = vec![(
+            String::from("content-type"),
+            String::from("application/json"),
+        )];
+
+        {
+            let mut db = new_driver().start_db(db_config()).unwrap();
+            assert_eq!(db.create_index("city", "$.city"), Ok(true));
+            assert_eq!(db.put_meta(b"u1", br#"{"city":"oslo"}"#, &meta), Ok(true));
+
+            // indexed by the value, not its stored form
+            assert_eq!(
+                db.index_lookup("city", "oslo"),
+                Ok(Some(vec![b"u1".to_vec()]))
+            );
+        }
+
+        // metadata persists across re-open
+        let db = new_driver().start_db(db_config()).unwrap();
+        let (val, got_meta) = db.get_meta(b"u1").unwrap().unwrap();
+        assert_eq!(&val[..], br#"{"city":"oslo"}"#);
+        assert_eq!(got_meta, meta);
+    }
+
+    #[test]
+    fn test_quota_reopen() {
+        let tmp_dir = TempDir::new("tqr").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+
+        {
+            let db_config = ConfigBuilder::new().path(tmp_path.clone()).build();
+            let mut db = new_driver().start_db(db_config).unwrap();
+            assert_eq!(db.put(b"name", b"alan"), Ok(true));
+            assert_eq!(db.put(b"age", b"25"), Ok(true));
+        }
+
+        // usage of existing records is tallied at open
+        let db_config = ConfigBuilder::new().path(tmp_path).max_keys(2).build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let usage = db.quota_usage().unwrap();
+        assert_eq!((usage.n_keys, usage.total_bytes), (2, 13));
+        assert_eq!(db.put(b"city", b"nyc"), Err(ERR_QUOTA_EXCEEDED));
+        assert_eq!(db.put(b"age", b"26"), Ok(true));
+    }
+
+    #[test]
+    fn test_manifest_conflict() {
+        let tmp_dir = TempDir::new("tmc").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+
+        // create db, recording its options
+        {
+            let db_config = ConfigBuilder::new()
+                .path(tmp_path.clone())
+                .read_only(false)
+                .build();
+            let mut db = new_driver().start_db(db_config).unwrap();
+            assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        }
+
+        // re-open with conflicting key_case
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(false)
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+        match new_driver().start_db(db_config) {
+            Err(e) => assert_eq!(
+                e,
+                Error::Backend(String::from(
+                    "db manifest: db was created with a different key_case"
+                ))
+            ),
+            Ok(_db) => panic!("unexpected success"),
+        }
+
+        // re-open with another driver
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(false)
+            .build();
+        match super::super::sled::new_driver().start_db(db_config) {
+            Err(e) => assert_eq!(
+                e,
+                Error::Backend(String::from(
+                    "db manifest: db was created by a different driver"
+                ))
+            ),
+            Ok(_db) => panic!("unexpected success"),
+        }
+
+        // re-open with matching options
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+        let db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
+    }
+
     #[test]
     fn test_stat() {
         let tmp_dir = TempDir::new("tc").unwrap();
@@ -332,6 +1074,44 @@ mod tests {
         assert_eq!(st.n_records, 2);
     }
 
+    #[test]
+    fn test_exists_multi() {
+        let tmp_dir = TempDir::new("tc").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"flag", b""), Ok(true));
+        assert_eq!(db.put(b"gone", b"x"), Ok(true));
+        assert_eq!(db.del(b"gone"), Ok(true));
+
+        let keys: Vec<&[u8]> = vec![b"flag", b"gone", b"name", b"missing"];
+        assert_eq!(db.exists_multi(&keys), Ok(vec![true, false, true, false]));
+    }
+
+    #[test]
+    fn test_get_multi() {
+        let tmp_dir = TempDir::new("tgm").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"flag", b""), Ok(true));
+
+        // one value per key, in order; missing keys None, not dropped
+        let keys = vec![b"missing".to_vec(), b"name".to_vec(), b"flag".to_vec()];
+        assert_eq!(
+            db.get_multi(&keys),
+            Ok(vec![None, Some(b"alan".to_vec()), Some(Vec::new())])
+        );
+        assert_eq!(db.get_multi(&[]), Ok(Vec::new()));
+    }
+
     #[test]
     fn test_iter() {
         let tmp_dir = TempDir::new("tc").unwrap();
@@ -368,6 +1148,69 @@ mod tests {
         assert_eq!(key_list.keys[1], b"name");
     }
 
+    #[test]
+    fn test_iter_reverse() {
+        let tmp_dir = TempDir::new("tir").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let mut opts = api::IterOptions::new();
+        opts.reverse();
+        assert_eq!(db.iter_keys(opts).unwrap().keys.len(), 0);
+
+        assert_eq!(db.put(b"2018/name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2019/fame", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2019/game", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2020/tame", b"alan"), Ok(true));
+
+        // descending, from the end, or before the start key
+        let mut opts = api::IterOptions::new();
+        opts.reverse();
+        let key_list = db.iter_keys(opts).unwrap();
+        assert!(key_list.list_end);
+        let want: Vec<&[u8]> = vec![b"2020/tame", b"2019/game", b"2019/fame", b"2018/name"];
+        assert_eq!(key_list.keys, want);
+        let mut opts = api::IterOptions::new();
+        opts.reverse().start(b"2019/game");
+        assert_eq!(db.iter_keys(opts).unwrap().keys, &want[2..]);
+        let mut opts = api::IterOptions::new();
+        opts.reverse().start(b"2019/zzz");
+        assert_eq!(db.iter_keys(opts).unwrap().keys, &want[1..]);
+
+        // within a prefix
+        let mut opts = api::IterOptions::new();
+        opts.reverse().prefix(b"2019/");
+        assert_eq!(db.iter_keys(opts).unwrap().keys, &want[1..3]);
+        let mut opts = api::IterOptions::new();
+        opts.reverse().prefix(b"2019/").start(b"2019/fame");
+        assert_eq!(db.iter_keys(opts).unwrap().keys.len(), 0);
+        let mut opts = api::IterOptions::new();
+        opts.reverse().prefix(b"2019/").start(b"2021");
+        assert_eq!(db.iter_keys(opts).unwrap().keys, &want[1..3]);
+
+        // pages of at most MAX_ITER_KEYS, as forward
+        for i in 0..api::MAX_ITER_KEYS + 10 {
+            let key = format!("page/{i:05}");
+            assert_eq!(db.put(key.as_bytes(), b"x"), Ok(true));
+        }
+        let mut opts = api::IterOptions::new();
+        opts.reverse().prefix(b"page/");
+        let page = db.iter_keys(opts.clone()).unwrap();
+        assert!(!page.list_end);
+        assert_eq!(page.keys.len(), api::MAX_ITER_KEYS);
+        assert_eq!(
+            page.keys[0],
+            format!("page/{:05}", api::MAX_ITER_KEYS + 9).as_bytes()
+        );
+        let page = db
+            .iter_keys(opts.continue_after(page.keys.last().unwrap()))
+            .unwrap();
+        assert!(page.list_end);
+        assert_eq!(page.keys.len(), 10);
+        assert_eq!(page.keys[9], b"page/00000");
+    }
+
     #[test]
     fn test_iter_prefix() {
         let tmp_dir = TempDir::new("tc").unwrap();
@@ -418,5 +1261,142 @@ mod tests {
             String::from_utf8_lossy(&key_list.keys[2]),
             String::from("2019/lame")
         );
+
+        // iterate with prefix ending in 0xff
+        assert_eq!(db.put(b"b\xff", b"alan"), Ok(true));
+        assert_eq!(db.put(b"b\xff\x01", b"alan"), Ok(true));
+        assert_eq!(db.put(b"b\xff\xff", b"alan"), Ok(true));
+        assert_eq!(db.put(b"c", b"alan"), Ok(true));
+
+        let mut opts = api::IterOptions::new();
+        opts.prefix(b"b\xff");
+
+        let key_list = db.iter_keys(opts).unwrap();
+        assert!(key_list.list_end);
+        assert_eq!(key_list.keys.len(), 3);
+        assert_eq!(key_list.keys[0], b"b\xff");
+        assert_eq!(key_list.keys[1], b"b\xff\x01");
+        assert_eq!(key_list.keys[2], b"b\xff\xff");
+    }
+
+    #[test]
+    fn test_iter_removed_start() {
+        let tmp_dir = TempDir::new("tirs").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put(b"b", b"2"), Ok(true));
+        assert_eq!(db.put(b"c", b"3"), Ok(true));
+
+        // continuing after a key removed since listed skips no other key
+        assert_eq!(db.del(b"b"), Ok(true));
+        let mut opts = api::IterOptions::new();
+        opts.start(b"b");
+        let key_list = db.iter_keys(opts).unwrap();
+        assert_eq!(key_list.keys, vec![b"c".to_vec()]);
+        assert!(key_list.list_end);
+
+        // ... nor fails, if it was the last key
+        assert_eq!(db.del(b"c"), Ok(true));
+        let mut opts = api::IterOptions::new();
+        opts.start(b"c");
+        let key_list = db.iter_keys(opts).unwrap();
+        assert!(key_list.keys.is_empty());
+        assert!(key_list.list_end);
+    }
+
+    #[test]
+    fn test_size_between() {
+        let tmp_dir = TempDir::new("tsb").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.size_between(b"", b""), Ok(0));
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put(b"b", b"22"), Ok(true));
+        assert_eq!(db.put(b"c", b"333"), Ok(true));
+
+        // start inclusive, end exclusive; empty end is unbounded
+        assert_eq!(db.size_between(b"", b""), Ok(9));
+        assert_eq!(db.size_between(b"b", b""), Ok(7));
+        assert_eq!(db.size_between(b"a", b"c"), Ok(5));
+        assert_eq!(db.size_between(b"bb", b"z"), Ok(4));
+
+        // empty ranges
+        assert_eq!(db.size_between(b"b", b"b"), Ok(0));
+        assert_eq!(db.size_between(b"c", b"a"), Ok(0));
+        assert_eq!(db.size_between(b"d", b""), Ok(0));
+    }
+
+    #[test]
+    fn test_has_prefix() {
+        let tmp_dir = TempDir::new("thp").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // an empty prefix matches any key, so none in an empty db
+        assert_eq!(db.has_prefix(b""), Ok(false));
+        assert_eq!(db.put(b"user:1", b"a"), Ok(true));
+        assert_eq!(db.put(b"user:2", b"b"), Ok(true));
+        assert_eq!(db.has_prefix(b""), Ok(true));
+
+        assert_eq!(db.has_prefix(b"user:"), Ok(true));
+        assert_eq!(db.has_prefix(b"user:2"), Ok(true));
+        assert_eq!(db.has_prefix(b"user:20"), Ok(false));
+        assert_eq!(db.has_prefix(b"use"), Ok(true));
+        assert_eq!(db.has_prefix(b"a"), Ok(false));
+        assert_eq!(db.has_prefix(b"z"), Ok(false));
+
+        assert_eq!(db.del(b"user:1"), Ok(true));
+        assert_eq!(db.del(b"user:2"), Ok(true));
+        assert_eq!(db.has_prefix(b"user:"), Ok(false));
+    }
+
+    #[test]
+    fn test_delete_prefix() {
+        let tmp_dir = TempDir::new("tdp").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let env = lmdb::Environment::new().open(Path::new(&tmp_path)).unwrap();
+        let db = env.create_db(None, lmdb::DatabaseFlags::empty()).unwrap();
+        let raw = LmdbWrapper {
+            env,
+            db,
+            read_only: false,
+            max_iter_keys: api::MAX_ITER_KEYS,
+        };
+
+        // tenants sharing the backend:  clearing one removes its range
+        let shared = Arc::new(Mutex::new(raw));
+        let mut t1 = PrefixedDb::new(Arc::clone(&shared), "t1").unwrap();
+        let mut t2 = PrefixedDb::new(Arc::clone(&shared), "t2").unwrap();
+        for key in [&b"a"[..], b"b", b"c"] {
+            assert_eq!(t1.put(key, b"x"), Ok(true));
+            assert_eq!(t2.put(key, b"y"), Ok(true));
+        }
+        assert_eq!(t1.clear(), Ok(true));
+        assert_eq!(t1.get(b"a"), Ok(None));
+        assert_eq!(t2.get(b"a"), Ok(Some(b"y".to_vec())));
+
+        let mut raw = shared.lock().unwrap();
+        assert_eq!(raw.delete_prefix(b"4:t2:"), Ok(0));
+        assert_eq!(raw.delete_prefix(b"2:t2:"), Ok(3));
+        assert_eq!(raw.stat().unwrap().n_records, 0);
+        assert!(matches!(
+            raw.delete_prefix(b""),
+            Err(Error::InvalidInput(_))
+        ));
     }
 }
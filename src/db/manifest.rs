@@ -0,0 +1,97 @@
+use super::api;
+use super::Error;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+
+// file within db directory, recording options fixed at db creation
+pub const MANIFEST_FN: &str = "kvdbd-manifest.json";
+
+#[derive(Serialize, Deserialize, PartialEq)]
+struct Manifest {
+    driver: String,   // db backend, e.g. "sled"
+    key_case: String, // key case folding applied to stored keys
+}
+
+impl Manifest {
+    fn new(cfg: &api::Config, driver: &str) -> Manifest {
+        Manifest {
+            driver: driver.to_string(),
+            key_case: match cfg.key_case {
+                api::KeyCase::CaseSensitive => String::from("case_sensitive"),
+                api::KeyCase::LowercaseFold => String::from("lowercase_fold"),
+            },
+        }
+    }
+}
+
+// manifest in the db directory at db_path, or None if there is none
+fn read(db_path: &str) -> Result<Option<Manifest>, Error> {
+    let path = Path::new(db_path).join(MANIFEST_FN);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    let text = match fs::read_to_string(&path) {
+        Ok(t) => t,
+        Err(_e) => return Err(Error::Backend(String::from("db manifest: read failed"))),
+    };
+    match serde_json::from_str(&text) {
+        Ok(m) => Ok(Some(m)),
+        Err(_e) => Err(Error::Corruption(String::from("db manifest: invalid"))),
+    }
+}
+
+/// Driver name and key case recorded when the db at db_path was
+/// created, or None if it has no manifest.
+pub fn stored_options(db_path: &str) -> Result<Option<(String, api::KeyCase)>, Error> {
+    let stored = match read(db_path)? {
+        None => return Ok(None),
+        Some(m) => m,
+    };
+    let key_case = match stored.key_case.as_str() {
+        "case_sensitive" => api::KeyCase::CaseSensitive,
+        "lowercase_fold" => api::KeyCase::LowercaseFold,
+        _ => return Err(Error::Corruption(String::from("db manifest: invalid"))),
+    };
+
+    Ok(Some((stored.driver, key_case)))
+}
+
+/// Verify that the options in cfg match those recorded when the db was
+/// created.  Call before opening the db.  A db without a manifest passes.
+pub fn validate(cfg: &api::Config, driver: &str) -> Result<(), Error> {
+    let stored = match read(&cfg.path)? {
+        None => return Ok(()),
+        Some(m) => m,
+    };
+
+    let wanted = Manifest::new(cfg, driver);
+    if stored.driver != wanted.driver {
+        return Err(Error::Backend(String::from(
+            "db manifest: db was created by a different driver",
+        )));
+    }
+    if stored.key_case != wanted.key_case {
+        return Err(Error::Backend(String::from(
+            "db manifest: db was created with a different key_case",
+        )));
+    }
+
+    Ok(())
+}
+
+/// Record creation options in db directory, if not already recorded.
+/// Call after opening the db.  Read-only dbs are not modified.
+pub fn create(cfg: &api::Config, driver: &str) -> Result<(), Error> {
+    let path = Path::new(&cfg.path).join(MANIFEST_FN);
+    if cfg.read_only || path.exists() {
+        return Ok(());
+    }
+
+    let text = serde_json::to_string_pretty(&Manifest::new(cfg, driver)).unwrap();
+    match fs::write(&path, text) {
+        Ok(_) => Ok(()),
+        Err(_e) => Err(Error::Backend(String::from("db manifest: write failed"))),
+    }
+}
@@ -0,0 +1,190 @@
+use super::api;
+use super::snapshot::CopySnapshot;
+use super::Error;
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+/// Db held in memory, e.g. for unit tests of code using a Db.  Records
+/// are not persisted, and are dropped with the db.
+pub struct MemDb {
+    db: HashMap<Vec<u8>, Arc<[u8]>>,
+    max_iter_keys: usize, // per Config.max_iter_keys
+}
+
+impl Default for MemDb {
+    fn default() -> MemDb {
+        MemDb {
+            db: HashMap::new(),
+            max_iter_keys: api::MAX_ITER_KEYS,
+        }
+    }
+}
+
+impl MemDb {
+    pub fn new() -> MemDb {
+        MemDb::default()
+    }
+}
+
+impl api::Db for MemDb {
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.db.clear();
+        Ok(true)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.db.get(key) {
+            None => Ok(None),
+            Some(val) => Ok(Some(val.to_vec())),
+        }
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        match self.db.get(key) {
+            None => Ok(None),
+            Some(val) => Ok(Some(api::ValueRef::new(Arc::clone(val)))),
+        }
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        Ok(api::DbStat {
+            n_records: self.db.len() as u64,
+        })
+    }
+
+    fn len(&self) -> Result<u64, Error> {
+        Ok(self.db.len() as u64)
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.max_iter_keys
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        let mut key_list = api::KeyList {
+            keys: Vec::new(),
+            list_end: true,
+        };
+
+        // HashMap has no order; sort, then walk in either direction
+        let prefix = opts.prefix.clone().unwrap_or_default();
+        let mut keys: Vec<&Vec<u8>> = self
+            .db
+            .keys()
+            .filter(|key| key.starts_with(&prefix))
+            .filter(|key| match &opts.start_key {
+                None => true,
+                Some(start_key) if opts.reverse => *key < start_key,
+                Some(start_key) => *key > start_key,
+            })
+            .collect();
+        keys.sort();
+        if opts.reverse {
+            keys.reverse();
+        }
+
+        for key in keys {
+            key_list.keys.push(key.clone());
+
+            if key_list.keys.len() >= self.max_iter_keys {
+                key_list.list_end = false;
+                break;
+            }
+        }
+
+        Ok(key_list)
+    }
+
+    // unordered; stops at the first match, without sorting
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        Ok(self.db.keys().any(|key| key.starts_with(prefix)))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.db.insert(key.to_vec(), Arc::from(val));
+        Ok(true)
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        match self.db.remove(key) {
+            None => Ok(false),
+            Some(_v) => Ok(true),
+        }
+    }
+
+    fn put_if_absent(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        match self.db.entry(key.to_vec()) {
+            Entry::Occupied(_entry) => Ok(false),
+            Entry::Vacant(entry) => {
+                entry.insert(Arc::from(val));
+                Ok(true)
+            }
+        }
+    }
+
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        let val = self
+            .db
+            .entry(key.to_vec())
+            .or_insert_with(|| Arc::from(init));
+        Ok(val.to_vec())
+    }
+
+    fn delete_prefix(&mut self, prefix: &[u8]) -> Result<u64, Error> {
+        if prefix.is_empty() {
+            return Err(Error::InvalidInput(String::from("empty prefix")));
+        }
+        let n_keys = self.db.len();
+        self.db.retain(|key, _val| !key.starts_with(prefix));
+        Ok((n_keys - self.db.len()) as u64)
+    }
+
+    // values are shared with the snapshot, which copies only the keys
+    fn snapshot(&self) -> Result<Box<dyn api::Snapshot + Send>, Error> {
+        let records: BTreeMap<Vec<u8>, Arc<[u8]>> = self
+            .db
+            .iter()
+            .map(|(key, val)| (key.clone(), Arc::clone(val)))
+            .collect();
+        Ok(Box::new(CopySnapshot::new(records, self.max_iter_keys)))
+    }
+
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        batch.check()?;
+        if let Some(key) = batch.guard_key() {
+            batch.check_guard(self.db.get(key).map(|val| &val[..]))?;
+        }
+        for dbm in &batch.ops {
+            match dbm.op {
+                api::MutationOp::Insert => {
+                    let val: Vec<u8> = dbm.value.clone().unwrap();
+                    self.db.insert(dbm.key.to_vec(), Arc::from(val));
+                }
+                api::MutationOp::Remove => {
+                    self.db.remove(&dbm.key);
+                }
+            }
+        }
+
+        Ok(true)
+    }
+}
+
+pub struct MemDriver {}
+
+impl api::Driver for MemDriver {
+    fn start_db(&self, cfg: api::Config) -> Result<Box<dyn api::Db + Send>, Error> {
+        let db = Box::new(MemDb {
+            db: HashMap::new(),
+            max_iter_keys: cfg.max_iter_keys,
+        }) as Box<dyn api::Db + Send>;
+        api::wrap_db(db, &cfg)
+    }
+}
+
+/// Driver of in-memory dbs, each empty when started, with cfg's layers
+/// (quota, change log, and so on) wrapped around it as for any backend.
+pub fn new_driver() -> Box<dyn api::Driver> {
+    Box::new(MemDriver {})
+}
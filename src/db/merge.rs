@@ -0,0 +1,231 @@
+use super::api;
+use super::Error;
+use std::time::Instant;
+
+/// Db layer which applies Db::merge() by the merge operator configured
+/// (Config.merge_fn), rather than last-write-wins:  key's value is read,
+/// and the operator's result stored, keeping any metadata.
+///
+/// Neither backend has a native merge operator, to defer merging until
+/// the value is read, as RocksDB does; each merge is a read-modify-write,
+/// atomic as &mut self excludes other writers.  Operands are not logged
+/// as such:  the change log, quota and so on see a put of the merged
+/// value.
+pub struct MergeDb<T: api::Db> {
+    inner: T,
+    merge_fn: api::MergeFn,
+}
+
+impl<T: api::Db> MergeDb<T> {
+    pub fn new(inner: T, merge_fn: api::MergeFn) -> MergeDb<T> {
+        MergeDb { inner, merge_fn }
+    }
+}
+
+impl<T: api::Db> api::Db for MergeDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        self.inner.apply_batch(batch)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.inner.put(key, val)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.inner.get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn apply_batch_until(&mut self, batch: &api::Batch, deadline: Instant) -> Result<usize, Error> {
+        self.inner.apply_batch_until(batch, deadline)
+    }
+
+    fn put_next(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        self.inner.put_next(val)
+    }
+
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> Result<bool, Error> {
+        self.inner.rename(from, to, overwrite)
+    }
+
+    fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<(), Error> {
+        self.inner.swap(key_a, key_b)
+    }
+
+    fn del_if_empty(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del_if_empty(key)
+    }
+
+    fn del_many(&mut self, keys: &[Vec<u8>]) -> Result<u64, Error> {
+        self.inner.del_many(keys)
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        self.inner.compare_and_swap(key, expected, new)
+    }
+
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        self.inner.get_or_init(key, init)
+    }
+
+    fn merge(&mut self, key: &[u8], operand: &[u8]) -> Result<(), Error> {
+        api::merge_value(&mut self.inner, key, operand, self.merge_fn)
+    }
+
+    fn add_sharded(&mut self, key: &[u8], delta: i64, shards: u32) -> Result<bool, Error> {
+        self.inner.add_sharded(key, delta, shards)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        self.inner.put_expire_at(key, val, unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.inner.iter_entries(opts, max_bytes)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.snapshot_get(id, key)
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.snapshot_iter_keys(id, start)
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.inner.create_index(name, json_path)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        self.inner.index_lookup(name, value)
+    }
+
+    fn degraded(&self) -> bool {
+        self.inner.degraded()
+    }
+
+    fn reset_degraded(&mut self) {
+        self.inner.reset_degraded()
+    }
+
+    fn put_meta(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        meta: &[(String, String)],
+    ) -> Result<bool, Error> {
+        self.inner.put_meta(key, val, meta)
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<(api::ValueRef, api::Metadata)>, Error> {
+        self.inner.get_meta(key)
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+
+    fn put_sync(&mut self, key: &[u8], val: &[u8], sync: bool) -> Result<bool, Error> {
+        self.inner.put_sync(key, val, sync)
+    }
+
+    fn del_sync(&mut self, key: &[u8], sync: bool) -> Result<bool, Error> {
+        self.inner.del_sync(key, sync)
+    }
+}
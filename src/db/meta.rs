@@ -0,0 +1,367 @@
+use super::api;
+use super::Error;
+use std::borrow::Cow;
+use std::time::Instant;
+
+// prefix of a stored value carrying metadata, followed by the metadata
+// length (u32, big-endian), the metadata (JSON array of [name, value]
+// pairs), then the value itself
+const META_MAGIC: &[u8] = b"\xffkvdbd:meta\x00";
+
+fn has_header(stored: &[u8]) -> bool {
+    stored.starts_with(META_MAGIC)
+}
+
+// stored value and metadata, split; values without a header have none
+fn split(stored: &[u8]) -> Result<(&[u8], &[u8]), Error> {
+    if !has_header(stored) {
+        return Ok((stored, b""));
+    }
+    let rest = &stored[META_MAGIC.len()..];
+    if rest.len() < 4 {
+        return Err(Error::Corruption(String::from("invalid value metadata")));
+    }
+    let mut len_bytes = [0u8; 4];
+    len_bytes.copy_from_slice(&rest[..4]);
+    let meta_len = u32::from_be_bytes(len_bytes) as usize;
+    if rest.len() - 4 < meta_len {
+        return Err(Error::Corruption(String::from("invalid value metadata")));
+    }
+    Ok((&rest[4 + meta_len..], &rest[4..4 + meta_len]))
+}
+
+/// Value stored within stored, without any metadata header.  For layers
+/// below MetaDb, which see stored values as written, e.g. to index them.
+pub fn value_of(stored: &[u8]) -> &[u8] {
+    match split(stored) {
+        Ok((val, _meta)) => val,
+        Err(_e) => stored,
+    }
+}
+
+/// Stored form of val, with metadata.  A value without metadata is
+/// stored as-is, unless it begins with the header prefix, when it gets
+/// an empty header, so that every stored value decodes unambiguously.
+pub fn encode<'a>(val: &'a [u8], meta: &[(String, String)]) -> Cow<'a, [u8]> {
+    if meta.is_empty() && !has_header(val) {
+        return Cow::Borrowed(val);
+    }
+    let enc_meta = if meta.is_empty() {
+        Vec::new()
+    } else {
+        serde_json::to_vec(meta).unwrap()
+    };
+    let mut stored = META_MAGIC.to_vec();
+    stored.extend_from_slice(&(enc_meta.len() as u32).to_be_bytes());
+    stored.extend_from_slice(&enc_meta);
+    stored.extend_from_slice(val);
+    Cow::Owned(stored)
+}
+
+fn decode_meta(enc_meta: &[u8]) -> Result<api::Metadata, Error> {
+    if enc_meta.is_empty() {
+        return Ok(Vec::new());
+    }
+    serde_json::from_slice(enc_meta)
+        .map_err(|_e| Error::Corruption(String::from("invalid value metadata")))
+}
+
+fn check_meta(meta: &[(String, String)]) -> Result<(), Error> {
+    let n_bytes: usize = meta.iter().map(|(name, val)| name.len() + val.len()).sum();
+    if meta.len() > api::MAX_META_ENTRIES || n_bytes > api::MAX_META_BYTES {
+        return Err(api::ERR_META_TOO_LARGE);
+    }
+    Ok(())
+}
+
+fn strip(stored: Vec<u8>) -> Vec<u8> {
+    if has_header(&stored) {
+        value_of(&stored).to_vec()
+    } else {
+        stored
+    }
+}
+
+// batch with each inserted value in stored form; None if no value needs
+// a header, so the batch may be applied as-is
+fn encode_batch(batch: &api::Batch) -> Option<api::Batch> {
+    let needs_header = |val: &Option<Vec<u8>>| val.as_deref().is_some_and(has_header);
+    let guard_needs_header = batch
+        .guard
+        .as_ref()
+        .is_some_and(|(_key, expected)| needs_header(expected));
+    if !guard_needs_header && !batch.ops.iter().any(|dbm| needs_header(&dbm.value)) {
+        return None;
+    }
+
+    let encode_opt = |val: &Option<Vec<u8>>| val.as_ref().map(|v| encode(v, &[]).into_owned());
+    Some(api::Batch {
+        ops: batch
+            .ops
+            .iter()
+            .map(|dbm| api::Mutation {
+                op: dbm.op.clone(),
+                key: dbm.key.clone(),
+                value: encode_opt(&dbm.value),
+            })
+            .collect(),
+        duplicate_policy: batch.duplicate_policy,
+        guard: batch
+            .guard
+            .as_ref()
+            .map(|(key, expected)| (key.clone(), encode_opt(expected))),
+    })
+}
+
+/// Db layer storing small metadata, e.g. a content type, with values.
+///
+/// A value written with metadata by put_meta() is stored with a header
+/// holding the metadata, in the same record, so metadata is written,
+/// renamed, swapped and removed atomically with its value.  Reads strip
+/// the header; get_meta() returns the metadata too.  Any other write of
+/// a key replaces its metadata with none.  Layers below see stored
+/// values, headers included:  quotas count metadata bytes, and a batch
+/// guard's expected value never matches a value stored with metadata.
+pub struct MetaDb<T: api::Db> {
+    inner: T,
+}
+
+impl<T: api::Db> MetaDb<T> {
+    pub fn new(inner: T) -> MetaDb<T> {
+        MetaDb { inner }
+    }
+}
+
+impl<T: api::Db> api::Db for MetaDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        let encoded = encode_batch(batch);
+        self.inner.apply_batch(encoded.as_ref().unwrap_or(batch))
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.inner.get(key)?.map(strip))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.inner.put(key, &encode(val, &[]))
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        Ok(self.inner.get_ref(key)?.map(|val| {
+            if has_header(&val) {
+                api::ValueRef::new(value_of(&val).to_vec())
+            } else {
+                val
+            }
+        }))
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let vals = self.inner.get_multi(keys)?;
+        Ok(vals.into_iter().map(|val| val.map(strip)).collect())
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn apply_batch_until(&mut self, batch: &api::Batch, deadline: Instant) -> Result<usize, Error> {
+        let encoded = encode_batch(batch);
+        self.inner
+            .apply_batch_until(encoded.as_ref().unwrap_or(batch), deadline)
+    }
+
+    fn put_next(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        self.inner.put_next(&encode(val, &[]))
+    }
+
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> Result<bool, Error> {
+        self.inner.rename(from, to, overwrite)
+    }
+
+    fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<(), Error> {
+        self.inner.swap(key_a, key_b)
+    }
+
+    fn del_if_empty(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del_if_empty(key)
+    }
+
+    fn del_many(&mut self, keys: &[Vec<u8>]) -> Result<u64, Error> {
+        self.inner.del_many(keys)
+    }
+
+    // expected is compared with the value as read, without its metadata;
+    // the swap is made against the stored value, header included
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let stored = self.inner.get(key)?;
+        if stored.as_deref().map(value_of) != expected {
+            return Ok(false);
+        }
+        let new = new.map(|val| encode(val, &[]));
+        self.inner
+            .compare_and_swap(key, stored.as_deref(), new.as_deref())
+    }
+
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        Ok(strip(self.inner.get_or_init(key, &encode(init, &[]))?))
+    }
+
+    fn add_sharded(&mut self, key: &[u8], delta: i64, shards: u32) -> Result<bool, Error> {
+        self.inner.add_sharded(key, delta, shards)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        self.inner.put_expire_at(key, &encode(val, &[]), unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        let mut entry_list = self.inner.iter_entries(opts, max_bytes)?;
+        for (_key, val) in &mut entry_list.entries {
+            if has_header(val) {
+                *val = value_of(val).to_vec();
+            }
+        }
+        Ok(entry_list)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.inner.snapshot_get(id, key)?.map(strip))
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.snapshot_iter_keys(id, start)
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.inner.create_index(name, json_path)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        self.inner.index_lookup(name, value)
+    }
+
+    fn degraded(&self) -> bool {
+        self.inner.degraded()
+    }
+
+    fn reset_degraded(&mut self) {
+        self.inner.reset_degraded()
+    }
+
+    fn put_meta(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        meta: &[(String, String)],
+    ) -> Result<bool, Error> {
+        check_meta(meta)?;
+        self.inner.put(key, &encode(val, meta))
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<(api::ValueRef, api::Metadata)>, Error> {
+        let stored = match self.inner.get_ref(key)? {
+            None => return Ok(None),
+            Some(stored) => stored,
+        };
+        if !has_header(&stored) {
+            return Ok(Some((stored, Vec::new())));
+        }
+        let (val, enc_meta) = split(&stored)?;
+        Ok(Some((
+            api::ValueRef::new(val.to_vec()),
+            decode_meta(enc_meta)?,
+        )))
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        let modified = self.inner.get_modified(key)?;
+        Ok(modified.map(|(stored, unix_ms)| (strip(stored), unix_ms)))
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        let versioned = self.inner.get_versioned(key)?;
+        Ok(versioned.map(|(stored, version)| (strip(stored), version)))
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
@@ -1,3 +1,90 @@
 pub mod api;
+pub mod asyncdb;
+pub mod audit;
+pub mod bytesenc;
+pub mod changelog;
+pub mod checksum;
+pub mod compress;
+pub mod degrade;
+pub mod encrypt;
+pub mod error;
+pub mod evict;
+pub mod expiry;
+pub mod groupcommit;
+pub mod histogram;
+pub mod index;
+pub mod iter;
+pub mod keycase;
+pub mod limit;
+#[cfg(feature = "lmdb")]
 pub mod lmdb;
+pub mod manifest;
+#[cfg(any(test, feature = "mem"))]
+pub mod mem;
+pub mod merge;
+pub mod meta;
+pub mod modtime;
+pub mod prefix;
+pub mod quota;
+pub mod sha256;
+pub mod singleflight;
+#[cfg(feature = "sled")]
 pub mod sled;
+pub mod slowlog;
+pub mod snapshot;
+pub mod sync;
+pub mod timeout;
+pub mod transform;
+pub mod txn;
+pub mod version;
+pub mod watch;
+
+pub use error::Error;
+
+/// Names of the drivers compiled in, per the sled, lmdb and mem features.
+pub const DRIVERS: &[&str] = &[
+    #[cfg(feature = "sled")]
+    "sled",
+    #[cfg(feature = "lmdb")]
+    "lmdb",
+    #[cfg(any(test, feature = "mem"))]
+    "mem",
+];
+
+/// Driver named name, one of DRIVERS, e.g. as chosen by a config file;
+/// an error, listing DRIVERS, if it is not compiled in.
+pub fn driver_for(name: &str) -> Result<Box<dyn api::Driver>, Error> {
+    match name {
+        #[cfg(feature = "sled")]
+        "sled" => Ok(sled::new_driver()),
+        #[cfg(feature = "lmdb")]
+        "lmdb" => Ok(lmdb::new_driver()),
+        #[cfg(any(test, feature = "mem"))]
+        "mem" => Ok(mem::new_driver()),
+        _ => Err(Error::InvalidInput(format!(
+            "unknown db driver {}, not one of: {}",
+            name,
+            DRIVERS.join(", ")
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_driver_for() {
+        let db_config = api::ConfigBuilder::new()
+            .path("/dev/null".to_string())
+            .build();
+        let mut db = driver_for("mem").unwrap().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"k", b"v"), Ok(true));
+
+        // unknown drivers list those compiled in
+        let err = driver_for("rocksdb").err().unwrap();
+        assert!(matches!(&err, Error::InvalidInput(msg) if msg.contains("mem")));
+        assert!(err.to_string().contains("rocksdb"));
+        assert!(DRIVERS.iter().all(|name| driver_for(name).is_ok()));
+    }
+}
@@ -0,0 +1,160 @@
+use super::api;
+use super::Error;
+
+// prefix of a stored value carrying its modification time, followed by
+// the time (u64, big-endian, ms since the Unix epoch), then the value
+const MODTIME_MAGIC: &[u8] = b"\xffkvdbd:mtime\x00";
+
+const MODTIME_LEN: usize = 8;
+
+fn stamp(val: &[u8], unix_ms: u64) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(MODTIME_MAGIC.len() + MODTIME_LEN + val.len());
+    stored.extend_from_slice(MODTIME_MAGIC);
+    stored.extend_from_slice(&unix_ms.to_be_bytes());
+    stored.extend_from_slice(val);
+    stored
+}
+
+// stored value and its modification time, split; values without a
+// header, stored before times were tracked, were modified at 0
+fn split(mut stored: Vec<u8>) -> Result<(Vec<u8>, u64), Error> {
+    if !stored.starts_with(MODTIME_MAGIC) {
+        return Ok((stored, 0));
+    }
+    let header_len = MODTIME_MAGIC.len() + MODTIME_LEN;
+    if stored.len() < header_len {
+        return Err(Error::Corruption(String::from("invalid modification time")));
+    }
+    let mut time_bytes = [0u8; MODTIME_LEN];
+    time_bytes.copy_from_slice(&stored[MODTIME_MAGIC.len()..header_len]);
+    stored.drain(..header_len);
+    Ok((stored, u64::from_be_bytes(time_bytes)))
+}
+
+/// Db layer recording when each value was last written, returned with it
+/// by get_modified().  Enabled per db by Config.track_modified.
+///
+/// Each write of a value - put, batch insert, or any write made of them,
+/// such as a rename - stores it with a small header holding the time of
+/// the write, in milliseconds since the Unix epoch, by the system clock
+/// (api::unix_now_ms()).  The clock is not monotonic:  if it steps back,
+/// e.g. across a restart on another host, a later write may be stamped
+/// earlier than the one it replaced.  touch() rewrites no value, so
+/// does not change its time.  Values stored before times were tracked
+/// lack the header, and read back as stored, modified at 0; a value
+/// stored beforehand which happens to begin with the header's reserved
+/// byte prefix would be misread.
+///
+/// Below the change log and indexes, so that every layer but transforms
+/// sees values without the header, and above transforms, so that it is
+/// compressed, encrypted and checksummed with the value.  As in
+/// TransformDb, a batch guard is checked here, against the value
+/// without its header.
+pub struct ModTimeDb<T: api::Db> {
+    inner: T,
+}
+
+impl<T: api::Db> ModTimeDb<T> {
+    pub fn new(inner: T) -> ModTimeDb<T> {
+        ModTimeDb { inner }
+    }
+}
+
+impl<T: api::Db> api::Db for ModTimeDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        batch.check()?;
+
+        // &mut self:  no other write to this db between check and apply
+        if let Some(key) = batch.guard_key() {
+            batch.check_guard(self.get(key)?.as_deref())?;
+        }
+        let now_ms = api::unix_now_ms();
+        let stamped = api::Batch {
+            ops: batch
+                .ops
+                .iter()
+                .map(|dbm| api::Mutation {
+                    op: dbm.op.clone(),
+                    key: dbm.key.clone(),
+                    value: dbm.value.as_ref().map(|val| stamp(val, now_ms)),
+                })
+                .collect(),
+            duplicate_policy: batch.duplicate_policy,
+            guard: None,
+        };
+        self.inner.apply_batch(&stamped)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.get_modified(key)?.map(|(val, _unix_ms)| val))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.inner.put(key, &stamp(val, api::unix_now_ms()))
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        let stored = stamp(val, api::unix_now_ms());
+        self.inner.put_expire_at(key, &stored, unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    // sizes as stored, with headers
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        match self.inner.get(key)? {
+            None => Ok(None),
+            Some(stored) => Ok(Some(split(stored)?)),
+        }
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
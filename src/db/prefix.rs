@@ -0,0 +1,188 @@
+use super::api::{self, DbExt};
+use super::Error;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// Key prefix of namespace db_id within a shared db:  the name's length,
+/// then the name, e.g. "3:db1:".  Leading with the length keeps prefixes
+/// of different names disjoint, so that "db1" never lists keys of "db10".
+pub fn namespace_prefix(db_id: &str) -> Vec<u8> {
+    format!("{}:{}:", db_id.len(), db_id).into_bytes()
+}
+
+/// Db layer which stores its keys within one namespace of a db shared
+/// with other namespaces, e.g. to serve several dbs from one backend,
+/// rather than open a backend (files, caches) per db.
+///
+/// Each key is stored under the namespace prefix, which is stripped
+/// again from keys listed.  Listings are bounded by the prefix, so never
+/// return keys of other namespaces, and clear() removes only this
+/// namespace's keys.  The shared db is locked for each operation; a
+/// lock poisoned by a panic is reported as api::ERR_LOCK_POISONED.
+///
+/// Layers configured per db (quota, change log, and so on) are wrapped
+/// around the PrefixedDb, by api::wrap_db(), and see only its keys.
+/// Operations without an override here use the Db defaults, built on
+/// get, put, iter_keys and the like, so are namespaced alike.
+pub struct PrefixedDb<T: api::Db> {
+    inner: Arc<Mutex<T>>,
+    prefix: Vec<u8>,
+    max_iter_keys: usize,
+}
+
+impl<T: api::Db> PrefixedDb<T> {
+    /// Namespace db_id, prefixed per namespace_prefix(), of shared db
+    /// inner.
+    pub fn new(inner: Arc<Mutex<T>>, db_id: &str) -> Result<PrefixedDb<T>, Error> {
+        let max_iter_keys = api::lock_shared(&inner, |_db| false)?.max_iter_keys();
+        Ok(PrefixedDb {
+            inner,
+            prefix: namespace_prefix(db_id),
+            max_iter_keys,
+        })
+    }
+
+    fn lock(&self) -> Result<MutexGuard<'_, T>, Error> {
+        api::lock_shared(&self.inner, |_db| false)
+    }
+
+    fn key(&self, key: &[u8]) -> Vec<u8> {
+        let mut pkey = Vec::with_capacity(self.prefix.len() + key.len());
+        pkey.extend_from_slice(&self.prefix);
+        pkey.extend_from_slice(key);
+        pkey
+    }
+
+    fn strip(&self, mut key_list: api::KeyList) -> api::KeyList {
+        for key in &mut key_list.keys {
+            key.drain(..self.prefix.len());
+        }
+        key_list
+    }
+}
+
+impl<T: api::Db> api::Db for PrefixedDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        let mut pbatch = batch.clone();
+        for dbm in &mut pbatch.ops {
+            dbm.key = self.key(&dbm.key);
+        }
+        if let Some((key, _expected)) = &mut pbatch.guard {
+            *key = self.key(key);
+        }
+        self.lock()?.apply_batch(&pbatch)
+    }
+
+    // removes this namespace only
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.lock()?.delete_prefix(&self.prefix)?;
+        Ok(true)
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.lock()?.del(&self.key(key))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.lock()?.get(&self.key(key))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.lock()?.put(&self.key(key), val)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        let popts = api::IterOptions {
+            start_key: opts.start_key.as_deref().map(|key| self.key(key)),
+            prefix: Some(self.key(opts.prefix.as_deref().unwrap_or_default())),
+            reverse: opts.reverse,
+        };
+        Ok(self.strip(self.lock()?.iter_keys(popts)?))
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.lock()?.has_prefix(&self.key(prefix))
+    }
+
+    // the shared db's count covers every namespace; count this one's keys
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        Ok(api::DbStat {
+            n_records: self.count_prefix(b"")?,
+        })
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.max_iter_keys
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.lock()?.get_ref(&self.key(key))
+    }
+
+    fn delete_prefix(&mut self, prefix: &[u8]) -> Result<u64, Error> {
+        if prefix.is_empty() {
+            return Err(Error::InvalidInput(String::from("empty prefix")));
+        }
+        self.lock()?.delete_prefix(&self.key(prefix))
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        self.lock()?.put_expire_at(&self.key(key), val, unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.lock()?.touch(&self.key(key), ttl_secs)
+    }
+
+    // an empty end is the end of this namespace, not of the shared db
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        let end = if end.is_empty() {
+            api::prefix_upper_bound(&self.prefix).unwrap_or_default()
+        } else {
+            self.key(end)
+        };
+        self.lock()?.size_between(&self.key(start), &end)
+    }
+
+    fn put_meta(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        meta: &[(String, String)],
+    ) -> Result<bool, Error> {
+        self.lock()?.put_meta(&self.key(key), val, meta)
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<(api::ValueRef, api::Metadata)>, Error> {
+        self.lock()?.get_meta(&self.key(key))
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.lock()?.get_modified(&self.key(key))
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.lock()?.get_versioned(&self.key(key))
+    }
+
+    // under one lock, so that the check and write are atomic
+    fn put_if_version(&mut self, key: &[u8], val: &[u8], expected: u64) -> Result<bool, Error> {
+        self.lock()?.put_if_version(&self.key(key), val, expected)
+    }
+
+    // compacts the shared db, of every prefix
+    fn compact(&mut self) -> Result<(), Error> {
+        self.lock()?.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.lock()?.flush()
+    }
+
+    fn put_sync(&mut self, key: &[u8], val: &[u8], sync: bool) -> Result<bool, Error> {
+        self.lock()?.put_sync(&self.key(key), val, sync)
+    }
+
+    fn del_sync(&mut self, key: &[u8], sync: bool) -> Result<bool, Error> {
+        self.lock()?.del_sync(&self.key(key), sync)
+    }
+}
@@ -0,0 +1,223 @@
+use super::api;
+use super::api::DbExt;
+use super::Error;
+use std::collections::HashMap;
+use std::ops::ControlFlow;
+
+// stored size of one record: key plus value bytes
+fn record_size(key: &[u8], val_len: usize) -> u64 {
+    (key.len() + val_len) as u64
+}
+
+/// Db layer which enforces a quota on key count and total bytes stored.
+///
+/// Usage is tallied once, by a full scan when the db is opened, then
+/// maintained incrementally by each write.  Record size is key plus value
+/// length; backend overhead is not counted.  A write which would raise
+/// key count or total bytes past its limit fails with
+/// api::ERR_QUOTA_EXCEEDED, changing nothing.  Writes which shrink usage
+/// always succeed, even while over quota.
+pub struct QuotaDb<T: api::Db> {
+    inner: T,
+    max_keys: Option<u64>,
+    max_total_bytes: Option<u64>,
+    n_keys: u64,
+    total_bytes: u64,
+}
+
+impl<T: api::Db> QuotaDb<T> {
+    pub fn new(
+        inner: T,
+        max_keys: Option<u64>,
+        max_total_bytes: Option<u64>,
+    ) -> Result<QuotaDb<T>, Error> {
+        let mut db = QuotaDb {
+            inner,
+            max_keys,
+            max_total_bytes,
+            n_keys: 0,
+            total_bytes: 0,
+        };
+        db.recount()?;
+
+        Ok(db)
+    }
+
+    fn recount(&mut self) -> Result<(), Error> {
+        let mut n_keys = 0;
+        let mut total_bytes = 0;
+        self.inner.for_each_entry(None, |key, val| {
+            n_keys += 1;
+            total_bytes += record_size(key, val.len());
+            ControlFlow::Continue(())
+        })?;
+        self.n_keys = n_keys;
+        self.total_bytes = total_bytes;
+
+        Ok(())
+    }
+
+    /// Apply batch, if usage after it stays within quota.
+    fn apply_counted(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        batch.check()?;
+
+        // size of each key touched, as of the batch's previous mutations
+        let mut sizes: HashMap<&[u8], Option<u64>> = HashMap::new();
+        let mut n_keys = self.n_keys as i64;
+        let mut total_bytes = self.total_bytes as i64;
+        for mutation in &batch.ops {
+            let key = mutation.key.as_slice();
+            let old_size = match sizes.get(key) {
+                Some(size) => *size,
+                None => self.inner.get(key)?.map(|val| record_size(key, val.len())),
+            };
+            let new_size = match mutation.op {
+                api::MutationOp::Insert => {
+                    Some(record_size(key, mutation.value.as_ref().unwrap().len()))
+                }
+                api::MutationOp::Remove => None,
+            };
+
+            n_keys += new_size.is_some() as i64 - old_size.is_some() as i64;
+            total_bytes += new_size.unwrap_or(0) as i64 - old_size.unwrap_or(0) as i64;
+            sizes.insert(key, new_size);
+        }
+
+        let n_keys = n_keys as u64;
+        let total_bytes = total_bytes as u64;
+        let over_keys = match self.max_keys {
+            None => false,
+            Some(max) => n_keys > self.n_keys && n_keys > max,
+        };
+        let over_bytes = match self.max_total_bytes {
+            None => false,
+            Some(max) => total_bytes > self.total_bytes && total_bytes > max,
+        };
+        if over_keys || over_bytes {
+            return Err(api::ERR_QUOTA_EXCEEDED);
+        }
+
+        let res = self.inner.apply_batch(batch)?;
+        self.n_keys = n_keys;
+        self.total_bytes = total_bytes;
+
+        Ok(res)
+    }
+}
+
+impl<T: api::Db> api::Db for QuotaDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        self.apply_counted(batch)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        let res = self.inner.clear()?;
+        self.n_keys = 0;
+        self.total_bytes = 0;
+        Ok(res)
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let val = match self.inner.get(key)? {
+            None => return Ok(false),
+            Some(val) => val,
+        };
+
+        let res = self.inner.del(key)?;
+        self.n_keys -= 1;
+        self.total_bytes -= record_size(key, val.len());
+        Ok(res)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let mut batch = api::Batch::default();
+        batch.insert(key, val);
+        self.apply_counted(&batch)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.inner.get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    // records removed by the backend are not seen here; recount after
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        let n_removed = self.inner.sweep_expired(max_keys)?;
+        if n_removed > 0 {
+            self.recount()?;
+        }
+        Ok(n_removed)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        Some(api::QuotaUsage {
+            n_keys: self.n_keys,
+            total_bytes: self.total_bytes,
+            max_keys: self.max_keys,
+            max_total_bytes: self.max_total_bytes,
+        })
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
@@ -0,0 +1,125 @@
+use super::api;
+use super::Error;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+// number of independently locked in-flight maps
+const N_SHARDS: usize = 16;
+
+type LoadResult = Result<Option<Vec<u8>>, Error>;
+
+// one in-progress load; result is set once, by the loading caller
+struct Flight {
+    result: Mutex<Option<LoadResult>>,
+    done: Condvar,
+}
+
+/// Read-through cache fill for a db shared between threads, running at
+/// most one loader per key at a time.
+///
+/// Unlike DbExt::get_or_load(), the db is not locked while a loader runs,
+/// so loads of different keys proceed in parallel.  Callers missing a key
+/// whose load is already in flight wait for that load, and share its
+/// result, error included.  Waiters are woken together when the load
+/// completes; there is no ordering among them.  A waiter gives up after
+/// the wait timeout, returning an error; the load itself is not bounded,
+/// and still stores its value on completion.  A db lock poisoned by a
+/// panic is reported as api::ERR_LOCK_POISONED.
+pub struct SingleFlight {
+    shards: Vec<Mutex<HashMap<Vec<u8>, Arc<Flight>>>>,
+    wait_timeout: Duration,
+}
+
+impl SingleFlight {
+    pub fn new(wait_timeout: Duration) -> SingleFlight {
+        SingleFlight {
+            shards: (0..N_SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            wait_timeout,
+        }
+    }
+
+    fn shard(&self, key: &[u8]) -> &Mutex<HashMap<Vec<u8>, Arc<Flight>>> {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        &self.shards[(hasher.finish() as usize) % N_SHARDS]
+    }
+
+    /// Lookup value by key in db; on a miss, load it via loader, store it,
+    /// and return it, as DbExt::get_or_load().
+    pub fn get_or_load<D, F>(&self, db: &Mutex<D>, key: &[u8], loader: F) -> LoadResult
+    where
+        D: api::Db + ?Sized,
+        F: FnOnce(&[u8]) -> LoadResult,
+    {
+        let cached = api::lock_shared(db, |_db| false)?.get(key)?;
+        if cached.is_some() {
+            return Ok(cached);
+        }
+
+        // join the in-flight load of this key, or start one
+        let (flight, leader) = {
+            let mut flights = self.shard(key).lock().unwrap();
+            match flights.get(key) {
+                Some(flight) => (Arc::clone(flight), false),
+                None => {
+                    let flight = Arc::new(Flight {
+                        result: Mutex::new(None),
+                        done: Condvar::new(),
+                    });
+                    flights.insert(key.to_vec(), Arc::clone(&flight));
+                    (flight, true)
+                }
+            }
+        };
+
+        if leader {
+            self.load(db, key, loader, &flight)
+        } else {
+            self.wait(&flight)
+        }
+    }
+
+    fn load<D, F>(&self, db: &Mutex<D>, key: &[u8], loader: F, flight: &Flight) -> LoadResult
+    where
+        D: api::Db + ?Sized,
+        F: FnOnce(&[u8]) -> LoadResult,
+    {
+        // a previous flight may have stored the value since our lookup;
+        // the db is unlocked while loader runs
+        let cached = api::lock_shared(db, |_db| false).and_then(|db| db.get(key));
+        let res = match cached {
+            Ok(None) => match loader(key) {
+                Ok(Some(val)) => api::lock_shared(db, |_db| false)
+                    .and_then(|mut db| db.put(key, &val))
+                    .map(|_| Some(val)),
+                other => other,
+            },
+            other => other,
+        };
+
+        *flight.result.lock().unwrap() = Some(res.clone());
+        self.shard(key).lock().unwrap().remove(key);
+        flight.done.notify_all();
+
+        res
+    }
+
+    fn wait(&self, flight: &Flight) -> LoadResult {
+        let deadline = Instant::now() + self.wait_timeout;
+        let mut result = flight.result.lock().unwrap();
+        loop {
+            if let Some(res) = &*result {
+                return res.clone();
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(Error::Timeout);
+            }
+            result = flight.done.wait_timeout(result, deadline - now).unwrap().0;
+        }
+    }
+}
@@ -1,51 +1,149 @@
 use super::api;
+use super::manifest;
+use super::Error;
+use fs2::FileExt;
+use std::fs::{self, File};
+use std::io;
+use std::ops::Bound;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// file within db directory, which a sled writer locks exclusively
+const DATA_FN: &str = "db";
+
+// distinguishes the replica copies opened by this process
+static NEXT_REPLICA_ID: AtomicUsize = AtomicUsize::new(0);
+
+// private copy of a replica's directory, removed when dropped
+struct ReplicaDir {
+    path: PathBuf,
+}
+
+impl ReplicaDir {
+    fn copy_from(src: &Path) -> io::Result<ReplicaDir> {
+        let id = NEXT_REPLICA_ID.fetch_add(1, Ordering::Relaxed);
+        let path = std::env::temp_dir().join(format!("kvdbd-replica-{}-{}", process::id(), id));
+        let dir = ReplicaDir { path };
+        copy_dir(src, &dir.path)?;
+        Ok(dir)
+    }
+}
+
+impl Drop for ReplicaDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
+fn copy_dir(src: &Path, dst: &Path) -> io::Result<()> {
+    fs::create_dir(dst)?;
+    for entry in fs::read_dir(src)? {
+        let entry = entry?;
+        let dst_path = dst.join(entry.file_name());
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &dst_path)?;
+        } else {
+            fs::copy(entry.path(), dst_path)?;
+        }
+    }
+    Ok(())
+}
 
 pub struct SledDb {
     db: sled::Db,
+    read_only: bool,
+    max_iter_keys: usize,             // per Config.max_iter_keys
+    _replica_dir: Option<ReplicaDir>, // dropped after db
+}
+
+impl SledDb {
+    fn check_writable(&self) -> Result<(), Error> {
+        match self.read_only {
+            true => Err(api::ERR_READ_ONLY),
+            false => Ok(()),
+        }
+    }
 }
 
 impl api::Db for SledDb {
-    fn clear(&mut self) -> Result<bool, &'static str> {
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.check_writable()?;
         match self.db.clear() {
             Ok(_) => Ok(true),
-            Err(_e) => Err("clear failed"),
+            Err(_e) => Err(Error::Backend(String::from("clear failed"))),
         }
     }
 
-    fn stat(&self) -> Result<api::DbStat, &'static str> {
+    fn stat(&self) -> Result<api::DbStat, Error> {
         Ok(api::DbStat {
             n_records: self.db.len() as u64,
         })
     }
 
-    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, &'static str> {
+    fn max_iter_keys(&self) -> usize {
+        self.max_iter_keys
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
         match self.db.get(key) {
             Ok(opt_val) => match opt_val {
                 None => Ok(None),
                 Some(val) => Ok(Some(val.to_vec())),
             },
-            Err(_e) => Err("get failed"),
+            Err(_e) => Err(Error::Backend(String::from("get failed"))),
         }
     }
 
-    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, &'static str> {
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        match self.db.get(key) {
+            Ok(opt_val) => match opt_val {
+                None => Ok(None),
+                Some(val) => Ok(Some(api::ValueRef::new(val))),
+            },
+            Err(_e) => Err(Error::Backend(String::from("get failed"))),
+        }
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        let mut found = Vec::with_capacity(keys.len());
+        for key in keys {
+            match self.db.contains_key(key) {
+                Ok(present) => found.push(present),
+                Err(_e) => return Err(Error::Backend(String::from("contains_key failed"))),
+            }
+        }
+
+        Ok(found)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.check_writable()?;
         match self.db.insert(key, val) {
             Ok(_old_val) => Ok(true),
-            Err(_e) => Err("put failed"),
+            Err(_e) => Err(Error::Backend(String::from("put failed"))),
         }
     }
 
-    fn del(&mut self, key: &[u8]) -> Result<bool, &'static str> {
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.check_writable()?;
         match self.db.remove(key) {
             Ok(old_val) => match old_val {
                 None => Ok(false),
                 Some(_v) => Ok(true),
             },
-            Err(_e) => Err("del failed"),
+            Err(_e) => Err(Error::Backend(String::from("del failed"))),
         }
     }
 
-    fn apply_batch(&mut self, batch_in: &api::Batch) -> Result<bool, &'static str> {
+    fn apply_batch(&mut self, batch_in: &api::Batch) -> Result<bool, Error> {
+        self.check_writable()?;
+        batch_in.check()?;
+
+        // &mut self:  no other write to this db between check and apply
+        if let Some(key) = batch_in.guard_key() {
+            batch_in.check_guard(self.get(key)?.as_deref())?;
+        }
         let mut batch = sled::Batch::default();
         for mutation in &batch_in.ops {
             match mutation.op {
@@ -58,33 +156,43 @@ impl api::Db for SledDb {
 
         match self.db.apply_batch(batch) {
             Ok(_optval) => Ok(true),
-            Err(_e) => Err("batch failed"),
+            Err(_e) => Err(Error::Backend(String::from("batch failed"))),
         }
     }
 
-    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, &'static str> {
-        let mut iter;
-
-        // todo: use self.db.scan_prefix() to narrow search,
-        // when prefix is present.  The trade-off:  when using
-        // scan_prefix(), we cannot jump directly to the start key.
-        if opts.start_key.is_none() {
-            iter = self.db.iter();
-        } else {
-            iter = self.db.range(opts.start_key.unwrap()..);
-            iter.next(); // absorb queried-for prev-key
-        }
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        let reverse_bound = api::reverse_upper_bound(&opts);
+        let prefix: Vec<u8> = opts.prefix.unwrap_or_default();
+        let pfx_len = prefix.len();
+        let upper_bound = api::prefix_upper_bound(&prefix);
 
         let mut key_list = api::KeyList {
             keys: Vec::new(),
             list_end: true,
         };
 
-        let prefix: Vec<u8> = match opts.prefix {
-            None => Vec::new(),
-            Some(value) => value,
-        };
-        let pfx_len = prefix.len();
+        // seek past start key, if continuing a previous iteration (it may
+        // since have been removed); or to prefix, if scanning a prefix; or
+        // to db start.  in reverse, walk back from before start key, or
+        // from the end of the prefix, or of the db; the range holds only
+        // keys of the prefix
+        let mut iter: Box<dyn Iterator<Item = sled::Result<(sled::IVec, sled::IVec)>>> =
+            match (opts.reverse, opts.start_key) {
+                (true, _) => match reverse_bound {
+                    Some(bound) if bound <= prefix => return Ok(key_list),
+                    Some(bound) => Box::new(
+                        self.db
+                            .range((Bound::Included(prefix.clone()), Bound::Excluded(bound)))
+                            .rev(),
+                    ),
+                    None => Box::new(self.db.range(prefix.clone()..).rev()),
+                },
+                (false, None) => Box::new(self.db.range(prefix.clone()..)),
+                (false, Some(start_key)) => Box::new(
+                    self.db
+                        .range((Bound::Excluded(start_key), Bound::Unbounded)),
+                ),
+            };
 
         loop {
             let opt_val = iter.next();
@@ -94,11 +202,18 @@ impl api::Db for SledDb {
 
             match opt_val.unwrap() {
                 Err(_e) => {
-                    return Err("iter failed");
+                    return Err(Error::Backend(String::from("iter failed")));
                 }
                 Ok(record_tuple) => {
                     let key = record_tuple.0.to_vec();
 
+                    // stop, once past all keys matching prefix
+                    if let Some(ub) = &upper_bound {
+                        if key >= *ub {
+                            break;
+                        }
+                    }
+
                     // filter by prefix
                     let mut want_push = true;
                     if pfx_len > 0 {
@@ -113,7 +228,7 @@ impl api::Db for SledDb {
                 }
             }
 
-            if key_list.keys.len() >= api::MAX_ITER_KEYS {
+            if key_list.keys.len() >= self.max_iter_keys {
                 key_list.list_end = false;
                 break;
             }
@@ -121,20 +236,124 @@ impl api::Db for SledDb {
 
         Ok(key_list)
     }
+
+    // the prefix's first key, if any
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        match self.db.scan_prefix(prefix).next() {
+            None => Ok(false),
+            Some(Err(_e)) => Err(Error::Backend(String::from("iter failed"))),
+            Some(Ok(_record)) => Ok(true),
+        }
+    }
+
+    // removes the range in one sled batch
+    fn delete_prefix(&mut self, prefix: &[u8]) -> Result<u64, Error> {
+        if prefix.is_empty() {
+            return Err(Error::InvalidInput(String::from("empty prefix")));
+        }
+        self.check_writable()?;
+
+        let mut batch = sled::Batch::default();
+        let mut n_keys = 0;
+        for res in self.db.scan_prefix(prefix) {
+            match res {
+                Err(_e) => return Err(Error::Backend(String::from("iter failed"))),
+                Ok((key, _val)) => {
+                    batch.remove(key);
+                    n_keys += 1;
+                }
+            }
+        }
+        match self.db.apply_batch(batch) {
+            Ok(_optval) => Ok(n_keys),
+            Err(_e) => Err(Error::Backend(String::from("batch failed"))),
+        }
+    }
+
+    // sled has no size estimate; sum the range's records
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        let iter = if end.is_empty() {
+            self.db.range(start..)
+        } else if start < end {
+            self.db.range(start..end)
+        } else {
+            return Ok(0);
+        };
+
+        let mut n_bytes = 0;
+        for res in iter {
+            match res {
+                Err(_e) => return Err(Error::Backend(String::from("iter failed"))),
+                Ok((key, val)) => n_bytes += (key.len() + val.len()) as u64,
+            }
+        }
+        Ok(n_bytes)
+    }
+
+    // sled rewrites sparse segments itself, in the background, once
+    // their writes are on disk, but offers no call to do so on demand:
+    // flush the pending writes, so that freed segments may be cleaned
+    fn compact(&mut self) -> Result<(), Error> {
+        match self.db.flush() {
+            Ok(_n_bytes) => Ok(()),
+            Err(_e) => Err(Error::Backend(String::from("compact failed"))),
+        }
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        match self.db.flush() {
+            Ok(_n_bytes) => Ok(()),
+            Err(_e) => Err(Error::Backend(String::from("flush failed"))),
+        }
+    }
 }
 
 pub struct SledDriver {}
 
 impl api::Driver for SledDriver {
-    fn start_db(&self, cfg: api::Config) -> Result<Box<dyn api::Db + Send>, &'static str> {
-        let sled_db_cfg = sled::ConfigBuilder::new()
-            .path(cfg.path)
-            .read_only(cfg.read_only)
-            .build();
+    fn start_db(&self, cfg: api::Config) -> Result<Box<dyn api::Db + Send>, Error> {
+        manifest::validate(&cfg, "sled")?;
+
+        // sled cannot open its files read-only, so a replica is copied,
+        // and the copy opened; recovery, if any, writes only the copy
+        let replica_dir = match cfg.replica {
+            false => None,
+            true => match ReplicaDir::copy_from(Path::new(&cfg.path)) {
+                Ok(dir) => Some(dir),
+                Err(_e) => return Err(Error::Backend(String::from("db replica copy failed"))),
+            },
+        };
+        let open_path = match &replica_dir {
+            None => PathBuf::from(&cfg.path),
+            Some(dir) => dir.path.clone(),
+        };
+
+        // sled panics, rather than failing, if another process holds the
+        // db, so test its lock first
+        let data_path = open_path.join(DATA_FN);
+        if let Ok(file) = File::open(&data_path) {
+            if FileExt::try_lock_exclusive(&file).is_err() {
+                return Err(api::ERR_DB_LOCKED);
+            }
+            let _ = FileExt::unlock(&file);
+        }
 
-        Ok(Box::new(SledDb {
-            db: sled::Db::start(sled_db_cfg).unwrap(),
-        }) as Box<dyn api::Db + Send>)
+        // a read-only db is opened for writing (locking it exclusively),
+        // and refuses writes
+        let sled_db_cfg = sled::ConfigBuilder::new().path(&open_path).build();
+        let db = match sled::Db::start(sled_db_cfg) {
+            Ok(db) => db,
+            Err(_e) => return Err(Error::Backend(String::from("db open failed"))),
+        };
+        let db = Box::new(SledDb {
+            db,
+            read_only: cfg.read_only,
+            max_iter_keys: cfg.max_iter_keys,
+            _replica_dir: replica_dir,
+        }) as Box<dyn api::Db + Send>;
+
+        manifest::create(&cfg, "sled")?;
+        api::wrap_db(db, &cfg)
     }
 }
 
@@ -143,7 +362,18 @@ pub fn new_driver() -> Box<dyn api::Driver> {
 }
 
 #[cfg(test)]
-use super::api::{Batch, ConfigBuilder};
+use super::api::Db;
+#[cfg(test)]
+use super::api::{
+    Batch, ConfigBuilder, DuplicatePolicy, KeyCase, ERR_DB_LOCKED, ERR_DUPLICATE_KEY,
+    ERR_GUARD_MISMATCH, ERR_READ_ONLY,
+};
+#[cfg(test)]
+use super::prefix::PrefixedDb;
+#[cfg(test)]
+use std::sync::{Arc, Mutex};
+#[cfg(test)]
+use std::{thread, time::Duration};
 #[cfg(test)]
 use tempdir::TempDir;
 
@@ -168,6 +398,52 @@ mod tests {
         assert_eq!(db.del(b"name"), Ok(true));
         assert_eq!(db.get(b"name"), Ok(None));
         assert_eq!(db.get(b"never_existed"), Ok(None));
+
+        // durable writes
+        assert_eq!(db.put_sync(b"name", b"grace", true), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("grace"))));
+        assert_eq!(db.del_sync(b"name", true), Ok(true));
+        assert_eq!(db.flush(), Ok(()));
+    }
+
+    #[test]
+    fn test_compact() {
+        let tmp_dir = TempDir::new("tcm").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        for i in 0..100u32 {
+            assert_eq!(db.put(&i.to_be_bytes(), b"value"), Ok(true));
+        }
+        for i in 0..90u32 {
+            assert_eq!(db.del(&i.to_be_bytes()), Ok(true));
+        }
+        assert_eq!(db.compact(), Ok(()));
+        assert_eq!(db.len(), Ok(10));
+        assert_eq!(db.get(&99u32.to_be_bytes()), Ok(Some(Vec::from("value"))));
+        assert_eq!(db.get(&0u32.to_be_bytes()), Ok(None));
+    }
+
+    #[test]
+    fn test_get_ref() {
+        let tmp_dir = TempDir::new("tgr").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        let big_val = vec![7u8; 1024 * 1024];
+        assert!(db.get_ref(b"name").unwrap().is_none());
+        assert_eq!(db.put(b"name", &big_val), Ok(true));
+
+        let val = db.get_ref(b"name").unwrap().unwrap();
+        assert_eq!(&val[..], &big_val[..]);
     }
 
     #[test]
@@ -208,6 +484,102 @@ mod tests {
         assert_eq!(db.get(b"city"), Ok(Some(Vec::from("anytown"))));
     }
 
+    #[test]
+    fn test_batch_order() {
+        let tmp_dir = TempDir::new("tbo").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+
+        // each mutation sees the ones before it
+        let mut batch = Batch::default();
+        batch.insert(b"age", b"25");
+        batch.remove(b"age");
+        batch.remove(b"name");
+        batch.insert(b"name", b"bob");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        assert_eq!(db.get(b"age"), Ok(None));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("bob"))));
+    }
+
+    #[test]
+    fn test_batch_duplicates() {
+        let tmp_dir = TempDir::new("tc").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"gone", b"x"), Ok(true));
+        let mut batch = Batch::default();
+        batch.insert(b"name", b"alan");
+        batch.insert(b"name", b"bob");
+        batch.insert(b"gone", b"y");
+        batch.remove(b"gone");
+
+        batch.duplicate_policy = DuplicatePolicy::Reject;
+        assert_eq!(db.apply_batch(&batch), Err(ERR_DUPLICATE_KEY));
+        assert_eq!(db.get(b"name"), Ok(None));
+        assert_eq!(db.get(b"gone"), Ok(Some(Vec::from("x"))));
+
+        batch.duplicate_policy = DuplicatePolicy::LastWins;
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("bob"))));
+        assert_eq!(db.get(b"gone"), Ok(None));
+
+        // a remove then an insert stores the insert, as applied in order
+        let mut batch = Batch::default();
+        batch.remove(b"name");
+        batch.insert(b"name", b"carol");
+        batch.insert(b"gone", b"z");
+        batch.remove(b"gone");
+        batch.insert(b"gone", b"back");
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("carol"))));
+        assert_eq!(db.get(b"gone"), Ok(Some(Vec::from("back"))));
+    }
+
+    #[test]
+    fn test_batch_guard() {
+        let tmp_dir = TempDir::new("tc").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"version", b"1"), Ok(true));
+        let mut batch = Batch {
+            guard: Some((b"version".to_vec(), Some(b"0".to_vec()))),
+            ..Batch::default()
+        };
+        batch.insert(b"version", b"2");
+        batch.insert(b"name", b"alan");
+
+        assert_eq!(db.apply_batch(&batch), Err(ERR_GUARD_MISMATCH));
+        assert_eq!(db.get(b"version"), Ok(Some(Vec::from("1"))));
+        assert_eq!(db.get(b"name"), Ok(None));
+
+        batch.guard = Some((b"version".to_vec(), Some(b"1".to_vec())));
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        assert_eq!(db.get(b"version"), Ok(Some(Vec::from("2"))));
+        assert_eq!(db.get(b"name"), Ok(Some(Vec::from("alan"))));
+
+        batch.guard = Some((b"lock".to_vec(), None));
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+        batch.guard = Some((b"name".to_vec(), None));
+        assert_eq!(db.apply_batch(&batch), Err(ERR_GUARD_MISMATCH));
+    }
+
     #[test]
     fn test_clear() {
         let tmp_dir = TempDir::new("tc").unwrap();
@@ -226,6 +598,177 @@ mod tests {
         assert_eq!(db.get(b"age"), Ok(None));
     }
 
+    #[test]
+    fn test_put_next() {
+        let tmp_dir = TempDir::new("tpn").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        let key1 = db.put_next(b"first").unwrap();
+        let key2 = db.put_next(b"second").unwrap();
+        assert_eq!(key1, b"00000000000000000001");
+        assert_eq!(key2, b"00000000000000000002");
+        assert_eq!(db.get(&key1), Ok(Some(Vec::from("first"))));
+        assert_eq!(db.get(&key2), Ok(Some(Vec::from("second"))));
+
+        // deleted keys are not re-used
+        assert_eq!(db.del(&key2), Ok(true));
+        let key3 = db.put_next(b"third").unwrap();
+        assert_eq!(key3, b"00000000000000000003");
+    }
+
+    #[test]
+    fn test_rename() {
+        let tmp_dir = TempDir::new("trn").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"tmp:1", b"alan"), Ok(true));
+        assert_eq!(db.put(b"taken", b"bob"), Ok(true));
+
+        assert_eq!(db.rename(b"tmp:none", b"final:1", false), Ok(false));
+        assert_eq!(db.rename(b"tmp:1", b"taken", false), Ok(false));
+        assert_eq!(db.get(b"taken"), Ok(Some(Vec::from("bob"))));
+
+        assert_eq!(db.rename(b"tmp:1", b"taken", true), Ok(true));
+        assert_eq!(db.get(b"tmp:1"), Ok(None));
+        assert_eq!(db.get(b"taken"), Ok(Some(Vec::from("alan"))));
+    }
+
+    #[test]
+    fn test_read_only() {
+        let tmp_dir = TempDir::new("tro").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(false)
+            .build();
+        let mut db = new_driver().start_db(db_config).unwrap();
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+
+        // locked while open for writing
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(true)
+            .build();
+        match new_driver().start_db(db_config) {
+            Err(e) => assert_eq!(e, ERR_DB_LOCKED),
+            Ok(_db) => panic!("unexpected success"),
+        }
+        drop(db);
+
+        // sled's background threads may hold the db briefly after drop
+        let mut db = Err(ERR_DB_LOCKED);
+        for _ in 0..100 {
+            let db_config = ConfigBuilder::new()
+                .path(tmp_path.clone())
+                .read_only(true)
+                .build();
+            db = new_driver().start_db(db_config);
+            if db.is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        let mut db = db.unwrap();
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert_eq!(db.put(b"name", b"bob"), Err(ERR_READ_ONLY));
+        assert_eq!(db.del(b"name"), Err(ERR_READ_ONLY));
+        assert_eq!(db.clear(), Err(ERR_READ_ONLY));
+        let mut batch = Batch::default();
+        batch.insert(b"age", b"40");
+        assert_eq!(db.apply_batch(&batch), Err(ERR_READ_ONLY));
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+    }
+
+    #[test]
+    fn test_replica() {
+        let tmp_dir = TempDir::new("trp").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(false)
+            .build();
+        let mut writer = new_driver().start_db(db_config).unwrap();
+        assert_eq!(writer.put(b"name", b"alan"), Ok(true));
+        assert_eq!(writer.flush(), Ok(()));
+
+        // opens while the writer holds the db, as of when opened
+        let replica_config = || {
+            ConfigBuilder::new()
+                .path(tmp_path.clone())
+                .replica(true)
+                .build()
+        };
+        let mut db = new_driver().start_db(replica_config()).unwrap();
+        assert_eq!(db.get(b"name"), Ok(Some(b"alan".to_vec())));
+        assert_eq!(db.put(b"name", b"bob"), Err(ERR_READ_ONLY));
+        assert_eq!(db.del(b"name"), Err(ERR_READ_ONLY));
+
+        assert_eq!(writer.put(b"age", b"41"), Ok(true));
+        assert_eq!(writer.flush(), Ok(()));
+        assert_eq!(db.get(b"age"), Ok(None));
+
+        // reopening picks up later writes
+        let db = new_driver().start_db(replica_config()).unwrap();
+        assert_eq!(db.get(b"age"), Ok(Some(b"41".to_vec())));
+        assert_eq!(writer.get(b"name"), Ok(Some(b"alan".to_vec())));
+    }
+
+    #[test]
+    fn test_manifest_conflict() {
+        let tmp_dir = TempDir::new("tmc").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+
+        // create db, recording its options
+        {
+            let db_config = ConfigBuilder::new()
+                .path(tmp_path.clone())
+                .read_only(false)
+                .build();
+            let mut db = new_driver().start_db(db_config).unwrap();
+            assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        }
+
+        // re-open with conflicting key_case
+        let db_config = ConfigBuilder::new()
+            .path(tmp_path.clone())
+            .read_only(false)
+            .key_case(KeyCase::LowercaseFold)
+            .build();
+        match new_driver().start_db(db_config) {
+            Err(e) => assert_eq!(
+                e,
+                Error::Backend(String::from(
+                    "db manifest: db was created with a different key_case"
+                ))
+            ),
+            Ok(_db) => panic!("unexpected success"),
+        }
+
+        // re-open with another driver
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+        match super::super::lmdb::new_driver().start_db(db_config) {
+            Err(e) => assert_eq!(
+                e,
+                Error::Backend(String::from(
+                    "db manifest: db was created by a different driver"
+                ))
+            ),
+            Ok(_db) => panic!("unexpected success"),
+        }
+    }
+
     #[test]
     fn test_stat() {
         let tmp_dir = TempDir::new("tc").unwrap();
@@ -246,6 +789,25 @@ mod tests {
         assert_eq!(st.n_records, 2);
     }
 
+    #[test]
+    fn test_exists_multi() {
+        let tmp_dir = TempDir::new("tc").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"flag", b""), Ok(true));
+        assert_eq!(db.put(b"gone", b"x"), Ok(true));
+        assert_eq!(db.del(b"gone"), Ok(true));
+
+        let keys: Vec<&[u8]> = vec![b"flag", b"gone", b"name", b"missing"];
+        assert_eq!(db.exists_multi(&keys), Ok(vec![true, false, true, false]));
+    }
+
     #[test]
     fn test_iter() {
         let tmp_dir = TempDir::new("tc").unwrap();
@@ -282,6 +844,69 @@ mod tests {
         assert_eq!(key_list.keys[1], b"name");
     }
 
+    #[test]
+    fn test_iter_reverse() {
+        let tmp_dir = TempDir::new("tir").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let mut db = new_driver().start_db(db_config).unwrap();
+        let mut opts = api::IterOptions::new();
+        opts.reverse();
+        assert_eq!(db.iter_keys(opts).unwrap().keys.len(), 0);
+
+        assert_eq!(db.put(b"2018/name", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2019/fame", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2019/game", b"alan"), Ok(true));
+        assert_eq!(db.put(b"2020/tame", b"alan"), Ok(true));
+
+        // descending, from the end, or before the start key
+        let mut opts = api::IterOptions::new();
+        opts.reverse();
+        let key_list = db.iter_keys(opts).unwrap();
+        assert!(key_list.list_end);
+        let want: Vec<&[u8]> = vec![b"2020/tame", b"2019/game", b"2019/fame", b"2018/name"];
+        assert_eq!(key_list.keys, want);
+        let mut opts = api::IterOptions::new();
+        opts.reverse().start(b"2019/game");
+        assert_eq!(db.iter_keys(opts).unwrap().keys, &want[2..]);
+        let mut opts = api::IterOptions::new();
+        opts.reverse().start(b"2019/zzz");
+        assert_eq!(db.iter_keys(opts).unwrap().keys, &want[1..]);
+
+        // within a prefix
+        let mut opts = api::IterOptions::new();
+        opts.reverse().prefix(b"2019/");
+        assert_eq!(db.iter_keys(opts).unwrap().keys, &want[1..3]);
+        let mut opts = api::IterOptions::new();
+        opts.reverse().prefix(b"2019/").start(b"2019/fame");
+        assert_eq!(db.iter_keys(opts).unwrap().keys.len(), 0);
+        let mut opts = api::IterOptions::new();
+        opts.reverse().prefix(b"2019/").start(b"2021");
+        assert_eq!(db.iter_keys(opts).unwrap().keys, &want[1..3]);
+
+        // pages of at most MAX_ITER_KEYS, as forward
+        for i in 0..api::MAX_ITER_KEYS + 10 {
+            let key = format!("page/{i:05}");
+            assert_eq!(db.put(key.as_bytes(), b"x"), Ok(true));
+        }
+        let mut opts = api::IterOptions::new();
+        opts.reverse().prefix(b"page/");
+        let page = db.iter_keys(opts.clone()).unwrap();
+        assert!(!page.list_end);
+        assert_eq!(page.keys.len(), api::MAX_ITER_KEYS);
+        assert_eq!(
+            page.keys[0],
+            format!("page/{:05}", api::MAX_ITER_KEYS + 9).as_bytes()
+        );
+        let page = db
+            .iter_keys(opts.continue_after(page.keys.last().unwrap()))
+            .unwrap();
+        assert!(page.list_end);
+        assert_eq!(page.keys.len(), 10);
+        assert_eq!(page.keys[9], b"page/00000");
+    }
+
     #[test]
     fn test_iter_prefix() {
         let tmp_dir = TempDir::new("tc").unwrap();
@@ -332,5 +957,306 @@ mod tests {
             String::from_utf8_lossy(&key_list.keys[2]),
             String::from("2019/lame")
         );
+
+        // iterate with prefix ending in 0xff
+        assert_eq!(db.put(b"b\xff", b"alan"), Ok(true));
+        assert_eq!(db.put(b"b\xff\x01", b"alan"), Ok(true));
+        assert_eq!(db.put(b"b\xff\xff", b"alan"), Ok(true));
+        assert_eq!(db.put(b"c", b"alan"), Ok(true));
+
+        let mut opts = api::IterOptions::new();
+        opts.prefix(b"b\xff");
+
+        let key_list = db.iter_keys(opts).unwrap();
+        assert!(key_list.list_end);
+        assert_eq!(key_list.keys.len(), 3);
+        assert_eq!(key_list.keys[0], b"b\xff");
+        assert_eq!(key_list.keys[1], b"b\xff\x01");
+        assert_eq!(key_list.keys[2], b"b\xff\xff");
+    }
+
+    #[test]
+    fn test_scan_glob() {
+        let tmp_dir = TempDir::new("tsg").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        for key in &[
+            "admin:1:active",
+            "user:1:active",
+            "user:1:inactive",
+            "user:10:active",
+            "user:2:active",
+            "user:",
+            "username",
+        ] {
+            assert_eq!(db.put(key.as_bytes(), b"x"), Ok(true));
+        }
+        let scan = |pattern: &str, start: Option<&str>| {
+            let key_list = db
+                .scan_glob(pattern.as_bytes(), start.map(|k| k.as_bytes()))
+                .unwrap();
+            assert!(key_list.list_end);
+            key_list
+                .keys
+                .iter()
+                .map(|key| String::from_utf8_lossy(key).to_string())
+                .collect::<Vec<String>>()
+        };
+
+        // leading literal, then mid-pattern wildcard; keys in byte order
+        // (':' sorts after '0')
+        assert_eq!(
+            scan("user:*:active", None),
+            vec!["user:10:active", "user:1:active", "user:2:active"]
+        );
+        assert_eq!(
+            scan("user:?:active", None),
+            vec!["user:1:active", "user:2:active"]
+        );
+        assert_eq!(
+            scan("user:1*", None),
+            vec!["user:10:active", "user:1:active", "user:1:inactive"]
+        );
+
+        // '*' matches none; literal pattern matches only itself
+        assert_eq!(scan("user:*", None).len(), 5);
+        assert_eq!(scan("user*", None).len(), 6);
+        assert_eq!(scan("user:", None), vec!["user:"]);
+        assert_eq!(scan("user", None), Vec::<String>::new());
+
+        // leading wildcard scans all keys
+        assert_eq!(
+            scan("*:1:*", None),
+            vec!["admin:1:active", "user:1:active", "user:1:inactive"]
+        );
+        assert_eq!(scan("*", None).len(), 7);
+
+        // resume after start
+        assert_eq!(
+            scan("user:*:active", Some("user:10:active")),
+            vec!["user:1:active", "user:2:active"]
+        );
+
+        // matches beyond one page are continued by the caller
+        let mut batch = Batch::default();
+        for i in 0..(api::MAX_ITER_KEYS + 10) {
+            batch.insert(format!("page:{i:05}:a").as_bytes(), b"x");
+            batch.insert(format!("page:{i:05}:b").as_bytes(), b"x");
+        }
+        assert_eq!(db.apply_batch(&batch), Ok(true));
+
+        let key_list = db.scan_glob(b"page:*:b", None).unwrap();
+        assert!(!key_list.list_end);
+        assert_eq!(key_list.keys.len(), api::MAX_ITER_KEYS);
+        assert_eq!(key_list.keys[0], b"page:00000:b");
+        let last_key = key_list.keys.last().unwrap().clone();
+        let key_list = db.scan_glob(b"page:*:b", Some(&last_key)).unwrap();
+        assert!(key_list.list_end);
+        assert_eq!(key_list.keys.len(), 10);
+    }
+
+    #[test]
+    fn test_iter_removed_start() {
+        let tmp_dir = TempDir::new("tirs").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put(b"b", b"2"), Ok(true));
+        assert_eq!(db.put(b"c", b"3"), Ok(true));
+
+        // continuing after a key removed since listed skips no other key
+        assert_eq!(db.del(b"b"), Ok(true));
+        let mut opts = api::IterOptions::new();
+        opts.start(b"b");
+        let key_list = db.iter_keys(opts).unwrap();
+        assert_eq!(key_list.keys, vec![b"c".to_vec()]);
+        assert!(key_list.list_end);
+
+        // ... nor fails, if it was the last key
+        assert_eq!(db.del(b"c"), Ok(true));
+        let mut opts = api::IterOptions::new();
+        opts.start(b"c");
+        let key_list = db.iter_keys(opts).unwrap();
+        assert!(key_list.keys.is_empty());
+        assert!(key_list.list_end);
+    }
+
+    #[test]
+    fn test_snapshot_pages() {
+        let tmp_dir = TempDir::new("tsp").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        let n_keys = api::MAX_ITER_KEYS + 500;
+        let key = |i: usize| format!("k{:05}", i * 2).into_bytes();
+        for i in 0..n_keys {
+            assert_eq!(db.put(&key(i), b"v"), Ok(true));
+        }
+
+        let id = db.snapshot_open().unwrap();
+        let page1 = db.snapshot_iter_keys(id, None).unwrap();
+        assert_eq!(page1.keys.len(), api::MAX_ITER_KEYS);
+        assert!(!page1.list_end);
+
+        // between pages:  remove the last key listed, and keys beyond it;
+        // insert keys between others
+        let last_key = page1.keys.last().unwrap().clone();
+        assert_eq!(db.del(&last_key), Ok(true));
+        assert_eq!(db.del(&key(n_keys - 1)), Ok(true));
+        for i in api::MAX_ITER_KEYS..n_keys {
+            let mut between = key(i);
+            between.push(b'x');
+            assert_eq!(db.put(&between, b"new"), Ok(true));
+        }
+
+        let page2 = db.snapshot_iter_keys(id, Some(&last_key)).unwrap();
+        let expected: Vec<Vec<u8>> = (api::MAX_ITER_KEYS..n_keys).map(key).collect();
+        let mut keys = page2.keys;
+        let mut list_end = page2.list_end;
+        while !list_end {
+            let page = db
+                .snapshot_iter_keys(id, keys.last().map(|k| &k[..]))
+                .unwrap();
+            keys.extend(page.keys);
+            list_end = page.list_end;
+        }
+        assert_eq!(keys, expected);
+        assert_eq!(
+            db.snapshot_get(id, &key(n_keys - 1)),
+            Ok(Some(b"v".to_vec()))
+        );
+        assert!(db.snapshot_close(id));
+    }
+
+    #[test]
+    fn test_size_between() {
+        let tmp_dir = TempDir::new("tsb").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        assert_eq!(db.size_between(b"", b""), Ok(0));
+        assert_eq!(db.put(b"a", b"1"), Ok(true));
+        assert_eq!(db.put(b"b", b"22"), Ok(true));
+        assert_eq!(db.put(b"c", b"333"), Ok(true));
+
+        // start inclusive, end exclusive; empty end is unbounded
+        assert_eq!(db.size_between(b"", b""), Ok(9));
+        assert_eq!(db.size_between(b"b", b""), Ok(7));
+        assert_eq!(db.size_between(b"a", b"c"), Ok(5));
+        assert_eq!(db.size_between(b"bb", b"z"), Ok(4));
+
+        // empty ranges
+        assert_eq!(db.size_between(b"b", b"b"), Ok(0));
+        assert_eq!(db.size_between(b"c", b"a"), Ok(0));
+        assert_eq!(db.size_between(b"d", b""), Ok(0));
+    }
+
+    #[test]
+    fn test_has_prefix() {
+        let tmp_dir = TempDir::new("thp").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = ConfigBuilder::new().path(tmp_path).read_only(false).build();
+
+        let driver = new_driver();
+
+        let mut db = driver.start_db(db_config).unwrap();
+
+        // an empty prefix matches any key, so none in an empty db
+        assert_eq!(db.has_prefix(b""), Ok(false));
+        assert_eq!(db.put(b"user:1", b"a"), Ok(true));
+        assert_eq!(db.put(b"user:2", b"b"), Ok(true));
+        assert_eq!(db.has_prefix(b""), Ok(true));
+
+        assert_eq!(db.has_prefix(b"user:"), Ok(true));
+        assert_eq!(db.has_prefix(b"user:2"), Ok(true));
+        assert_eq!(db.has_prefix(b"user:20"), Ok(false));
+        assert_eq!(db.has_prefix(b"use"), Ok(true));
+        assert_eq!(db.has_prefix(b"a"), Ok(false));
+        assert_eq!(db.has_prefix(b"z"), Ok(false));
+
+        assert_eq!(db.del(b"user:1"), Ok(true));
+        assert_eq!(db.del(b"user:2"), Ok(true));
+        assert_eq!(db.has_prefix(b"user:"), Ok(false));
+    }
+
+    #[test]
+    fn test_delete_prefix() {
+        let tmp_dir = TempDir::new("tdp").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let raw = SledDb {
+            db: sled::Db::start(sled::ConfigBuilder::new().path(&tmp_path).build()).unwrap(),
+            read_only: false,
+            max_iter_keys: api::MAX_ITER_KEYS,
+            _replica_dir: None,
+        };
+
+        // tenants sharing the backend:  clearing one removes its range
+        let shared = Arc::new(Mutex::new(raw));
+        let mut t1 = PrefixedDb::new(Arc::clone(&shared), "t1").unwrap();
+        let mut t2 = PrefixedDb::new(Arc::clone(&shared), "t2").unwrap();
+        for key in [&b"a"[..], b"b", b"c"] {
+            assert_eq!(t1.put(key, b"x"), Ok(true));
+            assert_eq!(t2.put(key, b"y"), Ok(true));
+        }
+        assert_eq!(t1.clear(), Ok(true));
+        assert_eq!(t1.get(b"a"), Ok(None));
+        assert_eq!(t2.get(b"a"), Ok(Some(b"y".to_vec())));
+
+        let mut raw = shared.lock().unwrap();
+        assert_eq!(raw.delete_prefix(b"4:t2:"), Ok(0));
+        assert_eq!(raw.delete_prefix(b"2:t2:"), Ok(3));
+        assert_eq!(raw.stat().unwrap().n_records, 0);
+        assert!(matches!(
+            raw.delete_prefix(b""),
+            Err(Error::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_expiry_reopen() {
+        let tmp_dir = TempDir::new("tex").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let db_config = || {
+            ConfigBuilder::new()
+                .path(tmp_path.clone())
+                .read_only(false)
+                .build()
+        };
+        let driver = new_driver();
+        let now_ms = api::unix_now_ms();
+
+        // absolute expiries are stored with the db, surviving a restart
+        let mut db = driver.start_db(db_config()).unwrap();
+        assert_eq!(db.put_expire_at(b"a", b"1", now_ms + 60_000), Ok(true));
+        assert_eq!(db.put_expire_at(b"b", b"2", now_ms + 300), Ok(true));
+        assert_eq!(db.put(b"c", b"3"), Ok(true));
+        drop(db);
+        thread::sleep(Duration::from_millis(350));
+        let db = driver.start_db(db_config()).unwrap();
+        assert_eq!(db.get(b"a"), Ok(Some(b"1".to_vec())));
+        assert_eq!(db.get(b"b"), Ok(None));
+        assert_eq!(
+            db.iter_keys(api::IterOptions::new()).unwrap().keys,
+            vec![b"a".to_vec(), b"c".to_vec()]
+        );
+        assert_eq!(db.stat().unwrap().n_records, 2);
     }
 }
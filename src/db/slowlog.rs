@@ -0,0 +1,309 @@
+use super::api;
+use super::Error;
+use log::warn;
+use std::time::{Duration, Instant};
+
+// one db operation which exceeded the slow-op threshold
+pub struct SlowOp {
+    pub op: &'static str,       // Db method name
+    pub key_len: Option<usize>, // key length, for single-key ops
+    pub elapsed: Duration,      // time spent in the inner db
+}
+
+fn warn_slow_op(slow_op: &SlowOp) {
+    let key_len = match slow_op.key_len {
+        None => String::from("-"),
+        Some(n) => n.to_string(),
+    };
+    warn!(
+        "slow db op: {} key_len={} {}ms",
+        slow_op.op,
+        key_len,
+        slow_op.elapsed.as_millis()
+    );
+}
+
+/// Db layer which times each operation, and reports those taking longer
+/// than a threshold.  Only the operation and key length are reported,
+/// never key or value bytes.
+pub struct SlowLogDb<T: api::Db> {
+    inner: T,
+    threshold: Duration,
+    report: Box<dyn Fn(&SlowOp) + Send + Sync>,
+}
+
+impl<T: api::Db> SlowLogDb<T> {
+    /// Wrap inner db, logging slow operations at warn level.
+    pub fn new(inner: T, threshold: Duration) -> SlowLogDb<T> {
+        SlowLogDb::with_reporter(inner, threshold, Box::new(warn_slow_op))
+    }
+
+    /// Wrap inner db, passing slow operations to report.
+    pub fn with_reporter(
+        inner: T,
+        threshold: Duration,
+        report: Box<dyn Fn(&SlowOp) + Send + Sync>,
+    ) -> SlowLogDb<T> {
+        SlowLogDb {
+            inner,
+            threshold,
+            report,
+        }
+    }
+
+    fn check(&self, op: &'static str, key_len: Option<usize>, start: Instant) {
+        let elapsed = start.elapsed();
+        if elapsed > self.threshold {
+            (self.report)(&SlowOp {
+                op,
+                key_len,
+                elapsed,
+            });
+        }
+    }
+
+    fn timed<R>(&self, op: &'static str, key_len: Option<usize>, f: impl FnOnce(&T) -> R) -> R {
+        let start = Instant::now();
+        let res = f(&self.inner);
+        self.check(op, key_len, start);
+        res
+    }
+
+    fn timed_mut<R>(
+        &mut self,
+        op: &'static str,
+        key_len: Option<usize>,
+        f: impl FnOnce(&mut T) -> R,
+    ) -> R {
+        let start = Instant::now();
+        let res = f(&mut self.inner);
+        self.check(op, key_len, start);
+        res
+    }
+}
+
+impl<T: api::Db> api::Db for SlowLogDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        self.timed_mut("apply_batch", None, |db| db.apply_batch(batch))
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.timed_mut("clear", None, |db| db.clear())
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.timed_mut("del", Some(key.len()), |db| db.del(key))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.timed("get", Some(key.len()), |db| db.get(key))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.timed_mut("put", Some(key.len()), |db| db.put(key, val))
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.timed("iter_keys", None, |db| db.iter_keys(opts))
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.timed("has_prefix", Some(prefix.len()), |db| db.has_prefix(prefix))
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.timed("stat", None, |db| db.stat())
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.timed("get_ref", Some(key.len()), |db| db.get_ref(key))
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.timed("get_multi", None, |db| db.get_multi(keys))
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.timed("exists_multi", None, |db| db.exists_multi(keys))
+    }
+
+    fn apply_batch_until(&mut self, batch: &api::Batch, deadline: Instant) -> Result<usize, Error> {
+        self.timed_mut("apply_batch_until", None, |db| {
+            db.apply_batch_until(batch, deadline)
+        })
+    }
+
+    fn put_next(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        self.timed_mut("put_next", None, |db| db.put_next(val))
+    }
+
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> Result<bool, Error> {
+        self.timed_mut("rename", Some(from.len()), |db| {
+            db.rename(from, to, overwrite)
+        })
+    }
+
+    fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<(), Error> {
+        self.timed_mut("swap", Some(key_a.len()), |db| db.swap(key_a, key_b))
+    }
+
+    fn del_if_empty(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.timed_mut("del_if_empty", Some(key.len()), |db| db.del_if_empty(key))
+    }
+
+    fn del_many(&mut self, keys: &[Vec<u8>]) -> Result<u64, Error> {
+        self.timed_mut("del_many", None, |db| db.del_many(keys))
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        self.timed_mut("compare_and_swap", Some(key.len()), |db| {
+            db.compare_and_swap(key, expected, new)
+        })
+    }
+
+    fn put_if_version(&mut self, key: &[u8], val: &[u8], expected: u64) -> Result<bool, Error> {
+        self.timed_mut("put_if_version", Some(key.len()), |db| {
+            db.put_if_version(key, val, expected)
+        })
+    }
+
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        self.timed_mut("get_or_init", Some(key.len()), |db| {
+            db.get_or_init(key, init)
+        })
+    }
+
+    fn merge(&mut self, key: &[u8], operand: &[u8]) -> Result<(), Error> {
+        self.timed_mut("merge", Some(key.len()), |db| db.merge(key, operand))
+    }
+
+    fn add_sharded(&mut self, key: &[u8], delta: i64, shards: u32) -> Result<bool, Error> {
+        self.timed_mut("add_sharded", Some(key.len()), |db| {
+            db.add_sharded(key, delta, shards)
+        })
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.timed("read_counter", Some(key.len()), |db| db.read_counter(key))
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        self.timed_mut("put_expire_at", Some(key.len()), |db| {
+            db.put_expire_at(key, val, unix_ms)
+        })
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.timed_mut("touch", Some(key.len()), |db| db.touch(key, ttl_secs))
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.timed_mut("sweep_expired", None, |db| db.sweep_expired(max_keys))
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.timed("next_expiry", None, |db| db.next_expiry())
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.timed("changes_since", None, |db| db.changes_since(seq))
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.timed("quota_usage", None, |db| db.quota_usage())
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.timed("scan_glob", None, |db| db.scan_glob(pattern, start))
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.timed("iter_entries", None, |db| db.iter_entries(opts, max_bytes))
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.timed("size_between", Some(start.len()), |db| {
+            db.size_between(start, end)
+        })
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.timed_mut("snapshot_open", None, |db| db.snapshot_open())
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.timed_mut("snapshot_close", None, |db| db.snapshot_close(id))
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.timed("snapshot_get", Some(key.len()), |db| {
+            db.snapshot_get(id, key)
+        })
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.timed("snapshot_iter_keys", None, |db| {
+            db.snapshot_iter_keys(id, start)
+        })
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.timed_mut("create_index", None, |db| db.create_index(name, json_path))
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        self.timed("index_lookup", None, |db| db.index_lookup(name, value))
+    }
+
+    fn degraded(&self) -> bool {
+        self.inner.degraded()
+    }
+
+    fn reset_degraded(&mut self) {
+        self.inner.reset_degraded()
+    }
+
+    fn put_meta(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        meta: &[(String, String)],
+    ) -> Result<bool, Error> {
+        self.timed_mut("put_meta", Some(key.len()), |db| {
+            db.put_meta(key, val, meta)
+        })
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<(api::ValueRef, api::Metadata)>, Error> {
+        self.timed("get_meta", Some(key.len()), |db| db.get_meta(key))
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.timed("get_modified", Some(key.len()), |db| db.get_modified(key))
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.timed("get_versioned", Some(key.len()), |db| db.get_versioned(key))
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.timed_mut("compact", None, |db| db.compact())
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.timed("flush", None, |db| db.flush())
+    }
+}
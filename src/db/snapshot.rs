@@ -0,0 +1,286 @@
+use super::api;
+use super::api::DbExt;
+use super::Error;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::{Bound, ControlFlow};
+use std::sync::Arc;
+
+// key -> value when the snapshot was opened; None = absent then
+type Preimages = BTreeMap<Vec<u8>, Option<Vec<u8>>>;
+
+/// Db layer providing read snapshots, for iteration consistent across
+/// pages while the db is written.
+///
+/// Copy on write:  while a snapshot is open, the first write to each key
+/// saves the key's prior value (its pre-image) to the snapshot.  Reads of
+/// the snapshot return the saved value where there is one, else the
+/// current value, which is unchanged since the snapshot was opened.
+/// Memory held is thus proportional to the keys written while a snapshot
+/// is open, not to the db size, and with no snapshot open, writes cost
+/// nothing extra.  A clear() while a snapshot is open saves every record.
+/// Snapshots are held in memory, so do not survive a restart.
+pub struct SnapshotDb<T: api::Db> {
+    inner: T,
+    snaps: HashMap<u64, Preimages>, // open snapshots, by id
+    next_id: u64,
+}
+
+impl<T: api::Db> SnapshotDb<T> {
+    pub fn new(inner: T) -> SnapshotDb<T> {
+        SnapshotDb {
+            inner,
+            snaps: HashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    // save key's current value to each open snapshot not already holding it
+    fn save_preimage(&mut self, key: &[u8]) -> Result<(), Error> {
+        if self.snaps.values().all(|snap| snap.contains_key(key)) {
+            return Ok(());
+        }
+
+        let val = self.inner.get(key)?;
+        for snap in self.snaps.values_mut() {
+            snap.entry(key.to_vec()).or_insert_with(|| val.clone());
+        }
+        Ok(())
+    }
+
+    fn snap(&self, id: u64) -> Result<&Preimages, Error> {
+        self.snaps.get(&id).ok_or(api::ERR_NO_SNAPSHOT)
+    }
+}
+
+impl<T: api::Db> api::Db for SnapshotDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        for mutation in &batch.ops {
+            self.save_preimage(&mutation.key)?;
+        }
+        self.inner.apply_batch(batch)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        if !self.snaps.is_empty() {
+            let mut records = Vec::new();
+            self.inner.for_each_entry(None, |key, val| {
+                records.push((key.to_vec(), val.to_vec()));
+                ControlFlow::Continue(())
+            })?;
+            for snap in self.snaps.values_mut() {
+                for (key, val) in &records {
+                    snap.entry(key.clone()).or_insert_with(|| Some(val.clone()));
+                }
+            }
+        }
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.save_preimage(key)?;
+        self.inner.del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        self.save_preimage(key)?;
+        self.inner.put(key, val)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.inner.get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.snaps.insert(id, Preimages::new());
+        Ok(id)
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.snaps.remove(&id).is_some()
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        match self.snap(id)?.get(key) {
+            Some(val) => Ok(val.clone()),
+            None => self.inner.get(key),
+        }
+    }
+
+    // merge a page of current keys with the snapshot's pre-images:  drop
+    // keys created since, and restore keys deleted since, up to the last
+    // current key listed
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        let snap = self.snap(id)?;
+        let mut opts = api::IterOptions::new();
+        if let Some(key) = start {
+            opts.start(key);
+        }
+        let page = self.inner.iter_keys(opts)?;
+
+        let mut keys: BTreeSet<Vec<u8>> = page
+            .keys
+            .iter()
+            .filter(|key| !matches!(snap.get(*key), Some(None)))
+            .cloned()
+            .collect();
+        let lower = match start {
+            None => Bound::Unbounded,
+            Some(key) => Bound::Excluded(key),
+        };
+        let upper = match page.keys.iter().max() {
+            Some(key) if !page.list_end => Bound::Included(key.as_slice()),
+            _ => Bound::Unbounded,
+        };
+        for (key, val) in snap.range::<[u8], _>((lower, upper)) {
+            if val.is_some() {
+                keys.insert(key.clone());
+            }
+        }
+
+        let mut key_list = api::KeyList {
+            keys: keys.into_iter().collect(),
+            list_end: page.list_end,
+        };
+        let max_keys = self.inner.max_iter_keys();
+        if key_list.keys.len() > max_keys {
+            key_list.keys.truncate(max_keys);
+            key_list.list_end = false;
+        }
+        Ok(key_list)
+    }
+}
+
+/// Point-in-time copy of a db, per Db::snapshot():  every record, held
+/// in memory until dropped, unchanged by later writes to the db.
+pub struct CopySnapshot {
+    records: BTreeMap<Vec<u8>, Arc<[u8]>>,
+    max_iter_keys: usize, // per Db::max_iter_keys()
+}
+
+impl CopySnapshot {
+    pub fn new(records: BTreeMap<Vec<u8>, Arc<[u8]>>, max_iter_keys: usize) -> CopySnapshot {
+        CopySnapshot {
+            records,
+            max_iter_keys,
+        }
+    }
+}
+
+impl api::Snapshot for CopySnapshot {
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.records.get(key).map(|val| val.to_vec()))
+    }
+
+    // the range holds only keys of the prefix, after start key (before,
+    // in reverse)
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        let prefix = opts.prefix.clone().unwrap_or_default();
+        let lower = match &opts.start_key {
+            Some(start_key) if !opts.reverse && *start_key >= prefix => {
+                Bound::Excluded(start_key.clone())
+            }
+            _ => Bound::Included(prefix.clone()),
+        };
+        let upper_bound = match opts.reverse {
+            true => api::reverse_upper_bound(&opts),
+            false => api::prefix_upper_bound(&prefix),
+        };
+        let upper = match upper_bound {
+            Some(bound) => Bound::Excluded(bound),
+            None => Bound::Unbounded,
+        };
+
+        // BTreeMap::range() panics on an inverted range
+        let empty = match (&lower, &upper) {
+            (Bound::Included(low) | Bound::Excluded(low), Bound::Excluded(high)) => low >= high,
+            _ => false,
+        };
+        let mut keys: Vec<Vec<u8>> = Vec::new();
+        if !empty {
+            let range = self.records.range((lower, upper)).map(|(key, _val)| key);
+            let limit = self.max_iter_keys + 1;
+            keys = match opts.reverse {
+                true => range.rev().take(limit).cloned().collect(),
+                false => range.take(limit).cloned().collect(),
+            };
+        }
+
+        let list_end = keys.len() <= self.max_iter_keys;
+        keys.truncate(self.max_iter_keys);
+        Ok(api::KeyList { keys, list_end })
+    }
+}
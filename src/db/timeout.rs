@@ -0,0 +1,437 @@
+use super::api;
+use super::Error;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+
+// worker threads per db, each running one operation at a time
+const OP_WORKERS: usize = 8;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+fn err_worker_stopped() -> Error {
+    Error::Backend(String::from("db worker stopped"))
+}
+
+/// Db layer which runs each operation on a pool of worker threads, and
+/// returns Error::Timeout if it has not completed within a time limit,
+/// rather than blocking its caller for as long as a stalled backend
+/// does (a hung disk, another process holding a lock).  Enabled per db
+/// by Config.op_timeout_ms.
+///
+/// An operation which times out is not cancelled:  it runs on, in the
+/// background, and a write may still be applied once the backend
+/// recovers, after its caller has been told it timed out.  A caller
+/// retrying a write must therefore expect it to have landed.  While an
+/// operation stalls, it holds its worker, and, if a write, the db:
+/// operations queued behind it time out in turn, until it completes.
+///
+/// Reads run concurrently with each other, as the db allows; a write
+/// runs alone.  Outermost, so that one limit covers all other layers:
+/// an operation which times out is still logged as slow, by
+/// slowlog::SlowLogDb, once it completes.  put_stream() reads its reader
+/// on the caller's thread, then puts the value via a worker.
+pub struct TimeoutDb<T: api::Db + 'static> {
+    inner: Arc<RwLock<T>>,
+    timeout: Duration,
+    jobs: Sender<Job>,
+    max_iter_keys: usize,
+}
+
+impl<T: api::Db + 'static> TimeoutDb<T> {
+    pub fn new(inner: T, timeout: Duration) -> TimeoutDb<T> {
+        let max_iter_keys = inner.max_iter_keys();
+        let (jobs, queue) = mpsc::channel::<Job>();
+        let queue = Arc::new(Mutex::new(queue));
+        for _i in 0..OP_WORKERS {
+            let queue = Arc::clone(&queue);
+            thread::spawn(move || loop {
+                // workers exit once the db, and its sender, is dropped
+                let job = match queue.lock() {
+                    Err(_e) => return,
+                    Ok(queue) => match queue.recv() {
+                        Err(_e) => return,
+                        Ok(job) => job,
+                    },
+                };
+                // a panic drops the job's result sender, unsent
+                let _res = panic::catch_unwind(AssertUnwindSafe(job));
+            });
+        }
+        TimeoutDb {
+            inner: Arc::new(RwLock::new(inner)),
+            timeout,
+            jobs,
+            max_iter_keys,
+        }
+    }
+
+    // run job on a worker; its result, or Timeout once the limit passes
+    fn submit<R: Send + 'static>(
+        &self,
+        job: impl FnOnce() -> Result<R, Error> + Send + 'static,
+    ) -> Result<R, Error> {
+        let (tx, rx) = mpsc::sync_channel(1);
+        let job: Job = Box::new(move || {
+            let _res = tx.send(job());
+        });
+        if self.jobs.send(job).is_err() {
+            return Err(err_worker_stopped());
+        }
+        match rx.recv_timeout(self.timeout) {
+            Ok(res) => res,
+            Err(RecvTimeoutError::Timeout) => Err(Error::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Err(err_worker_stopped()),
+        }
+    }
+
+    fn run<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&T) -> Result<R, Error> + Send + 'static,
+    ) -> Result<R, Error> {
+        let inner = Arc::clone(&self.inner);
+        self.submit(move || match inner.read() {
+            Err(_e) => Err(api::ERR_LOCK_POISONED),
+            Ok(db) => f(&db),
+        })
+    }
+
+    fn run_mut<R: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut T) -> Result<R, Error> + Send + 'static,
+    ) -> Result<R, Error> {
+        let inner = Arc::clone(&self.inner);
+        self.submit(move || match inner.write() {
+            Err(_e) => Err(api::ERR_LOCK_POISONED),
+            Ok(mut db) => f(&mut db),
+        })
+    }
+}
+
+impl<T: api::Db + 'static> api::Db for TimeoutDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        let batch = batch.clone();
+        self.run_mut(move |db| db.apply_batch(&batch))
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.run_mut(|db| db.clear())
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let key = key.to_vec();
+        self.run_mut(move |db| db.del(&key))
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let key = key.to_vec();
+        self.run(move |db| db.get(&key))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let (key, val) = (key.to_vec(), val.to_vec());
+        self.run_mut(move |db| db.put(&key, &val))
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.run(move |db| db.iter_keys(opts))
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.run(|db| db.stat())
+    }
+
+    fn len(&self) -> Result<u64, Error> {
+        self.run(|db| db.len())
+    }
+
+    fn is_empty(&self) -> Result<bool, Error> {
+        self.run(|db| db.is_empty())
+    }
+
+    // fixed once opened:  answered without a worker
+    fn max_iter_keys(&self) -> usize {
+        self.max_iter_keys
+    }
+
+    fn iter_keys_prefix(
+        &self,
+        prefix: &[u8],
+        start_key: Option<&[u8]>,
+    ) -> Result<api::KeyList, Error> {
+        let (prefix, start_key) = (prefix.to_vec(), start_key.map(<[u8]>::to_vec));
+        self.run(move |db| db.iter_keys_prefix(&prefix, start_key.as_deref()))
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        let prefix = prefix.to_vec();
+        self.run(move |db| db.has_prefix(&prefix))
+    }
+
+    fn iter_range(
+        &self,
+        start: &[u8],
+        end: &[u8],
+        start_key: Option<&[u8]>,
+    ) -> Result<api::KeyList, Error> {
+        let (start, end) = (start.to_vec(), end.to_vec());
+        let start_key = start_key.map(<[u8]>::to_vec);
+        self.run(move |db| db.iter_range(&start, &end, start_key.as_deref()))
+    }
+
+    fn get_range(&self, start: &[u8], end: &[u8], limit: usize) -> Result<api::EntryList, Error> {
+        let (start, end) = (start.to_vec(), end.to_vec());
+        self.run(move |db| db.get_range(&start, &end, limit))
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        let key = key.to_vec();
+        self.run(move |db| db.get_ref(&key))
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        let keys = keys.to_vec();
+        self.run(move |db| db.get_multi(&keys))
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        let keys: Vec<Vec<u8>> = keys.iter().map(|key| key.to_vec()).collect();
+        self.run(move |db| {
+            let keys: Vec<&[u8]> = keys.iter().map(Vec::as_slice).collect();
+            db.exists_multi(&keys)
+        })
+    }
+
+    fn exists(&self, key: &[u8]) -> Result<bool, Error> {
+        let key = key.to_vec();
+        self.run(move |db| db.exists(&key))
+    }
+
+    fn apply_batch_until(&mut self, batch: &api::Batch, deadline: Instant) -> Result<usize, Error> {
+        let batch = batch.clone();
+        self.run_mut(move |db| db.apply_batch_until(&batch, deadline))
+    }
+
+    fn put_next(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        let val = val.to_vec();
+        self.run_mut(move |db| db.put_next(&val))
+    }
+
+    fn rename(&mut self, from: &[u8], to: &[u8], overwrite: bool) -> Result<bool, Error> {
+        let (from, to) = (from.to_vec(), to.to_vec());
+        self.run_mut(move |db| db.rename(&from, &to, overwrite))
+    }
+
+    fn swap(&mut self, key_a: &[u8], key_b: &[u8]) -> Result<(), Error> {
+        let (key_a, key_b) = (key_a.to_vec(), key_b.to_vec());
+        self.run_mut(move |db| db.swap(&key_a, &key_b))
+    }
+
+    fn del_if_empty(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let key = key.to_vec();
+        self.run_mut(move |db| db.del_if_empty(&key))
+    }
+
+    fn delete_prefix(&mut self, prefix: &[u8]) -> Result<u64, Error> {
+        let prefix = prefix.to_vec();
+        self.run_mut(move |db| db.delete_prefix(&prefix))
+    }
+
+    fn del_many(&mut self, keys: &[Vec<u8>]) -> Result<u64, Error> {
+        let keys = keys.to_vec();
+        self.run_mut(move |db| db.del_many(&keys))
+    }
+
+    fn compare_and_swap(
+        &mut self,
+        key: &[u8],
+        expected: Option<&[u8]>,
+        new: Option<&[u8]>,
+    ) -> Result<bool, Error> {
+        let key = key.to_vec();
+        let (expected, new) = (expected.map(<[u8]>::to_vec), new.map(<[u8]>::to_vec));
+        self.run_mut(move |db| db.compare_and_swap(&key, expected.as_deref(), new.as_deref()))
+    }
+
+    fn put_if_absent(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let (key, val) = (key.to_vec(), val.to_vec());
+        self.run_mut(move |db| db.put_if_absent(&key, &val))
+    }
+
+    fn put_hashed(&mut self, val: &[u8]) -> Result<Vec<u8>, Error> {
+        let val = val.to_vec();
+        self.run_mut(move |db| db.put_hashed(&val))
+    }
+
+    fn get_or_init(&mut self, key: &[u8], init: &[u8]) -> Result<Vec<u8>, Error> {
+        let (key, init) = (key.to_vec(), init.to_vec());
+        self.run_mut(move |db| db.get_or_init(&key, &init))
+    }
+
+    fn append(&mut self, key: &[u8], suffix: &[u8]) -> Result<usize, Error> {
+        let (key, suffix) = (key.to_vec(), suffix.to_vec());
+        self.run_mut(move |db| db.append(&key, &suffix))
+    }
+
+    fn merge(&mut self, key: &[u8], operand: &[u8]) -> Result<(), Error> {
+        let (key, operand) = (key.to_vec(), operand.to_vec());
+        self.run_mut(move |db| db.merge(&key, &operand))
+    }
+
+    fn add_sharded(&mut self, key: &[u8], delta: i64, shards: u32) -> Result<bool, Error> {
+        let key = key.to_vec();
+        self.run_mut(move |db| db.add_sharded(&key, delta, shards))
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        let key = key.to_vec();
+        self.run(move |db| db.read_counter(&key))
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        let (key, val) = (key.to_vec(), val.to_vec());
+        self.run_mut(move |db| db.put_expire_at(&key, &val, unix_ms))
+    }
+
+    fn put_ttl(&mut self, key: &[u8], val: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        let (key, val) = (key.to_vec(), val.to_vec());
+        self.run_mut(move |db| db.put_ttl(&key, &val, ttl_secs))
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        let key = key.to_vec();
+        self.run_mut(move |db| db.touch(&key, ttl_secs))
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.run_mut(move |db| db.sweep_expired(max_keys))
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.run(|db| db.next_expiry())
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.run(move |db| db.changes_since(seq))
+    }
+
+    // no usage, if it times out
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.run(|db| Ok(db.quota_usage())).unwrap_or(None)
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        let (pattern, start) = (pattern.to_vec(), start.map(<[u8]>::to_vec));
+        self.run(move |db| db.scan_glob(&pattern, start.as_deref()))
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.run(move |db| db.iter_entries(opts, max_bytes))
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        let (start, end) = (start.to_vec(), end.to_vec());
+        self.run(move |db| db.size_between(&start, &end))
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.run_mut(|db| db.snapshot_open())
+    }
+
+    // not closed, if it times out:  it closes in the background
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.run_mut(move |db| Ok(db.snapshot_close(id)))
+            .unwrap_or(false)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        let key = key.to_vec();
+        self.run(move |db| db.snapshot_get(id, &key))
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        let start = start.map(<[u8]>::to_vec);
+        self.run(move |db| db.snapshot_iter_keys(id, start.as_deref()))
+    }
+
+    // the snapshot itself is read unbounded, as it holds no lock of the db
+    fn snapshot(&self) -> Result<Box<dyn api::Snapshot + Send>, Error> {
+        self.run(|db| db.snapshot())
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        let (name, json_path) = (name.to_string(), json_path.to_string());
+        self.run_mut(move |db| db.create_index(&name, &json_path))
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        let (name, value) = (name.to_string(), value.to_string());
+        self.run(move |db| db.index_lookup(&name, &value))
+    }
+
+    // not degraded, as far as known, if it times out
+    fn degraded(&self) -> bool {
+        self.run(|db| Ok(db.degraded())).unwrap_or(false)
+    }
+
+    fn reset_degraded(&mut self) {
+        let _res = self.run_mut(|db| {
+            db.reset_degraded();
+            Ok(())
+        });
+    }
+
+    fn put_meta(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        meta: &[(String, String)],
+    ) -> Result<bool, Error> {
+        let (key, val, meta) = (key.to_vec(), val.to_vec(), meta.to_vec());
+        self.run_mut(move |db| db.put_meta(&key, &val, &meta))
+    }
+
+    fn get_meta(&self, key: &[u8]) -> Result<Option<(api::ValueRef, api::Metadata)>, Error> {
+        let key = key.to_vec();
+        self.run(move |db| db.get_meta(&key))
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        let key = key.to_vec();
+        self.run(move |db| db.get_modified(&key))
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        let key = key.to_vec();
+        self.run(move |db| db.get_versioned(&key))
+    }
+
+    fn put_if_version(&mut self, key: &[u8], val: &[u8], expected: u64) -> Result<bool, Error> {
+        let (key, val) = (key.to_vec(), val.to_vec());
+        self.run_mut(move |db| db.put_if_version(&key, &val, expected))
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.run_mut(|db| db.compact())
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.run(|db| db.flush())
+    }
+
+    fn put_sync(&mut self, key: &[u8], val: &[u8], sync: bool) -> Result<bool, Error> {
+        let (key, val) = (key.to_vec(), val.to_vec());
+        self.run_mut(move |db| db.put_sync(&key, &val, sync))
+    }
+
+    fn del_sync(&mut self, key: &[u8], sync: bool) -> Result<bool, Error> {
+        let key = key.to_vec();
+        self.run_mut(move |db| db.del_sync(&key, sync))
+    }
+}
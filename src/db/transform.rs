@@ -0,0 +1,147 @@
+use super::api;
+use super::Error;
+use std::sync::Arc;
+
+/// Application-defined processing of each value, between the db and
+/// storage, e.g. redaction or compression.  on_read() must undo
+/// on_write():  on_read(&on_write(val)) == val, for every val, the empty
+/// value included.  Registered per db with ConfigBuilder::value_transform().
+pub trait ValueTransform: Send + Sync {
+    /// Stored form of val.
+    fn on_write(&self, val: &[u8]) -> Vec<u8>;
+
+    /// Value of stored, as written.
+    fn on_read(&self, stored: &[u8]) -> Vec<u8>;
+}
+
+/// Db layer passing every value through a chain of ValueTransforms.
+///
+/// Writes apply the transforms in registration order, and reads undo
+/// them in reverse:  the first registered sees values as written by
+/// the application, and the last, values as storage holds them.  To
+/// compress and then encrypt, register the compressor first.
+///
+/// Innermost, but for encrypt::EncryptedDb and checksum::ChecksumDb, so
+/// that every layer sees values untransformed, and all stored values -
+/// change log and index records included - are transformed.  A batch
+/// guard is checked here, against the untransformed value, so transforms
+/// need not be deterministic.  The offline inspect subcommands, which
+/// register no transforms, see values as stored.
+pub struct TransformDb<T: api::Db> {
+    inner: T,
+    transforms: Vec<Arc<dyn ValueTransform>>,
+}
+
+impl<T: api::Db> TransformDb<T> {
+    pub fn new(inner: T, transforms: Vec<Arc<dyn ValueTransform>>) -> TransformDb<T> {
+        TransformDb { inner, transforms }
+    }
+
+    fn on_write(&self, val: &[u8]) -> Vec<u8> {
+        let mut stored = val.to_vec();
+        for transform in &self.transforms {
+            stored = transform.on_write(&stored);
+        }
+        stored
+    }
+
+    fn on_read(&self, stored: Vec<u8>) -> Vec<u8> {
+        let mut val = stored;
+        for transform in self.transforms.iter().rev() {
+            val = transform.on_read(&val);
+        }
+        val
+    }
+}
+
+impl<T: api::Db> api::Db for TransformDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        batch.check()?;
+
+        // &mut self:  no other write to this db between check and apply
+        if let Some(key) = batch.guard_key() {
+            batch.check_guard(self.get(key)?.as_deref())?;
+        }
+        let transformed = api::Batch {
+            ops: batch
+                .ops
+                .iter()
+                .map(|dbm| api::Mutation {
+                    op: dbm.op.clone(),
+                    key: dbm.key.clone(),
+                    value: dbm.value.as_ref().map(|val| self.on_write(val)),
+                })
+                .collect(),
+            duplicate_policy: batch.duplicate_policy,
+            guard: None,
+        };
+        self.inner.apply_batch(&transformed)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.inner.get(key)?.map(|stored| self.on_read(stored)))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let stored = self.on_write(val);
+        self.inner.put(key, &stored)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        let stored = self.on_write(val);
+        self.inner.put_expire_at(key, &stored, unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    // sizes as stored, transformed
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
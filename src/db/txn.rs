@@ -0,0 +1,75 @@
+use super::api;
+use super::Error;
+use std::collections::{BTreeMap, HashMap};
+
+/// Optimistic multi-key transaction against one db.
+///
+/// Reads go to the db, and each value observed is remembered; writes are
+/// buffered, and seen by later reads within the transaction.  Nothing is
+/// locked while the transaction is open.  commit() checks that every key
+/// read still holds the value observed, then applies all writes in one
+/// batch; the caller must hold the db exclusively between the two, as
+/// `&mut` ensures.  Transactions are thus serializable:  each commits as
+/// if it ran alone, at its commit point.  Keys written without being read
+/// are not checked.
+#[derive(Default)]
+pub struct Txn {
+    reads: HashMap<Vec<u8>, Option<Vec<u8>>>, // key -> value observed
+    writes: BTreeMap<Vec<u8>, Option<Vec<u8>>>, // key -> new value; None = delete
+}
+
+impl Txn {
+    pub fn new() -> Txn {
+        Txn::default()
+    }
+
+    /// Lookup value by key, as of this transaction.
+    pub fn get<D: api::Db + ?Sized>(
+        &mut self,
+        db: &D,
+        key: &[u8],
+    ) -> Result<Option<Vec<u8>>, Error> {
+        if let Some(val) = self.writes.get(key) {
+            return Ok(val.clone());
+        }
+        if let Some(val) = self.reads.get(key) {
+            return Ok(val.clone());
+        }
+
+        let val = db.get(key)?;
+        self.reads.insert(key.to_vec(), val.clone());
+        Ok(val)
+    }
+
+    pub fn put(&mut self, key: &[u8], val: &[u8]) {
+        self.writes.insert(key.to_vec(), Some(val.to_vec()));
+    }
+
+    pub fn del(&mut self, key: &[u8]) {
+        self.writes.insert(key.to_vec(), None);
+    }
+
+    /// Apply buffered writes, if no key read has changed since.  Returns
+    /// false, applying nothing, on conflict.
+    pub fn commit<D: api::Db + ?Sized>(self, db: &mut D) -> Result<bool, Error> {
+        for (key, val) in &self.reads {
+            if db.get(key)? != *val {
+                return Ok(false);
+            }
+        }
+
+        if self.writes.is_empty() {
+            return Ok(true);
+        }
+
+        let mut batch = api::Batch::default();
+        for (key, val) in &self.writes {
+            match val {
+                Some(val) => batch.insert(key, val),
+                None => batch.remove(key),
+            };
+        }
+        db.apply_batch(&batch)?;
+        Ok(true)
+    }
+}
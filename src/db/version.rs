@@ -0,0 +1,188 @@
+use super::api;
+use super::Error;
+use std::collections::HashMap;
+
+// prefix of a stored value carrying its key's version, followed by the
+// version (u64, big-endian), then the value
+const VERSION_MAGIC: &[u8] = b"\xffkvdbd:ver\x00";
+
+const VERSION_LEN: usize = 8;
+
+fn stamp(val: &[u8], version: u64) -> Vec<u8> {
+    let mut stored = Vec::with_capacity(VERSION_MAGIC.len() + VERSION_LEN + val.len());
+    stored.extend_from_slice(VERSION_MAGIC);
+    stored.extend_from_slice(&version.to_be_bytes());
+    stored.extend_from_slice(val);
+    stored
+}
+
+// stored value and its key's version, split; values without a header,
+// stored before versions were tracked, are at version 0
+fn split(mut stored: Vec<u8>) -> Result<(Vec<u8>, u64), Error> {
+    if !stored.starts_with(VERSION_MAGIC) {
+        return Ok((stored, 0));
+    }
+    let header_len = VERSION_MAGIC.len() + VERSION_LEN;
+    if stored.len() < header_len {
+        return Err(Error::Corruption(String::from("invalid key version")));
+    }
+    let mut version_bytes = [0u8; VERSION_LEN];
+    version_bytes.copy_from_slice(&stored[VERSION_MAGIC.len()..header_len]);
+    stored.drain(..header_len);
+    Ok((stored, u64::from_be_bytes(version_bytes)))
+}
+
+/// Db layer keeping a version per key, returned with its value by
+/// get_versioned(), and checked by put_if_version().  Enabled per db by
+/// Config.track_versions.
+///
+/// Each write of a value - put, batch insert, or any write made of them,
+/// such as a merge or rename - stores it with a small header holding
+/// the key's version, one more than the version it replaces:  1, for a
+/// key absent before.  A batch writing one key twice bumps it twice.
+/// Versions count writes, not time, so are unaffected by the clock.
+/// touch() rewrites no value, so does not bump the version.  A delete
+/// removes the version with the value:  a key deleted then written
+/// again starts again at 1.  Values stored before versions were tracked
+/// lack the header, and read back as stored, at version 0.
+///
+/// Each write first reads the version it replaces, at the cost of one
+/// read per key written.  Placed as modtime::ModTimeDb is, directly
+/// above it:  below the change log and indexes, so that every layer but
+/// transforms and modification times sees values without the header.
+/// As in TransformDb, a batch guard is checked here, against the value
+/// without its header.
+pub struct VersionDb<T: api::Db> {
+    inner: T,
+}
+
+impl<T: api::Db> VersionDb<T> {
+    pub fn new(inner: T) -> VersionDb<T> {
+        VersionDb { inner }
+    }
+
+    // version of key as stored; 0 if absent
+    fn version(&self, key: &[u8]) -> Result<u64, Error> {
+        match self.inner.get(key)? {
+            None => Ok(0),
+            Some(stored) => Ok(split(stored)?.1),
+        }
+    }
+}
+
+impl<T: api::Db> api::Db for VersionDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        batch.check()?;
+
+        // &mut self:  no other write to this db between check and apply
+        if let Some(key) = batch.guard_key() {
+            batch.check_guard(self.get(key)?.as_deref())?;
+        }
+
+        // each mutation of a key, in order, from its version as stored
+        let mut versions: HashMap<&[u8], u64> = HashMap::new();
+        let mut ops = Vec::with_capacity(batch.ops.len());
+        for dbm in &batch.ops {
+            let version = match versions.get(dbm.key.as_slice()) {
+                Some(version) => *version,
+                None => self.version(&dbm.key)?,
+            };
+            let value = dbm.value.as_ref().map(|val| stamp(val, version + 1));
+            let next = if value.is_some() { version + 1 } else { 0 };
+            versions.insert(&dbm.key, next);
+            ops.push(api::Mutation {
+                op: dbm.op.clone(),
+                key: dbm.key.clone(),
+                value,
+            });
+        }
+        let stamped = api::Batch {
+            ops,
+            duplicate_policy: batch.duplicate_policy,
+            guard: None,
+        };
+        self.inner.apply_batch(&stamped)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        self.inner.clear()
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        self.inner.del(key)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        Ok(self.get_versioned(key)?.map(|(val, _version)| val))
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let version = self.version(key)?;
+        self.inner.put(key, &stamp(val, version + 1))
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        let stored = stamp(val, self.version(key)? + 1);
+        self.inner.put_expire_at(key, &stored, unix_ms)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    // sizes as stored, with headers
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    // times are stored outside the version header
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        match self.inner.get_modified(key)? {
+            None => Ok(None),
+            Some((stored, unix_ms)) => Ok(Some((split(stored)?.0, unix_ms))),
+        }
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        match self.inner.get(key)? {
+            None => Ok(None),
+            Some(stored) => Ok(Some(split(stored)?)),
+        }
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
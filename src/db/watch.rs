@@ -0,0 +1,410 @@
+use super::api;
+use super::Error;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{Poll, Waker};
+
+/// Events a watcher may fall behind by, by default, before it is cut off
+/// as lagging; see WatchHub::with_queue_len().
+pub const WATCH_QUEUE_LEN: usize = 1024;
+
+/// Kind of change published to watchers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WatchOp {
+    Put,
+    Del,
+    Clear, // every key removed; the event's key is empty
+}
+
+impl WatchOp {
+    /// Name of this kind, e.g. "put".
+    pub fn name(self) -> &'static str {
+        match self {
+            WatchOp::Put => "put",
+            WatchOp::Del => "del",
+            WatchOp::Clear => "clear",
+        }
+    }
+}
+
+/// One change, as published to watchers.
+#[derive(Clone, Debug, PartialEq)]
+pub struct WatchEvent {
+    pub seq: u64, // per hub, from 1, of changes published while watched
+    pub op: WatchOp,
+    pub key: Vec<u8>,
+}
+
+/// What a watcher reads next.
+#[derive(Debug, PartialEq)]
+pub enum WatchItem {
+    Event(WatchEvent),
+    Lagged, // events were dropped, and no more will be delivered
+    Closed, // the hub was closed, and every event delivered
+}
+
+struct Sub {
+    prefix: Vec<u8>,
+    queue: VecDeque<WatchEvent>,
+    lagged: bool,
+    waker: Option<Waker>,
+}
+
+impl Sub {
+    fn wants(&self, op: WatchOp, key: &[u8]) -> bool {
+        match op {
+            WatchOp::Clear => true,
+            WatchOp::Put | WatchOp::Del => key.starts_with(&self.prefix),
+        }
+    }
+}
+
+#[derive(Default)]
+struct HubState {
+    next_seq: u64,
+    next_id: u64,
+    subs: HashMap<u64, Sub>,
+    closed: bool,
+}
+
+/// Broadcast of a db's changes, as published by WatchDb, to watchers
+/// subscribed to it, e.g. to invalidate caches of the db.
+///
+/// Each watcher is sent every change published after it subscribed, to
+/// a key with its prefix, in publish order.  Changes are held per
+/// watcher until read, up to the hub's queue length; a watcher falling
+/// further behind is cut off, its held changes dropped, and reads
+/// WatchItem::Lagged.  Nothing is replayed:  changes published while no
+/// watcher is subscribed, or after one lags, are not delivered to it.
+pub struct WatchHub {
+    state: Mutex<HubState>,
+    queue_len: usize,
+}
+
+impl Default for WatchHub {
+    fn default() -> WatchHub {
+        WatchHub::with_queue_len(WATCH_QUEUE_LEN)
+    }
+}
+
+impl WatchHub {
+    pub fn new() -> WatchHub {
+        WatchHub::default()
+    }
+
+    /// Hub cutting off watchers more than queue_len changes behind.
+    pub fn with_queue_len(queue_len: usize) -> WatchHub {
+        WatchHub {
+            state: Mutex::new(HubState {
+                next_seq: 1,
+                ..HubState::default()
+            }),
+            queue_len: queue_len.max(1),
+        }
+    }
+
+    // hub state holds only queued copies of changes already made, so a
+    // lock poisoned by a panic is recovered, rather than failing writes
+    fn lock(&self) -> MutexGuard<'_, HubState> {
+        api::lock_shared(&self.state, |_state| true).unwrap()
+    }
+
+    /// Watch changes to keys beginning with prefix (all keys, if empty),
+    /// until the Watcher is dropped.
+    pub fn subscribe(self: &Arc<Self>, prefix: &[u8]) -> Watcher {
+        let mut state = self.lock();
+        let id = state.next_id;
+        state.next_id += 1;
+        state.subs.insert(
+            id,
+            Sub {
+                prefix: prefix.to_vec(),
+                queue: VecDeque::new(),
+                lagged: false,
+                waker: None,
+            },
+        );
+        Watcher {
+            hub: Arc::clone(self),
+            id,
+        }
+    }
+
+    /// Number of watchers subscribed.
+    pub fn watchers(&self) -> usize {
+        self.lock().subs.len()
+    }
+
+    /// Stop publishing, e.g. at shutdown.  Each watcher reads the changes
+    /// it holds, then WatchItem::Closed.
+    pub fn close(&self) {
+        let mut state = self.lock();
+        state.closed = true;
+        for sub in state.subs.values_mut() {
+            if let Some(waker) = sub.waker.take() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Publish changes, in order, to each watcher wanting them.
+    pub fn publish<'a, I>(&self, changes: I)
+    where
+        I: IntoIterator<Item = (WatchOp, &'a [u8])>,
+    {
+        let mut state = self.lock();
+        if state.subs.is_empty() || state.closed {
+            return;
+        }
+        let state = &mut *state;
+        for (op, key) in changes {
+            let seq = state.next_seq;
+            state.next_seq += 1;
+            for sub in state.subs.values_mut() {
+                if sub.lagged || !sub.wants(op, key) {
+                    continue;
+                }
+                if sub.queue.len() >= self.queue_len {
+                    sub.queue.clear();
+                    sub.lagged = true;
+                } else {
+                    sub.queue.push_back(WatchEvent {
+                        seq,
+                        op,
+                        key: key.to_vec(),
+                    });
+                }
+                if let Some(waker) = sub.waker.take() {
+                    waker.wake();
+                }
+            }
+        }
+    }
+}
+
+/// Subscription to a WatchHub; unsubscribes when dropped.
+pub struct Watcher {
+    hub: Arc<WatchHub>,
+    id: u64,
+}
+
+impl Watcher {
+    /// Next item, if one is ready; else Pending, and waker is woken once
+    /// one is.
+    pub fn poll_next(&self, waker: &Waker) -> Poll<WatchItem> {
+        let mut state = self.hub.lock();
+        let closed = state.closed;
+        let sub = state.subs.get_mut(&self.id).unwrap();
+        if let Some(event) = sub.queue.pop_front() {
+            return Poll::Ready(WatchItem::Event(event));
+        }
+        if sub.lagged {
+            return Poll::Ready(WatchItem::Lagged);
+        }
+        if closed {
+            return Poll::Ready(WatchItem::Closed);
+        }
+        sub.waker = Some(waker.clone());
+        Poll::Pending
+    }
+
+    /// Next item, if one is ready, without waiting.
+    pub fn try_next(&self) -> Option<WatchItem> {
+        match self.poll_next(Waker::noop()) {
+            Poll::Ready(item) => Some(item),
+            Poll::Pending => None,
+        }
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.hub.lock().subs.remove(&self.id);
+    }
+}
+
+/// Db layer which publishes each change to the db, once made, to a
+/// WatchHub:  a put or delete of a key, by any write (batches, renames,
+/// prefix deletes and so on are each published as the puts and deletes
+/// they make), or a clear.
+///
+/// No change is published for a write which fails, or which changes
+/// nothing, e.g. a del() of an absent key, or a compare-and-swap which
+/// does not match; a batch publishes each of its mutations.  Records
+/// removed by an expiry sweep are not published:  the backend removes
+/// them unseen.  A watcher is told of a change once it is made, but
+/// perhaps before it is flushed to storage.
+pub struct WatchDb<T: api::Db> {
+    inner: T,
+    hub: Arc<WatchHub>,
+}
+
+impl<T: api::Db> WatchDb<T> {
+    pub fn new(inner: T, hub: Arc<WatchHub>) -> WatchDb<T> {
+        WatchDb { inner, hub }
+    }
+
+    fn publish_ops(&self, ops: &[api::Mutation]) {
+        self.hub.publish(ops.iter().map(|dbm| {
+            let op = match dbm.op {
+                api::MutationOp::Insert => WatchOp::Put,
+                api::MutationOp::Remove => WatchOp::Del,
+            };
+            (op, dbm.key.as_slice())
+        }));
+    }
+}
+
+impl<T: api::Db> api::Db for WatchDb<T> {
+    fn apply_batch(&mut self, batch: &api::Batch) -> Result<bool, Error> {
+        let res = self.inner.apply_batch(batch)?;
+        self.publish_ops(&batch.ops);
+        Ok(res)
+    }
+
+    fn clear(&mut self) -> Result<bool, Error> {
+        let res = self.inner.clear()?;
+        self.hub.publish([(WatchOp::Clear, &b""[..])]);
+        Ok(res)
+    }
+
+    fn del(&mut self, key: &[u8]) -> Result<bool, Error> {
+        let res = self.inner.del(key)?;
+        if res {
+            self.hub.publish([(WatchOp::Del, key)]);
+        }
+        Ok(res)
+    }
+
+    fn get(&self, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.get(key)
+    }
+
+    fn put(&mut self, key: &[u8], val: &[u8]) -> Result<bool, Error> {
+        let res = self.inner.put(key, val)?;
+        self.hub.publish([(WatchOp::Put, key)]);
+        Ok(res)
+    }
+
+    fn iter_keys(&self, opts: api::IterOptions) -> Result<api::KeyList, Error> {
+        self.inner.iter_keys(opts)
+    }
+
+    fn has_prefix(&self, prefix: &[u8]) -> Result<bool, Error> {
+        self.inner.has_prefix(prefix)
+    }
+
+    fn stat(&self) -> Result<api::DbStat, Error> {
+        self.inner.stat()
+    }
+
+    fn max_iter_keys(&self) -> usize {
+        self.inner.max_iter_keys()
+    }
+
+    fn get_ref(&self, key: &[u8]) -> Result<Option<api::ValueRef>, Error> {
+        self.inner.get_ref(key)
+    }
+
+    fn get_multi(&self, keys: &[Vec<u8>]) -> Result<Vec<Option<Vec<u8>>>, Error> {
+        self.inner.get_multi(keys)
+    }
+
+    fn exists_multi(&self, keys: &[&[u8]]) -> Result<Vec<bool>, Error> {
+        self.inner.exists_multi(keys)
+    }
+
+    fn read_counter(&self, key: &[u8]) -> Result<i64, Error> {
+        self.inner.read_counter(key)
+    }
+
+    fn put_expire_at(&mut self, key: &[u8], val: &[u8], unix_ms: u64) -> Result<bool, Error> {
+        let res = self.inner.put_expire_at(key, val, unix_ms)?;
+        self.hub.publish([(WatchOp::Put, key)]);
+        Ok(res)
+    }
+
+    fn touch(&mut self, key: &[u8], ttl_secs: u64) -> Result<bool, Error> {
+        self.inner.touch(key, ttl_secs)
+    }
+
+    fn sweep_expired(&mut self, max_keys: usize) -> Result<usize, Error> {
+        self.inner.sweep_expired(max_keys)
+    }
+
+    fn next_expiry(&self) -> Result<Option<u64>, Error> {
+        self.inner.next_expiry()
+    }
+
+    fn changes_since(&self, seq: u64) -> Result<api::ChangeList, Error> {
+        self.inner.changes_since(seq)
+    }
+
+    fn quota_usage(&self) -> Option<api::QuotaUsage> {
+        self.inner.quota_usage()
+    }
+
+    fn scan_glob(&self, pattern: &[u8], start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.scan_glob(pattern, start)
+    }
+
+    fn iter_entries(
+        &self,
+        opts: api::IterOptions,
+        max_bytes: Option<usize>,
+    ) -> Result<api::EntryList, Error> {
+        self.inner.iter_entries(opts, max_bytes)
+    }
+
+    fn size_between(&self, start: &[u8], end: &[u8]) -> Result<u64, Error> {
+        self.inner.size_between(start, end)
+    }
+
+    fn snapshot_open(&mut self) -> Result<u64, Error> {
+        self.inner.snapshot_open()
+    }
+
+    fn snapshot_close(&mut self, id: u64) -> bool {
+        self.inner.snapshot_close(id)
+    }
+
+    fn snapshot_get(&self, id: u64, key: &[u8]) -> Result<Option<Vec<u8>>, Error> {
+        self.inner.snapshot_get(id, key)
+    }
+
+    fn snapshot_iter_keys(&self, id: u64, start: Option<&[u8]>) -> Result<api::KeyList, Error> {
+        self.inner.snapshot_iter_keys(id, start)
+    }
+
+    fn create_index(&mut self, name: &str, json_path: &str) -> Result<bool, Error> {
+        self.inner.create_index(name, json_path)
+    }
+
+    fn index_lookup(&self, name: &str, value: &str) -> Result<Option<Vec<Vec<u8>>>, Error> {
+        self.inner.index_lookup(name, value)
+    }
+
+    fn degraded(&self) -> bool {
+        self.inner.degraded()
+    }
+
+    fn reset_degraded(&mut self) {
+        self.inner.reset_degraded()
+    }
+
+    fn get_modified(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_modified(key)
+    }
+
+    fn get_versioned(&self, key: &[u8]) -> Result<Option<(Vec<u8>, u64)>, Error> {
+        self.inner.get_versioned(key)
+    }
+
+    fn compact(&mut self) -> Result<(), Error> {
+        self.inner.compact()
+    }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.inner.flush()
+    }
+}
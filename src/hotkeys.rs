@@ -0,0 +1,174 @@
+// approximate per-key read counts, for hot-key detection:  Space-Saving
+// (Metwally, Agrawal and El Abbadi, 2005), over a fixed number of slots
+//
+// Each read of a tracked key counts it once.  A read of an untracked key,
+// once every slot is taken, replaces the key counted least, taking its
+// count as its error, and counting on from there:  the new key may have
+// been read up to that many times before, unseen.  So a count never understates a
+// key's reads, and overstates them by at most its error; and every key
+// read more than total / slots times is tracked.  Memory is bounded by
+// the slots, whatever the number of distinct keys read.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Mutex;
+
+// keys tracked at once, per db
+pub const MAX_HOT_KEYS: usize = 1024;
+
+// reads counted of one tracked key
+struct Counter {
+    count: u64, // reads, at most error more than made
+    error: u64, // reads possibly made before tracking began
+}
+
+// tracked keys, with their counters, and ordered by count, least first
+struct Slots {
+    counters: HashMap<Vec<u8>, Counter>,
+    by_count: BTreeSet<(u64, Vec<u8>)>,
+    n_reads: u64,
+}
+
+/// One tracked key's approximate read count.
+#[derive(Debug, PartialEq)]
+pub struct HotKey {
+    pub key: Vec<u8>,
+    pub count: u64, // reads, overstated by at most error
+    pub error: u64,
+}
+
+/// Approximate read counts of a db's most read keys, in bounded memory.
+pub struct HotKeys {
+    max_keys: usize,
+    slots: Mutex<Slots>,
+}
+
+impl HotKeys {
+    pub fn new() -> HotKeys {
+        HotKeys::with_max_keys(MAX_HOT_KEYS)
+    }
+
+    /// Tracker of at most max_keys keys.
+    pub fn with_max_keys(max_keys: usize) -> HotKeys {
+        HotKeys {
+            max_keys: max_keys.max(1),
+            slots: Mutex::new(Slots {
+                counters: HashMap::new(),
+                by_count: BTreeSet::new(),
+                n_reads: 0,
+            }),
+        }
+    }
+
+    /// Count one read of key.
+    pub fn record(&self, key: &[u8]) {
+        let mut slots = match self.slots.lock() {
+            Ok(slots) => slots,
+            Err(_e) => return, // counting is best effort
+        };
+        slots.n_reads += 1;
+
+        let tracked = slots.counters.get(key).map(|c| (c.count, c.error));
+        let (count, error) = match tracked {
+            Some((count, error)) => {
+                slots.by_count.remove(&(count, key.to_vec()));
+                (count + 1, error)
+            }
+            None if slots.counters.len() < self.max_keys => (1, 0),
+            None => match slots.by_count.pop_first() {
+                None => return,
+                Some((least, least_key)) => {
+                    slots.counters.remove(&least_key);
+                    (least + 1, least)
+                }
+            },
+        };
+        slots
+            .counters
+            .insert(key.to_vec(), Counter { count, error });
+        slots.by_count.insert((count, key.to_vec()));
+    }
+
+    /// Up to n tracked keys, most read first, and the reads counted in
+    /// all, of any key.
+    pub fn top(&self, n: usize) -> (Vec<HotKey>, u64) {
+        let slots = match self.slots.lock() {
+            Ok(slots) => slots,
+            Err(_e) => return (Vec::new(), 0),
+        };
+        let top = slots
+            .by_count
+            .iter()
+            .rev()
+            .take(n)
+            .map(|(count, key)| HotKey {
+                key: key.clone(),
+                count: *count,
+                error: slots.counters[key].error,
+            })
+            .collect();
+        (top, slots.n_reads)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hot_keys() {
+        let hot = HotKeys::with_max_keys(3);
+        for _i in 0..5 {
+            hot.record(b"a");
+        }
+        for _i in 0..3 {
+            hot.record(b"b");
+        }
+        hot.record(b"c");
+
+        // exact, while every key read has a slot
+        let (top, n_reads) = hot.top(2);
+        assert_eq!(n_reads, 9);
+        assert_eq!(
+            top,
+            vec![
+                HotKey {
+                    key: b"a".to_vec(),
+                    count: 5,
+                    error: 0
+                },
+                HotKey {
+                    key: b"b".to_vec(),
+                    count: 3,
+                    error: 0
+                },
+            ]
+        );
+
+        // a new key replaces the least read, inheriting its count
+        hot.record(b"d");
+        let (top, n_reads) = hot.top(10);
+        assert_eq!(n_reads, 10);
+        assert_eq!(top.len(), 3);
+        assert_eq!(
+            top[2],
+            HotKey {
+                key: b"d".to_vec(),
+                count: 2,
+                error: 1
+            }
+        );
+
+        // however many keys are read, only max_keys are kept, and the
+        // hot ones among them
+        for i in 0..1000u32 {
+            hot.record(&i.to_be_bytes());
+            hot.record(b"a");
+        }
+        assert_eq!(hot.slots.lock().unwrap().counters.len(), 3);
+        assert_eq!(hot.slots.lock().unwrap().by_count.len(), 3);
+        let (top, n_reads) = hot.top(1);
+        assert_eq!(n_reads, 2010);
+        assert_eq!(top[0].key, b"a".to_vec());
+        assert!(top[0].count >= 1005 && top[0].count - top[0].error <= 1005);
+    }
+}
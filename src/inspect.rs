@@ -0,0 +1,241 @@
+use std::io::{self, Write};
+use std::ops::ControlFlow;
+use std::path::Path;
+
+use kvdbd::db;
+use kvdbd::db::api::{Db, DbExt};
+
+/// Offline inspection subcommands, operating directly on a db directory.
+pub fn subcommands() -> Vec<clap::App<'static, 'static>> {
+    let path_arg = clap::Arg::with_name("path")
+        .value_name("DB-PATH")
+        .help("Database directory")
+        .required(true);
+    let driver_arg = clap::Arg::with_name("driver")
+        .long("driver")
+        .value_name("DRIVER")
+        .help("Database backend (default: as recorded at db creation)")
+        .takes_value(true);
+    let base64_arg = clap::Arg::with_name("base64")
+        .long("base64")
+        .help("Print keys or values base64-encoded");
+
+    vec![
+        clap::SubCommand::with_name("count")
+            .about("Print the number of records in a database, without starting the server")
+            .arg(path_arg.clone())
+            .arg(driver_arg.clone()),
+        clap::SubCommand::with_name("get")
+            .about("Print the value stored under a key, without starting the server")
+            .arg(path_arg.clone())
+            .arg(
+                clap::Arg::with_name("key")
+                    .value_name("KEY")
+                    .help("Key to look up")
+                    .required(true),
+            )
+            .arg(driver_arg.clone())
+            .arg(base64_arg.clone()),
+        clap::SubCommand::with_name("list")
+            .about("Print each key in a database, one per line, without starting the server")
+            .arg(path_arg)
+            .arg(driver_arg)
+            .arg(base64_arg),
+    ]
+}
+
+// driver and key case for the db at path: as recorded in its manifest,
+// else guessed from its files
+fn db_options(path: &str, driver: Option<&str>) -> Result<(String, db::api::KeyCase), String> {
+    let stored = db::manifest::stored_options(path).map_err(|e| e.to_string())?;
+    let guessed = if Path::new(path).join("data.mdb").exists() {
+        "lmdb"
+    } else {
+        "sled"
+    };
+
+    match (driver, stored) {
+        (Some(driver), Some((stored, _))) if driver != stored => {
+            Err(format!("db was created by driver {stored}"))
+        }
+        (_, Some((stored, key_case))) => Ok((stored, key_case)),
+        (Some(driver), None) => Ok((driver.to_string(), db::api::KeyCase::CaseSensitive)),
+        (None, None) => Ok((guessed.to_string(), db::api::KeyCase::CaseSensitive)),
+    }
+}
+
+/// Open the db at path read-only, failing with a printable message.
+pub fn open_db(
+    drivers: &[(&str, &dyn db::api::Driver)],
+    path: &str,
+    driver: Option<&str>,
+) -> Result<Box<dyn Db + Send>, String> {
+    // opening read-only would otherwise create an empty db
+    if !Path::new(path).is_dir() {
+        return Err(String::from("no database directory at this path"));
+    }
+
+    let (driver, key_case) = db_options(path, driver)?;
+    let backend = match drivers.iter().find(|(name, _)| *name == driver) {
+        Some((_, backend)) => backend,
+        None => return Err(format!("unsupported db driver {driver}")),
+    };
+
+    let db_config = db::api::ConfigBuilder::new()
+        .path(path.to_string())
+        .read_only(true)
+        .key_case(key_case)
+        .build();
+    match backend.start_db(db_config) {
+        Ok(db) => Ok(db),
+        Err(e) if e == db::api::ERR_DB_LOCKED => Err(String::from(
+            "database is locked by a running server; stop the server, or query it over HTTP",
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn encode(bytes: &[u8], use_base64: bool) -> Vec<u8> {
+    match use_base64 {
+        true => base64::encode(bytes).into_bytes(),
+        false => bytes.to_vec(),
+    }
+}
+
+/// Run subcommand name against db, writing its output to out.
+pub fn run_cmd(
+    db: &dyn Db,
+    name: &str,
+    args: &clap::ArgMatches,
+    out: &mut dyn Write,
+) -> Result<(), String> {
+    let use_base64 = args.is_present("base64");
+    match name {
+        "count" => {
+            let stat = db.stat().map_err(|e| e.to_string())?;
+            writeln!(out, "{}", stat.n_records).map_err(|e| e.to_string())
+        }
+        "get" => {
+            let key = args.value_of("key").unwrap_or_default();
+            match db.get(key.as_bytes()).map_err(|e| e.to_string())? {
+                None => Err(String::from("key not found")),
+                Some(val) => {
+                    out.write_all(&encode(&val, use_base64))
+                        .map_err(|e| e.to_string())?;
+                    if use_base64 {
+                        writeln!(out).map_err(|e| e.to_string())?;
+                    }
+                    Ok(())
+                }
+            }
+        }
+        "list" => {
+            let mut res = Ok(());
+            db.for_each_key(None, |key| {
+                res = out
+                    .write_all(&encode(key, use_base64))
+                    .and_then(|_| writeln!(out));
+                match res {
+                    Ok(()) => ControlFlow::Continue(()),
+                    Err(_) => ControlFlow::Break(()),
+                }
+            })
+            .map_err(|e| e.to_string())?;
+            res.map_err(|e| e.to_string())
+        }
+        _ => Err(format!("unknown command {name}")),
+    }
+}
+
+/// Run an inspection subcommand to completion, returning the process
+/// exit code.
+pub fn run(drivers: &[(&str, &dyn db::api::Driver)], name: &str, args: &clap::ArgMatches) -> i32 {
+    let path = args.value_of("path").unwrap_or_default();
+    let res = open_db(drivers, path, args.value_of("driver")).and_then(|db| {
+        let stdout = io::stdout();
+        let mut out = stdout.lock();
+        run_cmd(&*db, name, args, &mut out)
+    });
+
+    match res {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("{} {}: {}: {}", crate::APPNAME, name, path, e);
+            1
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+    use std::time::Duration;
+    use tempdir::TempDir;
+
+    fn run_args(db: &dyn Db, argv: &[&str]) -> Result<Vec<u8>, String> {
+        let matches = clap::App::new("test")
+            .subcommands(subcommands())
+            .get_matches_from_safe(argv)
+            .unwrap();
+        let (name, args) = matches.subcommand();
+        let mut out = Vec::new();
+        run_cmd(db, name, args.unwrap(), &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    #[cfg(all(feature = "sled", feature = "lmdb"))]
+    fn test_inspect() {
+        let tmp_dir = TempDir::new("tin").unwrap();
+        let tmp_path = tmp_dir.path().to_str().unwrap().to_string();
+        let sled = db::sled::new_driver();
+        let lmdb = db::lmdb::new_driver();
+        let drivers: Vec<(&str, &dyn db::api::Driver)> = vec![("sled", &*sled), ("lmdb", &*lmdb)];
+
+        // missing path, not created
+        let missing = tmp_dir.path().join("missing");
+        let missing = missing.to_str().unwrap();
+        assert!(open_db(&drivers, missing, None).is_err());
+        assert!(!Path::new(missing).exists());
+
+        // create db, recording its options
+        {
+            let db_config = db::api::ConfigBuilder::new()
+                .path(tmp_path.clone())
+                .read_only(false)
+                .build();
+            let mut db = sled.start_db(db_config).unwrap();
+            assert_eq!(db.put(b"name", b"alan"), Ok(true));
+            assert_eq!(db.put(b"age", b"41"), Ok(true));
+
+            // held open for writing
+            match open_db(&drivers, &tmp_path, None) {
+                Err(e) => assert!(e.contains("locked by a running server")),
+                Ok(_db) => panic!("unexpected success"),
+            }
+        }
+
+        assert!(open_db(&drivers, &tmp_path, Some("lmdb")).is_err());
+
+        // sled's background threads may hold the db briefly after drop
+        let mut db = open_db(&drivers, &tmp_path, None);
+        for _ in 0..100 {
+            if db.is_ok() {
+                break;
+            }
+            thread::sleep(Duration::from_millis(10));
+            db = open_db(&drivers, &tmp_path, None);
+        }
+        let db = db.unwrap();
+
+        assert_eq!(run_args(&*db, &["t", "count", "p"]).unwrap(), b"2\n");
+        assert_eq!(run_args(&*db, &["t", "get", "p", "name"]).unwrap(), b"alan");
+        assert_eq!(
+            run_args(&*db, &["t", "get", "p", "name", "--base64"]).unwrap(),
+            b"YWxhbg==\n"
+        );
+        assert!(run_args(&*db, &["t", "get", "p", "nobody"]).is_err());
+        assert_eq!(run_args(&*db, &["t", "list", "p"]).unwrap(), b"age\nname\n");
+    }
+}
@@ -0,0 +1,106 @@
+// per-key locks, serializing read-modify-writes of one key
+//
+// A fixed number of mutexes, each guarding every key whose hash falls in
+// its shard:  read-modify-writes (append, merge, conditional writes) of
+// one key are serialized, while those of keys in other shards proceed
+// in parallel.  Two keys sharing a shard serialize too, which is safe,
+// if slower.  Memory is bounded by the shards, whatever the number of
+// distinct keys locked.
+//
+// Taken before the server state lock, never while holding it, so that
+// a writer waiting on a key does not hold up every other request.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::{Mutex, MutexGuard};
+
+// mutexes, server-wide
+pub const KEY_LOCK_SHARDS: usize = 256;
+
+/// Sharded locks over (db name, key) pairs.
+pub struct KeyLocks {
+    shards: Vec<Mutex<()>>,
+}
+
+impl KeyLocks {
+    pub fn new() -> KeyLocks {
+        KeyLocks::with_shards(KEY_LOCK_SHARDS)
+    }
+
+    /// Locks over n_shards mutexes.
+    pub fn with_shards(n_shards: usize) -> KeyLocks {
+        KeyLocks {
+            shards: (0..n_shards.max(1)).map(|_| Mutex::new(())).collect(),
+        }
+    }
+
+    // index of the mutex guarding key of db_name
+    fn shard(&self, db_name: &str, key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        db_name.hash(&mut hasher);
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    /// Lock key of db_name, until the guard is dropped, waiting while
+    /// another holds it.  The mutexes guard no data, so one poisoned by
+    /// a panicking holder is taken as is.
+    pub fn lock(&self, db_name: &str, key: &[u8]) -> MutexGuard<'_, ()> {
+        let shard = &self.shards[self.shard(db_name, key)];
+        match shard.lock() {
+            Ok(guard) => guard,
+            Err(poisoned) => poisoned.into_inner(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn test_key_locks() {
+        let locks = Arc::new(KeyLocks::with_shards(16));
+        let other_key = (0u32..)
+            .map(|n| format!("key{n}").into_bytes())
+            .find(|key| locks.shard("db", key) != locks.shard("db", b"a"))
+            .unwrap();
+        let guard = locks.lock("db", b"a");
+
+        // another key is locked while "a" is held
+        let (sender, receiver) = mpsc::channel();
+        let t_locks = Arc::clone(&locks);
+        let handle = thread::spawn(move || {
+            let _guard = t_locks.lock("db", &other_key);
+            sender.send(()).unwrap();
+        });
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+        handle.join().unwrap();
+
+        // "a" itself, of the same db, waits until released
+        let (sender, receiver) = mpsc::channel();
+        let t_locks = Arc::clone(&locks);
+        let handle = thread::spawn(move || {
+            let _guard = t_locks.lock("db", b"a");
+            sender.send(()).unwrap();
+        });
+        assert!(receiver.recv_timeout(Duration::from_millis(100)).is_err());
+        drop(guard);
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(5)), Ok(()));
+        handle.join().unwrap();
+
+        // a poisoned lock is still taken
+        let t_locks = Arc::clone(&locks);
+        let res = thread::spawn(move || {
+            let _guard = t_locks.lock("db", b"a");
+            panic!("holder panics");
+        })
+        .join();
+        assert!(res.is_err());
+        drop(locks.lock("db", b"a"));
+    }
+}
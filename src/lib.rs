@@ -0,0 +1,48 @@
+/*
+ * kvdbd: Storage core of the kvdbd database server, as a library
+ */
+
+//! The `db` module (the Db trait, sled and lmdb drivers, batches and
+//! transactions) may be used in-process, without the HTTP server.  It
+//! does not need the `server` feature, which builds the kvdbd, tester
+//! and kvdb-pb binaries; each driver is a feature of its own:
+//!
+//! ```toml
+//! kvdbd = { version = "0.6", default-features = false, features = ["sled"] }
+//! ```
+//!
+//! Open a database, and store and lookup values:
+//!
+//! ```
+//! use kvdbd::db;
+//! use kvdbd::db::api::{Batch, ConfigBuilder};
+//!
+//! let dir = tempdir::TempDir::new("embedded").unwrap();
+//! let mut builder = ConfigBuilder::new();
+//! builder.path(dir.path().join("db1.kv").to_str().unwrap().to_string());
+//! let mut db = db::sled::new_driver().start_db(builder.build()).unwrap();
+//!
+//! db.put(b"name", b"kvdbd").unwrap();
+//! let mut batch = Batch::default();
+//! batch.insert(b"a", b"1");
+//! batch.remove(b"name");
+//! db.apply_batch(&batch).unwrap();
+//!
+//! assert_eq!(db.get(b"a").unwrap(), Some(b"1".to_vec()));
+//! assert_eq!(db.get(b"name").unwrap(), None);
+//! ```
+//!
+//! `db::iter::DbIterator` walks a db's keys as an `Iterator`, fetching
+//! each page of `max_iter_keys` keys as the last runs out; `entries()`
+//! yields each key with its value.
+//!
+//! For tests, `db::mem::new_driver()` starts in-memory dbs, which
+//! persist nothing (the `mem` feature, on by default).
+//! `db::driver_for()` returns any compiled-in driver by name, e.g.
+//! `"sled"`, `"lmdb"` or `"mem"`, as a config file may choose it.
+//!
+//! Operations fail with a `db::Error`, whose variants tell refused
+//! writes (e.g. `ReadOnly`, `QuotaExceeded`) from backend failures
+//! (`Backend`) and undecodable stored data (`Corruption`).
+
+pub mod db;
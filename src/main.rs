@@ -1,70 +1,97 @@
-#[macro_use]
 extern crate actix_web;
 extern crate clap;
-mod db;
+mod accesslog;
+mod auditlog;
+mod auth;
+#[allow(dead_code)] // wire format; requests are encoded only by clients
+mod binproto;
+mod config;
+mod hotkeys;
+mod inspect;
+mod keylock;
+mod metrics;
+#[cfg(feature = "otel")]
+mod otel;
 mod protos;
+mod ratelimit;
 
 const APPNAME: &'static str = "kvdbd";
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 const DEF_CFG_FN: &'static str = "cfg-kvdbd.json";
 const DEF_BIND_ADDR: &'static str = "127.0.0.1";
 const DEF_BIND_PORT: &'static str = "8080";
+const KEEP_ALIVE_SECS: usize = 75;
+const SWEEP_MIN_WAIT: Duration = Duration::from_millis(100);
+const BINARY_ACCEPT_POLL: Duration = Duration::from_millis(100);
+const SCAN_REAP_INTERVAL: Duration = Duration::from_secs(1);
+const AUDIT_FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+const DEF_HISTOGRAM_SAMPLE: usize = 1000;
+const MAX_HISTOGRAM_SAMPLE: usize = 100_000;
+const DEF_HISTOGRAM_MAX_SCAN: u64 = 100_000;
+const MAX_IMPORT_LINE: usize = 64 * 1024 * 1024;
+const MAX_COUNT_CACHE_PREFIXES: usize = 10_000;
+const VALUE_STREAM_CHUNK: usize = 64 * 1024;
 
-use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use std::{env, fs, io, process};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Seek, SeekFrom, Write};
+use std::net::{IpAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, fmt, fs, io, mem, process};
 
-use actix_web::http::StatusCode;
-use actix_web::{guard, middleware, web, App, HttpRequest, HttpResponse, HttpServer, Result};
-use openssl::ssl::{SslAcceptor, SslFiletype, SslMethod};
+use actix_http::HttpService;
+use actix_server::ssl::{OpensslAcceptor, SslError};
+use actix_service::{NewService, Service};
+use actix_web::body::{BodySize, MessageBody};
+use actix_web::dev::{RequestHead, ServiceResponse, SizedStream};
+use actix_web::error::BlockingError;
+use actix_web::http::{header, HeaderMap, Method, StatusCode, Version};
+use actix_web::{
+    guard, middleware, web, App, HttpMessage, HttpRequest, HttpResponse, Resource, Result, Route,
+};
+use chrono::{DateTime, Local};
+use futures::future::{ok, Either};
+use futures::{Async, Future, Poll, Stream};
+use log::{info, warn};
+use openssl::ssl::{AlpnError, SslAcceptor, SslFiletype, SslMethod};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use signal_hook::iterator::Signals;
 
+use auditlog::AuditLog;
+use auth::DebugRequest;
+use config::{DbConfig, OpKind, RuntimeDbsConfig, ServerConfig};
+use config::{DEF_GROUP_COMMIT_MAX_OPS, DEF_MAX_MGET_KEYS};
+use hotkeys::HotKeys;
+use keylock::KeyLocks;
+use kvdbd::db;
+use kvdbd::db::api::{Consistency, DbExt, MutationList, VersionedPut};
+use kvdbd::db::groupcommit::{self, GroupCommit};
+use kvdbd::db::watch::{WatchHub, WatchItem, Watcher};
+use metrics::Metrics;
 use protobuf::{parse_from_bytes, Message, ProtobufError, ProtobufResult};
 use protos::pbapi::{
-    BatchRequest, BatchRequest_MagicNum, DbStatResponse, DbStatResponse_MagicNum, IterRequest,
-    IterRequest_MagicNum, KeyRequest, KeyRequest_MagicNum, KeyResponse, KeyResponse_MagicNum,
-    UpdateRequest, UpdateRequest_MagicNum,
+    BatchRequest, BatchRequest_MagicNum, DbStatResponse, DbStatResponse_MagicNum, EntryResponse,
+    EntryResponse_Entry, EntryResponse_MagicNum, IterRequest, IterRequest_MagicNum, KeyRequest,
+    KeyRequest_MagicNum, KeyResponse, KeyResponse_MagicNum, UpdateRequest, UpdateRequest_MagicNum,
 };
-
-// struct used for both input (server config file) and output (server info)
-#[derive(Serialize, Deserialize, Clone)]
-struct DbConfig {
-    name: String,
-    path: String,
-    driver: String,
-    read_only: bool,
-}
-
-#[derive(Serialize, Deserialize)]
-struct SslConfig {
-    private_key_path: String, // empty, if no SSL
-    cert_chain_path: String,  // empty, if no SSL
-}
-
-impl SslConfig {
-    fn new() -> SslConfig {
-        SslConfig {
-            private_key_path: String::new(),
-            cert_chain_path: String::new(),
-        }
-    }
-}
-
-// top-level schema for server configuration file
-#[derive(Serialize, Deserialize)]
-struct ServerConfig {
-    debug: bool,
-    ssl: SslConfig,
-    databases: Vec<DbConfig>,
-}
+use ratelimit::RateLimiter;
 
 // top-level server info output struct
 #[derive(Serialize, Deserialize)]
 struct ServerInfo {
     name: String,
     version: String,
+    api_base: String,
+    http2: bool,              // HTTP/2 accepted on this listener
+    binary_port: Option<u16>, // binary protocol listener port, if any
+    runtime_dbs: bool,        // dbs may be created via the API
     databases: Vec<DbConfig>,
+    degraded: Vec<String>, // dbs degraded to read-only by write failures
 }
 
 // JSON response to stat API request
@@ -78,672 +105,5786 @@ struct DbStatResponseJson {
 struct KeyResponseJson {
     keys: Vec<String>,
     list_end: bool,
+    page_size: usize,           // most keys listed per page
+    next_start: Option<String>, // lastkey of the next page; None if list_end
 }
 
-// per-db runtime state info
-struct DbState {
-    cfg: DbConfig,                   // imported db configuration
-    db: Box<dyn db::api::Db + Send>, // open db handle
+// query string option of requests keyed by path segment
+#[derive(Deserialize)]
+struct KeyEncQuery {
+    keyenc: Option<String>, // "base64":  keys are base64url, as are keys echoed
 }
 
-// runtime server state info
-struct ServerState {
-    debug: bool,
-    name_idx: HashMap<String, usize>,
-    dbs: Vec<DbState>, // all open databases
+// query string options for GET of a single object
+#[derive(Deserialize)]
+struct ObjGetQuery {
+    default: Option<String>, // body returned if key not found
 }
 
-struct Backend {
-    cli_help: String,
-    cli_value_name: String,
-    driver: Box<dyn db::api::Driver>,
+// query string options for POST to a single object
+#[derive(Deserialize)]
+struct ObjPostQuery {
+    rename_to: Option<String>, // move value to this key
+    overwrite: Option<bool>,   // rename: replace existing target key?
+    touch: Option<u64>,        // reset expiry to this many seconds from now
 }
 
-struct BackendState {
-    backends: HashMap<String, Backend>,
+// query string options for PUT and DELETE of data items
+#[derive(Deserialize)]
+struct WriteQuery {
+    durable: Option<String>,  // "1" or "true": fsync this write
+    if_empty: Option<String>, // DELETE: "1" or "true": only if value empty
 }
 
-fn build_backend(id: &str) -> Backend {
-    let value_str = format!("{}-DB-PATH", id);
-    let help_str = format!("Zeroconf; ignore server config, and create single database 'db' using backend {} with param {}", id, value_str);
-    Backend {
-        cli_help: help_str,
-        cli_value_name: value_str,
-        driver: match id {
-            "sled" => db::sled::new_driver(),
-            "lmdb" => db::lmdb::new_driver(),
-            _ => panic!("unknown db driver"),
+// query string options for change feed requests
+#[derive(Deserialize)]
+struct ChangesQuery {
+    since: Option<u64>, // list changes after this sequence number
+}
+
+// query string options for swap requests
+#[derive(Deserialize)]
+struct SwapQuery {
+    a: String, // keys whose values are exchanged
+    b: String,
+}
+
+// query string options for range size requests
+#[derive(Deserialize)]
+struct SizeQuery {
+    start: Option<String>, // first key of range; default: db start
+    end: Option<String>,   // key following range; default: db end
+}
+
+// query string options for drain requests
+#[derive(Deserialize)]
+struct DrainQuery {
+    reject_writes: Option<String>, // "1" or "true": also refuse writes
+}
+
+// query string options for replica reopen requests
+#[derive(Deserialize)]
+struct ReopenQuery {
+    db: Option<String>, // reopen only this db; default: every replica
+}
+
+// query string options for prefix count requests
+#[derive(Deserialize)]
+struct CountQuery {
+    prefix: Option<String>, // count keys with this prefix; default: all keys
+    fresh: Option<String>,  // "1": count now, bypassing the count cache
+}
+
+// query string options for db stats requests
+#[derive(Deserialize)]
+struct DbStatsQuery {
+    prefix: Option<String>, // count keys with this prefix; default: all keys
+}
+
+// query string options for prefix delete requests
+#[derive(Deserialize)]
+struct DeletePrefixQuery {
+    prefix: Option<String>, // delete keys with this prefix; required
+}
+
+// query string options for prefix existence tests
+#[derive(Deserialize)]
+struct HasPrefixQuery {
+    prefix: Option<String>, // test for a key with this prefix; required
+}
+
+// query string options for paged key listing
+#[derive(Deserialize)]
+struct KeysQuery {
+    after: Option<String>,   // cursor:  continue after this key (base64)
+    cursor: Option<String>,  // the same, as named by other listings
+    limit: Option<usize>,    // keys per page, at most
+    prefix: Option<String>,  // list only keys with this prefix (base64)
+    reverse: Option<String>, // descending key order, if set
+}
+
+// query string options for batch updates
+#[derive(Deserialize)]
+struct BatchQuery {
+    duplicates: Option<String>,  // "last_wins" (default) or "reject"
+    guard_key: Option<String>,   // apply only if this key (base64) ...
+    guard_value: Option<String>, // ... holds this value (base64), or is absent
+}
+
+// query string options for paged records
+#[derive(Deserialize)]
+struct EntriesQuery {
+    after: Option<String>,    // cursor:  continue after this key (base64)
+    limit: Option<usize>,     // records per page, at most
+    max_bytes: Option<usize>, // value bytes per page, at most
+    start: Option<String>,    // range:  keys from this one (base64), inclusive
+    end: Option<String>,      // range:  keys before this one (base64)
+}
+
+// query string options for record export
+#[derive(Deserialize)]
+struct ExportQuery {
+    format: Option<String>, // "jsonl" (default; dump: "binary"), "csv" or "binary"
+    prefix: Option<String>, // export only keys with this prefix (base64)
+}
+
+// query string options for change watch streams
+#[derive(Deserialize)]
+struct WatchQuery {
+    prefix: Option<String>, // watch only keys with this prefix, per keyenc
+}
+
+// query string options for record import
+#[derive(Deserialize)]
+struct ImportQuery {
+    clear: Option<String>, // "1" or "true": clear db before importing
+}
+
+// one line of a JSON lines import:  a put of v, or with op "del", a delete
+#[derive(Deserialize)]
+struct ImportRecord {
+    k: String,          // key (base64)
+    v: Option<String>,  // value (base64)
+    op: Option<String>, // "del" to delete k
+}
+
+// secondary index registration
+#[derive(Deserialize)]
+struct IndexJson {
+    name: String,      // index name; letters, digits, '_' and '-'
+    json_path: String, // field indexed in each JSON value, e.g. "$.email"
+}
+
+// query string options for secondary index lookups
+#[derive(Deserialize)]
+struct IndexQuery {
+    eq: String, // indexed field value to match
+}
+
+// query string options for glob key scans
+#[derive(Deserialize)]
+struct ScanQuery {
+    glob: String,            // key pattern; '*' and '?' wildcards
+    lastkey: Option<String>, // continue after this key
+}
+
+// query string options for hot key requests
+#[derive(Deserialize)]
+struct HotKeysQuery {
+    n: Option<usize>, // keys listed, at most
+}
+
+// keys listed by a hot key request, by default
+const DEF_HOT_KEYS_N: usize = 20;
+
+// query string options for size histogram requests
+#[derive(Deserialize)]
+struct HistogramQuery {
+    sample: Option<usize>, // records retained in sample
+    max_scan: Option<u64>, // records read, at most
+}
+
+// request header asking that a single write be made durable
+const SYNC_HEADER: &str = "X-KVDB-Sync";
+
+// is durability (fsync) requested for this write, by query string or
+// header?  overrides the db's default sync policy
+fn write_sync(req: &HttpRequest, query: &WriteQuery) -> bool {
+    if let Some(val) = &query.durable {
+        return is_set(val);
+    }
+    match req.headers().get(SYNC_HEADER) {
+        None => false,
+        Some(hdr) => is_set(hdr.to_str().unwrap_or("")),
+    }
+}
+
+// request header giving the freshness a read requires
+const CONSISTENCY_HEADER: &str = "X-KVDB-Consistency";
+
+// consistency requested for this read; Strong if none.  Err if the
+// header names no known level
+fn read_consistency(req: &HttpRequest) -> std::result::Result<Consistency, &'static str> {
+    match req.headers().get(CONSISTENCY_HEADER) {
+        None => Ok(Consistency::default()),
+        Some(hdr) => match hdr.to_str().ok().and_then(Consistency::parse) {
+            None => Err("invalid consistency"),
+            Some(consistency) => Ok(consistency),
         },
     }
 }
 
-fn register_backends() -> BackendState {
-    let mut bs = BackendState {
-        backends: HashMap::new(),
+// boolean query string option:  "1" or "true"
+fn is_set(val: &str) -> bool {
+    val == "1" || val.eq_ignore_ascii_case("true")
+}
+
+// db name and operation class of an API request, for disabled_ops;
+// None for server-wide routes, and paths matching no route
+fn request_op<'a>(method: &Method, path: &'a str, api_base: &str) -> Option<(&'a str, OpKind)> {
+    let mut elems = path.strip_prefix(api_base)?.strip_prefix('/')?.split('/');
+    let db_name = elems.next()?;
+    let op = match (elems.next(), method) {
+        (None, &Method::POST) => OpKind::Put, // alloc
+        (None, &Method::DELETE) => OpKind::Clear,
+        (Some("obj"), &Method::GET) | (Some("obj"), &Method::HEAD) => OpKind::Get,
+        (Some("obj"), &Method::DELETE) => OpKind::Delete,
+        (Some("obj"), _) => OpKind::Put, // PUT, POST rename, append and merge
+        (Some("_index"), &Method::GET) => OpKind::Get,
+        (Some("batch"), _) | (Some("_import"), _) | (Some("_index"), _) => OpKind::Batch,
+        (Some("restore"), _) | (Some("_cset"), _) => OpKind::Batch,
+        (Some("clear"), _) => OpKind::Clear,
+        (Some("del"), _) | (Some("_mdel"), _) => OpKind::Delete,
+        (Some("keys"), &Method::DELETE) => OpKind::Delete,
+        (Some("get"), _) | (Some("_mget"), _) | (Some("_exists"), _) => OpKind::Get,
+        (Some("put"), _) | (Some("_swap"), _) | (Some("flush"), _) => OpKind::Put,
+        (Some("blobs"), _) => OpKind::Put,
+        (Some("keys"), _) | (Some("keys.json"), _) | (Some("_keys"), _) => OpKind::Iter,
+        (Some("_scan"), _) | (Some("_export"), _) | (Some("_changes"), _) => OpKind::Iter,
+        (Some("_entries"), _) | (Some("dump"), _) | (Some("watch"), _) => OpKind::Iter,
+        (Some("stat"), _) | (Some("stat.json"), _) | (Some("stats"), _) => OpKind::Stat,
+        (Some("_size"), _) | (Some("_histogram"), _) | (Some("_count"), _) => OpKind::Stat,
+        (Some("_hotkeys"), _) => OpKind::Stat,
+        (Some("_sweep"), _) | (Some("compact"), _) => OpKind::Sweep,
+        (Some("_txn"), _) => OpKind::Txn,
+        _ => return None,
     };
+    Some((db_name, op))
+}
 
-    bs.backends
-        .insert(String::from("sled"), build_backend("sled"));
-    bs.backends
-        .insert(String::from("lmdb"), build_backend("lmdb"));
+// admin API paths, below api_base, which request_op() sees as db names
+const ADMIN_PATHS: [&str; 5] = ["_drain", "_inflight", "_reload", "_reopen", "_undrain"];
 
-    return bs;
+// open db_name beneath runtime_dbs.dir, if a valid name not yet open,
+// before the request naming it is routed.  a failure is logged, and the
+// request then finds no such db
+fn open_on_use(m_state: &RwLock<ServerState>, db_name: &str) {
+    // most requests name a db already open:  check under the shared lock,
+    // so as not to serialize them
+    match m_state.read() {
+        Ok(state) if !state.name_idx.contains_key(db_name) => {}
+        _ => return, // open; or poisoned, which the handler reports
+    }
+    if !RuntimeDbsConfig::valid_name(db_name) {
+        return;
+    }
+
+    // another request may have opened it since
+    let mut state = match m_state.write() {
+        Ok(state) => state,
+        Err(_e) => return, // the handler reports the poisoned lock
+    };
+    if state.name_idx.contains_key(db_name) {
+        return;
+    }
+    if let Err(e) = state.open_runtime_db(db_name) {
+        warn!("create of db {db_name} failed: {e}");
+    }
 }
 
-// helper function, 404 not found
-fn err_not_found() -> Result<HttpResponse> {
-    Ok(HttpResponse::build(StatusCode::NOT_FOUND)
-        .content_type("application/json")
-        .body(
-            json!({
-          "error": {
-             "code" : -404,
-              "message": "not found"}})
-            .to_string(),
-        ))
+// response header giving a GET's key version, if versions are tracked
+const VERSION_HEADER: &str = "X-KVDB-Version";
+
+// request header making a PUT conditional on the key's version
+const IF_VERSION_HEADER: &str = "X-KVDB-If-Version";
+
+// version a PUT's If-Version header expects of the key, if given.  Err if
+// the header is not a decimal integer
+fn write_if_version(req: &HttpRequest) -> std::result::Result<Option<u64>, &'static str> {
+    match req.headers().get(IF_VERSION_HEADER) {
+        None => Ok(None),
+        Some(hdr) => match hdr.to_str().ok().and_then(|v| v.trim().parse().ok()) {
+            Some(version) => Ok(Some(version)),
+            None => Err("invalid If-Version"),
+        },
+    }
 }
 
-// helper function, 400 bad request
-fn err_bad_req() -> Result<HttpResponse> {
-    Ok(HttpResponse::build(StatusCode::BAD_REQUEST)
-        .content_type("application/json")
-        .body(
-            json!({
-          "error": {
-             "code" : -400,
-              "message": "invalid/malformed request"}})
-            .to_string(),
-        ))
+// request header giving a PUT's absolute expiry, in unix milliseconds
+const EXPIRE_AT_HEADER: &str = "X-KVDB-Expire-At";
+
+// request header giving a PUT's expiry relative to now, in seconds, or
+// TTL_NONE
+const TTL_HEADER: &str = "X-KVDB-TTL";
+
+// TTL header value storing a PUT without expiry, despite any default_ttl_secs
+const TTL_NONE: &str = "none";
+
+// expiry requested for this PUT, if any, as absolute unix milliseconds;
+// db::api::NO_EXPIRY if TTL_NONE.  Err if either header is malformed, or
+// both are given
+fn write_expire_at(req: &HttpRequest) -> std::result::Result<Option<u64>, &'static str> {
+    let hdr_str = |name| match req.headers().get(name) {
+        None => Ok(None),
+        Some(hdr) => match hdr.to_str() {
+            Err(_e) => Err("invalid expiry"),
+            Ok(s) => Ok(Some(s.trim())),
+        },
+    };
+    let parse_num = |s: Option<&str>| match s {
+        None => Ok(None),
+        Some(s) => match s.parse() {
+            Err(_e) => Err("invalid expiry"),
+            Ok(n) => Ok(Some(n)),
+        },
+    };
+    let ttl = match hdr_str(TTL_HEADER)? {
+        Some(TTL_NONE) => Some(db::api::NO_EXPIRY),
+        ttl_str => parse_num(ttl_str)?.map(db::api::ttl_expire_at),
+    };
+    match (parse_num(hdr_str(EXPIRE_AT_HEADER)?)?, ttl) {
+        (None, None) => Ok(None),
+        (Some(unix_ms), None) => Ok(Some(unix_ms)),
+        (None, Some(unix_ms)) => Ok(Some(unix_ms)),
+        (Some(_), Some(_)) => Err("conflicting expiry"),
+    }
 }
 
-// helper function, 500 server error
-fn err_500() -> Result<HttpResponse> {
-    Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
-        .content_type("application/json")
-        .body(
-            json!({
-          "error": {
-             "code" : -500,
-              "message": "internal server error"}})
-            .to_string(),
-        ))
+// request header prefix of a PUT's metadata, each replayed on GET as the
+// header named by the rest, e.g. X-KVDB-Meta-Content-Type:  Content-Type
+const META_HEADER_PREFIX: &str = "x-kvdb-meta-";
+const META_HEADERS: [&str; 3] = ["content-type", "content-disposition", "content-language"];
+
+// metadata requested for this PUT, as (header name, value) pairs.  Err
+// if a metadata header names a header not in META_HEADERS
+fn write_meta(req: &HttpRequest) -> std::result::Result<Vec<(String, String)>, &'static str> {
+    let mut meta = Vec::new();
+    for (name, val) in req.headers() {
+        let name = match name.as_str().strip_prefix(META_HEADER_PREFIX) {
+            None => continue,
+            Some(name) => name,
+        };
+        if !META_HEADERS.contains(&name) {
+            return Err("unsupported metadata header");
+        }
+        match val.to_str() {
+            Ok(val) => meta.push((name.to_string(), val.to_string())),
+            Err(_e) => return Err("invalid metadata header"),
+        }
+    }
+    Ok(meta)
 }
 
-fn pbenc_db_stat_resp(n_records: u64) -> Vec<u8> {
-    let mut out_msg = DbStatResponse::new();
-    out_msg.magic = DbStatResponse_MagicNum::MAGIC;
-    out_msg.set_n_records(n_records);
+// request header naming a write, so a client may safely retry it
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const IDEMPOTENCY_KEY_TTL: Duration = Duration::from_secs(600);
+const MAX_IDEMPOTENCY_KEYS: usize = 100_000;
 
-    return out_msg.write_to_bytes().unwrap();
+// idempotency key of this write, if any
+fn write_idem_key(req: &HttpRequest) -> Option<String> {
+    let hdr = req.headers().get(IDEMPOTENCY_KEY_HEADER)?;
+    hdr.to_str().ok().map(|s| s.to_string())
 }
 
-fn pbenc_keys_resp(key_list: &db::api::KeyList) -> Vec<u8> {
-    let mut out_msg = KeyResponse::new();
-    out_msg.magic = KeyResponse_MagicNum::MAGIC;
+// outcome of matching a Range request header against a value
+#[derive(Debug, PartialEq)]
+enum ByteRange {
+    Full,                  // no usable range; return entire value
+    Partial(usize, usize), // inclusive [start, end] slice of value
+    Unsatisfiable,         // range lies outside value
+}
 
-    for key in &key_list.keys {
-        out_msg.keys.push(key.clone());
+/// Parse a single-range `Range: bytes=...` header, for a value of val_len
+/// bytes.  Malformed or multi-range headers are ignored (Full), as RFC 7233
+/// permits.
+fn parse_byte_range(hdr: &str, val_len: usize) -> ByteRange {
+    let spec = match hdr.trim().strip_prefix("bytes=") {
+        Some(spec) => spec,
+        None => return ByteRange::Full,
+    };
+    if spec.contains(',') {
+        return ByteRange::Full;
     }
-    out_msg.set_list_end(key_list.list_end);
 
-    return out_msg.write_to_bytes().unwrap();
+    let (start_str, end_str) = match spec.find('-') {
+        Some(pos) => (spec[..pos].trim(), spec[pos + 1..].trim()),
+        None => return ByteRange::Full,
+    };
+
+    // suffix range: last N bytes
+    if start_str.is_empty() {
+        return match end_str.parse::<usize>() {
+            Err(_e) => ByteRange::Full,
+            Ok(0) => ByteRange::Unsatisfiable,
+            Ok(_n) if val_len == 0 => ByteRange::Unsatisfiable,
+            Ok(n) => ByteRange::Partial(val_len.saturating_sub(n), val_len - 1),
+        };
+    }
+
+    let start = match start_str.parse::<usize>() {
+        Err(_e) => return ByteRange::Full,
+        Ok(n) => n,
+    };
+    let end = if end_str.is_empty() {
+        None
+    } else {
+        match end_str.parse::<usize>() {
+            Err(_e) => return ByteRange::Full,
+            Ok(n) => Some(n),
+        }
+    };
+
+    match end {
+        Some(end) if end < start => ByteRange::Full,
+        _ if start >= val_len => ByteRange::Unsatisfiable,
+        Some(end) => ByteRange::Partial(start, end.min(val_len - 1)),
+        None => ByteRange::Partial(start, val_len - 1),
+    }
 }
 
-fn pbdec_iter_req(wiredata: &[u8]) -> ProtobufResult<IterRequest> {
-    match parse_from_bytes::<IterRequest>(wiredata) {
-        Err(e) => Err(e),
-        Ok(req) => {
-            if req.magic != IterRequest_MagicNum::MAGIC {
-                let wire_err = protobuf::error::WireError::Other;
-                let err = ProtobufError::WireError(wire_err);
-                Err(err)
-            } else {
-                Ok(req)
+// per-db runtime state info
+struct DbState {
+    cfg: DbConfig,                                 // imported db configuration
+    db: Box<dyn db::api::Db + Send>,               // open db handle
+    group: Option<Arc<GroupCommit>>,               // PUT group commit, if enabled
+    count_cache: HashMap<Vec<u8>, (u64, Instant)>, // _count results, by prefix
+    watch: Arc<WatchHub>,                          // watch subscribers; kept across reopen
+    hot_keys: Option<HotKeys>,                     // GET counts, if track_hot_keys
+}
+
+impl DbState {
+    // state of db, newly opened with configuration cfg, publishing its
+    // changes to watch
+    fn new(cfg: DbConfig, db: Box<dyn db::api::Db + Send>, watch: Arc<WatchHub>) -> DbState {
+        let group = match cfg.group_commit_window_us {
+            0 => None,
+            us => Some(Arc::new(GroupCommit::new(
+                Duration::from_micros(us),
+                cfg.group_commit_max_ops,
+            ))),
+        };
+        let hot_keys = cfg.track_hot_keys.then(HotKeys::new);
+        DbState {
+            cfg,
+            db,
+            group,
+            count_cache: HashMap::new(),
+            watch,
+            hot_keys,
+        }
+    }
+
+    // db to serve a read from, at consistency.  the one local db serves
+    // every read, at any consistency; a replica or read cache serving
+    // Eventual reads would be chosen here
+    fn reader(&self, _consistency: Consistency) -> &(dyn db::api::Db + Send) {
+        &*self.db
+    }
+
+    // true if disabled_ops refuses op on this db
+    fn op_disabled(&self, op: OpKind) -> bool {
+        self.cfg.disabled_ops.contains(&op)
+    }
+
+    // store record, applying the db's empty-value policy, and expiring at
+    // expire_at (unix ms), if given.  if sync, flush to storage before
+    // returning
+    fn put(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        sync: bool,
+        expire_at: Option<u64>,
+    ) -> Result<bool, db::Error> {
+        if val.is_empty() && self.cfg.treat_empty_put_as_delete {
+            self.db.del_sync(key, sync)?;
+            return Ok(true);
+        }
+        match expire_at {
+            None => self.db.put_sync(key, val, sync),
+            Some(unix_ms) => {
+                let res = self.db.put_expire_at(key, val, unix_ms)?;
+                if sync {
+                    self.db.flush()?;
+                }
+                Ok(res)
             }
         }
     }
-}
 
-fn pbdec_key_req(wiredata: &[u8]) -> ProtobufResult<KeyRequest> {
-    match parse_from_bytes::<KeyRequest>(wiredata) {
-        Err(e) => Err(e),
-        Ok(req) => {
-            if req.magic != KeyRequest_MagicNum::MAGIC {
-                let wire_err = protobuf::error::WireError::Other;
-                let err = ProtobufError::WireError(wire_err);
-                Err(err)
-            } else {
-                Ok(req)
+    // store the len bytes read from reader, applying the db's empty-value
+    // policy.  if sync, flush to storage before returning
+    fn put_stream(
+        &mut self,
+        key: &[u8],
+        reader: &mut dyn io::Read,
+        len: usize,
+        sync: bool,
+    ) -> Result<bool, db::Error> {
+        if len == 0 && self.cfg.treat_empty_put_as_delete {
+            self.db.del_sync(key, sync)?;
+            return Ok(true);
+        }
+        let res = self.db.put_stream(key, reader)?;
+        if sync {
+            self.db.flush()?;
+        }
+        Ok(res)
+    }
+
+    // write new (None:  remove key), applying the db's empty-value
+    // policy, only if If-Match tags admit key's value, by a
+    // compare-and-swap against the value checked.  Ok(false), changing
+    // nothing, if not.  if sync, flush to storage before returning
+    fn write_if_match(
+        &mut self,
+        key: &[u8],
+        new: Option<&[u8]>,
+        tags: &[String],
+        sync: bool,
+    ) -> Result<bool, db::Error> {
+        let current = self.db.get_meta(key)?.map(|(val, _meta)| val.to_vec());
+        if !if_match_admits(tags, current.as_deref()) {
+            return Ok(false);
+        }
+        let new = new.filter(|val| !(val.is_empty() && self.cfg.treat_empty_put_as_delete));
+        let res = self.db.compare_and_swap(key, current.as_deref(), new)?;
+        if res && sync {
+            self.db.flush()?;
+        }
+        Ok(res)
+    }
+
+    // store val only if key is at version expected (0:  absent),
+    // returning false if not.  an empty value, on a db treating it as a
+    // delete, is refused.  if sync, flush to storage before returning
+    fn put_if_version(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        expected: u64,
+        sync: bool,
+    ) -> Result<bool, db::Error> {
+        if val.is_empty() && self.cfg.treat_empty_put_as_delete {
+            return Err(db::Error::InvalidInput(String::from("empty value")));
+        }
+        let res = self.db.put_if_version(key, val, expected)?;
+        if res && sync {
+            self.db.flush()?;
+        }
+        Ok(res)
+    }
+
+    // store record only if key is absent, returning false if present.  an
+    // empty value, on a db treating it as a delete, has nothing to
+    // create, and is refused.  if sync, flush to storage before returning
+    fn put_if_absent(&mut self, key: &[u8], val: &[u8], sync: bool) -> Result<bool, db::Error> {
+        if val.is_empty() && self.cfg.treat_empty_put_as_delete {
+            return Err(db::Error::InvalidInput(String::from("empty create")));
+        }
+        let res = self.db.put_if_absent(key, val)?;
+        if res && sync {
+            self.db.flush()?;
+        }
+        Ok(res)
+    }
+
+    // store record with metadata.  if sync, flush to storage before
+    // returning
+    fn put_meta(
+        &mut self,
+        key: &[u8],
+        val: &[u8],
+        meta: &[(String, String)],
+        sync: bool,
+    ) -> Result<bool, db::Error> {
+        let res = self.db.put_meta(key, val, meta)?;
+        if sync {
+            self.db.flush()?;
+        }
+        Ok(res)
+    }
+
+    // merge operand into record, by the db's merge operator.  if sync,
+    // flush to storage before returning
+    fn merge(&mut self, key: &[u8], operand: &[u8], sync: bool) -> Result<(), db::Error> {
+        self.db.merge(key, operand)?;
+        if sync {
+            self.db.flush()?;
+        }
+        Ok(())
+    }
+
+    // store val under its content hash, returning that key.  if sync,
+    // flush to storage before returning
+    fn put_hashed(&mut self, val: &[u8], sync: bool) -> Result<Vec<u8>, db::Error> {
+        let key = self.db.put_hashed(val)?;
+        if sync {
+            self.db.flush()?;
+        }
+        Ok(key)
+    }
+
+    // store init only if key is absent, returning the value now present.
+    // an empty init, on a db treating an empty put as a delete, could not
+    // be stored, and is refused.  if sync, flush to storage before
+    // returning
+    fn get_or_init(&mut self, key: &[u8], init: &[u8], sync: bool) -> Result<Vec<u8>, db::Error> {
+        if init.is_empty() && self.cfg.treat_empty_put_as_delete {
+            return Err(db::Error::InvalidInput(String::from("empty init")));
+        }
+        let val = self.db.get_or_init(key, init)?;
+        if sync {
+            self.db.flush()?;
+        }
+        Ok(val)
+    }
+
+    // append suffix to record, returning the new value length.  if sync,
+    // flush to storage before returning
+    fn append(&mut self, key: &[u8], suffix: &[u8], sync: bool) -> Result<usize, db::Error> {
+        let len = self.db.append(key, suffix)?;
+        if sync {
+            self.db.flush()?;
+        }
+        Ok(len)
+    }
+
+    // the group commit to apply a PUT of val via, if enabled.  expiring
+    // puts, and empty puts treated as deletes, are applied directly
+    fn put_group(&self, val: &[u8], expire_at: Option<u64>) -> Option<Arc<GroupCommit>> {
+        if expire_at.is_some() || (val.is_empty() && self.cfg.treat_empty_put_as_delete) {
+            return None;
+        }
+        self.group.clone()
+    }
+
+    // count keys with prefix, reusing a count made within the db's
+    // count_cache_secs unless fresh.  returns the count, and the age of a
+    // cached count
+    fn count_prefix(
+        &mut self,
+        prefix: &[u8],
+        fresh: bool,
+    ) -> Result<(u64, Option<Duration>), db::Error> {
+        let ttl = Duration::from_secs(self.cfg.count_cache_secs);
+        let now = Instant::now();
+        if let Some((n_keys, counted)) = self.count_cache.get(prefix) {
+            let age = now.duration_since(*counted);
+            if !fresh && age < ttl {
+                return Ok((*n_keys, Some(age)));
+            }
+        }
+
+        let n_keys = self.db.count_prefix(prefix)?;
+        if ttl.is_zero() {
+            self.count_cache.clear();
+        } else {
+            if self.count_cache.len() >= MAX_COUNT_CACHE_PREFIXES {
+                self.count_cache
+                    .retain(|_prefix, (_n_keys, counted)| now.duration_since(*counted) < ttl);
+            }
+            if self.count_cache.len() >= MAX_COUNT_CACHE_PREFIXES {
+                self.count_cache.clear();
             }
+            self.count_cache.insert(prefix.to_vec(), (n_keys, now));
+        }
+        Ok((n_keys, None))
+    }
+}
+
+// handler response completed later, e.g. after blocking work or reading a
+// streamed request body; or, one which may be either
+type ResponseFuture = Box<dyn Future<Item = HttpResponse, Error = actix_web::Error>>;
+type EitherResponse = actix_web::Either<HttpResponse, ResponseFuture>;
+
+// apply a PUT via group, the group commit of db idx.  the state lock must
+// not be held, as the group's leader takes it to apply the group
+fn put_grouped(
+    m_state: &RwLock<ServerState>,
+    group: &GroupCommit,
+    idx: usize,
+    key: &[u8],
+    val: &[u8],
+    sync: bool,
+) -> Result<bool, db::Error> {
+    group.put(key, val, sync, |batch, sync| {
+        match lock_shared_state(m_state) {
+            Ok(mut state) => groupcommit::apply_group(&mut *state.dbs[idx].db, batch, sync),
+            Err(e) => vec![Err(e); batch.ops.len()],
+        }
+    })
+}
+
+// respond to a PUT via put_grouped(), run on the blocking thread pool, so
+// that a PUT waiting for its group does not stall the worker's other
+// requests
+fn put_grouped_async(
+    m_state: &Arc<RwLock<ServerState>>,
+    group: Arc<GroupCommit>,
+    idx: usize,
+    key: Vec<u8>,
+    val: web::Bytes,
+    sync: bool,
+) -> ResponseFuture {
+    let m_state = Arc::clone(m_state);
+    let fut = web::block(move || put_grouped(&m_state, &group, idx, &key, &val, sync));
+    Box::new(fut.then(|res| match res {
+        Ok(_optval) => ok_json(json!({"result": true})),
+        Err(BlockingError::Error(e)) => err_db(e),
+        Err(BlockingError::Canceled) => err_500(),
+    }))
+}
+
+// drain states of the server, set by DRAIN and UNDRAIN
+const DRAIN_NONE: u8 = 0; // serving, and ready
+const DRAIN_READY: u8 = 1; // serving, not ready
+const DRAIN_WRITES: u8 = 2; // serving reads only, not ready
+
+// runtime server state info
+struct ServerState {
+    debug: bool,
+    api_base: String,
+    http2: bool,
+    binary_port: Option<u16>,
+    name_idx: HashMap<String, usize>,
+    dbs: Vec<DbState>,               // all open databases
+    n_connections: Arc<AtomicUsize>, // client connections currently open
+    max_connections: Option<usize>,
+    max_request_bytes: usize,      // longest request body read
+    drain: Arc<AtomicU8>,          // DRAIN_* state
+    inflight: Arc<InFlight>,       // requests being served
+    metrics: Option<Arc<Metrics>>, // request metrics, if enabled
+    key_locks: Arc<KeyLocks>,      // serializing read-modify-writes of a key
+    txns: HashMap<u64, TxnState>,  // open HTTP transactions, by id
+    next_txn_id: u64,
+    txn_timeout: Duration,
+    scans: HashMap<u64, ScanState>, // open snapshot scans, by id
+    next_scan_id: u64,
+    scan_timeout: Duration,
+    idem_keys: HashMap<(usize, String), Instant>, // applied writes, to expiry
+    recover_poisoned_lock: bool,
+    audit: Option<Arc<AuditLog>>, // log of writes, if enabled
+    cfg_path: Option<String>,     // configuration file; none in zeroconf mode
+    cfg: ServerConfig,            // configuration in effect
+}
+
+impl ServerState {
+    // attribute the writes audited from now on, while the state lock is
+    // held, to peer, the client making them
+    fn audit_as(&self, peer: Option<IpAddr>) {
+        if let Some(audit) = &self.audit {
+            audit.set_peer(peer);
+        }
+    }
+
+    // apply live settings of new configuration, which must not change
+    // any restart setting
+    fn apply_config(&mut self, new_cfg: &ServerConfig) {
+        self.debug = new_cfg.debug;
+        self.txn_timeout = Duration::from_secs(new_cfg.txn_timeout_secs);
+        self.scan_timeout = Duration::from_secs(new_cfg.scan_timeout_secs);
+        self.recover_poisoned_lock = new_cfg.recover_poisoned_lock;
+
+        for db_state in &mut self.dbs {
+            if let Some(new_db) = new_cfg
+                .databases
+                .iter()
+                .find(|db| db.name == db_state.cfg.name)
+            {
+                db_state.cfg.missing_as_empty = new_db.missing_as_empty;
+                db_state.cfg.default_cache_control = new_db.default_cache_control.clone();
+                db_state.cfg.max_mget_keys = new_db.max_mget_keys;
+                db_state.cfg.max_page_bytes = new_db.max_page_bytes;
+                db_state.cfg.count_cache_secs = new_db.count_cache_secs;
+                db_state.cfg.treat_empty_put_as_delete = new_db.treat_empty_put_as_delete;
+            }
+
+            // an operator reload is the signal that storage is repaired
+            db_state.db.reset_degraded();
+        }
+
+        self.cfg = new_cfg.clone();
+    }
+
+    // index of db name, opening it beneath runtime_dbs.dir first if not
+    // already open.  callers hold the state lock throughout, so that
+    // concurrent first requests naming a db open its backend only once.
+    // Ok((idx, created))
+    fn open_runtime_db(&mut self, name: &str) -> Result<(usize, bool), db::Error> {
+        if let Some(idx) = self.name_idx.get(name) {
+            return Ok((*idx, false));
+        }
+        let db_cfg = match &self.cfg.runtime_dbs {
+            Some(runtime_dbs) if RuntimeDbsConfig::valid_name(name) => runtime_dbs.db_config(name),
+            _ => return Err(db::Error::InvalidInput(format!("db name {name}"))),
+        };
+
+        // lmdb, unlike sled, requires the db directory to exist
+        if let Err(e) = fs::create_dir_all(&db_cfg.path) {
+            return Err(db::Error::Backend(e.to_string()));
+        }
+        let key_case = db_cfg
+            .key_case_mode()
+            .unwrap_or(db::api::KeyCase::CaseSensitive);
+        let watch = Arc::new(WatchHub::new());
+        let db_config = db_open_config(&db_cfg, key_case, &watch, self.audit.as_ref())?;
+        let db = db::driver_for(&db_cfg.driver)?.start_db(db_config)?;
+        info!("created db {} at {}", name, db_cfg.path);
+
+        let idx = self.dbs.len();
+        self.name_idx.insert(name.to_string(), idx);
+        self.dbs.push(DbState::new(db_cfg, db, watch));
+        Ok((idx, true))
+    }
+
+    // roll back transactions idle past their timeout
+    fn expire_txns(&mut self) {
+        let now = Instant::now();
+        self.txns.retain(|_id, txn_state| txn_state.expires > now);
+    }
+
+    // lookup open transaction id, on the named db, extending its timeout.
+    // returns db index
+    fn txn_lookup(&mut self, db_name: &str, id: u64) -> Option<usize> {
+        self.expire_txns();
+        let idx = *self.name_idx.get(db_name)?;
+        let txn_state = self.txns.get_mut(&id)?;
+        if txn_state.db_idx != idx {
+            return None;
+        }
+        txn_state.expires = Instant::now() + self.txn_timeout;
+        Some(idx)
+    }
+
+    // has a write with this idempotency key been applied to db idx?
+    fn idem_applied(&mut self, idx: usize, key: &str) -> bool {
+        let now = Instant::now();
+        self.idem_keys.retain(|_key, expires| *expires > now);
+        self.idem_keys.contains_key(&(idx, key.to_string()))
+    }
+
+    // remember write applied to db idx.  if full, forget the oldest
+    fn idem_record(&mut self, idx: usize, key: String) {
+        if self.idem_keys.len() >= MAX_IDEMPOTENCY_KEYS {
+            let oldest = self
+                .idem_keys
+                .iter()
+                .min_by_key(|(_key, expires)| **expires)
+                .map(|(key, _expires)| key.clone());
+            if let Some(oldest) = oldest {
+                self.idem_keys.remove(&oldest);
+            }
+        }
+        self.idem_keys
+            .insert((idx, key), Instant::now() + IDEMPOTENCY_KEY_TTL);
+    }
+
+    // close snapshot scans idle past their timeout, releasing snapshots
+    fn expire_scans(&mut self) {
+        let now = Instant::now();
+        let dbs = &mut self.dbs;
+        self.scans.retain(|_id, scan_state| {
+            if scan_state.expires > now {
+                return true;
+            }
+            dbs[scan_state.db_idx].db.snapshot_close(scan_state.snap_id);
+            false
+        });
+    }
+
+    // lookup open snapshot scan id, on the named db, extending its
+    // timeout.  returns db index
+    fn scan_lookup(&mut self, db_name: &str, id: u64) -> Option<usize> {
+        self.expire_scans();
+        let idx = *self.name_idx.get(db_name)?;
+        let scan_state = self.scans.get_mut(&id)?;
+        if scan_state.db_idx != idx {
+            return None;
+        }
+        scan_state.expires = Instant::now() + self.scan_timeout;
+        Some(idx)
+    }
+}
+
+// open snapshot scan state
+struct ScanState {
+    db_idx: usize,
+    snap_id: u64,              // db snapshot, read by this scan
+    last_key: Option<Vec<u8>>, // continue after this key
+    list_end: bool,            // every key has been returned
+    expires: Instant,          // closed, if idle until then
+    opened: Instant,
+    n_keys: u64,      // records returned so far
+    key_bytes: u64,   // their total key bytes
+    value_bytes: u64, // and value bytes
+}
+
+// open HTTP transaction state
+struct TxnState {
+    db_idx: usize,
+    txn: db::txn::Txn,
+    expires: Instant, // rolled back, if idle until then
+}
+
+// one open client connection, counted until the connection closes.  Held
+// by the connection, and by each of its requests (via extensions); or,
+// for the binary protocol, by the thread serving the connection.
+struct ConnGuard {
+    n_connections: Arc<AtomicUsize>,
+    over_limit: bool, // opened while max_connections were already open
+}
+
+impl ConnGuard {
+    fn new(n_connections: &Arc<AtomicUsize>, max_connections: Option<usize>) -> Rc<ConnGuard> {
+        Rc::new(ConnGuard::open(n_connections, max_connections))
+    }
+
+    fn open(n_connections: &Arc<AtomicUsize>, max_connections: Option<usize>) -> ConnGuard {
+        let n_open = n_connections.fetch_add(1, Ordering::SeqCst) + 1;
+        ConnGuard {
+            n_connections: Arc::clone(n_connections),
+            over_limit: match max_connections {
+                None => false,
+                Some(max) => n_open > max,
+            },
+        }
+    }
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        self.n_connections.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// requests currently being served, for INFLIGHT.  At most MAX_INFLIGHT
+// are listed at once; any more are only counted
+const MAX_INFLIGHT: usize = 1024;
+
+struct InFlight {
+    next_id: AtomicU64,
+    n_untracked: AtomicUsize,
+    ops: Mutex<HashMap<u64, InFlightOp>>,
+}
+
+struct InFlightOp {
+    op: OpKind,
+    db: String,
+    method: Method,
+    key_len: Option<usize>, // obj requests:  key bytes
+    started: Instant,
+}
+
+impl InFlight {
+    fn new() -> InFlight {
+        InFlight {
+            next_id: AtomicU64::new(1),
+            n_untracked: AtomicUsize::new(0),
+            ops: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // register a request as started, until the returned guard is dropped
+    fn start(inflight: &Arc<InFlight>, op: InFlightOp) -> InFlightGuard {
+        let id = match inflight.ops.lock() {
+            Ok(mut ops) if ops.len() < MAX_INFLIGHT => {
+                let id = inflight.next_id.fetch_add(1, Ordering::Relaxed);
+                ops.insert(id, op);
+                Some(id)
+            }
+            _ => {
+                inflight.n_untracked.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+        };
+        InFlightGuard {
+            inflight: Arc::clone(inflight),
+            id,
+        }
+    }
+}
+
+// one request listed by InFlight (or counted, if id is None), until its
+// response is ready, or the request is abandoned
+struct InFlightGuard {
+    inflight: Arc<InFlight>,
+    id: Option<u64>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        match self.id {
+            None => {
+                self.inflight.n_untracked.fetch_sub(1, Ordering::Relaxed);
+            }
+            Some(id) => {
+                if let Ok(mut ops) = self.inflight.ops.lock() {
+                    ops.remove(&id);
+                }
+            }
+        }
+    }
+}
+
+// encoding of the keys a request names, in its path or query string,
+// and of keys echoed in its response, per its keyenc query parameter
+#[derive(Clone, Copy)]
+enum KeyEnc {
+    Text,   // as the path text, percent-decoded by the router
+    Base64, // base64url (RFC 4648 section 5); padding optional
+}
+
+impl KeyEnc {
+    // None if keyenc names no known encoding
+    fn of(req: &HttpRequest) -> Option<KeyEnc> {
+        let query = web::Query::<KeyEncQuery>::from_query(req.query_string()).ok()?;
+        match query.keyenc.as_deref() {
+            None => Some(KeyEnc::Text),
+            Some("base64") => Some(KeyEnc::Base64),
+            Some(_) => None,
+        }
+    }
+
+    fn decode(self, key: &str) -> Option<Vec<u8>> {
+        match self {
+            KeyEnc::Text => Some(key.as_bytes().to_vec()),
+            KeyEnc::Base64 => {
+                base64::decode_config(key.trim_end_matches('='), base64::URL_SAFE_NO_PAD).ok()
+            }
+        }
+    }
+
+    fn encode(self, key: &[u8]) -> String {
+        match self {
+            KeyEnc::Text => String::from_utf8_lossy(key).to_string(),
+            KeyEnc::Base64 => base64::encode_config(key, base64::URL_SAFE_NO_PAD),
+        }
+    }
+}
+
+// key bytes named by path segment key, per the request's keyenc; None
+// if either is invalid
+fn path_key(req: &HttpRequest, key: &str) -> Option<Vec<u8>> {
+    KeyEnc::of(req)?.decode(key)
+}
+
+// key bytes of an obj request, from the percent-encoded path
+fn request_key_len(path: &str, api_base: &str, db_name: &str) -> Option<usize> {
+    let rest = path
+        .strip_prefix(api_base)?
+        .strip_prefix('/')?
+        .strip_prefix(db_name)?;
+    let key = rest.strip_prefix("/obj/")?;
+    // each %XX escape is one byte
+    Some(key.len() - 2 * key.matches('%').count())
+}
+
+// request failed by a db-layer error, e.g. a poisoned state lock
+#[derive(Debug)]
+struct DbError(db::Error);
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "db: {}", self.0)
+    }
+}
+
+impl actix_web::ResponseError for DbError {
+    fn error_response(&self) -> HttpResponse {
+        err_500().unwrap() // always Ok
+    }
+}
+
+// lock runtime-live state data, exclusively.  a panic while the lock was
+// held poisons it; recover, if so configured, else fail with
+// ERR_LOCK_POISONED
+fn lock_shared_state(
+    m_state: &RwLock<ServerState>,
+) -> Result<RwLockWriteGuard<ServerState>, db::Error> {
+    let state = db::api::write_shared(m_state, recover_state)?;
+
+    // writes are audited as made by no client, unless the holder says
+    state.audit_as(None);
+    Ok(state)
+}
+
+// lock runtime-live state data, shared with other readers, to read dbs
+// and state but change neither:  GET, HEAD, listings and the like.  as
+// lock_shared_state(), a poisoned lock is recovered if so configured
+fn read_shared_state(
+    m_state: &RwLock<ServerState>,
+) -> Result<RwLockReadGuard<ServerState>, db::Error> {
+    db::api::read_shared(m_state, recover_state)
+}
+
+// true if the state lock, poisoned by a panic, is to be recovered
+fn recover_state(state: &ServerState) -> bool {
+    if state.recover_poisoned_lock {
+        warn!("state lock poisoned by panic; recovering");
+    }
+    state.recover_poisoned_lock
+}
+
+// lock runtime-live state data, for a request handler:  as
+// lock_shared_state(), failing the request with 500
+fn lock_state(m_state: &RwLock<ServerState>) -> Result<RwLockWriteGuard<ServerState>, DbError> {
+    lock_shared_state(m_state).map_err(DbError)
+}
+
+// lock runtime-live state data, for a request handler only reading:  as
+// read_shared_state(), failing the request with 500
+fn read_state(m_state: &RwLock<ServerState>) -> Result<RwLockReadGuard<ServerState>, DbError> {
+    read_shared_state(m_state).map_err(DbError)
+}
+
+// lock runtime-live state data, for a handler writing on behalf of req:
+// as lock_state(), auditing the writes made as req's client's
+fn lock_state_for<'a>(
+    m_state: &'a RwLock<ServerState>,
+    req: &HttpRequest,
+) -> Result<RwLockWriteGuard<'a, ServerState>, DbError> {
+    let state = lock_state(m_state)?;
+    state.audit_as(req.peer_addr().map(|peer| peer.ip()));
+    Ok(state)
+}
+
+// the locks serializing read-modify-writes of a key, to take before the
+// state lock, and never while holding it; see keylock
+fn key_locks(m_state: &RwLock<ServerState>) -> Result<Arc<KeyLocks>, DbError> {
+    Ok(Arc::clone(&read_state(m_state)?.key_locks))
+}
+
+struct Backend {
+    cli_help: String,
+    cli_value_name: String,
+    driver: Box<dyn db::api::Driver>,
+}
+
+struct BackendState {
+    backends: HashMap<String, Backend>,
+}
+
+fn build_backend(id: &str, driver: Box<dyn db::api::Driver>) -> Backend {
+    let value_str = format!("{}-DB-PATH", id);
+    let help_str = format!("Zeroconf; ignore server config, and create single database 'db' using backend {} with param {}", id, value_str);
+    Backend {
+        cli_help: help_str,
+        cli_value_name: value_str,
+        driver,
+    }
+}
+
+// backend config for db_cfg, whose key_case mode is key_case, publishing
+// changes to watch.  an error if its encryption key cannot be read
+fn db_open_config(
+    db_cfg: &DbConfig,
+    key_case: db::api::KeyCase,
+    watch: &Arc<WatchHub>,
+    audit: Option<&Arc<AuditLog>>,
+) -> Result<db::api::Config, db::Error> {
+    let mut db_builder = db::api::ConfigBuilder::new();
+    db_builder
+        .path(db_cfg.path.clone())
+        .read_only(db_cfg.read_only)
+        .replica(db_cfg.replica)
+        .key_case(key_case);
+    if let Some(ms) = db_cfg.slow_op_threshold_ms {
+        db_builder.slow_op_threshold_ms(ms);
+    }
+    if let Some(ms) = db_cfg.op_timeout_ms {
+        db_builder.op_timeout_ms(ms);
+    }
+    if let Some(secs) = db_cfg.default_ttl_secs {
+        db_builder.default_ttl_secs(secs);
+    }
+    if let Some(n) = db_cfg.change_log_len {
+        db_builder.change_log_len(n);
+    }
+    if let Some(n) = db_cfg.max_keys {
+        db_builder.max_keys(n);
+    }
+    if let Some(n) = db_cfg.max_total_bytes {
+        db_builder.max_total_bytes(n);
+    }
+    if let Some(policy) = db_cfg.eviction_mode() {
+        db_builder.eviction(policy);
+    }
+    if let Some(n) = db_cfg.write_failure_threshold {
+        db_builder.write_failure_threshold(n);
+    }
+    db_builder.value_metadata(db_cfg.value_metadata);
+    db_builder.compress_values(db_cfg.compress_values);
+    if let Some(key) = db_cfg.encryption_key().map_err(db::Error::InvalidInput)? {
+        db_builder.encryption_key(key);
+    }
+    db_builder.verify_checksums(db_cfg.verify_checksums);
+    db_builder.track_modified(db_cfg.track_modified);
+    db_builder.track_versions(db_cfg.track_versions);
+    db_builder.max_iter_keys(db_cfg.max_iter_keys);
+    db_builder.sync_writes(db_cfg.sync_writes);
+    db_builder.max_key_len(db_cfg.max_key_len);
+    db_builder.max_value_len(db_cfg.max_value_len);
+    if let Some(merge_fn) = db_cfg.merge_op().and_then(|op| op.merge_fn()) {
+        db_builder.merge_fn(merge_fn);
+    }
+    db_builder.watch(Arc::clone(watch));
+    if let Some(log) = audit {
+        db_builder.audit(log.sink(&db_cfg.name));
+    }
+    Ok(db_builder.build())
+}
+
+fn register_backends() -> BackendState {
+    let mut bs = BackendState {
+        backends: HashMap::new(),
+    };
+
+    // every driver compiled in
+    for id in db::DRIVERS {
+        if let Ok(driver) = db::driver_for(id) {
+            bs.backends
+                .insert(String::from(*id), build_backend(id, driver));
+        }
+    }
+
+    return bs;
+}
+
+// helper function, 404 not found
+fn err_not_found() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::NOT_FOUND)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -404,
+              "message": "not found"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 400 bad request
+fn err_bad_req() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::BAD_REQUEST)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -400,
+              "message": "invalid/malformed request"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 400 bad request, describing the invalid parameter
+fn err_bad_param(msg: &str) -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::BAD_REQUEST)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -400,
+              "message": msg}})
+            .to_string(),
+        ))
+}
+
+// helper function, 403 forbidden
+fn err_forbidden() -> Result<HttpResponse> {
+    Ok(resp_forbidden())
+}
+
+fn resp_unauthorized() -> HttpResponse {
+    HttpResponse::build(StatusCode::UNAUTHORIZED)
+        .content_type("application/json")
+        .header(header::WWW_AUTHENTICATE, "Bearer")
+        .body(
+            json!({
+          "error": {
+             "code" : -401,
+              "message": "unauthorized"}})
+            .to_string(),
+        )
+}
+
+fn resp_forbidden() -> HttpResponse {
+    HttpResponse::build(StatusCode::FORBIDDEN)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -403,
+              "message": "forbidden"}})
+            .to_string(),
+        )
+}
+
+// helper function, 500 server error
+fn err_conflict() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::CONFLICT)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -409,
+              "message": "conflict"}})
+            .to_string(),
+        ))
+}
+
+fn err_precondition() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::PRECONDITION_FAILED)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -412,
+              "message": "precondition failed"}})
+            .to_string(),
+        ))
+}
+
+fn err_range(val_len: usize) -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::RANGE_NOT_SATISFIABLE)
+        .content_type("application/json")
+        .header(header::CONTENT_RANGE, format!("bytes */{val_len}"))
+        .body(
+            json!({
+          "error": {
+             "code" : -416,
+              "message": "range not satisfiable"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 507 quota exceeded
+fn err_quota() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::INSUFFICIENT_STORAGE)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -507,
+              "message": "quota exceeded"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 413 key or value past the db's length limits
+fn err_too_large() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::PAYLOAD_TOO_LARGE)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -413,
+              "message": "key or value too large"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 501 key expiry not supported by db
+fn err_expiry_unsupported() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::NOT_IMPLEMENTED)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -501,
+              "message": "key expiry not supported"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 501 value metadata not enabled for db
+fn err_meta_unsupported() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::NOT_IMPLEMENTED)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -501,
+              "message": "value metadata not enabled"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 501 hot key tracking not enabled for db
+fn err_hot_keys_unsupported() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::NOT_IMPLEMENTED)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -501,
+              "message": "hot key tracking not enabled"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 501 key versions not enabled for db
+fn err_version_unsupported() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::NOT_IMPLEMENTED)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -501,
+              "message": "key versions not enabled"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 501 feature not enabled for db
+fn err_not_enabled(feature: &str) -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::NOT_IMPLEMENTED)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -501,
+              "message": format!("{feature} not enabled")}})
+            .to_string(),
+        ))
+}
+
+// helper function, 503 db degraded to read-only by write failures
+fn err_degraded() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -503,
+              "message": "db degraded to read-only"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 503 db op not complete within op_timeout_ms
+fn err_timeout() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -503,
+              "message": "db op timed out"}})
+            .to_string(),
+        ))
+}
+
+// helper function, 503 write refused while the server drains
+// error response for a client over rate_limit_rps, which may retry after
+// retry_after
+fn resp_too_many_requests(retry_after: Duration) -> HttpResponse {
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    HttpResponse::build(StatusCode::TOO_MANY_REQUESTS)
+        .content_type("application/json")
+        .header(header::RETRY_AFTER, secs.max(1).to_string())
+        .body(
+            json!({
+          "error": {
+             "code" : -429,
+              "message": "too many requests"}})
+            .to_string(),
+        )
+}
+
+fn resp_draining() -> HttpResponse {
+    HttpResponse::build(StatusCode::SERVICE_UNAVAILABLE)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -503,
+              "message": "server draining"}})
+            .to_string(),
+        )
+}
+
+// helper function, error response for a failed db operation
+fn err_db(e: db::Error) -> Result<HttpResponse> {
+    match e {
+        db::Error::QuotaExceeded => err_quota(),
+        db::Error::TooLarge => err_too_large(),
+        db::Error::ExpiryUnsupported => err_expiry_unsupported(),
+        db::Error::DuplicateKey
+        | db::Error::MetaTooLarge
+        | db::Error::InvalidKey
+        | db::Error::InvalidIndex
+        | db::Error::InvalidInput(_) => err_bad_req(),
+        db::Error::MetaUnsupported => err_meta_unsupported(),
+        db::Error::VersionUnsupported => err_version_unsupported(),
+        db::Error::NotEnabled(feature) => err_not_enabled(&feature),
+        db::Error::Degraded => err_degraded(),
+        db::Error::Timeout => err_timeout(),
+        db::Error::GuardMismatch | db::Error::HashConflict | db::Error::IndexExists => {
+            err_conflict()
+        }
+        db::Error::ReadOnly => err_forbidden(),
+        db::Error::NotFound => err_not_found(),
+        _ => err_500(),
+    }
+}
+
+fn err_500() -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
+        .content_type("application/json")
+        .body(
+            json!({
+          "error": {
+             "code" : -500,
+              "message": "internal server error"}})
+            .to_string(),
+        ))
+}
+
+fn pbenc_db_stat_resp(n_records: u64) -> Vec<u8> {
+    let mut out_msg = DbStatResponse::new();
+    out_msg.magic = DbStatResponse_MagicNum::MAGIC;
+    out_msg.set_n_records(n_records);
+
+    return out_msg.write_to_bytes().unwrap();
+}
+
+fn pbenc_keys_resp(key_list: &db::api::KeyList) -> Vec<u8> {
+    let mut out_msg = KeyResponse::new();
+    out_msg.magic = KeyResponse_MagicNum::MAGIC;
+
+    for key in &key_list.keys {
+        out_msg.keys.push(key.clone());
+    }
+    out_msg.set_list_end(key_list.list_end);
+
+    return out_msg.write_to_bytes().unwrap();
+}
+
+fn pbenc_entries_resp(entry_list: &db::api::EntryList) -> Vec<u8> {
+    let mut out_msg = EntryResponse::new();
+    out_msg.magic = EntryResponse_MagicNum::MAGIC;
+
+    for (key, val) in &entry_list.entries {
+        let mut entry = EntryResponse_Entry::new();
+        entry.set_key(key.clone());
+        entry.set_value(val.clone());
+        out_msg.entries.push(entry);
+    }
+    out_msg.set_list_end(entry_list.list_end);
+    out_msg.write_to_bytes().unwrap()
+}
+
+fn pbdec_iter_req(wiredata: &[u8]) -> ProtobufResult<IterRequest> {
+    match parse_from_bytes::<IterRequest>(wiredata) {
+        Err(e) => Err(e),
+        Ok(req) => {
+            if req.magic != IterRequest_MagicNum::MAGIC {
+                let wire_err = protobuf::error::WireError::Other;
+                let err = ProtobufError::WireError(wire_err);
+                Err(err)
+            } else {
+                Ok(req)
+            }
+        }
+    }
+}
+
+fn pbdec_key_req(wiredata: &[u8]) -> ProtobufResult<KeyRequest> {
+    match parse_from_bytes::<KeyRequest>(wiredata) {
+        Err(e) => Err(e),
+        Ok(req) => {
+            if req.magic != KeyRequest_MagicNum::MAGIC {
+                let wire_err = protobuf::error::WireError::Other;
+                let err = ProtobufError::WireError(wire_err);
+                Err(err)
+            } else {
+                Ok(req)
+            }
+        }
+    }
+}
+
+fn pbdec_update_req(wiredata: &[u8]) -> ProtobufResult<UpdateRequest> {
+    match parse_from_bytes::<UpdateRequest>(wiredata) {
+        Err(e) => Err(e),
+        Ok(req) => {
+            if req.magic != UpdateRequest_MagicNum::MAGIC {
+                let wire_err = protobuf::error::WireError::Other;
+                let err = ProtobufError::WireError(wire_err);
+                Err(err)
+            } else {
+                Ok(req)
+            }
+        }
+    }
+}
+
+fn pbdec_batch_req(wiredata: &[u8]) -> ProtobufResult<BatchRequest> {
+    match parse_from_bytes::<BatchRequest>(wiredata) {
+        Err(e) => Err(e),
+        Ok(req) => {
+            if req.magic != BatchRequest_MagicNum::MAGIC {
+                let wire_err = protobuf::error::WireError::Other;
+                let err = ProtobufError::WireError(wire_err);
+                Err(err)
+            } else {
+                Ok(req)
+            }
+        }
+    }
+}
+
+// decode protobuf BatchRequest, and build batch from its updates
+fn pbdec_batch(wiredata: &[u8]) -> Option<db::api::Batch> {
+    let in_msg = pbdec_batch_req(wiredata).ok()?;
+
+    let mut batch = db::api::Batch::default();
+    for update in in_msg.get_reqs() {
+        if update.magic != UpdateRequest_MagicNum::MAGIC {
+            return None;
+        }
+        if update.is_insert {
+            batch.insert(&update.key, &update.value);
+        } else {
+            batch.remove(&update.key);
+        }
+    }
+    Some(batch)
+}
+
+// helper function, success + binary response
+fn ok_binary(val: Vec<u8>) -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::OK)
+        .content_type("application/octet-stream")
+        .body(val))
+}
+
+// helper function, success + binary value response.  a value longer
+// than VALUE_STREAM_CHUNK is sent a chunk at a time, from the value as
+// read, rather than copied whole into the response
+fn ok_value_body(val: db::api::ValueRef) -> Result<HttpResponse> {
+    if val.len() <= VALUE_STREAM_CHUNK {
+        return ok_binary(val.to_vec());
+    }
+    Ok(HttpResponse::build(StatusCode::OK)
+        .content_type("application/octet-stream")
+        .body(SizedStream::new(
+            val.len() as u64,
+            ValueChunks { val, pos: 0 },
+        )))
+}
+
+// streamed value response body, VALUE_STREAM_CHUNK bytes at a time
+struct ValueChunks {
+    val: db::api::ValueRef,
+    pos: usize, // bytes sent
+}
+
+impl Stream for ValueChunks {
+    type Item = web::Bytes;
+    type Error = actix_web::Error;
+
+    fn poll(&mut self) -> Poll<Option<web::Bytes>, actix_web::Error> {
+        if self.pos >= self.val.len() {
+            return Ok(Async::Ready(None));
+        }
+        let end = self.val.len().min(self.pos + VALUE_STREAM_CHUNK);
+        let chunk = web::Bytes::from(&self.val[self.pos..end]);
+        self.pos = end;
+        Ok(Async::Ready(Some(chunk)))
+    }
+}
+
+// helper function, success + slice [start, end] of binary response
+fn ok_partial(val: &[u8], start: usize, end: usize) -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::PARTIAL_CONTENT)
+        .content_type("application/octet-stream")
+        .header(
+            header::CONTENT_RANGE,
+            format!("bytes {}-{}/{}", start, end, val.len()),
+        )
+        .body(val[start..=end].to_vec()))
+}
+
+// entity tag of a stored value: FNV-1a hash of its contents
+fn value_etag(val: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in val {
+        hash ^= u64::from(*b);
+        hash = hash.wrapping_mul(0x0100_0000_01b3);
+    }
+    format!("\"{hash:016x}\"")
+}
+
+// entity tags listed by a write's If-Match header, if present.  Err if
+// the header is unreadable
+fn if_match_tags(req: &HttpRequest) -> std::result::Result<Option<Vec<String>>, &'static str> {
+    match req.headers().get(header::IF_MATCH) {
+        None => Ok(None),
+        Some(hdr) => match hdr.to_str() {
+            Ok(tags) => Ok(Some(
+                tags.split(',').map(|t| t.trim().to_string()).collect(),
+            )),
+            Err(_e) => Err("invalid If-Match"),
+        },
+    }
+}
+
+// true if a write's If-None-Match header is "*", making it create-only.
+// Err if the header is unreadable, or lists entity tags, which a write
+// does not support
+fn if_none_match_any(req: &HttpRequest) -> std::result::Result<bool, &'static str> {
+    match req.headers().get(header::IF_NONE_MATCH) {
+        None => Ok(false),
+        Some(hdr) => match hdr.to_str() {
+            Ok(tags) if tags.trim() == "*" => Ok(true),
+            _ => Err("invalid If-None-Match"),
+        },
+    }
+}
+
+// true if If-Match tags admit current, a key's value (None:  absent):
+// "*" admits any present value, and a tag, the value with that ETag
+fn if_match_admits(tags: &[String], current: Option<&[u8]>) -> bool {
+    match current {
+        None => false,
+        Some(val) => {
+            let etag = value_etag(val);
+            tags.iter().any(|t| t == "*" || *t == etag)
+        }
+    }
+}
+
+// If-Modified-Since header, in seconds since the Unix epoch; None if
+// absent, or not a valid HTTP date, when it is ignored
+fn if_modified_since(req: &HttpRequest) -> Option<u64> {
+    let hdr = req.headers().get(header::IF_MODIFIED_SINCE)?;
+    let date: header::HttpDate = hdr.to_str().ok()?.parse().ok()?;
+    let since = SystemTime::from(date).duration_since(UNIX_EPOCH).ok()?;
+    Some(since.as_secs())
+}
+
+// helper function, success + stored value, honoring conditional and
+// range request headers, with headers replayed from the value's metadata
+fn ok_value(
+    req: &HttpRequest,
+    val: db::api::ValueRef,
+    meta: &[(String, String)],
+    cache_control: Option<&String>,
+    modified: Option<u64>,
+) -> Result<HttpResponse> {
+    let etag = value_etag(&val);
+
+    // HTTP dates are of whole seconds; If-None-Match, if given, overrides
+    // If-Modified-Since
+    let modified_secs = modified.map(|unix_ms| unix_ms / 1000);
+    let not_modified = match req.headers().get(header::IF_NONE_MATCH) {
+        None => match (modified_secs, if_modified_since(req)) {
+            (Some(secs), Some(since)) => secs <= since,
+            _ => false,
+        },
+        Some(hdr) => match hdr.to_str() {
+            Ok(tags) => tags.split(',').any(|t| t.trim() == etag || t.trim() == "*"),
+            Err(_e) => false,
+        },
+    };
+
+    let mut resp = if not_modified {
+        HttpResponse::build(StatusCode::NOT_MODIFIED).finish()
+    } else {
+        match req.headers().get(header::RANGE) {
+            None => ok_value_body(val)?,
+            Some(range_hdr) => {
+                match parse_byte_range(range_hdr.to_str().unwrap_or(""), val.len()) {
+                    ByteRange::Full => ok_value_body(val)?,
+                    ByteRange::Partial(start, end) => ok_partial(&val, start, end)?,
+                    ByteRange::Unsatisfiable => return err_range(val.len()),
+                }
+            }
+        }
+    };
+
+    let headers = resp.headers_mut();
+    headers.insert(header::ETAG, header::HeaderValue::from_str(&etag).unwrap());
+    if let Some(secs) = modified_secs {
+        let date = header::HttpDate::from(UNIX_EPOCH + Duration::from_secs(secs));
+        if let Ok(date_val) = header::HeaderValue::from_str(&date.to_string()) {
+            headers.insert(header::LAST_MODIFIED, date_val);
+        }
+    }
+    if let Some(cc) = cache_control {
+        if let Ok(cc_val) = header::HeaderValue::from_str(cc) {
+            headers.insert(header::CACHE_CONTROL, cc_val);
+        }
+    }
+    for (name, val) in meta {
+        if !META_HEADERS.contains(&name.as_str()) {
+            continue;
+        }
+        if let (Ok(hdr_name), Ok(hdr_val)) = (
+            header::HeaderName::from_bytes(name.as_bytes()),
+            header::HeaderValue::from_str(val),
+        ) {
+            headers.insert(hdr_name, hdr_val);
+        }
+    }
+
+    Ok(resp)
+}
+
+// helper function, success + json response
+fn ok_json(jval: serde_json::Value) -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::OK)
+        .content_type("application/json")
+        .body(jval.to_string()))
+}
+
+// helper function, success + MessagePack response, fields by name
+fn ok_msgpack<T: Serialize>(val: &T) -> Result<HttpResponse> {
+    match rmp_serde::to_vec_named(val) {
+        Ok(body) => Ok(HttpResponse::build(StatusCode::OK)
+            .content_type("application/msgpack")
+            .body(body)),
+        Err(_e) => err_500(),
+    }
+}
+
+// helper function, success + protobuf response
+fn ok_protobuf(body: Vec<u8>) -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::OK)
+        .content_type("application/x-protobuf")
+        .body(body))
+}
+
+// true if the client's Accept header lists media type media_type
+fn accepts(req: &HttpRequest, media_type: &str) -> bool {
+    req.headers()
+        .get(header::ACCEPT)
+        .and_then(|hv| hv.to_str().ok())
+        .is_some_and(|accept| {
+            accept
+                .split(',')
+                .any(|media| media.split(';').next().unwrap_or("").trim() == media_type)
+        })
+}
+
+// true if the client asked for a MessagePack response, rather than JSON
+fn accepts_msgpack(req: &HttpRequest) -> bool {
+    accepts(req, "application/msgpack")
+}
+
+// true if the client asked for a protobuf response, rather than JSON
+fn accepts_protobuf(req: &HttpRequest) -> bool {
+    accepts(req, "application/x-protobuf")
+}
+
+// helper function, 201 Created + JSON response
+fn ok_json_created(jval: serde_json::Value) -> Result<HttpResponse> {
+    Ok(HttpResponse::build(StatusCode::CREATED)
+        .content_type("application/json")
+        .body(jval.to_string()))
+}
+
+// serialized JSON length of val
+fn json_len<T: Serialize>(val: &T) -> usize {
+    serde_json::to_string(val).map(|s| s.len()).unwrap_or(0)
+}
+
+// number of leading records, of the given serialized sizes, that fit
+// in a page of max_bytes.  at least one, so that paging progresses
+fn page_fit(max_bytes: Option<usize>, sizes: impl Iterator<Item = usize>) -> usize {
+    let mut n = 0;
+    let mut total = 0;
+    for size in sizes {
+        total += size + 1; // plus separator
+        if n > 0 && max_bytes.is_some_and(|max| total > max) {
+            break;
+        }
+        n += 1;
+    }
+    n
+}
+
+/// server-wide statistics
+fn req_stats(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // usage of each db with a quota
+    let mut quotas = serde_json::Map::new();
+    for db_state in &state.dbs {
+        if let Some(usage) = db_state.db.quota_usage() {
+            quotas.insert(
+                db_state.cfg.name.clone(),
+                json!({
+                    "keys": usage.n_keys,
+                    "max_keys": usage.max_keys,
+                    "total_bytes": usage.total_bytes,
+                    "max_total_bytes": usage.max_total_bytes,
+                }),
+            );
+        }
+    }
+
+    ok_json(json!({
+        "connections": state.n_connections.load(Ordering::SeqCst),
+        "max_connections": state.max_connections,
+        "max_request_bytes": state.max_request_bytes,
+        "quotas": quotas,
+    }))
+}
+
+/// METRICS in Prometheus text format, if enabled
+fn req_metrics(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    match &state.metrics {
+        None => err_not_found(),
+        Some(metrics) => Ok(HttpResponse::build(StatusCode::OK)
+            .content_type("text/plain; version=0.0.4")
+            .body(metrics.render())),
+    }
+}
+
+/// READY check, for orchestrators:  503 while the server drains, or any
+/// db is degraded to read-only by write failures
+fn req_ready(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    let degraded: Vec<&str> = state
+        .dbs
+        .iter()
+        .filter(|db_state| db_state.db.degraded())
+        .map(|db_state| db_state.cfg.name.as_str())
+        .collect();
+    let draining = state.drain.load(Ordering::SeqCst) != DRAIN_NONE;
+    let ready = degraded.is_empty() && !draining;
+    let status = match ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    Ok(HttpResponse::build(status)
+        .content_type("application/json")
+        .body(json!({"ready": ready, "draining": draining, "degraded": degraded}).to_string()))
+}
+
+// reserved key, looked up (never stored) by HEALTH readiness probes
+const HEALTH_PROBE_KEY: &[u8] = b"\x00kvdbd:health";
+
+/// HEALTH liveness check:  200 while the server is serving requests
+fn req_health(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/json")
+        .body(json!({"live": true}).to_string()))
+}
+
+/// HEALTH readiness check:  503 unless each db answers a lookup of a
+/// reserved key, catching a wedged or failed backend
+fn req_health_ready(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    let failing: Vec<&str> = state
+        .dbs
+        .iter()
+        .filter(|db_state| db_state.db.get(HEALTH_PROBE_KEY).is_err())
+        .map(|db_state| db_state.cfg.name.as_str())
+        .collect();
+    let ready = failing.is_empty();
+    let status = match ready {
+        true => StatusCode::OK,
+        false => StatusCode::SERVICE_UNAVAILABLE,
+    };
+    Ok(HttpResponse::build(status)
+        .content_type("application/json")
+        .body(json!({"ready": ready, "failing": failing}).to_string()))
+}
+
+/// DRAIN the server:  READY returns 503, so that orchestrators route new
+/// clients elsewhere, while requests continue to be served; if
+/// reject_writes, writes are refused with 503.  Admin-only.
+fn req_drain(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    query: web::Query<DrainQuery>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    match req.peer_addr() {
+        Some(peer) if peer.ip().is_loopback() => {}
+        _ => return err_forbidden(),
+    }
+
+    let reject_writes = query.reject_writes.as_deref().is_some_and(is_set);
+    let new_drain = match reject_writes {
+        true => DRAIN_WRITES,
+        false => DRAIN_READY,
+    };
+    state.drain.store(new_drain, Ordering::SeqCst);
+    ok_json(json!({"draining": true, "reject_writes": reject_writes}))
+}
+
+/// INFLIGHT:  list the db requests being served, oldest first, e.g. to
+/// find a stuck scan or slow write.  Admin-only.
+fn req_inflight(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    match req.peer_addr() {
+        Some(peer) if peer.ip().is_loopback() => {}
+        _ => return err_forbidden(),
+    }
+
+    let now = Instant::now();
+    let mut listed: Vec<(Duration, serde_json::Value)> = match state.inflight.ops.lock() {
+        Err(_e) => return Err(DbError(db::api::ERR_LOCK_POISONED).into()),
+        Ok(ops) => ops
+            .values()
+            .map(|op| {
+                let elapsed = now.duration_since(op.started);
+                (
+                    elapsed,
+                    json!({
+                        "op": op.op,
+                        "db": op.db,
+                        "method": op.method.as_str(),
+                        "key_len": op.key_len,
+                        "elapsed_ms": elapsed.as_millis() as u64,
+                    }),
+                )
+            })
+            .collect(),
+    };
+    listed.sort_by(|a, b| b.0.cmp(&a.0));
+
+    let inflight: Vec<serde_json::Value> = listed.into_iter().map(|(_elapsed, jv)| jv).collect();
+    ok_json(json!({
+        "inflight": inflight,
+        "untracked": state.inflight.n_untracked.load(Ordering::Relaxed),
+    }))
+}
+
+/// UNDRAIN the server, ending DRAIN:  READY and writes resume.  Admin-only.
+fn req_undrain(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    match req.peer_addr() {
+        Some(peer) if peer.ip().is_loopback() => {}
+        _ => return err_forbidden(),
+    }
+
+    state.drain.store(DRAIN_NONE, Ordering::SeqCst);
+    ok_json(json!({"draining": false}))
+}
+
+/// RELOAD configuration file, applying live settings.  Admin-only:
+/// accepted from loopback peers only.
+fn req_reload(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    let cfg_path = {
+        // lock runtime-live state data
+        let state = lock_state(&m_state)?;
+        if state.debug {
+            println!("{:?}", DebugRequest(&req));
+        }
+
+        match req.peer_addr() {
+            Some(peer) if peer.ip().is_loopback() => {}
+            _ => return err_forbidden(),
+        }
+        match &state.cfg_path {
+            None => return err_bad_req(), // zeroconf: nothing to reload
+            Some(cfg_path) => cfg_path.clone(),
+        }
+    };
+
+    // read file without holding the lock
+    let new_cfg = match ServerConfig::from_file(&cfg_path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            return Ok(HttpResponse::build(StatusCode::UNPROCESSABLE_ENTITY)
+                .content_type("application/json")
+                .body(
+                    json!({
+                  "error": {
+                     "code" : -422,
+                      "message": format!("config: {}", e)}})
+                    .to_string(),
+                ));
+        }
+    };
+
+    // all or nothing:  any restart setting changed rejects the reload
+    let mut state = lock_state(&m_state)?;
+    let changes = state.cfg.changes(&new_cfg);
+    if !changes.restart.is_empty() {
+        return Ok(HttpResponse::build(StatusCode::CONFLICT)
+            .content_type("application/json")
+            .body(
+                json!({
+                    "reloaded": false,
+                    "applied": [],
+                    "not_applied": changes.live,
+                    "restart_required": changes.restart,
+                })
+                .to_string(),
+            ));
+    }
+
+    state.apply_config(&new_cfg);
+    ok_json(json!({
+        "reloaded": true,
+        "applied": changes.live,
+        "not_applied": [],
+        "restart_required": [],
+    }))
+}
+
+/// REOPEN replica dbs, serving the current contents of their paths,
+/// e.g. after a filesystem snapshot is refreshed.  All or nothing:  if
+/// any fails to open, every db continues serving as before.  Open scans
+/// and transactions on a reopened db are closed.  Admin-only.
+fn req_reopen(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    query: web::Query<ReopenQuery>,
+) -> Result<HttpResponse> {
+    let targets: Vec<(usize, DbConfig, Arc<WatchHub>)> = {
+        // lock runtime-live state data
+        let state = lock_state(&m_state)?;
+        if state.debug {
+            println!("{:?}", DebugRequest(&req));
+        }
+
+        match req.peer_addr() {
+            Some(peer) if peer.ip().is_loopback() => {}
+            _ => return err_forbidden(),
+        }
+        match &query.db {
+            None => {}
+            Some(name) => match state.name_idx.get(name) {
+                None => return err_not_found(),
+                Some(idx) if !state.dbs[*idx].cfg.replica => return err_bad_req(),
+                Some(_idx) => {}
+            },
+        }
+        state
+            .dbs
+            .iter()
+            .enumerate()
+            .filter(|(_idx, db_state)| db_state.cfg.replica)
+            .filter(|(_idx, db_state)| query.db.as_ref().is_none_or(|n| *n == db_state.cfg.name))
+            .map(|(idx, db_state)| (idx, db_state.cfg.clone(), Arc::clone(&db_state.watch)))
+            .collect()
+    };
+
+    // open without holding the lock; a copy may take a while.  watchers
+    // stay subscribed, and see changes made through the new handle
+    let mut opened = Vec::with_capacity(targets.len());
+    for (idx, db_cfg, watch) in targets {
+        let key_case = db_cfg
+            .key_case_mode()
+            .unwrap_or(db::api::KeyCase::CaseSensitive);
+        // replicas are read-only, so have no writes to audit
+        let db_res = db_open_config(&db_cfg, key_case, &watch, None)
+            .and_then(|db_config| db::driver_for(&db_cfg.driver)?.start_db(db_config));
+        match db_res {
+            Ok(db) => opened.push((idx, db_cfg.name, db)),
+            Err(e) => {
+                warn!("reopen of db {} failed: {}", db_cfg.name, e);
+                return Ok(HttpResponse::build(StatusCode::INTERNAL_SERVER_ERROR)
+                    .content_type("application/json")
+                    .body(
+                        json!({
+                      "error": {
+                         "code" : -500,
+                          "message": format!("db {} reopen failed: {}", db_cfg.name, e)}})
+                        .to_string(),
+                    ));
+            }
+        }
+    }
+
+    let mut state = lock_state(&m_state)?;
+    let mut reopened = Vec::with_capacity(opened.len());
+    for (idx, name, db) in opened {
+        // snapshot ids and transactions belong to the old handle
+        state
+            .scans
+            .retain(|_id, scan_state| scan_state.db_idx != idx);
+        state.txns.retain(|_id, txn_state| txn_state.db_idx != idx);
+        let db_state = &mut state.dbs[idx];
+        db_state.db = db;
+        db_state.count_cache.clear();
+        reopened.push(name);
+    }
+    ok_json(json!({ "reopened": reopened }))
+}
+
+/// simple root index handler, describes our service
+fn req_index(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // fill basic server info struct used for output
+    let mut srv_info = ServerInfo {
+        name: String::from(APPNAME),
+        version: String::from(VERSION),
+        api_base: String::new(),
+        http2: false,
+        binary_port: None,
+        runtime_dbs: false,
+        databases: Vec::new(),
+        degraded: Vec::new(),
+    };
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    srv_info.api_base = state.api_base.clone();
+    srv_info.http2 = state.http2;
+    srv_info.binary_port = state.binary_port;
+    srv_info.runtime_dbs = state.cfg.runtime_dbs.is_some();
+
+    // copy each db config into output struct
+    for db_state in &state.dbs {
+        srv_info.databases.push(db_state.cfg.clone());
+        if db_state.db.degraded() {
+            srv_info.degraded.push(db_state.cfg.name.clone());
+        }
+    }
+
+    // serialize structs into json
+    let jv = serde_json::to_value(&srv_info)?;
+
+    // return json output
+    ok_json(jv)
+}
+
+/// CREATE database, at runtime:  200 if already open, and 201 if newly
+/// opened beneath runtime_dbs.dir.  403 unless runtime_dbs is configured
+fn req_db_create(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    if !state.name_idx.contains_key(&path.0) {
+        if state.cfg.runtime_dbs.is_none() {
+            return err_forbidden();
+        }
+        if !RuntimeDbsConfig::valid_name(&path.0) {
+            return err_bad_req();
+        }
+    }
+    match state.open_runtime_db(&path.0) {
+        Ok((_idx, true)) => Ok(HttpResponse::Created()
+            .content_type("application/json")
+            .body(json!({"name": path.0, "created": true}).to_string())),
+        Ok((_idx, false)) => ok_json(json!({"name": path.0, "created": false})),
+        Err(e) => {
+            warn!("create of db {} failed: {}", path.0, e);
+            err_db(e)
+        }
+    }
+}
+
+/// LIST names of open databases, in the order opened
+fn req_db_list(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    let names: Vec<&str> = state
+        .dbs
+        .iter()
+        .map(|db_state| db_state.cfg.name.as_str())
+        .collect();
+    ok_json(json!({ "databases": names }))
+}
+
+/// CLEAR all data items.
+fn req_clear(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to clear all records from db
+    match state.dbs[idx].db.clear() {
+        Ok(_optval) => ok_json(json!({"result": true})),
+        Err(e) => err_db(e),
+    }
+}
+
+// keys listed in an _mget or _exists body, at most max_keys:  a JSON
+// array of base64-encoded keys, or base64-encoded keys one per line.
+// base64 never begins with '[', so the first byte tells them apart
+fn decode_key_list(
+    body: &[u8],
+    max_keys: usize,
+) -> std::result::Result<Vec<Vec<u8>>, &'static str> {
+    let enc_keys: Vec<String> = match body.iter().find(|b| !b.is_ascii_whitespace()) {
+        Some(b'[') => match serde_json::from_slice(body) {
+            Ok(v) => v,
+            Err(_e) => return Err("invalid key list"),
+        },
+        _ => match std::str::from_utf8(body) {
+            Ok(text) => text
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(String::from)
+                .collect(),
+            Err(_e) => return Err("invalid key list"),
+        },
+    };
+    if enc_keys.len() > max_keys {
+        return Err("too many keys");
+    }
+    let mut keys = Vec::with_capacity(enc_keys.len());
+    for enc_key in &enc_keys {
+        match base64::decode(enc_key) {
+            Ok(key) => keys.push(key),
+            Err(_e) => return Err("invalid key list"),
+        }
+    }
+    Ok(keys)
+}
+
+/// GET many data items. JSON array, or lines, of base64 keys in HTTP
+/// payload, returns JSON array of base64 values, or null for keys not
+/// found.
+fn req_mget(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String,)>, web::Bytes),
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    let keys = match decode_key_list(&body, state.dbs[idx].cfg.max_mget_keys) {
+        Err(_e) => return err_bad_req(),
+        Ok(keys) => keys,
+    };
+
+    // attempt to read records from db
+    let consistency = match read_consistency(&req) {
+        Err(_e) => return err_bad_req(),
+        Ok(consistency) => consistency,
+    };
+    match state.dbs[idx].reader(consistency).get_multi(&keys) {
+        Ok(values) => {
+            let enc_values: Vec<Option<String>> = values
+                .iter()
+                .map(|optval| optval.as_ref().map(base64::encode))
+                .collect();
+            ok_json(json!({ "values": enc_values }))
+        }
+        Err(e) => err_db(e),
+    }
+}
+
+/// EXISTS: test presence of many keys, without reading their values.
+/// JSON array of base64-encoded keys in body.
+fn req_exists(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String,)>, web::Bytes),
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    // keys as for _mget, capped likewise
+    let keys = match decode_key_list(&body, state.dbs[idx].cfg.max_mget_keys) {
+        Err(_e) => return err_bad_req(),
+        Ok(keys) => keys,
+    };
+    let keys: Vec<&[u8]> = keys.iter().map(|key| key.as_slice()).collect();
+
+    match state.dbs[idx].db.exists_multi(&keys) {
+        Ok(found) => ok_json(json!({ "exists": found })),
+        Err(e) => err_db(e),
+    }
+}
+
+/// MDEL: remove many keys in one atomic batch.  Keys listed as for _mget;
+/// returns the number of keys found and removed.
+fn req_mdel(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String,)>, web::Bytes),
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    // keys as for _mget, capped likewise
+    let keys = match decode_key_list(&body, state.dbs[idx].cfg.max_mget_keys) {
+        Err(_e) => return err_bad_req(),
+        Ok(keys) => keys,
+    };
+
+    // absent keys are not an error, and count for nothing
+    match state.dbs[idx].db.del_many(&keys) {
+        Ok(n_deleted) => ok_json(json!({ "deleted": n_deleted })),
+        Err(e) => err_db(e),
+    }
+}
+
+/// INDEX-CREATE - register a secondary index over a field of JSON values
+fn req_index_create(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String,)>, web::Bytes),
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    let index: IndexJson = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(_e) => return err_bad_req(),
+    };
+
+    // indexes existing records before returning
+    match state.dbs[idx]
+        .db
+        .create_index(&index.name, &index.json_path)
+    {
+        Ok(created) => ok_json(json!({ "created": created })),
+        Err(e) => err_db(e),
+    }
+}
+
+/// INDEX-LOOKUP - keys of records whose indexed field equals a value
+fn req_index_lookup(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<IndexQuery>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    match state.dbs[idx].db.index_lookup(&path.1, &query.eq) {
+        Ok(None) => err_not_found(),
+        Ok(Some(keys)) => {
+            let keys: Vec<String> = keys.iter().map(base64::encode).collect();
+            ok_json(json!({ "keys": keys }))
+        }
+        Err(e) => err_db(e),
+    }
+}
+
+// batch of mutations, as decoded from JSON or MessagePack
+fn batch_of(ops: MutationList) -> db::api::Batch {
+    db::api::Batch {
+        ops: ops.0,
+        ..Default::default()
+    }
+}
+
+/// BATCH-UPDATE many databases.  JSON map of db name to batch mutations
+/// in HTTP payload.  Each db's batch is applied atomically within that
+/// db, but not across dbs; returns the outcome for each db.
+fn req_multibatch(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse> {
+    // decode JSON map of db name to batch, ordered by name
+    let enc_batches: BTreeMap<String, MutationList> = match serde_json::from_slice(&body) {
+        Ok(v) => v,
+        Err(e) => return err_bad_param(&e.to_string()),
+    };
+    let batches: Vec<(String, db::api::Batch)> = enc_batches
+        .into_iter()
+        .map(|(name, ops)| (name, batch_of(ops)))
+        .collect();
+
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // reject unknown db names, and dbs refusing batches, before applying
+    // any batch
+    let mut targets = Vec::with_capacity(batches.len());
+    for (name, batch) in batches {
+        match state.name_idx.get(&name) {
+            None => return err_not_found(),
+            Some(idx) if state.dbs[*idx].op_disabled(OpKind::Batch) => return err_forbidden(),
+            Some(idx) => targets.push((name, *idx, batch)),
+        }
+    }
+
+    // apply each batch, continuing past failures
+    let mut all_ok = true;
+    let mut results = serde_json::Map::new();
+    for (name, idx, batch) in targets {
+        let res = match state.dbs[idx].db.apply_batch(&batch) {
+            Ok(_optval) => json!({"result": true}),
+            Err(e) => {
+                all_ok = false;
+                json!({"result": false, "error": e.to_string()})
+            }
+        };
+        results.insert(name, res);
+    }
+
+    let status = if all_ok {
+        StatusCode::OK
+    } else {
+        StatusCode::MULTI_STATUS
+    };
+    Ok(HttpResponse::build(status)
+        .content_type("application/json")
+        .body(json!({"result": all_ok, "dbs": results}).to_string()))
+}
+
+/// List changes since a sequence number, for incremental sync
+fn req_changes(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<ChangesQuery>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    // change log not enabled for this db
+    if state.dbs[idx].cfg.change_log_len.is_none() {
+        return err_not_found();
+    }
+
+    match state.dbs[idx].db.changes_since(query.since.unwrap_or(0)) {
+        Ok(change_list) => {
+            let changes: Vec<serde_json::Value> = change_list
+                .changes
+                .iter()
+                .map(|change| {
+                    let op = match change.op {
+                        db::api::MutationOp::Insert => "put",
+                        db::api::MutationOp::Remove => "del",
+                    };
+                    json!({"seq": change.seq, "op": op, "key": base64::encode(&change.key)})
+                })
+                .collect();
+            ok_json(json!({
+                "changes": changes,
+                "last_seq": change_list.last_seq,
+                "list_end": change_list.list_end,
+                "needs_resync": change_list.needs_resync,
+            }))
+        }
+        Err(e) => err_db(e),
+    }
+}
+
+/// Return db stats as protobuf
+fn req_stat(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to list keys, starting at supplied key (or at db-start, if none)
+    let res = state.dbs[idx].db.stat();
+    if res.is_err() {
+        return err_500();
+    }
+    let st = res.unwrap();
+
+    // encode protobuf output to bytes
+    let out_bytes = pbenc_db_stat_resp(st.n_records);
+
+    ok_binary(out_bytes)
+}
+
+/// Return db stats as JSON
+fn req_stat_json(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to list keys, starting at supplied key (or at db-start, if none)
+    let res = state.dbs[idx].db.stat();
+    if res.is_err() {
+        return err_500();
+    }
+    let st = res.unwrap();
+
+    // fill for-JSON-output struct with return data
+    let out_msg = DbStatResponseJson {
+        n_records: st.n_records.to_string(),
+    };
+
+    // serialize structs into json
+    let jv = serde_json::to_value(&out_msg)?;
+
+    // return json output
+    ok_json(jv)
+}
+
+/// FLUSH db's completed writes durably to storage (fsync)
+fn req_flush(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    match state.dbs[idx].db.flush() {
+        Ok(()) => ok_json(json!({"result": true})),
+        Err(e) => err_db(e),
+    }
+}
+
+/// COMPACT db's storage, reclaiming the space of deleted and overwritten
+/// records.  Runs to completion before responding, holding the state
+/// lock, so other requests wait for it.
+fn req_compact(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    match state.dbs[idx].db.compact() {
+        Ok(()) => ok_json(json!({"result": true})),
+        Err(e) => err_db(e),
+    }
+}
+
+/// Return db STATS, the number of keys stored, as JSON
+fn req_db_stats(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<DbStatsQuery>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    // a prefix is counted now, bypassing the count cache of _count
+    let db = &state.dbs[idx].db;
+    match &query.prefix {
+        None => match db.len() {
+            Ok(n_keys) => ok_json(json!({ "name": path.0, "keys": n_keys })),
+            Err(e) => err_db(e),
+        },
+        Some(prefix) => match db.count_prefix(prefix.as_bytes()) {
+            Ok(n_keys) => ok_json(json!({ "name": path.0, "prefix": prefix, "keys": n_keys })),
+            Err(e) => err_db(e),
+        },
+    }
+}
+
+/// Sequential iteration through all KEYS in db. Start-key in HTTP payload.
+fn req_keys(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String,)>, web::Bytes),
+) -> Result<HttpResponse> {
+    // decode protobuf msg containing key, into KeyRequest struct
+    let res = pbdec_iter_req(&body);
+    if res.is_err() {
+        return err_bad_req();
+    }
+    let in_msg = res.unwrap();
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to list keys, starting at supplied key (or at db-start, if none)
+    let mut opts = db::api::IterOptions::new();
+    if !in_msg.start_key.is_empty() {
+        opts.start(&in_msg.start_key);
+    }
+    if !in_msg.prefix.is_empty() {
+        opts.prefix(&in_msg.prefix);
+    }
+    let res = state.dbs[idx].db.iter_keys(opts);
+    if res.is_err() {
+        return err_500();
+    }
+
+    // encode protobuf output to bytes
+    let key_list = res.unwrap();
+    let out_bytes = pbenc_keys_resp(&key_list);
+
+    ok_binary(out_bytes)
+}
+
+/// Sequential iteration through all KEYS in db. Start-key in HTTP payload.
+fn req_keys_json(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse> {
+    // query string continues previous search, its key as given (not
+    // percent-decoded), unless keys are encoded
+    let mut enc_lastkey: Option<&str> = None;
+    let mut key_enc = KeyEnc::Text;
+    for param in req.query_string().split('&').filter(|p| !p.is_empty()) {
+        match param.split_once('=') {
+            Some(("lastkey", val)) => enc_lastkey = Some(val),
+            Some(("keyenc", "base64")) => key_enc = KeyEnc::Base64,
+            _ => return err_bad_req(),
+        }
+    }
+    let lastkey: Option<Vec<u8>> = match enc_lastkey.map(|k| key_enc.decode(k)) {
+        None => None,
+        Some(None) => return err_bad_req(),
+        Some(Some(key)) => Some(key),
+    };
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to list keys, starting at supplied key (or at db-start, if none)
+    let res;
+    if lastkey.is_none() {
+        res = state.dbs[idx].db.iter_keys(db::api::IterOptions::new());
+    } else {
+        let mut opts = db::api::IterOptions::new();
+        opts.start(&lastkey.unwrap());
+        res = state.dbs[idx].db.iter_keys(opts);
+    }
+    if res.is_err() {
+        return err_500();
+    }
+    let key_list = res.unwrap();
+    if accepts_msgpack(&req) {
+        return ok_msgpack(&key_list);
+    }
+    if accepts_protobuf(&req) {
+        return ok_protobuf(pbenc_keys_resp(&key_list));
+    }
+
+    // fill for-JSON-output struct with return data
+    let next_start = match key_list.keys.last() {
+        Some(key) if !key_list.list_end => Some(key_enc.encode(key)),
+        _ => None,
+    };
+    let mut out_msg = KeyResponseJson {
+        keys: Vec::new(),
+        list_end: key_list.list_end,
+        page_size: state.dbs[idx].db.max_iter_keys(),
+        next_start,
+    };
+    for key in key_list.keys {
+        out_msg.keys.push(key_enc.encode(&key));
+    }
+
+    // serialize structs into json
+    let jv = serde_json::to_value(&out_msg)?;
+
+    // return json output
+    ok_json(jv)
+}
+
+/// Page through KEYS in db, as base64, with a cursor for the next page.
+fn req_keys_page(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<KeysQuery>,
+) -> Result<HttpResponse> {
+    if query.after.is_some() && query.cursor.is_some() {
+        return err_bad_param("after and cursor are alternatives");
+    }
+    let after = match query.after.as_ref().or(query.cursor.as_ref()) {
+        None => None,
+        Some(cursor) => match base64::decode(cursor) {
+            Ok(key) => Some(key),
+            Err(_e) => return err_bad_req(),
+        },
+    };
+    let prefix = match &query.prefix {
+        None => None,
+        Some(prefix) => match base64::decode(prefix) {
+            Ok(prefix) => Some(prefix),
+            Err(_e) => return err_bad_req(),
+        },
+    };
+    // clamped to the db's max_iter_keys by its pages
+    if query.limit == Some(0) {
+        return err_bad_param("limit must be at least 1");
+    }
+    let limit = query.limit.unwrap_or(usize::MAX);
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    let mut opts = db::api::IterOptions::new();
+    if let Some(key) = &after {
+        opts.start(key);
+    }
+    if let Some(prefix) = &prefix {
+        opts.prefix(prefix);
+    }
+    if query.reverse.as_deref().is_some_and(is_set) {
+        opts.reverse();
+    }
+    let mut key_list = match state.dbs[idx].db.iter_keys(opts) {
+        Ok(key_list) => key_list,
+        Err(e) => return err_db(e),
+    };
+    let max_page_bytes = state.dbs[idx].cfg.max_page_bytes;
+    let page_size = limit.min(state.dbs[idx].db.max_iter_keys());
+    drop(state);
+
+    // more keys follow this page, if the db said so, or it was cut short
+    // by either limit
+    let mut keys: Vec<String> = key_list.keys.iter().map(base64::encode).collect();
+    let n_fit = page_fit(max_page_bytes, keys.iter().map(json_len));
+    let n_keys = limit.min(n_fit);
+    if keys.len() > n_keys {
+        keys.truncate(n_keys);
+        key_list.keys.truncate(n_keys);
+        key_list.list_end = false;
+    }
+    if accepts_msgpack(&req) {
+        return ok_msgpack(&key_list);
+    }
+    if accepts_protobuf(&req) {
+        return ok_protobuf(pbenc_keys_resp(&key_list));
+    }
+    let next = key_list.next_cursor().map(|key| base64::encode(&key));
+    ok_json(json!({
+        "keys": keys,
+        "list_end": key_list.list_end,
+        "page_size": page_size,
+        "next": next,
+        "next_start": next,
+    }))
+}
+
+// entry_list cut short before the record whose value would take its
+// value bytes past max_bytes, if given; but at least one record, as
+// Db::iter_entries() does
+fn entries_within(
+    mut entry_list: db::api::EntryList,
+    max_bytes: Option<usize>,
+) -> db::api::EntryList {
+    let max_bytes = match max_bytes {
+        None => return entry_list,
+        Some(max_bytes) => max_bytes,
+    };
+    let mut n_bytes = 0;
+    let n_entries = entry_list
+        .entries
+        .iter()
+        .take_while(|(_key, val)| {
+            n_bytes += val.len();
+            n_bytes <= max_bytes
+        })
+        .count()
+        .max(1);
+    if entry_list.entries.len() > n_entries {
+        entry_list.entries.truncate(n_entries);
+        entry_list.list_end = false;
+    }
+    entry_list
+}
+
+/// ENTRIES: list a page of records, keys and values base64-encoded,
+/// bounded by record count and value bytes, optionally within a key
+/// range.  Cursor, limit, byte budget and range in query string.
+fn req_entries(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<EntriesQuery>,
+) -> Result<HttpResponse> {
+    let after = match &query.after {
+        None => None,
+        Some(cursor) => match base64::decode(cursor) {
+            Ok(key) => Some(key),
+            Err(_e) => return err_bad_req(),
+        },
+    };
+    let start = match &query.start {
+        None => None,
+        Some(start) => match base64::decode(start) {
+            Ok(key) => Some(key),
+            Err(_e) => return err_bad_req(),
+        },
+    };
+    let end = match &query.end {
+        None => None,
+        Some(end) => match base64::decode(end) {
+            Ok(key) => Some(key),
+            Err(_e) => return err_bad_req(),
+        },
+    };
+    // clamped to the db's max_iter_keys by its pages
+    if query.limit == Some(0) {
+        return err_bad_param("limit must be at least 1");
+    }
+    let limit = query.limit.unwrap_or(usize::MAX);
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    let res = if start.is_none() && end.is_none() {
+        let mut opts = db::api::IterOptions::new();
+        if let Some(key) = &after {
+            opts.start(key);
+        }
+        state.dbs[idx].db.iter_entries(opts, query.max_bytes)
+    } else {
+        // a range resumes after the cursor:  at the next key in byte
+        // order, the cursor with a zero byte appended
+        let mut start = start.unwrap_or_default();
+        if let Some(key) = after.filter(|key| *key >= start) {
+            start = [key.as_slice(), &[0]].concat();
+        }
+        let end = end.unwrap_or_default();
+        state.dbs[idx]
+            .db
+            .get_range(&start, &end, limit)
+            .map(|entry_list| entries_within(entry_list, query.max_bytes))
+    };
+    let mut entry_list = match res {
+        Ok(entry_list) => entry_list,
+        Err(e) => return err_db(e),
+    };
+    let max_page_bytes = state.dbs[idx].cfg.max_page_bytes;
+    let page_size = limit.min(state.dbs[idx].db.max_iter_keys());
+    drop(state);
+
+    let mut entries: Vec<serde_json::Value> = entry_list
+        .entries
+        .iter()
+        .map(|(key, val)| json!({"k": base64::encode(key), "v": base64::encode(val)}))
+        .collect();
+    let n_fit = page_fit(max_page_bytes, entries.iter().map(json_len));
+    let n_entries = limit.min(n_fit);
+    if entries.len() > n_entries {
+        entries.truncate(n_entries);
+        entry_list.entries.truncate(n_entries);
+        entry_list.list_end = false;
+    }
+    if accepts_msgpack(&req) {
+        return ok_msgpack(&entry_list);
+    }
+    if accepts_protobuf(&req) {
+        return ok_protobuf(pbenc_entries_resp(&entry_list));
+    }
+    let next = match entry_list.entries.last() {
+        Some((key, _val)) if !entry_list.list_end => Some(base64::encode(key)),
+        _ => None,
+    };
+    ok_json(json!({
+        "entries": entries,
+        "list_end": entry_list.list_end,
+        "page_size": page_size,
+        "next": next,
+        "next_start": next,
+    }))
+}
+
+// binary dump framing:  DUMP_MAGIC, then per record a big-endian u32
+// key length, key, u32 value length and value; then DUMP_END, so that a
+// dump cut short is detected
+const DUMP_MAGIC: &[u8] = b"KVDBDUMP\x00\x00\x00\x01";
+const DUMP_END: u32 = u32::MAX;
+
+#[derive(Clone, Copy)]
+enum ExportFormat {
+    JsonLines, // {"k":"<base64>","v":"<base64>"} per line
+    Csv,       // k,v header line, then base64 key,value per line
+    Binary,    // length-prefixed keys and values, per DUMP_MAGIC
+}
+
+impl ExportFormat {
+    fn from_name(name: &str) -> Option<ExportFormat> {
+        match name {
+            "" | "jsonl" => Some(ExportFormat::JsonLines),
+            "csv" => Some(ExportFormat::Csv),
+            "binary" => Some(ExportFormat::Binary),
+            _ => None,
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ExportFormat::JsonLines => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Binary => "application/octet-stream",
+        }
+    }
+
+    // sent before the first record, and after the last
+    fn header(self) -> Vec<u8> {
+        match self {
+            ExportFormat::JsonLines => Vec::new(),
+            ExportFormat::Csv => b"k,v\n".to_vec(),
+            ExportFormat::Binary => DUMP_MAGIC.to_vec(),
+        }
+    }
+
+    fn trailer(self) -> Vec<u8> {
+        match self {
+            ExportFormat::Binary => DUMP_END.to_be_bytes().to_vec(),
+            _ => Vec::new(),
+        }
+    }
+
+    // base64 needs no JSON escaping or CSV quoting
+    fn write_record(self, out: &mut Vec<u8>, key: &[u8], val: &[u8]) {
+        let line = match self {
+            ExportFormat::JsonLines => format!(
+                "{{\"k\":\"{}\",\"v\":\"{}\"}}\n",
+                base64::encode(key),
+                base64::encode(val)
+            ),
+            ExportFormat::Csv => format!("{},{}\n", base64::encode(key), base64::encode(val)),
+            ExportFormat::Binary => {
+                for field in &[key, val] {
+                    out.extend_from_slice(&(field.len() as u32).to_be_bytes());
+                    out.extend_from_slice(field);
+                }
+                return;
+            }
+        };
+        out.extend_from_slice(line.as_bytes());
+    }
+}
+
+// streamed export response body.  the state lock is taken afresh for each
+// page of keys, so an export does not stall other requests, and memory
+// held is bounded by one page of records
+struct ExportStream {
+    m_state: Arc<RwLock<ServerState>>,
+    idx: usize,
+    format: ExportFormat,
+    prefix: Option<Vec<u8>>,
+    after: Option<Vec<u8>>, // last key listed
+    header: Vec<u8>,        // sent before the first record
+    trailer: Vec<u8>,       // sent after the last
+    done: bool,
+}
+
+impl ExportStream {
+    // encode the next page of records; None once all are sent
+    fn next_page(&mut self) -> Result<Option<web::Bytes>, db::Error> {
+        let mut out = mem::take(&mut self.header);
+        while out.is_empty() && !self.done {
+            let state = read_shared_state(&self.m_state)?;
+            let db = &state.dbs[self.idx].db;
+            let mut opts = db::api::IterOptions::new();
+            if let Some(prefix) = &self.prefix {
+                opts.prefix(prefix);
+            }
+            if let Some(key) = &self.after {
+                opts.start(key);
+            }
+            let key_list = db.iter_keys(opts)?;
+            let vals = db.get_multi(&key_list.keys)?;
+            drop(state);
+
+            // a key deleted since listed is skipped
+            for (key, val) in key_list.keys.iter().zip(vals) {
+                if let Some(val) = val {
+                    self.format.write_record(&mut out, key, &val);
+                }
+            }
+            self.done = key_list.list_end || key_list.keys.is_empty();
+            if let Some(key) = key_list.keys.last() {
+                self.after = Some(key.clone());
+            }
+        }
+        if self.done {
+            out.append(&mut self.trailer);
+        }
+
+        if out.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(web::Bytes::from(out)))
+    }
+}
+
+impl Stream for ExportStream {
+    type Item = web::Bytes;
+    type Error = actix_web::Error;
+
+    fn poll(&mut self) -> Poll<Option<web::Bytes>, actix_web::Error> {
+        match self.next_page() {
+            Ok(page) => Ok(Async::Ready(page)),
+            Err(e) => Err(DbError(e).into()),
+        }
+    }
+}
+
+/// EXPORT records, streamed as JSON lines, CSV or binary.  Format and
+/// key prefix in query string.
+fn req_export(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse> {
+    export(&m_state, &req, &path.0, &query, "jsonl")
+}
+
+/// DUMP records, for backup:  EXPORT, in binary format by default.
+fn req_dump(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<ExportQuery>,
+) -> Result<HttpResponse> {
+    export(&m_state, &req, &path.0, &query, "binary")
+}
+
+// stream db_name's records, per query, in def_format if none is given
+fn export(
+    m_state: &Arc<RwLock<ServerState>>,
+    req: &HttpRequest,
+    db_name: &str,
+    query: &ExportQuery,
+    def_format: &str,
+) -> Result<HttpResponse> {
+    let format = match ExportFormat::from_name(query.format.as_deref().unwrap_or(def_format)) {
+        None => return err_bad_req(),
+        Some(format) => format,
+    };
+    let prefix = match &query.prefix {
+        None => None,
+        Some(prefix) => match base64::decode(prefix) {
+            Ok(prefix) => Some(prefix),
+            Err(_e) => return err_bad_req(),
+        },
+    };
+
+    // lock runtime-live state data, for reading
+    let state = read_state(m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(db_name) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(format.content_type())
+        .streaming(ExportStream {
+            m_state: Arc::clone(m_state),
+            idx,
+            format,
+            prefix,
+            after: None,
+            header: format.header(),
+            trailer: format.trailer(),
+            done: false,
+        }))
+}
+
+// wakes the futures task polling a WatchStream, once its watcher has an
+// item ready
+struct TaskWaker(futures::task::Task);
+
+impl std::task::Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.notify();
+    }
+}
+
+// streamed watch response body:  one server-sent event per change, keys
+// per the request's keyenc.  ends once the watcher lags or its hub is
+// closed, and unsubscribes when the client disconnects
+struct WatchStream {
+    watcher: Watcher,
+    key_enc: KeyEnc,
+    header: Vec<u8>, // sent at once, so the client sees the stream open
+    done: bool,
+}
+
+impl Stream for WatchStream {
+    type Item = web::Bytes;
+    type Error = actix_web::Error;
+
+    fn poll(&mut self) -> Poll<Option<web::Bytes>, actix_web::Error> {
+        let mut out = mem::take(&mut self.header);
+        let waker = std::task::Waker::from(Arc::new(TaskWaker(futures::task::current())));
+        while !self.done {
+            let line = match self.watcher.poll_next(&waker) {
+                std::task::Poll::Pending => break,
+                std::task::Poll::Ready(WatchItem::Event(event)) => {
+                    let data = match event.op {
+                        db::watch::WatchOp::Clear => json!({ "op": event.op.name() }),
+                        _ => json!({
+                            "op": event.op.name(),
+                            "key": self.key_enc.encode(&event.key),
+                        }),
+                    };
+                    format!(
+                        "id: {}\nevent: {}\ndata: {}\n\n",
+                        event.seq,
+                        event.op.name(),
+                        data
+                    )
+                }
+                std::task::Poll::Ready(WatchItem::Lagged) => {
+                    self.done = true;
+                    String::from("event: lagged\ndata: {\"op\":\"lagged\"}\n\n")
+                }
+                std::task::Poll::Ready(WatchItem::Closed) => {
+                    self.done = true;
+                    break;
+                }
+            };
+            out.extend_from_slice(line.as_bytes());
+        }
+
+        if !out.is_empty() {
+            return Ok(Async::Ready(Some(web::Bytes::from(out))));
+        }
+        if self.done {
+            return Ok(Async::Ready(None));
+        }
+        Ok(Async::NotReady)
+    }
+}
+
+/// WATCH changes to keys, streamed as server-sent events.  Key prefix in
+/// query string.
+fn req_watch(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<WatchQuery>,
+) -> Result<HttpResponse> {
+    let key_enc = match KeyEnc::of(&req) {
+        None => return err_bad_req(),
+        Some(key_enc) => key_enc,
+    };
+    let prefix = match key_enc.decode(query.prefix.as_deref().unwrap_or_default()) {
+        None => return err_bad_req(),
+        Some(prefix) => prefix,
+    };
+
+    // lock runtime-live state data
+    let state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    // subscribe before responding, so that every change made once the
+    // client has the response headers is sent
+    let watcher = state.dbs[idx].watch.subscribe(&prefix);
+    Ok(HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .streaming(WatchStream {
+            watcher,
+            key_enc,
+            header: b": watching\n\n".to_vec(),
+            done: false,
+        }))
+}
+
+enum ImportError {
+    Payload,       // request body read failed
+    BadRecord,     // malformed line or frame, line too long, or dump cut short
+    Db(db::Error), // batch apply failed
+}
+
+// progress through a binary dump, per DUMP_MAGIC
+#[derive(PartialEq)]
+enum DumpPart {
+    Header,
+    Records,
+    Ended, // trailer read
+}
+
+// streamed import state:  the partial line (or dump frame) carried between
+// body chunks, and decoded records not yet applied.  records are applied
+// in batches of BATCH_CHUNK_OPS, each under its own hold of the state lock
+struct Importer {
+    m_state: Arc<RwLock<ServerState>>,
+    idx: usize,
+    peer: Option<IpAddr>, // client importing, for the audit log
+    line: Vec<u8>,
+    dump: Option<DumpPart>, // reading a binary dump, not JSON lines
+    batch: db::api::Batch,
+    n_put: u64, // records applied
+    n_del: u64,
+}
+
+impl Importer {
+    fn feed(mut self, chunk: &[u8]) -> Result<Importer, ImportError> {
+        if self.dump.is_some() {
+            self.line.extend_from_slice(chunk);
+            self.frames()?;
+            return Ok(self);
+        }
+
+        let mut rest = chunk;
+        while let Some(pos) = rest.iter().position(|b| *b == b'\n') {
+            self.line.extend_from_slice(&rest[..pos]);
+            let line = mem::take(&mut self.line);
+            self.record(&line)?;
+            rest = &rest[pos + 1..];
+        }
+        self.line.extend_from_slice(rest);
+        if self.line.len() > MAX_IMPORT_LINE {
+            return Err(ImportError::BadRecord);
+        }
+        Ok(self)
+    }
+
+    // decode one line into the batch; blank lines are skipped
+    fn record(&mut self, line: &[u8]) -> Result<(), ImportError> {
+        if line.iter().all(u8::is_ascii_whitespace) {
+            return Ok(());
+        }
+        let rec: ImportRecord =
+            serde_json::from_slice(line).map_err(|_e| ImportError::BadRecord)?;
+        let key = base64::decode(&rec.k).map_err(|_e| ImportError::BadRecord)?;
+        match (rec.op.as_deref(), rec.v) {
+            (Some("del"), None) => self.batch.remove(&key),
+            (None, Some(v)) => {
+                let val = base64::decode(&v).map_err(|_e| ImportError::BadRecord)?;
+                self.batch.insert(&key, &val);
+            }
+            _ => return Err(ImportError::BadRecord),
+        }
+
+        if self.batch.ops.len() >= db::api::BATCH_CHUNK_OPS {
+            self.apply()?;
+        }
+        Ok(())
+    }
+
+    // decode the complete dump frames buffered, keeping any partial frame
+    fn frames(&mut self) -> Result<(), ImportError> {
+        let buf = mem::take(&mut self.line);
+        let mut pos = 0;
+        let read_len = |pos: usize| -> Option<usize> {
+            let mut len = [0u8; 4];
+            len.copy_from_slice(buf.get(pos..pos + 4)?);
+            Some(u32::from_be_bytes(len) as usize)
+        };
+        while let Some(part) = &self.dump {
+            match part {
+                DumpPart::Header => match buf.get(pos..pos + DUMP_MAGIC.len()) {
+                    None => break,
+                    Some(magic) if magic != DUMP_MAGIC => return Err(ImportError::BadRecord),
+                    Some(_magic) => {
+                        pos += DUMP_MAGIC.len();
+                        self.dump = Some(DumpPart::Records);
+                    }
+                },
+                DumpPart::Records => {
+                    let key_len = match read_len(pos) {
+                        None => break,
+                        Some(len) if len == DUMP_END as usize => {
+                            pos += 4;
+                            self.dump = Some(DumpPart::Ended);
+                            continue;
+                        }
+                        Some(len) if len > MAX_IMPORT_LINE => return Err(ImportError::BadRecord),
+                        Some(len) => len,
+                    };
+                    let val_pos = pos + 4 + key_len;
+                    let val_len = match read_len(val_pos) {
+                        None => break,
+                        Some(len) if key_len + len > MAX_IMPORT_LINE => {
+                            return Err(ImportError::BadRecord)
+                        }
+                        Some(len) => len,
+                    };
+                    let end = val_pos + 4 + val_len;
+                    if buf.len() < end {
+                        break;
+                    }
+                    self.batch
+                        .insert(&buf[pos + 4..val_pos], &buf[val_pos + 4..end]);
+                    pos = end;
+                    if self.batch.ops.len() >= db::api::BATCH_CHUNK_OPS {
+                        self.apply()?;
+                    }
+                }
+                // nothing may follow the trailer
+                DumpPart::Ended if pos < buf.len() => return Err(ImportError::BadRecord),
+                DumpPart::Ended => break,
+            }
+        }
+        self.line = buf[pos..].to_vec();
+        Ok(())
+    }
+
+    fn apply(&mut self) -> Result<(), ImportError> {
+        if self.batch.ops.is_empty() {
+            return Ok(());
+        }
+        let batch = mem::take(&mut self.batch);
+        let mut state = lock_shared_state(&self.m_state).map_err(ImportError::Db)?;
+        state.audit_as(self.peer);
+        state.dbs[self.idx]
+            .db
+            .apply_batch(&batch)
+            .map_err(ImportError::Db)?;
+        for mutation in &batch.ops {
+            match mutation.op {
+                db::api::MutationOp::Insert => self.n_put += 1,
+                db::api::MutationOp::Remove => self.n_del += 1,
+            }
+        }
+        Ok(())
+    }
+
+    // the final line need not end in a newline; a dump must end in its
+    // trailer, or was cut short
+    fn finish(mut self) -> Result<Importer, ImportError> {
+        match self.dump {
+            Some(DumpPart::Ended) => {}
+            Some(_) => return Err(ImportError::BadRecord),
+            None => {
+                let line = mem::take(&mut self.line);
+                self.record(&line)?;
+            }
+        }
+        self.apply()?;
+        Ok(self)
+    }
+}
+
+/// IMPORT records, streamed as JSON lines, as written by EXPORT.  Returns
+/// JSON counts of records put and deleted.
+fn req_import(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, query, payload): (web::Path<(String,)>, web::Query<ImportQuery>, web::Payload),
+) -> Result<EitherResponse> {
+    import(&m_state, &req, &path.0, &query, payload, None)
+}
+
+/// RESTORE records from a binary dump, as written by DUMP.  Returns
+/// JSON counts of records put and deleted, as IMPORT.
+fn req_restore(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, query, payload): (web::Path<(String,)>, web::Query<ImportQuery>, web::Payload),
+) -> Result<EitherResponse> {
+    import(
+        &m_state,
+        &req,
+        &path.0,
+        &query,
+        payload,
+        Some(DumpPart::Header),
+    )
+}
+
+// apply payload's records to db_name, as JSON lines, or a binary dump
+fn import(
+    m_state: &Arc<RwLock<ServerState>>,
+    req: &HttpRequest,
+    db_name: &str,
+    query: &ImportQuery,
+    payload: web::Payload,
+    dump: Option<DumpPart>,
+) -> Result<EitherResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state_for(m_state, req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(db_name) {
+        None => return err_not_found().map(EitherResponse::A),
+        Some(r_idx) => *r_idx,
+    };
+
+    // refused before the body is read, rather than at its first batch
+    if state.dbs[idx].cfg.read_only {
+        return err_forbidden().map(EitherResponse::A);
+    }
+
+    // replace, rather than merge with, existing records
+    if query.clear.as_deref().is_some_and(is_set) {
+        if state.dbs[idx].op_disabled(OpKind::Clear) {
+            return err_forbidden().map(EitherResponse::A);
+        }
+        if let Err(e) = state.dbs[idx].db.clear() {
+            return err_db(e).map(EitherResponse::A);
+        }
+    }
+    drop(state);
+
+    let importer = Importer {
+        m_state: Arc::clone(m_state),
+        idx,
+        peer: req.peer_addr().map(|peer| peer.ip()),
+        line: Vec::new(),
+        dump,
+        batch: db::api::Batch::default(),
+        n_put: 0,
+        n_del: 0,
+    };
+    let fut = payload
+        .map_err(|_e| ImportError::Payload)
+        .fold(importer, |importer, chunk| importer.feed(&chunk))
+        .and_then(Importer::finish)
+        .then(|res| match res {
+            Ok(importer) => ok_json(json!({"put": importer.n_put, "deleted": importer.n_del})),
+            Err(ImportError::Db(e)) => err_db(e),
+            Err(_e) => err_bad_req(),
+        });
+    Ok(EitherResponse::B(Box::new(fut)))
+}
+
+/// SCAN keys matching a glob pattern.  Pattern and last-key in query string.
+fn req_scan(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<ScanQuery>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    let lastkey = query.lastkey.as_ref().map(|k| k.as_bytes());
+    let key_list = match state.dbs[idx].db.scan_glob(query.glob.as_bytes(), lastkey) {
+        Ok(key_list) => key_list,
+        Err(e) => return err_db(e),
+    };
+
+    let mut out_msg = KeyResponseJson {
+        keys: key_list
+            .keys
+            .iter()
+            .map(|key| String::from_utf8_lossy(key).to_string())
+            .collect(),
+        list_end: key_list.list_end,
+        page_size: state.dbs[idx].db.max_iter_keys(),
+        next_start: None,
+    };
+
+    // cut short by page bytes:  continue from the last key returned
+    let max_page_bytes = state.dbs[idx].cfg.max_page_bytes;
+    let n_keys = page_fit(max_page_bytes, out_msg.keys.iter().map(json_len));
+    if out_msg.keys.len() > n_keys {
+        out_msg.keys.truncate(n_keys);
+        out_msg.list_end = false;
+    }
+    if !out_msg.list_end {
+        out_msg.next_start = out_msg.keys.last().cloned();
+    }
+    ok_json(serde_json::to_value(&out_msg)?)
+}
+
+/// OPEN a snapshot scan, for paging through keys and values as of now.
+/// returns scan id as json response
+fn req_scan_open(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    state.expire_scans();
+    let snap_id = match state.dbs[idx].db.snapshot_open() {
+        Ok(snap_id) => snap_id,
+        Err(e) => return err_db(e),
+    };
+    let id = state.next_scan_id;
+    state.next_scan_id += 1;
+    let expires = Instant::now() + state.scan_timeout;
+    state.scans.insert(
+        id,
+        ScanState {
+            db_idx: idx,
+            snap_id,
+            last_key: None,
+            list_end: false,
+            expires,
+            opened: Instant::now(),
+            n_keys: 0,
+            key_bytes: 0,
+            value_bytes: 0,
+        },
+    );
+
+    ok_json(json!({
+        "scan": id,
+        "timeout_secs": state.scan_timeout.as_secs(),
+    }))
+}
+
+/// NEXT page of a snapshot scan:  keys, and base64-encoded values.
+fn req_scan_next(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, u64)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index and scan (path elems 0, 1)
+    let idx = match state.scan_lookup(&path.0, path.1) {
+        None => return err_not_found(),
+        Some(idx) => idx,
+    };
+    let scan_state = &state.scans[&path.1];
+    if scan_state.list_end {
+        return ok_json(json!({"keys": [], "values": [], "list_end": true}));
+    }
+
+    let db = &state.dbs[idx].db;
+    let snap_id = scan_state.snap_id;
+    let key_list = match db.snapshot_iter_keys(snap_id, scan_state.last_key.as_deref()) {
+        Ok(key_list) => key_list,
+        Err(e) => return err_db(e),
+    };
+    let max_page_bytes = state.dbs[idx].cfg.max_page_bytes;
+    let mut keys = Vec::with_capacity(key_list.keys.len());
+    let mut values = Vec::with_capacity(key_list.keys.len());
+    let (mut key_bytes, mut value_bytes, mut page_bytes) = (0, 0, 0);
+    let mut list_end = key_list.list_end;
+    let mut last_key = None;
+    for key in &key_list.keys {
+        let val = match db.snapshot_get(snap_id, key) {
+            Ok(Some(val)) => val,
+            Ok(None) => continue, // listed, so present in snapshot
+            Err(_e) => return err_500(),
+        };
+        let enc_key = String::from_utf8_lossy(key).to_string();
+        let enc_val = base64::encode(&val);
+
+        // page full:  the next page continues from the last key returned
+        page_bytes += json_len(&enc_key) + json_len(&enc_val) + 2;
+        if !keys.is_empty() && max_page_bytes.is_some_and(|max| page_bytes > max) {
+            list_end = false;
+            break;
+        }
+        key_bytes += key.len() as u64;
+        value_bytes += val.len() as u64;
+        keys.push(enc_key);
+        values.push(enc_val);
+        last_key = Some(key);
+    }
+
+    let scan_state = state.scans.get_mut(&path.1).unwrap();
+    scan_state.n_keys += keys.len() as u64;
+    scan_state.key_bytes += key_bytes;
+    scan_state.value_bytes += value_bytes;
+    scan_state.list_end = list_end;
+    if let Some(last_key) = last_key {
+        scan_state.last_key = Some(last_key.clone());
+    }
+    ok_json(json!({"keys": keys, "values": values, "list_end": list_end}))
+}
+
+/// CLOSE a snapshot scan, releasing its snapshot.  Returns totals of the
+/// records returned by its pages.
+fn req_scan_close(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, u64)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index and scan (path elems 0, 1)
+    let idx = match state.scan_lookup(&path.0, path.1) {
+        None => return err_not_found(),
+        Some(idx) => idx,
+    };
+
+    let scan_state = state.scans.remove(&path.1).unwrap();
+    state.dbs[idx].db.snapshot_close(scan_state.snap_id);
+    ok_json(json!({
+        "result": true,
+        "keys": scan_state.n_keys,
+        "key_bytes": scan_state.key_bytes,
+        "value_bytes": scan_state.value_bytes,
+        "elapsed_ms": scan_state.opened.elapsed().as_millis() as u64,
+    }))
+}
+
+fn percentiles_json(p: &db::histogram::Percentiles) -> serde_json::Value {
+    json!({"p50": p.p50, "p90": p.p90, "p99": p.p99, "max": p.max})
+}
+
+/// HISTOGRAM of key and value lengths, approximated from a sample of the
+/// records read by a bounded scan.
+fn req_histogram(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<HistogramQuery>,
+) -> Result<HttpResponse> {
+    let sample_size = query.sample.unwrap_or(DEF_HISTOGRAM_SAMPLE);
+    let max_scan = query.max_scan.unwrap_or(DEF_HISTOGRAM_MAX_SCAN);
+    if sample_size == 0 || sample_size > MAX_HISTOGRAM_SAMPLE || max_scan == 0 {
+        return err_bad_req();
+    }
+
+    let idx: usize;
+    {
+        // lock runtime-live state data, for reading
+        let state = read_state(&m_state)?;
+        if state.debug {
+            println!("{:?}", DebugRequest(&req));
+        }
+
+        // lookup database index by name (path elem 0)
+        match state.name_idx.get(&path.0) {
+            None => return err_not_found(),
+            Some(r_idx) => idx = *r_idx,
+        }
+    }
+
+    // read one page per lock hold, so that other requests are served
+    // during a long scan
+    let mut sampler = db::histogram::SizeSampler::new(sample_size);
+    let mut start: Option<Vec<u8>> = None;
+    let mut scan_complete = false;
+    while sampler.n_seen() < max_scan {
+        let state = read_state(&m_state)?;
+        let max_keys = (max_scan - sampler.n_seen()) as usize;
+        match db::histogram::sample_page(
+            &state.dbs[idx].db,
+            &mut sampler,
+            start.as_deref(),
+            max_keys,
+        ) {
+            Ok(Some(key)) => start = Some(key),
+            Ok(None) => {
+                scan_complete = true;
+                break;
+            }
+            Err(e) => return err_db(e),
+        }
+    }
+
+    let hist = sampler.summary();
+    ok_json(json!({
+        "n_scanned": hist.n_seen,
+        "n_sampled": hist.n_sampled,
+        "scan_complete": scan_complete,
+        "key_len": percentiles_json(&hist.key_len),
+        "value_len": percentiles_json(&hist.value_len),
+    }))
+}
+
+/// DELETE data item. key in HTTP payload.  return ok as json response
+fn req_del(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, query, body): (web::Path<(String,)>, web::Query<WriteQuery>, web::Bytes),
+) -> Result<HttpResponse> {
+    // decode protobuf msg containing key, into KeyRequest struct
+    let res = pbdec_key_req(&body);
+    if res.is_err() {
+        return err_bad_req();
+    }
+    let in_msg = res.unwrap();
+
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to remove record from db, based on key (path elem 1)
+    let sync = write_sync(&req, &query);
+    match state.dbs[idx].db.del_sync(in_msg.get_key(), sync) {
+        Ok(optval) => match optval {
+            true => ok_json(json!({"result": true})),
+            false => err_not_found(), // db: value not found
+        },
+        Err(e) => err_db(e),
+    }
+}
+
+/// DELETE data item.  key in URI path.  return ok as json response
+fn req_obj_delete(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<WriteQuery>,
+) -> Result<HttpResponse> {
+    let key = match path_key(&req, &path.1) {
+        None => return err_bad_req(),
+        Some(key) => key,
+    };
+
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to remove record from db, based on key (path elem 1)
+    let sync = write_sync(&req, &query);
+    let if_match = match if_match_tags(&req) {
+        Err(_e) => return err_bad_req(),
+        Ok(if_match) => if_match,
+    };
+    if let Some(tags) = if_match {
+        if query.if_empty.as_deref().is_some_and(is_set) {
+            return err_bad_req();
+        }
+        return match state.dbs[idx].write_if_match(&key, None, &tags, sync) {
+            Ok(true) => ok_json(json!({"result": true})),
+            Ok(false) => err_precondition(),
+            Err(e) => err_db(e),
+        };
+    }
+    if query.if_empty.as_deref().is_some_and(is_set) {
+        let db = &mut state.dbs[idx].db;
+        return match db.del_if_empty(&key) {
+            Ok(true) => {
+                if sync && db.flush().is_err() {
+                    return err_500();
+                }
+                ok_json(json!({"result": true}))
+            }
+            Ok(false) => match db.get_ref(&key) {
+                Ok(Some(_val)) => err_conflict(), // db: value not empty
+                Ok(None) => err_not_found(),
+                Err(_e) => err_500(),
+            },
+            Err(e) => err_db(e),
+        };
+    }
+    match state.dbs[idx].db.del_sync(&key, sync) {
+        Ok(optval) => match optval {
+            true => ok_json(json!({"result": true})),
+            false => err_not_found(), // db: value not found
+        },
+        Err(e) => err_db(e),
+    }
+}
+
+/// GET data item. key in URI path, returns value in HTTP payload.
+fn req_obj_get(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<ObjGetQuery>,
+) -> Result<HttpResponse> {
+    let key = match path_key(&req, &path.1) {
+        None => return err_bad_req(),
+        Some(key) => key,
+    };
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to read record from db, based on key (path elem 1)
+    let consistency = match read_consistency(&req) {
+        Err(_e) => return err_bad_req(),
+        Ok(consistency) => consistency,
+    };
+    if let Some(hot_keys) = &state.dbs[idx].hot_keys {
+        hot_keys.record(&key);
+    }
+    let db = state.dbs[idx].reader(consistency);
+    match db.get_meta(&key) {
+        Ok(optval) => match optval {
+            Some((val, meta)) => {
+                let cache_control = state.dbs[idx].cfg.default_cache_control.as_ref();
+
+                // read under the state lock, so of the value just read;
+                // 0 (written before times were tracked) is unknown
+                let modified = if state.dbs[idx].cfg.track_modified {
+                    match db.get_modified(&key) {
+                        Ok(modified) => modified.map(|(_val, unix_ms)| unix_ms),
+                        Err(e) => return err_db(e),
+                    }
+                } else {
+                    None
+                };
+                let modified = modified.filter(|unix_ms| *unix_ms > 0);
+                let version = if state.dbs[idx].cfg.track_versions {
+                    match db.get_versioned(&key) {
+                        Ok(versioned) => versioned.map(|(_val, version)| version),
+                        Err(e) => return err_db(e),
+                    }
+                } else {
+                    None
+                };
+                let mut resp = ok_value(&req, val, &meta, cache_control, modified)?;
+                if let (Some(version), Ok(hdr_name)) = (
+                    version,
+                    header::HeaderName::from_bytes(VERSION_HEADER.as_bytes()),
+                ) {
+                    resp.headers_mut()
+                        .insert(hdr_name, header::HeaderValue::from(version));
+                }
+                Ok(resp)
+            }
+
+            // db: value not found.  return caller-supplied default,
+            // or empty value if so configured, or 404.
+            None => match &query.default {
+                Some(def_val) => ok_binary(def_val.as_bytes().to_vec()),
+                None => {
+                    if state.dbs[idx].cfg.missing_as_empty {
+                        ok_binary(Vec::new())
+                    } else {
+                        err_not_found()
+                    }
+                }
+            },
+        },
+        Err(e) => err_db(e),
+    }
+}
+
+/// HEAD of data item. key in URI path; 200 if present, 404 if not,
+/// without reading the value.  no body.
+fn req_obj_head(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse> {
+    let key = match path_key(&req, &path.1) {
+        None => return Ok(HttpResponse::BadRequest().finish()),
+        Some(key) => key,
+    };
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return Ok(HttpResponse::NotFound().finish()),
+        Some(r_idx) => *r_idx,
+    };
+
+    let consistency = match read_consistency(&req) {
+        Err(_e) => return Ok(HttpResponse::BadRequest().finish()),
+        Ok(consistency) => consistency,
+    };
+    match state.dbs[idx].reader(consistency).exists(&key) {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NotFound().finish()),
+        Err(_e) => Ok(HttpResponse::InternalServerError().finish()), // db: error
+    }
+}
+
+/// POST to data item. key in URI path, operation in query string.
+fn req_obj_post(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<ObjPostQuery>,
+) -> Result<HttpResponse> {
+    let key_enc = match KeyEnc::of(&req) {
+        None => return err_bad_req(),
+        Some(key_enc) => key_enc,
+    };
+    let key = match key_enc.decode(&path.1) {
+        None => return err_bad_req(),
+        Some(key) => key,
+    };
+
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    // reset expiry, leaving value unchanged
+    if let Some(ttl_secs) = query.touch {
+        if query.rename_to.is_some() {
+            return err_bad_req();
+        }
+        return match state.dbs[idx].db.touch(&key, ttl_secs) {
+            Ok(true) => ok_json(json!({"result": true})),
+            Ok(false) => err_not_found(),
+            Err(e) => err_db(e),
+        };
+    }
+
+    let to_key = match query.rename_to.as_deref().map(|k| key_enc.decode(k)) {
+        Some(Some(k)) => k,
+        _ => return err_bad_req(),
+    };
+    let overwrite = query.overwrite.unwrap_or(false);
+
+    // attempt to move record to new key.  if not moved, report
+    // whether the source was absent, or the target already exists.
+    let db = &mut state.dbs[idx].db;
+    match db.rename(&key, &to_key, overwrite) {
+        Ok(true) => ok_json(json!({"result": true})),
+        Ok(false) => match db.get_ref(&key) {
+            Ok(None) => err_not_found(),
+            Ok(Some(_v)) => err_conflict(),
+            Err(e) => err_db(e),
+        },
+        Err(e) => err_db(e),
+    }
+}
+
+/// GET data item. key in HTTP payload, returns value in HTTP payload.
+fn req_get(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String,)>, web::Bytes),
+) -> Result<HttpResponse> {
+    // decode protobuf msg containing key, into KeyRequest struct
+    let res = pbdec_key_req(&body);
+    if res.is_err() {
+        return err_bad_req();
+    }
+    let in_msg = res.unwrap();
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // attempt to read record from db, based on key (http payload)
+    let consistency = match read_consistency(&req) {
+        Err(_e) => return err_bad_req(),
+        Ok(consistency) => consistency,
+    };
+    match state.dbs[idx].reader(consistency).get_ref(in_msg.get_key()) {
+        Ok(optval) => match optval {
+            Some(val) => ok_binary(val.to_vec()),
+            None => err_not_found(), // db: value not found
+        },
+        Err(e) => err_db(e),
+    }
+}
+
+/// atomic PUT of multiple data items. data items in HTTP payload, as
+/// protobuf or JSON. ret json ok.
+fn req_batch(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, query, body): (web::Path<(String,)>, web::Query<BatchQuery>, web::Bytes),
+) -> Result<HttpResponse> {
+    // decode JSON or MessagePack mutations, if so typed, else protobuf
+    // msg containing key/value pairs
+    let batch = match req.content_type() {
+        "application/json" => serde_json::from_slice(&body)
+            .map(batch_of)
+            .map_err(|e| e.to_string()),
+        "application/msgpack" => rmp_serde::from_slice(&body)
+            .map(batch_of)
+            .map_err(|e| e.to_string()),
+        _ => match pbdec_batch(&body) {
+            None => return err_bad_req(),
+            Some(batch) => Ok(batch),
+        },
+    };
+    // a malformed mutation refuses the whole batch, naming the mutation
+    let mut batch = match batch {
+        Err(msg) => return err_bad_param(&msg),
+        Ok(batch) => batch,
+    };
+    batch.duplicate_policy = match query.duplicates.as_deref() {
+        None | Some("last_wins") => db::api::DuplicatePolicy::LastWins,
+        Some("reject") => db::api::DuplicatePolicy::Reject,
+        Some(_) => return err_bad_req(),
+    };
+    batch.guard = match (&query.guard_key, &query.guard_value) {
+        (None, None) => None,
+        (None, Some(_)) => return err_bad_req(),
+        (Some(enc_key), enc_val) => {
+            let key = match base64::decode(enc_key) {
+                Ok(key) => key,
+                Err(_e) => return err_bad_req(),
+            };
+            let expected = match enc_val.as_ref().map(base64::decode) {
+                None => None,
+                Some(Ok(val)) => Some(val),
+                Some(Err(_e)) => return err_bad_req(),
+            };
+            Some((key, expected))
+        }
+    };
+
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx: usize;
+    match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => idx = *r_idx,
+    }
+
+    // a retry of a batch already applied succeeds, without re-applying
+    let idem_key = write_idem_key(&req);
+    if let Some(key) = &idem_key {
+        if state.idem_applied(idx, key) {
+            return ok_json(json!({"result": true}));
+        }
+    }
+
+    // attempt to store record in db, based on key (path elem 1)
+    match state.dbs[idx].db.apply_batch(&batch) {
+        Ok(_optval) => {
+            if let Some(key) = idem_key {
+                state.idem_record(idx, key);
+            }
+            ok_json(json!({"result": true}))
+        }
+        Err(e) => err_db(e),
+    }
+}
+
+/// Bulk conditional SET.  JSON list of {key, value, version} in HTTP
+/// payload; each value is stored only if its key's version is at most
+/// the one given, in one batch.  Returns JSON list of whether each was
+/// applied.
+fn req_cset(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String,)>, web::Bytes),
+) -> Result<HttpResponse> {
+    let puts: Vec<VersionedPut> = match serde_json::from_slice(&body) {
+        Ok(puts) => puts,
+        Err(e) => return err_bad_param(&e.to_string()),
+    };
+
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(idx) => *idx,
+    };
+
+    match state.dbs[idx].db.put_if_not_newer(&puts) {
+        Ok(applied) => ok_json(json!({"result": true, "applied": applied})),
+        Err(e) => err_db(e),
+    }
+}
+
+/// POST data item. value in HTTP payload, key allocated by server.
+fn req_obj_alloc(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String,)>, web::Bytes),
+) -> Result<HttpResponse> {
+    let key_enc = match KeyEnc::of(&req) {
+        None => return err_bad_req(),
+        Some(key_enc) => key_enc,
+    };
+
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    // attempt to store record in db, under newly allocated key
+    match state.dbs[idx].db.put_next(&body) {
+        Ok(key) => ok_json(json!({
+            "result": true,
+            "key": key_enc.encode(&key)})),
+        Err(e) => err_db(e),
+    }
+}
+
+/// Remove all expired records from db idx, in bounded batches.  The state
+/// lock is released between batches, so foreground requests may proceed.
+fn sweep_db(m_state: &Arc<RwLock<ServerState>>, idx: usize) -> Result<usize, db::Error> {
+    let mut n_removed = 0;
+    loop {
+        let n = lock_shared_state(m_state)?.dbs[idx]
+            .db
+            .sweep_expired(db::api::SWEEP_BATCH_KEYS)?;
+        n_removed += n;
+        if n < db::api::SWEEP_BATCH_KEYS {
+            return Ok(n_removed);
+        }
+    }
+}
+
+/// Time until the next sweep of db idx:  the sweep interval, or sooner
+/// if the db reports a record expiring before then.
+fn sweep_wait(m_state: &Arc<RwLock<ServerState>>, idx: usize, interval: Duration) -> Duration {
+    let next_expiry = match read_shared_state(m_state) {
+        Ok(state) => state.dbs[idx].db.next_expiry(),
+        Err(e) => Err(e),
+    };
+    match next_expiry {
+        Ok(Some(expiry_ms)) => {
+            let now_ms = Local::now().timestamp_millis() as u64;
+            let due = Duration::from_millis(expiry_ms.saturating_sub(now_ms));
+            due.max(SWEEP_MIN_WAIT).min(interval)
+        }
+        _ => interval,
+    }
+}
+
+/// Flush every writable db to storage, e.g. at shutdown, so that writes
+/// acknowledged but not yet durable are not lost.  Each db is flushed,
+/// even if another fails; Err names those which failed.  A lock poisoned
+/// by a panic is recovered, as the dbs are flushed on the way out.
+fn flush_dbs(m_state: &RwLock<ServerState>) -> io::Result<()> {
+    let state = match m_state.write() {
+        Ok(state) => state,
+        Err(poisoned) => poisoned.into_inner(),
+    };
+    let mut failed = Vec::new();
+    for db_state in state.dbs.iter().filter(|db_state| !db_state.cfg.read_only) {
+        if let Err(e) = db_state.db.flush() {
+            warn!("flush of db {} failed: {}", db_state.cfg.name, e);
+            failed.push(db_state.cfg.name.as_str());
+        }
+    }
+    if failed.is_empty() {
+        return Ok(());
+    }
+    Err(io::Error::other(format!(
+        "flush failed: {}",
+        failed.join(", ")
+    )))
+}
+
+/// Start background sweeper thread for db idx.  The thread exits when the
+/// returned Sender is dropped.
+fn start_sweeper(
+    m_state: Arc<RwLock<ServerState>>,
+    idx: usize,
+    interval: Duration,
+) -> (mpsc::Sender<()>, thread::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        // wake at each interval or expiry, until shutdown
+        let mut wait = sweep_wait(&m_state, idx, interval);
+        while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(wait) {
+            if let Err(e) = sweep_db(&m_state, idx) {
+                warn!("sweep: db {idx} failed: {e}");
+            }
+            wait = sweep_wait(&m_state, idx, interval);
+        }
+    });
+
+    (tx, handle)
+}
+
+/// Start background thread compacting db idx each interval.  The thread
+/// exits when the returned Sender is dropped.
+fn start_compactor(
+    m_state: Arc<RwLock<ServerState>>,
+    idx: usize,
+    interval: Duration,
+) -> (mpsc::Sender<()>, thread::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(interval) {
+            let res = match lock_shared_state(&m_state) {
+                Ok(mut state) => state.dbs[idx].db.compact(),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = res {
+                warn!("compact: db {idx} failed: {e}");
+            }
+        }
+    });
+
+    (tx, handle)
+}
+
+/// Start background thread closing idle snapshot scans, whose snapshots
+/// otherwise grow with every write.  The thread exits when the returned
+/// Sender is dropped.
+fn start_scan_reaper(
+    m_state: Arc<RwLock<ServerState>>,
+) -> (mpsc::Sender<()>, thread::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(SCAN_REAP_INTERVAL) {
+            if let Ok(mut state) = m_state.write() {
+                state.expire_scans();
+            }
+        }
+    });
+
+    (tx, handle)
+}
+
+/// Start background thread writing out the audit log's buffered lines
+/// each AUDIT_FLUSH_INTERVAL, so that a crash loses little of it.  The
+/// thread exits when the returned Sender is dropped.
+fn start_audit_flusher(log: &Arc<AuditLog>) -> (mpsc::Sender<()>, thread::JoinHandle<()>) {
+    let (tx, rx) = mpsc::channel();
+    let log = Arc::clone(log);
+    let handle = thread::spawn(move || {
+        while let Err(RecvTimeoutError::Timeout) = rx.recv_timeout(AUDIT_FLUSH_INTERVAL) {
+            log.flush();
+        }
+    });
+
+    (tx, handle)
+}
+
+// decode and execute one binary protocol request, from client peer, if
+// known; returns response body
+fn binary_call(
+    m_state: &Arc<RwLock<ServerState>>,
+    peer: Option<IpAddr>,
+    body: &[u8],
+) -> Result<Vec<u8>, (u8, String)> {
+    let bad_req = |e: &str| (binproto::ST_BAD_REQUEST, e.to_string());
+    let db_err = |e: db::Error| match e {
+        db::Error::QuotaExceeded => (binproto::ST_QUOTA_EXCEEDED, e.to_string()),
+        _ => (binproto::ST_DB_ERROR, e.to_string()),
+    };
+
+    // opcode, db name, then opcode-specific fields
+    let mut rd = binproto::Reader::new(body);
+    let op = rd.byte().map_err(bad_req)?;
+    let db_name = rd.bytes().map_err(bad_req)?;
+    let mut fields = Vec::new();
+    while !rd.is_empty() {
+        fields.push(rd.bytes().map_err(bad_req)?);
+    }
+    let n_fields = match op {
+        binproto::OP_GET | binproto::OP_DEL | binproto::OP_BATCH => 1,
+        binproto::OP_PUT | binproto::OP_KEYS => 2,
+        binproto::OP_STAT => 0,
+        _ => return Err(bad_req("unknown opcode")),
+    };
+    if fields.len() != n_fields {
+        return Err(bad_req("wrong field count"));
+    }
+
+    // decode batch before locking, as req_batch
+    let batch = match op {
+        binproto::OP_BATCH => match pbdec_batch(fields[0]) {
+            None => return Err(bad_req("invalid batch")),
+            Some(batch) => Some(batch),
+        },
+        _ => None,
+    };
+
+    // refuse operations disabled for the db, as the HTTP API
+    let op_kind = match op {
+        binproto::OP_GET => OpKind::Get,
+        binproto::OP_PUT => OpKind::Put,
+        binproto::OP_DEL => OpKind::Delete,
+        binproto::OP_BATCH => OpKind::Batch,
+        binproto::OP_KEYS => OpKind::Iter,
+        _ => OpKind::Stat, // OP_STAT
+    };
+    let lookup = |state: &ServerState| -> Result<usize, (u8, String)> {
+        let idx = match std::str::from_utf8(db_name)
+            .ok()
+            .and_then(|name| state.name_idx.get(name))
+        {
+            None => return Err((binproto::ST_NO_DB, String::from("db not found"))),
+            Some(idx) => *idx,
+        };
+        if state.dbs[idx].op_disabled(op_kind) {
+            return Err((binproto::ST_FORBIDDEN, String::from("operation disabled")));
+        }
+        Ok(idx)
+    };
+
+    let mut out = vec![binproto::ST_OK];
+    if !op_kind.is_write() {
+        // lock runtime-live state data, for reading, as HTTP reads
+        let state = read_shared_state(m_state).map_err(db_err)?;
+        let db = &state.dbs[lookup(&state)?].db;
+        match op {
+            binproto::OP_GET => match db.get(fields[0]).map_err(db_err)? {
+                None => return Err((binproto::ST_NOT_FOUND, String::from("key not found"))),
+                Some(val) => binproto::put_bytes(&mut out, &val),
+            },
+            binproto::OP_KEYS => {
+                let mut opts = db::api::IterOptions::new();
+                if !fields[0].is_empty() {
+                    opts.start(fields[0]);
+                }
+                if !fields[1].is_empty() {
+                    opts.prefix(fields[1]);
+                }
+                let key_list = db.iter_keys(opts).map_err(db_err)?;
+                binproto::put_varint(&mut out, key_list.keys.len() as u64);
+                for key in &key_list.keys {
+                    binproto::put_bytes(&mut out, key);
+                }
+                out.push(key_list.list_end as u8);
+            }
+            _ => {
+                // OP_STAT
+                let st = db.stat().map_err(db_err)?;
+                binproto::put_varint(&mut out, st.n_records);
+            }
+        }
+        return Ok(out);
+    }
+
+    // lock runtime-live state data
+    let mut state = lock_shared_state(m_state).map_err(db_err)?;
+    state.audit_as(peer);
+    let idx = lookup(&state)?;
+    if state.drain.load(Ordering::SeqCst) == DRAIN_WRITES {
+        return Err((binproto::ST_UNAVAILABLE, String::from("server draining")));
+    }
+
+    // a grouped put waits for its group unlocked
+    if op == binproto::OP_PUT {
+        if let Some(group) = state.dbs[idx].put_group(fields[1], None) {
+            drop(state);
+            put_grouped(m_state, &group, idx, fields[0], fields[1], false).map_err(db_err)?;
+            return Ok(vec![binproto::ST_OK]);
         }
     }
-}
+    let db_state = &mut state.dbs[idx];
 
-fn pbdec_update_req(wiredata: &[u8]) -> ProtobufResult<UpdateRequest> {
-    match parse_from_bytes::<UpdateRequest>(wiredata) {
-        Err(e) => Err(e),
-        Ok(req) => {
-            if req.magic != UpdateRequest_MagicNum::MAGIC {
-                let wire_err = protobuf::error::WireError::Other;
-                let err = ProtobufError::WireError(wire_err);
-                Err(err)
-            } else {
-                Ok(req)
+    match op {
+        binproto::OP_PUT => {
+            db_state
+                .put(fields[0], fields[1], false, None)
+                .map_err(db_err)?;
+        }
+        binproto::OP_DEL => {
+            if !db_state.db.del(fields[0]).map_err(db_err)? {
+                return Err((binproto::ST_NOT_FOUND, String::from("key not found")));
             }
         }
+        binproto::OP_BATCH => {
+            db_state.db.apply_batch(&batch.unwrap()).map_err(db_err)?;
+        }
+        _ => unreachable!(),
     }
+
+    Ok(out)
 }
 
-fn pbdec_batch_req(wiredata: &[u8]) -> ProtobufResult<BatchRequest> {
-    match parse_from_bytes::<BatchRequest>(wiredata) {
-        Err(e) => Err(e),
-        Ok(req) => {
-            if req.magic != BatchRequest_MagicNum::MAGIC {
-                let wire_err = protobuf::error::WireError::Other;
-                let err = ProtobufError::WireError(wire_err);
-                Err(err)
-            } else {
-                Ok(req)
+// admission of binary protocol connections and requests, as of HTTP
+// ones:  connections count toward max_connections, requests toward each
+// client's rate_limit_rps, and are refused without the api_key, if set
+#[derive(Clone)]
+struct BinaryAdmission {
+    api_key: Option<Arc<str>>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    n_connections: Arc<AtomicUsize>,
+    max_connections: Option<usize>,
+}
+
+// serve binary protocol requests on one connection, until it closes.
+// with an api_key, requests are refused, closing the connection, until
+// an AUTH request presents it
+fn serve_binary_conn(
+    m_state: Arc<RwLock<ServerState>>,
+    mut stream: TcpStream,
+    admission: BinaryAdmission,
+) {
+    let mut reader = match stream.try_clone() {
+        Ok(s) => io::BufReader::new(s),
+        Err(_e) => return,
+    };
+    let peer = stream.peer_addr().ok().map(|peer| peer.ip());
+    let api_key = admission.api_key;
+    let mut admitted = api_key.is_none();
+
+    loop {
+        // an unauthenticated client may not have large frames buffered
+        let max_len = match admitted {
+            true => binproto::MAX_FRAME_LEN,
+            false => binproto::MAX_AUTH_FRAME_LEN,
+        };
+        let body = match binproto::read_frame_max(&mut reader, max_len) {
+            Ok(Some(body)) => body,
+            Ok(None) => return,
+            Err(e) => {
+                // the stream is out of sync; report, then close
+                if e.kind() == io::ErrorKind::InvalidData {
+                    let resp = binproto::enc_error(binproto::ST_BAD_FRAME, "bad frame");
+                    let _ = binproto::write_frame(&mut stream, &resp);
+                }
+                return;
+            }
+        };
+
+        let is_auth = body.first() == Some(&binproto::OP_AUTH);
+        if is_auth {
+            admitted = match &api_key {
+                None => true,
+                Some(api_key) => auth::auth_frame_admits(&body, api_key),
+            };
+        }
+        if !admitted {
+            let resp = binproto::enc_error(binproto::ST_UNAUTHORIZED, "unauthorized");
+            let _ = binproto::write_frame(&mut stream, &resp);
+            return;
+        }
+
+        let throttled = match (&admission.rate_limiter, peer) {
+            (Some(limiter), Some(peer)) => limiter.check(peer, Instant::now()).err(),
+            _ => None,
+        };
+        let resp = match (throttled, is_auth) {
+            (Some(retry_after), _) => {
+                let msg = format!(
+                    "too many requests; retry after {}ms",
+                    retry_after.as_millis()
+                );
+                binproto::enc_error(binproto::ST_TOO_MANY_REQUESTS, &msg)
             }
+            (None, true) => vec![binproto::ST_OK],
+            (None, false) => match binary_call(&m_state, peer, &body) {
+                Ok(resp) => resp,
+                Err((status, msg)) => binproto::enc_error(status, &msg),
+            },
+        };
+        if binproto::write_frame(&mut stream, &resp).is_err() {
+            return;
         }
     }
 }
 
-// helper function, success + binary response
-fn ok_binary(val: Vec<u8>) -> Result<HttpResponse> {
-    Ok(HttpResponse::build(StatusCode::OK)
-        .content_type("application/octet-stream")
-        .body(val))
-}
+/// Start binary protocol listener thread, serving each connection on a
+/// thread of its own, as admission allows.  A connection beyond
+/// max_connections is sent UNAVAILABLE, then closed, so that a flood of
+/// clients neither exhausts threads nor buffers unbounded frames.  The
+/// listener exits when the returned Sender is dropped; open connections
+/// are served until the process exits.
+fn start_binary_listener(
+    m_state: Arc<RwLock<ServerState>>,
+    listener: TcpListener,
+    admission: BinaryAdmission,
+) -> io::Result<(mpsc::Sender<()>, thread::JoinHandle<()>)> {
+    // poll for connections, so that shutdown is noticed
+    listener.set_nonblocking(true)?;
 
-// helper function, success + json response
-fn ok_json(jval: serde_json::Value) -> Result<HttpResponse> {
-    Ok(HttpResponse::build(StatusCode::OK)
-        .content_type("application/json")
-        .body(jval.to_string()))
+    let (tx, rx) = mpsc::channel();
+    let handle = thread::spawn(move || loop {
+        match listener.accept() {
+            Ok((mut stream, _peer)) => {
+                let conn = ConnGuard::open(&admission.n_connections, admission.max_connections);
+                if stream.set_nonblocking(false).is_err() {
+                    continue;
+                }
+                if conn.over_limit {
+                    let resp =
+                        binproto::enc_error(binproto::ST_UNAVAILABLE, "too many connections");
+                    let _ = binproto::write_frame(&mut stream, &resp);
+                    continue;
+                }
+                let m_state = Arc::clone(&m_state);
+                let admission = admission.clone();
+                thread::spawn(move || {
+                    let _conn = conn;
+                    serve_binary_conn(m_state, stream, admission)
+                });
+            }
+            Err(e) => {
+                if e.kind() != io::ErrorKind::WouldBlock {
+                    warn!("binary: accept failed: {e}");
+                }
+                if let Err(RecvTimeoutError::Disconnected) = rx.recv_timeout(BINARY_ACCEPT_POLL) {
+                    return;
+                }
+            }
+        }
+    });
+
+    Ok((tx, handle))
 }
 
-/// simple root index handler, describes our service
-#[get("/")]
-fn req_index(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// COUNT keys with a prefix.  Prefix in query string.  Served from the
+/// db's count cache, if enabled, unless fresh is set.
+fn req_count(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<CountQuery>,
 ) -> Result<HttpResponse> {
-    // fill basic server info struct used for output
-    let mut srv_info = ServerInfo {
-        name: String::from(APPNAME),
-        version: String::from(VERSION),
-        databases: Vec::new(),
-    };
-
     // lock runtime-live state data
-    let state = m_state.lock().unwrap();
+    let mut state = lock_state(&m_state)?;
     if state.debug {
-        println!("{:?}", req);
-    }
-
-    // copy each db config into output struct
-    for db_state in &state.dbs {
-        srv_info.databases.push(db_state.cfg.clone());
+        println!("{:?}", DebugRequest(&req));
     }
 
-    // serialize structs into json
-    let jv = serde_json::to_value(&srv_info)?;
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
 
-    // return json output
-    ok_json(jv)
+    let prefix = query.prefix.as_ref().map_or(&b""[..], |p| p.as_bytes());
+    let fresh = query.fresh.as_deref().is_some_and(is_set);
+    match state.dbs[idx].count_prefix(prefix, fresh) {
+        Ok((n_keys, None)) => ok_json(json!({ "count": n_keys, "cached": false })),
+        Ok((n_keys, Some(age))) => ok_json(json!({
+            "count": n_keys,
+            "cached": true,
+            "age_ms": age.as_millis() as u64})),
+        Err(e) => err_db(e),
+    }
 }
 
-/// CLEAR all data items.
-fn req_clear(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// DELETE all keys with a prefix, atomically.  Prefix in query string.
+fn req_delete_prefix(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
     path: web::Path<(String,)>,
+    query: web::Query<DeletePrefixQuery>,
 ) -> Result<HttpResponse> {
+    // an empty prefix would remove every key:  that is CLEAR's job
+    let prefix = match query.prefix.as_deref() {
+        None | Some("") => return err_bad_param("prefix must not be empty"),
+        Some(prefix) => prefix.as_bytes(),
+    };
+
     // lock runtime-live state data
-    let mut state = m_state.lock().unwrap();
+    let mut state = lock_state_for(&m_state, &req)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
+    let idx = match state.name_idx.get(&path.0) {
         None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
-    }
+        Some(r_idx) => *r_idx,
+    };
 
-    // attempt to clear all records from db
-    match state.dbs[idx].db.clear() {
-        Ok(_optval) => ok_json(json!({"result": true})),
-        Err(_e) => err_500(), // db: error
+    match state.dbs[idx].db.delete_prefix(prefix) {
+        Ok(n_deleted) => ok_json(json!({"result": true, "deleted": n_deleted})),
+        Err(e) => err_db(e),
     }
 }
 
-/// Return db stats as protobuf
-fn req_stat(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// HEAD of keys with a prefix.  Prefix in query string; 200 if any key
+/// has it, 404 if none, without listing them.  no body.
+fn req_keys_head(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
     path: web::Path<(String,)>,
+    query: web::Query<HasPrefixQuery>,
 ) -> Result<HttpResponse> {
-    // lock runtime-live state data
-    let state = m_state.lock().unwrap();
+    // an empty prefix tests for any key at all
+    let prefix = match query.prefix.as_deref() {
+        None => return Ok(HttpResponse::BadRequest().finish()),
+        Some(prefix) => prefix.as_bytes(),
+    };
+
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
-        None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
+    let idx = match state.name_idx.get(&path.0) {
+        None => return Ok(HttpResponse::NotFound().finish()),
+        Some(r_idx) => *r_idx,
+    };
+
+    match state.dbs[idx].db.has_prefix(prefix) {
+        Ok(true) => Ok(HttpResponse::Ok().finish()),
+        Ok(false) => Ok(HttpResponse::NotFound().finish()),
+        Err(_e) => Ok(HttpResponse::InternalServerError().finish()), // db: error
     }
+}
 
-    // attempt to list keys, starting at supplied key (or at db-start, if none)
-    let res = state.dbs[idx].db.stat();
-    if res.is_err() {
-        return err_500();
+/// HOTKEYS:  the keys most read by GET, with approximate counts, from a
+/// db configured with track_hot_keys.  n, in query string, lists at most
+/// n keys, base64-encoded, most read first.
+fn req_hot_keys(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<HotKeysQuery>,
+) -> Result<HttpResponse> {
+    let n = query.n.unwrap_or(DEF_HOT_KEYS_N);
+    if n == 0 || n > hotkeys::MAX_HOT_KEYS {
+        return err_bad_req();
     }
-    let st = res.unwrap();
 
-    // encode protobuf output to bytes
-    let out_bytes = pbenc_db_stat_resp(st.n_records);
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
 
-    ok_binary(out_bytes)
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
+
+    let hot_keys = match &state.dbs[idx].hot_keys {
+        None => return err_hot_keys_unsupported(),
+        Some(hot_keys) => hot_keys,
+    };
+    let (top, n_reads) = hot_keys.top(n);
+    let keys: Vec<serde_json::Value> = top
+        .iter()
+        .map(|hot| json!({"key": base64::encode(&hot.key), "count": hot.count, "error": hot.error}))
+        .collect();
+    ok_json(json!({
+        "n_reads": n_reads,
+        "max_keys": hotkeys::MAX_HOT_KEYS,
+        "keys": keys,
+    }))
 }
 
-/// Return db stats as JSON
-fn req_stat_json(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// SIZE of a key range, approximately, in bytes.  Range in query string.
+fn req_size(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
     path: web::Path<(String,)>,
+    query: web::Query<SizeQuery>,
 ) -> Result<HttpResponse> {
-    // lock runtime-live state data
-    let state = m_state.lock().unwrap();
+    // lock runtime-live state data, for reading
+    let state = read_state(&m_state)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
+    let idx = match state.name_idx.get(&path.0) {
         None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
+        Some(r_idx) => *r_idx,
+    };
+
+    let start = query.start.as_ref().map_or(&b""[..], |k| k.as_bytes());
+    let end = query.end.as_ref().map_or(&b""[..], |k| k.as_bytes());
+    match state.dbs[idx].db.size_between(start, end) {
+        Ok(n_bytes) => ok_json(json!({ "bytes": n_bytes })),
+        Err(e) => err_db(e),
     }
+}
 
-    // attempt to list keys, starting at supplied key (or at db-start, if none)
-    let res = state.dbs[idx].db.stat();
-    if res.is_err() {
-        return err_500();
+/// SWAP values of two data items, atomically.  Keys in query string.
+fn req_swap(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String,)>,
+    query: web::Query<SwapQuery>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
     }
-    let st = res.unwrap();
 
-    // fill for-JSON-output struct with return data
-    let out_msg = DbStatResponseJson {
-        n_records: st.n_records.to_string(),
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
     };
 
-    // serialize structs into json
-    let jv = serde_json::to_value(&out_msg)?;
-
-    // return json output
-    ok_json(jv)
+    match state.dbs[idx]
+        .db
+        .swap(query.a.as_bytes(), query.b.as_bytes())
+    {
+        Ok(()) => ok_json(json!({"result": true})),
+        Err(e) => err_db(e),
+    }
 }
 
-/// Sequential iteration through all KEYS in db. Start-key in HTTP payload.
-fn req_keys(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// SWEEP expired data items, now.
+fn req_sweep(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
-    (path, body): (web::Path<(String,)>, web::Bytes),
+    path: web::Path<(String,)>,
 ) -> Result<HttpResponse> {
-    // decode protobuf msg containing key, into KeyRequest struct
-    let res = pbdec_iter_req(&body);
-    if res.is_err() {
-        return err_bad_req();
+    let idx: usize;
+    {
+        // lock runtime-live state data
+        let state = lock_state(&m_state)?;
+        if state.debug {
+            println!("{:?}", DebugRequest(&req));
+        }
+
+        // lookup database index by name (path elem 0)
+        match state.name_idx.get(&path.0) {
+            None => return err_not_found(),
+            Some(r_idx) => idx = *r_idx,
+        }
     }
-    let in_msg = res.unwrap();
 
-    // lock runtime-live state data
-    let state = m_state.lock().unwrap();
+    match sweep_db(&m_state, idx) {
+        Ok(n_removed) => ok_json(json!({"result": true, "n_removed": n_removed})),
+        Err(e) => err_db(e),
+    }
+}
+
+/// PUT data item. key in URI path, value in HTTP payload.
+fn req_obj_put(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, query, body): (
+        web::Path<(String, String)>,
+        web::Query<WriteQuery>,
+        web::Bytes,
+    ),
+) -> Result<EitherResponse> {
+    let key = match path_key(&req, &path.1) {
+        None => return err_bad_req().map(EitherResponse::A),
+        Some(key) => key,
+    };
+
+    // serialize a conditional PUT, a read-modify-write, with others of
+    // key, then lock runtime-live state data
+    let key_locks = key_locks(&m_state)?;
+    let conditional = req.headers().contains_key(header::IF_MATCH)
+        || req.headers().contains_key(header::IF_NONE_MATCH)
+        || req.headers().contains_key(IF_VERSION_HEADER);
+    let _key_guard = conditional.then(|| key_locks.lock(&path.0, &key));
+    let mut state = lock_state_for(&m_state, &req)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
     let idx: usize;
     match state.name_idx.get(&path.0) {
-        None => return err_not_found(),
+        None => return err_not_found().map(EitherResponse::A),
         Some(r_idx) => idx = *r_idx,
     }
 
-    // attempt to list keys, starting at supplied key (or at db-start, if none)
-    let mut opts = db::api::IterOptions::new();
-    if !in_msg.start_key.is_empty() {
-        opts.start(&in_msg.start_key);
+    // attempt to store record in db, based on key (path elem 1)
+    let sync = write_sync(&req, &query);
+    let expire_at = match write_expire_at(&req) {
+        Err(_e) => return err_bad_req().map(EitherResponse::A),
+        Ok(expire_at) => expire_at,
+    };
+    let meta = match write_meta(&req) {
+        Err(_e) => return err_bad_req().map(EitherResponse::A),
+        Ok(meta) => meta,
+    };
+    let if_match = match if_match_tags(&req) {
+        Err(_e) => return err_bad_req().map(EitherResponse::A),
+        Ok(if_match) => if_match,
+    };
+    let if_version = match write_if_version(&req) {
+        Err(_e) => return err_bad_req().map(EitherResponse::A),
+        Ok(if_version) => if_version,
+    };
+
+    // a PUT conditional on the key's version is applied directly, and may
+    // carry neither metadata, an expiry, nor another condition
+    if let Some(expected) = if_version {
+        if if_match.is_some()
+            || req.headers().contains_key(header::IF_NONE_MATCH)
+            || expire_at.is_some()
+            || !meta.is_empty()
+        {
+            return err_bad_req().map(EitherResponse::A);
+        }
+        return match state.dbs[idx].put_if_version(&key, &body, expected, sync) {
+            Ok(true) => ok_json(json!({"result": true})),
+            Ok(false) => err_precondition(),
+            Err(e) => err_db(e),
+        }
+        .map(EitherResponse::A);
     }
-    if !in_msg.prefix.is_empty() {
-        opts.prefix(&in_msg.prefix);
+
+    // a create-only PUT is applied directly, and may carry neither
+    // metadata, an expiry, nor If-Match
+    let create_only = match if_none_match_any(&req) {
+        Err(_e) => return err_bad_req().map(EitherResponse::A),
+        Ok(create_only) => create_only,
+    };
+    if create_only {
+        if if_match.is_some() || expire_at.is_some() || !meta.is_empty() {
+            return err_bad_req().map(EitherResponse::A);
+        }
+        return match state.dbs[idx].put_if_absent(&key, &body, sync) {
+            Ok(true) => ok_json_created(json!({"result": true})),
+            Ok(false) => err_precondition(),
+            Err(e) => err_db(e),
+        }
+        .map(EitherResponse::A);
     }
-    let res = state.dbs[idx].db.iter_keys(opts);
-    if res.is_err() {
-        return err_500();
+
+    // a conditional PUT is applied directly, and may neither carry
+    // metadata nor expire
+    if let Some(tags) = if_match {
+        if expire_at.is_some() || !meta.is_empty() {
+            return err_bad_req().map(EitherResponse::A);
+        }
+        return match state.dbs[idx].write_if_match(&key, Some(&body), &tags, sync) {
+            Ok(true) => ok_json(json!({"result": true})),
+            Ok(false) => err_precondition(),
+            Err(e) => err_db(e),
+        }
+        .map(EitherResponse::A);
     }
 
-    // encode protobuf output to bytes
-    let key_list = res.unwrap();
-    let out_bytes = pbenc_keys_resp(&key_list);
+    // a PUT with metadata is applied directly, and may not expire
+    if !meta.is_empty() {
+        if expire_at.is_some() {
+            return err_bad_req().map(EitherResponse::A);
+        }
+        return match state.dbs[idx].put_meta(&key, &body, &meta, sync) {
+            Ok(_optval) => ok_json(json!({"result": true})),
+            Err(e) => err_db(e),
+        }
+        .map(EitherResponse::A);
+    }
 
-    ok_binary(out_bytes)
+    if let Some(group) = state.dbs[idx].put_group(&body, expire_at) {
+        drop(state);
+        return Ok(EitherResponse::B(put_grouped_async(
+            &m_state, group, idx, key, body, sync,
+        )));
+    }
+    match state.dbs[idx].put(&key, &body, sync, expire_at) {
+        Ok(_optval) => ok_json(json!({"result": true})),
+        Err(e) => err_db(e),
+    }
+    .map(EitherResponse::A)
 }
 
-/// Sequential iteration through all KEYS in db. Start-key in HTTP payload.
-fn req_keys_json(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
-    req: HttpRequest,
-    path: web::Path<(String,)>,
-) -> Result<HttpResponse> {
-    let mut lastkey: Option<Vec<u8>> = None;
+// anonymous temporary file, for spooling a streamed request body:
+// unlinked once created, so removed when closed, however the request ends
+fn spool_file() -> io::Result<fs::File> {
+    static SPOOL_SEQ: AtomicU64 = AtomicU64::new(0);
+    let name = format!(
+        "kvdbd-spool-{}-{}",
+        process::id(),
+        SPOOL_SEQ.fetch_add(1, Ordering::Relaxed)
+    );
+    let path = env::temp_dir().join(name);
+    let file = fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    fs::remove_file(&path)?;
+    Ok(file)
+}
 
-    // query string continues previous search
-    let qs = req.query_string();
-    if qs.find("&") != None {
-        // we only support a single key=value param
-        return err_bad_req();
-    }
-    let searchkey = "lastkey=";
-    if qs.len() > 0 {
-        if qs.len() < searchkey.len() {
-            return err_bad_req();
-        }
-        let key = &qs[0..searchkey.len()];
-        if key != searchkey {
-            return err_bad_req();
-        }
+// body length a request declares, if any
+fn content_length(headers: &HeaderMap) -> Option<usize> {
+    headers
+        .get(header::CONTENT_LENGTH)
+        .and_then(|hdr| hdr.to_str().ok())
+        .and_then(|len| len.parse().ok())
+}
 
-        let val = &qs[searchkey.len()..];
-        lastkey = Some(val.as_bytes().to_vec());
-    }
+// true if a PUT, per its request head, is spooled by req_obj_put_stream()
+// rather than buffered by req_obj_put():  a plain PUT, without an expiry,
+// metadata or a condition, declaring a body longer than
+// VALUE_STREAM_CHUNK, as a GET streams a value that long
+fn streamed_put(head: &RequestHead) -> bool {
+    let headers = &head.headers;
+    let long = content_length(headers).is_some_and(|len| len > VALUE_STREAM_CHUNK);
+    long && [
+        EXPIRE_AT_HEADER,
+        TTL_HEADER,
+        IF_VERSION_HEADER,
+        header::IF_MATCH.as_str(),
+        header::IF_NONE_MATCH.as_str(),
+    ]
+    .iter()
+    .all(|name| !headers.contains_key(*name))
+        && !headers
+            .keys()
+            .any(|name| name.as_str().starts_with(META_HEADER_PREFIX))
+}
 
-    // lock runtime-live state data
-    let state = m_state.lock().unwrap();
+/// PUT data item, streamed, for a PUT chosen by streamed_put().  key in
+/// URI path, value in HTTP payload, spooled to a temporary file as it
+/// arrives, rather than buffered in memory, then read into the db by
+/// put_stream().  No backend writes a value incrementally, so the value
+/// is still read whole into memory as it is stored; spooling spares the
+/// request buffer, and holding the state lock while the body arrives.
+/// The body is bounded by max_request_bytes, and the value by the db's
+/// max_value_len, each checked as it arrives.
+fn req_obj_put_stream(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, query, payload): (
+        web::Path<(String, String)>,
+        web::Query<WriteQuery>,
+        web::Payload,
+    ),
+) -> Result<EitherResponse> {
+    let key = match path_key(&req, &path.1) {
+        None => return err_bad_req().map(EitherResponse::A),
+        Some(key) => key,
+    };
+    let sync = write_sync(&req, &query);
+
+    // lock runtime-live state data, for reading, until the value arrives
+    let state = read_state(&m_state)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
-        None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
-    }
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found().map(EitherResponse::A),
+        Some(r_idx) => *r_idx,
+    };
 
-    // attempt to list keys, starting at supplied key (or at db-start, if none)
-    let res;
-    if lastkey.is_none() {
-        res = state.dbs[idx].db.iter_keys(db::api::IterOptions::new());
-    } else {
-        let mut opts = db::api::IterOptions::new();
-        opts.start(&lastkey.unwrap());
-        res = state.dbs[idx].db.iter_keys(opts);
+    // refused before the body is read, rather than once spooled
+    if state.dbs[idx].cfg.read_only {
+        return err_forbidden().map(EitherResponse::A);
     }
-    if res.is_err() {
-        return err_500();
+    let max_len = match state.dbs[idx].cfg.max_value_len {
+        0 => state.max_request_bytes,
+        max_value_len => max_value_len.min(state.max_request_bytes),
+    };
+    drop(state);
+    if content_length(req.headers()).is_some_and(|len| len > max_len) {
+        return err_db(db::Error::TooLarge).map(EitherResponse::A);
     }
-    let key_list = res.unwrap();
 
-    // fill for-JSON-output struct with return data
-    let mut out_msg = KeyResponseJson {
-        keys: Vec::new(),
-        list_end: key_list.list_end,
+    let spool = match spool_file() {
+        Err(_e) => return err_500().map(EitherResponse::A),
+        Ok(spool) => spool,
+    };
+    let m_state = Arc::clone(&m_state);
+    let db_name = path.0.clone();
+    let fut = payload
+        .map_err(|_e| db::Error::InvalidInput(String::from("request body read failed")))
+        .fold((spool, 0), move |(mut spool, len), chunk| {
+            let len = len + chunk.len();
+            if len > max_len {
+                return Err(db::Error::TooLarge);
+            }
+            match spool.write_all(&chunk) {
+                Err(e) => Err(db::Error::Backend(format!("spool failed: {e}"))),
+                Ok(()) => Ok((spool, len)),
+            }
+        })
+        .and_then(move |(mut spool, len)| {
+            if let Err(e) = spool.seek(SeekFrom::Start(0)) {
+                return Err(db::Error::Backend(format!("spool failed: {e}")));
+            }
+
+            // the db may have been closed, or others opened, while the
+            // body arrived:  look it up again
+            let mut state = lock_shared_state(&m_state)?;
+            let idx = match state.name_idx.get(&db_name) {
+                None => return Err(db::Error::NotFound),
+                Some(idx) => *idx,
+            };
+            state.audit_as(req.peer_addr().map(|peer| peer.ip()));
+            state.dbs[idx].put_stream(&key, &mut spool, len, sync)
+        })
+        .then(|res| match res {
+            Ok(_optval) => ok_json(json!({"result": true})),
+            Err(e) => err_db(e),
+        });
+    Ok(EitherResponse::B(Box::new(fut)))
+}
+
+/// APPEND to data item. key in URI path, suffix in HTTP payload. returns
+/// the new value length as json response
+fn req_obj_append(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, query, body): (
+        web::Path<(String, String)>,
+        web::Query<WriteQuery>,
+        web::Bytes,
+    ),
+) -> Result<HttpResponse> {
+    let key = match path_key(&req, &path.1) {
+        None => return err_bad_req(),
+        Some(key) => key,
     };
-    for key in key_list.keys {
-        out_msg.keys.push(String::from_utf8_lossy(&key).to_string());
+
+    // serialize with other read-modify-writes of key, then lock
+    // runtime-live state data
+    let key_locks = key_locks(&m_state)?;
+    let _key_guard = key_locks.lock(&path.0, &key);
+    let mut state = lock_state_for(&m_state, &req)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
     }
 
-    // serialize structs into json
-    let jv = serde_json::to_value(&out_msg)?;
+    // lookup database index by name (path elem 0)
+    let idx = match state.name_idx.get(&path.0) {
+        None => return err_not_found(),
+        Some(r_idx) => *r_idx,
+    };
 
-    // return json output
-    ok_json(jv)
+    let sync = write_sync(&req, &query);
+    match state.dbs[idx].append(&key, &body, sync) {
+        Ok(len) => ok_json(json!({"result": true, "len": len})),
+        Err(e) => err_db(e),
+    }
 }
 
-/// DELETE data item. key in HTTP payload.  return ok as json response
-fn req_del(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// INIT data item. key in URI path, initial value in HTTP payload, stored
+/// only if key is absent.  returns the value now stored, initial or not
+fn req_obj_init(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
-    (path, body): (web::Path<(String,)>, web::Bytes),
+    (path, query, body): (
+        web::Path<(String, String)>,
+        web::Query<WriteQuery>,
+        web::Bytes,
+    ),
 ) -> Result<HttpResponse> {
-    // decode protobuf msg containing key, into KeyRequest struct
-    let res = pbdec_key_req(&body);
-    if res.is_err() {
-        return err_bad_req();
-    }
-    let in_msg = res.unwrap();
+    let key = match path_key(&req, &path.1) {
+        None => return err_bad_req(),
+        Some(key) => key,
+    };
 
-    // lock runtime-live state data
-    let mut state = m_state.lock().unwrap();
+    // serialize with other read-modify-writes of key, then lock
+    // runtime-live state data
+    let key_locks = key_locks(&m_state)?;
+    let _key_guard = key_locks.lock(&path.0, &key);
+    let mut state = lock_state_for(&m_state, &req)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
+    let idx = match state.name_idx.get(&path.0) {
         None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
-    }
+        Some(r_idx) => *r_idx,
+    };
 
-    // attempt to remove record from db, based on key (path elem 1)
-    match state.dbs[idx].db.del(in_msg.get_key()) {
-        Ok(optval) => match optval {
-            true => ok_json(json!({"result": true})),
-            false => err_not_found(), // db: value not found
-        },
-        Err(_e) => err_500(), // db: error
+    let sync = write_sync(&req, &query);
+    match state.dbs[idx].get_or_init(&key, &body, sync) {
+        Ok(val) => ok_binary(val),
+        Err(e) => err_db(e),
     }
 }
 
-/// DELETE data item.  key in URI path.  return ok as json response
-fn req_obj_delete(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// MERGE into data item. key in URI path, operand in HTTP payload, merged
+/// per the db's merge operator
+fn req_obj_merge(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
-    path: web::Path<(String, String)>,
+    (path, query, body): (
+        web::Path<(String, String)>,
+        web::Query<WriteQuery>,
+        web::Bytes,
+    ),
 ) -> Result<HttpResponse> {
-    // lock runtime-live state data
-    let mut state = m_state.lock().unwrap();
+    let key = match path_key(&req, &path.1) {
+        None => return err_bad_req(),
+        Some(key) => key,
+    };
+
+    // serialize with other read-modify-writes of key, then lock
+    // runtime-live state data
+    let key_locks = key_locks(&m_state)?;
+    let _key_guard = key_locks.lock(&path.0, &key);
+    let mut state = lock_state_for(&m_state, &req)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
+    let idx = match state.name_idx.get(&path.0) {
         None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
-    }
+        Some(r_idx) => *r_idx,
+    };
 
-    // attempt to remove record from db, based on key (path elem 1)
-    match state.dbs[idx].db.del(path.1.as_bytes()) {
-        Ok(optval) => match optval {
-            true => ok_json(json!({"result": true})),
-            false => err_not_found(), // db: value not found
-        },
-        Err(_e) => err_500(), // db: error
+    let sync = write_sync(&req, &query);
+    match state.dbs[idx].merge(&key, &body, sync) {
+        Ok(()) => ok_json(json!({"result": true})),
+        Err(e) => err_db(e),
     }
 }
 
-/// GET data item. key in URI path, returns value in HTTP payload.
-fn req_obj_get(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// POST blob. value in HTTP payload, key is the value's SHA-256 hash, in
+/// hex.  returns the key as json response, and its URI as Location
+fn req_blob_put(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
-    path: web::Path<(String, String)>,
+    (path, query, body): (web::Path<(String,)>, web::Query<WriteQuery>, web::Bytes),
 ) -> Result<HttpResponse> {
     // lock runtime-live state data
-    let state = m_state.lock().unwrap();
+    let mut state = lock_state_for(&m_state, &req)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
+    let idx = match state.name_idx.get(&path.0) {
         None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
-    }
+        Some(r_idx) => *r_idx,
+    };
 
-    // attempt to read record from db, based on key (path elem 1)
-    match state.dbs[idx].db.get(path.1.as_bytes()) {
-        Ok(optval) => match optval {
-            Some(val) => ok_binary(val.to_vec()),
-            None => err_not_found(), // db: value not found
-        },
-        Err(_e) => err_500(), // db: error
+    let sync = write_sync(&req, &query);
+    match state.dbs[idx].put_hashed(&body, sync) {
+        Ok(key) => {
+            // hex digits need no escaping, as a path element
+            let key = String::from_utf8_lossy(&key);
+            let location = format!("{}/obj/{}", req.path().trim_end_matches("/blobs"), key);
+            Ok(HttpResponse::build(StatusCode::CREATED)
+                .content_type("application/json")
+                .header(header::LOCATION, location)
+                .body(json!({"result": true, "key": key}).to_string()))
+        }
+        Err(e) => err_db(e),
     }
 }
 
-/// GET data item. key in HTTP payload, returns value in HTTP payload.
-fn req_get(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// BEGIN transaction.  returns transaction id as json response
+fn req_txn_begin(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
-    (path, body): (web::Path<(String,)>, web::Bytes),
+    path: web::Path<(String,)>,
 ) -> Result<HttpResponse> {
-    // decode protobuf msg containing key, into KeyRequest struct
-    let res = pbdec_key_req(&body);
-    if res.is_err() {
-        return err_bad_req();
-    }
-    let in_msg = res.unwrap();
-
     // lock runtime-live state data
-    let state = m_state.lock().unwrap();
+    let mut state = lock_state(&m_state)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
+    let idx = match state.name_idx.get(&path.0) {
         None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
-    }
+        Some(r_idx) => *r_idx,
+    };
 
-    // attempt to read record from db, based on key (http payload)
-    match state.dbs[idx].db.get(in_msg.get_key()) {
-        Ok(optval) => match optval {
-            Some(val) => ok_binary(val.to_vec()),
-            None => err_not_found(), // db: value not found
+    // open transaction, reclaiming any abandoned ones
+    state.expire_txns();
+    let id = state.next_txn_id;
+    state.next_txn_id += 1;
+    let expires = Instant::now() + state.txn_timeout;
+    state.txns.insert(
+        id,
+        TxnState {
+            db_idx: idx,
+            txn: db::txn::Txn::new(),
+            expires,
         },
-        Err(_e) => err_500(), // db: error
-    }
+    );
+
+    ok_json(json!({
+        "txn": id,
+        "timeout_secs": state.txn_timeout.as_secs(),
+    }))
 }
 
-/// atomic PUT of multiple data items. data items in HTTP payload. ret json ok.
-fn req_batch(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// GET data item within transaction.  txn id and key in URI path.
+fn req_txn_obj_get(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
-    (path, body): (web::Path<(String,)>, web::Bytes),
+    path: web::Path<(String, u64, String)>,
 ) -> Result<HttpResponse> {
-    // decode protobuf msg containing key/value pairs
-    let res = pbdec_batch_req(&body);
-    if res.is_err() {
-        return err_bad_req();
+    let key = match path_key(&req, &path.2) {
+        None => return err_bad_req(),
+        Some(key) => key,
+    };
+
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
     }
-    let in_msg = res.unwrap();
 
-    // build batch
-    let mut batch = db::api::Batch::default();
-    let updates = in_msg.reqs.to_vec();
-    for update in &updates {
-        if update.magic != UpdateRequest_MagicNum::MAGIC {
-            return err_bad_req();
-        }
-        if update.is_insert {
-            batch.insert(&update.key, &update.value);
-        } else {
-            batch.remove(&update.key);
+    // lookup database index and transaction (path elems 0, 1)
+    let idx = match state.txn_lookup(&path.0, path.1) {
+        None => return err_not_found(),
+        Some(idx) => idx,
+    };
+
+    // read record as of transaction, recording it in the read set
+    let state = &mut *state;
+    let txn = &mut state.txns.get_mut(&path.1).unwrap().txn;
+    match txn.get(&state.dbs[idx].db, &key) {
+        Ok(Some(val)) => ok_binary(val),
+        Ok(None) => {
+            if state.dbs[idx].cfg.missing_as_empty {
+                ok_binary(Vec::new())
+            } else {
+                err_not_found()
+            }
         }
+        Err(e) => err_db(e),
     }
+}
+
+/// PUT data item within transaction.  txn id and key in URI path, value
+/// in HTTP payload.  Buffered until commit.
+fn req_txn_obj_put(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    (path, body): (web::Path<(String, u64, String)>, web::Bytes),
+) -> Result<HttpResponse> {
+    let key = match path_key(&req, &path.2) {
+        None => return err_bad_req(),
+        Some(key) => key,
+    };
 
     // lock runtime-live state data
-    let mut state = m_state.lock().unwrap();
+    let mut state = lock_state(&m_state)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
-    // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
+    // lookup database index and transaction (path elems 0, 1)
+    let idx = match state.txn_lookup(&path.0, path.1) {
         None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
+        Some(idx) => idx,
+    };
+
+    // buffer write, applying the db's empty-value policy
+    let as_delete = body.is_empty() && state.dbs[idx].cfg.treat_empty_put_as_delete;
+    let txn = &mut state.txns.get_mut(&path.1).unwrap().txn;
+    if as_delete {
+        txn.del(&key);
+    } else {
+        txn.put(&key, &body);
     }
 
-    // attempt to store record in db, based on key (path elem 1)
-    match state.dbs[idx].db.apply_batch(&batch) {
-        Ok(_optval) => ok_json(json!({"result": true})),
-        Err(_e) => err_500(), // db: error
+    ok_json(json!({"result": true}))
+}
+
+/// DELETE data item within transaction.  txn id and key in URI path.
+/// Buffered until commit.
+fn req_txn_obj_delete(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, u64, String)>,
+) -> Result<HttpResponse> {
+    let key = match path_key(&req, &path.2) {
+        None => return err_bad_req(),
+        Some(key) => key,
+    };
+
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
     }
+
+    // lookup database index and transaction (path elems 0, 1)
+    if state.txn_lookup(&path.0, path.1).is_none() {
+        return err_not_found();
+    }
+
+    let txn = &mut state.txns.get_mut(&path.1).unwrap().txn;
+    txn.del(&key);
+
+    ok_json(json!({"result": true}))
 }
 
-/// PUT data item. key in URI path, value in HTTP payload.
-fn req_obj_put(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+/// COMMIT transaction.  409 if a key it read has since changed.  The
+/// transaction is closed, either way.
+fn req_txn_commit(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
-    (path, body): (web::Path<(String, String)>, web::Bytes),
+    path: web::Path<(String, u64)>,
 ) -> Result<HttpResponse> {
     // lock runtime-live state data
-    let mut state = m_state.lock().unwrap();
+    let mut state = lock_state_for(&m_state, &req)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
-    // lookup database index by name (path elem 0)
-    let idx: usize;
-    match state.name_idx.get(&path.0) {
+    // lookup database index and transaction (path elems 0, 1)
+    let idx = match state.txn_lookup(&path.0, path.1) {
         None => return err_not_found(),
-        Some(r_idx) => idx = *r_idx,
+        Some(idx) => idx,
+    };
+
+    // validate read set, and apply write set, atomically under state lock
+    let txn_state = state.txns.remove(&path.1).unwrap();
+    match txn_state.txn.commit(&mut state.dbs[idx].db) {
+        Ok(true) => ok_json(json!({"result": true})),
+        Ok(false) => err_conflict(),
+        Err(e) => err_db(e),
     }
+}
 
-    // attempt to store record in db, based on key (path elem 1)
-    match state.dbs[idx].db.put(path.1.as_bytes(), &body.to_vec()) {
-        Ok(_optval) => ok_json(json!({"result": true})),
-        Err(_e) => err_500(), // db: error
+/// ROLLBACK transaction, discarding its writes.
+fn req_txn_rollback(
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
+    req: HttpRequest,
+    path: web::Path<(String, u64)>,
+) -> Result<HttpResponse> {
+    // lock runtime-live state data
+    let mut state = lock_state(&m_state)?;
+    if state.debug {
+        println!("{:?}", DebugRequest(&req));
+    }
+
+    // lookup database index and transaction (path elems 0, 1)
+    if state.txn_lookup(&path.0, path.1).is_none() {
+        return err_not_found();
     }
+
+    state.txns.remove(&path.1);
+    ok_json(json!({"result": true}))
 }
 
 /// PUT data item. key/value in HTTP payload.
 fn req_put(
-    m_state: web::Data<Arc<Mutex<ServerState>>>,
+    m_state: web::Data<Arc<RwLock<ServerState>>>,
     req: HttpRequest,
-    (path, body): (web::Path<(String,)>, web::Bytes),
-) -> Result<HttpResponse> {
+    (path, query, body): (web::Path<(String,)>, web::Query<WriteQuery>, web::Bytes),
+) -> Result<EitherResponse> {
     // decode protobuf msg containing key, into KeyRequest struct
     let res = pbdec_update_req(&body);
     if res.is_err() {
-        return err_bad_req();
+        return err_bad_req().map(EitherResponse::A);
     }
     let in_msg = res.unwrap();
     if !in_msg.is_insert {
-        return err_bad_req();
+        return err_bad_req().map(EitherResponse::A);
     }
 
     // lock runtime-live state data
-    let mut state = m_state.lock().unwrap();
+    let mut state = lock_state_for(&m_state, &req)?;
     if state.debug {
-        println!("{:?}", req);
+        println!("{:?}", DebugRequest(&req));
     }
 
     // lookup database index by name (path elem 0)
     let idx: usize;
     match state.name_idx.get(&path.0) {
-        None => return err_not_found(),
+        None => return err_not_found().map(EitherResponse::A),
         Some(r_idx) => idx = *r_idx,
     }
 
+    // a retry of a put already applied succeeds, without re-applying
+    let idem_key = write_idem_key(&req);
+    if let Some(key) = &idem_key {
+        if state.idem_applied(idx, key) {
+            return ok_json(json!({"result": true})).map(EitherResponse::A);
+        }
+    }
+
     // attempt to store record in db, based on key
-    match state.dbs[idx].db.put(&in_msg.key, &in_msg.value) {
-        Ok(_optval) => ok_json(json!({"result": true})),
-        Err(_e) => err_500(), // db: error
+    let sync = write_sync(&req, &query);
+    let expire_at = match write_expire_at(&req) {
+        Err(_e) => return err_bad_req().map(EitherResponse::A),
+        Ok(expire_at) => expire_at,
+    };
+    // an idempotent put is recorded under the same lock hold as applied,
+    // so is never grouped
+    if idem_key.is_none() {
+        if let Some(group) = state.dbs[idx].put_group(&in_msg.value, expire_at) {
+            drop(state);
+            let (key, val) = (in_msg.key, web::Bytes::from(in_msg.value));
+            return Ok(EitherResponse::B(put_grouped_async(
+                &m_state, group, idx, key, val, sync,
+            )));
+        }
+    }
+    match state.dbs[idx].put(&in_msg.key, &in_msg.value, sync, expire_at) {
+        Ok(_optval) => {
+            if let Some(key) = idem_key {
+                state.idem_record(idx, key);
+            }
+            ok_json(json!({"result": true}))
+        }
+        Err(e) => err_db(e),
     }
+    .map(EitherResponse::A)
 }
 
 /// 404 handler
@@ -751,8 +5892,57 @@ fn p404() -> Result<HttpResponse> {
     err_not_found()
 }
 
+// resource at path, serving each route for its method.  other methods get
+// 405 Method Not Allowed, with an Allow header listing those served
+fn resource(path: &str, routes: Vec<(Method, Route)>) -> Resource {
+    let mut allow: Vec<&str> = routes
+        .iter()
+        .map(|(method, _route)| method.as_str())
+        .collect();
+    allow.dedup(); // a method may have several routes, each guarded
+    let allow = allow.join(", ");
+    let mut resource = web::resource(path);
+    for (method, route) in routes {
+        resource = resource.route(route.method(method));
+    }
+    resource.default_service(web::route().to(move || {
+        HttpResponse::MethodNotAllowed()
+            .header(header::ALLOW, allow.as_str())
+            .finish()
+    }))
+}
+
+fn log_access<B: MessageBody>(
+    log: &accesslog::AccessLog,
+    res: &ServiceResponse<B>,
+    time: DateTime<Local>,
+    duration: Duration,
+) {
+    let req = res.request();
+    let method = req.method().to_string();
+    let version = format!("{:?}", req.version());
+    let bytes = match res.response().body().size() {
+        BodySize::Sized(n) => n as u64,
+        BodySize::Sized64(n) => n,
+        _ => 0,
+    };
+
+    log.write(&accesslog::AccessLogEntry {
+        time,
+        peer: req.peer_addr(),
+        method: &method,
+        path: req.path(),
+        version: &version,
+        db: req.match_info().get("db"),
+        key_len: req.match_info().get("key").map(|k| k.len()),
+        status: res.status().as_u16(),
+        bytes,
+        duration,
+    });
+}
+
 fn main() -> io::Result<()> {
-    env::set_var("RUST_LOG", "actix_web=debug");
+    env::set_var("RUST_LOG", "actix_web=debug,kvdbd=warn");
     env_logger::init();
 
     let backend_state = register_backends();
@@ -777,7 +5967,7 @@ fn main() -> io::Result<()> {
             clap::Arg::with_name("config")
                 .short("c")
                 .long("config")
-                .value_name("JSON-FILE")
+                .value_name("FILE")
                 .help(&help_config)
                 .takes_value(true),
         )
@@ -807,13 +5997,21 @@ fn main() -> io::Result<()> {
         );
     }
 
+    cli_app = cli_app.subcommands(inspect::subcommands());
+
     // parse command line
     let cli_matches = cli_app.get_matches();
 
-    // configure based on CLI options
-    let bind_addr = cli_matches.value_of("bind-addr").unwrap_or(DEF_BIND_ADDR);
-    let bind_port = cli_matches.value_of("bind-port").unwrap_or(DEF_BIND_PORT);
-    let bind_pair = format!("{}:{}", bind_addr, bind_port);
+    // offline inspection: run the subcommand and exit, without serving
+    if let (name, Some(args)) = cli_matches.subcommand() {
+        let drivers: Vec<(&str, &dyn db::api::Driver)> = backend_state
+            .backends
+            .iter()
+            .map(|(be_name, be_info)| (be_name.as_str(), &*be_info.driver))
+            .collect();
+        process::exit(inspect::run(&drivers, name, args));
+    }
+
     let server_hdr = format!("{}/{}", APPNAME, VERSION);
 
     // init server state
@@ -822,111 +6020,757 @@ fn main() -> io::Result<()> {
 
     // determine if zeroconf is requested
     let mut zeroconf = false;
-    let mut server_cfg = ServerConfig {
-        debug: false,
-        ssl: SslConfig::new(),
-        databases: vec![],
-    };
+    let mut server_cfg = ServerConfig::default();
     for (be_name, _be_info) in &backend_state.backends {
         // if matched, build single-db static configuration
         if cli_matches.is_present(be_name) {
             server_cfg = ServerConfig {
-                debug: false,
-                ssl: SslConfig::new(),
                 databases: vec![DbConfig {
                     name: String::from("db"),
                     path: cli_matches.value_of(be_name).unwrap().to_string(),
                     driver: be_name.clone(),
                     read_only: false,
+                    replica: false,
+                    missing_as_empty: false,
+                    key_case: String::new(),
+                    ttl_sweep_secs: 0,
+                    default_ttl_secs: None,
+                    auto_compact_interval_secs: 0,
+                    default_cache_control: None,
+                    max_mget_keys: DEF_MAX_MGET_KEYS,
+                    max_iter_keys: db::api::MAX_ITER_KEYS,
+                    max_page_bytes: None,
+                    count_cache_secs: 0,
+                    treat_empty_put_as_delete: false,
+                    slow_op_threshold_ms: None,
+                    op_timeout_ms: None,
+                    change_log_len: None,
+                    max_keys: None,
+                    max_total_bytes: None,
+                    eviction: String::new(),
+                    write_failure_threshold: None,
+                    value_metadata: false,
+                    compress_values: false,
+                    encryption_key_file: None,
+                    verify_checksums: false,
+                    track_modified: false,
+                    track_versions: false,
+                    track_hot_keys: false,
+                    sync_writes: false,
+                    max_key_len: db::api::DEF_MAX_KEY_LEN,
+                    max_value_len: db::api::DEF_MAX_VALUE_LEN,
+                    group_commit_window_us: 0,
+                    group_commit_max_ops: DEF_GROUP_COMMIT_MAX_OPS,
+                    disabled_ops: Vec::new(),
+                    merge: String::new(),
                 }],
+                ..ServerConfig::default()
             };
             zeroconf = true;
             break;
         }
     }
 
-    // read JSON configuration file, unless already configured
+    // read JSON or TOML configuration file, unless already configured
+    let mut cfg_path = None;
     if !zeroconf {
         let cfg_fn = cli_matches.value_of("config").unwrap_or(DEF_CFG_FN);
-        let cfg_text = fs::read_to_string(cfg_fn)?;
-        server_cfg = serde_json::from_str(&cfg_text)?;
+        server_cfg = match ServerConfig::from_file(cfg_fn) {
+            Ok(cfg) => cfg,
+            Err(e) => {
+                println!("config: {cfg_fn}: {e}");
+                process::exit(1);
+            }
+        };
+        cfg_path = Some(cfg_fn.to_string());
+    }
+
+    // configure listen address; CLI options override config file
+    let bind_addr = match cli_matches.value_of("bind-addr") {
+        Some(addr) => addr.to_string(),
+        None => server_cfg
+            .listen
+            .addr
+            .clone()
+            .unwrap_or_else(|| String::from(DEF_BIND_ADDR)),
+    };
+    let bind_port = match cli_matches.value_of("bind-port") {
+        Some(port) => port.to_string(),
+        None => match server_cfg.listen.port {
+            Some(port) => port.to_string(),
+            None => String::from(DEF_BIND_PORT),
+        },
+    };
+    let bind_pair = format!("{bind_addr}:{bind_port}");
+    // fail now, rather than when the server binds, after opening the dbs
+    if bind_pair.to_socket_addrs().is_err() {
+        println!("config: Invalid listen address {bind_pair}.");
+        process::exit(1);
     }
 
+    // open audit log, if configured, before the dbs whose writes it logs
+    let audit_log = match &server_cfg.audit_log_path {
+        None => None,
+        Some(log_path) => Some(Arc::new(AuditLog::open(
+            log_path,
+            server_cfg.audit_log_values,
+        )?)),
+    };
+
     // configure and open databases
     for db_cfg in &server_cfg.databases {
-        // setup backend config
-        let db_config = db::api::ConfigBuilder::new()
-            .path(db_cfg.path.clone())
-            .read_only(db_cfg.read_only)
-            .build();
+        let key_case = match db_cfg.key_case_mode() {
+            Some(key_case) => key_case,
+            None => {
+                println!(
+                    "config: Unsupported key_case {} specified.",
+                    db_cfg.key_case
+                );
+                process::exit(1);
+            }
+        };
+
+        let watch = Arc::new(WatchHub::new());
+        let db_config = match db_open_config(db_cfg, key_case, &watch, audit_log.as_ref()) {
+            Ok(db_config) => db_config,
+            Err(e) => {
+                println!("config: Database {} open failed: {}", db_cfg.name, e);
+                process::exit(1);
+            }
+        };
 
         // verify this is a known backend
         if !backend_state.backends.contains_key(&db_cfg.driver) {
-            println!("config: Unsupported db driver {} specified.", db_cfg.driver);
+            println!(
+                "config: Unsupported db driver {} specified; compiled-in drivers: {}.",
+                db_cfg.driver,
+                db::DRIVERS.join(", ")
+            );
             process::exit(1);
         }
 
         let backend = &backend_state.backends[&db_cfg.driver];
 
+        let db = match backend.driver.start_db(db_config) {
+            Ok(db) => db,
+            Err(e) => {
+                println!("config: Database {} open failed: {}", db_cfg.name, e);
+                process::exit(1);
+            }
+        };
+
         // add db to server state
         let next_idx = dbs.len();
         name_idx.insert(db_cfg.name.clone(), next_idx);
-        dbs.push(DbState {
-            cfg: db_cfg.clone(),
-            db: backend.driver.start_db(db_config).unwrap(),
-        });
+        dbs.push(DbState::new(db_cfg.clone(), db, watch));
+    }
+
+    // dbs created at runtime must have a known backend, too
+    if let Some(runtime_dbs) = &server_cfg.runtime_dbs {
+        if !backend_state.backends.contains_key(&runtime_dbs.driver) {
+            println!(
+                "config: Unsupported runtime_dbs driver {} specified; compiled-in drivers: {}.",
+                runtime_dbs.driver,
+                db::DRIVERS.join(", ")
+            );
+            process::exit(1);
+        }
+    }
+
+    // API routes are mounted beneath api_base; "/" is the same as ""
+    let api_base = server_cfg.api_base_path();
+    if !api_base.is_empty() && !api_base.starts_with('/') {
+        println!(
+            "config: api_base {} must begin with '/'.",
+            server_cfg.api_base
+        );
+        process::exit(1);
     }
 
-    let srv_state = Arc::new(Mutex::new(ServerState {
-        debug: false,
+    // h2 is negotiated via ALPN under TLS; cleartext h2c only if enabled
+    let use_tls = server_cfg.ssl.enabled();
+    let http2 = use_tls || server_cfg.http2_cleartext;
+
+    // operations refused per db, checked before routing
+    let disabled_ops: Arc<HashMap<String, Vec<OpKind>>> = Arc::new(
+        server_cfg
+            .databases
+            .iter()
+            .filter(|db_cfg| !db_cfg.disabled_ops.is_empty())
+            .map(|db_cfg| (db_cfg.name.clone(), db_cfg.disabled_ops.clone()))
+            .collect(),
+    );
+
+    // open unknown dbs named by requests, if runtime_dbs allows
+    let create_on_use = server_cfg
+        .runtime_dbs
+        .as_ref()
+        .is_some_and(|runtime_dbs| runtime_dbs.create_on_use);
+
+    // shared secret required of API requests, if any
+    let api_key: Option<Arc<str>> = server_cfg.api_key.as_deref().map(Arc::from);
+
+    // API request rate allowed each client, if limited
+    let rate_limiter = match server_cfg.rate_limit_rps {
+        0 => None,
+        rps => Some(Arc::new(RateLimiter::new(rps))),
+    };
+
+    let n_connections = Arc::new(AtomicUsize::new(0));
+    let max_connections = server_cfg.max_connections;
+    let max_request_bytes = server_cfg.max_request_bytes;
+    let drain = Arc::new(AtomicU8::new(DRAIN_NONE));
+    let inflight = Arc::new(InFlight::new());
+    let metrics = match server_cfg.metrics_enabled {
+        true => Some(Arc::new(Metrics::new())),
+        false => None,
+    };
+
+    let srv_state = Arc::new(RwLock::new(ServerState {
+        debug: server_cfg.debug,
+        api_base: api_base.clone(),
+        http2,
+        binary_port: server_cfg.binary_port,
         name_idx: name_idx,
         dbs: dbs,
+        n_connections: Arc::clone(&n_connections),
+        max_connections: server_cfg.max_connections,
+        max_request_bytes,
+        drain: Arc::clone(&drain),
+        inflight: Arc::clone(&inflight),
+        metrics: metrics.clone(),
+        txns: HashMap::new(),
+        next_txn_id: 1,
+        txn_timeout: Duration::from_secs(server_cfg.txn_timeout_secs),
+        scans: HashMap::new(),
+        next_scan_id: 1,
+        scan_timeout: Duration::from_secs(server_cfg.scan_timeout_secs),
+        idem_keys: HashMap::new(),
+        key_locks: Arc::new(KeyLocks::new()),
+        recover_poisoned_lock: server_cfg.recover_poisoned_lock,
+        audit: audit_log.clone(),
+        cfg_path: cfg_path.clone(),
+        cfg: server_cfg.clone(),
     }));
 
+    // start background expired-key sweepers
+    let mut sweepers = Vec::new();
+    for (idx, db_cfg) in server_cfg.databases.iter().enumerate() {
+        if db_cfg.ttl_sweep_secs > 0 {
+            let interval = Duration::from_secs(db_cfg.ttl_sweep_secs);
+            sweepers.push(start_sweeper(Arc::clone(&srv_state), idx, interval));
+        }
+    }
+
+    // start background compactors
+    let mut compactors = Vec::new();
+    for (idx, db_cfg) in server_cfg.databases.iter().enumerate() {
+        if db_cfg.auto_compact_interval_secs > 0 {
+            let interval = Duration::from_secs(db_cfg.auto_compact_interval_secs);
+            compactors.push(start_compactor(Arc::clone(&srv_state), idx, interval));
+        }
+    }
+
+    let scan_reaper = start_scan_reaper(Arc::clone(&srv_state));
+    let audit_flusher = audit_log.as_ref().map(start_audit_flusher);
+
+    // start binary protocol listener, if configured
+    let binary_listener = match server_cfg.binary_port {
+        None => None,
+        Some(port) => {
+            let binary_pair = format!("{bind_addr}:{port}");
+            println!("Starting binary protocol server: {binary_pair}");
+            let listener = TcpListener::bind(&binary_pair)?;
+            let admission = BinaryAdmission {
+                api_key: api_key.clone(),
+                rate_limiter: rate_limiter.clone(),
+                n_connections: Arc::clone(&n_connections),
+                max_connections,
+            };
+            Some(start_binary_listener(
+                Arc::clone(&srv_state),
+                listener,
+                admission,
+            )?)
+        }
+    };
+
+    // open access log, if configured
+    let access_log = match &server_cfg.access_log_path {
+        None => None,
+        Some(log_path) => {
+            let log_fmt = match accesslog::LogFormat::from_name(&server_cfg.access_log_format) {
+                Some(f) => f,
+                None => {
+                    println!(
+                        "config: Unsupported access_log_format {} specified.",
+                        server_cfg.access_log_format
+                    );
+                    process::exit(1);
+                }
+            };
+            Some(Arc::new(accesslog::AccessLog::open(log_path, log_fmt)?))
+        }
+    };
+
+    // export a trace span per request, if built with tracing
+    #[cfg(feature = "otel")]
+    let (tracer, otel_exporter) = otel::tracer_from_env().unzip();
+
     // configure web server
     let sys = actix_rt::System::new(APPNAME);
+    let exit_state = Arc::clone(&srv_state);
 
     let app = move || {
-        App::new()
+        let access_log = access_log.clone();
+        let disabled_ops = Arc::clone(&disabled_ops);
+        let drain = Arc::clone(&drain);
+        let inflight = Arc::clone(&inflight);
+        let metrics = metrics.clone();
+        let runtime_state = match create_on_use {
+            true => Some(Arc::clone(&srv_state)),
+            false => None,
+        };
+        let op_api_base = api_base.clone();
+        let auth_api_base = api_base.clone();
+        let api_key = api_key.clone();
+        let rate_limiter = rate_limiter.clone();
+        let api_path = |path: &str| format!("{api_base}{path}");
+
+        let app = App::new()
             // pass application state to each handler
             .data(Arc::clone(&srv_state))
+            // bound each buffered request body, counted as it is read,
+            // whatever its Content-Length:  past the bound, 413
+            .data(web::PayloadConfig::new(max_request_bytes))
+            // refuse operations disabled for the db, and writes while
+            // draining, regardless of handler; list the rest as in flight
+            .wrap_fn(move |req, srv| {
+                let db_op = request_op(req.method(), req.path(), &op_api_base)
+                    .filter(|(db_name, _op)| !ADMIN_PATHS.contains(db_name));
+                if let (Some(m_state), Some((db_name, _op))) = (&runtime_state, db_op) {
+                    open_on_use(m_state, db_name);
+                }
+                let (disabled, drained) = match db_op {
+                    None => (false, false),
+                    Some((db_name, op)) => (
+                        disabled_ops
+                            .get(db_name)
+                            .is_some_and(|ops| ops.contains(&op)),
+                        op.is_write() && drain.load(Ordering::SeqCst) == DRAIN_WRITES,
+                    ),
+                };
+                let started = Instant::now();
+                let record = |status: StatusCode| {
+                    if let (Some(metrics), Some((_db_name, op))) = (&metrics, db_op) {
+                        metrics.record(op, status.as_u16(), started.elapsed());
+                    }
+                };
+                if disabled {
+                    record(StatusCode::FORBIDDEN);
+                    return Either::A(ok(req.into_response(resp_forbidden())));
+                } else if drained {
+                    record(StatusCode::SERVICE_UNAVAILABLE);
+                    return Either::A(ok(req.into_response(resp_draining())));
+                }
+
+                let guard = db_op.map(|(db_name, op)| {
+                    let op = InFlightOp {
+                        op,
+                        db: db_name.to_string(),
+                        method: req.method().clone(),
+                        key_len: request_key_len(req.path(), &op_api_base, db_name),
+                        started: Instant::now(),
+                    };
+                    InFlight::start(&inflight, op)
+                });
+                let metrics = metrics.clone();
+                let op = db_op.map(|(_db_name, op)| op);
+                Either::B(srv.call(req).then(move |res| {
+                    drop(guard);
+                    if let (Some(metrics), Some(op)) = (metrics, op) {
+                        let status = match &res {
+                            Ok(res) => res.status(),
+                            Err(_e) => StatusCode::INTERNAL_SERVER_ERROR,
+                        };
+                        metrics.record(op, status.as_u16(), started.elapsed());
+                    }
+                    res
+                }))
+            })
+            // refuse h2c prior-knowledge connections, unless enabled;
+            // connections beyond max_connections; API requests over the
+            // client's rate_limit_rps; and API requests not bearing the
+            // api_key, if one is set
+            .wrap_fn(move |req, srv| {
+                let over_limit = match req.extensions().get::<Rc<ConnGuard>>() {
+                    None => false,
+                    Some(conn) => conn.over_limit,
+                };
+                let is_api = req
+                    .path()
+                    .strip_prefix(auth_api_base.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'));
+                let admitted = match &api_key {
+                    Some(key) if is_api => auth::bearer_admits(
+                        req.headers()
+                            .get(header::AUTHORIZATION)
+                            .map(|hdr| hdr.as_bytes()),
+                        key,
+                    ),
+                    _ => true,
+                };
+                let throttled = match (&rate_limiter, req.peer_addr()) {
+                    (Some(limiter), Some(peer)) if is_api => {
+                        limiter.check(peer.ip(), Instant::now()).err()
+                    }
+                    _ => None,
+                };
+                if over_limit {
+                    let res = HttpResponse::ServiceUnavailable().force_close().finish();
+                    Either::A(ok(req.into_response(res)))
+                } else if !http2 && req.version() == Version::HTTP_2 {
+                    let res = HttpResponse::build(StatusCode::HTTP_VERSION_NOT_SUPPORTED).finish();
+                    Either::A(ok(req.into_response(res)))
+                } else if let Some(retry_after) = throttled {
+                    let res = resp_too_many_requests(retry_after);
+                    Either::A(ok(req.into_response(res)))
+                } else if !admitted {
+                    Either::A(ok(req.into_response(resp_unauthorized())))
+                } else {
+                    Either::B(srv.call(req))
+                }
+            })
             // apply default headers
             .wrap(middleware::DefaultHeaders::new().header("Server", server_hdr.to_string()))
+            // responses to mutating requests must never be cached
+            .wrap_fn(|req, srv| {
+                let mutating = req.method() != Method::GET && req.method() != Method::HEAD;
+                srv.call(req).map(move |mut res| {
+                    if mutating {
+                        res.headers_mut().insert(
+                            header::CACHE_CONTROL,
+                            header::HeaderValue::from_static("no-store"),
+                        );
+                    }
+                    res
+                })
+            })
+            // append each request to access log, if enabled
+            .wrap_fn(move |req, srv| {
+                let time = Local::now();
+                let start = Instant::now();
+                let access_log = access_log.clone();
+                srv.call(req).map(move |res| {
+                    if let Some(log) = &access_log {
+                        log_access(log, &res, time, start.elapsed());
+                    }
+                    res
+                })
+            });
+
+        // trace each request, if enabled
+        #[cfg(feature = "otel")]
+        let app = {
+            let tracer = tracer.clone();
+            let api_base = api_base.clone();
+            app.wrap_fn(move |req, srv| {
+                let span = tracer.as_ref().and_then(|t| t.start(req.headers()));
+                let tracer = tracer.clone();
+                let api_base = api_base.clone();
+                srv.call(req).map(move |res| {
+                    if let (Some(tracer), Some(span)) = (tracer, span) {
+                        tracer.end_response(span, &res, &api_base);
+                    }
+                    res
+                })
+            })
+        };
+
+        app
             // enable logger - always register actix-web Logger middleware last
             .wrap(middleware::Logger::default())
             // register our routes
-            .service(req_index)
-            .service(web::resource("/api/{db}/batch").route(web::post().to(req_batch)))
-            .service(web::resource("/api/{db}/clear").route(web::post().to(req_clear)))
-            .service(web::resource("/api/{db}/del").route(web::post().to(req_del)))
-            .service(web::resource("/api/{db}/get").route(web::post().to(req_get)))
-            .service(web::resource("/api/{db}/keys.json").route(web::get().to(req_keys_json)))
-            .service(web::resource("/api/{db}/keys").route(web::post().to(req_keys)))
-            .service(
-                web::resource("/api/{db}/obj/{key}")
-                    .route(web::get().to(req_obj_get))
-                    .route(web::put().to(req_obj_put))
-                    .route(web::delete().to(req_obj_delete)),
-            )
-            .service(web::resource("/api/{db}/put").route(web::post().to(req_put)))
-            .service(web::resource("/api/{db}/stat").route(web::get().to(req_stat)))
-            .service(web::resource("/api/{db}/stat.json").route(web::get().to(req_stat_json)))
-            // default
-            .default_service(
-                // 404 for GET request
-                web::resource("")
-                    .route(web::get().to(p404))
-                    // all requests that are not `GET` -- redundant?
-                    .route(
+            .service(resource(
+                "/",
+                vec![(Method::GET, web::route().to(req_index))],
+            ))
+            .service(resource(
+                "/_stats",
+                vec![(Method::GET, web::route().to(req_stats))],
+            ))
+            .service(resource(
+                "/metrics",
+                vec![(Method::GET, web::route().to(req_metrics))],
+            ))
+            .service(resource(
+                "/_ready",
+                vec![(Method::GET, web::route().to(req_ready))],
+            ))
+            .service(resource(
+                "/health",
+                vec![(Method::GET, web::route().to(req_health))],
+            ))
+            .service(resource(
+                "/health/ready",
+                vec![(Method::GET, web::route().to(req_health_ready))],
+            ))
+            .service(resource(
+                &api_path("/"),
+                vec![(Method::GET, web::route().to(req_db_list))],
+            ))
+            .service(resource(
+                &api_path("/_drain"),
+                vec![(Method::POST, web::route().to(req_drain))],
+            ))
+            .service(resource(
+                &api_path("/_inflight"),
+                vec![(Method::GET, web::route().to(req_inflight))],
+            ))
+            .service(resource(
+                &api_path("/_reload"),
+                vec![(Method::POST, web::route().to(req_reload))],
+            ))
+            .service(resource(
+                &api_path("/_reopen"),
+                vec![(Method::POST, web::route().to(req_reopen))],
+            ))
+            .service(resource(
+                &api_path("/_undrain"),
+                vec![(Method::POST, web::route().to(req_undrain))],
+            ))
+            .service(resource(
+                &api_path("/_multibatch"),
+                vec![(Method::POST, web::route().to(req_multibatch))],
+            ))
+            .service(resource(
+                &api_path("/{db}"),
+                vec![
+                    (Method::PUT, web::route().to(req_db_create)),
+                    (Method::POST, web::route().to(req_obj_alloc)),
+                    (Method::DELETE, web::route().to(req_clear)),
+                ],
+            ))
+            .service(resource(
+                &api_path("/{db}/batch"),
+                vec![(Method::POST, web::route().to(req_batch))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_cset"),
+                vec![(Method::POST, web::route().to(req_cset))],
+            ))
+            .service(resource(
+                &api_path("/{db}/blobs"),
+                vec![(Method::POST, web::route().to(req_blob_put))],
+            ))
+            .service(resource(
+                &api_path("/{db}/clear"),
+                vec![(Method::POST, web::route().to(req_clear))],
+            ))
+            .service(resource(
+                &api_path("/{db}/del"),
+                vec![(Method::POST, web::route().to(req_del))],
+            ))
+            .service(resource(
+                &api_path("/{db}/get"),
+                vec![(Method::POST, web::route().to(req_get))],
+            ))
+            .service(resource(
+                &api_path("/{db}/keys.json"),
+                vec![(Method::GET, web::route().to(req_keys_json))],
+            ))
+            .service(resource(
+                &api_path("/{db}/keys"),
+                vec![
+                    (Method::HEAD, web::route().to(req_keys_head)),
+                    (Method::POST, web::route().to(req_keys)),
+                    (Method::DELETE, web::route().to(req_delete_prefix)),
+                ],
+            ))
+            .service(resource(
+                &api_path("/{db}/obj/{key}"),
+                vec![
+                    (Method::GET, web::route().to(req_obj_get)),
+                    (Method::HEAD, web::route().to(req_obj_head)),
+                    (Method::POST, web::route().to(req_obj_post)),
+                    (
+                        Method::PUT,
                         web::route()
-                            .guard(guard::Not(guard::Get()))
-                            .to(HttpResponse::MethodNotAllowed),
+                            .guard(guard::fn_guard(streamed_put))
+                            .to(req_obj_put_stream),
                     ),
-            )
+                    (Method::PUT, web::route().to(req_obj_put)),
+                    (Method::DELETE, web::route().to(req_obj_delete)),
+                ],
+            ))
+            .service(resource(
+                &api_path("/{db}/obj/{key}/init"),
+                vec![(Method::POST, web::route().to(req_obj_init))],
+            ))
+            .service(resource(
+                &api_path("/{db}/obj/{key}/append"),
+                vec![(Method::POST, web::route().to(req_obj_append))],
+            ))
+            .service(resource(
+                &api_path("/{db}/obj/{key}/merge"),
+                vec![(Method::POST, web::route().to(req_obj_merge))],
+            ))
+            .service(resource(
+                &api_path("/{db}/put"),
+                vec![(Method::POST, web::route().to(req_put))],
+            ))
+            .service(resource(
+                &api_path("/{db}/flush"),
+                vec![(Method::POST, web::route().to(req_flush))],
+            ))
+            .service(resource(
+                &api_path("/{db}/compact"),
+                vec![(Method::POST, web::route().to(req_compact))],
+            ))
+            .service(resource(
+                &api_path("/{db}/stat"),
+                vec![(Method::GET, web::route().to(req_stat))],
+            ))
+            .service(resource(
+                &api_path("/{db}/stat.json"),
+                vec![(Method::GET, web::route().to(req_stat_json))],
+            ))
+            .service(resource(
+                &api_path("/{db}/stats"),
+                vec![(Method::GET, web::route().to(req_db_stats))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_changes"),
+                vec![(Method::GET, web::route().to(req_changes))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_count"),
+                vec![(Method::GET, web::route().to(req_count))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_entries"),
+                vec![(Method::GET, web::route().to(req_entries))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_exists"),
+                vec![(Method::POST, web::route().to(req_exists))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_export"),
+                vec![(Method::GET, web::route().to(req_export))],
+            ))
+            .service(resource(
+                &api_path("/{db}/dump"),
+                vec![(Method::GET, web::route().to(req_dump))],
+            ))
+            .service(resource(
+                &api_path("/{db}/watch"),
+                vec![(Method::GET, web::route().to(req_watch))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_import"),
+                vec![(Method::POST, web::route().to(req_import))],
+            ))
+            .service(resource(
+                &api_path("/{db}/restore"),
+                vec![(Method::POST, web::route().to(req_restore))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_index"),
+                vec![(Method::POST, web::route().to(req_index_create))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_index/{name}"),
+                vec![(Method::GET, web::route().to(req_index_lookup))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_histogram"),
+                vec![(Method::GET, web::route().to(req_histogram))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_hotkeys"),
+                vec![(Method::GET, web::route().to(req_hot_keys))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_mget"),
+                vec![(Method::POST, web::route().to(req_mget))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_mdel"),
+                vec![(Method::POST, web::route().to(req_mdel))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_keys"),
+                vec![(Method::GET, web::route().to(req_keys_page))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_scan"),
+                vec![(Method::GET, web::route().to(req_scan))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_scan/open"),
+                vec![(Method::GET, web::route().to(req_scan_open))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_scan/{scan}/next"),
+                vec![(Method::GET, web::route().to(req_scan_next))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_scan/{scan}/close"),
+                vec![(Method::POST, web::route().to(req_scan_close))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_size"),
+                vec![(Method::GET, web::route().to(req_size))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_sweep"),
+                vec![(Method::POST, web::route().to(req_sweep))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_swap"),
+                vec![(Method::POST, web::route().to(req_swap))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_txn/begin"),
+                vec![(Method::POST, web::route().to(req_txn_begin))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_txn/{txn}/obj/{key}"),
+                vec![
+                    (Method::GET, web::route().to(req_txn_obj_get)),
+                    (Method::PUT, web::route().to(req_txn_obj_put)),
+                    (Method::DELETE, web::route().to(req_txn_obj_delete)),
+                ],
+            ))
+            .service(resource(
+                &api_path("/{db}/_txn/{txn}/commit"),
+                vec![(Method::POST, web::route().to(req_txn_commit))],
+            ))
+            .service(resource(
+                &api_path("/{db}/_txn/{txn}/rollback"),
+                vec![(Method::POST, web::route().to(req_txn_rollback))],
+            ))
+            // default:  404 for paths matching no resource, by any method;
+            // a resource answers methods it does not serve with 405
+            .default_service(web::route().to(p404))
     };
 
+    // each worker stops accepting at max_connections, leaving further
+    // clients queued in the listen backlog; connections accepted beyond
+    // max_connections in total are refused with 503, then closed
+    // at shutdown, requests in progress are given shutdown_grace_secs to
+    // finish; signals are handled below, rather than by the server
+    let mut server = actix_server::Server::build()
+        .disable_signals()
+        .system_exit()
+        .shutdown_timeout(server_cfg.shutdown_grace_secs);
+    if let Some(max) = max_connections {
+        server = server.maxconn(max);
+    }
+
     // if TLS key/cert present in config, run in TLS mode
-    if server_cfg.ssl.private_key_path.len() > 0 && server_cfg.ssl.cert_chain_path.len() > 0 {
+    if use_tls {
         let mut builder = SslAcceptor::mozilla_intermediate(SslMethod::tls()).unwrap();
         builder
             .set_private_key_file(server_cfg.ssl.private_key_path, SslFiletype::PEM)
@@ -934,17 +6778,101 @@ fn main() -> io::Result<()> {
         builder
             .set_certificate_chain_file(server_cfg.ssl.cert_chain_path)
             .unwrap();
+
+        // negotiate h2, or http/1.1 for older clients
+        builder.set_alpn_select_callback(|_, protos| {
+            const H2: &[u8] = b"\x02h2";
+            if protos.windows(3).any(|window| window == H2) {
+                Ok(b"h2")
+            } else {
+                Err(AlpnError::NOACK)
+            }
+        });
+        builder.set_alpn_protos(b"\x08http/1.1\x02h2")?;
+        let acceptor = OpensslAcceptor::new(builder.build());
+
         println!("Starting https server: {}", bind_pair);
-        HttpServer::new(app)
-            .bind_ssl(bind_pair.to_string(), builder)?
-            .start();
+        server = server.bind(APPNAME, bind_pair.to_string(), move || {
+            let n_connections = Arc::clone(&n_connections);
+            acceptor.clone().map_err(SslError::Ssl).and_then(
+                HttpService::build()
+                    .keep_alive(KEEP_ALIVE_SECS)
+                    .on_connect(move |_| ConnGuard::new(&n_connections, max_connections))
+                    .finish(app())
+                    .map_err(SslError::Service)
+                    .map_init_err(|_| ()),
+            )
+        })?;
 
     // otherwise, plain ole HTTP
     } else {
         println!("Starting http server: {}", bind_pair);
-        HttpServer::new(app).bind(bind_pair.to_string())?.start();
+        server = server.bind(APPNAME, bind_pair.to_string(), move || {
+            let n_connections = Arc::clone(&n_connections);
+            HttpService::build()
+                .keep_alive(KEEP_ALIVE_SECS)
+                .on_connect(move |_| ConnGuard::new(&n_connections, max_connections))
+                .finish(app())
+        })?;
+    }
+    let server = server.start();
+
+    // on SIGTERM or SIGINT, stop accepting connections, and let requests
+    // in progress finish; a second signal stops at once
+    let signals = Signals::new([signal_hook::SIGTERM, signal_hook::SIGINT])?;
+    let sig_state = Arc::clone(&exit_state);
+    thread::spawn(move || {
+        let mut graceful = true;
+        for sig in signals.forever() {
+            info!("signal {sig} received, stopping");
+
+            // end watch streams, which would otherwise hold their
+            // connections open for the whole grace period
+            if let Ok(state) = lock_shared_state(&sig_state) {
+                for db_state in &state.dbs {
+                    db_state.watch.close();
+                }
+            }
+            let _done = server.stop(graceful);
+            graceful = false;
+        }
+    });
+
+    // start event loop, run until shutdown signal
+    let mut res = sys.run();
+
+    // stop binary protocol listener
+    if let Some((tx, handle)) = binary_listener {
+        drop(tx);
+        handle.join().unwrap();
+    }
+
+    // stop background sweepers and compactors
+    for (tx, handle) in sweepers.into_iter().chain(compactors) {
+        drop(tx);
+        handle.join().unwrap();
+    }
+    let (tx, handle) = scan_reaper;
+    drop(tx);
+    handle.join().unwrap();
+    if let Some((tx, handle)) = audit_flusher {
+        drop(tx);
+        handle.join().unwrap();
+    }
+
+    // make acknowledged writes durable before exit, and their audit
+    if let Err(e) = flush_dbs(&exit_state) {
+        res = res.and(Err(e));
+    }
+    if let Some(log) = &audit_log {
+        log.flush();
+    }
+
+    // export spans still queued
+    #[cfg(feature = "otel")]
+    if let Some(exporter) = otel_exporter {
+        exporter.shutdown();
     }
 
-    // start event loop, run forever
-    sys.run()
+    res
 }
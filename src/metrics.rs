@@ -0,0 +1,128 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::config::OpKind;
+
+// upper bounds of request latency histogram buckets, in seconds
+const LATENCY_BUCKETS: [f64; 12] = [
+    0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0,
+];
+
+// counts for one operation class
+#[derive(Default)]
+struct OpMetrics {
+    by_status: BTreeMap<u16, u64>,         // requests, by response status
+    buckets: [u64; LATENCY_BUCKETS.len()], // requests within each bound
+    sum_secs: f64,                         // total latency
+}
+
+/// Request counters and latency histograms, by operation class, for
+/// METRICS in Prometheus text format.
+#[derive(Default)]
+pub struct Metrics {
+    ops: Mutex<BTreeMap<&'static str, OpMetrics>>,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+
+    /// Count one request of class op, answered with status after elapsed.
+    pub fn record(&self, op: OpKind, status: u16, elapsed: Duration) {
+        let mut ops = match self.ops.lock() {
+            Ok(ops) => ops,
+            Err(_e) => return, // metrics are best effort
+        };
+        let op_metrics = ops.entry(op.name()).or_default();
+        *op_metrics.by_status.entry(status).or_insert(0) += 1;
+        let secs = elapsed.as_secs_f64();
+        for (bound, n) in LATENCY_BUCKETS.iter().zip(op_metrics.buckets.iter_mut()) {
+            if secs <= *bound {
+                *n += 1;
+            }
+        }
+        op_metrics.sum_secs += secs;
+    }
+
+    /// All metrics, in Prometheus text exposition format.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let ops = match self.ops.lock() {
+            Ok(ops) => ops,
+            Err(_e) => return out,
+        };
+
+        out.push_str("# HELP kvdbd_requests_total API requests, by operation and status.\n");
+        out.push_str("# TYPE kvdbd_requests_total counter\n");
+        for (op, op_metrics) in ops.iter() {
+            for (status, n) in &op_metrics.by_status {
+                let _ = writeln!(
+                    out,
+                    "kvdbd_requests_total{{op=\"{op}\",code=\"{status}\"}} {n}"
+                );
+            }
+        }
+
+        out.push_str("# HELP kvdbd_request_duration_seconds API request latency, by operation.\n");
+        out.push_str("# TYPE kvdbd_request_duration_seconds histogram\n");
+        for (op, op_metrics) in ops.iter() {
+            for (bound, n) in LATENCY_BUCKETS.iter().zip(op_metrics.buckets.iter()) {
+                let _ = writeln!(
+                    out,
+                    "kvdbd_request_duration_seconds_bucket{{op=\"{op}\",le=\"{bound}\"}} {n}"
+                );
+            }
+            let count: u64 = op_metrics.by_status.values().sum();
+            let _ = writeln!(
+                out,
+                "kvdbd_request_duration_seconds_bucket{{op=\"{op}\",le=\"+Inf\"}} {count}"
+            );
+            let _ = writeln!(
+                out,
+                "kvdbd_request_duration_seconds_sum{{op=\"{}\"}} {}",
+                op, op_metrics.sum_secs
+            );
+            let _ = writeln!(
+                out,
+                "kvdbd_request_duration_seconds_count{{op=\"{op}\"}} {count}"
+            );
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::new();
+        metrics.record(OpKind::Get, 200, Duration::from_micros(300));
+        metrics.record(OpKind::Get, 404, Duration::from_millis(20));
+        metrics.record(OpKind::Get, 404, Duration::from_secs(10));
+        metrics.record(OpKind::Put, 200, Duration::from_millis(2));
+
+        let text = metrics.render();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines.contains(&"kvdbd_requests_total{op=\"get\",code=\"200\"} 1"));
+        assert!(lines.contains(&"kvdbd_requests_total{op=\"get\",code=\"404\"} 2"));
+        assert!(lines.contains(&"kvdbd_requests_total{op=\"put\",code=\"200\"} 1"));
+
+        // buckets are cumulative, up to +Inf, which counts every request
+        let bucket = |op: &str, le: &str| {
+            format!("kvdbd_request_duration_seconds_bucket{{op=\"{op}\",le=\"{le}\"}}")
+        };
+        assert!(lines.contains(&format!("{} 1", bucket("get", "0.0005")).as_str()));
+        assert!(lines.contains(&format!("{} 2", bucket("get", "0.025")).as_str()));
+        assert!(lines.contains(&format!("{} 2", bucket("get", "5")).as_str()));
+        assert!(lines.contains(&format!("{} 3", bucket("get", "+Inf")).as_str()));
+        assert!(lines.contains(&"kvdbd_request_duration_seconds_count{op=\"get\"} 3"));
+        assert!(lines.contains(&format!("{} 0", bucket("put", "0.001")).as_str()));
+        assert!(lines.contains(&format!("{} 1", bucket("put", "0.0025")).as_str()));
+        assert!(!text.contains("op=\"delete\""));
+    }
+}
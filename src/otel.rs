@@ -0,0 +1,663 @@
+use std::collections::hash_map::RandomState;
+use std::env;
+use std::fmt::Write as FmtWrite;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use actix_web::dev::ServiceResponse;
+use actix_web::http::{HeaderMap, StatusCode};
+use actix_web::HttpRequest;
+use reqwest::Client;
+use serde_json::json;
+
+const DEF_OTLP_ENDPOINT: &str = "http://localhost:4318";
+const EXPORT_BATCH_SPANS: usize = 512;
+const EXPORT_INTERVAL: Duration = Duration::from_secs(5);
+const SPAN_KIND_SERVER: u8 = 2;
+const STATUS_UNSET: u8 = 0;
+const STATUS_ERROR: u8 = 2;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum AttrValue {
+    Str(String),
+    Int(i64),
+}
+
+/// One completed request span, as exported.
+#[derive(Clone, Debug)]
+pub struct SpanData {
+    pub trace_id: [u8; 16],
+    pub span_id: [u8; 8],
+    pub parent_span_id: Option<[u8; 8]>, // from traceparent header, if any
+    pub name: String,
+    pub start_unix_ns: u64,
+    pub end_unix_ns: u64,
+    pub attributes: Vec<(&'static str, AttrValue)>,
+    pub status_code: u8, // OTLP status:  unset, or error
+}
+
+/// Destination of completed spans.
+pub trait SpanExporter: Send + Sync {
+    fn export(&self, span: SpanData);
+}
+
+// W3C trace context, of the caller's span
+#[derive(Debug, PartialEq)]
+struct TraceParent {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    sampled: bool,
+}
+
+fn parse_hex<const N: usize>(s: &str) -> Option<[u8; N]> {
+    if s.len() != N * 2 {
+        return None;
+    }
+    let mut out = [0u8; N];
+    for (i, byte) in out.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(s.get(i * 2..i * 2 + 2)?, 16).ok()?;
+    }
+    Some(out)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut s = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(s, "{byte:02x}").unwrap();
+    }
+    s
+}
+
+// traceparent:  version-traceid-parentid-flags, e.g.
+// "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01".  None if
+// malformed, or if either id is all zero, as the spec requires
+fn parse_traceparent(hdr: &str) -> Option<TraceParent> {
+    let fields: Vec<&str> = hdr.trim().split('-').collect();
+    if fields.len() < 4 || fields[0].len() != 2 || fields[0] == "ff" {
+        return None;
+    }
+    if fields[0] == "00" && fields.len() != 4 {
+        return None;
+    }
+    let trace_id: [u8; 16] = parse_hex(fields[1])?;
+    let span_id: [u8; 8] = parse_hex(fields[2])?;
+    let flags: [u8; 1] = parse_hex(fields[3])?;
+    if trace_id == [0; 16] || span_id == [0; 8] {
+        return None;
+    }
+    Some(TraceParent {
+        trace_id,
+        span_id,
+        sampled: flags[0] & 1 != 0,
+    })
+}
+
+fn unix_now_ns() -> u64 {
+    match SystemTime::now().duration_since(UNIX_EPOCH) {
+        Ok(d) => d.as_nanos() as u64,
+        Err(_e) => 0,
+    }
+}
+
+/// A request span, started but not yet ended.
+pub struct SpanStart {
+    trace_id: [u8; 16],
+    span_id: [u8; 8],
+    parent_span_id: Option<[u8; 8]>,
+    start_unix_ns: u64,
+    start: Instant,
+}
+
+/// Request attributes recorded on a span, when it ends.
+pub struct RequestSummary<'a> {
+    pub method: &'a str,
+    pub route: &'a str, // route pattern, e.g. "/api/{db}/obj/{key}"; "" if none
+    pub op: &'a str,    // kvdbd operation, e.g. "obj", "batch", "_scan"
+    pub db: Option<&'a str>,
+    pub key_len: Option<usize>,
+    pub status: u16,
+}
+
+/// Creates one span per request, continuing the caller's trace if a
+/// traceparent header is given, and hands ended spans to the exporter.
+pub struct Tracer {
+    exporter: Arc<dyn SpanExporter>,
+    ids: RandomState,
+    n_ids: AtomicU64,
+}
+
+impl Tracer {
+    pub fn new(exporter: Arc<dyn SpanExporter>) -> Tracer {
+        Tracer {
+            exporter,
+            ids: RandomState::new(),
+            n_ids: AtomicU64::new(0),
+        }
+    }
+
+    // random non-zero id:  a counter, hashed under a random key
+    fn random_u64(&self) -> u64 {
+        let mut hasher = self.ids.build_hasher();
+        hasher.write_u64(self.n_ids.fetch_add(1, Ordering::Relaxed));
+        hasher.finish().max(1)
+    }
+
+    /// Start span for a request with these headers.  None if the caller's
+    /// trace is not sampled.
+    pub fn start(&self, headers: &HeaderMap) -> Option<SpanStart> {
+        let parent = headers
+            .get("traceparent")
+            .and_then(|hdr| hdr.to_str().ok())
+            .and_then(parse_traceparent);
+        let (trace_id, parent_span_id) = match parent {
+            Some(tp) if !tp.sampled => return None,
+            Some(tp) => (tp.trace_id, Some(tp.span_id)),
+            None => {
+                let mut trace_id = [0u8; 16];
+                trace_id[..8].copy_from_slice(&self.random_u64().to_be_bytes());
+                trace_id[8..].copy_from_slice(&self.random_u64().to_be_bytes());
+                (trace_id, None)
+            }
+        };
+
+        Some(SpanStart {
+            trace_id,
+            span_id: self.random_u64().to_be_bytes(),
+            parent_span_id,
+            start_unix_ns: unix_now_ns(),
+            start: Instant::now(),
+        })
+    }
+
+    /// End span, and export it.
+    pub fn end(&self, span: SpanStart, summary: &RequestSummary) {
+        let mut attributes = vec![
+            (
+                "http.request.method",
+                AttrValue::Str(summary.method.to_string()),
+            ),
+            (
+                "http.response.status_code",
+                AttrValue::Int(summary.status.into()),
+            ),
+            ("kvdb.op", AttrValue::Str(summary.op.to_string())),
+        ];
+        if !summary.route.is_empty() {
+            attributes.push(("http.route", AttrValue::Str(summary.route.to_string())));
+        }
+        if let Some(db) = summary.db {
+            attributes.push(("db.namespace", AttrValue::Str(db.to_string())));
+        }
+        if let Some(n) = summary.key_len {
+            attributes.push(("kvdb.key_len", AttrValue::Int(n as i64)));
+        }
+        let result = match summary.status {
+            200..=299 => "ok",
+            404 => "not_found",
+            400..=499 => "rejected",
+            _ => "error",
+        };
+        attributes.push(("kvdb.result", AttrValue::Str(result.to_string())));
+
+        // server spans are errors on 5xx only; 4xx are the client's
+        let status_code = match summary.status {
+            500..=599 => STATUS_ERROR,
+            _ => STATUS_UNSET,
+        };
+        self.exporter.export(SpanData {
+            trace_id: span.trace_id,
+            span_id: span.span_id,
+            parent_span_id: span.parent_span_id,
+            name: format!("{} {}", summary.method, summary.route)
+                .trim_end()
+                .to_string(),
+            start_unix_ns: span.start_unix_ns,
+            end_unix_ns: span.start_unix_ns + span.start.elapsed().as_nanos() as u64,
+            attributes,
+            status_code,
+        });
+    }
+
+    /// End span of an HTTP request, from its response.
+    pub fn end_response<B>(&self, span: SpanStart, res: &ServiceResponse<B>, api_base: &str) {
+        let req = res.request();
+
+        // paths matching no route are unbounded; keep them out of spans
+        let unmatched = req.match_info().is_empty() && res.status() == StatusCode::NOT_FOUND;
+        let route = match unmatched {
+            true => String::new(),
+            false => route_of(req),
+        };
+        let op = match unmatched {
+            true => "unmatched",
+            false => route_op(&route, api_base),
+        };
+        self.end(
+            span,
+            &RequestSummary {
+                method: req.method().as_str(),
+                route: &route,
+                op,
+                db: req.match_info().get("db"),
+                key_len: req.match_info().get("key").map(|k| k.len()),
+                status: res.status().as_u16(),
+            },
+        );
+    }
+}
+
+// route pattern of request:  its path, with each matched parameter
+// replaced by its name, keeping keys out of span names
+fn route_of(req: &HttpRequest) -> String {
+    let path = req.match_info().get_ref().path();
+    let base = path.as_ptr() as usize;
+    let mut params: Vec<(usize, usize, &str)> = Vec::new();
+    for (name, value) in req.match_info().iter() {
+        let start = match (value.as_ptr() as usize).checked_sub(base) {
+            Some(start) if start + value.len() <= path.len() => start,
+            _ => continue, // not a slice of path
+        };
+        if !value.is_empty() {
+            params.push((start, start + value.len(), name));
+        }
+    }
+    params.sort_unstable();
+
+    let mut route = String::new();
+    let mut pos = 0;
+    for (start, end, name) in params {
+        if start < pos {
+            continue;
+        }
+        route.push_str(&path[pos..start]);
+        route.push('{');
+        route.push_str(name);
+        route.push('}');
+        pos = end;
+    }
+    route.push_str(&path[pos..]);
+    route
+}
+
+// kvdbd operation of a route:  the path element following the db name,
+// e.g. "obj" for "/api/{db}/obj/{key}", or "alloc" for "/api/{db}";
+// else the element following the API base, e.g. "_reload"
+fn route_op<'a>(route: &'a str, api_base: &str) -> &'a str {
+    let rel = route.strip_prefix(api_base).unwrap_or(route);
+    let mut elems = rel.trim_start_matches('/').split('/');
+    match elems.next() {
+        Some("{db}") => elems.next().unwrap_or("alloc"),
+        Some(elem) if !elem.is_empty() => elem,
+        _ => "index",
+    }
+}
+
+fn otlp_attr(key: &str, val: &AttrValue) -> serde_json::Value {
+    match val {
+        AttrValue::Str(s) => json!({"key": key, "value": {"stringValue": s}}),
+        AttrValue::Int(n) => json!({"key": key, "value": {"intValue": n.to_string()}}),
+    }
+}
+
+// OTLP/HTTP JSON encoding of a batch of spans
+fn otlp_json(service_name: &str, spans: &[SpanData]) -> serde_json::Value {
+    let spans: Vec<serde_json::Value> = spans
+        .iter()
+        .map(|span| {
+            json!({
+                "traceId": to_hex(&span.trace_id),
+                "spanId": to_hex(&span.span_id),
+                "parentSpanId": span.parent_span_id.map_or(String::new(), |id| to_hex(&id)),
+                "name": span.name,
+                "kind": SPAN_KIND_SERVER,
+                "startTimeUnixNano": span.start_unix_ns.to_string(),
+                "endTimeUnixNano": span.end_unix_ns.to_string(),
+                "attributes": span
+                    .attributes
+                    .iter()
+                    .map(|(key, val)| otlp_attr(key, val))
+                    .collect::<Vec<_>>(),
+                "status": {"code": span.status_code},
+            })
+        })
+        .collect();
+
+    json!({"resourceSpans": [{
+        "resource": {"attributes": [otlp_attr("service.name", &AttrValue::Str(service_name.to_string()))]},
+        "scopeSpans": [{
+            "scope": {"name": "kvdbd", "version": env!("CARGO_PKG_VERSION")},
+            "spans": spans,
+        }],
+    }]})
+}
+
+/// Exports spans to an OTLP/HTTP collector, as JSON, in batches, from a
+/// background thread, so requests never wait on the collector.
+pub struct OtlpExporter {
+    tx: Mutex<Option<mpsc::Sender<SpanData>>>,
+    handle: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl OtlpExporter {
+    pub fn new(
+        endpoint: String,
+        headers: Vec<(String, String)>,
+        service_name: String,
+    ) -> OtlpExporter {
+        let (tx, rx) = mpsc::channel::<SpanData>();
+        let handle = thread::spawn(move || {
+            let client = Client::new();
+            let mut batch = Vec::new();
+            let mut deadline = Instant::now() + EXPORT_INTERVAL;
+            loop {
+                let wait = deadline.saturating_duration_since(Instant::now());
+                let done = match rx.recv_timeout(wait) {
+                    Ok(span) => {
+                        batch.push(span);
+                        false
+                    }
+                    Err(RecvTimeoutError::Timeout) => false,
+                    Err(RecvTimeoutError::Disconnected) => true,
+                };
+                if (batch.len() >= EXPORT_BATCH_SPANS || Instant::now() >= deadline || done)
+                    && !batch.is_empty()
+                {
+                    let mut req = client
+                        .post(&endpoint)
+                        .json(&otlp_json(&service_name, &batch));
+                    for (name, val) in &headers {
+                        req = req.header(name.as_str(), val.as_str());
+                    }
+                    match req.send() {
+                        Ok(resp) if !resp.status().is_success() => {
+                            println!("otel: export to {} failed: {}", endpoint, resp.status())
+                        }
+                        Err(e) => println!("otel: export to {endpoint} failed: {e}"),
+                        Ok(_resp) => {}
+                    }
+                    batch.clear();
+                }
+                if Instant::now() >= deadline {
+                    deadline = Instant::now() + EXPORT_INTERVAL;
+                }
+                if done {
+                    return;
+                }
+            }
+        });
+
+        OtlpExporter {
+            tx: Mutex::new(Some(tx)),
+            handle: Mutex::new(Some(handle)),
+        }
+    }
+
+    /// Export spans still queued, and stop the export thread.
+    pub fn shutdown(&self) {
+        drop(self.tx.lock().unwrap().take());
+        if let Some(handle) = self.handle.lock().unwrap().take() {
+            handle.join().unwrap();
+        }
+    }
+}
+
+impl SpanExporter for OtlpExporter {
+    fn export(&self, span: SpanData) {
+        if let Some(tx) = &*self.tx.lock().unwrap() {
+            let _ = tx.send(span);
+        }
+    }
+}
+
+// OTEL_EXPORTER_OTLP_HEADERS:  "name=value,name2=value2"
+fn parse_headers(s: &str) -> Vec<(String, String)> {
+    s.split(',')
+        .filter_map(|pair| {
+            let (name, val) = pair.split_once('=')?;
+            Some((name.trim().to_string(), val.trim().to_string()))
+        })
+        .filter(|(name, _val)| !name.is_empty())
+        .collect()
+}
+
+/// Tracer exporting via OTLP, configured by the standard OpenTelemetry
+/// environment variables.  None if tracing is disabled, by
+/// OTEL_SDK_DISABLED=true or OTEL_TRACES_EXPORTER=none.
+pub fn tracer_from_env() -> Option<(Arc<Tracer>, Arc<OtlpExporter>)> {
+    let var = |name: &str| env::var(name).ok().filter(|v| !v.is_empty());
+    if var("OTEL_SDK_DISABLED").is_some_and(|v| v.eq_ignore_ascii_case("true"))
+        || var("OTEL_TRACES_EXPORTER").is_some_and(|v| v == "none")
+    {
+        return None;
+    }
+
+    let endpoint = match var("OTEL_EXPORTER_OTLP_TRACES_ENDPOINT") {
+        Some(url) => url,
+        None => {
+            let base = var("OTEL_EXPORTER_OTLP_ENDPOINT").unwrap_or(DEF_OTLP_ENDPOINT.to_string());
+            format!("{}/v1/traces", base.trim_end_matches('/'))
+        }
+    };
+    let mut headers = parse_headers(&var("OTEL_EXPORTER_OTLP_HEADERS").unwrap_or_default());
+    headers.extend(parse_headers(
+        &var("OTEL_EXPORTER_OTLP_TRACES_HEADERS").unwrap_or_default(),
+    ));
+    let service_name = var("OTEL_SERVICE_NAME").unwrap_or("kvdbd".to_string());
+
+    println!("Exporting traces: {endpoint}");
+    let exporter = Arc::new(OtlpExporter::new(endpoint, headers, service_name));
+    let tracer = Tracer::new(Arc::clone(&exporter) as Arc<dyn SpanExporter>);
+    Some((Arc::new(tracer), exporter))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::http::header::{HeaderName, HeaderValue};
+
+    impl SpanData {
+        fn attr(&self, key: &str) -> Option<&AttrValue> {
+            self.attributes.iter().find(|a| a.0 == key).map(|a| &a.1)
+        }
+    }
+
+    // collects spans, for inspection
+    #[derive(Clone, Default)]
+    struct InMemoryExporter {
+        spans: Arc<Mutex<Vec<SpanData>>>,
+    }
+
+    impl SpanExporter for InMemoryExporter {
+        fn export(&self, span: SpanData) {
+            self.spans.lock().unwrap().push(span);
+        }
+    }
+
+    fn traceparent(val: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("traceparent"),
+            HeaderValue::from_str(val).unwrap(),
+        );
+        headers
+    }
+
+    #[test]
+    fn test_parse_traceparent() {
+        let tp =
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01").unwrap();
+        assert_eq!(to_hex(&tp.trace_id), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(to_hex(&tp.span_id), "00f067aa0ba902b7");
+        assert!(tp.sampled);
+
+        let tp =
+            parse_traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00").unwrap();
+        assert!(!tp.sampled);
+
+        // later versions may append fields
+        assert!(
+            parse_traceparent("01-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-x")
+                .is_some()
+        );
+
+        for bad in &[
+            "",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01-x",
+            "ff-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-0000000000000000-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473-00f067aa0ba902b7-01",
+            "00-4bf92f3577b34da6a3ce929d0e0e473g-00f067aa0ba902b7-01",
+        ] {
+            assert_eq!(parse_traceparent(bad), None);
+        }
+    }
+
+    #[test]
+    fn test_span_attributes() {
+        let exporter = InMemoryExporter::default();
+        let tracer = Tracer::new(Arc::new(exporter.clone()));
+
+        // continues caller's trace
+        let headers = traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01");
+        let span = tracer.start(&headers).unwrap();
+        tracer.end(
+            span,
+            &RequestSummary {
+                method: "GET",
+                route: "/api/{db}/obj/{key}",
+                op: "obj",
+                db: Some("db1"),
+                key_len: Some(5),
+                status: 404,
+            },
+        );
+
+        let spans = exporter.spans.lock().unwrap();
+        assert_eq!(spans.len(), 1);
+        let span = &spans[0];
+        assert_eq!(to_hex(&span.trace_id), "4bf92f3577b34da6a3ce929d0e0e4736");
+        assert_eq!(
+            span.parent_span_id.map(|id| to_hex(&id)),
+            Some("00f067aa0ba902b7".to_string())
+        );
+        assert_ne!(span.span_id, [0; 8]);
+        assert_eq!(span.name, "GET /api/{db}/obj/{key}");
+        assert!(span.end_unix_ns >= span.start_unix_ns);
+        assert_eq!(
+            span.attr("http.request.method"),
+            Some(&AttrValue::Str("GET".to_string()))
+        );
+        assert_eq!(
+            span.attr("http.response.status_code"),
+            Some(&AttrValue::Int(404))
+        );
+        assert_eq!(
+            span.attr("kvdb.op"),
+            Some(&AttrValue::Str("obj".to_string()))
+        );
+        assert_eq!(
+            span.attr("http.route"),
+            Some(&AttrValue::Str("/api/{db}/obj/{key}".to_string()))
+        );
+        assert_eq!(
+            span.attr("db.namespace"),
+            Some(&AttrValue::Str("db1".to_string()))
+        );
+        assert_eq!(span.attr("kvdb.key_len"), Some(&AttrValue::Int(5)));
+        assert_eq!(
+            span.attr("kvdb.result"),
+            Some(&AttrValue::Str("not_found".to_string()))
+        );
+        assert_eq!(span.status_code, STATUS_UNSET);
+    }
+
+    #[test]
+    fn test_span_root_and_unsampled() {
+        let exporter = InMemoryExporter::default();
+        let tracer = Tracer::new(Arc::new(exporter.clone()));
+        let summary = RequestSummary {
+            method: "POST",
+            route: "/api/{db}/batch",
+            op: "batch",
+            db: Some("db1"),
+            key_len: None,
+            status: 500,
+        };
+
+        // no traceparent:  new trace, each with its own id
+        for _i in 0..2 {
+            let span = tracer.start(&HeaderMap::new()).unwrap();
+            tracer.end(span, &summary);
+        }
+        {
+            let spans = exporter.spans.lock().unwrap();
+            assert_eq!(spans.len(), 2);
+            assert_eq!(spans[0].parent_span_id, None);
+            assert_ne!(spans[0].trace_id, spans[1].trace_id);
+            assert_eq!(spans[0].attr("kvdb.key_len"), None);
+            assert_eq!(
+                spans[0].attr("kvdb.result"),
+                Some(&AttrValue::Str("error".to_string()))
+            );
+            assert_eq!(spans[0].status_code, STATUS_ERROR);
+        }
+
+        // caller's trace not sampled:  no span
+        let headers = traceparent("00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00");
+        assert!(tracer.start(&headers).is_none());
+        assert_eq!(exporter.spans.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_route_op() {
+        assert_eq!(route_op("/api/{db}/obj/{key}", "/api"), "obj");
+        assert_eq!(route_op("/api/{db}/_scan/{scan}/next", "/api"), "_scan");
+        assert_eq!(route_op("/api/{db}", "/api"), "alloc");
+        assert_eq!(route_op("/api/_reload", "/api"), "_reload");
+        assert_eq!(route_op("/{db}/get", ""), "get");
+        assert_eq!(route_op("/", "/api"), "index");
+    }
+
+    #[test]
+    fn test_otlp_json() {
+        let span = SpanData {
+            trace_id: [1; 16],
+            span_id: [2; 8],
+            parent_span_id: None,
+            name: String::from("GET /api/{db}/obj/{key}"),
+            start_unix_ns: 1_000,
+            end_unix_ns: 2_000,
+            attributes: vec![
+                ("db.namespace", AttrValue::Str("db1".to_string())),
+                ("kvdb.key_len", AttrValue::Int(3)),
+            ],
+            status_code: STATUS_UNSET,
+        };
+        let jv = otlp_json("kvdbd", &[span]);
+        let res = &jv["resourceSpans"][0];
+        assert_eq!(
+            res["resource"]["attributes"][0]["value"]["stringValue"],
+            "kvdbd"
+        );
+        let out = &res["scopeSpans"][0]["spans"][0];
+        assert_eq!(out["traceId"], "01010101010101010101010101010101");
+        assert_eq!(out["spanId"], "0202020202020202");
+        assert_eq!(out["parentSpanId"], "");
+        assert_eq!(out["startTimeUnixNano"], "1000");
+        assert_eq!(out["attributes"][1]["value"]["intValue"], "3");
+
+        assert_eq!(
+            parse_headers("api-key=secret, x-team = kv ,bad"),
+            vec![
+                ("api-key".to_string(), "secret".to_string()),
+                ("x-team".to_string(), "kv".to_string())
+            ]
+        );
+    }
+}
@@ -0,0 +1,149 @@
+// per-client request rate limiting:  a token bucket per client IP address
+//
+// Each client's bucket holds up to one second's worth of requests, and
+// refills continuously at the configured rate, so a client may burst up
+// to rate_limit_rps requests at once, then sustain that rate.  Reads and
+// writes share one bucket.  To limit them separately, key buckets by
+// (IpAddr, bool), the bool from OpKind::is_write() of the request's
+// request_op(), and give each class its own rate.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+// clients tracked at once, bounding limiter memory under a flood of
+// addresses; idle clients are forgotten first, to make room
+const MAX_CLIENTS: usize = 16384;
+
+struct Bucket {
+    tokens: f64,   // requests admitted now, without waiting
+    last: Instant, // when tokens was last refilled
+}
+
+/// Token-bucket rate limiter, shared across connections and workers,
+/// admitting up to rps requests per second from each client IP.
+pub struct RateLimiter {
+    rps: f64,
+    max_clients: usize,
+    clients: Mutex<HashMap<IpAddr, Bucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(rps: u32) -> RateLimiter {
+        RateLimiter::with_max_clients(rps, MAX_CLIENTS)
+    }
+
+    /// Limiter tracking at most max_clients client addresses.
+    pub fn with_max_clients(rps: u32, max_clients: usize) -> RateLimiter {
+        RateLimiter {
+            rps: f64::from(rps.max(1)),
+            max_clients: max_clients.max(1),
+            clients: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Admit one request from ip, at now; or, if its bucket is empty, the
+    /// time until it could be admitted.
+    pub fn check(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let mut clients = match self.clients.lock() {
+            Ok(clients) => clients,
+            Err(_e) => return Ok(()), // limiting is best effort
+        };
+        if !clients.contains_key(&ip) && clients.len() >= self.max_clients {
+            self.evict(&mut clients, now);
+        }
+
+        let rps = self.rps;
+        let bucket = clients.entry(ip).or_insert(Bucket {
+            tokens: rps,
+            last: now,
+        });
+        let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rps).min(rps);
+        bucket.last = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rps))
+        }
+    }
+
+    // make room for one more client:  forget those idle long enough to
+    // have refilled their buckets, as they would be admitted as new; if
+    // none is, the one seen least recently
+    fn evict(&self, clients: &mut HashMap<IpAddr, Bucket>, now: Instant) {
+        let rps = self.rps;
+        clients.retain(|_ip, bucket| {
+            let elapsed = now.saturating_duration_since(bucket.last).as_secs_f64();
+            bucket.tokens + elapsed * rps < rps
+        });
+        if clients.len() < self.max_clients {
+            return;
+        }
+        let oldest = clients
+            .iter()
+            .min_by_key(|(_ip, bucket)| bucket.last)
+            .map(|(ip, _bucket)| *ip);
+        if let Some(ip) = oldest {
+            clients.remove(&ip);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(n: u8) -> IpAddr {
+        IpAddr::from([10, 0, 0, n])
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = RateLimiter::new(4);
+        let start = Instant::now();
+
+        // a burst of one second's worth is admitted, then refused...
+        for _i in 0..4 {
+            assert_eq!(limiter.check(ip(1), start), Ok(()));
+        }
+        let wait = limiter.check(ip(1), start).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(250));
+
+        // ...while other clients are admitted as before
+        assert_eq!(limiter.check(ip(2), start), Ok(()));
+
+        // the bucket refills at the rate, up to one second's worth
+        let later = start + Duration::from_millis(250);
+        assert_eq!(limiter.check(ip(1), later), Ok(()));
+        assert!(limiter.check(ip(1), later).is_err());
+        let later = start + Duration::from_secs(60);
+        for _i in 0..4 {
+            assert_eq!(limiter.check(ip(1), later), Ok(()));
+        }
+        assert!(limiter.check(ip(1), later).is_err());
+    }
+
+    #[test]
+    fn test_rate_limit_evict() {
+        let limiter = RateLimiter::with_max_clients(2, 2);
+        let start = Instant::now();
+        limiter.check(ip(1), start).unwrap();
+        limiter.check(ip(1), start).unwrap();
+        limiter.check(ip(2), start).unwrap();
+
+        // at the limit, the client seen least recently makes room...
+        let later = start + Duration::from_millis(100);
+        limiter.check(ip(2), later).unwrap();
+        limiter.check(ip(3), later).unwrap();
+        assert_eq!(limiter.clients.lock().unwrap().len(), 2);
+        assert_eq!(limiter.check(ip(1), later), Ok(()));
+
+        // ...unless clients are idle, with full buckets, which all go
+        let later = start + Duration::from_secs(5);
+        limiter.check(ip(4), later).unwrap();
+        assert_eq!(limiter.clients.lock().unwrap().len(), 1);
+    }
+}
@@ -8,278 +8,171 @@
 
 extern crate clap;
 extern crate reqwest;
+#[allow(dead_code)] // wire format; the tester only encodes requests
+mod binproto;
+#[allow(dead_code)] // client API; not every constructor is used by the tester
+mod client;
 mod protos;
 
 const T_ENDPOINT: &'static str = "http://127.0.0.1:8080";
-const T_BASEURI: &'static str = "/api";
+const T_BINARY_ADDR: &str = "127.0.0.1";
+const T_DEF_API_BASE: &str = "/api";
 
 const APPNAME: &'static str = "kvdbd-tester";
 const VERSION: &'static str = env!("CARGO_PKG_VERSION");
 
-use reqwest::{Client, StatusCode};
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use std::net::TcpStream;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use std::{env, process};
 
+use reqwest::{header, Client, Method, StatusCode};
+use serde_json::json;
+
+use kvdbd::db;
 use protobuf::parse_from_bytes;
-use protobuf::Message;
-use protos::pbapi::{
-    BatchRequest, BatchRequest_MagicNum, DbStatResponse, IterRequest, IterRequest_MagicNum,
-    KeyRequest, KeyRequest_MagicNum, KeyResponse, UpdateRequest, UpdateRequest_MagicNum,
-};
+use protos::pbapi::{DbStatResponse, KeyResponse};
 
-struct KeyList {
-    keys: Vec<Vec<u8>>,
-    list_end: bool,
-}
+use client::{
+    pbenc_batch_req, pbenc_key_req, pbenc_update_req, BinClient, ClientError, KvdbClient,
+};
+use db::api::{Batch, DuplicatePolicy, EntryList, IterOptions, KeyList};
 
-fn pbenc_iter_req(start_key: Option<Vec<u8>>, prefix: Option<Vec<u8>>) -> Vec<u8> {
-    let mut out_msg = IterRequest::new();
-    out_msg.magic = IterRequest_MagicNum::MAGIC;
-    match start_key {
-        None => out_msg.set_start_key(Vec::new()),
-        Some(s) => out_msg.set_start_key(s),
-    }
-    match prefix {
-        None => out_msg.set_prefix(Vec::new()),
-        Some(s) => out_msg.set_prefix(s),
-    }
-    return out_msg.write_to_bytes().unwrap();
-}
+// API base path, as reported by the server index
+static T_API_BASE: OnceLock<String> = OnceLock::new();
 
-fn pbenc_key_req(key: &[u8]) -> Vec<u8> {
-    let mut out_msg = KeyRequest::new();
-    out_msg.magic = KeyRequest_MagicNum::MAGIC;
-    out_msg.set_key(key.to_vec());
-    return out_msg.write_to_bytes().unwrap();
+fn t_api_base() -> &'static str {
+    T_API_BASE.get_or_init(|| String::from(T_DEF_API_BASE))
 }
 
-fn pbenc_update_ins(key: &[u8], val: &[u8]) -> UpdateRequest {
-    let mut out_msg = UpdateRequest::new();
-    out_msg.magic = UpdateRequest_MagicNum::MAGIC;
-    out_msg.set_key(key.to_vec());
-    out_msg.set_value(val.to_vec());
-    out_msg.set_is_insert(true);
-
-    out_msg
+fn t_client(client: &Client, db_id: &str) -> KvdbClient {
+    KvdbClient::with_api_base(client.clone(), T_ENDPOINT, t_api_base(), db_id)
 }
 
-fn pbenc_update_del(key: &[u8]) -> UpdateRequest {
-    let mut out_msg = UpdateRequest::new();
-    out_msg.magic = UpdateRequest_MagicNum::MAGIC;
-    out_msg.set_key(key.to_vec());
-    out_msg.set_is_insert(false);
-
-    out_msg
-}
+fn t_iter(client: &Client, db_id: String, start_key: Option<Vec<u8>>) -> KeyList {
+    let mut opts = IterOptions::new();
+    if let Some(key) = start_key {
+        opts.start(&key);
+    }
 
-fn pbenc_update_req(key: &[u8], val: &[u8]) -> Vec<u8> {
-    let out_msg = pbenc_update_ins(key, val);
-    return out_msg.write_to_bytes().unwrap();
+    match t_client(client, &db_id).iter(opts) {
+        Ok(key_list) => key_list,
+        Err(e) => panic!("iter failed: {}", e),
+    }
 }
 
-fn t_iter(client: &Client, db_id: String, start_key: Option<Vec<u8>>) -> KeyList {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let keys_url = format!("{}keys", basepath);
-
-    // encode keys request
-    let out_bytes = pbenc_iter_req(start_key, None);
+// query service index; returns server info, including db configurations
+fn t_server_info(client: &Client) -> serde_json::Value {
+    let index_url = format!("{T_ENDPOINT}/");
 
-    // exec keys request; check for successful response
-    let resp_res = client.post(&keys_url).body(out_bytes).send();
+    let resp_res = client.get(&index_url).send();
     if resp_res.is_err() {
-        assert!(false);
+        panic!("request failed");
     }
     let mut resp = resp_res.unwrap();
     assert_eq!(resp.status(), StatusCode::OK);
 
-    // decode protobuf list-of-keys response
-    let mut body: Vec<u8> = vec![];
-    match resp.copy_to(&mut body) {
-        Err(_e) => assert!(false),
-        Ok(_o) => {}
-    }
-    let in_msg;
-    match parse_from_bytes::<KeyResponse>(&body) {
-        Err(_e) => {
-            assert!(false);
-            panic!("silence E0381 warning");
-        }
-        Ok(req) => {
-            in_msg = req;
-        }
-    }
+    resp.json().unwrap()
+}
 
-    // copy from pb struct to normal struct for returning data
-    let mut key_list: Vec<Vec<u8>> = Vec::new();
-    for key in in_msg.get_keys() {
-        key_list.push(key.clone());
+// returns each database's configuration, keyed by name
+fn t_db_configs(info: &serde_json::Value) -> HashMap<String, serde_json::Value> {
+    let mut db_cfgs = HashMap::new();
+    for db_cfg in info["databases"].as_array().unwrap() {
+        let name = db_cfg["name"].as_str().unwrap().to_string();
+        db_cfgs.insert(name, db_cfg.clone());
     }
 
-    KeyList {
-        keys: key_list,
-        list_end: in_msg.list_end,
-    }
+    db_cfgs
 }
 
-fn t_get_gone(client: &Client, db_id: String, key: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let get_url = format!("{}get", basepath);
-
-    // encode verification get request
-    let out_bytes = pbenc_key_req(key.as_bytes());
+// GET key via REST API, where key does not exist
+// true if the db's disabled_ops refuses op
+fn t_op_disabled(db_cfg: &serde_json::Value, op: &str) -> bool {
+    db_cfg["disabled_ops"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|v| v.as_str() == Some(op))
+}
 
-    // exec get request; key1 should not exist, following batch
-    let resp_res = client.post(&get_url).body(out_bytes).send();
+fn t_obj_get_gone(client: &Client, url: &str, db_cfg: &serde_json::Value) {
+    let resp_res = client.get(url).send();
     match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
+        Ok(mut resp) => {
+            if db_cfg["missing_as_empty"].as_bool().unwrap_or(false) {
+                assert_eq!(resp.status(), StatusCode::OK);
+                assert_eq!(resp.text().unwrap(), "");
+            } else {
+                assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+            }
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn t_get_gone(client: &Client, db_id: String, key: String) {
+    match t_client(client, &db_id).get(key.as_bytes()) {
+        Ok(optval) => assert_eq!(optval, None),
         Err(_e) => assert!(false),
     }
 }
 
 fn t_get_ok(client: &Client, db_id: String, key: String, value: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let get_url = format!("{}get", basepath);
-
-    // encode verification get request
-    let out_bytes = pbenc_key_req(key.as_bytes());
-
-    // exec get request; key1 should not exist, following batch
-    let resp_res = client.post(&get_url).body(out_bytes.clone()).send();
-    match resp_res {
-        Ok(mut resp) => {
-            assert_eq!(resp.status(), StatusCode::OK);
-
-            match resp.text() {
-                Ok(body) => assert_eq!(body, value),
-                Err(_e) => assert!(false),
-            }
-        }
+    match t_client(client, &db_id).get(key.as_bytes()) {
+        Ok(optval) => assert_eq!(optval, Some(value.into_bytes())),
         Err(_e) => assert!(false),
     }
 }
 
 fn t_put_bytes(client: &Client, db_id: String, key: &[u8], value: &[u8]) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let put_url = format!("{}put", basepath);
-
-    // encode put request
-    let out_bytes = pbenc_update_req(key, value);
-
-    // exec put request
-    let resp_res = client.post(&put_url).body(out_bytes).send();
-    match resp_res {
-        Ok(mut resp) => {
-            assert_eq!(resp.status(), StatusCode::OK);
-
-            match resp.text() {
-                Ok(_body) => {}
-                Err(_e) => assert!(false),
-            }
-        }
+    match t_client(client, &db_id).put(key, value) {
+        Ok(res) => assert!(res),
         Err(_e) => assert!(false),
     }
 }
 
 fn t_put(client: &Client, db_id: String, key: String, value: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let put_url = format!("{}put", basepath);
-
-    // encode put request
-    let out_bytes = pbenc_update_req(key.as_bytes(), value.as_bytes());
-
-    // exec put request
-    let resp_res = client.post(&put_url).body(out_bytes).send();
-    match resp_res {
-        Ok(mut resp) => {
-            assert_eq!(resp.status(), StatusCode::OK);
-
-            match resp.text() {
-                Ok(_body) => {}
-                Err(_e) => assert!(false),
-            }
-        }
-        Err(_e) => assert!(false),
-    }
+    t_put_bytes(client, db_id, key.as_bytes(), value.as_bytes());
 }
 
 fn t_del(client: &Client, db_id: String, key: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let del_url = format!("{}del", basepath);
-
-    // encode del request
-    let out_bytes = pbenc_key_req(key.as_bytes());
-
-    // exec del request
-    let resp_res = client.post(&del_url).body(out_bytes).send();
-    match resp_res {
-        Ok(mut resp) => {
-            assert_eq!(resp.status(), StatusCode::OK);
-
-            match resp.text() {
-                Ok(_body) => {}
-                Err(_e) => assert!(false),
-            }
-        }
+    match t_client(client, &db_id).del(key.as_bytes()) {
+        Ok(res) => assert!(res),
         Err(_e) => assert!(false),
     }
 }
 
 fn t_del_gone(client: &Client, db_id: String, key: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let del_url = format!("{}del", basepath);
-
-    // encode del request
-    let out_bytes = pbenc_key_req(key.as_bytes());
-
-    // exec del request
-    let resp_res = client.post(&del_url).body(out_bytes).send();
-    match resp_res {
-        Ok(mut resp) => {
-            assert_eq!(resp.status(), StatusCode::NOT_FOUND);
-
-            match resp.text() {
-                Ok(_body) => {}
-                Err(_e) => assert!(false),
-            }
-        }
+    match t_client(client, &db_id).del(key.as_bytes()) {
+        Ok(res) => assert!(!res),
         Err(_e) => assert!(false),
     }
 }
 
 fn op_batch(client: &Client, db_id: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let batch_url = format!("{}batch", basepath);
     let test_key = String::from("op_batch_key1");
     let test_value = format!("helloworld op_put {}", db_id);
 
     t_put(client, db_id.clone(), test_key.clone(), test_value);
 
-    let mut out_msg = BatchRequest::new();
-    out_msg.magic = BatchRequest_MagicNum::MAGIC;
+    let mut batch = Batch::default();
 
     // op1: delete
-    let req = pbenc_update_del("op_batch_key1".as_bytes());
-    out_msg.reqs.push(req);
+    batch.remove(b"op_batch_key1");
 
     // op2: insert
-    let req = pbenc_update_ins("op_batch_key2".as_bytes(), "op_batch_value2".as_bytes());
-    out_msg.reqs.push(req);
+    batch.insert(b"op_batch_key2", b"op_batch_value2");
 
     // op3: insert
-    let req = pbenc_update_ins("op_batch_key3".as_bytes(), "op_batch_value3".as_bytes());
-    out_msg.reqs.push(req);
-
-    let out_bytes: Vec<u8> = out_msg.write_to_bytes().unwrap();
+    batch.insert(b"op_batch_key3", b"op_batch_value3");
 
     // exec batch request
-    let resp_res = client.post(&batch_url).body(out_bytes).send();
-    match resp_res {
-        Ok(mut resp) => {
-            assert_eq!(resp.status(), StatusCode::OK);
-
-            match resp.text() {
-                Ok(_body) => {}
-                Err(_e) => assert!(false),
-            }
-        }
+    match t_client(client, &db_id).batch(&batch) {
+        Ok(res) => assert!(res),
         Err(_e) => assert!(false),
     }
 
@@ -297,8 +190,156 @@ fn op_batch(client: &Client, db_id: String) {
         String::from("op_batch_value3"),
     );
 
-    t_del(client, db_id.clone(), String::from("op_batch_key2"));
-    t_del(client, db_id, String::from("op_batch_key3"));
+    // repeated keys:  last wins, unless the batch rejects duplicates
+    let kc = t_client(client, &db_id);
+    let mut batch = Batch::default();
+    batch.insert(b"op_batch_key2", b"first");
+    batch.insert(b"op_batch_key2", b"second");
+    batch.insert(b"op_batch_key3", b"kept");
+    batch.remove(b"op_batch_key3");
+    batch.duplicate_policy = DuplicatePolicy::Reject;
+    match kc.batch(&batch) {
+        Err(ClientError::Status(status)) => assert_eq!(status, StatusCode::BAD_REQUEST),
+        _ => panic!("unexpected result"),
+    }
+    assert_eq!(
+        kc.get(b"op_batch_key2").unwrap(),
+        Some(b"op_batch_value2".to_vec())
+    );
+    batch.duplicate_policy = DuplicatePolicy::LastWins;
+    assert!(kc.batch(&batch).unwrap());
+    assert_eq!(kc.get(b"op_batch_key2").unwrap(), Some(b"second".to_vec()));
+    assert_eq!(kc.get(b"op_batch_key3").unwrap(), None);
+
+    let url = format!(
+        "{}{}/{}/batch?duplicates=first",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let resp = client
+        .post(&url)
+        .body(pbenc_batch_req(&batch))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // guarded batch:  refused, changing nothing, unless the guard matches
+    let mut batch = Batch {
+        guard: Some((b"op_batch_key2".to_vec(), Some(b"first".to_vec()))),
+        ..Batch::default()
+    };
+    batch.insert(b"op_batch_key2", b"third");
+    batch.insert(b"op_batch_key3", b"guarded");
+    match kc.batch(&batch) {
+        Err(ClientError::Status(status)) => assert_eq!(status, StatusCode::CONFLICT),
+        _ => panic!("unexpected result"),
+    }
+    assert_eq!(kc.get(b"op_batch_key2").unwrap(), Some(b"second".to_vec()));
+    assert_eq!(kc.get(b"op_batch_key3").unwrap(), None);
+    batch.guard = Some((b"op_batch_key2".to_vec(), Some(b"second".to_vec())));
+    assert!(kc.batch(&batch).unwrap());
+    assert_eq!(kc.get(b"op_batch_key2").unwrap(), Some(b"third".to_vec()));
+    assert_eq!(kc.get(b"op_batch_key3").unwrap(), Some(b"guarded".to_vec()));
+
+    let url = format!(
+        "{}{}/{}/batch?guard_value=eA%3D%3D",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let resp = client
+        .post(&url)
+        .body(pbenc_batch_req(&batch))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // JSON body:  base64 keys and values, all or nothing
+    let url = format!("{}{}/{}/batch", T_ENDPOINT, t_api_base(), db_id);
+    let b64 = |s: &str| base64::encode(s.as_bytes());
+    let ops = json!([
+        {"key": b64("op_batch_key2"), "value": b64("json")},
+        {"key": b64("op_batch_key3")},
+    ]);
+    let resp = client.post(&url).json(&ops).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(kc.get(b"op_batch_key2").unwrap(), Some(b"json".to_vec()));
+    assert_eq!(kc.get(b"op_batch_key3").unwrap(), None);
+    let ops = json!([
+        {"key": b64("op_batch_key3"), "value": b64("partial")},
+        {"key": "not base64!"},
+    ]);
+    let resp = client.post(&url).json(&ops).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(kc.get(b"op_batch_key3").unwrap(), None);
+
+    // a malformed mutation is named by its index, and refuses the batch
+    let ops = json!([
+        {"key": b64("op_batch_key3"), "value": b64("partial")},
+        {"key": b64("op_batch_key2"), "op": "insert"},
+    ]);
+    let mut resp = client.post(&url).json(&ops).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    let jv: serde_json::Value = resp.json().unwrap();
+    let msg = jv["error"]["message"].as_str().unwrap();
+    assert!(msg.starts_with("mutation 1: insert missing value"));
+    assert_eq!(kc.get(b"op_batch_key3").unwrap(), None);
+
+    // MessagePack body:  the same mutations, keys and values as native
+    // byte strings
+    let mut batch = Batch::default();
+    batch.insert(b"op_batch_key3", b"\x00msgpack\xff");
+    batch.remove(b"op_batch_key2");
+    let resp = client
+        .post(&url)
+        .header(header::CONTENT_TYPE, "application/msgpack")
+        .body(rmp_serde::to_vec(&batch.ops).unwrap())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        kc.get(b"op_batch_key3").unwrap(),
+        Some(b"\x00msgpack\xff".to_vec())
+    );
+    assert_eq!(kc.get(b"op_batch_key2").unwrap(), None);
+    let resp = client
+        .post(&url)
+        .header(header::CONTENT_TYPE, "application/msgpack")
+        .body(&b"\xc1"[..])
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // protobuf body, so typed:  as untyped, the default
+    let mut batch = Batch::default();
+    batch.insert(b"op_batch_key4", b"\x00protobuf\xff");
+    batch.remove(b"op_batch_key3");
+    let resp = client
+        .post(&url)
+        .header(header::CONTENT_TYPE, "application/x-protobuf")
+        .body(pbenc_batch_req(&batch))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        kc.get(b"op_batch_key4").unwrap(),
+        Some(b"\x00protobuf\xff".to_vec())
+    );
+    assert_eq!(kc.get(b"op_batch_key3").unwrap(), None);
+    assert!(kc.del(b"op_batch_key4").unwrap());
+    assert!(kc.put(b"op_batch_key2", b"json").unwrap());
+
+    let url = format!("{url}?duplicates=reject");
+    let ops = json!([
+        {"key": b64("op_batch_key2"), "value": b64("first")},
+        {"key": b64("op_batch_key2"), "value": b64("second")},
+    ]);
+    let resp = client.post(&url).json(&ops).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(kc.get(b"op_batch_key2").unwrap(), Some(b"json".to_vec()));
+
+    t_del(client, db_id, String::from("op_batch_key2"));
 }
 
 fn op_del(client: &Client, db_id: String) {
@@ -316,7 +357,7 @@ fn op_stat(client: &Client, db_id: String) {
 
     t_put(client, db_id.clone(), test_key.clone(), test_value);
 
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
     let stat_url = format!("{}stat", basepath);
 
     // exec db-stat request
@@ -345,6 +386,16 @@ fn op_stat(client: &Client, db_id: String) {
     }
 
     assert_eq!(in_msg.n_records, 1);
+
+    // the same count, as JSON
+    let stats_url = format!("{basepath}stats");
+    let jv: serde_json::Value = client.get(&stats_url).send().unwrap().json().unwrap();
+    assert_eq!(jv["name"], db_id.as_str());
+    assert_eq!(jv["keys"], 1);
+
+    let url = format!("{}{}/_op_stat_nonesuch/stats", T_ENDPOINT, t_api_base());
+    let resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 }
 
 fn op_iter(client: &Client, db_id: String) {
@@ -387,168 +438,4317 @@ fn op_iter(client: &Client, db_id: String) {
     }
 }
 
-fn op_get(client: &Client, db_id: String) {
-    let test_key = String::from("op_key1");
-    let test_value = format!("helloworld op_get {}", db_id);
-
-    t_get_gone(client, db_id.clone(), test_key.clone());
-    t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
-    t_get_ok(client, db_id.clone(), test_key.clone(), test_value);
-    t_del(client, db_id.clone(), test_key.clone());
-    t_get_gone(client, db_id, test_key);
+fn t_keys_page(client: &Client, db_id: &str, query: &[(&str, &str)]) -> serde_json::Value {
+    let url = format!("{}{}/{}/_keys", T_ENDPOINT, t_api_base(), db_id);
+    let mut resp = client.get(&url).query(query).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.json().unwrap()
 }
 
-fn op_clear(client: &Client, db_id: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let clear_url = format!("{}clear", basepath);
-    let test_key = String::from("op_clear_key");
-    let test_value = format!("helloworld op_clear {}", db_id);
-
-    t_get_gone(client, db_id.clone(), test_key.clone());
-    t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
-    t_get_ok(client, db_id.clone(), test_key.clone(), test_value);
-
-    // exec clear-db request
-    let resp_res = client.post(&clear_url).send();
-    match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
-        Err(_e) => assert!(false),
-    }
-
-    t_get_gone(client, db_id, test_key);
+fn t_entries(client: &Client, db_id: &str, query: &[(&str, &str)]) -> serde_json::Value {
+    let url = format!("{}{}/{}/_entries", T_ENDPOINT, t_api_base(), db_id);
+    let mut resp = client.get(&url).query(query).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.json().unwrap()
 }
 
-fn op_put(client: &Client, db_id: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let put_url = format!("{}put", basepath);
-    let get_url = format!("{}get", basepath);
-    let del_url = format!("{}del", basepath);
-    let test_key = String::from("op_put_key");
-    let test_value = format!("helloworld op_put {}", db_id);
-
-    // encode put request
-    let out_bytes = pbenc_update_req(test_key.as_bytes(), test_value.as_bytes());
-
-    // exec put request
-    let resp_res = client.post(&put_url).body(out_bytes).send();
-    match resp_res {
+fn t_export(client: &Client, db_id: &str, query: &[(&str, &str)]) -> String {
+    let url = format!("{}{}/{}/_export", T_ENDPOINT, t_api_base(), db_id);
+    match client.get(&url).query(query).send() {
         Ok(mut resp) => {
             assert_eq!(resp.status(), StatusCode::OK);
-
-            match resp.text() {
-                Ok(_body) => {}
-                Err(_e) => assert!(false),
-            }
+            resp.text().unwrap()
         }
-        Err(_e) => assert!(false),
+        Err(_e) => panic!("export failed"),
     }
+}
 
-    // encode verification get request
-    let out_bytes = pbenc_key_req(test_key.as_bytes());
+// decode JSON lines or CSV export into a batch of inserts
+fn t_export_batch(text: &str, csv: bool) -> Batch {
+    let mut lines = text.lines();
+    if csv {
+        assert_eq!(lines.next(), Some("k,v"));
+    }
+    let mut batch = Batch::default();
+    for line in lines {
+        let (key, val) = if csv {
+            let mut fields = line.split(',');
+            (
+                fields.next().unwrap().to_string(),
+                fields.next().unwrap().to_string(),
+            )
+        } else {
+            let jv: serde_json::Value = serde_json::from_str(line).unwrap();
+            (
+                jv["k"].as_str().unwrap().to_string(),
+                jv["v"].as_str().unwrap().to_string(),
+            )
+        };
+        batch.insert(
+            &base64::decode(&key).unwrap(),
+            &base64::decode(&val).unwrap(),
+        );
+    }
+    batch
+}
 
-    // exec get request
-    let resp_res = client.post(&get_url).body(out_bytes.clone()).send();
-    match resp_res {
+fn t_dump(client: &Client, db_id: &str, query: &[(&str, &str)]) -> Vec<u8> {
+    let url = format!("{}{}/{}/dump", T_ENDPOINT, t_api_base(), db_id);
+    match client.get(&url).query(query).send() {
         Ok(mut resp) => {
             assert_eq!(resp.status(), StatusCode::OK);
-
-            match resp.text() {
-                Ok(body) => assert_eq!(body, test_value),
-                Err(_e) => assert!(false),
-            }
+            let mut body: Vec<u8> = Vec::new();
+            resp.copy_to(&mut body).unwrap();
+            body
         }
-        Err(_e) => assert!(false),
+        Err(_e) => panic!("dump failed"),
     }
+}
 
-    // re-use same KeyRequest bytes for our delete request
+// decode binary dump into a batch of inserts, checking its framing
+fn t_dump_batch(body: &[u8]) -> Batch {
+    fn take<'a>(rest: &mut &'a [u8], n: usize) -> &'a [u8] {
+        let (head, tail) = rest.split_at(n);
+        *rest = tail;
+        head
+    }
+    fn take_len(rest: &mut &[u8]) -> u32 {
+        let mut len = [0u8; 4];
+        len.copy_from_slice(take(rest, 4));
+        u32::from_be_bytes(len)
+    }
 
-    // exec del request
-    let resp_res = client.post(&del_url).body(out_bytes).send();
-    match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
-        Err(_e) => assert!(false),
+    let mut rest = body;
+    assert_eq!(take(&mut rest, 12), b"KVDBDUMP\x00\x00\x00\x01");
+    let mut batch = Batch::default();
+    loop {
+        let key_len = take_len(&mut rest);
+        if key_len == u32::MAX {
+            break;
+        }
+        let key = take(&mut rest, key_len as usize);
+        let val_len = take_len(&mut rest);
+        batch.insert(key, take(&mut rest, val_len as usize));
     }
+    assert!(rest.is_empty());
+    batch
 }
 
-fn op_obj(client: &Client, db_id: String) {
-    let basepath = format!("{}{}/{}/", T_ENDPOINT, T_BASEURI, db_id);
-    let test_key = String::from("1");
-    let test_value = format!("helloworld {}", db_id);
+// export, delete, then re-import via batch:  more records than one
+// iteration page, with binary keys and values
+fn op_export(client: &Client, db_id: String) {
+    const N_RECORDS: usize = 1005;
 
-    // Check that a record with key 1 doesn't exist.
-    let url = format!("{}obj/{}", basepath, test_key);
-    let resp_res = client.get(&url).send();
-    match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
-        Err(_e) => assert!(false),
+    let prefix = b"op_export_";
+    let records: Vec<(Vec<u8>, Vec<u8>)> = (0..N_RECORDS)
+        .map(|n| {
+            let key = [&prefix[..], format!("{n:04}").as_bytes(), &[0xff]].concat();
+            let val = [&b"\",\0 "[..], n.to_string().as_bytes()].concat();
+            (key, val)
+        })
+        .collect();
+    let mut batch = Batch::default();
+    for (key, val) in &records {
+        batch.insert(key, val);
     }
+    let kc = t_client(client, &db_id);
+    assert!(kc.batch(&batch).unwrap());
 
-    // verify DELETE(non exist) returns not-found
-    let resp_res = client.delete(&url).send();
-    match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
-        Err(_e) => assert!(false),
+    let enc_prefix = base64::encode(&prefix[..]);
+    for format in &["jsonl", "csv"] {
+        let text = t_export(
+            client,
+            &db_id,
+            &[("format", format), ("prefix", &enc_prefix)],
+        );
+        let export = t_export_batch(&text, *format == "csv");
+        assert_eq!(export.ops.len(), N_RECORDS);
+
+        let mut removals = Batch::default();
+        for (key, _val) in &records {
+            removals.remove(key);
+        }
+        assert!(kc.batch(&removals).unwrap());
+        assert_eq!(kc.get(&records[0].0).unwrap(), None);
+
+        assert!(kc.batch(&export).unwrap());
+        for (key, val) in &records {
+            assert_eq!(kc.get(key).unwrap().as_ref(), Some(val));
+        }
     }
 
-    // PUT a new record
-    let resp_res = client.put(&url).body(test_value.clone()).send();
-    match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
-        Err(_e) => assert!(false),
+    // dump, as binary, round trips the same way
+    let body = t_dump(client, &db_id, &[("prefix", &enc_prefix)]);
+    let dump = t_dump_batch(&body);
+    assert_eq!(dump.ops.len(), N_RECORDS);
+    let mut removals = Batch::default();
+    for (key, _val) in &records {
+        removals.remove(key);
+    }
+    assert!(kc.batch(&removals).unwrap());
+    assert!(kc.batch(&dump).unwrap());
+    for (key, val) in &records {
+        assert_eq!(kc.get(key).unwrap().as_ref(), Some(val));
     }
 
-    // Check that the record exists with the correct contents.
-    let resp_res = client.get(&url).send();
-    match resp_res {
-        Ok(mut resp) => {
-            assert_eq!(resp.status(), StatusCode::OK);
+    // format defaults to JSON lines; no prefix exports every record
+    let text = t_export(client, &db_id, &[]);
+    assert!(text.lines().count() >= N_RECORDS);
+    assert!(text.starts_with("{\"k\":"));
+    let body = t_dump(client, &db_id, &[]);
+    assert!(t_dump_batch(&body).ops.len() >= N_RECORDS);
+    let body = t_dump(client, &db_id, &[("format", "jsonl")]);
+    assert!(body.starts_with(b"{\"k\":"));
 
-            match resp.text() {
-                Ok(body) => assert_eq!(body, test_value),
-                Err(_e) => assert!(false),
-            }
-        }
-        Err(_e) => assert!(false),
+    let url = format!("{}{}/{}/_export", T_ENDPOINT, t_api_base(), db_id);
+    for query in &[[("format", "xml")], [("prefix", "!!")]] {
+        let resp = client.get(&url).query(query).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
     }
+    let url = format!("{}{}/no_such_db/_export", T_ENDPOINT, t_api_base());
+    let resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
 
-    // Check that the record exists with the correct contents,
-    // protobuf-style.
+    let mut removals = Batch::default();
+    for (key, _val) in &records {
+        removals.remove(key);
+    }
+    assert!(kc.batch(&removals).unwrap());
+}
+
+fn t_import(client: &Client, db_id: &str, query: &str, body: String) -> (StatusCode, String) {
+    let url = format!("{}{}/{}/_import{}", T_ENDPOINT, t_api_base(), db_id, query);
+    match client.post(&url).body(body).send() {
+        Ok(mut resp) => (resp.status(), resp.text().unwrap()),
+        Err(_e) => panic!("import failed"),
+    }
+}
+
+fn t_n_records(client: &Client, db_id: &str) -> u64 {
+    let url = format!("{}{}/{}/stat.json", T_ENDPOINT, t_api_base(), db_id);
+    let jv: serde_json::Value = client.get(&url).send().unwrap().json().unwrap();
+    jv["n_records"].as_str().unwrap().parse().unwrap()
+}
+
+// round trip:  export, clear, import, compare
+fn op_import(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    const N_RECORDS: usize = 1500;
+
+    let mut batch = Batch::default();
+    for n in 0..N_RECORDS {
+        let key = format!("op_import_{n:04}");
+        batch.insert(key.as_bytes(), &[n as u8, 0, 0xff]);
+    }
+    let kc = t_client(client, &db_id);
+    assert!(kc.batch(&batch).unwrap());
+
+    // replace the db's contents with its own export
+    let n_records = t_n_records(client, &db_id);
+    let export = t_export(client, &db_id, &[]);
+    assert_eq!(export.lines().count() as u64, n_records);
+    let mut query = "?clear=1";
+    if t_op_disabled(db_cfg, "clear") {
+        let (status, _text) = t_import(client, &db_id, query, export.clone());
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        query = "";
+    }
+    let (status, text) = t_import(client, &db_id, query, export);
+    assert_eq!(status, StatusCode::OK);
+    let jv: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(jv, json!({"put": n_records, "deleted": 0}));
+    assert_eq!(t_n_records(client, &db_id), n_records);
+    assert_eq!(kc.get(b"op_import_0771").unwrap(), Some(vec![3, 0, 0xff]));
+
+    // merge, deleting; the last line need not end in a newline
+    let mut body = String::from("\n");
+    for n in 0..N_RECORDS {
+        let key = format!("op_import_{n:04}");
+        body.push_str(&format!(
+            "{{\"k\":\"{}\",\"op\":\"del\"}}\n",
+            base64::encode(&key)
+        ));
+    }
+    body.push_str(&format!(
+        "{{\"k\":\"{}\",\"v\":\"{}\"}}",
+        base64::encode("op_import_last"),
+        base64::encode("v")
+    ));
+    let (status, text) = t_import(client, &db_id, "", body);
+    assert_eq!(status, StatusCode::OK);
+    let jv: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(jv, json!({"put": 1, "deleted": N_RECORDS}));
+    assert_eq!(
+        t_n_records(client, &db_id),
+        n_records + 1 - N_RECORDS as u64
+    );
+    assert_eq!(kc.get(b"op_import_0000").unwrap(), None);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("op_import_last"),
+        String::from("v"),
+    );
+
+    // malformed lines are refused
+    for body in &["not json", "{\"k\":\"!!\",\"v\":\"\"}", "{\"k\":\"YQ==\"}"] {
+        let (status, _text) = t_import(client, &db_id, "", body.to_string());
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+    let (status, _text) = t_import(client, "no_such_db", "", String::new());
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    t_del(client, db_id, String::from("op_import_last"));
+}
+
+fn t_restore(client: &Client, db_id: &str, query: &str, body: Vec<u8>) -> (StatusCode, String) {
+    let url = format!("{}{}/{}/restore{}", T_ENDPOINT, t_api_base(), db_id, query);
+    match client.post(&url).body(body).send() {
+        Ok(mut resp) => (resp.status(), resp.text().unwrap()),
+        Err(_e) => panic!("restore failed"),
+    }
+}
+
+fn op_restore(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    const N_RECORDS: usize = 1500;
+
+    let mut batch = Batch::default();
+    for n in 0..N_RECORDS {
+        let key = format!("op_restore_{n:04}");
+        batch.insert(key.as_bytes(), &[n as u8, 0, 0xff]);
+    }
+    let kc = t_client(client, &db_id);
+    assert!(kc.batch(&batch).unwrap());
+
+    // replace the db's contents with its own dump
+    let n_records = t_n_records(client, &db_id);
+    let dump = t_dump(client, &db_id, &[]);
+    let mut query = "?clear=true";
+    if t_op_disabled(db_cfg, "clear") {
+        let (status, _text) = t_restore(client, &db_id, query, dump.clone());
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        query = "";
+    }
+    let (status, text) = t_restore(client, &db_id, query, dump.clone());
+    assert_eq!(status, StatusCode::OK);
+    let jv: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(jv, json!({"put": n_records, "deleted": 0}));
+    assert_eq!(t_n_records(client, &db_id), n_records);
+    assert_eq!(kc.get(b"op_restore_0771").unwrap(), Some(vec![3, 0, 0xff]));
+
+    let mut removals = Batch::default();
+    for n in 0..N_RECORDS {
+        removals.remove(format!("op_restore_{n:04}").as_bytes());
+    }
+    assert!(kc.batch(&removals).unwrap());
+
+    // a dump cut short, or otherwise malformed, is refused
+    let mut one = b"KVDBDUMP\x00\x00\x00\x01".to_vec();
+    for field in &[&b"op_restore_one"[..], b"1"] {
+        one.extend_from_slice(&(field.len() as u32).to_be_bytes());
+        one.extend_from_slice(field);
+    }
+    let whole = [&one[..], &[0xff; 4]].concat();
+    let bad_bodies = vec![
+        one.clone(),                                               // no trailer
+        whole[..whole.len() - 7].to_vec(),                         // partial frame
+        [&whole[..], b"x"].concat(),                               // data after trailer
+        [&b"KVDBDUMP\x00\x00\x00\x02"[..], &whole[12..]].concat(), // unknown version
+        b"{\"k\":\"YQ==\",\"v\":\"\"}".to_vec(),                   // JSON lines
+        Vec::new(),                                                // empty
+    ];
+    for body in bad_bodies {
+        let (status, _text) = t_restore(client, &db_id, "", body);
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+    let (status, text) = t_restore(client, &db_id, "", whole);
+    assert_eq!(status, StatusCode::OK);
+    let jv: serde_json::Value = serde_json::from_str(&text).unwrap();
+    assert_eq!(jv["put"], 1);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("op_restore_one"),
+        String::from("1"),
+    );
+
+    let (status, _text) = t_restore(client, "no_such_db", "", Vec::new());
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    t_del(client, db_id, String::from("op_restore_one"));
+}
+
+fn op_keys_page(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    // binary keys, sorting after any other test's keys
+    let keys: Vec<Vec<u8>> = (1..6)
+        .map(|n| [&[0xffu8, 0xff][..], format!("keys{n}").as_bytes()].concat())
+        .collect();
+    for key in &keys {
+        t_put_bytes(client, db_id.clone(), key, b"x");
+    }
+    let enc_keys: Vec<String> = keys.iter().map(base64::encode).collect();
+    let start = base64::encode(&[0xffu8, 0xff]);
+
+    // first page:  next is the cursor after its last key
+    let jv = t_keys_page(client, &db_id, &[("after", &start), ("limit", "2")]);
+    assert_eq!(jv["keys"], json!(enc_keys[0..2]));
+    assert_eq!(jv["next"], json!(enc_keys[1]));
+    assert_eq!(jv["next_start"], jv["next"]);
+    assert_eq!(jv["list_end"], json!(false));
+    let max_keys = db_cfg["max_iter_keys"].as_u64().unwrap() as usize;
+    assert_eq!(jv["page_size"], json!(max_keys.min(2)));
+
+    // middle page
+    let cursor = jv["next"].as_str().unwrap().to_string();
+    let jv = t_keys_page(client, &db_id, &[("after", &cursor), ("limit", "2")]);
+    assert_eq!(jv["keys"], json!(enc_keys[2..4]));
+    assert_eq!(jv["next"], json!(enc_keys[3]));
+
+    // final page:  no next cursor
+    let cursor = jv["next"].as_str().unwrap().to_string();
+    let jv = t_keys_page(client, &db_id, &[("after", &cursor), ("limit", "2")]);
+    assert_eq!(jv["keys"], json!(enc_keys[4..]));
+    assert_eq!(jv["next"], json!(null));
+    assert_eq!(jv["next_start"], json!(null));
+    assert_eq!(jv["list_end"], json!(true));
+
+    // limit is capped at the db's max_iter_keys, and defaults to it
+    let n_first = keys.len().min(max_keys);
+    let first_next = match n_first < keys.len() {
+        true => json!(enc_keys[n_first - 1]),
+        false => json!(null),
+    };
+    let jv = t_keys_page(client, &db_id, &[("after", &start), ("limit", "1000000")]);
+    assert_eq!(jv["keys"], json!(enc_keys[..n_first]));
+    assert_eq!(jv["next"], first_next);
+    let jv = t_keys_page(client, &db_id, &[]);
+    assert!(!jv["keys"].as_array().unwrap().is_empty());
+    assert_eq!(jv["page_size"], json!(max_keys));
+
+    // prefix:  only its keys, with no next cursor once they run out; an
+    // empty prefix lists every key
+    let jv = t_keys_page(client, &db_id, &[("prefix", &start)]);
+    assert_eq!(jv["keys"], json!(enc_keys[..n_first]));
+    assert_eq!(jv["next"], first_next);
+    let prefix = base64::encode(&keys[2]);
+    let jv = t_keys_page(client, &db_id, &[("prefix", &prefix)]);
+    assert_eq!(jv["keys"], json!([&enc_keys[2]]));
+    assert_eq!(jv["next"], json!(null));
+    let jv = t_keys_page(client, &db_id, &[("prefix", ""), ("limit", "1")]);
+    assert_eq!(jv["keys"].as_array().unwrap().len(), 1);
+
+    // MessagePack:  the page as a KeyList, keys as byte strings
+    let url = format!("{}{}/{}/_keys", T_ENDPOINT, t_api_base(), db_id);
+    let mut resp = client
+        .get(&url)
+        .query(&[("after", &start[..]), ("limit", "2")])
+        .header(header::ACCEPT, "application/msgpack")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/msgpack");
+    let mut body = Vec::new();
+    resp.read_to_end(&mut body).unwrap();
+    let key_list: KeyList = rmp_serde::from_slice(&body).unwrap();
+    assert_eq!(key_list.keys, keys[0..2].to_vec());
+    assert!(!key_list.list_end);
+
+    // protobuf:  the page as a KeyResponse
+    let mut resp = client
+        .get(&url)
+        .query(&[("after", &start[..]), ("limit", "2")])
+        .header(header::ACCEPT, "application/x-protobuf")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        resp.headers()[header::CONTENT_TYPE],
+        "application/x-protobuf"
+    );
+    let mut body = Vec::new();
+    resp.read_to_end(&mut body).unwrap();
+    let in_msg = parse_from_bytes::<KeyResponse>(&body).unwrap();
+    assert_eq!(in_msg.get_keys(), &keys[0..2]);
+    assert!(!in_msg.list_end);
+
+    // reverse:  from the last key, then continuing before the cursor
+    let jv = t_keys_page(client, &db_id, &[("reverse", "1"), ("limit", "2")]);
+    assert_eq!(jv["keys"], json!([&enc_keys[4], &enc_keys[3]]));
+    assert_eq!(jv["next"], json!(enc_keys[3]));
+    let cursor = jv["next"].as_str().unwrap().to_string();
+    let query = [("reverse", "1"), ("after", &cursor), ("limit", "2")];
+    let jv = t_keys_page(client, &db_id, &query);
+    assert_eq!(jv["keys"], json!([&enc_keys[2], &enc_keys[1]]));
+
+    // cursor, as an alias of after:  still resumes once its key is gone
+    let kc = t_client(client, &db_id);
+    let jv = t_keys_page(client, &db_id, &[("cursor", &start), ("limit", "2")]);
+    assert_eq!(jv["keys"], json!(enc_keys[0..2]));
+    assert!(kc.del(&keys[1]).unwrap());
+    let cursor = jv["next"].as_str().unwrap().to_string();
+    let jv = t_keys_page(client, &db_id, &[("cursor", &cursor), ("limit", "2")]);
+    assert_eq!(jv["keys"], json!(enc_keys[2..4]));
+    t_put_bytes(client, db_id.clone(), &keys[1], b"x");
+
+    let url = format!("{}{}/{}/_keys", T_ENDPOINT, t_api_base(), db_id);
+    for query in &[
+        [("after", "!!"), ("limit", "1")],
+        [("after", &start[..]), ("limit", "0")],
+        [("prefix", "!!"), ("limit", "1")],
+        [("after", &start[..]), ("cursor", &start[..])],
+    ] {
+        let resp = client.get(&url).query(query).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+    let mut resp = client.get(&url).query(&[("limit", "0")]).send().unwrap();
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["error"]["message"], "limit must be at least 1");
+
+    for key in &keys {
+        assert!(kc.del(key).unwrap());
+    }
+}
+
+// pages bounded by value bytes:  tiny values, amid huge ones
+fn op_entries(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    const MAX_BYTES: &str = "10000";
+
+    // binary keys, sorting after any other test's keys
+    let keys: Vec<Vec<u8>> = (1..7)
+        .map(|n| [&[0xffu8, 0xff][..], format!("entries{n}").as_bytes()].concat())
+        .collect();
+    let vals: Vec<Vec<u8>> = [3, 20000, 3, 3, 20000, 3]
+        .iter()
+        .enumerate()
+        .map(|(n, len)| vec![b'a' + n as u8; *len])
+        .collect();
+    for (key, val) in keys.iter().zip(&vals) {
+        t_put_bytes(client, db_id.clone(), key, val);
+    }
+    let enc = |n: usize| json!({"k": base64::encode(&keys[n]), "v": base64::encode(&vals[n])});
+
+    // a huge value fills a page alone; tiny ones share, up to the budget
+    let start = base64::encode(&[0xffu8, 0xff]);
+    let mut cursor = start.clone();
+    for page in &[vec![0], vec![1], vec![2, 3], vec![4], vec![5]] {
+        let jv = t_entries(
+            client,
+            &db_id,
+            &[("after", &cursor), ("max_bytes", MAX_BYTES)],
+        );
+        let expected: Vec<serde_json::Value> = page.iter().map(|n| enc(*n)).collect();
+        assert_eq!(jv["entries"], json!(expected));
+        assert_eq!(jv["next_start"], jv["next"]);
+        assert_eq!(jv["list_end"], json!(jv["next"].is_null()));
+        if *page == vec![5] {
+            assert_eq!(jv["next"], json!(null));
+        } else {
+            assert_eq!(
+                jv["next"],
+                json!(base64::encode(&keys[*page.last().unwrap()]))
+            );
+            cursor = jv["next"].as_str().unwrap().to_string();
+        }
+    }
+
+    // without a budget, unless the server caps page bytes; and with a limit
+    let jv = t_entries(client, &db_id, &[("after", &start)]);
+    if db_cfg["max_page_bytes"].is_null() {
+        assert_eq!(jv["entries"].as_array().unwrap().len(), keys.len());
+        assert_eq!(jv["next"], json!(null));
+    } else {
+        assert_eq!(jv["entries"], json!([enc(0)]));
+        assert_eq!(jv["next"], json!(base64::encode(&keys[0])));
+    }
+    let jv = t_entries(client, &db_id, &[("after", &start), ("limit", "2")]);
+    if db_cfg["max_page_bytes"].is_null() {
+        assert_eq!(jv["entries"], json!([enc(0), enc(1)]));
+        assert_eq!(jv["next"], json!(base64::encode(&keys[1])));
+    }
+    let jv = t_entries(client, &db_id, &[("after", &start), ("limit", "1")]);
+    assert_eq!(jv["entries"], json!([enc(0)]));
+    assert_eq!(jv["next"], json!(base64::encode(&keys[0])));
+    assert_eq!(jv["page_size"], json!(1));
+
+    // a key range, start inclusive and end exclusive, resuming after the
+    // cursor within it
+    let (range_start, range_end) = (base64::encode(&keys[2]), base64::encode(&keys[4]));
+    let range = [("start", &range_start[..]), ("end", &range_end[..])];
+    let jv = t_entries(client, &db_id, &range);
+    assert_eq!(jv["entries"], json!([enc(2), enc(3)]));
+    assert_eq!(jv["list_end"], json!(true));
+    assert_eq!(jv["next_start"], json!(null));
+    let jv = t_entries(client, &db_id, &[range[0], range[1], ("limit", "1")]);
+    assert_eq!(jv["entries"], json!([enc(2)]));
+    assert_eq!(jv["list_end"], json!(false));
+    assert_eq!(jv["next_start"], json!(range_start));
+    let jv = t_entries(
+        client,
+        &db_id,
+        &[range[0], range[1], ("after", &range_start), ("limit", "1")],
+    );
+    assert_eq!(jv["entries"], json!([enc(3)]));
+    assert_eq!(jv["list_end"], json!(true));
+    let jv = t_entries(client, &db_id, &[("start", &range_end[..]), ("limit", "1")]);
+    assert_eq!(jv["entries"], json!([enc(4)]));
+    assert_eq!(jv["list_end"], json!(false));
+
+    // MessagePack:  the page as an EntryList
+    let url = format!("{}{}/{}/_entries", T_ENDPOINT, t_api_base(), db_id);
+    let mut resp = client
+        .get(&url)
+        .query(&[("after", &start[..]), ("limit", "1")])
+        .header(header::ACCEPT, "application/msgpack")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let mut body = Vec::new();
+    resp.read_to_end(&mut body).unwrap();
+    let entry_list: EntryList = rmp_serde::from_slice(&body).unwrap();
+    assert_eq!(entry_list.entries, vec![(keys[0].clone(), vals[0].clone())]);
+    assert!(!entry_list.list_end);
+
+    // protobuf:  the page as an EntryResponse, as the client reads it
+    let after = base64::decode(&start).unwrap();
+    let entry_list = t_client(client, &db_id).entries(Some(&after)).unwrap();
+    assert_eq!(entry_list.entries[0], (keys[0].clone(), vals[0].clone()));
+
+    for query in &[
+        [("after", "!!")],
+        [("limit", "0")],
+        [("max_bytes", "-1")],
+        [("start", "!!")],
+        [("end", "!!")],
+    ] {
+        let resp = client.get(&url).query(query).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    let kc = t_client(client, &db_id);
+    for key in &keys {
+        assert!(kc.del(key).unwrap());
+    }
+}
+
+// pages of large keys, cut short by the server's page byte cap
+fn op_page_bytes(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let max_bytes = match db_cfg["max_page_bytes"].as_u64() {
+        None => return,
+        Some(n) => n as usize,
+    };
+
+    // binary keys, sorting after any other test's keys, each encoding to
+    // some 40% of a page
+    let key_len = max_bytes * 3 / 10;
+    let keys: Vec<Vec<u8>> = (1..6)
+        .map(|n| {
+            let mut key = [&[0xffu8, 0xff][..], format!("pagebytes{n}").as_bytes()].concat();
+            key.resize(key_len, b'x');
+            key
+        })
+        .collect();
+    for key in &keys {
+        t_put_bytes(client, db_id.clone(), key, b"x");
+    }
+    let enc_keys: Vec<String> = keys.iter().map(base64::encode).collect();
+
+    // two keys per page, though the limit allows more
+    let mut cursor = base64::encode(&[0xffu8, 0xff]);
+    for page in &[0..2, 2..4] {
+        let jv = t_keys_page(client, &db_id, &[("after", &cursor), ("limit", "10")]);
+        assert_eq!(jv["keys"], json!(enc_keys[page.clone()]));
+        assert_eq!(jv["next"], json!(enc_keys[page.end - 1]));
+        cursor = jv["next"].as_str().unwrap().to_string();
+    }
+    let jv = t_keys_page(client, &db_id, &[("after", &cursor)]);
+    assert_eq!(jv["keys"], json!(enc_keys[4..]));
+    assert_eq!(jv["next"], json!(null));
+
+    // a smaller limit ends the page first
+    let start = base64::encode(&[0xffu8, 0xff]);
+    let jv = t_keys_page(client, &db_id, &[("after", &start), ("limit", "1")]);
+    assert_eq!(jv["keys"], json!(enc_keys[0..1]));
+    assert_eq!(jv["next"], json!(enc_keys[0]));
+
+    let kc = t_client(client, &db_id);
+    for key in &keys {
+        assert!(kc.del(key).unwrap());
+    }
+}
+
+fn t_scan(client: &Client, db_id: &str, glob: &str, lastkey: Option<&str>) -> serde_json::Value {
+    let url = format!("{}{}/{}/_scan", T_ENDPOINT, t_api_base(), db_id);
+    let mut query = vec![("glob", glob)];
+    if let Some(key) = lastkey {
+        query.push(("lastkey", key));
+    }
+    let mut resp = client.get(&url).query(&query).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.json().unwrap()
+}
+
+fn t_size(client: &Client, db_id: &str, start: &str, end: &str) -> u64 {
+    let url = format!("{}{}/{}/_size", T_ENDPOINT, t_api_base(), db_id);
+    let query = [("start", start), ("end", end)];
+    let mut resp = client.get(&url).query(&query).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    jv["bytes"].as_u64().unwrap()
+}
+
+fn op_size(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let records = [("size:a", "1"), ("size:b", "22"), ("size:c", "333")];
+    for (key, val) in &records {
+        t_put(client, db_id.clone(), key.to_string(), val.to_string());
+    }
+
+    // key and value bytes as stored, from start to before end; values
+    // encrypted at rest gain a version byte, nonce and tag, those
+    // checksummed, a version byte and CRC32, and those dated, a header
+    let mut overhead = match db_cfg["encryption_key_file"].as_str() {
+        Some(_path) => 1 + 12 + 16,
+        None => 0,
+    };
+    if db_cfg["verify_checksums"].as_bool() == Some(true) {
+        overhead += 1 + 4;
+    }
+    if db_cfg["track_modified"].as_bool() == Some(true) {
+        overhead += 13 + 8;
+    }
+    if db_cfg["track_versions"].as_bool() == Some(true) {
+        overhead += 11 + 8;
+    }
+    assert_eq!(t_size(client, &db_id, "size:", "size;"), 24 + 3 * overhead);
+    assert_eq!(t_size(client, &db_id, "size:b", "size:c"), 8 + overhead);
+    assert_eq!(t_size(client, &db_id, "size:c", "size:a"), 0);
+    assert!(t_size(client, &db_id, "", "") >= 24);
+
+    let url = format!("{}{}/db_none/_size", T_ENDPOINT, t_api_base());
+    let resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    for (key, _val) in &records {
+        t_del(client, db_id.clone(), key.to_string());
+    }
+}
+
+fn t_count(client: &Client, db_id: &str, query: &[(&str, &str)]) -> serde_json::Value {
+    let url = format!("{}{}/{}/_count", T_ENDPOINT, t_api_base(), db_id);
+    let mut resp = client.get(&url).query(query).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.json().unwrap()
+}
+
+fn op_delete_prefix(client: &Client, db_id: String) {
+    let url = format!("{}{}/{}/keys", T_ENDPOINT, t_api_base(), db_id);
+    let keys = ["delpfx:1:a", "delpfx:1:b", "delpfx:10", "delpfx:2:a"];
+    for key in &keys {
+        t_put(client, db_id.clone(), key.to_string(), String::from("x"));
+    }
+
+    // exactly the keys with the prefix are removed
+    let resp_res = client.delete(&url).query(&[("prefix", "delpfx:1:")]).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            let jv: serde_json::Value = resp.json().unwrap();
+            assert_eq!(jv, json!({"result": true, "deleted": 2}));
+        }
+        Err(e) => panic!("{}", e),
+    }
+    t_get_gone(client, db_id.clone(), String::from("delpfx:1:a"));
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("delpfx:10"),
+        String::from("x"),
+    );
+
+    // an empty or missing prefix is refused, deleting nothing
+    for query in &[&[("prefix", "")][..], &[]] {
+        let resp = client.delete(&url).query(query).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+    let count = t_count(client, &db_id, &[("prefix", "delpfx:"), ("fresh", "1")]);
+    assert_eq!(count["count"], json!(2));
+
+    let resp = client.delete(&url).query(&[("prefix", "delpfx:")]).send();
+    let jv: serde_json::Value = resp.unwrap().json().unwrap();
+    assert_eq!(jv["deleted"], json!(2));
+}
+
+fn op_has_prefix(client: &Client, db_id: String) {
+    let url = format!("{}{}/{}/keys", T_ENDPOINT, t_api_base(), db_id);
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("haspfx:a"),
+        String::from("x"),
+    );
+
+    // 200 if a key has the prefix, 404 if none; no body either way
+    for (prefix, status) in &[
+        ("haspfx:", StatusCode::OK),
+        ("haspfx:a", StatusCode::OK),
+        ("haspfx:b", StatusCode::NOT_FOUND),
+        ("", StatusCode::OK),
+    ] {
+        let resp = client.head(&url).query(&[("prefix", prefix)]).send();
+        match resp {
+            Ok(mut resp) => {
+                assert_eq!(resp.status(), *status);
+                assert_eq!(resp.text().unwrap(), "");
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // a missing prefix is refused
+    let resp = client.head(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    t_del(client, db_id.clone(), String::from("haspfx:a"));
+    let resp = client.head(&url).query(&[("prefix", "haspfx:")]).send();
+    assert_eq!(resp.unwrap().status(), StatusCode::NOT_FOUND);
+}
+
+fn op_count(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    for key in &["count:a", "count:b", "count:c"] {
+        t_put(client, db_id.clone(), key.to_string(), String::from("x"));
+    }
+    let jv = t_count(client, &db_id, &[("prefix", "count:"), ("fresh", "1")]);
+    assert_eq!(jv["count"], json!(3));
+    assert_eq!(jv["cached"], json!(false));
+
+    // with a count cache, the count made above is served until fresh
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("count:d"),
+        String::from("x"),
+    );
+    let jv = t_count(client, &db_id, &[("prefix", "count:")]);
+    if db_cfg["count_cache_secs"].as_u64().unwrap_or(0) > 0 {
+        assert_eq!(jv["count"], json!(3));
+        assert_eq!(jv["cached"], json!(true));
+        assert!(jv["age_ms"].is_u64());
+    } else {
+        assert_eq!(jv["count"], json!(4));
+        assert_eq!(jv["cached"], json!(false));
+    }
+    let jv = t_count(client, &db_id, &[("prefix", "count:"), ("fresh", "1")]);
+    assert_eq!(jv["count"], json!(4));
+    assert_eq!(jv["cached"], json!(false));
+    assert_eq!(
+        t_count(client, &db_id, &[("prefix", "count;")])["count"],
+        json!(0)
+    );
+
+    // the same count, uncached, from stats
+    let stats_url = format!("{}{}/{}/stats", T_ENDPOINT, t_api_base(), db_id);
+    let resp_res = client.get(&stats_url).query(&[("prefix", "count:")]).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            let jv: serde_json::Value = resp.json().unwrap();
+            assert_eq!(jv["prefix"], "count:");
+            assert_eq!(jv["keys"], json!(4));
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    let url = format!("{}{}/db_none/_count", T_ENDPOINT, t_api_base());
+    let resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    for key in &["count:a", "count:b", "count:c", "count:d"] {
+        t_del(client, db_id.clone(), key.to_string());
+    }
+}
+
+fn op_scan(client: &Client, db_id: String) {
+    let keys = [
+        "scan:user:1:active",
+        "scan:user:10:active",
+        "scan:user:2:inactive",
+        "scan:users",
+    ];
+    for key in &keys {
+        t_put_bytes(client, db_id.clone(), key.as_bytes(), b"x");
+    }
+
+    let jv = t_scan(client, &db_id, "scan:user:*:active", None);
+    assert_eq!(
+        jv["keys"],
+        json!(["scan:user:10:active", "scan:user:1:active"])
+    );
+    assert_eq!(jv["list_end"], true);
+    assert_eq!(jv["next_start"], json!(null));
+    let jv = t_scan(client, &db_id, "scan:user:?:*", None);
+    assert_eq!(
+        jv["keys"],
+        json!(["scan:user:1:active", "scan:user:2:inactive"])
+    );
+    let jv = t_scan(client, &db_id, "scan:user*", Some("scan:user:2:inactive"));
+    assert_eq!(jv["keys"], json!(["scan:users"]));
+
+    // glob is required
+    let url = format!("{}{}/{}/_scan", T_ENDPOINT, t_api_base(), db_id);
+    let resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    for key in &keys {
+        t_del(client, db_id.clone(), key.to_string());
+    }
+}
+
+fn t_scan_open(client: &Client, db_id: &str) -> String {
+    let url = format!("{}{}/{}/_scan/open", T_ENDPOINT, t_api_base(), db_id);
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert!(jv["timeout_secs"].as_u64().unwrap() > 0);
+    format!(
+        "{}{}/{}/_scan/{}/",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id,
+        jv["scan"].as_u64().unwrap()
+    )
+}
+
+fn t_scan_next(client: &Client, scan_url: &str) -> (StatusCode, serde_json::Value) {
+    let url = format!("{scan_url}next");
+    let mut resp = client.get(&url).send().unwrap();
+    match resp.status() {
+        StatusCode::OK => (StatusCode::OK, resp.json().unwrap()),
+        status => (status, json!(null)),
+    }
+}
+
+fn op_snapshot_scan(client: &Client, db_id: String) {
+    let records = [
+        ("snapscan:a", "1"),
+        ("snapscan:b", "2"),
+        ("snapscan:c", "3"),
+    ];
+    for (key, val) in &records {
+        t_put(client, db_id.clone(), key.to_string(), val.to_string());
+    }
+
+    // writes after open are not seen by the scan
+    let scan_url = t_scan_open(client, &db_id);
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("snapscan:aa"),
+        String::from("new"),
+    );
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("snapscan:b"),
+        String::from("changed"),
+    );
+    t_del(client, db_id.clone(), String::from("snapscan:c"));
+
+    let mut seen = Vec::new();
+    let (mut n_keys, mut key_bytes, mut value_bytes) = (0, 0, 0);
+    loop {
+        let (status, jv) = t_scan_next(client, &scan_url);
+        assert_eq!(status, StatusCode::OK);
+        let keys = jv["keys"].as_array().unwrap();
+        let values = jv["values"].as_array().unwrap();
+        assert_eq!(keys.len(), values.len());
+        for (key, val) in keys.iter().zip(values) {
+            let key = key.as_str().unwrap();
+            let val = base64::decode(val.as_str().unwrap()).unwrap();
+            n_keys += 1;
+            key_bytes += key.len();
+            value_bytes += val.len();
+            if key.starts_with("snapscan:") {
+                seen.push((key.to_string(), String::from_utf8(val).unwrap()));
+            }
+        }
+        if jv["list_end"].as_bool().unwrap() {
+            break;
+        }
+    }
+    let expected: Vec<(String, String)> = records
+        .iter()
+        .map(|(key, val)| (key.to_string(), val.to_string()))
+        .collect();
+    assert_eq!(seen, expected);
+
+    // at list end, further pages are empty
+    let (status, jv) = t_scan_next(client, &scan_url);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv["keys"], json!([]));
+
+    // close returns totals of every page
+    let url = format!("{scan_url}close");
+    let mut resp = client.post(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["keys"], json!(n_keys));
+    assert_eq!(jv["key_bytes"], json!(key_bytes));
+    assert_eq!(jv["value_bytes"], json!(value_bytes));
+    assert!(n_keys >= records.len() && value_bytes >= 3);
+    assert!(jv["elapsed_ms"].is_u64());
+    let (status, _jv) = t_scan_next(client, &scan_url);
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(
+        client.post(&url).send().unwrap().status(),
+        StatusCode::NOT_FOUND
+    );
+
+    let url = format!("{}{}/db_none/_scan/open", T_ENDPOINT, t_api_base());
+    let resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    t_del(client, db_id.clone(), String::from("snapscan:a"));
+    t_del(client, db_id.clone(), String::from("snapscan:aa"));
+    t_del(client, db_id, String::from("snapscan:b"));
+}
+
+fn t_histogram(client: &Client, db_id: &str, query: &str) -> (StatusCode, serde_json::Value) {
+    let url = format!(
+        "{}{}/{}/_histogram{}",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id,
+        query
+    );
+    let mut resp = client.get(&url).send().unwrap();
+    let status = resp.status();
+    if status != StatusCode::OK {
+        return (status, serde_json::Value::Null);
+    }
+    (status, resp.json().unwrap())
+}
+
+fn op_histogram(client: &Client, db_id: String) {
+    // db may hold other tests' records; check only what these add
+    for i in 1..=20 {
+        let key = format!("hist:{i:02}");
+        t_put_bytes(client, db_id.clone(), key.as_bytes(), &vec![b'v'; 1000 + i]);
+    }
+
+    let (status, jv) = t_histogram(client, &db_id, "");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv["scan_complete"], true);
+    let n_scanned = jv["n_scanned"].as_u64().unwrap();
+    assert_eq!(jv["n_sampled"].as_u64().unwrap(), n_scanned.min(1000));
+    assert!(n_scanned >= 20);
+    assert!(jv["value_len"]["max"].as_u64().unwrap() >= 1020);
+    assert!(jv["key_len"]["p50"].as_u64().unwrap() <= jv["key_len"]["p99"].as_u64().unwrap());
+
+    // bounded scan, smaller sample
+    let (status, jv) = t_histogram(client, &db_id, "?sample=3&max_scan=10");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv["n_scanned"], 10);
+    assert_eq!(jv["n_sampled"], 3);
+    assert_eq!(jv["scan_complete"], false);
+
+    let (status, _jv) = t_histogram(client, &db_id, "?sample=0");
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    for i in 1..=20 {
+        t_del(client, db_id.clone(), format!("hist:{i:02}"));
+    }
+}
+
+fn op_get(client: &Client, db_id: String) {
+    let test_key = String::from("op_key1");
+    let test_value = format!("helloworld op_get {}", db_id);
+
+    t_get_gone(client, db_id.clone(), test_key.clone());
+    t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
+    t_get_ok(client, db_id.clone(), test_key.clone(), test_value);
+
+    // HEAD:  presence only, no body
+    let obj_url = format!("{}{}/{}/obj/{}", T_ENDPOINT, t_api_base(), db_id, test_key);
+    let mut resp = client.head(&obj_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.text().unwrap(), "");
+
+    t_del(client, db_id.clone(), test_key.clone());
+    t_get_gone(client, db_id, test_key);
+    let mut resp = client.head(&obj_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+    assert_eq!(resp.text().unwrap(), "");
+}
+
+fn op_consistency(client: &Client, db_id: String) {
+    let test_key = "op_consistency";
+    let test_value = format!("helloworld op_consistency {db_id}");
+    let obj_url = format!("{}{}/{}/obj/{}", T_ENDPOINT, t_api_base(), db_id, test_key);
+    let mget_url = format!("{}{}/{}/_mget", T_ENDPOINT, t_api_base(), db_id);
+    t_put(
+        client,
+        db_id.clone(),
+        test_key.to_string(),
+        test_value.clone(),
+    );
+
+    // accepted, at any level, and read alike
+    for level in &["strong", "eventual", "Eventual"] {
+        let mut resp = client
+            .get(&obj_url)
+            .header("X-KVDB-Consistency", *level)
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.text().unwrap(), test_value);
+
+        let mut resp = client
+            .post(&mget_url)
+            .header("X-KVDB-Consistency", *level)
+            .json(&[base64::encode(test_key)])
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let jv: serde_json::Value = resp.json().unwrap();
+        assert_eq!(jv["values"], json!([base64::encode(&test_value)]));
+    }
+
+    // an unknown level is refused
+    let resp = client
+        .get(&obj_url)
+        .header("X-KVDB-Consistency", "weak")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    t_del(client, db_id, test_key.to_string());
+}
+
+// run offline inspection subcommand of the kvdbd binary beside this one
+fn t_inspect(args: &[&str]) -> process::Output {
+    let exe = env::current_exe().unwrap().with_file_name("kvdbd");
+    process::Command::new(exe).args(args).output().unwrap()
+}
+
+fn op_inspect(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let path = db_cfg["path"].as_str().unwrap();
+    let test_key = String::from("op_inspect_key");
+    let test_value = format!("helloworld op_inspect {db_id}");
+
+    t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
+
+    // sled is locked by the running server; lmdb may be read alongside it
+    let out = t_inspect(&["get", path, &test_key]);
+    if db_cfg["driver"].as_str() == Some("sled") {
+        assert!(!out.status.success());
+        assert!(String::from_utf8_lossy(&out.stderr).contains("locked by a running server"));
+    } else {
+        assert!(out.status.success());
+        if db_cfg["verify_checksums"].as_bool() == Some(true) {
+            assert!(out.stdout.ends_with(test_value.as_bytes())); // as stored
+        } else {
+            assert_eq!(out.stdout, test_value.as_bytes());
+        }
+
+        let out = t_inspect(&["list", path]);
+        assert!(out.status.success());
+        let keys = String::from_utf8(out.stdout).unwrap();
+        assert!(keys.lines().any(|k| k == test_key));
+
+        let out = t_inspect(&["count", path]);
+        assert!(out.status.success());
+        let n: u64 = String::from_utf8(out.stdout)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert!(n >= 1);
+
+        let out = t_inspect(&["get", path, "op_inspect_missing"]);
+        assert!(!out.status.success());
+    }
+
+    t_del(client, db_id, test_key);
+}
+
+fn op_clear(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let clear_url = format!("{}clear", basepath);
+    let test_key = String::from("op_clear_key");
+    let test_value = format!("helloworld op_clear {}", db_id);
+
+    t_get_gone(client, db_id.clone(), test_key.clone());
+    t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
+    t_get_ok(client, db_id.clone(), test_key.clone(), test_value.clone());
+
+    // exec clear-db request
+    let disabled = t_op_disabled(db_cfg, "clear");
+    let resp_res = client.post(&clear_url).send();
+    match resp_res {
+        Ok(resp) if disabled => assert_eq!(resp.status(), StatusCode::FORBIDDEN),
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(_e) => assert!(false),
+    }
+
+    // ... also reachable as DELETE of the db itself
+    let db_url = basepath.trim_end_matches('/');
+    if !disabled {
+        t_get_gone(client, db_id.clone(), test_key.clone());
+        t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
+    }
+    match client.delete(db_url).send() {
+        Ok(resp) if disabled => assert_eq!(resp.status(), StatusCode::FORBIDDEN),
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+
+    if disabled {
+        // refused without effect; other writes are still permitted
+        t_get_ok(client, db_id.clone(), test_key.clone(), test_value);
+        let test_value = format!("op_clear still writable {db_id}");
+        t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
+        t_get_ok(client, db_id.clone(), test_key.clone(), test_value);
+        t_del(client, db_id, test_key);
+        return;
+    }
+    t_get_gone(client, db_id, test_key);
+}
+
+fn t_obj_rename(client: &Client, db_id: &str, from: &str, query: &str) -> StatusCode {
+    let url = format!(
+        "{}{}/{}/obj/{}?{}",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id,
+        from,
+        query
+    );
+    match client.post(&url).send() {
+        Ok(resp) => resp.status(),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn t_swap(client: &Client, db_id: &str, query: &str) -> StatusCode {
+    let url = format!("{}{}/{}/_swap?{}", T_ENDPOINT, t_api_base(), db_id, query);
+    match client.post(&url).send() {
+        Ok(resp) => resp.status(),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn t_exists(client: &Client, db_id: &str, keys: &[&[u8]]) -> (StatusCode, serde_json::Value) {
+    let url = format!("{}{}/{}/_exists", T_ENDPOINT, t_api_base(), db_id);
+    let enc_keys: Vec<String> = keys.iter().map(base64::encode).collect();
+    match client.post(&url).json(&enc_keys).send() {
+        Ok(mut resp) => {
+            let jv = resp.json().unwrap_or(serde_json::Value::Null);
+            (resp.status(), jv)
+        }
+        Err(_e) => panic!("exists failed"),
+    }
+}
+
+fn t_mdel(client: &Client, db_id: &str, keys: &[&[u8]]) -> (StatusCode, serde_json::Value) {
+    let url = format!("{}{}/{}/_mdel", T_ENDPOINT, t_api_base(), db_id);
+    let enc_keys: Vec<String> = keys.iter().map(base64::encode).collect();
+    match client.post(&url).json(&enc_keys).send() {
+        Ok(mut resp) => {
+            let jv = resp.json().unwrap_or(serde_json::Value::Null);
+            (resp.status(), jv)
+        }
+        Err(_e) => panic!("mdel failed"),
+    }
+}
+
+fn t_index_create(
+    client: &Client,
+    db_id: &str,
+    body: serde_json::Value,
+) -> (StatusCode, serde_json::Value) {
+    let url = format!("{}{}/{}/_index", T_ENDPOINT, t_api_base(), db_id);
+    match client.post(&url).json(&body).send() {
+        Ok(mut resp) => {
+            let jv = resp.json().unwrap_or(serde_json::Value::Null);
+            (resp.status(), jv)
+        }
+        Err(_e) => panic!("index create failed"),
+    }
+}
+
+// keys of records whose indexed field is value, decoded
+fn t_index_lookup(
+    client: &Client,
+    db_id: &str,
+    name: &str,
+    value: &str,
+) -> (StatusCode, Vec<String>) {
+    let url = format!("{}{}/{}/_index/{}", T_ENDPOINT, t_api_base(), db_id, name);
+    match client.get(&url).query(&[("eq", value)]).send() {
+        Ok(mut resp) => {
+            let jv: serde_json::Value = resp.json().unwrap_or(serde_json::Value::Null);
+            let keys = jv["keys"]
+                .as_array()
+                .map(|keys| {
+                    keys.iter()
+                        .map(|k| {
+                            String::from_utf8(base64::decode(k.as_str().unwrap()).unwrap()).unwrap()
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            (resp.status(), keys)
+        }
+        Err(_e) => panic!("index lookup failed"),
+    }
+}
+
+// index entries follow inserts, overwrites and deletes
+fn op_index(client: &Client, db_id: String) {
+    let put = |key: &str, city: &str| {
+        let val = json!({ "op_index_city": city }).to_string();
+        t_put(client, db_id.clone(), key.to_string(), val);
+    };
+    put("op_index_u1", "oslo");
+
+    let index = json!({"name": "op_index", "json_path": "$.op_index_city"});
+    let (status, jv) = t_index_create(client, &db_id, index.clone());
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv, json!({"created": true}));
+    let (status, jv) = t_index_create(client, &db_id, index);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv, json!({"created": false}));
+
+    let conflicting = json!({"name": "op_index", "json_path": "$.other"});
+    let (status, _jv) = t_index_create(client, &db_id, conflicting);
+    assert_eq!(status, StatusCode::CONFLICT);
+    let invalid = json!({"name": "op:index", "json_path": "$.other"});
+    let (status, _jv) = t_index_create(client, &db_id, invalid);
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // existing record indexed on creation; later puts as written
+    put("op_index_u2", "oslo");
+    put("op_index_u3", "rome");
+    let (status, keys) = t_index_lookup(client, &db_id, "op_index", "oslo");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(keys, vec!["op_index_u1", "op_index_u2"]);
+
+    // overwrite and delete remove stale entries
+    put("op_index_u1", "rome");
+    t_del(client, db_id.clone(), String::from("op_index_u3"));
+    let (_status, keys) = t_index_lookup(client, &db_id, "op_index", "oslo");
+    assert_eq!(keys, vec!["op_index_u2"]);
+    let (_status, keys) = t_index_lookup(client, &db_id, "op_index", "rome");
+    assert_eq!(keys, vec!["op_index_u1"]);
+
+    let (status, _keys) = t_index_lookup(client, &db_id, "op_index_missing", "oslo");
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    t_del(client, db_id.clone(), String::from("op_index_u1"));
+    t_del(client, db_id.clone(), String::from("op_index_u2"));
+    let (_status, keys) = t_index_lookup(client, &db_id, "op_index", "rome");
+    assert!(keys.is_empty());
+}
+
+// presence of present and absent keys, in request order
+fn op_exists(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let bin_key: &[u8] = &[0xff, 0x00, b'x'];
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("exists_a"),
+        String::from("alpha"),
+    );
+    t_put_bytes(client, db_id.clone(), bin_key, b"binary");
+
+    let keys: Vec<&[u8]> = vec![b"exists_missing", b"exists_a", bin_key, b"exists_a"];
+    let (status, jv) = t_exists(client, &db_id, &keys);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv, json!({"exists": [false, true, true, true]}));
+
+    let (status, jv) = t_exists(client, &db_id, &[]);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv, json!({"exists": []}));
+
+    // a deleted key is absent
+    t_del(client, db_id.clone(), String::from("exists_a"));
+    let (status, jv) = t_exists(client, &db_id, &[b"exists_a", bin_key]);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv, json!({"exists": [false, true]}));
+
+    // too many keys, and malformed keys
+    let max_keys = db_cfg["max_mget_keys"].as_u64().unwrap() as usize;
+    let keys: Vec<&[u8]> = vec![b"exists_a"; max_keys + 1];
+    let (status, _jv) = t_exists(client, &db_id, &keys);
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let url = format!("{}{}/{}/_exists", T_ENDPOINT, t_api_base(), db_id);
+    for body in &["[\"!!\"]", "{}"] {
+        let resp = client.post(&url).body(body.to_string()).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+
+    assert!(t_client(client, &db_id).del(bin_key).unwrap());
+}
+
+fn op_mdel(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let bin_key: &[u8] = &[0xff, 0x00, b'd'];
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("mdel_a"),
+        String::from("alpha"),
+    );
+    t_put_bytes(client, db_id.clone(), bin_key, b"binary");
+
+    // absent and repeated keys count for nothing more
+    let keys: Vec<&[u8]> = vec![b"mdel_missing", b"mdel_a", bin_key, b"mdel_a"];
+    let (status, jv) = t_mdel(client, &db_id, &keys);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv, json!({"deleted": 2}));
+    t_get_gone(client, db_id.clone(), String::from("mdel_a"));
+    let (status, jv) = t_exists(client, &db_id, &[bin_key]);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv, json!({"exists": [false]}));
+
+    let (status, jv) = t_mdel(client, &db_id, &keys);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv, json!({"deleted": 0}));
+
+    // keys one per line
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("mdel_b"),
+        String::from("beta"),
+    );
+    let url = format!("{}{}/{}/_mdel", T_ENDPOINT, t_api_base(), db_id);
+    let body = format!(
+        "{}\n{}\n",
+        base64::encode("mdel_b"),
+        base64::encode("mdel_c")
+    );
+    let mut resp = client.post(&url).body(body).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv, json!({"deleted": 1}));
+    t_get_gone(client, db_id.clone(), String::from("mdel_b"));
+
+    // too many keys, and malformed keys, remove nothing
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("mdel_a"),
+        String::from("alpha"),
+    );
+    let max_keys = db_cfg["max_mget_keys"].as_u64().unwrap() as usize;
+    let keys: Vec<&[u8]> = vec![b"mdel_a"; max_keys + 1];
+    let (status, _jv) = t_mdel(client, &db_id, &keys);
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    for body in &["[\"!!\"]", "{}"] {
+        let resp = client.post(&url).body(body.to_string()).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    }
+    t_del(client, db_id, String::from("mdel_a"));
+}
+
+fn t_mget(client: &Client, db_id: &str, keys: &[&[u8]]) -> (StatusCode, serde_json::Value) {
+    let mget_url = format!("{}{}/{}/_mget", T_ENDPOINT, t_api_base(), db_id);
+    let enc_keys: Vec<String> = keys.iter().map(base64::encode).collect();
+
+    let resp_res = client.post(&mget_url).json(&enc_keys).send();
+    match resp_res {
+        Ok(mut resp) => {
+            let jv = resp.json().unwrap_or(serde_json::Value::Null);
+            (resp.status(), jv)
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn op_mget(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("mget_a"),
+        String::from("alpha"),
+    );
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("mget_empty"),
+        String::new(),
+    );
+
+    // present, missing and empty values, in request order
+    let keys: Vec<&[u8]> = vec![b"mget_a", b"mget_missing", b"mget_empty", b"mget_a"];
+    let (status, jv) = t_mget(client, &db_id, &keys);
+    assert_eq!(status, StatusCode::OK);
+    let values = jv["values"].as_array().unwrap();
+    assert_eq!(values.len(), 4);
+    assert_eq!(values[0], base64::encode("alpha"));
+    assert!(values[1].is_null());
+    if db_cfg["treat_empty_put_as_delete"].as_bool().unwrap() {
+        assert!(values[2].is_null());
+    } else {
+        assert_eq!(values[2], "");
+    }
+    assert_eq!(values[3], base64::encode("alpha"));
+
+    // keys one per line, rather than a JSON array
+    let url = format!("{}{}/{}/_mget", T_ENDPOINT, t_api_base(), db_id);
+    let body = format!(
+        "{}\n{}\n",
+        base64::encode("mget_missing"),
+        base64::encode("mget_a")
+    );
+    let mut resp = client.post(&url).body(body).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv, json!({ "values": [null, base64::encode("alpha")] }));
+    let resp = client.post(&url).body("not base64!\n").send().unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // too many keys
+    let max_keys = db_cfg["max_mget_keys"].as_u64().unwrap() as usize;
+    let keys: Vec<&[u8]> = vec![b"mget_a"; max_keys + 1];
+    let (status, _jv) = t_mget(client, &db_id, &keys);
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    t_del(client, db_id.clone(), String::from("mget_a"));
+    if !db_cfg["treat_empty_put_as_delete"].as_bool().unwrap() {
+        t_del(client, db_id, String::from("mget_empty"));
+    }
+}
+
+// durable PUTs from many clients at once, each acknowledged only once
+// stored; with group commit configured, they share batches and flushes
+fn op_put_concurrent(client: &Client, db_id: String) {
+    const N_THREADS: usize = 4;
+    const N_PUTS: usize = 10; // per thread
+
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|n| {
+            let client = client.clone();
+            let db_id = db_id.clone();
+            thread::spawn(move || {
+                for i in 0..N_PUTS {
+                    let url = format!(
+                        "{}{}/{}/obj/op_put_concurrent_{}_{}?durable=1",
+                        T_ENDPOINT,
+                        t_api_base(),
+                        db_id,
+                        n,
+                        i
+                    );
+                    match client.put(&url).body(format!("v{i}")).send() {
+                        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+                        Err(e) => panic!("{}", e),
+                    }
+                }
+            })
+        })
+        .collect();
+    for th in threads {
+        th.join().unwrap();
+    }
+
+    for n in 0..N_THREADS {
+        for i in 0..N_PUTS {
+            let key = format!("op_put_concurrent_{n}_{i}");
+            t_get_ok(client, db_id.clone(), key.clone(), format!("v{i}"));
+            t_del(client, db_id.clone(), key);
+        }
+    }
+}
+
+fn op_put_durable(client: &Client, db_id: String) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let url = format!("{basepath}obj/op_put_durable_key");
+    let value = format!("helloworld op_put_durable {db_id}");
+
+    // durable PUT, by query string, then by header
+    let resp_res = client
+        .put(&format!("{url}?durable=1"))
+        .body(value.clone())
+        .send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+    let resp_res = client
+        .put(&url)
+        .header("X-KVDB-Sync", "true")
+        .body(value.clone())
+        .send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("op_put_durable_key"),
+        value,
+    );
+
+    // durable DELETE
+    let resp_res = client.delete(&format!("{url}?durable=true")).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+    t_get_gone(client, db_id, String::from("op_put_durable_key"));
+}
+
+fn t_del_if_empty(client: &Client, db_id: &str, key: &str) -> StatusCode {
+    let url = format!(
+        "{}{}/{}/obj/{}?if_empty=1",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id,
+        key
+    );
+    client.delete(&url).send().unwrap().status()
+}
+
+fn op_del_if_empty(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    // non-empty:  kept
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("del_if_empty_full"),
+        String::from("member:1"),
+    );
+    let status = t_del_if_empty(client, &db_id, "del_if_empty_full");
+    assert_eq!(status, StatusCode::CONFLICT);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("del_if_empty_full"),
+        String::from("member:1"),
+    );
+
+    // absent
+    let status = t_del_if_empty(client, &db_id, "del_if_empty_none");
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // empty:  removed.  (an empty PUT is itself a delete, on some dbs)
+    if !db_cfg["treat_empty_put_as_delete"].as_bool().unwrap() {
+        t_put(
+            client,
+            db_id.clone(),
+            String::from("del_if_empty_empty"),
+            String::new(),
+        );
+        let status = t_del_if_empty(client, &db_id, "del_if_empty_empty");
+        assert_eq!(status, StatusCode::OK);
+        t_get_gone(client, db_id.clone(), String::from("del_if_empty_empty"));
+    }
+
+    t_del(client, db_id, String::from("del_if_empty_full"));
+}
+
+fn t_put_expire_at(client: &Client, db_id: &str, key: &str, expire_at: &str) -> StatusCode {
+    let url = format!("{}{}/{}/obj/{}", T_ENDPOINT, t_api_base(), db_id, key);
+    match client
+        .put(&url)
+        .header("X-KVDB-Expire-At", expire_at)
+        .body("expiring")
+        .send()
+    {
+        Ok(resp) => resp.status(),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn t_put_ttl(client: &Client, db_id: &str, key: &str, ttl_secs: &str) -> StatusCode {
+    let url = format!("{}{}/{}/obj/{}", T_ENDPOINT, t_api_base(), db_id, key);
+    match client
+        .put(&url)
+        .header("X-KVDB-TTL", ttl_secs)
+        .body("expiring")
+        .send()
+    {
+        Ok(resp) => resp.status(),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn op_put_expire_at(client: &Client, db_id: String) {
+    let key = "op_put_expire_at_key";
+    let value = format!("helloworld op_put_expire_at {db_id}");
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+
+    // expiry in the past:  key is absent at once
+    t_put(client, db_id.clone(), String::from(key), value.clone());
+    let past = (now_ms - 1000).to_string();
+    assert_eq!(t_put_expire_at(client, &db_id, key, &past), StatusCode::OK);
+    t_get_gone(client, db_id.clone(), String::from(key));
+
+    // expiry in the future:  present until then, then absent
+    let future = (now_ms + 1000).to_string();
+    assert_eq!(
+        t_put_expire_at(client, &db_id, key, &future),
+        StatusCode::OK
+    );
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from(key),
+        String::from("expiring"),
+    );
+    thread::sleep(Duration::from_millis(1100));
+    t_get_gone(client, db_id.clone(), String::from(key));
+    t_put(client, db_id.clone(), String::from(key), value.clone());
+
+    // malformed expiry
+    assert_eq!(
+        t_put_expire_at(client, &db_id, key, "tomorrow"),
+        StatusCode::BAD_REQUEST
+    );
+    t_get_ok(client, db_id.clone(), String::from(key), value.clone());
+
+    // relative expiry, in seconds:  present until then, then absent,
+    // unlisted and uncounted; cleared by a plain put, or a TTL of "none"
+    let ttl_key = "op_put_ttl_key";
+    let count_ttl = || t_count(client, &db_id, &[("prefix", "op_put_ttl"), ("fresh", "1")]);
+    assert_eq!(t_put_ttl(client, &db_id, ttl_key, "1"), StatusCode::OK);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from(ttl_key),
+        String::from("expiring"),
+    );
+    assert_eq!(count_ttl()["count"], 1);
+    assert_eq!(t_put_ttl(client, &db_id, key, "1"), StatusCode::OK);
+    t_put(client, db_id.clone(), String::from(key), value.clone());
+    let none_key = "op_put_no_ttl";
+    assert_eq!(t_put_ttl(client, &db_id, none_key, "1"), StatusCode::OK);
+    assert_eq!(t_put_ttl(client, &db_id, none_key, "none"), StatusCode::OK);
+    thread::sleep(Duration::from_millis(1100));
+    t_get_gone(client, db_id.clone(), String::from(ttl_key));
+    assert_eq!(count_ttl()["count"], 0);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from(none_key),
+        String::from("expiring"),
+    );
+    t_get_ok(client, db_id.clone(), String::from(key), value.clone());
+    t_del(client, db_id.clone(), String::from(none_key));
+
+    // 0 is absent at once; malformed, or with an absolute expiry too,
+    // rejected
+    assert_eq!(
+        t_put_ttl(client, &db_id, key, "-1"),
+        StatusCode::BAD_REQUEST
+    );
+    let url = format!("{}{}/{}/obj/{}", T_ENDPOINT, t_api_base(), db_id, key);
+    let resp = client
+        .put(&url)
+        .header("X-KVDB-TTL", "0")
+        .header("X-KVDB-Expire-At", past.as_str())
+        .body("expiring")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    t_get_ok(client, db_id.clone(), String::from(key), value);
+    assert_eq!(t_put_ttl(client, &db_id, key, "0"), StatusCode::OK);
+    t_get_gone(client, db_id, String::from(key));
+}
+
+fn op_put_too_large(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let max_key_len = db_cfg["max_key_len"].as_u64().unwrap() as usize;
+    if max_key_len == 0 {
+        return;
+    }
+
+    // a key one byte past the limit is refused, by PUT and by batch
+    let mut test_key = String::from("op_put_too_large_key");
+    while test_key.len() <= max_key_len {
+        test_key.push('k');
+    }
+    let resp_res = client
+        .put(&format!("{basepath}obj/{test_key}"))
+        .body("x")
+        .send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE),
+        Err(e) => panic!("{}", e),
+    }
+
+    let mut batch = Batch::default();
+    batch.insert(b"op_put_too_large_key", b"x");
+    batch.insert(test_key.as_bytes(), b"x");
+    match t_client(client, &db_id).batch(&batch) {
+        Err(ClientError::Status(status)) => assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE),
+        _ => panic!("unexpected result"),
+    }
+    t_get_gone(client, db_id, String::from("op_put_too_large_key"));
+}
+
+// send a PUT of n_bytes to path, with Content-Length or chunked; returns
+// the response status line, once the server answers
+fn t_raw_put(path: &str, n_bytes: usize, chunked: bool) -> String {
+    let host = T_ENDPOINT.trim_start_matches("http://");
+    let mut stream = TcpStream::connect(host).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let framing = if chunked {
+        String::from("Transfer-Encoding: chunked")
+    } else {
+        format!("Content-Length: {n_bytes}")
+    };
+    let head = format!("PUT {path} HTTP/1.1\r\nHost: {host}\r\n{framing}\r\n\r\n");
+    stream.write_all(head.as_bytes()).unwrap();
+
+    // the server may answer, and stop reading, before the body is sent
+    let chunk = vec![b'x'; 16384];
+    let mut n_sent = 0;
+    while n_sent < n_bytes {
+        let n = std::cmp::min(chunk.len(), n_bytes - n_sent);
+        let res = if chunked {
+            stream
+                .write_all(format!("{n:x}\r\n").as_bytes())
+                .and_then(|_| stream.write_all(&chunk[..n]))
+                .and_then(|_| stream.write_all(b"\r\n"))
+        } else {
+            stream.write_all(&chunk[..n])
+        };
+        if res.is_err() {
+            break;
+        }
+        n_sent += n;
+    }
+    if chunked && n_sent == n_bytes {
+        let _ = stream.write_all(b"0\r\n\r\n");
+    }
+
+    let mut buf = [0u8; 4096];
+    let n = stream.read(&mut buf).unwrap();
+    let resp = String::from_utf8_lossy(&buf[..n]).to_string();
+    resp.lines().next().unwrap_or("").to_string()
+}
+
+fn op_request_bytes(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let stats = t_stats(client);
+    let max_bytes = stats["max_request_bytes"].as_u64().unwrap() as usize;
+    let path = format!("{}/{}/obj/op_request_bytes", t_api_base(), db_id);
+
+    // a body at the limit is read, if the db takes values that long
+    let max_value_len = db_cfg["max_value_len"].as_u64().unwrap() as usize;
+    let fits_quota = match stats["quotas"][&db_id]["max_total_bytes"].as_u64() {
+        None => true,
+        Some(n) => n as usize > max_bytes * 2,
+    };
+    if (max_value_len == 0 || max_value_len >= max_bytes) && fits_quota {
+        for chunked in &[false, true] {
+            let status = t_raw_put(&path, max_bytes, *chunked);
+            assert!(status.starts_with("HTTP/1.1 200"), "{}", status);
+            t_del(client, db_id.clone(), String::from("op_request_bytes"));
+        }
+    }
+
+    // one byte more is refused, whether or not its length is declared
+    for chunked in &[false, true] {
+        let status = t_raw_put(&path, max_bytes + 1, *chunked);
+        assert!(status.starts_with("HTTP/1.1 413"), "{}", status);
+    }
+    t_get_gone(client, db_id, String::from("op_request_bytes"));
+}
+
+fn op_put_empty(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let url = format!("{basepath}obj/op_put_empty_key");
+    let keys: Vec<&[u8]> = vec![b"op_put_empty_key"];
+
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("op_put_empty_key"),
+        String::from("x"),
+    );
+
+    // PUT empty body
+    let resp_res = client.put(&url).body("").send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+
+    // _mget distinguishes empty value ("") from absent (null)
+    let (status, jv) = t_mget(client, &db_id, &keys);
+    assert_eq!(status, StatusCode::OK);
+    if db_cfg["treat_empty_put_as_delete"].as_bool().unwrap() {
+        assert!(jv["values"][0].is_null());
+        t_get_gone(client, db_id, String::from("op_put_empty_key"));
+    } else {
+        assert_eq!(jv["values"][0], "");
+
+        // GET of empty value: 200 with empty body
+        let resp_res = client.get(&url).send();
+        match resp_res {
+            Ok(mut resp) => {
+                assert_eq!(resp.status(), StatusCode::OK);
+                assert_eq!(resp.text().unwrap(), "");
+            }
+            Err(e) => panic!("{}", e),
+        }
+        t_get_ok(
+            client,
+            db_id.clone(),
+            String::from("op_put_empty_key"),
+            String::new(),
+        );
+
+        t_del(client, db_id, String::from("op_put_empty_key"));
+    }
+}
+
+fn t_changes(client: &Client, db_id: &str, since: u64) -> (StatusCode, serde_json::Value) {
+    let url = format!(
+        "{}{}/{}/_changes?since={}",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id,
+        since
+    );
+    match client.get(&url).send() {
+        Ok(mut resp) => {
+            let status = resp.status();
+            if status == StatusCode::OK {
+                (status, resp.json().unwrap())
+            } else {
+                (status, serde_json::Value::Null)
+            }
+        }
+        Err(_e) => panic!("changes request failed"),
+    }
+}
+
+fn op_watch(client: &Client, db_id: String) {
+    let url = format!(
+        "{}{}/{}/watch?prefix=op_watch_",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let kc = t_client(client, &db_id);
+
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], "text/event-stream");
+
+    // watching once the header comment arrives; changes to other keys
+    // are not sent
+    let mut text = String::new();
+    let mut buf = [0u8; 4096];
+    while !text.ends_with("\n\n") {
+        let n = resp.read(&mut buf).unwrap();
+        assert!(n > 0);
+        text.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+    }
+    assert_eq!(text, ": watching\n\n");
+    assert!(kc.put(b"op_watch_a", b"1").unwrap());
+    assert!(kc.put(b"op_other", b"1").unwrap());
+    assert!(kc.del(b"op_other").unwrap());
+    assert!(kc.del(b"op_watch_a").unwrap());
+
+    text.clear();
+    while text.matches("\n\n").count() < 2 {
+        let n = resp.read(&mut buf).unwrap();
+        assert!(n > 0);
+        text.push_str(std::str::from_utf8(&buf[..n]).unwrap());
+    }
+    let events: Vec<(&str, &str)> = text
+        .split_terminator("\n\n")
+        .map(|event| {
+            let lines: Vec<&str> = event.lines().collect();
+            assert!(lines[0].starts_with("id: "));
+            (lines[1], lines[2])
+        })
+        .collect();
+    assert_eq!(
+        events,
+        vec![
+            ("event: put", r#"data: {"key":"op_watch_a","op":"put"}"#),
+            ("event: del", r#"data: {"key":"op_watch_a","op":"del"}"#),
+        ]
+    );
+}
+
+fn op_changes(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    if db_cfg["change_log_len"].is_null() {
+        let (status, _v) = t_changes(client, &db_id, 0);
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        return;
+    }
+
+    // current position
+    let (status, v) = t_changes(client, &db_id, 0);
+    assert_eq!(status, StatusCode::OK);
+    let last_seq = v["last_seq"].as_u64().unwrap();
+
+    // new changes are listed after it, in order
+    let test_key = String::from("op_changes_key");
+    t_put(client, db_id.clone(), test_key.clone(), String::from("v"));
+    t_del(client, db_id.clone(), test_key.clone());
+
+    let (status, v) = t_changes(client, &db_id, last_seq);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(v["needs_resync"], false);
+    assert_eq!(v["list_end"], true);
+    assert_eq!(v["last_seq"].as_u64().unwrap(), last_seq + 2);
+    let enc_key = base64::encode(&test_key);
+    assert_eq!(
+        v["changes"],
+        json!([
+            {"seq": last_seq + 1, "op": "put", "key": enc_key},
+            {"seq": last_seq + 2, "op": "del", "key": enc_key}
+        ])
+    );
+}
+
+fn t_txn_begin(client: &Client, db_id: &str) -> (String, u64) {
+    let url = format!("{}{}/{}/_txn/begin", T_ENDPOINT, t_api_base(), db_id);
+    let mut resp = client.post(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    let txn_url = format!(
+        "{}{}/{}/_txn/{}/",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id,
+        jv["txn"].as_u64().unwrap()
+    );
+    (txn_url, jv["timeout_secs"].as_u64().unwrap())
+}
+
+fn t_txn_get(client: &Client, txn_url: &str, key: &str) -> (StatusCode, String) {
+    let url = format!("{txn_url}obj/{key}");
+    let mut resp = client.get(&url).send().unwrap();
+    (resp.status(), resp.text().unwrap())
+}
+
+fn t_txn_put(client: &Client, txn_url: &str, key: &str, value: &str) -> StatusCode {
+    let url = format!("{txn_url}obj/{key}");
+    client
+        .put(&url)
+        .body(value.to_string())
+        .send()
+        .unwrap()
+        .status()
+}
+
+fn t_txn_end(client: &Client, txn_url: &str, op: &str) -> StatusCode {
+    let url = format!("{txn_url}{op}");
+    client.post(&url).send().unwrap().status()
+}
+
+fn op_txn(client: &Client, db_id: String) {
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("txn_a"),
+        String::from("10"),
+    );
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("txn_b"),
+        String::from("5"),
+    );
+
+    // move 3 from a to b; writes invisible outside txn until commit
+    let (txn_url, timeout_secs) = t_txn_begin(client, &db_id);
+    assert_eq!(
+        t_txn_get(client, &txn_url, "txn_a"),
+        (StatusCode::OK, String::from("10"))
+    );
+    assert_eq!(
+        t_txn_get(client, &txn_url, "txn_b"),
+        (StatusCode::OK, String::from("5"))
+    );
+    assert_eq!(t_txn_put(client, &txn_url, "txn_a", "7"), StatusCode::OK);
+    assert_eq!(t_txn_put(client, &txn_url, "txn_b", "8"), StatusCode::OK);
+    assert_eq!(
+        t_txn_get(client, &txn_url, "txn_a"),
+        (StatusCode::OK, String::from("7"))
+    );
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("txn_a"),
+        String::from("10"),
+    );
+    assert_eq!(t_txn_end(client, &txn_url, "commit"), StatusCode::OK);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("txn_a"),
+        String::from("7"),
+    );
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("txn_b"),
+        String::from("8"),
+    );
+
+    // transaction is closed by commit
+    assert_eq!(t_txn_end(client, &txn_url, "commit"), StatusCode::NOT_FOUND);
+    assert_eq!(
+        t_txn_get(client, &txn_url, "txn_a").0,
+        StatusCode::NOT_FOUND
+    );
+
+    // concurrent txns read the same key; the second commit conflicts
+    let (txn1_url, _timeout_secs) = t_txn_begin(client, &db_id);
+    let (txn2_url, _timeout_secs) = t_txn_begin(client, &db_id);
+    assert_eq!(t_txn_get(client, &txn1_url, "txn_a").0, StatusCode::OK);
+    assert_eq!(t_txn_get(client, &txn2_url, "txn_a").0, StatusCode::OK);
+    assert_eq!(t_txn_put(client, &txn1_url, "txn_a", "1"), StatusCode::OK);
+    assert_eq!(t_txn_put(client, &txn2_url, "txn_a", "2"), StatusCode::OK);
+    assert_eq!(t_txn_put(client, &txn2_url, "txn_b", "2"), StatusCode::OK);
+    assert_eq!(t_txn_end(client, &txn1_url, "commit"), StatusCode::OK);
+    assert_eq!(t_txn_end(client, &txn2_url, "commit"), StatusCode::CONFLICT);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("txn_a"),
+        String::from("1"),
+    );
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("txn_b"),
+        String::from("8"),
+    );
+    assert_eq!(
+        t_txn_end(client, &txn2_url, "rollback"),
+        StatusCode::NOT_FOUND
+    );
+
+    // rollback discards writes
+    let (txn_url, _timeout_secs) = t_txn_begin(client, &db_id);
+    assert_eq!(t_txn_put(client, &txn_url, "txn_a", "99"), StatusCode::OK);
+    let url = format!("{txn_url}obj/txn_b");
+    assert_eq!(client.delete(&url).send().unwrap().status(), StatusCode::OK);
+    assert_eq!(t_txn_end(client, &txn_url, "rollback"), StatusCode::OK);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("txn_a"),
+        String::from("1"),
+    );
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("txn_b"),
+        String::from("8"),
+    );
+
+    // txn ids are bound to their db
+    let (txn_url, _timeout_secs) = t_txn_begin(client, &db_id);
+    let other_url = txn_url.replace(&format!("/{db_id}/"), "/no-such-db/");
+    assert_eq!(
+        t_txn_end(client, &other_url, "commit"),
+        StatusCode::NOT_FOUND
+    );
+
+    // abandoned txn is rolled back after its timeout, if short enough to
+    // wait for
+    assert_eq!(t_txn_put(client, &txn_url, "txn_a", "99"), StatusCode::OK);
+    if timeout_secs <= 2 {
+        thread::sleep(Duration::from_secs(timeout_secs + 1));
+        assert_eq!(t_txn_end(client, &txn_url, "commit"), StatusCode::NOT_FOUND);
+        t_get_ok(
+            client,
+            db_id.clone(),
+            String::from("txn_a"),
+            String::from("1"),
+        );
+    } else {
+        assert_eq!(t_txn_end(client, &txn_url, "rollback"), StatusCode::OK);
+    }
+
+    t_del(client, db_id.clone(), String::from("txn_a"));
+    t_del(client, db_id, String::from("txn_b"));
+}
+
+fn op_api_base(client: &Client, db_id: String) {
+    let stat_path = format!("/{db_id}/stat.json");
+
+    // db is served beneath the configured base path...
+    let url = format!("{}{}{}", T_ENDPOINT, t_api_base(), stat_path);
+    match client.get(&url).send() {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+
+    // ...and nowhere else
+    if t_api_base() != T_DEF_API_BASE {
+        let url = format!("{T_ENDPOINT}{T_DEF_API_BASE}{stat_path}");
+        match client.get(&url).send() {
+            Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+fn t_method_not_allowed(client: &Client, method: Method, url: &str, allow: &str) {
+    match client.request(method, url).send() {
+        Ok(resp) => {
+            assert_eq!(resp.status(), StatusCode::METHOD_NOT_ALLOWED);
+            assert_eq!(resp.headers()[header::ALLOW], allow);
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn op_method_not_allowed(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let db_url = format!("{}{}/{}", T_ENDPOINT, t_api_base(), db_id);
+
+    // a path answers the methods it does not serve with 405, and Allow
+    if !t_op_disabled(db_cfg, "stat") {
+        let url = format!("{db_url}/stat");
+        t_method_not_allowed(client, Method::DELETE, &url, "GET");
+    }
+    if !t_op_disabled(db_cfg, "put") {
+        let url = format!("{db_url}/obj/op_method_not_allowed");
+        t_method_not_allowed(client, Method::PATCH, &url, "GET, HEAD, POST, PUT, DELETE");
+    }
+    let url = format!("{db_url}/keys");
+    t_method_not_allowed(client, Method::PUT, &url, "HEAD, POST, DELETE");
+    let url = format!("{T_ENDPOINT}/health");
+    t_method_not_allowed(client, Method::POST, &url, "GET");
+
+    // a path matching nothing is 404, by any method
+    let url = format!("{db_url}/op_method_not_allowed");
+    match client.post(&url).send() {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn op_http2(db_id: String, http2: bool) {
+    const N_THREADS: usize = 8;
+
+    // h2c with prior knowledge; all threads share this client's connection
+    let h2_client = Client::builder().h2_prior_knowledge().build().unwrap();
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+
+    if !http2 {
+        let url = format!("{basepath}stat.json");
+        match h2_client.get(&url).send() {
+            Ok(resp) => assert_eq!(resp.status(), StatusCode::HTTP_VERSION_NOT_SUPPORTED),
+            Err(e) => panic!("{}", e),
+        }
+        return;
+    }
+
+    // concurrent streams, multiplexed over one connection
+    let mut threads = Vec::new();
+    for t in 0..N_THREADS {
+        let h2_client = h2_client.clone();
+        let url = format!("{basepath}obj/op_http2_key{t}");
+        threads.push(thread::spawn(move || {
+            let value = format!("h2 value {t}");
+            match h2_client.put(&url).body(value.clone()).send() {
+                Ok(resp) => {
+                    assert_eq!(resp.version(), reqwest::Version::HTTP_2);
+                    assert_eq!(resp.status(), StatusCode::OK);
+                }
+                Err(e) => panic!("{}", e),
+            }
+            match h2_client.get(&url).send() {
+                Ok(mut resp) => {
+                    assert_eq!(resp.version(), reqwest::Version::HTTP_2);
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    assert_eq!(resp.text().unwrap(), value);
+                }
+                Err(e) => panic!("{}", e),
+            }
+            match h2_client.delete(&url).send() {
+                Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+                Err(e) => panic!("{}", e),
+            }
+        }));
+    }
+    for th in threads {
+        th.join().unwrap();
+    }
+
+    // HTTP/1.1 clients are still served
+    let url = format!("{basepath}stat.json");
+    match Client::new().get(&url).send() {
+        Ok(resp) => {
+            assert_eq!(resp.version(), reqwest::Version::HTTP_11);
+            assert_eq!(resp.status(), StatusCode::OK);
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn op_swap(client: &Client, db_id: String) {
+    let value1 = format!("helloworld op_swap 1 {db_id}");
+    let value2 = format!("helloworld op_swap 2 {db_id}");
+
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("swap_a"),
+        value1.clone(),
+    );
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("swap_b"),
+        value2.clone(),
+    );
+
+    // both present
+    let status = t_swap(client, &db_id, "a=swap_a&b=swap_b");
+    assert_eq!(status, StatusCode::OK);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("swap_a"),
+        value2.clone(),
+    );
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("swap_b"),
+        value1.clone(),
+    );
+
+    // one absent:  value moves, absent key is deleted
+    let status = t_swap(client, &db_id, "a=swap_b&b=swap_c");
+    assert_eq!(status, StatusCode::OK);
+    t_get_gone(client, db_id.clone(), String::from("swap_b"));
+    t_get_ok(client, db_id.clone(), String::from("swap_c"), value1);
+
+    // both absent
+    let status = t_swap(client, &db_id, "a=swap_b&b=swap_none");
+    assert_eq!(status, StatusCode::OK);
+    t_get_gone(client, db_id.clone(), String::from("swap_b"));
+    t_get_gone(client, db_id.clone(), String::from("swap_none"));
+
+    // missing key
+    let status = t_swap(client, &db_id, "a=swap_a");
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    t_get_ok(client, db_id.clone(), String::from("swap_a"), value2);
+
+    t_del(client, db_id.clone(), String::from("swap_a"));
+    t_del(client, db_id, String::from("swap_c"));
+}
+
+fn op_obj_rename(client: &Client, db_id: String) {
+    let value1 = format!("helloworld op_obj_rename 1 {db_id}");
+    let value2 = format!("helloworld op_obj_rename 2 {db_id}");
+
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("rename_a"),
+        value1.clone(),
+    );
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("rename_b"),
+        value2.clone(),
+    );
+
+    // absent source
+    let status = t_obj_rename(client, &db_id, "rename_none", "rename_to=rename_c");
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // conflict, without overwrite
+    let status = t_obj_rename(client, &db_id, "rename_a", "rename_to=rename_b");
+    assert_eq!(status, StatusCode::CONFLICT);
+    t_get_ok(client, db_id.clone(), String::from("rename_b"), value2);
+
+    // overwrite
+    let query = "rename_to=rename_b&overwrite=true";
+    let status = t_obj_rename(client, &db_id, "rename_a", query);
+    assert_eq!(status, StatusCode::OK);
+    t_get_gone(client, db_id.clone(), String::from("rename_a"));
+    t_get_ok(client, db_id.clone(), String::from("rename_b"), value1);
+
+    // missing target
+    let status = t_obj_rename(client, &db_id, "rename_b", "");
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    t_del(client, db_id, String::from("rename_b"));
+}
+
+fn op_obj_keyenc(client: &Client, db_id: String) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let kc = t_client(client, &db_id);
+    let b64 = |key: &[u8]| base64::encode_config(key, base64::URL_SAFE_NO_PAD);
+    let test_key: &[u8] = b"op_keyenc/\x00\xff";
+    let moved_key: &[u8] = b"op_keyenc/\xfe";
+    let test_value = format!("helloworld op_obj_keyenc {db_id}");
+    let url = |key: &[u8]| format!("{}obj/{}?keyenc=base64", basepath, b64(key));
+
+    // a key no path can spell:  slash, null byte and 0xff
+    let resp_res = client.put(&url(test_key)).body(test_value.clone()).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+    assert_eq!(
+        kc.get(test_key).unwrap(),
+        Some(test_value.as_bytes().to_vec())
+    );
+    let resp_res = client.get(&url(test_key)).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.text().unwrap(), test_value);
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    // listed in the same encoding, resuming after an encoded key
+    let list_url = format!(
+        "{}keys.json?keyenc=base64&lastkey={}",
+        basepath,
+        b64(b"op_keyenc")
+    );
+    let resp_res = client.get(&list_url).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            let jv: serde_json::Value = resp.json().unwrap();
+            assert_eq!(jv["keys"][0], b64(test_key));
+            match jv["list_end"].as_bool() {
+                Some(true) => assert!(jv["next_start"].is_null()),
+                _ => assert_eq!(
+                    jv["next_start"],
+                    *jv["keys"].as_array().unwrap().last().unwrap()
+                ),
+            }
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    // renamed to an encoded key, then deleted by it
+    let query = format!("keyenc=base64&rename_to={}", b64(moved_key));
+    let status = t_obj_rename(client, &db_id, &b64(test_key), &query);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(kc.get(test_key).unwrap(), None);
+    let resp_res = client.delete(&url(moved_key)).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+    assert_eq!(kc.get(moved_key).unwrap(), None);
+
+    // an unknown encoding, or a key not in it, is refused
+    for bad_url in [
+        format!("{}obj/{}?keyenc=rot13", basepath, b64(test_key)),
+        format!("{basepath}obj/not*base64?keyenc=base64"),
+    ] {
+        let resp_res = client.get(&bad_url).send();
+        match resp_res {
+            Ok(resp) => assert_eq!(resp.status(), StatusCode::BAD_REQUEST),
+            Err(e) => panic!("{}", e),
+        }
+    }
+}
+
+fn op_obj_init(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    const N_THREADS: usize = 8;
+    let url = format!("{}{}/{}/obj/op_init/init", T_ENDPOINT, t_api_base(), db_id);
+
+    // concurrent initializers, each with its own value, all see one
+    let mut threads = Vec::new();
+    for t in 0..N_THREADS {
+        let client = client.clone();
+        let url = url.clone();
+        threads.push(thread::spawn(move || {
+            let mut resp = client.post(&url).body(format!("init {t}")).send().unwrap();
+            assert_eq!(resp.status(), StatusCode::OK);
+            resp.text().unwrap()
+        }));
+    }
+    let vals: HashSet<String> = threads.into_iter().map(|th| th.join().unwrap()).collect();
+    assert_eq!(vals.len(), 1);
+    let kc = t_client(client, &db_id);
+    let stored = kc.get(b"op_init").unwrap().unwrap();
+    assert!(vals.contains(&String::from_utf8(stored).unwrap()));
+
+    // an empty init is refused where it would be a delete
+    let resp = client
+        .post(&url.replace("op_init", "op_init_empty"))
+        .send()
+        .unwrap();
+    if db_cfg["treat_empty_put_as_delete"].as_bool().unwrap() {
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        t_get_gone(client, db_id.clone(), String::from("op_init_empty"));
+    } else {
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(kc.del(b"op_init_empty").unwrap());
+    }
+    assert!(kc.del(b"op_init").unwrap());
+}
+
+// concurrent read-modify-writes of one key lose no updates:  appends, and
+// increments made by guarded batches, retried on conflict
+fn op_concurrent_rmw(client: &Client, db_id: String) {
+    const N_THREADS: usize = 8;
+    const N_WRITES: usize = 25;
+    let url = format!(
+        "{}{}/{}/obj/op_rmw_log/append",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("op_rmw_counter"),
+        String::from("0"),
+    );
+
+    let mut threads = Vec::new();
+    for _t in 0..N_THREADS {
+        let client = client.clone();
+        let url = url.clone();
+        let db_id = db_id.clone();
+        threads.push(thread::spawn(move || {
+            let kc = t_client(&client, &db_id);
+            let mut lens = Vec::new();
+            for _i in 0..N_WRITES {
+                let mut resp = client.post(&url).body("x").send().unwrap();
+                assert_eq!(resp.status(), StatusCode::OK);
+                let jv: serde_json::Value = resp.json().unwrap();
+                lens.push(jv["len"].as_u64().unwrap());
+
+                loop {
+                    let old = kc.get(b"op_rmw_counter").unwrap().unwrap();
+                    let n: u64 = String::from_utf8(old.clone()).unwrap().parse().unwrap();
+                    let mut batch = Batch {
+                        guard: Some((b"op_rmw_counter".to_vec(), Some(old))),
+                        ..Batch::default()
+                    };
+                    batch.insert(b"op_rmw_counter", (n + 1).to_string().as_bytes());
+                    match kc.batch(&batch) {
+                        Ok(_) => break,
+                        Err(ClientError::Status(StatusCode::CONFLICT)) => continue,
+                        Err(e) => panic!("batch failed: {}", e),
+                    }
+                }
+            }
+            lens
+        }));
+    }
+
+    // each append saw a length no other did
+    let mut lens: Vec<u64> = Vec::new();
+    for th in threads {
+        lens.extend(th.join().unwrap());
+    }
+    lens.sort();
+    let n_total = (N_THREADS * N_WRITES) as u64;
+    assert_eq!(lens, (1..=n_total).collect::<Vec<u64>>());
+
+    let kc = t_client(client, &db_id);
+    assert_eq!(
+        kc.get(b"op_rmw_counter").unwrap(),
+        Some(n_total.to_string().into_bytes())
+    );
+    assert_eq!(
+        kc.get(b"op_rmw_log").unwrap(),
+        Some(vec![b'x'; n_total as usize])
+    );
+    assert!(kc.del(b"op_rmw_counter").unwrap());
+    assert!(kc.del(b"op_rmw_log").unwrap());
+}
+
+fn op_obj_append(client: &Client, db_id: String) {
+    let url = format!(
+        "{}{}/{}/obj/op_append/append",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let kc = t_client(client, &db_id);
+
+    // absent key created, then grown; each reply gives the new length
+    for (suffix, len) in [("line1\n", 6), ("line2\n", 12)] {
+        let resp_res = client.post(&url).body(suffix).send();
+        match resp_res {
+            Ok(mut resp) => {
+                assert_eq!(resp.status(), StatusCode::OK);
+                let jv: serde_json::Value = resp.json().unwrap();
+                assert_eq!(jv["len"], len);
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+    let resp_res = client
+        .post(&format!("{url}?durable=1"))
+        .body("line3\n")
+        .send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+    assert_eq!(
+        kc.get(b"op_append").unwrap(),
+        Some(b"line1\nline2\nline3\n".to_vec())
+    );
+    assert!(kc.del(b"op_append").unwrap());
+}
+
+fn op_obj_merge(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let url = format!(
+        "{}{}/{}/obj/op_merge/merge",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let kc = t_client(client, &db_id);
+
+    for operand in [r#"{"a":1}"#, r#"{"b":2}"#] {
+        let resp_res = client.post(&url).body(operand).send();
+        match resp_res {
+            Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    // merged by the db's operator; by default, the last operand wins
+    let merged = match db_cfg["merge"].as_str() {
+        Some("json_shallow") => r#"{"a":1,"b":2}"#,
+        _ => r#"{"b":2}"#,
+    };
+    assert_eq!(
+        kc.get(b"op_merge").unwrap(),
+        Some(merged.as_bytes().to_vec())
+    );
+    assert!(kc.del(b"op_merge").unwrap());
+}
+
+fn op_blob_put(client: &Client, db_id: String) {
+    let url = format!("{}{}/{}/blobs", T_ENDPOINT, t_api_base(), db_id);
+    let kc = t_client(client, &db_id);
+    let key = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    // stored under its SHA-256 hash; storing it again changes nothing
+    for _i in 0..2 {
+        let resp_res = client.post(&url).body("abc").send();
+        match resp_res {
+            Ok(mut resp) => {
+                assert_eq!(resp.status(), StatusCode::CREATED);
+                assert_eq!(
+                    resp.headers()["location"],
+                    format!("{}/{}/obj/{}", t_api_base(), db_id, key)
+                );
+                let jv: serde_json::Value = resp.json().unwrap();
+                assert_eq!(jv["key"], key);
+            }
+            Err(e) => panic!("{}", e),
+        }
+    }
+    assert_eq!(kc.get(key.as_bytes()).unwrap(), Some(b"abc".to_vec()));
+
+    // a hash key holding other content is not overwritten
+    assert!(kc.put(key.as_bytes(), b"not abc").unwrap());
+    match client.post(&url).body("abc").send() {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::CONFLICT),
+        Err(e) => panic!("{}", e),
+    }
+    assert_eq!(kc.get(key.as_bytes()).unwrap(), Some(b"not abc".to_vec()));
+    assert!(kc.del(key.as_bytes()).unwrap());
+}
+
+fn op_obj_put_stream(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let key = "op_obj_put_stream";
+    let url = format!("{basepath}obj/{key}");
+
+    // a value over one response chunk, spooled in, streamed back out;
+    // smaller, within a quota
+    let value_len = match db_cfg["max_total_bytes"].as_u64() {
+        Some(max_total_bytes) if max_total_bytes > 0 => 1000,
+        _ => 70_000,
+    };
+    let value: Vec<u8> = (0..value_len).map(|i| (i % 251) as u8).collect();
+    let resp_res = client.put(&url).body(value.clone()).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            let jv: serde_json::Value = resp.json().unwrap();
+            assert_eq!(jv["result"], true);
+        }
+        Err(_e) => panic!("streamed put failed"),
+    }
+    match client.get(&url).send() {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers()["content-length"], &value_len.to_string());
+            let mut body: Vec<u8> = Vec::new();
+            resp.copy_to(&mut body).unwrap();
+            assert!(body == value);
+        }
+        Err(_e) => panic!("get of streamed value failed"),
+    }
+
+    // a conditional PUT, however long, is buffered, and its condition
+    // applied
+    let resp_res = client
+        .put(&url)
+        .header("If-None-Match", "*")
+        .body(value.clone())
+        .send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED),
+        Err(_e) => panic!("conditional put failed"),
+    }
+
+    // a value over max_value_len is refused as it arrives; nothing stored
+    let max_value_len = db_cfg["max_value_len"].as_u64().unwrap() as usize;
+    let max_request_bytes = t_stats(client)["max_request_bytes"].as_u64().unwrap() as usize;
+    if max_value_len > 0 && max_value_len < max_request_bytes {
+        let too_large = vec![b'x'; max_value_len + 1];
+        let resp_res = client
+            .put(&format!("{url}_too_large"))
+            .body(too_large)
+            .send();
+        match resp_res {
+            Ok(resp) => assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE),
+            Err(_e) => panic!("oversized put failed"),
+        }
+        t_get_gone(client, db_id.clone(), format!("{key}_too_large"));
+    }
+
+    let url = format!("{}{}/no_such_db/obj/{}", T_ENDPOINT, t_api_base(), key);
+    let resp = client.put(&url).body(vec![b'x'; 70_000]).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    t_del(client, db_id, String::from(key));
+}
+
+// touch extends expiry, leaving the value
+fn op_obj_touch(client: &Client, db_id: String) {
+    let status = t_obj_rename(client, &db_id, "touch_none", "touch=60");
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    // the key outlives its original expiry
+    assert_eq!(t_put_ttl(client, &db_id, "touch_a", "1"), StatusCode::OK);
+    let status = t_obj_rename(client, &db_id, "touch_a", "touch=60");
+    assert_eq!(status, StatusCode::OK);
+    thread::sleep(Duration::from_millis(1100));
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("touch_a"),
+        String::from("expiring"),
+    );
+
+    let status = t_obj_rename(client, &db_id, "touch_a", "touch=60&rename_to=touch_b");
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // zero ttl expires at once
+    let status = t_obj_rename(client, &db_id, "touch_a", "touch=0");
+    assert_eq!(status, StatusCode::OK);
+    t_get_gone(client, db_id, String::from("touch_a"));
+}
+
+fn op_client(db_id: String) {
+    let kc = t_client(&Client::new(), &db_id);
+
+    // binary keys and values need no encoding
+    let key1: &[u8] = b"op_client\x00\xff/key1";
+    let key2: &[u8] = b"op_client\x00\xff/key2";
+    let val: &[u8] = b"\x00\x01\x02 op_client value";
+
+    assert_eq!(kc.get(key1).unwrap(), None);
+    assert!(kc.put(key1, val).unwrap());
+    assert_eq!(kc.get(key1).unwrap(), Some(val.to_vec()));
+
+    let mut batch = Batch::default();
+    batch.remove(key1);
+    batch.insert(key2, val);
+    assert!(kc.batch(&batch).unwrap());
+    assert_eq!(kc.get(key1).unwrap(), None);
+    assert_eq!(kc.get(key2).unwrap(), Some(val.to_vec()));
+
+    let mut opts = IterOptions::new();
+    opts.prefix(b"op_client\x00");
+    let key_list = kc.iter(opts).unwrap();
+    assert_eq!(key_list.keys, vec![key2.to_vec()]);
+    assert!(key_list.list_end);
+
+    assert!(kc.del(key2).unwrap());
+    assert!(!kc.del(key2).unwrap());
+
+    // a write replayed under the same idempotency key is applied once
+    let idem_key = format!("op_client {db_id}");
+    assert!(kc.put_idempotent(key1, val, &idem_key).unwrap());
+    assert!(kc.put_idempotent(key1, b"replay", &idem_key).unwrap());
+    assert_eq!(kc.get(key1).unwrap(), Some(val.to_vec()));
+    let mut batch = Batch::default();
+    batch.remove(key1);
+    assert!(kc.batch_idempotent(&batch, &idem_key).unwrap());
+    assert_eq!(kc.get(key1).unwrap(), Some(val.to_vec()));
+    assert!(kc.del(key1).unwrap());
+
+    // unknown db yields a typed error
+    let kc_bad = t_client(&Client::new(), "no-such-db");
+    match kc_bad.put(key1, val) {
+        Err(ClientError::Status(status)) => assert_eq!(status, StatusCode::NOT_FOUND),
+        _ => panic!("unexpected result"),
+    }
+}
+
+fn op_binary(client: &Client, db_id: String, binary_port: Option<u64>) {
+    let binary_pair = match binary_port {
+        None => return,
+        Some(port) => format!("{T_BINARY_ADDR}:{port}"),
+    };
+    let mut bc = BinClient::connect(&binary_pair, &db_id).unwrap();
+
+    let key1: &[u8] = b"op_binary\x00\xff/key1";
+    let key2: &[u8] = b"op_binary\x00\xff/key2";
+    let val: &[u8] = b"\x00\x01\x02 op_binary value";
+
+    let n_records = bc.stat().unwrap();
+    assert_eq!(bc.get(key1).unwrap(), None);
+    assert!(bc.put(key1, val).unwrap());
+    assert_eq!(bc.get(key1).unwrap(), Some(val.to_vec()));
+    assert_eq!(bc.stat().unwrap(), n_records + 1);
+
+    // same db, as seen via HTTP
+    let kc = t_client(client, &db_id);
+    assert_eq!(kc.get(key1).unwrap(), Some(val.to_vec()));
+
+    let mut batch = Batch::default();
+    batch.remove(key1);
+    batch.insert(key2, val);
+    assert!(bc.batch(&batch).unwrap());
+    assert_eq!(bc.get(key1).unwrap(), None);
+    assert_eq!(bc.get(key2).unwrap(), Some(val.to_vec()));
+
+    let mut opts = IterOptions::new();
+    opts.prefix(b"op_binary\x00");
+    let key_list = bc.iter(opts).unwrap();
+    assert_eq!(key_list.keys, vec![key2.to_vec()]);
+    assert!(key_list.list_end);
+
+    assert!(bc.del(key2).unwrap());
+    assert!(!bc.del(key2).unwrap());
+    assert_eq!(bc.stat().unwrap(), n_records);
+
+    // unknown db yields an error status; the connection stays usable
+    let mut bc_bad = BinClient::connect(&binary_pair, "no-such-db").unwrap();
+    match bc_bad.put(key1, val) {
+        Err(ClientError::BinStatus(status, _msg)) => assert_eq!(status, binproto::ST_NO_DB),
+        _ => panic!("unexpected result"),
+    }
+    match bc_bad.get(key1) {
+        Err(ClientError::BinStatus(status, _msg)) => assert_eq!(status, binproto::ST_NO_DB),
+        _ => panic!("unexpected result"),
+    }
+
+    // without an api_key set, AUTH admits any key, leaving the
+    // connection usable
+    bc.auth("any key").unwrap();
+    assert_eq!(bc.stat().unwrap(), n_records);
+
+    // malformed request body
+    let mut stream = TcpStream::connect(&binary_pair).unwrap();
+    binproto::write_frame(&mut stream, &[binproto::OP_GET]).unwrap();
+    let resp = binproto::read_frame(&mut stream).unwrap().unwrap();
+    assert_eq!(resp[0], binproto::ST_BAD_REQUEST);
+    let req = binproto::enc_request(0xee, &db_id, &[]);
+    binproto::write_frame(&mut stream, &req).unwrap();
+    let resp = binproto::read_frame(&mut stream).unwrap().unwrap();
+    assert_eq!(resp[0], binproto::ST_BAD_REQUEST);
+
+    // oversized frame is refused, then the connection is closed
+    let mut frame = Vec::new();
+    binproto::put_varint(&mut frame, binproto::MAX_FRAME_LEN + 1);
+    stream.write_all(&frame).unwrap();
+    let resp = binproto::read_frame(&mut stream).unwrap().unwrap();
+    assert_eq!(resp[0], binproto::ST_BAD_FRAME);
+    assert_eq!(binproto::read_frame(&mut stream).unwrap(), None);
+}
+
+fn op_sweep(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let sweep_url = format!("{basepath}_sweep");
+    let test_key = String::from("op_sweep_key");
+    let test_value = format!("helloworld op_sweep {db_id}");
+    let expiring_key = "op_sweep_expiring";
+
+    t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
+
+    // exec sweep request; unexpired records are not removed
+    let resp_res = client.post(&sweep_url).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            let jv: serde_json::Value = resp.json().unwrap();
+            assert_eq!(jv["result"], true);
+            assert_eq!(jv["n_removed"], 0);
+        }
+        Err(_e) => panic!("sweep request failed"),
+    }
+
+    // an expired record leaves the db's quota count when swept; unless
+    // the db's own sweeper removed it first
+    assert_eq!(t_put_ttl(client, &db_id, expiring_key, "1"), StatusCode::OK);
+    let n_keys = t_quota_usage(client, &db_id)["keys"].as_u64();
+    thread::sleep(Duration::from_millis(1100));
+    let mut resp = client.post(&sweep_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["result"], true);
+    let has_sweeper = db_cfg["ttl_sweep_secs"].as_u64().unwrap_or(0) > 0;
+    if !has_sweeper {
+        assert_eq!(jv["n_removed"], 1);
+    }
+    if let Some(n_keys) = n_keys {
+        let usage = t_quota_usage(client, &db_id);
+        assert!(usage["keys"].as_u64().unwrap() < n_keys);
+    }
+    t_get_gone(client, db_id.clone(), expiring_key.to_string());
+
+    t_get_ok(client, db_id.clone(), test_key.clone(), test_value);
+    t_del(client, db_id, test_key);
+}
+
+fn op_flush(client: &Client, db_id: String) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let flush_url = format!("{basepath}flush");
+    let test_key = String::from("op_flush_key");
+    let test_value = format!("helloworld op_flush {db_id}");
+
+    t_put(client, db_id.clone(), test_key.clone(), test_value.clone());
+
+    // exec flush request; records are unchanged
+    let resp_res = client.post(&flush_url).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            let jv: serde_json::Value = resp.json().unwrap();
+            assert_eq!(jv["result"], true);
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    t_get_ok(client, db_id.clone(), test_key.clone(), test_value);
+    t_del(client, db_id, test_key);
+}
+
+fn op_compact(client: &Client, db_id: String) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let compact_url = format!("{basepath}compact");
+    let kept_key = String::from("op_compact_kept");
+    let kept_value = format!("helloworld op_compact {db_id}");
+
+    // deleted and overwritten records, for compaction to reclaim
+    t_put(client, db_id.clone(), kept_key.clone(), String::from("old"));
+    t_put(client, db_id.clone(), kept_key.clone(), kept_value.clone());
+    for i in 0..10 {
+        let key = format!("op_compact_gone{i}");
+        t_put(client, db_id.clone(), key.clone(), kept_value.clone());
+        t_del(client, db_id.clone(), key);
+    }
+
+    // exec compact request; records are unchanged
+    let mut resp = client.post(&compact_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["result"], true);
+    t_get_ok(client, db_id.clone(), kept_key.clone(), kept_value);
+    t_get_gone(client, db_id.clone(), String::from("op_compact_gone0"));
+
+    let url = format!("{}{}/no_such_db/compact", T_ENDPOINT, t_api_base());
+    let resp = client.post(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    t_del(client, db_id, kept_key);
+}
+
+fn op_put(client: &Client, db_id: String) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let put_url = format!("{}put", basepath);
+    let get_url = format!("{}get", basepath);
+    let del_url = format!("{}del", basepath);
+    let test_key = String::from("op_put_key");
+    let test_value = format!("helloworld op_put {}", db_id);
+
+    // encode put request
+    let out_bytes = pbenc_update_req(test_key.as_bytes(), test_value.as_bytes());
+
+    // exec put request
+    let resp_res = client.post(&put_url).body(out_bytes).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+
+            match resp.text() {
+                Ok(_body) => {}
+                Err(_e) => assert!(false),
+            }
+        }
+        Err(_e) => assert!(false),
+    }
+
+    // encode verification get request
+    let out_bytes = pbenc_key_req(test_key.as_bytes());
+
+    // exec get request
+    let resp_res = client.post(&get_url).body(out_bytes.clone()).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+
+            match resp.text() {
+                Ok(body) => assert_eq!(body, test_value),
+                Err(_e) => assert!(false),
+            }
+        }
+        Err(_e) => assert!(false),
+    }
+
+    // re-use same KeyRequest bytes for our delete request
+
+    // exec del request
+    let resp_res = client.post(&del_url).body(out_bytes).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(_e) => assert!(false),
+    }
+}
+
+fn op_obj(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let test_key = String::from("1");
+    let test_value = format!("helloworld {}", db_id);
+
+    // Check that a record with key 1 doesn't exist.
+    let url = format!("{}obj/{}", basepath, test_key);
+    t_obj_get_gone(client, &url, db_cfg);
+
+    // verify DELETE(non exist) returns not-found
+    let resp_res = client.delete(&url).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
+        Err(_e) => assert!(false),
+    }
+
+    // PUT a new record
+    let resp_res = client.put(&url).body(test_value.clone()).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(_e) => assert!(false),
+    }
+
+    // Check that the record exists with the correct contents.
+    let resp_res = client.get(&url).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+
+            match resp.text() {
+                Ok(body) => assert_eq!(body, test_value),
+                Err(_e) => assert!(false),
+            }
+        }
+        Err(_e) => assert!(false),
+    }
+
+    // Check that the record exists with the correct contents,
+    // protobuf-style.
     let out_bytes = pbenc_key_req(test_key.as_bytes());
 
-    let get_pb_url = format!("{}get", basepath);
-    let resp_res = client.post(&get_pb_url).body(out_bytes).send();
-    match resp_res {
+    let get_pb_url = format!("{}get", basepath);
+    let resp_res = client.post(&get_pb_url).body(out_bytes).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+
+            match resp.text() {
+                Ok(body) => assert_eq!(body, test_value),
+                Err(_e) => assert!(false),
+            }
+        }
+        Err(_e) => assert!(false),
+    }
+
+    // DELETE record
+    let resp_res = client.delete(&url).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(_e) => assert!(false),
+    }
+
+    // Check (again) that a record with key 1 doesn't exist.
+    t_obj_get_gone(client, &url, db_cfg);
+
+    // verify (again) DELETE(non exist) returns not-found
+    let resp_res = client.delete(&url).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
+        Err(_e) => assert!(false),
+    }
+}
+
+fn op_obj_default(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let url = format!("{basepath}obj/op_obj_default_key");
+
+    // absent key, no default: 404, or empty value if so configured
+    t_obj_get_gone(client, &url, db_cfg);
+
+    // absent key, caller-supplied default value
+    let def_url = format!("{url}?default=some%20value");
+    let resp_res = client.get(&def_url).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.text().unwrap(), "some value");
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    // present key: default ignored
+    let resp_res = client.put(&url).body("stored value").send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+    let resp_res = client.get(&def_url).send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.text().unwrap(), "stored value");
+        }
+        Err(e) => panic!("{}", e),
+    }
+    let resp_res = client.delete(&url).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(_e) => assert!(false),
+    }
+}
+
+// GET key via REST API, with Range header: (status, content-range, body)
+fn t_obj_get_range(client: &Client, url: &str, range: &str) -> (StatusCode, String, String) {
+    let resp_res = client.get(url).header("Range", range).send();
+    match resp_res {
+        Ok(mut resp) => {
+            let content_range = match resp.headers().get("content-range") {
+                None => String::new(),
+                Some(v) => v.to_str().unwrap().to_string(),
+            };
+            (resp.status(), content_range, resp.text().unwrap())
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn op_obj_range(client: &Client, db_id: String) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let url = format!("{basepath}obj/op_obj_range_key");
+
+    let resp_res = client.put(&url).body("0123456789").send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+
+    // mid-value range
+    let (status, content_range, body) = t_obj_get_range(client, &url, "bytes=2-5");
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(content_range, "bytes 2-5/10");
+    assert_eq!(body, "2345");
+
+    // open-ended and suffix ranges
+    let (status, content_range, body) = t_obj_get_range(client, &url, "bytes=7-");
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(content_range, "bytes 7-9/10");
+    assert_eq!(body, "789");
+    let (status, content_range, body) = t_obj_get_range(client, &url, "bytes=-3");
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(content_range, "bytes 7-9/10");
+    assert_eq!(body, "789");
+
+    // range end past value end is truncated
+    let (status, content_range, body) = t_obj_get_range(client, &url, "bytes=8-100");
+    assert_eq!(status, StatusCode::PARTIAL_CONTENT);
+    assert_eq!(content_range, "bytes 8-9/10");
+    assert_eq!(body, "89");
+
+    // out-of-bounds range
+    let (status, content_range, _body) = t_obj_get_range(client, &url, "bytes=10-20");
+    assert_eq!(status, StatusCode::RANGE_NOT_SATISFIABLE);
+    assert_eq!(content_range, "bytes */10");
+
+    // multiple ranges: entire value returned
+    let (status, _content_range, body) = t_obj_get_range(client, &url, "bytes=0-1,4-5");
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body, "0123456789");
+
+    let resp_res = client.delete(&url).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn op_obj_cache(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let url = format!("{basepath}obj/op_obj_cache_key");
+
+    // mutating responses are not cacheable
+    let resp_res = client.put(&url).body("cached value").send();
+    match resp_res {
+        Ok(resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.headers()["cache-control"], "no-store");
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    // GET includes ETag, and configured Cache-Control
+    let etag;
+    let resp_res = client.get(&url).send();
+    match resp_res {
+        Ok(resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            etag = resp.headers()["etag"].to_str().unwrap().to_string();
+            match db_cfg["default_cache_control"].as_str() {
+                Some(cc) => assert_eq!(resp.headers()["cache-control"], cc),
+                None => assert!(resp.headers().get("cache-control").is_none()),
+            }
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    // conditional GET, value unchanged
+    let resp_res = client
+        .get(&url)
+        .header("If-None-Match", etag.as_str())
+        .send();
+    match resp_res {
+        Ok(resp) => {
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(resp.headers()["etag"], etag.as_str());
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    // conditional GET, value changed
+    let resp_res = client.put(&url).body("new cached value").send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+    let resp_res = client
+        .get(&url)
+        .header("If-None-Match", etag.as_str())
+        .send();
+    match resp_res {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::OK);
+            assert_eq!(resp.text().unwrap(), "new cached value");
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    let resp_res = client.delete(&url).send();
+    match resp_res {
+        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+// Last-Modified, and conditional GET by If-Modified-Since
+fn op_obj_modified(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let url = format!(
+        "{}{}/{}/obj/op_obj_modified_key",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let resp = client.put(&url).body("dated value").send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    if db_cfg["track_modified"].as_bool() != Some(true) {
+        assert!(resp.headers().get("last-modified").is_none());
+        t_del(client, db_id, String::from("op_obj_modified_key"));
+        return;
+    }
+    let last_modified = resp.headers()["last-modified"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(last_modified.ends_with(" GMT"));
+
+    // unmodified since the value's time:  304, without a body
+    let resp = client
+        .get(&url)
+        .header("If-Modified-Since", last_modified.as_str())
+        .send();
+    match resp {
+        Ok(mut resp) => {
+            assert_eq!(resp.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(resp.headers()["last-modified"], last_modified.as_str());
+            assert_eq!(resp.text().unwrap(), "");
+        }
+        Err(e) => panic!("{}", e),
+    }
+
+    // modified since an earlier time; an invalid date is ignored, as is
+    // If-Modified-Since alongside If-None-Match
+    for (name, val) in &[
+        ("If-Modified-Since", "Thu, 01 Jan 1970 00:00:00 GMT"),
+        ("If-Modified-Since", "yesterday"),
+    ] {
+        let resp = client.get(&url).header(*name, *val).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    let resp = client
+        .get(&url)
+        .header("If-Modified-Since", last_modified.as_str())
+        .header("If-None-Match", "\"other\"")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    t_del(client, db_id, String::from("op_obj_modified_key"));
+}
+
+// conditional PUT and DELETE, by If-Match of the value's ETag
+fn op_obj_if_match(client: &Client, db_id: String) {
+    let url = format!(
+        "{}{}/{}/obj/op_obj_if_match_key",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let get_etag = || {
+        let resp = client.get(&url).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        resp.headers()["etag"].to_str().unwrap().to_string()
+    };
+    let put_if = |tag: &str, val: &str| {
+        let resp = client
+            .put(&url)
+            .header("If-Match", tag)
+            .body(val.to_string());
+        resp.send().unwrap().status()
+    };
+
+    // absent key:  no tag matches, not even "*"
+    assert_eq!(put_if("*", "v1"), StatusCode::PRECONDITION_FAILED);
+    let resp = client.head(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // present key:  "*", or its current ETag, among others, matches
+    let resp = client.put(&url).body("v1").send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(put_if("*", "v2"), StatusCode::OK);
+    let etag = get_etag();
+    assert_eq!(put_if(&format!("\"0\", {etag}"), "v3"), StatusCode::OK);
+
+    // a stale ETag no longer matches, and the value is unchanged
+    assert_eq!(put_if(&etag, "v4"), StatusCode::PRECONDITION_FAILED);
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.text().unwrap(), "v3");
+
+    // DELETE alike
+    let resp = client
+        .delete(&url)
+        .header("If-Match", etag.as_str())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::PRECONDITION_FAILED);
+    let etag = get_etag();
+    let resp = client
+        .delete(&url)
+        .header("If-Match", etag.as_str())
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let resp = client.head(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // deleted between GET and PUT:  the old ETag matches nothing, rather
+    // than recreating the key
+    assert_eq!(put_if(&etag, "v5"), StatusCode::PRECONDITION_FAILED);
+    let resp = client.head(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+
+    // the ETag depends on the value alone:  rewriting it keeps its tag
+    let resp = client.put(&url).body("v3").send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(get_etag(), etag);
+    let resp = client.delete(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+// approximate GET counts of the most read keys
+fn op_hot_keys(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let hot_keys = |query: &str| {
+        client
+            .get(&format!("{basepath}_hotkeys{query}"))
+            .send()
+            .unwrap()
+    };
+
+    if db_cfg["track_hot_keys"].as_bool() != Some(true) {
+        assert_eq!(hot_keys("").status(), StatusCode::NOT_IMPLEMENTED);
+        return;
+    }
+
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("op_hot_key"),
+        String::from("v"),
+    );
+    for _i in 0..50 {
+        let resp = client
+            .get(&format!("{basepath}obj/op_hot_key"))
+            .send()
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    // the key just read 50 times is among the hottest, counted at least
+    // that often
+    let mut resp = hot_keys("?n=5");
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    let keys = jv["keys"].as_array().unwrap();
+    assert!(!keys.is_empty() && keys.len() <= 5);
+    assert!(jv["n_reads"].as_u64().unwrap() >= 50);
+    let hot_key = base64::encode("op_hot_key");
+    let hot = keys.iter().find(|k| k["key"] == hot_key.as_str()).unwrap();
+    let count = hot["count"].as_u64().unwrap();
+    assert!(count >= 50 && count - hot["error"].as_u64().unwrap() <= 50);
+    let counts: Vec<u64> = keys.iter().map(|k| k["count"].as_u64().unwrap()).collect();
+    assert!(counts.windows(2).all(|w| w[0] >= w[1]));
+
+    // n is bounded by the keys tracked
+    let max_keys = jv["max_keys"].as_u64().unwrap();
+    assert_eq!(hot_keys("?n=0").status(), StatusCode::BAD_REQUEST);
+    let query = format!("?n={}", max_keys + 1);
+    assert_eq!(hot_keys(&query).status(), StatusCode::BAD_REQUEST);
+    t_del(client, db_id, String::from("op_hot_key"));
+}
+
+// key versions, on GET, and PUT conditional on them
+fn op_obj_version(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let url = format!(
+        "{}{}/{}/obj/op_obj_version_key",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let get_version = || {
+        let resp = client.get(&url).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        resp.headers()
+            .get("x-kvdb-version")
+            .map(|hdr| hdr.to_str().unwrap().parse::<u64>().unwrap())
+    };
+    let put_if = |version: &str, val: &str| {
+        let resp = client
+            .put(&url)
+            .header("X-KVDB-If-Version", version)
+            .body(val.to_string());
+        resp.send().unwrap().status()
+    };
+
+    if db_cfg["track_versions"].as_bool() != Some(true) {
+        let resp = client.put(&url).body("v1").send().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(get_version(), None);
+        assert_eq!(put_if("1", "v2"), StatusCode::NOT_IMPLEMENTED);
+        t_del(client, db_id, String::from("op_obj_version_key"));
+        return;
+    }
+
+    // version 0 expects the key absent; each write bumps it
+    assert_eq!(put_if("1", "v1"), StatusCode::PRECONDITION_FAILED);
+    assert_eq!(put_if("0", "v1"), StatusCode::OK);
+    assert_eq!(get_version(), Some(1));
+    let resp = client.put(&url).body("v2").send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(get_version(), Some(2));
+
+    // a stale version no longer matches, and the value is unchanged
+    assert_eq!(put_if("2", "v3"), StatusCode::OK);
+    assert_eq!(put_if("2", "v4"), StatusCode::PRECONDITION_FAILED);
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.text().unwrap(), "v3");
+    assert_eq!(get_version(), Some(3));
+
+    // a version must be a number, and stand alone
+    assert_eq!(put_if("three", "v4"), StatusCode::BAD_REQUEST);
+    let resp = client
+        .put(&url)
+        .header("X-KVDB-If-Version", "3")
+        .header("If-Match", "*")
+        .body("v4")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    // deleted between GET and PUT:  the old version matches nothing
+    t_del(client, db_id.clone(), String::from("op_obj_version_key"));
+    assert_eq!(put_if("3", "v4"), StatusCode::PRECONDITION_FAILED);
+    t_get_gone(client, db_id, String::from("op_obj_version_key"));
+}
+
+// bulk conditional set, each put applied over versions no newer
+fn op_cset(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let url = format!("{}{}/{}/_cset", T_ENDPOINT, t_api_base(), db_id);
+    let b64 = |s: &str| base64::encode(s.as_bytes());
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("op_cset_a"),
+        String::from("v1"),
+    );
+    let puts = json!([
+        {"key": b64("op_cset_a"), "value": b64("stale"), "version": 0},
+        {"key": b64("op_cset_a"), "value": b64("v2"), "version": 1},
+        {"key": b64("op_cset_b"), "value": b64("new"), "version": 0},
+    ]);
+
+    if db_cfg["track_versions"].as_bool() != Some(true) {
+        let resp = client.post(&url).json(&puts).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::NOT_IMPLEMENTED);
+        t_get_ok(
+            client,
+            db_id.clone(),
+            String::from("op_cset_a"),
+            String::from("v1"),
+        );
+        t_del(client, db_id, String::from("op_cset_a"));
+        return;
+    }
+
+    let mut resp = client.post(&url).json(&puts).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["applied"], json!([false, true, true]));
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("op_cset_a"),
+        String::from("v2"),
+    );
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("op_cset_b"),
+        String::from("new"),
+    );
+
+    // a malformed put refuses the set
+    let puts = json!([
+        {"key": b64("op_cset_a"), "value": b64("v3"), "version": 9},
+        {"key": b64("op_cset_b"), "value": b64("v3")},
+    ]);
+    let resp = client.post(&url).json(&puts).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        String::from("op_cset_a"),
+        String::from("v2"),
+    );
+
+    t_del(client, db_id.clone(), String::from("op_cset_a"));
+    t_del(client, db_id, String::from("op_cset_b"));
+}
+
+fn op_obj_if_none_match(client: &Client, db_id: String) {
+    let url = format!(
+        "{}{}/{}/obj/op_obj_if_none_match_key",
+        T_ENDPOINT,
+        t_api_base(),
+        db_id
+    );
+    let put_if_none = |tag: &str, val: &str| {
+        let resp = client
+            .put(&url)
+            .header("If-None-Match", tag)
+            .body(val.to_string());
+        resp.send().unwrap().status()
+    };
+
+    // absent key:  created; present key:  unchanged
+    assert_eq!(put_if_none("*", "v1"), StatusCode::CREATED);
+    assert_eq!(put_if_none("*", "v2"), StatusCode::PRECONDITION_FAILED);
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.text().unwrap(), "v1");
+
+    // only "*" is understood, and not with If-Match
+    assert_eq!(put_if_none("\"0\"", "v2"), StatusCode::BAD_REQUEST);
+    let resp = client
+        .put(&url)
+        .header("If-None-Match", "*")
+        .header("If-Match", "*")
+        .body("v2")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+    let resp = client.delete(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+}
+
+fn op_obj_meta(client: &Client, db_id: String, db_cfg: &serde_json::Value) {
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    let url = format!("{basepath}obj/op_obj_meta_key");
+    let put_meta = |content_type: &str| {
+        client
+            .put(&url)
+            .header("X-KVDB-Meta-Content-Type", content_type)
+            .header(
+                "X-KVDB-Meta-Content-Disposition",
+                "attachment; filename=\"a.txt\"",
+            )
+            .body("hello")
+            .send()
+            .unwrap()
+    };
+
+    if !db_cfg["value_metadata"].as_bool().unwrap_or(false) {
+        assert_eq!(put_meta("text/plain").status(), StatusCode::NOT_IMPLEMENTED);
+        return;
+    }
+
+    // stored metadata is replayed as response headers
+    assert_eq!(put_meta("text/plain").status(), StatusCode::OK);
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(resp.headers()["content-type"], "text/plain");
+    assert_eq!(
+        resp.headers()["content-disposition"],
+        "attachment; filename=\"a.txt\""
+    );
+    assert_eq!(resp.text().unwrap(), "hello");
+
+    // a PUT without metadata clears it
+    t_put(
+        client,
+        db_id.clone(),
+        String::from("op_obj_meta_key"),
+        String::from("bye"),
+    );
+    let resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.headers()["content-type"], "application/octet-stream");
+    assert!(resp.headers().get("content-disposition").is_none());
+
+    // only listed headers may be stored, within the metadata size limit
+    let resp = client
+        .put(&url)
+        .header("X-KVDB-Meta-Set-Cookie", "a=b")
+        .body("hello")
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+    assert_eq!(
+        put_meta(&"x".repeat(2000)).status(),
+        StatusCode::BAD_REQUEST
+    );
+
+    t_del(client, db_id, String::from("op_obj_meta_key"));
+}
+
+fn t_obj_alloc(client: &Client, db_id: &str, value: &str) -> String {
+    let alloc_url = format!("{}{}/{}", T_ENDPOINT, t_api_base(), db_id);
+
+    let resp_res = client.post(&alloc_url).body(value.to_string()).send();
+    if resp_res.is_err() {
+        panic!("request failed");
+    }
+    let mut resp = resp_res.unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["result"], true);
+    jv["key"].as_str().unwrap().to_string()
+}
+
+fn op_obj_alloc(db_id: String) {
+    const N_THREADS: usize = 4;
+    const N_ALLOCS: usize = 25;
+
+    // allocate keys from several concurrent clients
+    let mut threads = Vec::new();
+    for t in 0..N_THREADS {
+        let db_id = db_id.clone();
+        threads.push(thread::spawn(move || {
+            let client = Client::new();
+            let mut keys = Vec::new();
+            for i in 0..N_ALLOCS {
+                let value = format!("alloc {t} {i}");
+                let key = t_obj_alloc(&client, &db_id, &value);
+                keys.push((key, value));
+            }
+            keys
+        }));
+    }
+
+    let mut all_keys = HashSet::new();
+    let client = Client::new();
+    let basepath = format!("{}{}/{}/", T_ENDPOINT, t_api_base(), db_id);
+    for th in threads {
+        for (key, value) in th.join().unwrap() {
+            // every allocated key is distinct, and holds its value
+            assert!(all_keys.insert(key.clone()));
+
+            let url = format!("{basepath}obj/{key}");
+            match client.get(&url).send() {
+                Ok(mut resp) => {
+                    assert_eq!(resp.status(), StatusCode::OK);
+                    assert_eq!(resp.text().unwrap(), value);
+                }
+                Err(e) => panic!("{}", e),
+            }
+        }
+    }
+    assert_eq!(all_keys.len(), N_THREADS * N_ALLOCS);
+}
+
+// read one HTTP/1.1 response from a raw connection: (status, body)
+fn t_read_response(stream: &mut TcpStream, buf: &mut Vec<u8>) -> (u16, Vec<u8>) {
+    loop {
+        if let Some(hdr_end) = buf.windows(4).position(|w| w == b"\r\n\r\n") {
+            let hdr = String::from_utf8(buf[..hdr_end].to_vec()).unwrap();
+            let status: u16 = hdr.split(' ').nth(1).unwrap().parse().unwrap();
+            let mut body_len = 0;
+            for line in hdr.lines() {
+                let lc_line = line.to_lowercase();
+                if let Some(len_str) = lc_line.strip_prefix("content-length:") {
+                    body_len = len_str.trim().parse().unwrap();
+                }
+            }
+
+            let body_start = hdr_end + 4;
+            if buf.len() >= body_start + body_len {
+                let body = buf[body_start..body_start + body_len].to_vec();
+                buf.drain(..body_start + body_len);
+                return (status, body);
+            }
+        }
+
+        let mut chunk = [0u8; 4096];
+        let n = stream.read(&mut chunk).unwrap();
+        assert!(n > 0); // connection closed early
+        buf.extend_from_slice(&chunk[..n]);
+    }
+}
+
+fn t_stats(client: &Client) -> serde_json::Value {
+    let url = format!("{T_ENDPOINT}/_stats");
+    match client.get(&url).send() {
         Ok(mut resp) => {
             assert_eq!(resp.status(), StatusCode::OK);
+            resp.json().unwrap()
+        }
+        Err(_e) => panic!("stats request failed"),
+    }
+}
 
-            match resp.text() {
-                Ok(body) => assert_eq!(body, test_value),
-                Err(_e) => assert!(false),
+fn t_quota_usage(client: &Client, db_id: &str) -> serde_json::Value {
+    t_stats(client)["quotas"][db_id].clone()
+}
+
+fn t_put_status(client: &Client, db_id: &str, key: &str, value: Vec<u8>) -> StatusCode {
+    let url = format!("{}{}/{}/obj/{}", T_ENDPOINT, t_api_base(), db_id, key);
+    client.put(&url).body(value).send().unwrap().status()
+}
+
+fn t_multibatch(client: &Client, batches: &serde_json::Value) -> (StatusCode, serde_json::Value) {
+    let url = format!("{}{}/_multibatch", T_ENDPOINT, t_api_base());
+    match client.post(&url).json(batches).send() {
+        Ok(mut resp) => {
+            let jv = resp.json().unwrap_or(serde_json::Value::Null);
+            (resp.status(), jv)
+        }
+        Err(e) => panic!("{}", e),
+    }
+}
+
+fn op_multibatch(client: &Client) {
+    let b64 = |s: &str| base64::encode(s.as_bytes());
+
+    // batches for two dbs, both applied
+    t_put(
+        client,
+        String::from("db2"),
+        String::from("mb_old"),
+        String::from("0"),
+    );
+    let batches = json!({
+        "db1": [{"key": b64("mb_a"), "value": b64("1")}],
+        "db2": [{"key": b64("mb_b"), "value": b64("2")}, {"key": b64("mb_old")}],
+    });
+    let (status, jv) = t_multibatch(client, &batches);
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(jv["result"], json!(true));
+    assert_eq!(jv["dbs"]["db1"]["result"], json!(true));
+    assert_eq!(jv["dbs"]["db2"]["result"], json!(true));
+    t_get_ok(
+        client,
+        String::from("db1"),
+        String::from("mb_a"),
+        String::from("1"),
+    );
+    t_get_ok(
+        client,
+        String::from("db2"),
+        String::from("mb_b"),
+        String::from("2"),
+    );
+
+    // unknown db: rejected, nothing applied
+    let batches = json!({
+        "db1": [{"key": b64("mb_c"), "value": b64("3")}],
+        "db_none": [{"key": b64("mb_c"), "value": b64("3")}],
+    });
+    let (status, _jv) = t_multibatch(client, &batches);
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    t_get_gone(client, String::from("db1"), String::from("mb_c"));
+
+    // malformed key
+    let batches = json!({"db1": [{"key": "not base64!"}]});
+    let (status, _jv) = t_multibatch(client, &batches);
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+
+    // partial failure:  a batch over one db's quota fails alone
+    for (full_db, other_db) in &[("db1", "db2"), ("db2", "db1")] {
+        let max_bytes = match t_quota_usage(client, full_db)["max_total_bytes"].as_u64() {
+            None => continue,
+            Some(n) => n as usize,
+        };
+        let big_value = base64::encode(&vec![b'm'; max_bytes + 1]);
+        let batches = json!({
+            *full_db: [{"key": b64("mb_c"), "value": big_value}],
+            *other_db: [{"key": b64("mb_c"), "value": b64("3")}],
+        });
+        let (status, jv) = t_multibatch(client, &batches);
+        assert_eq!(status, StatusCode::MULTI_STATUS);
+        assert_eq!(jv["result"], json!(false));
+        assert_eq!(jv["dbs"][full_db]["result"], json!(false));
+        assert_eq!(jv["dbs"][full_db]["error"], json!("quota exceeded"));
+        assert_eq!(jv["dbs"][other_db]["result"], json!(true));
+        t_get_gone(client, full_db.to_string(), String::from("mb_c"));
+        t_get_ok(
+            client,
+            other_db.to_string(),
+            String::from("mb_c"),
+            String::from("3"),
+        );
+        t_del(client, other_db.to_string(), String::from("mb_c"));
+        break;
+    }
+
+    t_del(client, String::from("db1"), String::from("mb_a"));
+    t_del(client, String::from("db2"), String::from("mb_b"));
+}
+
+fn op_quota(client: &Client, db_id: String) {
+    let usage = t_quota_usage(client, &db_id);
+    let max_bytes = match usage["max_total_bytes"].as_u64() {
+        None => return,
+        Some(n) => n,
+    };
+    let total_bytes = usage["total_bytes"].as_u64().unwrap();
+    assert!(total_bytes <= max_bytes);
+
+    // fill remaining quota exactly; one more byte is refused
+    let key = "op_quota_fill";
+    let room = (max_bytes - total_bytes) as usize - key.len();
+    assert_eq!(
+        t_put_status(client, &db_id, key, vec![b'q'; room]),
+        StatusCode::OK
+    );
+    let usage = t_quota_usage(client, &db_id);
+    assert_eq!(usage["total_bytes"].as_u64().unwrap(), max_bytes);
+    assert_eq!(
+        t_put_status(client, &db_id, "op_quota_x", vec![b'x']),
+        StatusCode::INSUFFICIENT_STORAGE
+    );
+    t_get_gone(client, db_id.clone(), String::from("op_quota_x"));
+
+    // shrinking a record, or deleting it, frees quota
+    assert_eq!(
+        t_put_status(client, &db_id, key, vec![b'q'; room - 16]),
+        StatusCode::OK
+    );
+    assert_eq!(
+        t_put_status(client, &db_id, "op_quota_x", vec![b'x']),
+        StatusCode::OK
+    );
+    t_del(client, db_id.clone(), String::from(key));
+    t_del(client, db_id.clone(), String::from("op_quota_x"));
+    let usage = t_quota_usage(client, &db_id);
+    assert_eq!(usage["total_bytes"].as_u64().unwrap(), total_bytes);
+}
+
+// no db is degraded by write failures, so the server is ready
+fn op_ready(client: &Client) {
+    let info = t_server_info(client);
+    assert_eq!(info["degraded"], json!([]));
+
+    let url = format!("{T_ENDPOINT}/_ready");
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(
+        jv,
+        json!({"ready": true, "draining": false, "degraded": []})
+    );
+}
+
+// every open db is listed; new dbs may be created only if the server
+// allows runtime_dbs
+fn op_runtime_db(client: &Client, info: &serde_json::Value) {
+    let base_url = format!("{}{}", T_ENDPOINT, t_api_base());
+    let list_dbs = || -> serde_json::Value {
+        let mut resp = client.get(&format!("{base_url}/")).send().unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let jv: serde_json::Value = resp.json().unwrap();
+        jv["databases"].clone()
+    };
+    let names = list_dbs();
+    for db_cfg in info["databases"].as_array().unwrap() {
+        assert!(names.as_array().unwrap().contains(&db_cfg["name"]));
+    }
+
+    // an open db is not created again
+    let db_id = info["databases"][0]["name"].as_str().unwrap();
+    let mut resp = client.put(&format!("{base_url}/{db_id}")).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv, json!({"name": db_id, "created": false}));
+
+    let new_id = "op_runtime_db";
+    let resp = client.put(&format!("{base_url}/{new_id}")).send().unwrap();
+    if !info["runtime_dbs"].as_bool().unwrap() {
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+        return;
+    }
+    // created by this run, or still open since a previous one
+    assert!(resp.status() == StatusCode::CREATED || resp.status() == StatusCode::OK);
+    assert!(list_dbs().as_array().unwrap().contains(&json!(new_id)));
+    t_put(
+        client,
+        new_id.to_string(),
+        String::from("key"),
+        String::from("val"),
+    );
+    t_get_ok(
+        client,
+        new_id.to_string(),
+        String::from("key"),
+        String::from("val"),
+    );
+    t_del(client, new_id.to_string(), String::from("key"));
+
+    // names must stay beneath runtime_dbs.dir, and clear of admin paths
+    let resp = client
+        .put(&format!("{base_url}/_op_runtime_db"))
+        .send()
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+}
+
+// the server is live, and each db answers the readiness probe
+fn op_health(client: &Client) {
+    let url = format!("{T_ENDPOINT}/health");
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv, json!({"live": true}));
+
+    let url = format!("{T_ENDPOINT}/health/ready");
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv, json!({"ready": true, "failing": []}));
+}
+
+fn op_drain(client: &Client, binary_port: Option<u64>) {
+    let info = t_server_info(client);
+    let db_id = info["databases"][0]["name"].as_str().unwrap().to_string();
+    let ready_url = format!("{T_ENDPOINT}/_ready");
+    let drain_url = format!("{}{}/_drain", T_ENDPOINT, t_api_base());
+    let undrain_url = format!("{}{}/_undrain", T_ENDPOINT, t_api_base());
+    let obj_url = format!("{}{}/{}/obj/op_drain", T_ENDPOINT, t_api_base(), db_id);
+
+    // draining:  not ready, but reads and writes are still served
+    let mut resp = client.post(&drain_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv, json!({"draining": true, "reject_writes": false}));
+    let mut resp = client.get(&ready_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["ready"], false);
+    assert_eq!(jv["draining"], true);
+    let resp = client.put(&obj_url).body("drained").send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        "op_drain".to_string(),
+        "drained".to_string(),
+    );
+
+    // draining, rejecting writes:  reads still served, writes refused
+    let url = format!("{drain_url}?reject_writes=1");
+    let mut resp = client.post(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["reject_writes"], true);
+    let resp = client.put(&obj_url).body("refused").send().unwrap();
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let resp = client.delete(&obj_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::SERVICE_UNAVAILABLE);
+    t_get_ok(
+        client,
+        db_id.clone(),
+        "op_drain".to_string(),
+        "drained".to_string(),
+    );
+    if let Some(port) = binary_port {
+        let binary_pair = format!("{T_BINARY_ADDR}:{port}");
+        let mut bc = BinClient::connect(&binary_pair, &db_id).unwrap();
+        assert!(bc.put(b"op_drain", b"refused").is_err());
+        assert_eq!(bc.get(b"op_drain").unwrap(), Some(b"drained".to_vec()));
+    }
+    assert_eq!(t_server_info(client)["name"], info["name"]);
+
+    // undrain:  ready, and writes resume
+    let mut resp = client.post(&undrain_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv, json!({"draining": false}));
+    let resp = client.get(&ready_url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    t_del(client, db_id, "op_drain".to_string());
+}
+
+fn op_max_connections(client: &Client) {
+    let stats = t_stats(client);
+    assert!(stats["connections"].as_u64().unwrap() >= 1);
+    let max_conns = match stats["max_connections"].as_u64() {
+        None => return,
+        Some(n) => n as usize,
+    };
+
+    // open more connections than allowed, each sending one request
+    let host = T_ENDPOINT.trim_start_matches("http://");
+    let n_conns = max_conns + 8;
+    let req = format!("GET /_stats HTTP/1.1\r\nHost: {host}\r\n\r\n");
+    let mut streams = Vec::new();
+    for _i in 0..n_conns {
+        let mut stream = TcpStream::connect(host).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        stream.write_all(req.as_bytes()).unwrap();
+        streams.push(stream);
+    }
+
+    // each is served, refused, or left waiting to be accepted
+    let mut n_ok = 0;
+    let mut n_refused = 0;
+    for stream in &mut streams {
+        let mut buf = [0u8; 4096];
+        match stream.read(&mut buf) {
+            Ok(n) if n > 0 => {
+                let resp = String::from_utf8_lossy(&buf[..n]).to_string();
+                if resp.starts_with("HTTP/1.1 200") {
+                    n_ok += 1;
+                } else {
+                    assert!(resp.starts_with("HTTP/1.1 503"));
+                    n_refused += 1;
+                }
             }
+            _ => {} // timed out, still queued
         }
-        Err(_e) => assert!(false),
     }
+    assert!(n_ok <= max_conns);
+    assert!(n_ok > 0 && n_ok + n_refused <= n_conns);
 
-    // DELETE record
-    let resp_res = client.delete(&url).send();
-    match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
-        Err(_e) => assert!(false),
+    // closed connections are no longer counted; server still serves
+    drop(streams);
+    thread::sleep(Duration::from_millis(500));
+    let stats = t_stats(&Client::new());
+    assert!((stats["connections"].as_u64().unwrap() as usize) < max_conns);
+}
+
+// binary protocol connections count toward max_connections, too
+fn op_binary_max_connections(client: &Client, binary_port: Option<u64>) {
+    let stats = t_stats(client);
+    let (max_conns, port) = match (stats["max_connections"].as_u64(), binary_port) {
+        (Some(n), Some(port)) => (n as usize, port),
+        _ => return,
+    };
+    let binary_pair = format!("{T_BINARY_ADDR}:{port}");
+
+    // those beyond the limit are sent UNAVAILABLE, unprompted, and closed
+    let mut streams = Vec::new();
+    for _i in 0..max_conns + 1 {
+        let stream = TcpStream::connect(&binary_pair).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(200)))
+            .unwrap();
+        streams.push(stream);
+    }
+    let mut n_refused = 0;
+    for stream in &mut streams {
+        if let Ok(Some(resp)) = binproto::read_frame(stream) {
+            assert_eq!(resp[0], binproto::ST_UNAVAILABLE);
+            n_refused += 1;
+        }
     }
+    assert!(n_refused >= 1 && n_refused < streams.len());
 
-    // Check (again) that a record with key 1 doesn't exist.
-    let resp_res = client.get(&url).send();
-    match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
-        Err(_e) => assert!(false),
+    // an admitted one is served; closed ones are no longer counted
+    let mut stream = streams.remove(0);
+    stream.set_read_timeout(None).unwrap();
+    let req = binproto::enc_request(binproto::OP_STAT, "no-such-db", &[]);
+    binproto::write_frame(&mut stream, &req).unwrap();
+    let resp = binproto::read_frame(&mut stream).unwrap().unwrap();
+    assert_eq!(resp[0], binproto::ST_NO_DB);
+    drop(stream);
+    drop(streams);
+    thread::sleep(Duration::from_millis(500));
+    let stats = t_stats(&Client::new());
+    assert!((stats["connections"].as_u64().unwrap() as usize) < max_conns);
+}
+
+fn t_inflight(client: &Client) -> serde_json::Value {
+    let url = format!("{}{}/_inflight", T_ENDPOINT, t_api_base());
+    let mut resp = client.get(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    resp.json().unwrap()
+}
+
+fn op_inflight(client: &Client) {
+    let info = t_server_info(client);
+    let db_id = info["databases"][0]["name"].as_str().unwrap().to_string();
+    let is_op = |jv: &serde_json::Value| jv["db"] == db_id.as_str() && jv["key_len"] == 11;
+
+    // a PUT whose body arrives slowly is in flight until answered
+    let host = T_ENDPOINT.trim_start_matches("http://");
+    let mut stream = TcpStream::connect(host).unwrap();
+    let req = format!(
+        "PUT {}/{}/obj/op%20inflight HTTP/1.1\r\nHost: {}\r\nContent-Length: 10\r\n\r\nslow ",
+        t_api_base(),
+        db_id,
+        host
+    );
+    stream.write_all(req.as_bytes()).unwrap();
+    thread::sleep(Duration::from_millis(300));
+
+    let jv = t_inflight(client);
+    let listed: Vec<&serde_json::Value> = jv["inflight"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter(|op| is_op(op))
+        .collect();
+    assert_eq!(listed.len(), 1);
+    assert_eq!(listed[0]["op"], "put");
+    assert_eq!(listed[0]["method"], "PUT");
+    assert!(listed[0]["elapsed_ms"].as_u64().unwrap() >= 200);
+    assert_eq!(jv["untracked"], 0);
+
+    // answered:  no longer listed
+    stream.write_all(b"write").unwrap();
+    let mut buf = [0u8; 4096];
+    let n = stream.read(&mut buf).unwrap();
+    assert!(String::from_utf8_lossy(&buf[..n]).starts_with("HTTP/1.1 200"));
+    let jv = t_inflight(client);
+    assert!(!jv["inflight"].as_array().unwrap().iter().any(is_op));
+    t_get_ok(
+        client,
+        db_id.clone(),
+        "op inflight".to_string(),
+        "slow write".to_string(),
+    );
+    t_del(client, db_id, "op inflight".to_string());
+}
+
+fn op_reopen(client: &Client) {
+    let info = t_server_info(client);
+    let url = format!("{}{}/_reopen", T_ENDPOINT, t_api_base());
+
+    // every replica, or one named replica; naming any other db fails
+    let mut replicas = Vec::new();
+    for db_cfg in info["databases"].as_array().unwrap() {
+        let name = db_cfg["name"].as_str().unwrap();
+        let mut resp = client.post(&format!("{url}?db={name}")).send().unwrap();
+        if db_cfg["replica"].as_bool().unwrap_or(false) {
+            assert_eq!(resp.status(), StatusCode::OK);
+            let jv: serde_json::Value = resp.json().unwrap();
+            assert_eq!(jv, json!({ "reopened": [name] }));
+            replicas.push(name);
+        } else {
+            assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+        }
     }
+    let mut resp = client.post(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv, json!({ "reopened": replicas }));
 
-    // verify (again) DELETE(non exist) returns not-found
-    let resp_res = client.delete(&url).send();
-    match resp_res {
-        Ok(resp) => assert_eq!(resp.status(), StatusCode::NOT_FOUND),
-        Err(_e) => assert!(false),
+    let resp = client.post(&format!("{url}?db=op_reopen")).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::NOT_FOUND);
+}
+
+fn op_reload(client: &Client) {
+    // config file is unchanged:  reload succeeds, changing nothing
+    let url = format!("{}{}/_reload", T_ENDPOINT, t_api_base());
+    let mut resp = client.post(&url).send().unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let jv: serde_json::Value = resp.json().unwrap();
+    assert_eq!(jv["reloaded"], true);
+    assert_eq!(jv["applied"], json!([]));
+    assert_eq!(jv["restart_required"], json!([]));
+
+    // server config unaffected
+    assert_eq!(
+        t_server_info(client)["databases"].as_array().unwrap().len(),
+        2
+    );
+}
+
+fn op_pipeline(db_id: String) {
+    const N_OPS: usize = 10;
+
+    let host = T_ENDPOINT.trim_start_matches("http://");
+    let mut stream = TcpStream::connect(host).unwrap();
+
+    // write all requests up front, without waiting for any response
+    let mut reqs = Vec::new();
+    for i in 0..N_OPS {
+        let value = format!("pipelined value {i}");
+        reqs.extend_from_slice(
+            format!(
+                "PUT {}/{}/obj/pipe{} HTTP/1.1\r\nHost: {}\r\nContent-Length: {}\r\n\r\n{}",
+                t_api_base(),
+                db_id,
+                i,
+                host,
+                value.len(),
+                value
+            )
+            .as_bytes(),
+        );
+        reqs.extend_from_slice(
+            format!(
+                "GET {}/{}/obj/pipe{} HTTP/1.1\r\nHost: {}\r\n\r\n",
+                t_api_base(),
+                db_id,
+                i,
+                host
+            )
+            .as_bytes(),
+        );
+    }
+    for i in 0..N_OPS {
+        reqs.extend_from_slice(
+            format!(
+                "DELETE {}/{}/obj/pipe{} HTTP/1.1\r\nHost: {}\r\n\r\n",
+                t_api_base(),
+                db_id,
+                i,
+                host
+            )
+            .as_bytes(),
+        );
+    }
+    stream.write_all(&reqs).unwrap();
+
+    // responses arrive in request order, on the same connection
+    let mut buf = Vec::new();
+    for i in 0..N_OPS {
+        let (status, body) = t_read_response(&mut stream, &mut buf);
+        assert_eq!(status, 200);
+        assert_eq!(body, b"{\"result\":true}".to_vec());
+
+        let (status, body) = t_read_response(&mut stream, &mut buf);
+        assert_eq!(status, 200);
+        assert_eq!(body, format!("pipelined value {i}").into_bytes());
+    }
+    for _i in 0..N_OPS {
+        let (status, _body) = t_read_response(&mut stream, &mut buf);
+        assert_eq!(status, 200);
+    }
+}
+
+// benchmark:  durable PUTs from many clients at once, reporting
+// throughput.  compare runs against a db with and without
+// group_commit_window_us configured
+fn bench_put_concurrent(client: &Client, db_id: &str) {
+    const N_THREADS: usize = 8;
+    const N_PUTS: usize = 250; // per thread
+
+    let start = Instant::now();
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|n| {
+            let client = client.clone();
+            let db_id = db_id.to_string();
+            thread::spawn(move || {
+                for i in 0..N_PUTS {
+                    let url = format!(
+                        "{}{}/{}/obj/bench_put_{}_{}?durable=1",
+                        T_ENDPOINT,
+                        t_api_base(),
+                        db_id,
+                        n,
+                        i
+                    );
+                    match client.put(&url).body("benchmark value").send() {
+                        Ok(resp) => assert_eq!(resp.status(), StatusCode::OK),
+                        Err(e) => panic!("{}", e),
+                    }
+                }
+            })
+        })
+        .collect();
+    for th in threads {
+        th.join().unwrap();
     }
+    let secs = start.elapsed().as_secs_f64();
+
+    println!(
+        "{}: {} clients x {} durable PUTs in {:.2}s: {:.0} PUTs/sec",
+        db_id,
+        N_THREADS,
+        N_PUTS,
+        secs,
+        (N_THREADS * N_PUTS) as f64 / secs
+    );
 }
 
 fn main() {
@@ -556,26 +4756,120 @@ fn main() {
     let cli_app = clap::App::new(APPNAME)
         .version(VERSION)
         .author("Jeff Garzik <jgarzik@pobox.com>")
-        .about("Integration tester for kvdbd");
+        .about("Integration tester for kvdbd")
+        .arg(
+            clap::Arg::with_name("bench")
+                .long("bench")
+                .value_name("DB")
+                .help("Benchmark concurrent durable PUTs to database DB, instead of testing")
+                .takes_value(true),
+        );
 
     // parse command line
-    let _cli_matches = cli_app.get_matches();
+    let cli_matches = cli_app.get_matches();
 
     // create http client
     let client = Client::new();
 
+    if let Some(db_id) = cli_matches.value_of("bench") {
+        let info = t_server_info(&client);
+        T_API_BASE
+            .set(info["api_base"].as_str().unwrap().to_string())
+            .unwrap();
+        bench_put_concurrent(&client, db_id);
+        return;
+    }
+
+    // query server and per-db configuration, for config-dependent tests
+    let info = t_server_info(&client);
+    T_API_BASE
+        .set(info["api_base"].as_str().unwrap().to_string())
+        .unwrap();
+    let db_cfgs = t_db_configs(&info);
+
     // test, for each database
     for n in 1..3 {
         let db_id = format!("db{}", n);
+        let db_cfg = &db_cfgs[&db_id];
 
         op_batch(&client, db_id.clone());
         op_del(&client, db_id.clone());
         op_get(&client, db_id.clone());
-        op_obj(&client, db_id.clone());
+        op_consistency(&client, db_id.clone());
+        op_inspect(&client, db_id.clone(), db_cfg);
+        op_obj(&client, db_id.clone(), db_cfg);
+        op_obj_default(&client, db_id.clone(), db_cfg);
+        op_obj_range(&client, db_id.clone());
+        op_obj_cache(&client, db_id.clone(), db_cfg);
+        op_obj_modified(&client, db_id.clone(), db_cfg);
+        op_obj_if_match(&client, db_id.clone());
+        op_obj_version(&client, db_id.clone(), db_cfg);
+        op_cset(&client, db_id.clone(), db_cfg);
+        op_hot_keys(&client, db_id.clone(), db_cfg);
+        op_obj_if_none_match(&client, db_id.clone());
+        op_obj_meta(&client, db_id.clone(), db_cfg);
         op_put(&client, db_id.clone());
-        op_clear(&client, db_id.clone());
+        op_clear(&client, db_id.clone(), db_cfg);
         op_stat(&client, db_id.clone());
         op_iter(&client, db_id.clone());
+        op_keys_page(&client, db_id.clone(), db_cfg);
+        op_entries(&client, db_id.clone(), db_cfg);
+        op_page_bytes(&client, db_id.clone(), db_cfg);
+        op_export(&client, db_id.clone());
+        op_import(&client, db_id.clone(), db_cfg);
+        op_restore(&client, db_id.clone(), db_cfg);
+        op_scan(&client, db_id.clone());
+        op_snapshot_scan(&client, db_id.clone());
+        op_size(&client, db_id.clone(), db_cfg);
+        op_count(&client, db_id.clone(), db_cfg);
+        op_delete_prefix(&client, db_id.clone());
+        op_has_prefix(&client, db_id.clone());
+        op_histogram(&client, db_id.clone());
+        op_obj_alloc(db_id.clone());
+        op_pipeline(db_id.clone());
+        op_sweep(&client, db_id.clone(), db_cfg);
+        op_flush(&client, db_id.clone());
+        op_compact(&client, db_id.clone());
+        op_obj_rename(&client, db_id.clone());
+        op_obj_keyenc(&client, db_id.clone());
+        op_obj_append(&client, db_id.clone());
+        op_obj_init(&client, db_id.clone(), db_cfg);
+        op_concurrent_rmw(&client, db_id.clone());
+        op_obj_merge(&client, db_id.clone(), db_cfg);
+        op_blob_put(&client, db_id.clone());
+        op_obj_put_stream(&client, db_id.clone(), db_cfg);
+        op_obj_touch(&client, db_id.clone());
+        op_swap(&client, db_id.clone());
+        op_put_durable(&client, db_id.clone());
+        op_put_concurrent(&client, db_id.clone());
+        op_del_if_empty(&client, db_id.clone(), db_cfg);
+        op_put_expire_at(&client, db_id.clone());
+        op_client(db_id.clone());
+        op_mget(&client, db_id.clone(), db_cfg);
+        op_exists(&client, db_id.clone(), db_cfg);
+        op_mdel(&client, db_id.clone(), db_cfg);
+        op_index(&client, db_id.clone());
+        op_put_empty(&client, db_id.clone(), db_cfg);
+        op_put_too_large(&client, db_id.clone(), db_cfg);
+        op_request_bytes(&client, db_id.clone(), db_cfg);
+        op_api_base(&client, db_id.clone());
+        op_method_not_allowed(&client, db_id.clone(), db_cfg);
+        op_txn(&client, db_id.clone());
+        op_quota(&client, db_id.clone());
+        op_changes(&client, db_id.clone(), db_cfg);
+        op_watch(&client, db_id.clone());
+        op_http2(db_id.clone(), info["http2"].as_bool().unwrap());
+        op_binary(&client, db_id.clone(), info["binary_port"].as_u64());
     }
+    op_multibatch(&client);
+    op_max_connections(&client);
+    op_binary_max_connections(&client, info["binary_port"].as_u64());
+    op_ready(&client);
+    op_health(&client);
+    op_drain(&client, info["binary_port"].as_u64());
+    op_inflight(&client);
+    op_reopen(&client);
+    op_reload(&client);
+    op_runtime_db(&client, &info);
     println!("Integration testing successful.");
 }